@@ -0,0 +1,38 @@
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use gosub_engine::bytes::{CharIterator, Encoding};
+use gosub_engine::html5::parser::document::{Document, DocumentBuilder};
+use gosub_engine::html5::parser::Html5Parser;
+use gosub_engine::testing::stress::generate_document;
+
+/// Size of the generated document in megabytes. Can be overridden with GOSUB_STRESS_MB.
+const DEFAULT_SIZE_MB: usize = 50;
+
+fn criterion_benchmark(c: &mut Criterion) {
+    let size_mb = std::env::var("GOSUB_STRESS_MB")
+        .ok()
+        .and_then(|size| size.parse().ok())
+        .unwrap_or(DEFAULT_SIZE_MB);
+
+    // Careful about generating the document inside the closure
+    let html = generate_document(size_mb << 20);
+
+    let mut group = c.benchmark_group("Large document");
+    group
+        .sample_size(10)
+        .throughput(Throughput::Bytes(html.len() as u64));
+
+    group.bench_function(format!("parse {} MB", size_mb), |b| {
+        b.iter(|| {
+            let mut chars = CharIterator::new();
+            chars.read_from_str(&html, Some(Encoding::UTF8));
+
+            let document = DocumentBuilder::new_document();
+            let _ = Html5Parser::parse_document(&mut chars, Document::clone(&document), None);
+        });
+    });
+
+    group.finish();
+}
+
+criterion_group!(benches, criterion_benchmark);
+criterion_main!(benches);
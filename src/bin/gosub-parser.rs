@@ -1,10 +1,10 @@
 use anyhow::Result;
+use gosub_engine::html5::graph::{to_dot, to_graphml, GraphOptions};
+use gosub_engine::html5::node::NodeId;
 use gosub_engine::html5::parser::document::{Document, DocumentBuilder};
-use gosub_engine::{
-    bytes::{CharIterator, Confidence, Encoding},
-    html5::parser::Html5Parser,
-};
+use gosub_engine::{bytes::CharIterator, html5::parser::Html5Parser};
 use std::fs;
+use std::io::Read;
 use std::process::exit;
 
 fn bail(message: &str) -> ! {
@@ -12,12 +12,23 @@ fn bail(message: &str) -> ! {
     exit(1);
 }
 
+/// Returns the charset label of a `Content-Type` header (e.g. "text/html; charset=utf-8")
+fn charset_label(content_type: &str) -> Option<String> {
+    content_type.split(';').find_map(|parameter| {
+        let (name, value) = parameter.split_once('=')?;
+        name.trim()
+            .eq_ignore_ascii_case("charset")
+            .then(|| value.trim().trim_matches('"').to_string())
+    })
+}
+
 fn main() -> Result<()> {
     let url = std::env::args()
         .nth(1)
-        .unwrap_or_else(|| bail("Usage: gosub-parser <url>"));
+        .unwrap_or_else(|| bail("Usage: gosub-parser <url> [--dot|--graphml]"));
+    let format = std::env::args().nth(2);
 
-    let html = if url.starts_with("http://") || url.starts_with("https://") {
+    let (bytes, charset) = if url.starts_with("http://") || url.starts_with("https://") {
         // Fetch the html from the url
         let response = ureq::get(&url).call()?;
         if response.status() != 200 {
@@ -26,25 +37,40 @@ fn main() -> Result<()> {
                 response.status()
             ));
         }
-        response.into_string()?
+        let charset = response.header("content-type").and_then(charset_label);
+        let mut bytes = Vec::new();
+        response.into_reader().read_to_end(&mut bytes)?;
+        (bytes, charset)
     } else {
         // Get html from the file
-        fs::read_to_string(&url)?
+        (fs::read(&url)?, None)
     };
 
+    // The encoding is sniffed from the bytes, unless the server declared it
     let mut chars = CharIterator::new();
-    chars.read_from_str(&html, Some(Encoding::UTF8));
-    chars.set_confidence(Confidence::Certain);
-
-    // If the encoding confidence is not Confidence::Certain, we should detect the encoding.
-    if !chars.is_certain_encoding() {
-        chars.detect_encoding()
-    }
+    chars.read_from_bytes(&bytes, charset.as_deref());
 
     let document = DocumentBuilder::new_document();
     let parse_errors = Html5Parser::parse_document(&mut chars, Document::clone(&document), None)?;
 
-    println!("Generated tree: \n\n {}", document);
+    match format.as_deref() {
+        Some("--dot") => {
+            print!(
+                "{}",
+                to_dot(&document.get(), NodeId::root(), &GraphOptions::default())
+            );
+            return Ok(());
+        }
+        Some("--graphml") => {
+            print!(
+                "{}",
+                to_graphml(&document.get(), NodeId::root(), &GraphOptions::default())
+            );
+            return Ok(());
+        }
+        Some(format) => bail(&format!("Unknown output format: {}", format)),
+        None => println!("Generated tree: \n\n {}", document),
+    }
 
     for e in parse_errors {
         println!("Parse Error: {}", e.message)
@@ -0,0 +1,64 @@
+use gosub_engine::testing::differential::{compare, dump_gosub, dump_reference};
+use gosub_engine::testing::tree_construction::fixture::read_fixtures;
+use std::fs;
+
+/// Compares the gosub parser against html5ever. Without arguments, all (non-fragment) inputs of
+/// the html5lib tree-construction tests are compared. Otherwise the given files are compared, and
+/// the full tree dumps of both parsers are shown for every divergence.
+fn main() {
+    let paths: Vec<String> = std::env::args().skip(1).collect();
+
+    if paths.is_empty() {
+        compare_fixtures();
+        return;
+    }
+
+    for path in paths {
+        let html = match fs::read_to_string(&path) {
+            Ok(html) => html,
+            Err(e) => {
+                println!("❌ {}: {}", path, e);
+                continue;
+            }
+        };
+
+        match compare(&html, true) {
+            None => println!("✅ {}: trees are equal", path),
+            Some(divergence) => {
+                println!("❌ {}: {}", path, divergence);
+                println!("\ngosub:\n{}", dump_gosub(&html, true).join("\n"));
+                println!("\nhtml5ever:\n{}\n", dump_reference(&html, true).join("\n"));
+            }
+        }
+    }
+}
+
+fn compare_fixtures() {
+    let fixtures = read_fixtures(None).expect("fixtures");
+    let mut inputs = 0;
+    let mut divergences = 0;
+
+    for fixture in fixtures {
+        for test in fixture.tests {
+            if test.spec.document_fragment.is_some() {
+                continue;
+            }
+
+            for &scripting_enabled in test.script_modes() {
+                inputs += 1;
+                if let Some(divergence) = compare(test.spec_data(), scripting_enabled) {
+                    divergences += 1;
+                    println!(
+                        "❌ {}:{} (scripting {}): {}\n",
+                        fixture.path,
+                        test.line,
+                        if scripting_enabled { "on" } else { "off" },
+                        divergence
+                    );
+                }
+            }
+        }
+    }
+
+    println!("🏁 Compared {} inputs, {} divergences", inputs, divergences);
+}
@@ -0,0 +1,24 @@
+use gosub_engine::testing::html5lib::Report;
+use gosub_engine::types::Result;
+use std::path::PathBuf;
+
+/// Runs all tokenizer and tree-construction tests of html5lib-tests and prints the pass rate per
+/// fixture file. An html5lib-tests checkout can be given as argument (the one in the repository is
+/// used otherwise). With `--json`, the full results are printed as JSON instead, so they can be
+/// stored and compared between runs.
+fn main() -> Result<()> {
+    let json = std::env::args().any(|arg| arg == "--json");
+    let root = std::env::args().skip(1).find(|arg| !arg.starts_with("--"));
+
+    let report = match root {
+        Some(root) => Report::run_from(&PathBuf::from(root))?,
+        None => Report::run()?,
+    };
+    if json {
+        println!("{}", report.to_json());
+    } else {
+        println!("{}", report);
+    }
+
+    Ok(())
+}
@@ -1,12 +1,13 @@
 use gosub_engine::html5::parser::document::DocumentBuilder;
 use gosub_engine::{
-    bytes::{CharIterator, Confidence, Encoding},
+    bytes::{prescan::extract_charset_from_content, CharIterator},
     html5::{
         node::{Node, NodeData},
         parser::{document::Document, Html5Parser},
     },
     types::Result,
 };
+use std::io::Read;
 use std::process::exit;
 
 fn main() -> Result<()> {
@@ -24,16 +25,16 @@ fn main() -> Result<()> {
         println!("could not get url. Status code {}", response.status());
         exit(1);
     }
-    let html = response.into_string()?;
+    // ureq reports utf-8 when the Content-Type header has no charset, so read it from the header
+    let charset = response
+        .header("content-type")
+        .and_then(extract_charset_from_content);
+    let mut bytes = Vec::new();
+    response.into_reader().read_to_end(&mut bytes)?;
 
+    // The encoding is sniffed from the bytes when there is no charset or it is not a known label
     let mut chars = CharIterator::new();
-    chars.read_from_str(&html, Some(Encoding::UTF8));
-    chars.set_confidence(Confidence::Certain);
-
-    // If the encoding confidence is not Confidence::Certain, we should detect the encoding.
-    if !chars.is_certain_encoding() {
-        chars.detect_encoding()
-    }
+    chars.read_from_bytes(&bytes, charset.as_deref());
 
     let document = DocumentBuilder::new_document();
     let parse_errors = Html5Parser::parse_document(&mut chars, Document::clone(&document), None)?;
@@ -0,0 +1,14 @@
+use gosub_engine::testing::wpt::Report;
+
+/// Runs the supported part of the web-platform-tests and prints the pass rate. With `--json`, the
+/// full results are printed as JSON instead, so they can be stored and compared between runs.
+fn main() {
+    let json = std::env::args().any(|arg| arg == "--json");
+
+    let report = Report::run();
+    if json {
+        println!("{}", report.to_json());
+    } else {
+        println!("{}", report);
+    }
+}
@@ -0,0 +1,843 @@
+//! Browsing contexts
+//!
+//! A browsing context is the environment in which documents are presented to the user (a tab,
+//! a window or a frame). It owns the window of the active document and the session history of
+//! all documents that have been loaded into it.
+//!
+//! Browsing contexts of frames (iframes) are nested inside the browsing context of the document
+//! that contains the frame. All browsing contexts are owned by a [`FrameTree`].
+//!
+//! See: https://html.spec.whatwg.org/multipage/document-sequences.html#browsing-context
+pub mod navigation;
+
+use crate::browsing_context::navigation::{
+    NavigationCause, NavigationDecision, NavigationPolicy, NavigationRequest, MAX_POLICY_REDIRECTS,
+};
+use crate::bytes::{CharIterator, Encoding};
+use crate::event_loop::{EventLoopHandle, TaskSource};
+use crate::html5::node::NodeId;
+use crate::html5::parser::document::{Document, DocumentBuilder, DocumentHandle};
+use crate::html5::parser::Html5Parser;
+use crate::html5::viewport::{DeviceSize, ResolvedViewport};
+use crate::net::origin::Origin;
+use crate::net::referrer::ReferrerPolicy;
+use crate::types::{Error, ParseError, Result};
+use core::fmt;
+use core::fmt::Debug;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+use url::Url;
+
+/// Size of the device used when the embedder does not provide one
+const DEFAULT_DEVICE_SIZE: DeviceSize = DeviceSize {
+    width: 1024.0,
+    height: 768.0,
+};
+
+/// An entry in the session history of a browsing context
+#[derive(Debug, Clone)]
+pub struct SessionHistoryEntry {
+    pub url: Url,
+    /// Title of the document at the time it was loaded
+    pub title: Option<String>,
+    pub document: DocumentHandle,
+}
+
+/// The global object of a document
+#[derive(Debug, Clone)]
+pub struct Window {
+    document: DocumentHandle,
+    event_loop: EventLoopHandle,
+    device_size: DeviceSize,
+}
+
+impl Window {
+    /// Returns a handle to the document of the window
+    pub fn document(&self) -> DocumentHandle {
+        Document::clone(&self.document)
+    }
+
+    /// Returns the event loop the window runs on
+    pub fn event_loop(&self) -> &EventLoopHandle {
+        &self.event_loop
+    }
+
+    /// Returns the size of the device the window is displayed on
+    pub fn device_size(&self) -> DeviceSize {
+        self.device_size
+    }
+
+    /// Queues moving the focus through the tab order of the document, as if the user pressed the
+    /// tab key (or shift+tab when going backwards)
+    pub fn queue_focus_navigation(&self, backwards: bool) {
+        let mut document = self.document();
+        self.event_loop
+            .queue_task(TaskSource::UserInteraction, move || {
+                document.focus_next(backwards);
+            });
+    }
+
+    /// Returns the viewport of the document, resolved against the device size
+    pub fn viewport(&self) -> ResolvedViewport {
+        self.document
+            .get()
+            .viewport()
+            .unwrap_or_default()
+            .resolve(self.device_size)
+    }
+
+    /// Renders the document when the event loop has a rendering opportunity at the given time (in
+    /// milliseconds). Returns true when it was rendered.
+    pub fn render_if_due(&self, now: f64, renderer: &mut dyn Renderer) -> bool {
+        match self.event_loop.rendering_opportunity(now) {
+            Some(timestamp) => {
+                self.update_the_rendering(timestamp, renderer);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Renders a frame of the document: dispatches the coalesced pointer moves, runs the
+    /// animation frame callbacks, lays out the document, delivers the resize and intersection
+    /// observations, and paints.
+    pub fn update_the_rendering(&self, timestamp: f64, renderer: &mut dyn Renderer) {
+        let mut document = self.document();
+
+        document.flush_pointer_moves();
+        document.run_animation_frame_callbacks(timestamp);
+
+        renderer.update_layout(&document);
+        if document.deliver_resize_observations() > 0 {
+            // The observers can have changed the document
+            renderer.update_layout(&document);
+        }
+        document.update_intersection_observations();
+
+        renderer.paint(&document);
+    }
+}
+
+/// Rendering of documents, implemented by the embedder
+pub trait Renderer {
+    /// Brings the style and layout of the document up to date
+    fn update_layout(&mut self, document: &DocumentHandle);
+    /// Paints the document
+    fn paint(&mut self, document: &DocumentHandle);
+}
+
+/// Id of a browsing context in a frame tree
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct BrowsingContextId(usize);
+
+/// A browsing context (tab, window or frame)
+#[derive(Debug)]
+pub struct BrowsingContext {
+    /// Name of the browsing context, used for targeting links (e.g. target="main")
+    pub name: String,
+    /// Parent browsing context when this is a frame
+    parent: Option<BrowsingContextId>,
+    /// Browsing contexts of the frames in the active document
+    children: Vec<BrowsingContextId>,
+    /// The frame element (in the document of the parent) this browsing context is displayed in
+    container: Option<NodeId>,
+    window: Window,
+    session_history: Vec<SessionHistoryEntry>,
+    history_index: usize,
+}
+
+impl BrowsingContext {
+    /// Creates a new browsing context with an empty about:blank document
+    pub fn new(event_loop: EventLoopHandle) -> Self {
+        let url = Url::parse("about:blank").expect("valid url");
+        let mut document = DocumentBuilder::new_document();
+        document.get_mut().url = Some(url.clone());
+
+        Self {
+            name: String::new(),
+            parent: None,
+            children: Vec::new(),
+            container: None,
+            window: Window {
+                document: Document::clone(&document),
+                event_loop,
+                device_size: DEFAULT_DEVICE_SIZE,
+            },
+            session_history: vec![SessionHistoryEntry {
+                url,
+                title: None,
+                document,
+            }],
+            history_index: 0,
+        }
+    }
+
+    /// Returns the parent browsing context, or None for a top-level browsing context
+    pub fn parent(&self) -> Option<BrowsingContextId> {
+        self.parent
+    }
+
+    /// Returns the browsing contexts of the frames in the active document
+    pub fn children(&self) -> &[BrowsingContextId] {
+        &self.children
+    }
+
+    /// Returns the frame element this browsing context is displayed in
+    pub fn container(&self) -> Option<NodeId> {
+        self.container
+    }
+
+    /// Returns the window of the active document
+    pub fn window(&self) -> &Window {
+        &self.window
+    }
+
+    /// Returns the active document
+    pub fn active_document(&self) -> DocumentHandle {
+        self.window.document()
+    }
+
+    /// Returns the origin of the active document
+    pub fn origin(&self) -> Origin {
+        self.window.document.get().origin().clone()
+    }
+
+    /// Sets the size of the device the browsing context is displayed on
+    pub fn set_device_size(&mut self, device_size: DeviceSize) {
+        self.window.device_size = device_size;
+    }
+
+    /// Parses the html into a new document for the given url and makes it the active document.
+    /// Any session history entries after the current one are removed.
+    pub fn load_html(&mut self, url: Url, html: &str) -> Result<Vec<ParseError>> {
+        let mut chars = CharIterator::new();
+        chars.read_from_str(html, Some(Encoding::UTF8));
+
+        let mut document = DocumentBuilder::new_document();
+        document.get_mut().url = Some(url.clone());
+        document.get_mut().set_origin(Origin::from_url(&url));
+        let errors = Html5Parser::parse_document(&mut chars, Document::clone(&document), None)?;
+
+        let title = document.get().title();
+        self.session_history.truncate(self.history_index + 1);
+        self.session_history.push(SessionHistoryEntry {
+            url,
+            title,
+            document: Document::clone(&document),
+        });
+        self.history_index = self.session_history.len() - 1;
+        self.window.document = document;
+
+        Ok(errors)
+    }
+
+    /// Returns all entries in the session history
+    pub fn session_history(&self) -> &[SessionHistoryEntry] {
+        &self.session_history
+    }
+
+    /// Returns the session history entry of the active document
+    pub fn current_entry(&self) -> &SessionHistoryEntry {
+        &self.session_history[self.history_index]
+    }
+
+    /// Moves through the session history by the given number of steps (negative to go back).
+    /// Returns false when there is no entry at that position.
+    pub fn traverse_history(&mut self, delta: isize) -> bool {
+        let Some(index) = self.history_index.checked_add_signed(delta) else {
+            return false;
+        };
+        let Some(entry) = self.session_history.get(index) else {
+            return false;
+        };
+
+        self.window.document = Document::clone(&entry.document);
+        self.history_index = index;
+        true
+    }
+
+    /// Returns true when there is an earlier entry in the session history
+    pub fn can_go_back(&self) -> bool {
+        self.history_index > 0
+    }
+
+    /// Returns true when there is a later entry in the session history
+    pub fn can_go_forward(&self) -> bool {
+        self.history_index + 1 < self.session_history.len()
+    }
+}
+
+/// Owns all browsing contexts (top-level and nested) that share an event loop
+pub struct FrameTree {
+    contexts: HashMap<BrowsingContextId, BrowsingContext>,
+    /// Top-level browsing contexts in order of creation
+    top_level: Vec<BrowsingContextId>,
+    next_id: usize,
+    event_loop: EventLoopHandle,
+    /// Embedder hook that is consulted before every navigation
+    navigation_policy: Option<Rc<RefCell<dyn NavigationPolicy>>>,
+}
+
+impl Debug for FrameTree {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("FrameTree")
+            .field("contexts", &self.contexts)
+            .field("top_level", &self.top_level)
+            .field("has_navigation_policy", &self.navigation_policy.is_some())
+            .finish()
+    }
+}
+
+impl FrameTree {
+    /// Creates a new empty frame tree
+    pub fn new(event_loop: EventLoopHandle) -> Self {
+        Self {
+            contexts: HashMap::new(),
+            top_level: Vec::new(),
+            next_id: 0,
+            event_loop,
+            navigation_policy: None,
+        }
+    }
+
+    /// Sets the hook that decides whether navigations can continue
+    pub fn set_navigation_policy(&mut self, policy: Rc<RefCell<dyn NavigationPolicy>>) {
+        self.navigation_policy = Some(policy);
+    }
+
+    /// Consults the navigation policy for the request. Redirects by the policy are applied to
+    /// the request (and consulted again), so the returned decision is never a redirect. Without
+    /// a policy, all navigations are allowed.
+    pub fn request_navigation(
+        &self,
+        mut request: NavigationRequest,
+    ) -> (NavigationRequest, NavigationDecision) {
+        let Some(policy) = &self.navigation_policy else {
+            return (request, NavigationDecision::Allow);
+        };
+
+        for _ in 0..=MAX_POLICY_REDIRECTS {
+            match policy.borrow_mut().decide(&request) {
+                NavigationDecision::Redirect(url) => request.url = url,
+                decision => return (request, decision),
+            }
+        }
+
+        // Too many redirects
+        (request, NavigationDecision::Block)
+    }
+
+    /// Creates the navigation request for following the hyperlink (a or area element) in the
+    /// active document of the source browsing context, and consults the navigation policy.
+    /// Returns None when the element is not a valid hyperlink.
+    pub fn follow_hyperlink(
+        &mut self,
+        source: BrowsingContextId,
+        node_id: NodeId,
+    ) -> Option<(NavigationRequest, NavigationDecision)> {
+        let document = self.get(source)?.active_document();
+        let (url, target, referrer, referrer_policy) = {
+            let doc = document.get();
+            let node = doc.get_node_by_id(node_id)?;
+            let href = node.get_attribute("href")?;
+
+            let target = node
+                .get_attribute("target")
+                .or(doc.base_target.as_ref())
+                .cloned()
+                .unwrap_or_default();
+
+            let referrer_policy = doc.element_referrer_policy(node_id);
+            let referrer = match referrer_policy {
+                ReferrerPolicy::NoReferrer => None,
+                _ => doc.url.clone(),
+            };
+
+            (doc.resolve_url(href)?, target, referrer, referrer_policy)
+        };
+
+        let target = self.choose_target(source, &target)?;
+        let request = NavigationRequest {
+            url,
+            target,
+            source: Some(source),
+            cause: NavigationCause::LinkClick,
+            referrer,
+            referrer_policy,
+        };
+        Some(self.request_navigation(request))
+    }
+
+    fn insert(&mut self, context: BrowsingContext) -> BrowsingContextId {
+        let id = BrowsingContextId(self.next_id);
+        self.next_id += 1;
+        self.contexts.insert(id, context);
+        id
+    }
+
+    /// Creates a new top-level browsing context (a tab or window)
+    pub fn create_top_level(&mut self, name: &str) -> BrowsingContextId {
+        let mut context = BrowsingContext::new(self.event_loop.clone());
+        context.name = name.to_string();
+
+        let id = self.insert(context);
+        self.top_level.push(id);
+        id
+    }
+
+    /// Creates a browsing context for the frame element (container) in the active document of
+    /// the parent browsing context. Returns None when the parent does not exist.
+    pub fn create_child(
+        &mut self,
+        parent: BrowsingContextId,
+        container: NodeId,
+        name: &str,
+    ) -> Option<BrowsingContextId> {
+        if !self.contexts.contains_key(&parent) {
+            return None;
+        }
+
+        let mut context = BrowsingContext::new(self.event_loop.clone());
+        context.name = name.to_string();
+        context.parent = Some(parent);
+        context.container = Some(container);
+        context.window.device_size = self.contexts[&parent].window.device_size;
+
+        // The initial about:blank document of a frame has the origin of the document it is in
+        let origin = self.contexts[&parent].origin();
+        context.active_document().get_mut().set_origin(origin);
+
+        let id = self.insert(context);
+        self.contexts.get_mut(&parent)?.children.push(id);
+        Some(id)
+    }
+
+    /// Returns the active document of the target browsing context for a script running in the
+    /// accessor browsing context (e.g. through `window.parent.document`). Documents can only be
+    /// accessed from the same origin.
+    pub fn access_document(
+        &self,
+        accessor: BrowsingContextId,
+        target: BrowsingContextId,
+    ) -> Result<DocumentHandle> {
+        let (Some(accessor), Some(target)) = (self.get(accessor), self.get(target)) else {
+            return Err(Error::Security(
+                "browsing context does not exist".to_string(),
+            ));
+        };
+
+        let (accessor_origin, target_origin) = (accessor.origin(), target.origin());
+        if !accessor_origin.same_origin(&target_origin) {
+            return Err(Error::Security(format!(
+                "blocked a frame with origin {} from accessing a frame with origin {}",
+                accessor_origin.serialize(),
+                target_origin.serialize()
+            )));
+        }
+        Ok(target.active_document())
+    }
+
+    /// Returns the browsing context with the given id
+    pub fn get(&self, id: BrowsingContextId) -> Option<&BrowsingContext> {
+        self.contexts.get(&id)
+    }
+
+    /// Returns the browsing context with the given id as a mutable reference
+    pub fn get_mut(&mut self, id: BrowsingContextId) -> Option<&mut BrowsingContext> {
+        self.contexts.get_mut(&id)
+    }
+
+    /// Returns all top-level browsing contexts
+    pub fn top_level(&self) -> &[BrowsingContextId] {
+        &self.top_level
+    }
+
+    /// Returns the top-level browsing context the given browsing context is nested in
+    pub fn top(&self, id: BrowsingContextId) -> Option<BrowsingContextId> {
+        let mut current = id;
+        loop {
+            match self.contexts.get(&current)?.parent {
+                Some(parent) => current = parent,
+                None => return Some(current),
+            }
+        }
+    }
+
+    /// Returns the parent of the given browsing context
+    pub fn parent(&self, id: BrowsingContextId) -> Option<BrowsingContextId> {
+        self.contexts.get(&id)?.parent
+    }
+
+    /// Returns the children of the given browsing context
+    pub fn children(&self, id: BrowsingContextId) -> &[BrowsingContextId] {
+        self.contexts
+            .get(&id)
+            .map(|c| c.children.as_slice())
+            .unwrap_or_default()
+    }
+
+    /// Returns the given browsing context and all browsing contexts nested in it, in tree order
+    pub fn descendants(&self, id: BrowsingContextId) -> Vec<BrowsingContextId> {
+        let mut result = Vec::new();
+
+        let mut stack = vec![id];
+        while let Some(id) = stack.pop() {
+            if !self.contexts.contains_key(&id) {
+                continue;
+            }
+            result.push(id);
+            stack.extend(self.children(id).iter().rev());
+        }
+
+        result
+    }
+
+    /// Finds (or creates) the browsing context that a link or form with the given target in the
+    /// source browsing context should navigate. Unknown names result in a new top-level
+    /// browsing context with that name.
+    ///
+    /// See: https://html.spec.whatwg.org/multipage/document-sequences.html#the-rules-for-choosing-a-navigable
+    pub fn choose_target(
+        &mut self,
+        source: BrowsingContextId,
+        target: &str,
+    ) -> Option<BrowsingContextId> {
+        if !self.contexts.contains_key(&source) {
+            return None;
+        }
+
+        let target = target.trim();
+        match target.to_ascii_lowercase().as_str() {
+            "" | "_self" => return Some(source),
+            "_parent" => return Some(self.parent(source).unwrap_or(source)),
+            "_top" => return self.top(source),
+            "_blank" => return Some(self.create_top_level("")),
+            _ => {}
+        }
+
+        // Search the frame tree of the source first, then all other top-level contexts
+        let top = self.top(source)?;
+        let mut candidates = self.descendants(top);
+        for other in self.top_level.iter().filter(|id| **id != top) {
+            candidates.extend(self.descendants(*other));
+        }
+
+        if let Some(id) = candidates
+            .into_iter()
+            .find(|id| self.contexts[id].name == target)
+        {
+            return Some(id);
+        }
+
+        Some(self.create_top_level(target))
+    }
+
+    /// Removes the browsing context together with all browsing contexts nested in it
+    pub fn remove(&mut self, id: BrowsingContextId) {
+        if let Some(parent) = self.parent(id) {
+            if let Some(parent) = self.contexts.get_mut(&parent) {
+                parent.children.retain(|child| *child != id);
+            }
+        }
+        self.top_level.retain(|top| *top != id);
+
+        for id in self.descendants(id) {
+            self.contexts.remove(&id);
+        }
+    }
+
+    /// Removes the nested browsing contexts whose frame element is no longer connected to the
+    /// active document of the given browsing context (e.g. because the iframe has been removed
+    /// from the DOM). Returns the ids of the removed child browsing contexts.
+    pub fn remove_disconnected_frames(&mut self, id: BrowsingContextId) -> Vec<BrowsingContextId> {
+        let Some(context) = self.contexts.get(&id) else {
+            return Vec::new();
+        };
+
+        let document = context.active_document();
+        let removed = context
+            .children
+            .iter()
+            .filter(|child| {
+                self.contexts[child]
+                    .container
+                    .map_or(true, |node_id| !document.get().is_connected(node_id))
+            })
+            .copied()
+            .collect::<Vec<_>>();
+
+        for child in &removed {
+            self.remove(*child);
+        }
+        removed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::event_loop::FrameClock;
+    use crate::html5::animation_frame::FrameRequestCallback;
+
+    #[test]
+    fn new_browsing_context() {
+        let context = BrowsingContext::new(EventLoopHandle::new());
+
+        assert_eq!(context.current_entry().url.as_str(), "about:blank");
+        assert_eq!(
+            context.active_document().get().url,
+            Some(context.current_entry().url.clone())
+        );
+        assert!(!context.can_go_back());
+        assert!(!context.can_go_forward());
+    }
+
+    #[test]
+    fn load_and_traverse_history() {
+        let mut context = BrowsingContext::new(EventLoopHandle::new());
+        let first = Url::parse("https://example.com/").unwrap();
+        let second = Url::parse("https://example.com/second").unwrap();
+
+        context
+            .load_html(first.clone(), "<title>First</title>")
+            .unwrap();
+        context
+            .load_html(second.clone(), "<title>Second</title>")
+            .unwrap();
+        assert_eq!(context.session_history().len(), 3);
+        assert_eq!(context.current_entry().title, Some("Second".to_string()));
+        assert_eq!(context.active_document().get().url, Some(second));
+
+        assert!(context.traverse_history(-1));
+        assert_eq!(context.active_document().get().url, Some(first.clone()));
+        assert!(context.can_go_forward());
+        assert!(!context.traverse_history(-5));
+
+        // Loading a new document removes the forward entries
+        context
+            .load_html(Url::parse("https://example.com/third").unwrap(), "")
+            .unwrap();
+        assert_eq!(context.session_history().len(), 3);
+        assert!(!context.can_go_forward());
+    }
+
+    #[test]
+    fn window_viewport() {
+        let mut context = BrowsingContext::new(EventLoopHandle::new());
+        context.set_device_size(DeviceSize::new(375.0, 812.0));
+        context
+            .load_html(
+                Url::parse("https://example.com/").unwrap(),
+                r#"<meta name="viewport" content="width=device-width">"#,
+            )
+            .unwrap();
+
+        let viewport = context.window().viewport();
+        assert_eq!(viewport.width, 375.0);
+    }
+
+    #[test]
+    fn update_the_rendering() {
+        struct Recorder(Rc<RefCell<Vec<String>>>);
+
+        impl Renderer for Recorder {
+            fn update_layout(&mut self, _document: &DocumentHandle) {
+                self.0.borrow_mut().push("layout".into());
+            }
+
+            fn paint(&mut self, _document: &DocumentHandle) {
+                self.0.borrow_mut().push("paint".into());
+            }
+        }
+
+        let event_loop = EventLoopHandle::new();
+        event_loop.set_frame_clock(FrameClock::with_refresh_rate(100.0));
+        let context = BrowsingContext::new(event_loop);
+        let log = Rc::new(RefCell::new(Vec::new()));
+        let mut renderer = Recorder(log.clone());
+
+        let recorder = log.clone();
+        let callback: FrameRequestCallback =
+            Rc::new(move |_, timestamp| recorder.borrow_mut().push(format!("frame {timestamp}")));
+        context
+            .active_document()
+            .get_mut()
+            .request_animation_frame(callback);
+
+        assert!(context.window().render_if_due(3.0, &mut renderer));
+        assert!(!context.window().render_if_due(8.0, &mut renderer));
+        assert!(context.window().render_if_due(14.0, &mut renderer));
+        assert_eq!(
+            *log.borrow(),
+            ["frame 3", "layout", "paint", "layout", "paint"]
+        );
+    }
+
+    fn frame_tree() -> (
+        FrameTree,
+        BrowsingContextId,
+        BrowsingContextId,
+        BrowsingContextId,
+    ) {
+        let mut tree = FrameTree::new(EventLoopHandle::new());
+        let top = tree.create_top_level("");
+        tree.get_mut(top)
+            .unwrap()
+            .load_html(
+                Url::parse("https://example.com/").unwrap(),
+                r#"<iframe id="menu" name="menu"></iframe><iframe id="main"></iframe>"#,
+            )
+            .unwrap();
+
+        let document = tree.get(top).unwrap().active_document();
+        let menu_node = document.get().get_node_by_named_id("menu").unwrap().id;
+        let main_node = document.get().get_node_by_named_id("main").unwrap().id;
+
+        let menu = tree.create_child(top, menu_node, "menu").unwrap();
+        let main = tree.create_child(top, main_node, "main").unwrap();
+        (tree, top, menu, main)
+    }
+
+    #[test]
+    fn frame_tree_traversal() {
+        let (mut tree, top, menu, main) = frame_tree();
+        let nested = tree.create_child(main, NodeId::root(), "nested").unwrap();
+
+        assert_eq!(tree.children(top), &[menu, main]);
+        assert_eq!(tree.parent(nested), Some(main));
+        assert_eq!(tree.top(nested), Some(top));
+        assert_eq!(tree.descendants(top), vec![top, menu, main, nested]);
+    }
+
+    #[test]
+    fn choose_navigation_target() {
+        let (mut tree, top, menu, main) = frame_tree();
+
+        assert_eq!(tree.choose_target(menu, ""), Some(menu));
+        assert_eq!(tree.choose_target(menu, "_self"), Some(menu));
+        assert_eq!(tree.choose_target(menu, "_parent"), Some(top));
+        assert_eq!(tree.choose_target(menu, "_top"), Some(top));
+        assert_eq!(tree.choose_target(menu, "main"), Some(main));
+
+        let blank = tree.choose_target(menu, "_blank").unwrap();
+        assert!(tree.top_level().contains(&blank));
+
+        let named = tree.choose_target(menu, "help").unwrap();
+        assert_eq!(tree.get(named).unwrap().name, "help");
+        assert_eq!(tree.choose_target(main, "help"), Some(named));
+    }
+
+    #[test]
+    fn remove_disconnected_frames() {
+        let (mut tree, top, menu, main) = frame_tree();
+        let nested = tree.create_child(main, NodeId::root(), "nested").unwrap();
+
+        let mut document = tree.get(top).unwrap().active_document();
+        let main_node = tree.get(main).unwrap().container().unwrap();
+        document.detach_node_from_parent(main_node);
+
+        assert_eq!(tree.remove_disconnected_frames(top), vec![main]);
+        assert_eq!(tree.children(top), &[menu]);
+        assert!(tree.get(main).is_none());
+        assert!(tree.get(nested).is_none());
+    }
+
+    #[test]
+    fn cross_origin_frame_access() {
+        let (mut tree, top, menu, main) = frame_tree();
+
+        // Frames start with the origin of their parent
+        assert_eq!(
+            tree.get(menu).unwrap().origin(),
+            tree.get(top).unwrap().origin()
+        );
+        assert!(tree.access_document(menu, top).is_ok());
+
+        tree.get_mut(main)
+            .unwrap()
+            .load_html(Url::parse("https://ads.example.net/").unwrap(), "")
+            .unwrap();
+        assert!(tree.access_document(main, top).is_err());
+        assert!(tree.access_document(top, main).is_err());
+        assert!(tree.access_document(main, main).is_ok());
+
+        tree.get_mut(menu)
+            .unwrap()
+            .load_html(Url::parse("https://example.com/menu").unwrap(), "")
+            .unwrap();
+        assert!(tree.access_document(top, menu).is_ok());
+
+        // Unrelated about:blank documents have different opaque origins
+        let first = tree.create_top_level("");
+        let second = tree.create_top_level("");
+        assert!(tree.get(first).unwrap().origin().is_opaque());
+        assert!(tree.access_document(first, second).is_err());
+    }
+
+    /// Keeps navigation within example.com, and sends http to https
+    struct ScopePolicy;
+
+    impl NavigationPolicy for ScopePolicy {
+        fn decide(&mut self, request: &NavigationRequest) -> NavigationDecision {
+            if request.url.host_str() != Some("example.com") {
+                return NavigationDecision::Block;
+            }
+            if request.url.scheme() == "http" {
+                let mut url = request.url.clone();
+                url.set_scheme("https").unwrap();
+                return NavigationDecision::Redirect(url);
+            }
+            if request.cause == NavigationCause::MetaRefresh {
+                return NavigationDecision::Delay(std::time::Duration::from_secs(10));
+            }
+            NavigationDecision::Allow
+        }
+    }
+
+    #[test]
+    fn navigation_policy() {
+        let mut tree = FrameTree::new(EventLoopHandle::new());
+        let top = tree.create_top_level("");
+        tree.get_mut(top)
+            .unwrap()
+            .load_html(
+                Url::parse("https://example.com/").unwrap(),
+                r#"<a id="internal" href="http://example.com/about">about</a>
+                   <a id="external" href="https://other.com/" rel="noreferrer">other</a>
+                   <a id="popup" href="/help" target="_blank">help</a>"#,
+            )
+            .unwrap();
+        tree.set_navigation_policy(Rc::new(RefCell::new(ScopePolicy)));
+
+        let document = tree.get(top).unwrap().active_document();
+        let node = |id: &str| document.get().get_node_by_named_id(id).unwrap().id;
+
+        let (request, decision) = tree.follow_hyperlink(top, node("internal")).unwrap();
+        assert_eq!(decision, NavigationDecision::Allow);
+        assert_eq!(request.url.as_str(), "https://example.com/about");
+        assert_eq!(request.target, top);
+        assert_eq!(request.referrer.unwrap().as_str(), "https://example.com/");
+
+        let (request, decision) = tree.follow_hyperlink(top, node("external")).unwrap();
+        assert_eq!(decision, NavigationDecision::Block);
+        assert!(request.referrer.is_none());
+
+        let (request, _) = tree.follow_hyperlink(top, node("popup")).unwrap();
+        assert_ne!(request.target, top);
+        assert!(tree.top_level().contains(&request.target));
+
+        let (_, decision) = tree.request_navigation(NavigationRequest {
+            url: Url::parse("https://example.com/refresh").unwrap(),
+            target: top,
+            source: Some(top),
+            cause: NavigationCause::MetaRefresh,
+            referrer: None,
+            referrer_policy: ReferrerPolicy::default(),
+        });
+        assert_eq!(
+            decision,
+            NavigationDecision::Delay(std::time::Duration::from_secs(10))
+        );
+    }
+}
@@ -0,0 +1,182 @@
+//! Navigation policy
+//!
+//! Before a browsing context navigates to another url, the embedder gets the chance to allow,
+//! block, redirect or delay the navigation through a [`NavigationPolicy`]. This is used by
+//! browsers to apply user settings, and by crawlers to keep navigation within scope and to
+//! throttle requests.
+use crate::browsing_context::BrowsingContextId;
+use crate::html5::parser::document::Document;
+use crate::net::referrer::ReferrerPolicy;
+use std::time::Duration;
+use url::Url;
+
+/// Maximum number of redirects a policy can make for a single navigation
+pub const MAX_POLICY_REDIRECTS: usize = 20;
+
+/// What caused a navigation
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum NavigationCause {
+    /// Navigation requested by the embedder (e.g. the user entered an url)
+    Embedder,
+    /// A hyperlink was followed
+    LinkClick,
+    /// A form was submitted
+    FormSubmission,
+    /// A `<meta http-equiv="refresh">` element
+    MetaRefresh,
+    /// Script initiated navigation (e.g. location.href = ...)
+    Script,
+}
+
+/// A navigation that is about to happen
+#[derive(Debug, Clone, PartialEq)]
+pub struct NavigationRequest {
+    /// Destination of the navigation
+    pub url: Url,
+    /// Browsing context that will be navigated
+    pub target: BrowsingContextId,
+    /// Browsing context that initiated the navigation (if any)
+    pub source: Option<BrowsingContextId>,
+    pub cause: NavigationCause,
+    /// Url of the document that initiated the navigation
+    pub referrer: Option<Url>,
+    /// Decides how much of the referrer is sent (see `Fetcher::navigation_request()`)
+    pub referrer_policy: ReferrerPolicy,
+}
+
+/// Decision of a navigation policy
+#[derive(Debug, Clone, PartialEq)]
+pub enum NavigationDecision {
+    /// The navigation can continue
+    Allow,
+    /// The navigation must not happen
+    Block,
+    /// Navigate to the given url instead
+    Redirect(Url),
+    /// The navigation can continue after the given delay (throttling)
+    Delay(Duration),
+}
+
+/// Hook that is consulted before every navigation
+pub trait NavigationPolicy {
+    /// Decides whether the navigation can continue
+    fn decide(&mut self, request: &NavigationRequest) -> NavigationDecision;
+}
+
+/// Parsed content of a `<meta http-equiv="refresh">` element
+#[derive(Debug, Clone, PartialEq)]
+pub struct MetaRefresh {
+    /// Time to wait before refreshing
+    pub delay: Duration,
+    /// Destination, or None when the document itself should be reloaded
+    pub url: Option<Url>,
+}
+
+impl Document {
+    /// Returns the refresh as defined by the first `<meta http-equiv="refresh">` element with
+    /// valid content. Relative urls are resolved against the base url of the document.
+    pub fn meta_refresh(&self) -> Option<MetaRefresh> {
+        self.elements_by_tag_name("meta")
+            .into_iter()
+            .filter_map(|node_id| self.get_node_by_id(node_id))
+            .filter(|node| {
+                node.get_attribute("http-equiv")
+                    .is_some_and(|v| v.trim().eq_ignore_ascii_case("refresh"))
+            })
+            .find_map(|node| {
+                let (delay, url) = parse_meta_refresh(node.get_attribute("content")?)?;
+                let url = match url {
+                    Some(url) => Some(self.resolve_url(&url)?),
+                    None => None,
+                };
+                Some(MetaRefresh { delay, url })
+            })
+    }
+}
+
+/// Parses the content of a meta refresh element (e.g. "5; url=/next")
+///
+/// See: https://html.spec.whatwg.org/multipage/semantics.html#shared-declarative-refresh-steps
+fn parse_meta_refresh(content: &str) -> Option<(Duration, Option<String>)> {
+    let content = content.trim_start();
+
+    let digits = content.chars().take_while(|c| c.is_ascii_digit()).count();
+    let rest = content[digits..].trim_start_matches(|c: char| c.is_ascii_digit() || c == '.');
+    if digits == 0 && !rest.starts_with([';', ',', ' ']) && !content.starts_with('.') {
+        return None;
+    }
+    let seconds = content[..digits].parse::<u64>().unwrap_or(0);
+
+    let rest = rest.trim_start();
+    let rest = rest.strip_prefix([';', ',']).unwrap_or(rest).trim_start();
+    if rest.is_empty() {
+        return Some((Duration::from_secs(seconds), None));
+    }
+
+    // The url can optionally be prefixed with "url="
+    let mut url = rest;
+    if url.len() >= 3 && url[..3].eq_ignore_ascii_case("url") {
+        let after = url[3..].trim_start();
+        if let Some(after) = after.strip_prefix('=') {
+            url = after.trim_start();
+        }
+    }
+
+    let url = match url.chars().next() {
+        Some(quote @ ('"' | '\'')) => url[1..].split(quote).next().unwrap_or_default(),
+        _ => url,
+    };
+
+    Some((Duration::from_secs(seconds), Some(url.trim().to_string())))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bytes::{CharIterator, Encoding};
+    use crate::html5::parser::document::DocumentBuilder;
+    use crate::html5::parser::Html5Parser;
+
+    #[test]
+    fn meta_refresh_content() {
+        assert_eq!(
+            parse_meta_refresh("5"),
+            Some((Duration::from_secs(5), None))
+        );
+        assert_eq!(
+            parse_meta_refresh("0; url=/next"),
+            Some((Duration::from_secs(0), Some("/next".to_string())))
+        );
+        assert_eq!(
+            parse_meta_refresh("3.5, URL = 'https://example.com/a b'"),
+            Some((
+                Duration::from_secs(3),
+                Some("https://example.com/a b".to_string())
+            ))
+        );
+        assert_eq!(
+            parse_meta_refresh("1;/plain"),
+            Some((Duration::from_secs(1), Some("/plain".to_string())))
+        );
+        assert_eq!(parse_meta_refresh("soon"), None);
+    }
+
+    #[test]
+    fn meta_refresh_from_document() {
+        let mut chars = CharIterator::new();
+        chars.read_from_str(
+            r#"<meta http-equiv="Refresh" content="2; url=next.html">"#,
+            Some(Encoding::UTF8),
+        );
+        let mut document = DocumentBuilder::new_document();
+        document.get_mut().url = Some(Url::parse("https://example.com/dir/").unwrap());
+        let _ = Html5Parser::parse_document(&mut chars, Document::clone(&document), None);
+
+        let refresh = document.get().meta_refresh().unwrap();
+        assert_eq!(refresh.delay, Duration::from_secs(2));
+        assert_eq!(
+            refresh.url.unwrap().as_str(),
+            "https://example.com/dir/next.html"
+        );
+    }
+}
@@ -1,25 +1,127 @@
+use crate::bytes::decode::{decode, sniff_bom};
+use crate::bytes::prescan::prescan_charset;
 use crate::html5::tokenizer::{CHAR_CR, CHAR_LF};
+use std::cell::Cell;
 use std::collections::HashMap;
 use std::fs::File;
 use std::io::Read;
 use std::{fmt, io};
 
+mod decode;
+pub mod prescan;
+
 /// Encoding defines the way the buffer stream is read, as what defines a "character".
-#[derive(PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum Encoding {
     /// Stream is of UTF8 characters
     UTF8,
     /// Stream consists of 8-bit ASCII characters
     ASCII,
+    /// Stream is of 16-bit code units, little endian
+    UTF16LE,
+    /// Stream is of 16-bit code units, big endian
+    UTF16BE,
+    Windows1250,
+    Windows1251,
+    /// Western encoding, which is also used for `latin1`, `iso-8859-1` and `ascii` labels
+    Windows1252,
+    Windows1253,
+    Windows1254,
+    Windows1255,
+    Windows1256,
+    Windows1257,
+    Windows1258,
+}
+
+impl Encoding {
+    /// Returns the encoding for the given label (e.g. "utf-8" or "latin1"), or None when the label
+    /// is unknown or not supported
+    ///
+    /// See: https://encoding.spec.whatwg.org/#concept-encoding-get
+    pub fn from_label(label: &str) -> Option<Encoding> {
+        let encoding = encoding_rs::Encoding::for_label(label.as_bytes())?;
+
+        let supported = [
+            Encoding::UTF8,
+            Encoding::UTF16LE,
+            Encoding::UTF16BE,
+            Encoding::Windows1250,
+            Encoding::Windows1251,
+            Encoding::Windows1252,
+            Encoding::Windows1253,
+            Encoding::Windows1254,
+            Encoding::Windows1255,
+            Encoding::Windows1256,
+            Encoding::Windows1257,
+            Encoding::Windows1258,
+        ];
+        supported
+            .into_iter()
+            .find(|supported| supported.name() == encoding.name())
+    }
+
+    /// Returns the name of the encoding (e.g. "UTF-8" or "windows-1252")
+    pub fn name(&self) -> &'static str {
+        match self {
+            Encoding::UTF8 => "UTF-8",
+            Encoding::ASCII => "US-ASCII",
+            Encoding::UTF16LE => "UTF-16LE",
+            Encoding::UTF16BE => "UTF-16BE",
+            Encoding::Windows1250 => "windows-1250",
+            Encoding::Windows1251 => "windows-1251",
+            Encoding::Windows1252 => "windows-1252",
+            Encoding::Windows1253 => "windows-1253",
+            Encoding::Windows1254 => "windows-1254",
+            Encoding::Windows1255 => "windows-1255",
+            Encoding::Windows1256 => "windows-1256",
+            Encoding::Windows1257 => "windows-1257",
+            Encoding::Windows1258 => "windows-1258",
+        }
+    }
+
+    /// Returns the decoder of the single-byte windows encodings
+    fn single_byte_encoding(&self) -> Option<&'static encoding_rs::Encoding> {
+        match self {
+            Encoding::Windows1250 => Some(encoding_rs::WINDOWS_1250),
+            Encoding::Windows1251 => Some(encoding_rs::WINDOWS_1251),
+            Encoding::Windows1252 => Some(encoding_rs::WINDOWS_1252),
+            Encoding::Windows1253 => Some(encoding_rs::WINDOWS_1253),
+            Encoding::Windows1254 => Some(encoding_rs::WINDOWS_1254),
+            Encoding::Windows1255 => Some(encoding_rs::WINDOWS_1255),
+            Encoding::Windows1256 => Some(encoding_rs::WINDOWS_1256),
+            Encoding::Windows1257 => Some(encoding_rs::WINDOWS_1257),
+            Encoding::Windows1258 => Some(encoding_rs::WINDOWS_1258),
+            _ => None,
+        }
+    }
 }
 
 /// The confidence decides how confident we are that the input stream is of this encoding
-#[derive(PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum Confidence {
     /// This encoding might be the one we need
     Tentative,
     /// We are certain to use this encoding
     Certain,
+    /// The input was not decoded from bytes (like a string), so its encoding does not matter
+    Irrelevant,
+}
+
+/// Result of changing the encoding of a stream that is being parsed
+///
+/// See: https://html.spec.whatwg.org/multipage/parsing.html#changing-the-encoding-while-parsing
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum EncodingChange {
+    /// The confidence was not tentative, so the encoding was not changed
+    Ignored,
+    /// The stream already had the encoding, which is now certain
+    Confirmed,
+    /// The input read so far means the same in the new encoding, so the rest of the stream is
+    /// decoded with the new encoding without starting over
+    Switched,
+    /// The input read so far means something else in the new encoding. The stream has been
+    /// decoded again with the new encoding and rewound, and must be parsed again from the start.
+    Restart,
 }
 
 /// This struct defines a position in the stream. POsition itself is 0-based, but line and col are
@@ -47,6 +149,32 @@ impl fmt::Display for Position {
     }
 }
 
+/// Part of the stream, like the characters of a token. The byte offsets are those of the stream
+/// encoded as UTF-8, so they can be used to slice the decoded input.
+#[derive(PartialEq, Debug, Copy, Clone)]
+pub struct Span {
+    /// Position of the first character
+    pub start: Position,
+    /// Position after the last character
+    pub end: Position,
+    /// Offset of the first character in bytes
+    pub start_byte: usize,
+    /// Offset after the last character in bytes
+    pub end_byte: usize,
+}
+
+impl Span {
+    /// Returns the span from the start of this span to the end of the other one
+    pub fn to(&self, other: &Span) -> Span {
+        Span {
+            start: self.start,
+            end: other.end,
+            start_byte: self.start_byte,
+            end_byte: other.end_byte,
+        }
+    }
+}
+
 /// Defines a single character/element in the stream. This is either a UTF8 character, or
 /// a surrogate characters since these cannot be stored in a single char.
 /// Eof is denoted as a separate element.
@@ -94,6 +222,30 @@ impl Bytes {
     }
 }
 
+/// Decides the encoding of a byte stream with the encoding sniffing algorithm: a byte order mark
+/// or the label of the transport layer (like the charset of a `Content-Type` header) is certain.
+/// Otherwise the first bytes are prescanned for a `<meta charset>`, and the fallback encoding is
+/// used when there is none. Both are tentative, so the document can still declare its encoding
+/// while it is parsed.
+///
+/// See: https://html.spec.whatwg.org/multipage/parsing.html#encoding-sniffing-algorithm
+pub fn sniff_encoding(
+    bytes: &[u8],
+    transport_label: Option<&str>,
+    fallback: Encoding,
+) -> (Encoding, Confidence) {
+    if let Some((encoding, _)) = sniff_bom(bytes) {
+        return (encoding, Confidence::Certain);
+    }
+    if let Some(encoding) = transport_label.and_then(Encoding::from_label) {
+        return (encoding, Confidence::Certain);
+    }
+    match prescan_charset(bytes) {
+        Some(encoding) => (encoding, Confidence::Tentative),
+        None => (fallback, Confidence::Tentative),
+    }
+}
+
 /// Buffered UTF-8 iterator
 /// TODO: Implement `Peekable` and `Iterator<Item = char>`
 pub struct CharIterator {
@@ -113,6 +265,16 @@ pub struct CharIterator {
     u8_buffer: Vec<u8>,
     /// If all things are ok, both buffer and u8_buffer should refer to the same memory location (?)
     pub has_read_eof: bool, // True when we just read an EOF
+    /// True while more input can be appended to the stream
+    is_open: bool,
+    /// Set when the end of the buffer is reached while the stream is still open
+    reached_open_end: Cell<bool>,
+    /// Appended bytes that are not decoded yet (an incomplete sequence)
+    pending_bytes: Vec<u8>,
+    /// True when the last decoded character is a CR that might be followed by a LF
+    pending_cr: bool,
+    /// Encoding of byte streams that do not declare their encoding
+    fallback_encoding: Encoding,
 }
 
 impl Default for CharIterator {
@@ -137,6 +299,11 @@ impl CharIterator {
             buffer: Vec::new(),
             u8_buffer: Vec::new(),
             has_read_eof: false,
+            is_open: false,
+            reached_open_end: Cell::new(false),
+            pending_bytes: Vec::new(),
+            pending_cr: false,
+            fallback_encoding: Encoding::UTF8,
         }
     }
 
@@ -145,9 +312,29 @@ impl CharIterator {
         self.confidence == Confidence::Certain
     }
 
-    /// Detect the given encoding from stream analysis
-    pub fn detect_encoding(&self) {
-        todo!()
+    /// Sets the encoding of byte streams that do not declare their encoding (UTF-8 by default).
+    /// Browsers use an encoding that depends on the locale of the user, like windows-1252.
+    pub fn set_fallback_encoding(&mut self, e: Encoding) {
+        self.fallback_encoding = e;
+    }
+
+    /// Detects the encoding of the bytes in the stream with the encoding sniffing algorithm (see
+    /// `sniff_encoding()`) when the encoding is tentative, and decodes the stream again. This is
+    /// meant for streams of bytes that were read without detection (like with
+    /// `read_from_file()`, or with `append_bytes()` once the first bytes have arrived), before
+    /// they are parsed.
+    pub fn detect_encoding(&mut self) {
+        if self.confidence != Confidence::Tentative {
+            return;
+        }
+
+        let (encoding, confidence) = sniff_encoding(&self.u8_buffer, None, self.fallback_encoding);
+        if let Some((_, bom_len)) = sniff_bom(&self.u8_buffer) {
+            self.u8_buffer.drain(..bom_len);
+        }
+        self.confidence = confidence;
+        self.force_set_encoding(encoding);
+        self.reset();
     }
 
     /// Returns true when the stream pointer is at the end of the stream
@@ -188,6 +375,21 @@ impl CharIterator {
         pos
     }
 
+    /// Returns the number of bytes of the characters between the offsets, encoded as UTF-8.
+    /// Surrogates are counted as the replacement character they are read as.
+    pub(crate) fn utf8_len(&self, start: usize, end: usize) -> usize {
+        let end = end.min(self.buffer.len());
+        let start = start.min(end);
+        self.buffer[start..end]
+            .iter()
+            .map(|c| match c {
+                Ch(c) => c.len_utf8(),
+                Surrogate(_) => char::REPLACEMENT_CHARACTER.len_utf8(),
+                _ => 0,
+            })
+            .sum()
+    }
+
     /// Returns the current offset in the stream
     pub fn tell(&self) -> usize {
         self.position.offset
@@ -198,6 +400,29 @@ impl CharIterator {
         self.confidence = c;
     }
 
+    /// Changes the encoding to the encoding that was found while parsing (e.g. in a late
+    /// `<meta charset>`) and makes it certain. Only a tentative encoding is changed.
+    pub fn change_encoding(&mut self, e: Encoding) -> EncodingChange {
+        if self.confidence != Confidence::Tentative {
+            return EncodingChange::Ignored;
+        }
+        self.confidence = Confidence::Certain;
+        if self.encoding == e {
+            return EncodingChange::Confirmed;
+        }
+
+        let read = self.buffer[..self.position.offset].to_vec();
+        self.force_set_encoding(e);
+        if self.buffer.starts_with(&read) {
+            return EncodingChange::Switched;
+        }
+
+        self.reset();
+        self.line_columns.clear();
+        self.has_read_eof = false;
+        EncodingChange::Restart
+    }
+
     /// Changes the encoding and if necessary, decodes the u8 buffer into the correct encoding
     pub fn set_encoding(&mut self, e: Encoding) {
         // Don't convert if the encoding is the same as it already is
@@ -211,68 +436,17 @@ impl CharIterator {
     /// Sets the encoding for this stream, and decodes the u8_buffer into the buffer with the
     /// correct encoding.
     pub fn force_set_encoding(&mut self, e: Encoding) {
-        match e {
-            Encoding::UTF8 => {
-                let str_buf;
-                unsafe {
-                    str_buf = std::str::from_utf8_unchecked(&self.u8_buffer)
-                        .replace("\u{000D}\u{000A}", "\u{000A}")
-                        .replace('\u{000D}', "\u{000A}");
-                }
-
-                // Convert the utf8 string into characters so we can use easy indexing
-                self.buffer = vec![];
-                for c in str_buf.chars() {
-                    // // Check if we have a non-bmp character. This means it's above 0x10000
-                    // let cp = c as u32;
-                    // if cp > 0x10000 && cp <= 0x10FFFF {
-                    //     let adjusted = cp - 0x10000;
-                    //     let lead = ((adjusted >> 10) & 0x3FF) as u16 + 0xD800;
-                    //     let trail = (adjusted & 0x3FF) as u16 + 0xDC00;
-                    //     self.buffer.push(Element::Surrogate(lead));
-                    //     self.buffer.push(Element::Surrogate(trail));
-                    //     continue;
-                    // }
-
-                    if (0xD800..=0xDFFF).contains(&(c as u32)) {
-                        self.buffer.push(Bytes::Surrogate(c as u16));
-                    } else {
-                        self.buffer.push(Ch(c));
-                    }
-                }
-                self.length = self.buffer.len();
-            }
-            Encoding::ASCII => {
-                // Convert the string into characters so we can use easy indexing. Any non-ascii chars (> 0x7F) are converted to '?'
-                self.buffer = self.normalize_newlines_and_ascii(&self.u8_buffer);
-                self.length = self.buffer.len();
-            }
-        }
-
         self.encoding = e;
-    }
 
-    /// Normalizes newlines (CRLF/CR => LF) and converts high ascii to '?'
-    fn normalize_newlines_and_ascii(&self, buffer: &Vec<u8>) -> Vec<Bytes> {
-        let mut result = Vec::with_capacity(buffer.len());
-
-        for i in 0..buffer.len() {
-            if buffer[i] == CHAR_CR as u8 {
-                // convert CR to LF, or CRLF to LF
-                if i + 1 < buffer.len() && buffer[i + 1] == CHAR_LF as u8 {
-                    continue;
-                }
-                result.push(Ch(CHAR_LF));
-            } else if buffer[i] >= 0x80 {
-                // Convert high ascii to ?
-                result.push(Ch('?'));
-            } else {
-                // everything else is ok
-                result.push(Ch(buffer[i] as char))
-            }
+        // In an open stream, an incomplete sequence at the end is decoded when the rest arrives
+        let (chars, decoded) = decode(e, &self.u8_buffer, !self.is_open);
+        self.pending_bytes = self.u8_buffer[decoded..].to_vec();
+        self.buffer = Vec::with_capacity(chars.len());
+        self.pending_cr = false;
+        self.push_chars(chars);
+        if !self.is_open {
+            self.flush_cr();
         }
-
-        result
     }
 
     /// Populates the current buffer with the contents of given file f
@@ -284,13 +458,107 @@ impl CharIterator {
         Ok(())
     }
 
+    /// Populates the current buffer with the bytes, decoded with the encoding of the label (e.g.
+    /// "utf-16le" or "windows-1251", like the charset of a `Content-Type` header). A byte order
+    /// mark overrides the label. Without a (known) label and byte order mark, the encoding
+    /// declared by a `<meta charset>` in the first bytes is used, or else the fallback encoding,
+    /// with a tentative confidence so the document can still declare its encoding.
+    pub fn read_from_bytes(&mut self, bytes: &[u8], label: Option<&str>) {
+        let (encoding, confidence) = sniff_encoding(bytes, label, self.fallback_encoding);
+        let bom_len = sniff_bom(bytes).map_or(0, |(_, bom_len)| bom_len);
+
+        self.u8_buffer = bytes[bom_len..].to_vec();
+        self.confidence = confidence;
+        self.force_set_encoding(encoding);
+        self.reset();
+    }
+
     /// Populates the current buffer with the contents of the given string s
     pub fn read_from_str(&mut self, s: &str, e: Option<Encoding>) {
         self.u8_buffer = Vec::from(s.as_bytes());
+        self.confidence = Confidence::Irrelevant;
         self.force_set_encoding(e.unwrap_or(Encoding::UTF8));
         self.reset();
     }
 
+    /// Empties the stream and opens it for incremental input: chunks of bytes are added with
+    /// `append_bytes()` as they arrive, until the stream is closed. The stream is decoded with the
+    /// fallback encoding until the encoding is detected or changed. Reaching the end of the buffer of an open stream is
+    /// tracked, so readers can retry when more input has arrived.
+    pub fn open(&mut self) {
+        self.u8_buffer.clear();
+        self.buffer.clear();
+        self.pending_bytes.clear();
+        self.pending_cr = false;
+        self.line_columns.clear();
+        self.length = 0;
+        self.encoding = self.fallback_encoding;
+        self.confidence = Confidence::Tentative;
+        self.has_read_eof = false;
+        self.is_open = true;
+        self.reset();
+    }
+
+    /// Returns true when more input can be appended to the stream
+    pub fn is_open(&self) -> bool {
+        self.is_open
+    }
+
+    /// Decodes the chunk and adds it to the end of the stream. Sequences that are split over
+    /// chunks are decoded when the rest arrives.
+    pub fn append_bytes(&mut self, bytes: &[u8]) {
+        self.u8_buffer.extend_from_slice(bytes);
+        self.pending_bytes.extend_from_slice(bytes);
+
+        let (chars, decoded) = decode(self.encoding, &self.pending_bytes, false);
+        self.pending_bytes.drain(..decoded);
+        self.push_chars(chars);
+    }
+
+    /// Closes the stream: no more input is expected, and the end of the buffer is the end of the
+    /// stream
+    pub fn close(&mut self) {
+        let pending = std::mem::take(&mut self.pending_bytes);
+        let (chars, _) = decode(self.encoding, &pending, true);
+        self.push_chars(chars);
+        self.flush_cr();
+        self.is_open = false;
+    }
+
+    /// Returns true when the end of the buffer of the open stream has been reached since the last
+    /// call, and resets the flag
+    pub(crate) fn take_reached_open_end(&self) -> bool {
+        self.reached_open_end.replace(false)
+    }
+
+    /// Adds decoded characters to the buffer, normalizing newlines (CRLF/CR => LF). A CR at the
+    /// end is held back, as it could be the first half of a CRLF.
+    fn push_chars(&mut self, chars: Vec<Bytes>) {
+        for c in chars {
+            if self.pending_cr {
+                self.pending_cr = false;
+                self.buffer.push(Ch(CHAR_LF));
+                if c == Ch(CHAR_LF) {
+                    continue;
+                }
+            }
+            if c == Ch(CHAR_CR) {
+                self.pending_cr = true;
+                continue;
+            }
+            self.buffer.push(c);
+        }
+        self.length = self.buffer.len();
+    }
+
+    /// Adds the held back CR (if any) as a LF
+    fn flush_cr(&mut self) {
+        if std::mem::take(&mut self.pending_cr) {
+            self.buffer.push(Ch(CHAR_LF));
+            self.length = self.buffer.len();
+        }
+    }
+
     /// Returns the number of characters left in the buffer
     #[cfg(test)]
     fn chars_left(&self) -> usize {
@@ -322,6 +590,9 @@ impl CharIterator {
         }
 
         // otherwise, we have reached the end of the stream
+        if self.is_open {
+            self.reached_open_end.set(true);
+        }
         self.has_read_eof = true;
 
         Eof
@@ -360,6 +631,9 @@ impl CharIterator {
 
     /// Looks ahead in the stream and returns len characters
     pub(crate) fn look_ahead_slice(&self, len: usize) -> String {
+        if self.is_open && self.position.offset + len > self.length {
+            self.reached_open_end.set(true);
+        }
         let end_pos = std::cmp::min(self.length, self.position.offset + len);
 
         let slice = &self.buffer[self.position.offset..end_pos];
@@ -371,6 +645,9 @@ impl CharIterator {
     pub(crate) fn look_ahead(&self, offset: usize) -> Bytes {
         // Trying to look after the stream
         if self.position.offset + offset >= self.length {
+            if self.is_open {
+                self.reached_open_end.set(true);
+            }
             return Eof;
         }
 
@@ -381,6 +658,7 @@ impl CharIterator {
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::html5::tokenizer::CHAR_REPLACEMENT;
 
     #[test]
     fn test_stream() {
@@ -505,4 +783,85 @@ mod test {
         chars.unread();
         assert!(matches!(chars.read_char(), Eof));
     }
+
+    #[test]
+    fn test_append_bytes() {
+        let mut chars = CharIterator::new();
+        chars.open();
+        chars.append_bytes(b"a\r");
+        chars.append_bytes(b"\nb\xc3");
+        assert_eq!(chars.read_char(), Ch('a'));
+        assert_eq!(chars.read_char(), Ch('\n'));
+        assert_eq!(chars.read_char(), Ch('b'));
+        assert!(!chars.take_reached_open_end());
+        assert!(matches!(chars.read_char(), Eof));
+        assert!(chars.take_reached_open_end());
+        chars.unread();
+
+        chars.append_bytes(b"\xa9\xff\r");
+        chars.close();
+        assert_eq!(chars.read_char(), Ch('é'));
+        assert_eq!(chars.read_char(), Ch(CHAR_REPLACEMENT));
+        assert_eq!(chars.read_char(), Ch('\n'));
+        assert!(matches!(chars.read_char(), Eof));
+        assert!(!chars.take_reached_open_end());
+    }
+
+    #[test]
+    fn test_read_from_bytes() {
+        let mut chars = CharIterator::new();
+
+        // The byte order mark wins from the label
+        chars.read_from_bytes(b"\xff\xfea\x00\r\x00\n\x00\x00\xd8", Some("windows-1252"));
+        assert_eq!(chars.encoding, Encoding::UTF16LE);
+        assert!(chars.is_certain_encoding());
+        assert_eq!(chars.read_char(), Ch('a'));
+        assert_eq!(chars.read_char(), Ch('\n'));
+        assert_eq!(chars.read_char(), Bytes::Surrogate(0xD800));
+        assert!(matches!(chars.read_char(), Eof));
+
+        chars.read_from_bytes(b"\xcf\xf0\xe8", Some("cp1251"));
+        assert_eq!(chars.encoding, Encoding::Windows1251);
+        assert_eq!(chars.look_ahead_slice(3), "При");
+
+        chars.read_from_bytes(b"caf\xc3\xa9", Some("unknown"));
+        assert_eq!(chars.encoding, Encoding::UTF8);
+        assert!(!chars.is_certain_encoding());
+        assert_eq!(chars.look_ahead_slice(4), "café");
+
+        // Without a label, the prescan finds the declared encoding, or the fallback is used
+        chars.read_from_bytes(b"<meta charset=koi8-r><meta charset=latin1>caf\xe9", None);
+        assert_eq!(chars.encoding, Encoding::Windows1252);
+        assert!(!chars.is_certain_encoding());
+        chars.set_fallback_encoding(Encoding::Windows1251);
+        chars.read_from_bytes(b"\xcf\xf0\xe8", None);
+        assert_eq!(chars.encoding, Encoding::Windows1251);
+        assert!(!chars.is_certain_encoding());
+    }
+
+    #[test]
+    fn test_detect_encoding() {
+        let mut chars = CharIterator::new();
+        chars.open();
+        chars.append_bytes(b"\xef\xbb\xbf<p>caf\xc3\xa9");
+        chars.detect_encoding();
+        assert_eq!(chars.encoding, Encoding::UTF8);
+        assert!(chars.is_certain_encoding());
+        assert_eq!(chars.look_ahead_slice(7), "<p>café");
+
+        chars.open();
+        chars.append_bytes(b"<meta http-equiv=content-type content='text/html; charset=cp1251'>");
+        chars.append_bytes(b"\xcf");
+        chars.detect_encoding();
+        chars.close();
+        assert_eq!(chars.encoding, Encoding::Windows1251);
+        assert!(!chars.is_certain_encoding());
+        chars.skip(66);
+        assert_eq!(chars.look_ahead_slice(1), "П");
+
+        // A certain encoding is not detected again
+        chars.read_from_str("<meta charset=utf-16le>", None);
+        chars.detect_encoding();
+        assert_eq!(chars.encoding, Encoding::UTF8);
+    }
 }
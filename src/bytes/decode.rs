@@ -0,0 +1,192 @@
+//! Decoders
+//!
+//! Turns the bytes of the input stream into characters, for every supported encoding. Decoding
+//! can be done in chunks: a sequence that is split over chunks is left undecoded until the rest
+//! arrives. Invalid sequences are decoded as U+FFFD, except for unpaired UTF-16 surrogates, which
+//! are kept as surrogates so the tokenizer can report them.
+//!
+//! See: https://encoding.spec.whatwg.org/
+use crate::bytes::{Bytes, Encoding};
+use crate::html5::tokenizer::CHAR_REPLACEMENT;
+
+/// Decodes the bytes with the encoding. Returns the characters and the number of bytes that have
+/// been decoded. The remaining bytes are an incomplete sequence, which is decoded when more bytes
+/// are appended to it, or as an error when `last` is set.
+pub(crate) fn decode(encoding: Encoding, bytes: &[u8], last: bool) -> (Vec<Bytes>, usize) {
+    match encoding {
+        Encoding::UTF8 => decode_utf8(bytes, last),
+        Encoding::UTF16LE => decode_utf16(bytes, last, u16::from_le_bytes),
+        Encoding::UTF16BE => decode_utf16(bytes, last, u16::from_be_bytes),
+        Encoding::ASCII => {
+            // Any non-ascii chars (> 0x7F) are converted to '?'
+            let chars = bytes
+                .iter()
+                .map(|&b| Bytes::Ch(if b < 0x80 { b as char } else { '?' }))
+                .collect();
+            (chars, bytes.len())
+        }
+        _ => {
+            let Some(single_byte) = encoding.single_byte_encoding() else {
+                return (Vec::new(), bytes.len());
+            };
+            let (text, _) = single_byte.decode_without_bom_handling(bytes);
+            (text.chars().map(Bytes::Ch).collect(), bytes.len())
+        }
+    }
+}
+
+fn decode_utf8(bytes: &[u8], last: bool) -> (Vec<Bytes>, usize) {
+    let mut chars = Vec::with_capacity(bytes.len());
+    let mut rest = bytes;
+
+    while !rest.is_empty() {
+        match std::str::from_utf8(rest) {
+            Ok(valid) => {
+                chars.extend(valid.chars().map(Bytes::Ch));
+                rest = &[];
+            }
+            Err(error) => {
+                let (valid, invalid) = rest.split_at(error.valid_up_to());
+                chars.extend(String::from_utf8_lossy(valid).chars().map(Bytes::Ch));
+                match error.error_len() {
+                    Some(len) => {
+                        chars.push(Bytes::Ch(CHAR_REPLACEMENT));
+                        rest = &invalid[len..];
+                    }
+                    // Incomplete sequence at the end of the bytes
+                    None if last => {
+                        chars.push(Bytes::Ch(CHAR_REPLACEMENT));
+                        rest = &[];
+                    }
+                    None => {
+                        rest = invalid;
+                        break;
+                    }
+                }
+            }
+        }
+    }
+
+    (chars, bytes.len() - rest.len())
+}
+
+fn decode_utf16(bytes: &[u8], last: bool, read: fn([u8; 2]) -> u16) -> (Vec<Bytes>, usize) {
+    let unit = |pos: usize| read([bytes[pos], bytes[pos + 1]]);
+    let mut chars = Vec::with_capacity(bytes.len() / 2);
+    let mut pos = 0;
+
+    while pos + 1 < bytes.len() {
+        let lead = unit(pos);
+        match lead {
+            0xD800..=0xDBFF if pos + 3 < bytes.len() => {
+                let trail = unit(pos + 2);
+                if (0xDC00..=0xDFFF).contains(&trail) {
+                    let c = 0x10000 + ((lead as u32 - 0xD800) << 10) + (trail as u32 - 0xDC00);
+                    chars.push(Bytes::Ch(char::from_u32(c).unwrap_or(CHAR_REPLACEMENT)));
+                    pos += 4;
+                    continue;
+                }
+                chars.push(Bytes::Surrogate(lead));
+            }
+            // The trail surrogate may be in the next chunk
+            0xD800..=0xDBFF if !last => break,
+            0xD800..=0xDFFF => chars.push(Bytes::Surrogate(lead)),
+            _ => chars.push(Bytes::Ch(
+                char::from_u32(lead as u32).unwrap_or(CHAR_REPLACEMENT),
+            )),
+        }
+        pos += 2;
+    }
+
+    // A single byte without the second byte of its code unit
+    if last && pos < bytes.len() {
+        chars.push(Bytes::Ch(CHAR_REPLACEMENT));
+        pos = bytes.len();
+    }
+
+    (chars, pos)
+}
+
+/// Returns the encoding of the byte order mark the bytes start with (if any), and the length of
+/// the byte order mark
+pub(crate) fn sniff_bom(bytes: &[u8]) -> Option<(Encoding, usize)> {
+    if bytes.starts_with(&[0xEF, 0xBB, 0xBF]) {
+        Some((Encoding::UTF8, 3))
+    } else if bytes.starts_with(&[0xFE, 0xFF]) {
+        Some((Encoding::UTF16BE, 2))
+    } else if bytes.starts_with(&[0xFF, 0xFE]) {
+        Some((Encoding::UTF16LE, 2))
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decoded(encoding: Encoding, bytes: &[u8], last: bool) -> (String, usize) {
+        let (chars, len) = decode(encoding, bytes, last);
+        (chars.iter().map(|c| c.to_string()).collect(), len)
+    }
+
+    #[test]
+    fn utf16() {
+        // "a€😀" in both byte orders
+        let le = [0x61, 0x00, 0xAC, 0x20, 0x3D, 0xD8, 0x00, 0xDE];
+        let be = [0x00, 0x61, 0x20, 0xAC, 0xD8, 0x3D, 0xDE, 0x00];
+        assert_eq!(decoded(Encoding::UTF16LE, &le, true), ("a€😀".into(), 8));
+        assert_eq!(decoded(Encoding::UTF16BE, &be, true), ("a€😀".into(), 8));
+
+        // Split code units and surrogate pairs wait for the rest
+        assert_eq!(
+            decoded(Encoding::UTF16LE, &le[..5], false),
+            ("a€".into(), 4)
+        );
+        assert_eq!(
+            decoded(Encoding::UTF16LE, &le[..7], false),
+            ("a€".into(), 4)
+        );
+        assert_eq!(
+            decoded(Encoding::UTF16LE, &le[..7], true),
+            ("a€U+D83D\u{FFFD}".into(), 7)
+        );
+
+        // Unpaired surrogates are kept
+        let (chars, _) = decode(
+            Encoding::UTF16BE,
+            &[0xDC, 0x00, 0xD8, 0x00, 0x00, 0x61],
+            true,
+        );
+        assert_eq!(
+            chars,
+            [
+                Bytes::Surrogate(0xDC00),
+                Bytes::Surrogate(0xD800),
+                Bytes::Ch('a')
+            ]
+        );
+    }
+
+    #[test]
+    fn single_byte() {
+        assert_eq!(
+            decoded(Encoding::Windows1252, b"caf\xe9 \x80", true),
+            ("café €".into(), 6)
+        );
+        assert_eq!(
+            decoded(Encoding::Windows1251, b"\xcf\xf0\xe8", true),
+            ("При".into(), 3)
+        );
+        assert_eq!(
+            decoded(Encoding::UTF8, b"caf\xc3", false),
+            ("caf".into(), 3)
+        );
+        assert_eq!(
+            decoded(Encoding::UTF8, b"caf\xc3", true),
+            ("caf\u{FFFD}".into(), 4)
+        );
+        assert_eq!(sniff_bom(b"\xff\xfea\x00"), Some((Encoding::UTF16LE, 2)));
+        assert_eq!(sniff_bom(b"abc"), None);
+    }
+}
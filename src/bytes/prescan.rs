@@ -0,0 +1,299 @@
+//! Encoding prescan
+//!
+//! Determines the character encoding of a document by looking at `<meta charset>` and
+//! `<meta http-equiv="content-type">` elements in the first bytes of the stream, without
+//! tokenizing or parsing the document. This is useful for proxies and crawlers that only need
+//! the charset decision before deciding how to process a body.
+//!
+//! See: https://html.spec.whatwg.org/multipage/parsing.html#prescan-a-byte-stream-to-determine-its-encoding
+use crate::bytes::Encoding;
+use std::collections::HashSet;
+
+/// Number of bytes that are examined by the prescan
+pub const PRESCAN_LIMIT: usize = 1024;
+
+/// Prescans the first [`PRESCAN_LIMIT`] bytes of the given stream for a meta charset declaration.
+/// Returns None when no (supported) encoding is declared.
+pub fn prescan_charset(bytes: &[u8]) -> Option<Encoding> {
+    let input = &bytes[..bytes.len().min(PRESCAN_LIMIT)];
+    let mut pos = 0;
+
+    while pos < input.len() {
+        let rest = &input[pos..];
+
+        if rest.starts_with(b"<!--") {
+            // The "-->" may share its dashes with the "<!--" (as in "<!-->")
+            pos = pos + 2 + find(&input[pos + 2..], b"-->")? + 3;
+            continue;
+        }
+
+        if rest.len() >= 6
+            && rest[..5].eq_ignore_ascii_case(b"<meta")
+            && (is_space(rest[5]) || rest[5] == b'/')
+        {
+            pos += 6;
+            if let Some(encoding) = process_meta(input, &mut pos) {
+                return Some(encoding);
+            }
+        } else if rest.len() >= 3
+            && rest[0] == b'<'
+            && (rest[1].is_ascii_alphabetic() || (rest[1] == b'/' && rest[2].is_ascii_alphabetic()))
+        {
+            // Skip the tag name and all its attributes
+            pos += if rest[1] == b'/' { 2 } else { 1 };
+            while pos < input.len() && !is_space(input[pos]) && input[pos] != b'>' {
+                pos += 1;
+            }
+            while get_attribute(input, &mut pos).is_some() {}
+        } else if rest.starts_with(b"<!") || rest.starts_with(b"</") || rest.starts_with(b"<?") {
+            pos += find(rest, b">")? + 1;
+            continue;
+        }
+
+        pos += 1;
+    }
+
+    None
+}
+
+/// Processes the attributes of a meta element and returns the declared encoding (if any)
+fn process_meta(input: &[u8], pos: &mut usize) -> Option<Encoding> {
+    let mut attribute_list = HashSet::new();
+    let mut got_pragma = false;
+    let mut need_pragma = None;
+    let mut charset = None;
+
+    while let Some((name, value)) = get_attribute(input, pos) {
+        if !attribute_list.insert(name.clone()) {
+            continue;
+        }
+
+        match name.as_str() {
+            "http-equiv" if value == "content-type" => got_pragma = true,
+            "content" if charset.is_none() => {
+                if let Some(label) = extract_charset_from_content(&value) {
+                    charset = prescan_encoding(&label);
+                    need_pragma = Some(true);
+                }
+            }
+            "charset" if charset.is_none() => {
+                charset = prescan_encoding(&value);
+                need_pragma = Some(false);
+            }
+            _ => {}
+        }
+    }
+
+    match need_pragma {
+        None => None,
+        Some(true) if !got_pragma => None,
+        _ => charset,
+    }
+}
+
+/// Returns the encoding for the given label, taking the prescan overrides into account
+pub(crate) fn prescan_encoding(label: &str) -> Option<Encoding> {
+    match label.trim_matches(|c: char| c.is_ascii_whitespace()) {
+        // A document cannot declare itself as UTF-16, as the prescan would not have worked
+        "utf-16" | "utf-16be" | "utf-16le" | "unicodefffe" | "unicodefeff" | "ucs-2"
+        | "iso-10646-ucs-2" | "csunicode" | "unicode" => Some(Encoding::UTF8),
+        "x-user-defined" => Some(Encoding::Windows1252),
+        label => Encoding::from_label(label),
+    }
+}
+
+/// Reads the next attribute from the stream, with lowercased name and value. Returns None when
+/// the end of the tag (or the stream) has been reached.
+///
+/// See: https://html.spec.whatwg.org/multipage/parsing.html#concept-get-attributes-when-sniffing
+fn get_attribute(input: &[u8], pos: &mut usize) -> Option<(String, String)> {
+    while *pos < input.len() && (is_space(input[*pos]) || input[*pos] == b'/') {
+        *pos += 1;
+    }
+    if *pos >= input.len() || input[*pos] == b'>' {
+        return None;
+    }
+
+    let mut name = Vec::new();
+    let mut value = Vec::new();
+
+    // Attribute name
+    loop {
+        let b = *input.get(*pos)?;
+        match b {
+            b'=' if !name.is_empty() => break,
+            b if is_space(b) => {
+                skip_spaces(input, pos);
+                if input.get(*pos) != Some(&b'=') {
+                    return Some((to_string(name), String::new()));
+                }
+                break;
+            }
+            b'/' | b'>' => return Some((to_string(name), String::new())),
+            b => name.push(b.to_ascii_lowercase()),
+        }
+        *pos += 1;
+    }
+
+    // Skip the '=' and the spaces after it
+    *pos += 1;
+    skip_spaces(input, pos);
+
+    // Attribute value
+    match *input.get(*pos)? {
+        quote @ (b'"' | b'\'') => {
+            *pos += 1;
+            loop {
+                let b = *input.get(*pos)?;
+                *pos += 1;
+                if b == quote {
+                    return Some((to_string(name), to_string(value)));
+                }
+                value.push(b.to_ascii_lowercase());
+            }
+        }
+        b'>' => Some((to_string(name), String::new())),
+        _ => {
+            while let Some(&b) = input.get(*pos) {
+                if is_space(b) || b == b'>' {
+                    break;
+                }
+                value.push(b.to_ascii_lowercase());
+                *pos += 1;
+            }
+            Some((to_string(name), to_string(value)))
+        }
+    }
+}
+
+/// Extracts the charset label from the content attribute of a meta element, or from a
+/// Content-Type header (e.g. "text/html; charset=utf-8")
+///
+/// See: https://html.spec.whatwg.org/multipage/urls-and-fetching.html#algorithm-for-extracting-a-character-encoding-from-a-meta-element
+pub fn extract_charset_from_content(content: &str) -> Option<String> {
+    let content = content.as_bytes();
+    let mut pos = 0;
+
+    loop {
+        pos += find_ignore_case(&content[pos..], b"charset")? + 7;
+        skip_spaces(content, &mut pos);
+        if content.get(pos) == Some(&b'=') {
+            break;
+        }
+    }
+
+    pos += 1;
+    skip_spaces(content, &mut pos);
+
+    match *content.get(pos)? {
+        quote @ (b'"' | b'\'') => {
+            let len = find(&content[pos + 1..], &[quote])?;
+            Some(to_string(content[pos + 1..pos + 1 + len].to_vec()))
+        }
+        _ => {
+            let len = content[pos..]
+                .iter()
+                .position(|&b| is_space(b) || b == b';')
+                .unwrap_or(content.len() - pos);
+            Some(to_string(content[pos..pos + len].to_vec()))
+        }
+    }
+}
+
+fn is_space(b: u8) -> bool {
+    matches!(b, b'\t' | b'\n' | b'\x0C' | b'\r' | b' ')
+}
+
+fn skip_spaces(input: &[u8], pos: &mut usize) {
+    while *pos < input.len() && is_space(input[*pos]) {
+        *pos += 1;
+    }
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|w| w == needle)
+}
+
+fn find_ignore_case(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|w| w.eq_ignore_ascii_case(needle))
+}
+
+fn to_string(bytes: Vec<u8>) -> String {
+    String::from_utf8_lossy(&bytes).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn meta_charset() {
+        assert_eq!(
+            prescan_charset(b"<!doctype html><META CHARSET='UTF-8'>"),
+            Some(Encoding::UTF8)
+        );
+        assert_eq!(
+            prescan_charset(b"<meta charset=latin1><meta charset=utf-8>"),
+            Some(Encoding::Windows1252)
+        );
+        assert_eq!(
+            prescan_charset(b"<meta name=x charset=\"utf-16le\">"),
+            Some(Encoding::UTF8)
+        );
+        assert_eq!(prescan_charset(b"<meta charset=shift_jis>"), None);
+        assert_eq!(prescan_charset(b"<html><body>no charset"), None);
+    }
+
+    #[test]
+    fn meta_http_equiv() {
+        assert_eq!(
+            prescan_charset(
+                b"<meta http-equiv=\"Content-Type\" content=\"text/html; charset=us-ascii\">"
+            ),
+            Some(Encoding::Windows1252)
+        );
+        assert_eq!(
+            prescan_charset(
+                b"<meta content='text/html; charset=\"utf-8\"' http-equiv=content-type>"
+            ),
+            Some(Encoding::UTF8)
+        );
+        // Content without the pragma is ignored
+        assert_eq!(
+            prescan_charset(b"<meta content=\"text/html; charset=utf-8\">"),
+            None
+        );
+    }
+
+    #[test]
+    fn skips_comments_and_other_markup() {
+        assert_eq!(
+            prescan_charset(b"<!-- <meta charset=latin1> --><meta charset=utf8>"),
+            Some(Encoding::UTF8)
+        );
+        assert_eq!(
+            prescan_charset(b"<title data-x='<meta charset=latin1>'></title><?pi?>"),
+            None
+        );
+
+        let mut late = vec![b' '; PRESCAN_LIMIT];
+        late.extend_from_slice(b"<meta charset=utf-8>");
+        assert_eq!(prescan_charset(&late), None);
+    }
+
+    #[test]
+    fn charset_from_content() {
+        assert_eq!(
+            extract_charset_from_content("text/html; charset = utf-8;"),
+            Some("utf-8".to_string())
+        );
+        assert_eq!(
+            extract_charset_from_content("charsetcharset='latin1'"),
+            Some("latin1".to_string())
+        );
+        assert_eq!(extract_charset_from_content("charset=\"utf-8"), None);
+        assert_eq!(extract_charset_from_content("text/html"), None);
+    }
+}
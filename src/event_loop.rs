@@ -0,0 +1,240 @@
+//! Event loop
+//!
+//! Work that should not be done right away (firing events, running callbacks, navigating) is
+//! queued as a task on the event loop. Tasks are executed in order by the embedder calling
+//! [`EventLoopHandle::run_next_task`] or [`EventLoopHandle::run_until_idle`]. After every task,
+//! all queued microtasks are executed (microtask checkpoint).
+//!
+//! Between tasks, the event loop can have a rendering opportunity, at which the documents are
+//! rendered again. The `FrameClock` of the event loop decides when that is: at a fixed refresh
+//! rate, or whenever the embedder asks when it syncs with the display itself.
+//!
+//! See: https://html.spec.whatwg.org/multipage/webappapis.html#event-loops
+use core::fmt;
+use core::fmt::Debug;
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::rc::Rc;
+
+/// Source of a task. Used for debugging and for prioritizing tasks in the future.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TaskSource {
+    DomManipulation,
+    UserInteraction,
+    Networking,
+    Navigation,
+    Timer,
+    Rendering,
+}
+
+type Callback = Box<dyn FnOnce()>;
+
+struct Task {
+    source: TaskSource,
+    callback: Callback,
+}
+
+/// Default refresh rate of the frame clock
+pub const DEFAULT_REFRESH_RATE: f64 = 60.0;
+
+/// Clock that decides when the event loop has a rendering opportunity
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FrameClock {
+    /// Time between frames in milliseconds
+    interval: f64,
+    /// Time of the last frame in milliseconds
+    last_frame: Option<f64>,
+}
+
+impl Default for FrameClock {
+    fn default() -> Self {
+        Self::with_refresh_rate(DEFAULT_REFRESH_RATE)
+    }
+}
+
+impl FrameClock {
+    /// Creates a clock that has a frame at the given rate (in frames per second), like the vsync
+    /// of a display
+    pub fn with_refresh_rate(refresh_rate: f64) -> Self {
+        Self {
+            interval: 1000.0 / refresh_rate,
+            last_frame: None,
+        }
+    }
+
+    /// Creates a clock that has a frame whenever it is asked, for embedders that drive the frames
+    /// themselves
+    pub fn embedder_driven() -> Self {
+        Self {
+            interval: 0.0,
+            last_frame: None,
+        }
+    }
+
+    /// Returns the time of the frame when there is a rendering opportunity at the given time (in
+    /// milliseconds). Frame times are aligned to the refresh rate, so frames that were missed are
+    /// skipped instead of rendered late.
+    pub fn rendering_opportunity(&mut self, now: f64) -> Option<f64> {
+        let frame = match self.last_frame {
+            Some(last_frame) if now < last_frame + self.interval => return None,
+            Some(last_frame) if self.interval > 0.0 => {
+                last_frame + ((now - last_frame) / self.interval).floor() * self.interval
+            }
+            _ => now,
+        };
+        self.last_frame = Some(frame);
+        Some(frame)
+    }
+}
+
+/// Queue of tasks and microtasks
+#[derive(Default)]
+pub struct EventLoop {
+    tasks: VecDeque<Task>,
+    microtasks: VecDeque<Callback>,
+    frame_clock: FrameClock,
+}
+
+impl Debug for EventLoop {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("EventLoop")
+            .field(
+                "tasks",
+                &self.tasks.iter().map(|t| t.source).collect::<Vec<_>>(),
+            )
+            .field("microtasks", &self.microtasks.len())
+            .field("frame_clock", &self.frame_clock)
+            .finish()
+    }
+}
+
+/// Shared handle to an event loop
+#[derive(Debug, Clone, Default)]
+pub struct EventLoopHandle(Rc<RefCell<EventLoop>>);
+
+impl PartialEq for EventLoopHandle {
+    fn eq(&self, other: &Self) -> bool {
+        Rc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl EventLoopHandle {
+    /// Creates a new event loop
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Queues a task that will be executed after all earlier queued tasks
+    pub fn queue_task(&self, source: TaskSource, callback: impl FnOnce() + 'static) {
+        self.0.borrow_mut().tasks.push_back(Task {
+            source,
+            callback: Box::new(callback),
+        });
+    }
+
+    /// Queues a microtask that will be executed at the next microtask checkpoint
+    pub fn queue_microtask(&self, callback: impl FnOnce() + 'static) {
+        self.0.borrow_mut().microtasks.push_back(Box::new(callback));
+    }
+
+    /// Returns the number of tasks that are waiting to be executed
+    pub fn pending_tasks(&self) -> usize {
+        self.0.borrow().tasks.len()
+    }
+
+    /// Executes the oldest task, followed by a microtask checkpoint. Returns false when there
+    /// was no task to execute.
+    pub fn run_next_task(&self) -> bool {
+        // The borrow must be released before running the task, as the task can queue new tasks
+        let task = self.0.borrow_mut().tasks.pop_front();
+        let Some(task) = task else {
+            return false;
+        };
+
+        (task.callback)();
+        self.perform_microtask_checkpoint();
+        true
+    }
+
+    /// Executes tasks until the queue is empty. Returns the number of tasks executed.
+    pub fn run_until_idle(&self) -> usize {
+        let mut count = 0;
+        while self.run_next_task() {
+            count += 1;
+        }
+        count
+    }
+
+    /// Replaces the frame clock of the event loop
+    pub fn set_frame_clock(&self, frame_clock: FrameClock) {
+        self.0.borrow_mut().frame_clock = frame_clock;
+    }
+
+    /// Returns the time of the frame to render when the event loop has a rendering opportunity
+    /// at the given time (in milliseconds)
+    pub fn rendering_opportunity(&self, now: f64) -> Option<f64> {
+        self.0.borrow_mut().frame_clock.rendering_opportunity(now)
+    }
+
+    /// Executes all queued microtasks, including the ones that are queued while doing so
+    pub fn perform_microtask_checkpoint(&self) {
+        loop {
+            let microtask = self.0.borrow_mut().microtasks.pop_front();
+            match microtask {
+                Some(microtask) => microtask(),
+                None => break,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tasks_and_microtasks() {
+        let event_loop = EventLoopHandle::new();
+        let log = Rc::new(RefCell::new(Vec::new()));
+
+        let l = log.clone();
+        let el = event_loop.clone();
+        event_loop.queue_task(TaskSource::DomManipulation, move || {
+            l.borrow_mut().push("task 1");
+
+            let l2 = l.clone();
+            el.queue_microtask(move || l2.borrow_mut().push("microtask"));
+            let l3 = l.clone();
+            el.queue_task(TaskSource::Timer, move || l3.borrow_mut().push("task 3"));
+        });
+        let l = log.clone();
+        event_loop.queue_task(TaskSource::Networking, move || {
+            l.borrow_mut().push("task 2")
+        });
+
+        assert_eq!(event_loop.pending_tasks(), 2);
+        assert_eq!(event_loop.run_until_idle(), 3);
+        assert_eq!(
+            *log.borrow(),
+            vec!["task 1", "microtask", "task 2", "task 3"]
+        );
+        assert!(!event_loop.run_next_task());
+    }
+
+    #[test]
+    fn frame_clock() {
+        let mut clock = FrameClock::with_refresh_rate(50.0);
+        assert_eq!(clock.rendering_opportunity(5.0), Some(5.0));
+        assert_eq!(clock.rendering_opportunity(20.0), None);
+        assert_eq!(clock.rendering_opportunity(25.0), Some(25.0));
+
+        // Missed frames are skipped, and the next frame stays aligned
+        assert_eq!(clock.rendering_opportunity(90.0), Some(85.0));
+        assert_eq!(clock.rendering_opportunity(100.0), None);
+        assert_eq!(clock.rendering_opportunity(105.0), Some(105.0));
+
+        let mut clock = FrameClock::embedder_driven();
+        assert_eq!(clock.rendering_opportunity(1.0), Some(1.0));
+        assert_eq!(clock.rendering_opportunity(1.5), Some(1.5));
+    }
+}
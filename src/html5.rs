@@ -2,10 +2,63 @@
 //!
 //! The parser's job is to take a stream of bytes and turn it into a DOM tree. The parser is
 //! implemented as a state machine and runs in the current thread.
+pub mod animation_frame;
+#[cfg(feature = "arbitrary")]
+pub mod arbitrary_dom;
+pub mod atoms;
+pub mod background;
+pub mod box_decoration;
+pub mod character_data;
+pub mod clipboard;
+pub mod compaction;
+pub mod dialog;
+pub mod display_list;
 pub mod dom;
+pub mod drag_drop;
+pub mod editing;
 pub mod element_class;
+pub mod element_ref;
+pub mod element_style;
 pub mod error_logger;
+pub mod events;
+pub mod focus;
+pub mod form_controls;
+pub mod frozen;
+pub mod generated_content;
+pub mod geometry;
+pub mod graph;
+pub mod history;
+#[cfg(feature = "html5ever")]
+pub mod html5ever_sink;
+pub mod import;
+pub mod index;
+pub mod insertion;
+pub mod intersection;
+pub mod lifecycle;
+pub mod links;
+pub mod lists;
+pub mod metadata;
+pub mod mutation;
 pub mod node;
+pub mod node_path;
+pub mod pagination;
 pub mod parser;
+pub mod pointer;
+pub mod query_cache;
+pub mod resize;
+pub mod scroll;
+pub mod select;
+pub mod selector;
+pub mod serializer;
+#[cfg(feature = "servo_selectors")]
+pub mod servo_selectors;
+pub mod snapshot;
+pub mod stacking;
+pub mod structured_data;
 pub mod tokenizer;
+pub mod transform;
+pub mod traversal;
 pub mod util;
+pub mod validation;
+pub mod viewport;
+pub mod visibility;
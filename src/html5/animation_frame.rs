@@ -0,0 +1,176 @@
+//! Animation frame callbacks
+//!
+//! `request_animation_frame()` registers a callback that is invoked once, right before the next
+//! time the document is rendered, with the time of that frame. Callbacks that are requested while
+//! the callbacks of a frame run (e.g. an animation that requests its next step) are invoked on the
+//! next frame.
+//!
+//! See: https://html.spec.whatwg.org/multipage/imagebitmap-and-animations.html#animation-frames
+use crate::html5::parser::document::{Document, DocumentHandle};
+use core::fmt;
+use core::fmt::Debug;
+use std::rc::Rc;
+
+/// Callback that is invoked with the time of the frame (in milliseconds)
+pub type FrameRequestCallback = Rc<dyn Fn(&DocumentHandle, f64)>;
+
+/// Id of a requested animation frame callback, which can be used to cancel it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct AnimationFrameId(u32);
+
+/// Animation frame callbacks of a document
+#[derive(Default)]
+pub(crate) struct AnimationFrames {
+    last_id: u32,
+    callbacks: Vec<(AnimationFrameId, FrameRequestCallback)>,
+}
+
+impl Debug for AnimationFrames {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AnimationFrames")
+            .field("last_id", &self.last_id)
+            .field(
+                "callbacks",
+                &self.callbacks.iter().map(|(id, _)| id).collect::<Vec<_>>(),
+            )
+            .finish()
+    }
+}
+
+impl PartialEq for AnimationFrames {
+    fn eq(&self, other: &Self) -> bool {
+        self.last_id == other.last_id
+            && self.callbacks.len() == other.callbacks.len()
+            && self
+                .callbacks
+                .iter()
+                .zip(&other.callbacks)
+                .all(|(a, b)| a.0 == b.0 && Rc::ptr_eq(&a.1, &b.1))
+    }
+}
+
+impl Document {
+    /// Requests the callback to be invoked before the next frame is rendered
+    pub fn request_animation_frame(&mut self, callback: FrameRequestCallback) -> AnimationFrameId {
+        let frames = &mut self.animation_frames;
+        frames.last_id += 1;
+        let id = AnimationFrameId(frames.last_id);
+        frames.callbacks.push((id, callback));
+        id
+    }
+
+    /// Cancels a requested callback, if it has not been invoked yet
+    pub fn cancel_animation_frame(&mut self, id: AnimationFrameId) {
+        self.animation_frames
+            .callbacks
+            .retain(|(callback_id, _)| *callback_id != id);
+    }
+
+    /// Returns true when callbacks are waiting for the next frame
+    pub fn has_animation_frame_callbacks(&self) -> bool {
+        !self.animation_frames.callbacks.is_empty()
+    }
+}
+
+impl DocumentHandle {
+    /// Invokes the callbacks that were requested before this frame, with the time of the frame.
+    /// Returns the number of callbacks that were invoked.
+    pub fn run_animation_frame_callbacks(&mut self, timestamp: f64) -> usize {
+        let ids = self
+            .get()
+            .animation_frames
+            .callbacks
+            .iter()
+            .map(|(id, _)| *id)
+            .collect::<Vec<_>>();
+
+        let mut invoked = 0;
+        for id in ids {
+            // Earlier callbacks can cancel later ones
+            let callback = {
+                let mut doc = self.get_mut();
+                let callbacks = &mut doc.animation_frames.callbacks;
+                match callbacks
+                    .iter()
+                    .position(|(callback_id, _)| *callback_id == id)
+                {
+                    Some(index) => callbacks.remove(index).1,
+                    None => continue,
+                }
+            };
+
+            callback(self, timestamp);
+            invoked += 1;
+        }
+        invoked
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::html5::parser::document::DocumentBuilder;
+    use std::cell::RefCell;
+
+    #[test]
+    fn callbacks_run_once_per_frame() {
+        let mut document = DocumentBuilder::new_document();
+        let log = Rc::new(RefCell::new(Vec::new()));
+
+        // An animation that requests its next step from every step, for three frames
+        fn step(log: Rc<RefCell<Vec<f64>>>) -> FrameRequestCallback {
+            Rc::new(move |document, timestamp| {
+                log.borrow_mut().push(timestamp);
+                if log.borrow().len() < 3 {
+                    let mut document = Document::clone(document);
+                    document
+                        .get_mut()
+                        .request_animation_frame(step(log.clone()));
+                }
+            })
+        }
+        document
+            .get_mut()
+            .request_animation_frame(step(log.clone()));
+
+        assert_eq!(document.run_animation_frame_callbacks(16.0), 1);
+        assert_eq!(document.run_animation_frame_callbacks(32.0), 1);
+        assert_eq!(document.run_animation_frame_callbacks(48.0), 1);
+        assert_eq!(document.run_animation_frame_callbacks(64.0), 0);
+        assert_eq!(*log.borrow(), [16.0, 32.0, 48.0]);
+        assert!(!document.get().has_animation_frame_callbacks());
+    }
+
+    #[test]
+    fn cancel() {
+        let mut document = DocumentBuilder::new_document();
+        let log = Rc::new(RefCell::new(Vec::new()));
+        let second_id = Rc::new(RefCell::new(None));
+
+        // The first callback cancels the second one while the frame runs
+        let recorder = log.clone();
+        let to_cancel = second_id.clone();
+        let first: FrameRequestCallback = Rc::new(move |document, _| {
+            recorder.borrow_mut().push("first");
+            if let Some(id) = *to_cancel.borrow() {
+                Document::clone(document)
+                    .get_mut()
+                    .cancel_animation_frame(id);
+            }
+        });
+        document.get_mut().request_animation_frame(first);
+
+        let recorder = log.clone();
+        let second: FrameRequestCallback =
+            Rc::new(move |_, _| recorder.borrow_mut().push("second"));
+        *second_id.borrow_mut() = Some(document.get_mut().request_animation_frame(second));
+
+        let recorder = log.clone();
+        let third: FrameRequestCallback = Rc::new(move |_, _| recorder.borrow_mut().push("third"));
+        let third = document.get_mut().request_animation_frame(third);
+        document.get_mut().cancel_animation_frame(third);
+
+        assert_eq!(document.run_animation_frame_callbacks(0.0), 1);
+        assert_eq!(*log.borrow(), ["first"]);
+    }
+}
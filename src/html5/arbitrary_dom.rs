@@ -0,0 +1,329 @@
+//! Arbitrary documents
+//!
+//! [`Arbitrary`] implementations that generate random (but valid) documents and random sequences
+//! of tree mutations. These can be used by fuzzers and property tests, for instance to check
+//! that serializing and parsing a document is a fixpoint, or that mutations never corrupt the
+//! tree.
+use crate::html5::node::{Node, NodeData, NodeId, HTML_NAMESPACE};
+use crate::html5::parser::document::{Document, DocumentBuilder, DocumentHandle};
+use arbitrary::{Arbitrary, Result, Unstructured};
+use std::collections::HashMap;
+
+/// Maximum depth of the generated element tree (below the body)
+const MAX_DEPTH: usize = 6;
+/// Maximum number of children that are generated for an element
+const MAX_CHILDREN: usize = 5;
+
+/// Elements that are generated with flow content
+const FLOW_ELEMENTS: [&str; 4] = ["div", "section", "article", "blockquote"];
+/// Elements that are generated with phrasing content
+const PHRASING_ELEMENTS: [&str; 6] = ["span", "b", "i", "em", "code", "abbr"];
+/// Attribute names that are generated
+const ATTRIBUTE_NAMES: [&str; 5] = ["id", "class", "title", "lang", "data-value"];
+/// Characters used for text and attribute values, including the ones that need escaping
+const CHARACTERS: [char; 12] = ['a', 'b', 'z', '0', ' ', '&', '<', '>', '"', '\'', '=', 'é'];
+
+/// A random document with an html, head and body element. The body contains a random tree of
+/// elements and text, which parses back into the same tree when serialized.
+#[derive(Debug)]
+pub struct ArbitraryDocument(pub DocumentHandle);
+
+impl<'a> Arbitrary<'a> for ArbitraryDocument {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        let mut document = DocumentBuilder::new_document();
+
+        let html = add_element(&mut document, NodeId::root(), "html", HashMap::new());
+        add_element(&mut document, html, "head", HashMap::new());
+        let body = add_element(&mut document, html, "body", HashMap::new());
+        add_children(u, &mut document, body, 0, true)?;
+
+        Ok(ArbitraryDocument(document))
+    }
+}
+
+fn add_element(
+    document: &mut DocumentHandle,
+    parent: NodeId,
+    name: &str,
+    attributes: HashMap<String, String>,
+) -> NodeId {
+    document
+        .add_node(
+            Node::new_element(name, attributes, HTML_NAMESPACE),
+            parent,
+            None,
+        )
+        .expect("elements are only generated below elements")
+}
+
+fn arbitrary_text(u: &mut Unstructured) -> Result<String> {
+    let len = u.int_in_range(1..=12)?;
+    (0..len).map(|_| u.choose(&CHARACTERS).copied()).collect()
+}
+
+fn arbitrary_attributes(u: &mut Unstructured) -> Result<HashMap<String, String>> {
+    let mut attributes = HashMap::new();
+    for _ in 0..u.int_in_range(0..=2)? {
+        let name = u.choose(&ATTRIBUTE_NAMES)?;
+        attributes.insert(name.to_string(), arbitrary_text(u)?);
+    }
+    Ok(attributes)
+}
+
+/// Adds random children to the parent. Text nodes are never adjacent, as the parser would merge
+/// them.
+fn add_children(
+    u: &mut Unstructured,
+    document: &mut DocumentHandle,
+    parent: NodeId,
+    depth: usize,
+    flow: bool,
+) -> Result<()> {
+    let mut previous_was_text = false;
+
+    for _ in 0..u.int_in_range(0..=MAX_CHILDREN)? {
+        if u.is_empty() {
+            break;
+        }
+
+        if !previous_was_text && (depth >= MAX_DEPTH || u.ratio(1, 3)?) {
+            document
+                .add_node(Node::new_text(&arbitrary_text(u)?), parent, None)
+                .expect("text is only generated below elements");
+            previous_was_text = true;
+            continue;
+        }
+        if depth >= MAX_DEPTH {
+            break;
+        }
+        previous_was_text = false;
+
+        // Flow content can contain paragraphs and lists, which in turn contain phrasing content
+        // and list items
+        let kind = if flow { u.int_in_range(0..=3)? } else { 3 };
+        let attributes = arbitrary_attributes(u)?;
+        match kind {
+            0 => {
+                let name = u.choose(&FLOW_ELEMENTS)?;
+                let element = add_element(document, parent, name, attributes);
+                add_children(u, document, element, depth + 1, true)?;
+            }
+            1 => {
+                let element = add_element(document, parent, "p", attributes);
+                add_children(u, document, element, depth + 1, false)?;
+            }
+            2 => {
+                let list = add_element(document, parent, "ul", attributes);
+                for _ in 0..u.int_in_range(0..=3)? {
+                    let item = add_element(document, list, "li", HashMap::new());
+                    add_children(u, document, item, depth + 2, true)?;
+                }
+            }
+            _ => {
+                let name = u.choose(&PHRASING_ELEMENTS)?;
+                let element = add_element(document, parent, name, attributes);
+                add_children(u, document, element, depth + 1, false)?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// A mutation of the tree. Nodes are referred to by index into the registered nodes of the
+/// document (modulo the number of nodes), so any mutation can be applied to any document.
+#[derive(Debug, Clone, Arbitrary)]
+pub enum Mutation {
+    /// Appends a new element to the parent
+    AppendElement { parent: usize },
+    /// Appends a new text node to the parent
+    AppendText { parent: usize, text: String },
+    /// Moves the node to the end of the new parent
+    Relocate { node: usize, parent: usize },
+    /// Inserts the node into the new parent at the given position
+    Attach {
+        node: usize,
+        parent: usize,
+        position: usize,
+    },
+    /// Detaches the node from its parent
+    Detach { node: usize },
+}
+
+impl Mutation {
+    /// Applies the mutation to the document. Mutations the DOM does not allow (like appending to
+    /// a text node) leave the document as it is.
+    pub fn apply(&self, document: &mut DocumentHandle) {
+        let node_count = document.get().node_count();
+        let node = |index: &usize| NodeId::from(index % node_count);
+
+        match self {
+            Mutation::AppendElement { parent } => {
+                let element = Node::new_element("div", HashMap::new(), HTML_NAMESPACE);
+                let _ = document.add_node(element, node(parent), None);
+            }
+            Mutation::AppendText { parent, text } => {
+                let _ = document.add_node(Node::new_text(text), node(parent), None);
+            }
+            Mutation::Relocate {
+                node: child,
+                parent,
+            } => {
+                // The document node can never be moved
+                if node(child) != NodeId::root() {
+                    document.relocate(node(child), node(parent));
+                }
+            }
+            Mutation::Attach {
+                node: child,
+                parent,
+                position,
+            } => {
+                if node(child) != NodeId::root() {
+                    document.detach_node_from_parent(node(child));
+                    let _ =
+                        document.attach_node_to_parent(node(child), node(parent), Some(*position));
+                }
+            }
+            Mutation::Detach { node: child } => {
+                document.detach_node_from_parent(node(child));
+            }
+        }
+    }
+}
+
+impl Document {
+    /// Number of nodes that are registered in the document
+    fn node_count(&self) -> usize {
+        let mut count = 0;
+        while self.get_node_by_id(NodeId::from(count)).is_some() {
+            count += 1;
+        }
+        count
+    }
+
+    /// Checks that the tree is consistent: every child refers back to its parent, no node has
+    /// more than one parent, and following the parents never leads to a cycle. Returns a
+    /// description of the first problem found.
+    pub fn check_consistency(&self) -> std::result::Result<(), String> {
+        let node_count = self.node_count();
+        let mut seen_as_child = vec![false; node_count];
+
+        for id in 0..node_count {
+            let node = self
+                .get_node_by_id(NodeId::from(id))
+                .expect("node not found");
+
+            for &child_id in &node.children {
+                let child = self
+                    .get_node_by_id(child_id)
+                    .ok_or_else(|| format!("{} has unknown child {}", node.id, child_id))?;
+                if child.parent != Some(node.id) {
+                    return Err(format!(
+                        "{} is a child of {}, but not its parent",
+                        child_id, node.id
+                    ));
+                }
+                if std::mem::replace(&mut seen_as_child[usize::from(child_id)], true) {
+                    return Err(format!("{} is a child of multiple nodes", child_id));
+                }
+            }
+
+            let mut ancestor = node.parent;
+            for _ in 0..node_count {
+                let Some(ancestor_id) = ancestor else {
+                    break;
+                };
+                if ancestor_id == node.id {
+                    return Err(format!("{} is its own ancestor", node.id));
+                }
+                ancestor = self.get_node_by_id(ancestor_id).and_then(|n| n.parent);
+            }
+            if ancestor.is_some() {
+                return Err(format!("cycle above {}", node.id));
+            }
+            if let Some(parent) = node.parent {
+                let parent = self.get_node_by_id(parent).expect("parent not found");
+                if !parent.children.contains(&node.id) {
+                    return Err(format!("{} is not a child of its parent", node.id));
+                }
+            }
+            if let NodeData::Document(_) = node.data {
+                if node.parent.is_some() {
+                    return Err("the document node has a parent".to_string());
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bytes::{CharIterator, Encoding};
+    use crate::html5::parser::Html5Parser;
+    use crate::html5::serializer::{serialize, SerializerOptions};
+
+    /// Deterministic pseudo-random bytes for the given seed (xorshift)
+    fn random_bytes(seed: u64, len: usize) -> Vec<u8> {
+        let mut state = seed.wrapping_mul(0x9E3779B97F4A7C15) | 1;
+        (0..len)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                state as u8
+            })
+            .collect()
+    }
+
+    fn serialize_document(document: &DocumentHandle) -> String {
+        serialize(
+            &document.get(),
+            NodeId::root(),
+            &SerializerOptions::default(),
+        )
+    }
+
+    #[test]
+    fn serialize_parse_serialize_is_fixpoint() {
+        for seed in 0..300 {
+            let bytes = random_bytes(seed, 1024);
+            let mut u = Unstructured::new(&bytes);
+            let ArbitraryDocument(document) = ArbitraryDocument::arbitrary(&mut u).unwrap();
+            assert_eq!(document.get().check_consistency(), Ok(()));
+
+            let html = serialize_document(&document);
+
+            let mut chars = CharIterator::new();
+            chars.read_from_str(&html, Some(Encoding::UTF8));
+            let parsed = DocumentBuilder::new_document();
+            let _ = Html5Parser::parse_document(&mut chars, Document::clone(&parsed), None);
+
+            assert_eq!(serialize_document(&parsed), html, "seed {}", seed);
+        }
+    }
+
+    #[test]
+    fn mutations_keep_tree_consistent() {
+        for seed in 0..300 {
+            let bytes = random_bytes(seed, 2048);
+            let mut u = Unstructured::new(&bytes);
+            let ArbitraryDocument(mut document) = ArbitraryDocument::arbitrary(&mut u).unwrap();
+
+            let mutations = Vec::<Mutation>::arbitrary(&mut u).unwrap();
+            for mutation in &mutations {
+                mutation.apply(&mut document);
+                assert_eq!(
+                    document.get().check_consistency(),
+                    Ok(()),
+                    "seed {} after {:?}",
+                    seed,
+                    mutation
+                );
+            }
+        }
+    }
+}
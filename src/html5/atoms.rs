@@ -0,0 +1,152 @@
+//! Atom table
+//!
+//! Every document keeps a table of the node names it contains. Each distinct name is stored once
+//! and identified by an `Atom`, a small integer that is cheap to copy and compare. Embedders that
+//! look up the same names over and over (a crawler walking all `<a>` elements, a renderer
+//! checking for `<img>`) can intern these names once, and compare nodes by atom instead of by
+//! string.
+//!
+//! Atoms are only meaningful within the document that handed them out.
+use crate::html5::node::NodeId;
+use crate::html5::parser::document::Document;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+/// Interned name
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Atom(u32);
+
+impl Atom {
+    /// Returns the index of the atom in its table
+    pub fn as_usize(&self) -> usize {
+        self.0 as usize
+    }
+}
+
+/// Table of interned names
+#[derive(Debug, Default, PartialEq)]
+pub struct AtomTable {
+    /// Names in order of their atom
+    names: Vec<Rc<str>>,
+    lookup: HashMap<Rc<str>, Atom>,
+}
+
+impl AtomTable {
+    /// Returns the atom of the name, adding the name to the table when needed
+    pub fn intern(&mut self, name: &str) -> Atom {
+        if let Some(atom) = self.lookup.get(name) {
+            return *atom;
+        }
+
+        let atom = Atom(self.names.len() as u32);
+        let name: Rc<str> = Rc::from(name);
+        self.names.push(name.clone());
+        self.lookup.insert(name, atom);
+        atom
+    }
+
+    /// Returns the atom of the name, if it has been interned
+    pub fn get(&self, name: &str) -> Option<Atom> {
+        self.lookup.get(name).copied()
+    }
+
+    /// Returns the name of the atom
+    pub fn resolve(&self, atom: Atom) -> Option<&str> {
+        self.names.get(atom.as_usize()).map(|name| name.as_ref())
+    }
+
+    /// Returns the number of interned names
+    pub fn len(&self) -> usize {
+        self.names.len()
+    }
+
+    /// Returns true when no names have been interned
+    pub fn is_empty(&self) -> bool {
+        self.names.is_empty()
+    }
+
+    /// Returns all atoms with their names, in the order they have been interned
+    pub fn iter(&self) -> impl Iterator<Item = (Atom, &str)> {
+        self.names
+            .iter()
+            .enumerate()
+            .map(|(index, name)| (Atom(index as u32), name.as_ref()))
+    }
+}
+
+impl Document {
+    /// Returns the atom table of the document
+    pub fn atoms(&self) -> &AtomTable {
+        &self.atoms
+    }
+
+    /// Interns the name in the atom table of the document
+    pub fn intern(&mut self, name: &str) -> Atom {
+        self.atoms.intern(name)
+    }
+
+    /// Returns the atom of the name of the node
+    pub fn node_atom(&self, node_id: NodeId) -> Option<Atom> {
+        self.node_atoms.get(&node_id).copied()
+    }
+
+    /// Returns true when the name of the node is the given atom
+    pub fn node_name_is(&self, node_id: NodeId, atom: Atom) -> bool {
+        self.node_atom(node_id) == Some(atom)
+    }
+
+    /// Interns the name of the node, so it can be compared by atom
+    pub(crate) fn intern_node_name(&mut self, node_id: NodeId) {
+        let Some(node) = self.arena.get_node(node_id) else {
+            return;
+        };
+        let atom = self.atoms.intern(&node.name);
+        self.node_atoms.insert(node_id, atom);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::parse;
+
+    #[test]
+    fn intern() {
+        let mut table = AtomTable::default();
+        assert!(table.is_empty());
+
+        let div = table.intern("div");
+        let span = table.intern("span");
+        assert_ne!(div, span);
+        assert_eq!(table.intern("div"), div);
+        assert_eq!(table.get("span"), Some(span));
+        assert_eq!(table.get("p"), None);
+        assert_eq!(table.resolve(span), Some("span"));
+        assert_eq!(table.len(), 2);
+        assert_eq!(
+            table.iter().collect::<Vec<_>>(),
+            vec![(div, "div"), (span, "span")]
+        );
+    }
+
+    #[test]
+    fn compare_nodes_by_atom() {
+        let mut document = parse("<p>one</p><div><p>two</p></div>");
+        let p = document.get_mut().intern("p");
+        let section = document.get_mut().intern("section");
+
+        let doc = document.get();
+        let paragraphs: Vec<NodeId> = doc
+            .arena
+            .nodes()
+            .map(|node| node.id)
+            .filter(|node_id| doc.node_name_is(*node_id, p))
+            .collect();
+        assert_eq!(paragraphs.len(), 2);
+        assert_eq!(paragraphs, doc.elements_by_tag_name("p"));
+        assert!(!doc.node_name_is(paragraphs[0], section));
+
+        let div = doc.elements_by_tag_name("div")[0];
+        assert_eq!(doc.atoms().get("div"), doc.node_atom(div));
+    }
+}
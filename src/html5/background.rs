@@ -0,0 +1,1098 @@
+//! Backgrounds
+//!
+//! The background of an element follows from its `background-color`, `background-image`,
+//! `background-position`, `background-size` and `background-repeat` declarations and the
+//! `background` shorthand (in cascade order). The images are painted in layers, from the last
+//! one (at the bottom, on top of the color) to the first one. When there are more images than
+//! positions, sizes or repeats, those lists are repeated.
+//!
+//! Images are positioned in the padding box and painted in the border box, which are the
+//! initial values of `background-origin` and `background-clip`. An image is either a URL or a
+//! (repeating) linear or radial gradient. URLs are resolved against the base URL of the document
+//! and are loaded by the embedder: `Document::background_image_urls` lists them, so they can be
+//! fetched as images (see `Fetcher::subresource_request`), and `LayoutGeometry::image_size`
+//! returns the natural size of the ones that are loaded. Images that are not loaded are not
+//! painted. Gradients have no natural size, and fill the positioning area by default.
+//!
+//! See: https://drafts.csswg.org/css-backgrounds-3/ and https://drafts.csswg.org/css-images-3/
+use crate::html5::box_decoration::words;
+use crate::html5::element_style::{split_outside_blocks, StyleDeclaration};
+use crate::html5::geometry::Rect;
+use crate::html5::node::{NodeData, NodeId};
+use crate::html5::parser::document::Document;
+use crate::html5::transform::{parse_angle, parse_transform_origin, LengthPercentage};
+
+/// A stop of a gradient
+#[derive(Debug, Clone, PartialEq)]
+pub struct ColorStop {
+    pub color: String,
+    /// Position on the gradient line (or ray), or None to be placed between its neighbours
+    pub position: Option<LengthPercentage>,
+}
+
+/// Direction of a linear gradient
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LinearDirection {
+    /// Angle in radians, clockwise from the top
+    Angle(f32),
+    /// Towards a corner (`to top right`). The angle depends on the size of the image.
+    Corner { right: bool, bottom: bool },
+}
+
+/// Size of the ending shape of a radial gradient
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RadialExtent {
+    ClosestSide,
+    ClosestCorner,
+    FarthestSide,
+    FarthestCorner,
+    /// Explicit horizontal and vertical radius, with percentages of the width and height
+    Size(LengthPercentage, LengthPercentage),
+}
+
+/// The kind of a gradient
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GradientKind {
+    Linear(LinearDirection),
+    Radial {
+        circle: bool,
+        extent: RadialExtent,
+        /// Center, with percentages of the width and height
+        center: (LengthPercentage, LengthPercentage),
+    },
+}
+
+/// Geometry of a gradient in an image of a given size, for the paint backend
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GradientShape {
+    /// The gradient line, from the start point (offset 0) to the end point (offset 1)
+    Linear { start: (f32, f32), end: (f32, f32) },
+    /// The ending shape, an ellipse with offset 1
+    Radial {
+        center: (f32, f32),
+        radii: (f32, f32),
+    },
+}
+
+/// A linear or radial gradient
+#[derive(Debug, Clone, PartialEq)]
+pub struct Gradient {
+    pub kind: GradientKind,
+    /// The color stops, in order. There are at least two.
+    pub stops: Vec<ColorStop>,
+    /// The stops are repeated along the gradient line (or ray)
+    pub repeating: bool,
+}
+
+impl Gradient {
+    /// Parses a `linear-gradient()`, `radial-gradient()` or one of their repeating variants.
+    /// Returns None when the value is not a (valid) gradient.
+    pub fn parse(value: &str) -> Option<Gradient> {
+        let (name, arguments) = function(value)?;
+        let (repeating, name) = match name.strip_prefix("repeating-") {
+            Some(name) => (true, name),
+            None => (false, name.as_str()),
+        };
+        let mut arguments = split_outside_blocks(arguments, ',').into_iter().peekable();
+        // The first argument is either a color stop or the direction (or shape) of the gradient
+        let first = arguments.peek().copied().unwrap_or_default();
+        let first_word = words(first).next().unwrap_or_default().to_ascii_lowercase();
+        let kind = match name {
+            "linear-gradient" if first_word == "to" || parse_angle(&first_word).is_ok() => {
+                arguments.next();
+                GradientKind::Linear(parse_linear_direction(first)?)
+            }
+            "linear-gradient" => GradientKind::Linear(LinearDirection::Angle(std::f32::consts::PI)),
+            "radial-gradient"
+                if RADIAL_KEYWORDS.contains(&first_word.as_str())
+                    || LengthPercentage::parse(&first_word).is_ok() =>
+            {
+                arguments.next();
+                parse_radial_shape(first)?
+            }
+            "radial-gradient" => GradientKind::Radial {
+                circle: false,
+                extent: RadialExtent::FarthestCorner,
+                center: CENTER,
+            },
+            _ => return None,
+        };
+
+        let mut stops = Vec::new();
+        for argument in arguments {
+            let components: Vec<&str> = words(argument).collect();
+            let (color, positions) = components.split_first()?;
+            if positions.len() > 2 {
+                return None;
+            }
+            for position in positions {
+                let position = LengthPercentage::parse(position).ok()?;
+                stops.push(ColorStop {
+                    color: color.to_string(),
+                    position: Some(position),
+                });
+            }
+            if positions.is_empty() {
+                stops.push(ColorStop {
+                    color: color.to_string(),
+                    position: None,
+                });
+            }
+        }
+        if stops.len() < 2 {
+            return None;
+        }
+        Some(Gradient {
+            kind,
+            stops,
+            repeating,
+        })
+    }
+
+    /// Returns the geometry of the gradient in the image
+    pub fn shape(&self, image: &Rect) -> GradientShape {
+        let (width, height) = (image.width, image.height);
+        match self.kind {
+            GradientKind::Linear(direction) => {
+                let angle = match direction {
+                    LinearDirection::Angle(angle) => angle,
+                    // The line through the center perpendicular to the gradient line runs through
+                    // the two neighbouring corners
+                    LinearDirection::Corner { right, bottom } => {
+                        let x = if right { height } else { -height };
+                        let y = if bottom { width } else { -width };
+                        x.atan2(-y)
+                    }
+                };
+                let (sin, cos) = angle.sin_cos();
+                let length = (width * sin).abs() + (height * cos).abs();
+                let (center_x, center_y) = (image.x + width / 2.0, image.y + height / 2.0);
+                let (dx, dy) = (sin * length / 2.0, -cos * length / 2.0);
+                GradientShape::Linear {
+                    start: (center_x - dx, center_y - dy),
+                    end: (center_x + dx, center_y + dy),
+                }
+            }
+            GradientKind::Radial {
+                circle,
+                extent,
+                center,
+            } => {
+                let x = image.x + center.0.resolve(width);
+                let y = image.y + center.1.resolve(height);
+                let horizontal = [(x - image.x).abs(), (image.right() - x).abs()];
+                let vertical = [(y - image.y).abs(), (image.bottom() - y).abs()];
+                let closest = (
+                    horizontal[0].min(horizontal[1]),
+                    vertical[0].min(vertical[1]),
+                );
+                let farthest = (
+                    horizontal[0].max(horizontal[1]),
+                    vertical[0].max(vertical[1]),
+                );
+                let radii = match extent {
+                    RadialExtent::Size(x, y) => (x.resolve(width), y.resolve(height)),
+                    RadialExtent::ClosestSide if circle => {
+                        let radius = closest.0.min(closest.1);
+                        (radius, radius)
+                    }
+                    RadialExtent::FarthestSide if circle => {
+                        let radius = farthest.0.max(farthest.1);
+                        (radius, radius)
+                    }
+                    RadialExtent::ClosestSide => closest,
+                    RadialExtent::FarthestSide => farthest,
+                    RadialExtent::ClosestCorner | RadialExtent::FarthestCorner => {
+                        let (dx, dy) = if extent == RadialExtent::ClosestCorner {
+                            closest
+                        } else {
+                            farthest
+                        };
+                        if circle {
+                            let radius = dx.hypot(dy);
+                            (radius, radius)
+                        } else {
+                            // The ellipse keeps the aspect ratio of the side ellipse
+                            (dx * std::f32::consts::SQRT_2, dy * std::f32::consts::SQRT_2)
+                        }
+                    }
+                };
+                GradientShape::Radial {
+                    center: (x, y),
+                    radii: (radii.0.max(0.0), radii.1.max(0.0)),
+                }
+            }
+        }
+    }
+
+    /// Returns the colors of the stops with their offsets on the gradient line (or ray) of the
+    /// image, where 0 is the start and 1 the end. Stops without a position are spread evenly
+    /// between their neighbours (the first one defaults to 0 and the last one to 1), and a stop
+    /// is never placed before the one before it.
+    pub fn color_stops(&self, image: &Rect) -> Vec<(f32, String)> {
+        let length = match self.shape(image) {
+            GradientShape::Linear { start, end } => (end.0 - start.0).hypot(end.1 - start.1),
+            GradientShape::Radial { radii, .. } => radii.0,
+        };
+        if self.stops.is_empty() {
+            return Vec::new();
+        }
+        let last = self.stops.len() - 1;
+        let mut offsets: Vec<Option<f32>> = self
+            .stops
+            .iter()
+            .enumerate()
+            .map(|(index, stop)| match stop.position {
+                Some(position) if length > 0.0 => Some(position.resolve(length) / length),
+                Some(_) => Some(0.0),
+                None if index == 0 => Some(0.0),
+                None if index == last => Some(1.0),
+                None => None,
+            })
+            .collect();
+
+        let mut previous = f32::MIN;
+        for offset in offsets.iter_mut().flatten() {
+            *offset = offset.max(previous);
+            previous = *offset;
+        }
+        let mut index = 0;
+        while index < offsets.len() {
+            if offsets[index].is_some() {
+                index += 1;
+                continue;
+            }
+            let start = index - 1;
+            let end = (index..offsets.len())
+                .find(|&index| offsets[index].is_some())
+                .unwrap_or(last);
+            let (from, to) = (offsets[start].unwrap_or(0.0), offsets[end].unwrap_or(1.0));
+            for (step, offset) in offsets[index..end].iter_mut().enumerate() {
+                *offset = Some(from + (to - from) * (step + 1) as f32 / (end - start) as f32);
+            }
+            index = end;
+        }
+
+        offsets
+            .into_iter()
+            .zip(&self.stops)
+            .map(|(offset, stop)| (offset.unwrap_or(0.0), stop.color.clone()))
+            .collect()
+    }
+}
+
+/// An image of a background layer
+#[derive(Debug, Clone, PartialEq)]
+pub enum BackgroundImage {
+    /// An image at the URL. After `Document::background`, the URL is absolute.
+    Url(String),
+    Gradient(Gradient),
+}
+
+impl BackgroundImage {
+    /// Parses a `background-image` value into its images, from the top one to the bottom one.
+    /// Layers with `none` have no image. Returns None when the value is invalid.
+    pub fn parse_list(value: &str) -> Option<Vec<Option<BackgroundImage>>> {
+        split_outside_blocks(value, ',')
+            .into_iter()
+            .map(|image| match image.trim() {
+                image if image.eq_ignore_ascii_case("none") => Some(None),
+                image => BackgroundImage::parse(image).map(Some),
+            })
+            .collect()
+    }
+
+    fn parse(value: &str) -> Option<BackgroundImage> {
+        let (name, argument) = function(value)?;
+        if name == "url" {
+            let url = argument.trim();
+            let url = ['"', '\'']
+                .iter()
+                .find_map(|quote| url.strip_prefix(*quote)?.strip_suffix(*quote))
+                .unwrap_or(url);
+            return Some(BackgroundImage::Url(url.to_string()));
+        }
+        Gradient::parse(value).map(BackgroundImage::Gradient)
+    }
+}
+
+/// Size of the images of a background layer
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BackgroundSize {
+    /// The largest size that fits the positioning area, keeping the aspect ratio
+    Contain,
+    /// The smallest size that covers the positioning area, keeping the aspect ratio
+    Cover,
+    /// Width and height, with percentages of the positioning area. None is `auto`.
+    Explicit(Option<LengthPercentage>, Option<LengthPercentage>),
+}
+
+impl Default for BackgroundSize {
+    fn default() -> Self {
+        BackgroundSize::Explicit(None, None)
+    }
+}
+
+impl BackgroundSize {
+    fn parse(value: &str) -> Option<BackgroundSize> {
+        let component = |word: &str| -> Option<Option<LengthPercentage>> {
+            if word.eq_ignore_ascii_case("auto") {
+                return Some(None);
+            }
+            match LengthPercentage::parse(word).ok()? {
+                LengthPercentage::Length(size) | LengthPercentage::Percentage(size)
+                    if size < 0.0 =>
+                {
+                    None
+                }
+                size => Some(Some(size)),
+            }
+        };
+        let components: Vec<&str> = words(value).collect();
+        match components[..] {
+            [keyword] if keyword.eq_ignore_ascii_case("contain") => Some(BackgroundSize::Contain),
+            [keyword] if keyword.eq_ignore_ascii_case("cover") => Some(BackgroundSize::Cover),
+            [width] => Some(BackgroundSize::Explicit(component(width)?, None)),
+            [width, height] => Some(BackgroundSize::Explicit(
+                component(width)?,
+                component(height)?,
+            )),
+            _ => None,
+        }
+    }
+
+    /// Returns the size of the image in the positioning area, for an image with the natural
+    /// size (None for gradients)
+    pub fn resolve(&self, area: &Rect, natural: Option<(f32, f32)>) -> (f32, f32) {
+        let natural = natural.filter(|(width, height)| *width > 0.0 && *height > 0.0);
+        match *self {
+            BackgroundSize::Contain | BackgroundSize::Cover => {
+                let Some((width, height)) = natural else {
+                    return (area.width, area.height);
+                };
+                let (horizontal, vertical) = (area.width / width, area.height / height);
+                let scale = if *self == BackgroundSize::Contain {
+                    horizontal.min(vertical)
+                } else {
+                    horizontal.max(vertical)
+                };
+                (width * scale, height * scale)
+            }
+            BackgroundSize::Explicit(width, height) => {
+                let width = width.map(|width| width.resolve(area.width));
+                let height = height.map(|height| height.resolve(area.height));
+                match (width, height, natural) {
+                    (Some(width), Some(height), _) => (width, height),
+                    (Some(width), None, Some((w, h))) => (width, width * h / w),
+                    (None, Some(height), Some((w, h))) => (height * w / h, height),
+                    (Some(width), None, None) => (width, area.height),
+                    (None, Some(height), None) => (area.width, height),
+                    (None, None, natural) => natural.unwrap_or((area.width, area.height)),
+                }
+            }
+        }
+    }
+}
+
+/// How an image is repeated in one direction
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Repeat {
+    #[default]
+    Repeat,
+    NoRepeat,
+    /// Repeated as often as it fits without clipping, with the space between the images
+    Space,
+    /// Repeated, and scaled so a whole number of images fits
+    Round,
+}
+
+impl Repeat {
+    fn from_keyword(keyword: &str) -> Option<Repeat> {
+        let repeat = match keyword.to_ascii_lowercase().as_str() {
+            "repeat" => Repeat::Repeat,
+            "no-repeat" => Repeat::NoRepeat,
+            "space" => Repeat::Space,
+            "round" => Repeat::Round,
+            _ => return None,
+        };
+        Some(repeat)
+    }
+}
+
+/// How the images of a background layer are repeated: horizontally and vertically
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct BackgroundRepeat(pub Repeat, pub Repeat);
+
+impl BackgroundRepeat {
+    fn parse(value: &str) -> Option<BackgroundRepeat> {
+        let components: Vec<&str> = words(value).collect();
+        match components[..] {
+            [keyword] if keyword.eq_ignore_ascii_case("repeat-x") => {
+                Some(BackgroundRepeat(Repeat::Repeat, Repeat::NoRepeat))
+            }
+            [keyword] if keyword.eq_ignore_ascii_case("repeat-y") => {
+                Some(BackgroundRepeat(Repeat::NoRepeat, Repeat::Repeat))
+            }
+            [keyword] => {
+                Repeat::from_keyword(keyword).map(|repeat| BackgroundRepeat(repeat, repeat))
+            }
+            [x, y] => Some(BackgroundRepeat(
+                Repeat::from_keyword(x)?,
+                Repeat::from_keyword(y)?,
+            )),
+            _ => None,
+        }
+    }
+}
+
+/// Where the images of a background layer are painted
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Tiling {
+    /// One of the images
+    pub tile: Rect,
+    /// Distance between the images horizontally and vertically, or None when the image is not
+    /// repeated in that direction
+    pub step: (Option<f32>, Option<f32>),
+}
+
+impl Tiling {
+    /// Returns the images that are (partly) inside the clip rectangle
+    pub fn tiles(&self, clip: &Rect) -> Vec<Rect> {
+        let starts = |start: f32, step: Option<f32>, from: f32, to: f32| match step {
+            Some(step) if step > 0.0 => {
+                let first = start - ((start - from) / step).ceil() * step;
+                let count = ((to - first) / step).ceil().max(0.0) as usize;
+                (0..count)
+                    .map(|index| first + index as f32 * step)
+                    .collect()
+            }
+            _ => vec![start],
+        };
+        let tile = &self.tile;
+        let columns = starts(tile.x, self.step.0, clip.x, clip.right());
+        let rows = starts(tile.y, self.step.1, clip.y, clip.bottom());
+        rows.iter()
+            .flat_map(|&y| {
+                columns
+                    .iter()
+                    .map(move |&x| Rect::new(x, y, tile.width, tile.height))
+            })
+            .filter(|tile| tile.intersection(clip).is_some())
+            .collect()
+    }
+}
+
+/// A layer of a background: an image with its position, size and repeat
+#[derive(Debug, Clone, PartialEq)]
+pub struct BackgroundLayer {
+    pub image: Option<BackgroundImage>,
+    /// Offset of the image, with percentages of the positioning area minus the size of the image
+    pub position: (LengthPercentage, LengthPercentage),
+    pub size: BackgroundSize,
+    pub repeat: BackgroundRepeat,
+}
+
+impl BackgroundLayer {
+    /// Returns where the images are painted, for the positioning area (the padding box) and the
+    /// natural size of the image (None for gradients)
+    pub fn tiling(&self, area: &Rect, natural: Option<(f32, f32)>) -> Tiling {
+        let (mut width, mut height) = self.size.resolve(area, natural);
+        let BackgroundRepeat(repeat_x, repeat_y) = self.repeat;
+        let round = |size: f32, length: f32| {
+            if size <= 0.0 {
+                return size;
+            }
+            length / (length / size).round().max(1.0)
+        };
+        let (rounded_width, rounded_height) = (
+            if repeat_x == Repeat::Round {
+                round(width, area.width)
+            } else {
+                width
+            },
+            if repeat_y == Repeat::Round {
+                round(height, area.height)
+            } else {
+                height
+            },
+        );
+        // When only one direction is rounded, an `auto` size in the other direction keeps the
+        // aspect ratio
+        match self.size {
+            BackgroundSize::Explicit(_, None) if repeat_y != Repeat::Round && width > 0.0 => {
+                height = height / width * rounded_width;
+            }
+            BackgroundSize::Explicit(None, _) if repeat_x != Repeat::Round && height > 0.0 => {
+                width = width / height * rounded_height;
+            }
+            _ => {}
+        }
+        if repeat_x == Repeat::Round {
+            width = rounded_width;
+        }
+        if repeat_y == Repeat::Round {
+            height = rounded_height;
+        }
+
+        let place =
+            |repeat: Repeat, position: LengthPercentage, start: f32, length: f32, size: f32| {
+                let count = if size > 0.0 {
+                    (length / size).floor()
+                } else {
+                    0.0
+                };
+                match repeat {
+                    Repeat::Space if count >= 2.0 => {
+                        (start, Some(size + (length - count * size) / (count - 1.0)))
+                    }
+                    Repeat::Space | Repeat::NoRepeat => {
+                        (start + position.resolve(length - size), None)
+                    }
+                    Repeat::Repeat | Repeat::Round => {
+                        (start + position.resolve(length - size), Some(size))
+                    }
+                }
+            };
+        let (x, step_x) = place(repeat_x, self.position.0, area.x, area.width, width);
+        let (y, step_y) = place(repeat_y, self.position.1, area.y, area.height, height);
+        Tiling {
+            tile: Rect::new(x, y, width, height),
+            step: (step_x, step_y),
+        }
+    }
+}
+
+/// Keywords that start the shape of a radial gradient
+const RADIAL_KEYWORDS: [&str; 7] = [
+    "circle",
+    "ellipse",
+    "closest-side",
+    "closest-corner",
+    "farthest-side",
+    "farthest-corner",
+    "at",
+];
+
+/// Initial position of the images, in the top left corner
+const TOP_LEFT: (LengthPercentage, LengthPercentage) = (
+    LengthPercentage::Percentage(0.0),
+    LengthPercentage::Percentage(0.0),
+);
+
+const CENTER: (LengthPercentage, LengthPercentage) = (
+    LengthPercentage::Percentage(50.0),
+    LengthPercentage::Percentage(50.0),
+);
+
+/// The background of an element
+#[derive(Debug, Clone, PartialEq)]
+pub struct Background {
+    pub color: String,
+    /// Images of the layers, from the top one to the bottom one. None for `none`.
+    pub images: Vec<Option<BackgroundImage>>,
+    pub positions: Vec<(LengthPercentage, LengthPercentage)>,
+    pub sizes: Vec<BackgroundSize>,
+    pub repeats: Vec<BackgroundRepeat>,
+}
+
+impl Default for Background {
+    fn default() -> Self {
+        Self {
+            color: "transparent".to_string(),
+            images: Vec::new(),
+            positions: vec![TOP_LEFT],
+            sizes: vec![BackgroundSize::default()],
+            repeats: vec![BackgroundRepeat::default()],
+        }
+    }
+}
+
+impl Background {
+    /// Returns true when the color is painted
+    pub fn has_color(&self) -> bool {
+        !self.color.eq_ignore_ascii_case("transparent")
+    }
+
+    /// Returns the layers, from the top one to the bottom one. The positions, sizes and repeats
+    /// are repeated for all images.
+    pub fn layers(&self) -> Vec<BackgroundLayer> {
+        self.images
+            .iter()
+            .enumerate()
+            .map(|(index, image)| BackgroundLayer {
+                image: image.clone(),
+                position: cycle(&self.positions, index).unwrap_or(TOP_LEFT),
+                size: cycle(&self.sizes, index).unwrap_or_default(),
+                repeat: cycle(&self.repeats, index).unwrap_or_default(),
+            })
+            .collect()
+    }
+
+    /// Applies the declarations in cascade order: the normal ones in order, and then the
+    /// important ones
+    fn apply(&mut self, declarations: &[StyleDeclaration]) {
+        let normal = declarations
+            .iter()
+            .filter(|declaration| !declaration.important);
+        let important = declarations
+            .iter()
+            .filter(|declaration| declaration.important);
+        for declaration in normal.chain(important) {
+            self.apply_declaration(&declaration.property, declaration.value.trim());
+        }
+    }
+
+    fn apply_declaration(&mut self, property: &str, value: &str) {
+        match property {
+            "background" => {
+                if let Some(background) = parse_shorthand(value) {
+                    *self = background;
+                }
+            }
+            "background-color" => self.color = value.to_string(),
+            "background-image" => {
+                if let Some(images) = BackgroundImage::parse_list(value) {
+                    self.images = images;
+                }
+            }
+            "background-position" => {
+                if let Some(positions) = parse_list(value, parse_position) {
+                    self.positions = positions;
+                }
+            }
+            "background-size" => {
+                if let Some(sizes) = parse_list(value, BackgroundSize::parse) {
+                    self.sizes = sizes;
+                }
+            }
+            "background-repeat" => {
+                if let Some(repeats) = parse_list(value, BackgroundRepeat::parse) {
+                    self.repeats = repeats;
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+fn cycle<T: Clone>(values: &[T], index: usize) -> Option<T> {
+    match values.len() {
+        0 => None,
+        length => Some(values[index % length].clone()),
+    }
+}
+
+fn parse_list<T>(value: &str, parse: impl Fn(&str) -> Option<T>) -> Option<Vec<T>> {
+    split_outside_blocks(value, ',')
+        .into_iter()
+        .map(|item| parse(item.trim()))
+        .collect()
+}
+
+/// Returns the lowercase name and the arguments of a function like `url(a.png)`
+fn function(value: &str) -> Option<(String, &str)> {
+    let value = value.trim();
+    let open = value.find('(')?;
+    let arguments = value[open + 1..].strip_suffix(')')?;
+    Some((value[..open].trim().to_ascii_lowercase(), arguments))
+}
+
+/// Parses a position of one or two components, like `right top` or `10px 50%`
+fn parse_position(value: &str) -> Option<(LengthPercentage, LengthPercentage)> {
+    if words(value).count() > 2 {
+        return None;
+    }
+    parse_transform_origin(value).ok()
+}
+
+fn parse_linear_direction(value: &str) -> Option<LinearDirection> {
+    let components: Vec<String> = words(value).map(str::to_ascii_lowercase).collect();
+    let components: Vec<&str> = components.iter().map(String::as_str).collect();
+    let degrees = |degrees: f32| Some(LinearDirection::Angle(degrees.to_radians()));
+    match components[..] {
+        ["to", "top"] => degrees(0.0),
+        ["to", "right"] => degrees(90.0),
+        ["to", "bottom"] => degrees(180.0),
+        ["to", "left"] => degrees(270.0),
+        ["to", first, second] => {
+            let (vertical, horizontal) = if matches!(first, "top" | "bottom") {
+                (first, second)
+            } else {
+                (second, first)
+            };
+            if !matches!(vertical, "top" | "bottom") || !matches!(horizontal, "left" | "right") {
+                return None;
+            }
+            Some(LinearDirection::Corner {
+                right: horizontal == "right",
+                bottom: vertical == "bottom",
+            })
+        }
+        [angle] => parse_angle(angle).ok().map(LinearDirection::Angle),
+        _ => None,
+    }
+}
+
+/// Parses the shape, size and position of a radial gradient, like `circle 10px at top`
+fn parse_radial_shape(value: &str) -> Option<GradientKind> {
+    let components: Vec<&str> = words(value).collect();
+    let at = components
+        .iter()
+        .position(|word| word.eq_ignore_ascii_case("at"));
+    let (shape, center) = match at {
+        Some(at) => (
+            &components[..at],
+            parse_position(&components[at + 1..].join(" "))?,
+        ),
+        None => (&components[..], CENTER),
+    };
+
+    let mut circle = None;
+    let mut extent = None;
+    let mut lengths = Vec::new();
+    for word in shape {
+        match word.to_ascii_lowercase().as_str() {
+            "circle" if circle.is_none() => circle = Some(true),
+            "ellipse" if circle.is_none() => circle = Some(false),
+            "closest-side" if extent.is_none() => extent = Some(RadialExtent::ClosestSide),
+            "closest-corner" if extent.is_none() => extent = Some(RadialExtent::ClosestCorner),
+            "farthest-side" if extent.is_none() => extent = Some(RadialExtent::FarthestSide),
+            "farthest-corner" if extent.is_none() => extent = Some(RadialExtent::FarthestCorner),
+            _ => lengths.push(LengthPercentage::parse(word).ok()?),
+        }
+    }
+
+    let size = match (lengths.as_slice(), circle) {
+        ([], _) => None,
+        // A circle has a single radius, which cannot be a percentage
+        ([LengthPercentage::Length(radius)], None | Some(true)) if extent.is_none() => {
+            circle = Some(true);
+            Some(RadialExtent::Size(
+                LengthPercentage::Length(*radius),
+                LengthPercentage::Length(*radius),
+            ))
+        }
+        ([x, y], None | Some(false)) if extent.is_none() => Some(RadialExtent::Size(*x, *y)),
+        _ => return None,
+    };
+    Some(GradientKind::Radial {
+        circle: circle.unwrap_or(false),
+        extent: size.or(extent).unwrap_or(RadialExtent::FarthestCorner),
+        center,
+    })
+}
+
+/// Parses a layer of the `background` shorthand. The color may only be given in the last layer.
+fn parse_shorthand(value: &str) -> Option<Background> {
+    let layers = split_outside_blocks(value, ',');
+    let mut background = Background {
+        images: Vec::new(),
+        positions: Vec::new(),
+        sizes: Vec::new(),
+        repeats: Vec::new(),
+        ..Background::default()
+    };
+    for (index, layer) in layers.iter().enumerate() {
+        let mut image = None;
+        let mut position = Vec::new();
+        let mut size = None;
+        let mut repeat = Vec::new();
+        let mut color = None;
+
+        let mut components = words(layer)
+            .flat_map(|word| {
+                let parts = split_outside_blocks(word, '/');
+                let count = parts.len();
+                parts
+                    .into_iter()
+                    .enumerate()
+                    .flat_map(move |(index, part)| {
+                        let slash = (index + 1 < count).then_some("/");
+                        [Some(part.trim()), slash].into_iter().flatten()
+                    })
+            })
+            .filter(|word| !word.is_empty())
+            .peekable();
+        while let Some(word) = components.next() {
+            let lowercase = word.to_ascii_lowercase();
+            if word == "/" {
+                if position.is_empty() || size.is_some() {
+                    return None;
+                }
+                let mut parts = vec![components.next()?];
+                if let Some(next) = components.peek() {
+                    if BackgroundSize::parse(&format!("{} {next}", parts[0])).is_some() {
+                        parts.push(components.next()?);
+                    }
+                }
+                size = Some(BackgroundSize::parse(&parts.join(" "))?);
+            } else if lowercase == "none" && image.is_none() {
+                image = Some(None);
+            } else if let Some(parsed) = BackgroundImage::parse(word).filter(|_| image.is_none()) {
+                image = Some(Some(parsed));
+            } else if lowercase == "repeat-x" || lowercase == "repeat-y" {
+                if !repeat.is_empty() {
+                    return None;
+                }
+                repeat.push(word);
+                repeat.push("");
+            } else if Repeat::from_keyword(word).is_some() && repeat.len() < 2 {
+                repeat.push(word);
+            } else if parse_position(word).is_some() && position.len() < 2 {
+                position.push(word);
+            } else if matches!(
+                lowercase.as_str(),
+                "scroll"
+                    | "fixed"
+                    | "local"
+                    | "border-box"
+                    | "padding-box"
+                    | "content-box"
+                    | "text"
+            ) {
+                // `background-attachment`, `background-origin` and `background-clip` are not
+                // supported
+            } else if color.is_none() && index == layers.len() - 1 {
+                color = Some(word.to_string());
+            } else {
+                return None;
+            }
+        }
+
+        background.images.push(image.flatten());
+        background.positions.push(match position[..] {
+            [] => TOP_LEFT,
+            _ => parse_position(&position.join(" "))?,
+        });
+        background.sizes.push(size.unwrap_or_default());
+        background.repeats.push(match repeat[..] {
+            [] => BackgroundRepeat::default(),
+            _ => BackgroundRepeat::parse(repeat.join(" ").trim())?,
+        });
+        if let Some(color) = color {
+            background.color = color;
+        }
+    }
+    Some(background)
+}
+
+impl Document {
+    /// Returns the background of the element, with the URLs of its images resolved against the
+    /// base URL of the document (images with an invalid URL are left out) and `currentcolor`
+    /// replaced by the color of the element
+    pub fn background(&self, node_id: NodeId) -> Background {
+        let mut background = Background::default();
+        background.apply(&self.declared_style(node_id));
+
+        let current_color = self.current_color(node_id);
+        let resolve_color = |color: &mut String| {
+            if color.eq_ignore_ascii_case("currentcolor") {
+                *color = current_color.clone();
+            }
+        };
+        resolve_color(&mut background.color);
+        for image in &mut background.images {
+            match image {
+                Some(BackgroundImage::Gradient(gradient)) => {
+                    gradient
+                        .stops
+                        .iter_mut()
+                        .for_each(|stop| resolve_color(&mut stop.color));
+                }
+                Some(BackgroundImage::Url(url)) => {
+                    *image = self
+                        .resolve_url(url)
+                        .map(|url| BackgroundImage::Url(url.to_string()));
+                }
+                None => {}
+            }
+        }
+        background
+    }
+
+    /// Returns the (absolute) URLs of the background images of the elements, in tree order, for
+    /// the embedder to load
+    pub fn background_image_urls(&self) -> Vec<(NodeId, String)> {
+        let mut urls = Vec::new();
+        for node in self.descendants(NodeId::root()) {
+            if !matches!(node.data, NodeData::Element(_)) {
+                continue;
+            }
+            for image in self.background(node.id).images.into_iter().flatten() {
+                if let BackgroundImage::Url(url) = image {
+                    urls.push((node.id, url));
+                }
+            }
+        }
+        urls
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::html5::element_style::parse_declarations;
+
+    fn background(declarations: &str) -> Background {
+        let mut background = Background::default();
+        background.apply(&parse_declarations(declarations));
+        background
+    }
+
+    #[test]
+    fn parse_backgrounds() {
+        let parsed = background(
+            "background: url('a.png') right 10px / 20px no-repeat, \
+                         linear-gradient(red, blue) repeat-y red; \
+             background-size: contain, 50% auto",
+        );
+        assert_eq!(parsed.color, "red");
+        assert_eq!(
+            parsed.images,
+            [
+                Some(BackgroundImage::Url("a.png".to_string())),
+                Some(BackgroundImage::Gradient(Gradient {
+                    kind: GradientKind::Linear(LinearDirection::Angle(std::f32::consts::PI)),
+                    stops: vec![
+                        ColorStop {
+                            color: "red".to_string(),
+                            position: None,
+                        },
+                        ColorStop {
+                            color: "blue".to_string(),
+                            position: None,
+                        },
+                    ],
+                    repeating: false,
+                })),
+            ]
+        );
+        let layers = parsed.layers();
+        assert_eq!(
+            layers[0].position,
+            (
+                LengthPercentage::Percentage(100.0),
+                LengthPercentage::Length(10.0)
+            )
+        );
+        assert_eq!(layers[0].size, BackgroundSize::Contain);
+        assert_eq!(
+            layers[0].repeat,
+            BackgroundRepeat(Repeat::NoRepeat, Repeat::NoRepeat)
+        );
+        assert_eq!(
+            layers[1].size,
+            BackgroundSize::Explicit(Some(LengthPercentage::Percentage(50.0)), None)
+        );
+        assert_eq!(
+            layers[1].repeat,
+            BackgroundRepeat(Repeat::NoRepeat, Repeat::Repeat)
+        );
+        assert_eq!(layers[1].position, TOP_LEFT);
+
+        // Lists are repeated, and invalid values are ignored
+        let repeated = background(
+            "background-image: none, url(b.png), none; background-repeat: space, round; \
+             background-position: center, bottom; background-size: 1px 2px 3px",
+        );
+        let layers = repeated.layers();
+        assert_eq!(layers.len(), 3);
+        assert_eq!(
+            layers[2].repeat,
+            BackgroundRepeat(Repeat::Space, Repeat::Space)
+        );
+        assert_eq!(layers[2].position, CENTER);
+        assert_eq!(layers[1].size, BackgroundSize::default());
+        assert!(!repeated.has_color());
+        assert_eq!(background("background: red, blue").color, "transparent");
+    }
+
+    #[test]
+    fn gradients() {
+        let image = Rect::new(0.0, 0.0, 100.0, 50.0);
+        let gradient =
+            Gradient::parse("linear-gradient(to right, red, lime 20%, blue, white)").unwrap();
+        let GradientShape::Linear { start, end } = gradient.shape(&image) else {
+            panic!("expected a linear gradient");
+        };
+        let round = |(x, y): (f32, f32)| (x.round(), y.round());
+        assert_eq!((round(start), round(end)), ((0.0, 25.0), (100.0, 25.0)));
+        let offsets: Vec<f32> = gradient
+            .color_stops(&image)
+            .into_iter()
+            .map(|(offset, _)| (offset * 100.0).round() / 100.0)
+            .collect();
+        assert_eq!(offsets, [0.0, 0.2, 0.6, 1.0]);
+
+        // The corners are on the perpendicular line through the center
+        let corner =
+            Gradient::parse("repeating-linear-gradient(to top right, red 10px, blue 5px)").unwrap();
+        assert!(corner.repeating);
+        let GradientShape::Linear { start, end } = corner.shape(&image) else {
+            panic!("expected a linear gradient");
+        };
+        assert!(start.0 < 50.0 && start.1 > 25.0 && end.0 > 50.0 && end.1 < 25.0);
+        let offsets: Vec<f32> = corner
+            .color_stops(&image)
+            .into_iter()
+            .map(|(offset, _)| offset)
+            .collect();
+        assert_eq!(offsets[0], offsets[1]);
+
+        let radial =
+            Gradient::parse("radial-gradient(circle closest-side at 20px 50%, red, blue)").unwrap();
+        assert_eq!(
+            radial.shape(&image),
+            GradientShape::Radial {
+                center: (20.0, 25.0),
+                radii: (20.0, 20.0)
+            }
+        );
+        let ellipse = Gradient::parse("radial-gradient(red, blue)").unwrap();
+        let GradientShape::Radial { center, radii } = ellipse.shape(&image) else {
+            panic!("expected a radial gradient");
+        };
+        assert_eq!(center, (50.0, 25.0));
+        assert!((radii.0 - 50.0 * std::f32::consts::SQRT_2).abs() < 0.01);
+        assert!(Gradient::parse("radial-gradient(circle 10%, red, blue)").is_none());
+        assert!(Gradient::parse("linear-gradient(red)").is_none());
+        assert!(Gradient::parse("conic-gradient(red, blue)").is_none());
+    }
+
+    #[test]
+    fn tiling() {
+        let area = Rect::new(10.0, 10.0, 100.0, 50.0);
+        let layer = |declarations: &str| background(declarations).layers().remove(0);
+
+        let centered = layer("background: url(a.png) no-repeat center");
+        let tiling = centered.tiling(&area, Some((20.0, 10.0)));
+        assert_eq!(tiling.tile, Rect::new(50.0, 30.0, 20.0, 10.0));
+        assert_eq!(tiling.tiles(&area), [tiling.tile]);
+
+        let cover = layer("background: url(a.png) 0 0 / cover");
+        let tiling = cover.tiling(&area, Some((20.0, 20.0)));
+        assert_eq!(tiling.tile, Rect::new(10.0, 10.0, 100.0, 100.0));
+
+        // Repeated images start before the area, so the clip is covered
+        let repeated = layer("background: url(a.png) 5px 0 repeat-x");
+        let tiling = repeated.tiling(&area, Some((40.0, 10.0)));
+        assert_eq!(tiling.step, (Some(40.0), None));
+        let tiles = tiling.tiles(&Rect::new(0.0, 0.0, 120.0, 60.0));
+        let columns: Vec<f32> = tiles.iter().map(|tile| tile.x).collect();
+        assert_eq!(columns, [-25.0, 15.0, 55.0, 95.0]);
+
+        let spaced = layer("background: url(a.png) space");
+        let tiling = spaced.tiling(&area, Some((30.0, 20.0)));
+        assert_eq!(tiling.tile.x, 10.0);
+        assert_eq!(tiling.step, (Some(35.0), Some(30.0)));
+
+        // The height keeps the aspect ratio when only the width is rounded
+        let rounded = layer("background: url(a.png) round no-repeat; background-size: 45px auto");
+        let tiling = rounded.tiling(&area, Some((20.0, 10.0)));
+        assert_eq!(tiling.tile, Rect::new(10.0, 10.0, 50.0, 25.0));
+        assert_eq!(tiling.step, (Some(50.0), None));
+
+        let gradient = layer("background-image: linear-gradient(red, blue)");
+        assert_eq!(gradient.tiling(&area, None).tile, area);
+    }
+}
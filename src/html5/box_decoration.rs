@@ -0,0 +1,715 @@
+//! Borders, outlines and box shadows
+//!
+//! The decorations of an element follow from its `border`, `border-radius`, `outline` and
+//! `box-shadow` declarations (including the shorthands, in cascade order). Lengths are in CSS
+//! pixels, and colors are kept as the CSS values they are declared with, except for
+//! `currentcolor`, which is resolved to the `color` of the element (or its nearest ancestor that
+//! declares one).
+//!
+//! Corners are rounded with elliptical radii. When the radii of two corners on the same side do
+//! not fit the side, all radii are scaled down by the same factor. The inner edge of the border
+//! is rounded with the radii minus the border widths.
+//!
+//! See: https://drafts.csswg.org/css-backgrounds-3/ and https://drafts.csswg.org/css-ui-4/#outline
+use crate::html5::element_style::{cascaded_value, split_outside_blocks, StyleDeclaration};
+use crate::html5::geometry::Rect;
+use crate::html5::node::NodeId;
+use crate::html5::parser::document::Document;
+use crate::html5::transform::{parse_length, LengthPercentage};
+
+/// Border widths of the `thin`, `medium` and `thick` keywords
+const THIN: f32 = 1.0;
+const MEDIUM: f32 = 3.0;
+const THICK: f32 = 5.0;
+
+/// Color of text that does not declare one
+const DEFAULT_COLOR: &str = "black";
+
+/// Sides of a box, in the order of the CSS shorthands
+const SIDES: [&str; 4] = ["top", "right", "bottom", "left"];
+
+/// Corners of a box, in the order of the `border-radius` shorthand
+const CORNERS: [&str; 4] = ["top-left", "top-right", "bottom-right", "bottom-left"];
+
+/// Style of a border or outline line
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum BorderStyle {
+    #[default]
+    None,
+    Hidden,
+    Dotted,
+    Dashed,
+    Solid,
+    Double,
+    Groove,
+    Ridge,
+    Inset,
+    Outset,
+}
+
+impl BorderStyle {
+    /// Returns the style for the keyword, or None when it is unknown. The `auto` outline style is
+    /// painted as a solid line.
+    pub fn from_keyword(keyword: &str) -> Option<BorderStyle> {
+        let style = match keyword.to_ascii_lowercase().as_str() {
+            "none" => BorderStyle::None,
+            "hidden" => BorderStyle::Hidden,
+            "dotted" => BorderStyle::Dotted,
+            "dashed" => BorderStyle::Dashed,
+            "solid" | "auto" => BorderStyle::Solid,
+            "double" => BorderStyle::Double,
+            "groove" => BorderStyle::Groove,
+            "ridge" => BorderStyle::Ridge,
+            "inset" => BorderStyle::Inset,
+            "outset" => BorderStyle::Outset,
+            _ => return None,
+        };
+        Some(style)
+    }
+
+    /// Returns true when a line of this style is painted
+    pub fn is_visible(&self) -> bool {
+        !matches!(self, BorderStyle::None | BorderStyle::Hidden)
+    }
+}
+
+/// A side of the border, or the outline
+#[derive(Debug, Clone, PartialEq)]
+pub struct BorderSide {
+    /// Width in CSS pixels. Nothing is painted when the style is not visible, whatever the width.
+    pub width: f32,
+    pub style: BorderStyle,
+    pub color: String,
+}
+
+impl Default for BorderSide {
+    fn default() -> Self {
+        Self {
+            width: MEDIUM,
+            style: BorderStyle::None,
+            color: "currentcolor".to_string(),
+        }
+    }
+}
+
+impl BorderSide {
+    /// Parses a `border` (or `outline`) shorthand. Components that are left out get their
+    /// initial value.
+    fn from_shorthand(value: &str) -> Self {
+        let mut side = BorderSide::default();
+        for word in words(value) {
+            if let Some(width) = parse_width(word) {
+                side.width = width;
+            } else if let Some(style) = BorderStyle::from_keyword(word) {
+                side.style = style;
+            } else {
+                side.color = word.to_string();
+            }
+        }
+        side
+    }
+
+    /// Returns the width that is painted: 0 when the style is not visible
+    fn used_width(&self) -> f32 {
+        if self.style.is_visible() {
+            self.width
+        } else {
+            0.0
+        }
+    }
+}
+
+/// A rectangle with rounded corners. The radii are (horizontal, vertical) in the order top-left,
+/// top-right, bottom-right and bottom-left.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct RoundedRect {
+    pub rect: Rect,
+    pub radii: [(f32, f32); 4],
+}
+
+impl RoundedRect {
+    /// Returns the rounded rectangle, with the radii scaled down so the curves of adjacent
+    /// corners do not overlap
+    pub fn new(rect: Rect, radii: [(f32, f32); 4]) -> Self {
+        let [top_left, top_right, bottom_right, bottom_left] = radii;
+        let sides = [
+            (rect.width, top_left.0 + top_right.0),
+            (rect.height, top_right.1 + bottom_right.1),
+            (rect.width, bottom_left.0 + bottom_right.0),
+            (rect.height, top_left.1 + bottom_left.1),
+        ];
+        let factor = sides
+            .iter()
+            .filter(|(_, sum)| *sum > 0.0)
+            .map(|(length, sum)| length / sum)
+            .fold(1.0f32, f32::min);
+        Self {
+            rect,
+            radii: radii.map(|(x, y)| (x * factor, y * factor)),
+        }
+    }
+
+    /// Returns true when no corner is rounded
+    pub fn is_rect(&self) -> bool {
+        self.radii.iter().all(|&(x, y)| x <= 0.0 || y <= 0.0)
+    }
+
+    /// Returns the rounded rectangle shrunk by the widths (top, right, bottom, left). The radii
+    /// shrink with it, like the inner edge of a border. Negative widths grow it.
+    pub fn shrink(&self, widths: [f32; 4]) -> RoundedRect {
+        let [top, right, bottom, left] = widths;
+        let rect = self.rect.inflate([-top, -right, -bottom, -left]);
+        let [top_left, top_right, bottom_right, bottom_left] = self.radii;
+        let shrink = |(x, y): (f32, f32), dx: f32, dy: f32| {
+            if x <= 0.0 || y <= 0.0 {
+                return (0.0, 0.0);
+            }
+            ((x - dx).max(0.0), (y - dy).max(0.0))
+        };
+        RoundedRect {
+            rect,
+            radii: [
+                shrink(top_left, left, top),
+                shrink(top_right, right, top),
+                shrink(bottom_right, right, bottom),
+                shrink(bottom_left, left, bottom),
+            ],
+        }
+    }
+
+    /// Returns true when the point is inside the rounded rectangle
+    pub fn contains(&self, x: f32, y: f32) -> bool {
+        let rect = &self.rect;
+        if x < rect.x || x >= rect.right() || y < rect.y || y >= rect.bottom() {
+            return false;
+        }
+        let [top_left, top_right, bottom_right, bottom_left] = self.radii;
+        let corners = [
+            (top_left, rect.x + top_left.0, rect.y + top_left.1),
+            (top_right, rect.right() - top_right.0, rect.y + top_right.1),
+            (
+                bottom_right,
+                rect.right() - bottom_right.0,
+                rect.bottom() - bottom_right.1,
+            ),
+            (
+                bottom_left,
+                rect.x + bottom_left.0,
+                rect.bottom() - bottom_left.1,
+            ),
+        ];
+        corners
+            .iter()
+            .enumerate()
+            .all(|(index, &((rx, ry), cx, cy))| {
+                if rx <= 0.0 || ry <= 0.0 {
+                    return true;
+                }
+                let outside_x = if index == 0 || index == 3 {
+                    x < cx
+                } else {
+                    x > cx
+                };
+                let outside_y = if index < 2 { y < cy } else { y > cy };
+                if !outside_x || !outside_y {
+                    return true;
+                }
+                let (dx, dy) = ((x - cx) / rx, (y - cy) / ry);
+                dx * dx + dy * dy <= 1.0
+            })
+    }
+}
+
+/// A shadow of the `box-shadow` property
+#[derive(Debug, Clone, PartialEq)]
+pub struct BoxShadow {
+    pub offset_x: f32,
+    pub offset_y: f32,
+    /// Blur radius (twice the standard deviation of the blur)
+    pub blur: f32,
+    /// Grows (or with a negative value shrinks) the shadow
+    pub spread: f32,
+    pub color: String,
+    /// The shadow is painted inside the padding box instead of outside the border box
+    pub inset: bool,
+}
+
+impl BoxShadow {
+    /// Parses a `box-shadow` value into its shadows, from the top one to the bottom one. `none`
+    /// has no shadows. Returns None when the value is invalid.
+    pub fn parse_list(value: &str) -> Option<Vec<BoxShadow>> {
+        if value.trim().eq_ignore_ascii_case("none") {
+            return Some(Vec::new());
+        }
+        split_outside_blocks(value, ',')
+            .into_iter()
+            .map(BoxShadow::parse)
+            .collect()
+    }
+
+    fn parse(value: &str) -> Option<BoxShadow> {
+        let mut lengths = Vec::new();
+        let mut color = None;
+        let mut inset = false;
+        for word in words(value) {
+            if word.eq_ignore_ascii_case("inset") && !inset {
+                inset = true;
+            } else if let Ok(length) = parse_length(word) {
+                lengths.push(length);
+            } else if color.is_none() {
+                color = Some(word.to_string());
+            } else {
+                return None;
+            }
+        }
+        let (offset_x, offset_y, blur, spread) = match lengths[..] {
+            [x, y] => (x, y, 0.0, 0.0),
+            [x, y, blur] => (x, y, blur, 0.0),
+            [x, y, blur, spread] => (x, y, blur, spread),
+            _ => return None,
+        };
+        if blur < 0.0 {
+            return None;
+        }
+        Some(BoxShadow {
+            offset_x,
+            offset_y,
+            blur,
+            spread,
+            color: color.unwrap_or_else(|| "currentcolor".to_string()),
+            inset,
+        })
+    }
+
+    /// Returns the shape of the shadow (before it is blurred) for the border box. An outer
+    /// shadow is painted outside the border box only, and an inset shadow inside the padding box
+    /// only, outside of this shape.
+    pub fn shape(&self, border_box: &RoundedRect, border_widths: [f32; 4]) -> RoundedRect {
+        if self.inset {
+            let padding_box = border_box.shrink(border_widths);
+            let shape = padding_box.shrink([self.spread; 4]);
+            return translate(&shape, self.offset_x, self.offset_y);
+        }
+        let shape = border_box.shrink([-self.spread; 4]);
+        translate(&shape, self.offset_x, self.offset_y)
+    }
+}
+
+fn translate(shape: &RoundedRect, x: f32, y: f32) -> RoundedRect {
+    RoundedRect {
+        rect: Rect::new(
+            shape.rect.x + x,
+            shape.rect.y + y,
+            shape.rect.width,
+            shape.rect.height,
+        ),
+        radii: shape.radii,
+    }
+}
+
+/// The borders, outline and box shadows of an element
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct BoxDecoration {
+    /// The sides of the border: top, right, bottom and left
+    pub border: [BorderSide; 4],
+    /// Radii of the corners, with percentages of the width and height of the border box. In
+    /// the order top-left, top-right, bottom-right and bottom-left.
+    pub radii: [(LengthPercentage, LengthPercentage); 4],
+    pub outline: BorderSide,
+    /// Distance between the outline and the border box
+    pub outline_offset: f32,
+    /// Shadows, from the top one to the bottom one
+    pub shadows: Vec<BoxShadow>,
+}
+
+impl BoxDecoration {
+    /// Returns the widths of the border that are painted: top, right, bottom and left
+    pub fn border_widths(&self) -> [f32; 4] {
+        [0, 1, 2, 3].map(|side| self.border[side].used_width())
+    }
+
+    /// Returns true when a side of the border is painted
+    pub fn has_border(&self) -> bool {
+        self.border_widths().iter().any(|&width| width > 0.0)
+    }
+
+    /// Returns the width of the outline that is painted
+    pub fn outline_width(&self) -> f32 {
+        self.outline.used_width()
+    }
+
+    /// Returns the border box with its rounded corners
+    pub fn rounded_border_box(&self, border_box: Rect) -> RoundedRect {
+        let radii = self.radii.map(|(x, y)| {
+            (
+                x.resolve(border_box.width).max(0.0),
+                y.resolve(border_box.height).max(0.0),
+            )
+        });
+        RoundedRect::new(border_box, radii)
+    }
+
+    /// Returns the outer edge of the outline. The outline follows the rounded corners of the
+    /// border box.
+    pub fn outline_rect(&self, border_box: Rect) -> RoundedRect {
+        let grow = self.outline_offset + self.outline_width();
+        let rounded = self.rounded_border_box(border_box);
+        let mut outline = rounded.shrink([-grow; 4]);
+        if grow < 0.0 {
+            return outline;
+        }
+        for (radius, original) in outline.radii.iter_mut().zip(rounded.radii) {
+            if original.0 <= 0.0 || original.1 <= 0.0 {
+                *radius = (0.0, 0.0);
+            } else {
+                *radius = (original.0 + grow, original.1 + grow);
+            }
+        }
+        outline
+    }
+
+    /// Returns the area the decorations are painted in: the border box with the outline and the
+    /// outer shadows (including their blur)
+    pub fn ink_rect(&self, border_box: Rect) -> Rect {
+        let mut ink = border_box;
+        if self.outline_width() > 0.0 {
+            ink = ink.union(&self.outline_rect(border_box).rect);
+        }
+        for shadow in self.shadows.iter().filter(|shadow| !shadow.inset) {
+            let shape = shadow.shape(&RoundedRect::new(border_box, [(0.0, 0.0); 4]), [0.0; 4]);
+            ink = ink.union(&shape.rect.inflate([shadow.blur; 4]));
+        }
+        ink
+    }
+
+    /// Applies the declarations in cascade order: the normal ones in order, and then the
+    /// important ones
+    fn apply(&mut self, declarations: &[StyleDeclaration]) {
+        let normal = declarations
+            .iter()
+            .filter(|declaration| !declaration.important);
+        let important = declarations
+            .iter()
+            .filter(|declaration| declaration.important);
+        for declaration in normal.chain(important) {
+            self.apply_declaration(&declaration.property, declaration.value.trim());
+        }
+    }
+
+    fn apply_declaration(&mut self, property: &str, value: &str) {
+        match property {
+            "border" => {
+                let side = BorderSide::from_shorthand(value);
+                self.border = [side.clone(), side.clone(), side.clone(), side];
+            }
+            "border-width" => {
+                if let Some(widths) = four_sides(value, parse_width) {
+                    for (side, width) in self.border.iter_mut().zip(widths) {
+                        side.width = width;
+                    }
+                }
+            }
+            "border-style" => {
+                if let Some(styles) = four_sides(value, BorderStyle::from_keyword) {
+                    for (side, style) in self.border.iter_mut().zip(styles) {
+                        side.style = style;
+                    }
+                }
+            }
+            "border-color" => {
+                if let Some(colors) = four_sides(value, |word| Some(word.to_string())) {
+                    for (side, color) in self.border.iter_mut().zip(colors) {
+                        side.color = color;
+                    }
+                }
+            }
+            "border-radius" => {
+                if let Some(radii) = parse_border_radius(value) {
+                    self.radii = radii;
+                }
+            }
+            "outline" => self.outline = BorderSide::from_shorthand(value),
+            "outline-width" => {
+                if let Some(width) = parse_width(value) {
+                    self.outline.width = width;
+                }
+            }
+            "outline-style" => {
+                if let Some(style) = BorderStyle::from_keyword(value) {
+                    self.outline.style = style;
+                }
+            }
+            "outline-color" => self.outline.color = value.to_string(),
+            "outline-offset" => {
+                if let Ok(offset) = parse_length(value) {
+                    self.outline_offset = offset;
+                }
+            }
+            "box-shadow" => {
+                if let Some(shadows) = BoxShadow::parse_list(value) {
+                    self.shadows = shadows;
+                }
+            }
+            _ => self.apply_longhand(property, value),
+        }
+    }
+
+    /// Applies the properties of a single side or corner, like `border-top` or
+    /// `border-top-left-radius`
+    fn apply_longhand(&mut self, property: &str, value: &str) {
+        let Some(rest) = property.strip_prefix("border-") else {
+            return;
+        };
+        if let Some(index) = CORNERS
+            .iter()
+            .position(|corner| rest.strip_suffix("-radius") == Some(*corner))
+        {
+            let components: Vec<&str> = words(value).collect();
+            let radius = match components[..] {
+                [radius] => parse_radius(radius).map(|radius| (radius, radius)),
+                [x, y] => parse_radius(x).zip(parse_radius(y)),
+                _ => None,
+            };
+            if let Some(radius) = radius {
+                self.radii[index] = radius;
+            }
+            return;
+        }
+
+        let (side, component) = rest.split_once('-').unwrap_or((rest, ""));
+        let Some(index) = SIDES.iter().position(|name| *name == side) else {
+            return;
+        };
+        let border = &mut self.border[index];
+        match component {
+            "" => *border = BorderSide::from_shorthand(value),
+            "width" => {
+                if let Some(width) = parse_width(value) {
+                    border.width = width;
+                }
+            }
+            "style" => {
+                if let Some(style) = BorderStyle::from_keyword(value) {
+                    border.style = style;
+                }
+            }
+            "color" => border.color = value.to_string(),
+            _ => {}
+        }
+    }
+
+    /// Replaces `currentcolor` with the color
+    fn resolve_current_color(&mut self, color: &str) {
+        let sides = self.border.iter_mut().chain([&mut self.outline]);
+        let colors = sides
+            .map(|side| &mut side.color)
+            .chain(self.shadows.iter_mut().map(|shadow| &mut shadow.color));
+        for value in colors {
+            if value.eq_ignore_ascii_case("currentcolor") {
+                *value = color.to_string();
+            }
+        }
+    }
+}
+
+/// Splits the value into its space separated components, keeping functions like `rgb(1, 2, 3)`
+/// together
+pub(crate) fn words(value: &str) -> impl Iterator<Item = &str> {
+    split_outside_blocks(value, ' ')
+        .into_iter()
+        .flat_map(|part| split_outside_blocks(part, '\t'))
+        .map(str::trim)
+        .filter(|word| !word.is_empty())
+}
+
+/// Parses a border width: a non-negative length, or one of the keywords
+fn parse_width(value: &str) -> Option<f32> {
+    match value.to_ascii_lowercase().as_str() {
+        "thin" => Some(THIN),
+        "medium" => Some(MEDIUM),
+        "thick" => Some(THICK),
+        value => parse_length(value).ok().filter(|width| *width >= 0.0),
+    }
+}
+
+fn parse_radius(value: &str) -> Option<LengthPercentage> {
+    let radius = match value.strip_suffix('%') {
+        Some(percentage) => LengthPercentage::Percentage(percentage.trim().parse().ok()?),
+        None => LengthPercentage::Length(parse_length(value).ok()?),
+    };
+    match radius {
+        LengthPercentage::Length(length) | LengthPercentage::Percentage(length) if length < 0.0 => {
+            None
+        }
+        radius => Some(radius),
+    }
+}
+
+/// Expands 1 to 4 values to the four sides (or corners), like the CSS shorthands do
+pub(crate) fn four_sides<T: Clone>(
+    value: &str,
+    parse: impl Fn(&str) -> Option<T>,
+) -> Option<[T; 4]> {
+    let values = words(value).map(parse).collect::<Option<Vec<T>>>()?;
+    let (first, second, third, fourth) = match values.len() {
+        1 => (0, 0, 0, 0),
+        2 => (0, 1, 0, 1),
+        3 => (0, 1, 2, 1),
+        4 => (0, 1, 2, 3),
+        _ => return None,
+    };
+    Some([
+        values[first].clone(),
+        values[second].clone(),
+        values[third].clone(),
+        values[fourth].clone(),
+    ])
+}
+
+/// Parses a `border-radius` value, with the vertical radii after a slash
+fn parse_border_radius(value: &str) -> Option<[(LengthPercentage, LengthPercentage); 4]> {
+    let (horizontal, vertical) = value.split_once('/').unwrap_or((value, value));
+    let horizontal = four_sides(horizontal, parse_radius)?;
+    let vertical = four_sides(vertical, parse_radius)?;
+    Some([0, 1, 2, 3].map(|corner| (horizontal[corner], vertical[corner])))
+}
+
+impl Document {
+    /// Returns the borders, outline and box shadows of the element
+    pub fn box_decoration(&self, node_id: NodeId) -> BoxDecoration {
+        let mut decoration = BoxDecoration::default();
+        decoration.apply(&self.declared_style(node_id));
+        decoration.resolve_current_color(&self.current_color(node_id));
+        decoration
+    }
+
+    /// Returns the `color` of the element, or of its nearest ancestor that declares one
+    pub(crate) fn current_color(&self, node_id: NodeId) -> String {
+        let mut current = Some(node_id);
+        while let Some(node) = current.and_then(|node_id| self.get_node_by_id(node_id)) {
+            let declarations = self.declared_style(node.id);
+            if let Some(color) = cascaded_value(&declarations, "color")
+                .filter(|color| !color.eq_ignore_ascii_case("currentcolor"))
+            {
+                return color.to_string();
+            }
+            current = node.parent;
+        }
+        DEFAULT_COLOR.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::html5::element_style::parse_declarations;
+
+    fn decoration(declarations: &str) -> BoxDecoration {
+        let mut decoration = BoxDecoration::default();
+        decoration.apply(&parse_declarations(declarations));
+        decoration
+    }
+
+    #[test]
+    fn borders() {
+        let border = decoration(
+            "border: 2px solid red; border-left: thick dashed; border-top-width: 1pc; \
+             border-color: blue green !important; border-bottom-style: none",
+        );
+        assert_eq!(border.border_widths(), [16.0, 2.0, 0.0, THICK]);
+        assert_eq!(border.border[0].color, "blue");
+        assert_eq!(border.border[1].color, "green");
+        assert_eq!(border.border[3].style, BorderStyle::Dashed);
+        assert!(border.has_border());
+        assert!(!decoration("border-width: 4px").has_border());
+
+        let colored = decoration("border: rgb(1, 2, 3) 1px double");
+        assert_eq!(colored.border[2].color, "rgb(1, 2, 3)");
+        assert_eq!(colored.border[2].style, BorderStyle::Double);
+
+        let outline = decoration("outline: auto; outline-offset: 2px; outline-width: thin");
+        assert_eq!(outline.outline.style, BorderStyle::Solid);
+        assert_eq!(outline.outline_width(), THIN);
+        let rect = outline.outline_rect(Rect::new(10.0, 10.0, 20.0, 20.0));
+        assert_eq!(rect.rect, Rect::new(7.0, 7.0, 26.0, 26.0));
+        assert!(rect.is_rect());
+
+        let mut current = decoration("border: solid; box-shadow: 1px 1px");
+        current.resolve_current_color("navy");
+        assert_eq!(current.border[0].color, "navy");
+        assert_eq!(current.outline.color, "navy");
+        assert_eq!(current.shadows[0].color, "navy");
+    }
+
+    #[test]
+    fn radii() {
+        let rounded = decoration("border-radius: 10px 50% / 5px; border-bottom-left-radius: 0")
+            .rounded_border_box(Rect::new(0.0, 0.0, 100.0, 40.0));
+        // The top radii (10 + 50) fit the width, the right radii (5 + 5) fit the height
+        assert_eq!(
+            rounded.radii,
+            [(10.0, 5.0), (50.0, 5.0), (10.0, 5.0), (0.0, 0.0)]
+        );
+        assert!(!rounded.contains(0.5, 0.5));
+        assert!(rounded.contains(5.0, 3.0));
+        assert!(rounded.contains(0.5, 39.5));
+        assert!(!rounded.contains(100.0, 20.0));
+
+        // Radii that do not fit are scaled down
+        let scaled = RoundedRect::new(Rect::new(0.0, 0.0, 100.0, 50.0), [(100.0, 100.0); 4]);
+        assert_eq!(scaled.radii, [(25.0, 25.0); 4]);
+
+        let inner = scaled.shrink([5.0, 10.0, 5.0, 30.0]);
+        assert_eq!(inner.rect, Rect::new(30.0, 5.0, 60.0, 40.0));
+        assert_eq!(inner.radii[0], (0.0, 20.0));
+        assert_eq!(inner.radii[1], (15.0, 20.0));
+    }
+
+    #[test]
+    fn shadows() {
+        let shadows =
+            BoxShadow::parse_list("1px 2px 3px 4px rgba(0, 0, 0, 0.5), inset 0 0 2px blue")
+                .unwrap();
+        assert_eq!(
+            shadows,
+            [
+                BoxShadow {
+                    offset_x: 1.0,
+                    offset_y: 2.0,
+                    blur: 3.0,
+                    spread: 4.0,
+                    color: "rgba(0, 0, 0, 0.5)".to_string(),
+                    inset: false,
+                },
+                BoxShadow {
+                    offset_x: 0.0,
+                    offset_y: 0.0,
+                    blur: 2.0,
+                    spread: 0.0,
+                    color: "blue".to_string(),
+                    inset: true,
+                },
+            ]
+        );
+        assert_eq!(BoxShadow::parse_list("none"), Some(Vec::new()));
+        assert_eq!(BoxShadow::parse_list("1px"), None);
+        assert_eq!(BoxShadow::parse_list("1px 1px -2px"), None);
+        assert_eq!(BoxShadow::parse_list("1px 1px red blue"), None);
+
+        let border_box = RoundedRect::new(Rect::new(10.0, 10.0, 20.0, 20.0), [(0.0, 0.0); 4]);
+        assert_eq!(
+            shadows[0].shape(&border_box, [0.0; 4]).rect,
+            Rect::new(7.0, 8.0, 28.0, 28.0)
+        );
+        assert_eq!(
+            shadows[1].shape(&border_box, [1.0; 4]).rect,
+            Rect::new(11.0, 11.0, 18.0, 18.0)
+        );
+
+        let decoration = decoration("box-shadow: 1px 2px 3px 4px red, inset 0 0 9px blue");
+        assert_eq!(
+            decoration.ink_rect(Rect::new(10.0, 10.0, 20.0, 20.0)),
+            Rect::new(4.0, 5.0, 34.0, 34.0)
+        );
+    }
+}
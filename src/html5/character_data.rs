@@ -0,0 +1,268 @@
+//! Character data
+//!
+//! Editing of the text of text and comment nodes, like the `CharacterData` and `Text` interfaces
+//! of the DOM: inserting, deleting and replacing parts of the text, and splitting a text node in
+//! two. Offsets and counts are in characters (just like the boundaries of a `Range`, see the
+//! editing module).
+//!
+//! Every change is a `CharacterData` mutation (and splitting a node attached to a parent also a
+//! `ChildList` mutation), and the boundaries of the selection are moved along with the text they
+//! are in, so a selection stays on the same text.
+//!
+//! See: https://dom.spec.whatwg.org/#interface-characterdata
+use crate::html5::editing::{Boundary, Range};
+use crate::html5::node::{Node, NodeData, NodeId};
+use crate::html5::parser::document::Document;
+use crate::types::{Error, Result};
+
+impl Document {
+    /// Returns the number of characters in the text or comment node
+    pub fn text_length(&self, node_id: NodeId) -> Result<usize> {
+        Ok(self.text_data(node_id)?.chars().count())
+    }
+
+    /// Returns (at most) `count` characters of the text or comment node, starting at the offset.
+    /// Fails when the offset is beyond the end of the text.
+    pub fn substring_data(&self, node_id: NodeId, offset: usize, count: usize) -> Result<String> {
+        let text = self.text_data(node_id)?;
+        check_offset(node_id, text, offset)?;
+        Ok(text.chars().skip(offset).take(count).collect())
+    }
+
+    /// Inserts the data into the text or comment node at the offset
+    pub fn insert_data(&mut self, node_id: NodeId, offset: usize, data: &str) -> Result<()> {
+        self.replace_data(node_id, offset, 0, data)
+    }
+
+    /// Removes (at most) `count` characters from the text or comment node, starting at the offset
+    pub fn delete_data(&mut self, node_id: NodeId, offset: usize, count: usize) -> Result<()> {
+        self.replace_data(node_id, offset, count, "")
+    }
+
+    /// Replaces (at most) `count` characters of the text or comment node, starting at the offset,
+    /// with the data. Fails when the offset is beyond the end of the text.
+    pub fn replace_data(
+        &mut self,
+        node_id: NodeId,
+        offset: usize,
+        count: usize,
+        data: &str,
+    ) -> Result<()> {
+        let text = self.text_data(node_id)?;
+        check_offset(node_id, text, offset)?;
+
+        let count = count.min(text.chars().count() - offset);
+        let mut chars = text.chars();
+        let mut new_text: String = chars.by_ref().take(offset).collect();
+        new_text.push_str(data);
+        new_text.extend(chars.skip(count));
+        self.set_character_data(node_id, &new_text)?;
+
+        // Boundaries in the replaced text move to its start, and boundaries after it move along
+        let inserted = data.chars().count();
+        self.move_selection(|boundary| {
+            if boundary.node_id != node_id || boundary.offset <= offset {
+                return boundary;
+            }
+            if boundary.offset <= offset + count {
+                return Boundary::new(node_id, offset);
+            }
+            Boundary::new(node_id, boundary.offset + inserted - count)
+        });
+
+        Ok(())
+    }
+
+    /// Splits the text node at the offset: the text after it is moved into a new text node, which
+    /// is added right after the node when it has a parent. Returns the ID of the new text node.
+    pub fn split_text(&mut self, node_id: NodeId, offset: usize) -> Result<NodeId> {
+        if let Some(node) = self.get_node_by_id(node_id) {
+            if !matches!(node.data, NodeData::Text(_)) {
+                return Err(Error::DocumentTask(format!(
+                    "Node ID {} is not a text node",
+                    node_id
+                )));
+            }
+        }
+        let length = self.text_length(node_id)?;
+        let tail = self.substring_data(node_id, offset, length.saturating_sub(offset))?;
+        let position = self.get_node_by_id(node_id).and_then(|node| {
+            let parent_id = node.parent?;
+            let index = self
+                .get_node_by_id(parent_id)?
+                .children
+                .iter()
+                .position(|&id| id == node_id)?;
+            Some((parent_id, index))
+        });
+
+        self.begin_transaction();
+        let new_id = match position {
+            Some((parent_id, index)) => {
+                let new_id = match self.add_node(Node::new_text(&tail), parent_id, Some(index + 1))
+                {
+                    Ok(new_id) => new_id,
+                    Err(error) => {
+                        self.end_transaction();
+                        return Err(error);
+                    }
+                };
+                // Boundaries after the text go to the new node, and boundaries between the
+                // children of the parent after the node stay in front of the same child
+                self.move_selection(|boundary| {
+                    if boundary.node_id == node_id && boundary.offset > offset {
+                        Boundary::new(new_id, boundary.offset - offset)
+                    } else if boundary.node_id == parent_id && boundary.offset > index {
+                        Boundary::new(parent_id, boundary.offset + 1)
+                    } else {
+                        boundary
+                    }
+                });
+                new_id
+            }
+            None => self.add_new_node(Node::new_text(&tail)),
+        };
+        let result = self.delete_data(node_id, offset, length - offset);
+        self.end_transaction();
+        result?;
+
+        Ok(new_id)
+    }
+
+    /// Returns the text of the text or comment node
+    fn text_data(&self, node_id: NodeId) -> Result<&str> {
+        match self.get_node_by_id(node_id).map(|node| &node.data) {
+            Some(NodeData::Text(text)) => Ok(text.value()),
+            Some(NodeData::Comment(comment)) => Ok(comment.value()),
+            Some(_) => Err(Error::DocumentTask(format!(
+                "Node ID {} is not a text or comment node",
+                node_id
+            ))),
+            None => Err(Error::DocumentTask(format!(
+                "Node ID {} not found",
+                node_id
+            ))),
+        }
+    }
+
+    /// Moves both boundaries of the selection (if any)
+    fn move_selection(&mut self, f: impl Fn(Boundary) -> Boundary) {
+        if let Some(range) = self.selection {
+            self.selection = Some(Range::new(f(range.start), f(range.end)));
+        }
+    }
+}
+
+/// Fails when the offset is beyond the end of the text
+fn check_offset(node_id: NodeId, text: &str, offset: usize) -> Result<()> {
+    if offset > text.chars().count() {
+        return Err(Error::DocumentTask(format!(
+            "Offset {} is outside of text node {}",
+            offset, node_id
+        )));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::html5::mutation::{MutationCallback, MutationObserverInit};
+    use crate::html5::parser::document::{DocumentHandle, MutationRecord};
+    use crate::testing::parse;
+    use std::rc::Rc;
+
+    fn setup(html: &str) -> (DocumentHandle, NodeId, NodeId) {
+        let document = parse(html);
+
+        let p = document.get().get_node_by_named_id("p").unwrap().id;
+        let text = document.get().get_node_by_id(p).unwrap().children[0];
+        (document, p, text)
+    }
+
+    #[test]
+    fn edit_data() {
+        let (mut document, p, text) = setup("<p id=p>héllo world</p>");
+        let mut doc = document.get_mut();
+        assert_eq!(doc.text_length(text).unwrap(), 11);
+        assert_eq!(doc.substring_data(text, 6, 100).unwrap(), "world");
+        assert!(doc.substring_data(text, 12, 1).is_err());
+        assert!(doc.text_length(p).is_err());
+
+        doc.insert_data(text, 5, ",").unwrap();
+        assert_eq!(doc.text_content(p), "héllo, world");
+        doc.delete_data(text, 0, 1).unwrap();
+        assert_eq!(doc.text_content(p), "éllo, world");
+        doc.replace_data(text, 6, 100, "there").unwrap();
+        assert_eq!(doc.text_content(p), "éllo, there");
+        assert!(doc.insert_data(text, 12, "!").is_err());
+        assert!(doc.delete_data(p, 0, 1).is_err());
+
+        // The selection stays on the same text
+        doc.set_selection(Some(Range::new(
+            Boundary::new(text, 2),
+            Boundary::new(text, 8),
+        )))
+        .unwrap();
+        doc.replace_data(text, 1, 3, "ll").unwrap();
+        assert_eq!(doc.text_content(p), "éll, there");
+        assert_eq!(
+            doc.selection(),
+            Some(Range::new(Boundary::new(text, 1), Boundary::new(text, 7)))
+        );
+    }
+
+    #[test]
+    fn split_text() {
+        let (mut document, p, text) = setup("<p id=p>one two<b>three</b></p>");
+        let b = document.get().get_node_by_id(p).unwrap().children[1];
+        let records = Rc::new(std::cell::RefCell::new(Vec::new()));
+        let log = records.clone();
+        let callback: MutationCallback =
+            Rc::new(move |_, batch| log.borrow_mut().extend(batch.to_vec()));
+        let mut doc = document.get_mut();
+        let observer = doc.create_mutation_observer(callback);
+        let options = MutationObserverInit {
+            child_list: true,
+            character_data: true,
+            subtree: true,
+            ..Default::default()
+        };
+        doc.observe_mutations(observer, p, options).unwrap();
+        doc.set_selection(Some(Range::new(
+            Boundary::new(text, 5),
+            Boundary::new(p, 1),
+        )))
+        .unwrap();
+
+        let tail = doc.split_text(text, 4).unwrap();
+        assert_eq!(doc.get_node_by_id(p).unwrap().children, vec![text, tail, b]);
+        assert_eq!(doc.substring_data(text, 0, 10).unwrap(), "one ");
+        assert_eq!(doc.substring_data(tail, 0, 10).unwrap(), "two");
+        assert_eq!(
+            doc.selection(),
+            Some(Range::new(Boundary::new(tail, 1), Boundary::new(p, 2)))
+        );
+        assert_eq!(
+            doc.take_mutation_records(observer).unwrap(),
+            vec![
+                MutationRecord::ChildList {
+                    parent_id: p,
+                    node_id: tail
+                },
+                MutationRecord::CharacterData(text),
+            ]
+        );
+
+        // Splitting at the end leaves an empty node, and detached nodes are split as well
+        let empty = doc.split_text(tail, 3).unwrap();
+        assert_eq!(doc.text_length(empty).unwrap(), 0);
+        doc.detach_node_from_parent(empty);
+        let detached = Node::new_text("ab");
+        let detached = doc.add_new_node(detached);
+        let b_text = doc.split_text(detached, 1).unwrap();
+        assert_eq!(doc.get_node_by_id(b_text).unwrap().parent, None);
+        assert_eq!(doc.substring_data(b_text, 0, 1).unwrap(), "b");
+        assert!(doc.split_text(detached, 2).is_err());
+    }
+}
@@ -0,0 +1,317 @@
+//! Clipboard
+//!
+//! Copy, cut and paste work on the selection of the document and the clipboard of the system,
+//! which the embedder provides by implementing `Clipboard`. Each action fires its event (`copy`,
+//! `cut` or `paste`) first, with the data in a `DataTransfer`. When a listener cancels the event,
+//! the data it put in the data transfer is written to the clipboard instead of the selection (copy
+//! and cut), or nothing is inserted (paste).
+//!
+//! The selection is written to the clipboard both as HTML (through the serializer) and as plain
+//! text. Cut removes the selection and paste inserts the text of the clipboard, but only when the
+//! selection is editable.
+//!
+//! See: https://w3c.github.io/clipboard-apis/#clipboard-actions
+use crate::html5::drag_drop::{DataTransfer, DataTransferMode};
+use crate::html5::editing::{Editor, Range};
+use crate::html5::events::{Event, EventDetail};
+use crate::html5::node::{NodeData, NodeId};
+use crate::html5::parser::document::{Document, DocumentHandle};
+use crate::html5::serializer::{serialize, SerializerOptions};
+use core::fmt;
+use core::fmt::Debug;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+/// The clipboard of the system, implemented by the embedder
+pub trait Clipboard {
+    /// Returns the data on the clipboard
+    fn read(&mut self) -> DataTransfer;
+    /// Replaces the data on the clipboard
+    fn write(&mut self, data: &DataTransfer);
+}
+
+/// Clipboard of a document, if the embedder provided one
+#[derive(Default)]
+pub(crate) struct ClipboardSlot(Option<Rc<RefCell<dyn Clipboard>>>);
+
+impl Debug for ClipboardSlot {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "ClipboardSlot({})", self.0.is_some())
+    }
+}
+
+impl PartialEq for ClipboardSlot {
+    fn eq(&self, other: &Self) -> bool {
+        match (&self.0, &other.0) {
+            (Some(a), Some(b)) => std::ptr::eq(Rc::as_ptr(a).cast::<()>(), Rc::as_ptr(b).cast()),
+            (a, b) => a.is_none() && b.is_none(),
+        }
+    }
+}
+
+impl Document {
+    /// Sets the clipboard that copy, cut and paste use
+    pub fn set_clipboard(&mut self, clipboard: Rc<RefCell<dyn Clipboard>>) {
+        self.clipboard = ClipboardSlot(Some(clipboard));
+    }
+
+    /// Returns the selection as a data transfer with its HTML and its text
+    pub fn selection_data(&self) -> Option<DataTransfer> {
+        let range = self.selection().filter(|range| !range.is_collapsed())?;
+        let contents = self.clone_range(&range).ok()?;
+        let contents = contents.get();
+
+        let mut data = DataTransfer::new();
+        data.set_data(
+            "text/html",
+            &serialize(&contents, NodeId::root(), &SerializerOptions::default()),
+        );
+        data.set_data("text/plain", &contents.text_content(NodeId::root()));
+        Some(data)
+    }
+
+    /// Returns the node clipboard events are fired at: the element the selection starts in, or
+    /// the body when there is no selection
+    fn clipboard_target(&self) -> NodeId {
+        if let Some(range) = self.selection() {
+            let node = self.get_node_by_id(range.start.node_id);
+            return match node {
+                Some(node) if matches!(node.data, NodeData::Text(_)) => {
+                    node.parent.unwrap_or(node.id)
+                }
+                _ => range.start.node_id,
+            };
+        }
+
+        self.elements_by_tag_name("body")
+            .first()
+            .copied()
+            .unwrap_or(NodeId::root())
+    }
+
+    /// Returns the selection when it can be edited
+    fn editable_selection(&self) -> Option<Range> {
+        self.selection()
+            .filter(|range| self.is_editable(range.start.node_id))
+    }
+}
+
+impl DocumentHandle {
+    /// Copies the selection to the clipboard. Returns true when the clipboard was written.
+    pub fn copy(&mut self) -> bool {
+        self.copy_or_cut("copy")
+    }
+
+    /// Copies the selection to the clipboard, and removes it when it is editable. Returns true
+    /// when the clipboard was written.
+    pub fn cut(&mut self) -> bool {
+        self.copy_or_cut("cut")
+    }
+
+    /// Replaces the selection with the text on the clipboard, when the selection is editable.
+    /// Returns true when text was inserted.
+    pub fn paste(&mut self) -> bool {
+        let clipboard = self.get().clipboard.0.clone();
+        let mut data = match &clipboard {
+            Some(clipboard) => clipboard.borrow_mut().read(),
+            None => DataTransfer::new(),
+        };
+        data.set_mode(DataTransferMode::ReadOnly);
+        let data = Rc::new(RefCell::new(data));
+
+        if !self.fire_clipboard_event("paste", &data) {
+            return false;
+        }
+
+        let Some(range) = self.get().editable_selection() else {
+            return false;
+        };
+        let Some(text) = data.borrow().get_data("text/plain").map(str::to_string) else {
+            return false;
+        };
+        let Ok(caret) = Editor::new(self).insert_text(&range, &text) else {
+            return false;
+        };
+        self.get_mut()
+            .set_selection(Some(Range::collapsed(caret)))
+            .is_ok()
+    }
+
+    fn copy_or_cut(&mut self, event_type: &str) -> bool {
+        let data = Rc::new(RefCell::new(DataTransfer::new()));
+        let canceled = !self.fire_clipboard_event(event_type, &data);
+
+        let data = if canceled {
+            // The listeners provide the data themselves
+            let data = data.borrow().clone();
+            (!data.items().is_empty()).then_some(data)
+        } else {
+            self.get().selection_data()
+        };
+        let Some(data) = data else {
+            return false;
+        };
+
+        let clipboard = self.get().clipboard.0.clone();
+        if let Some(clipboard) = clipboard {
+            clipboard.borrow_mut().write(&data);
+        }
+
+        if event_type == "cut" && !canceled {
+            let range = self.get().editable_selection();
+            if let Some(range) = range {
+                let _ = self.delete_contents(&range);
+            }
+        }
+        true
+    }
+
+    /// Fires a clipboard event carrying the data. Returns false when the event was canceled.
+    fn fire_clipboard_event(&mut self, event_type: &str, data: &Rc<RefCell<DataTransfer>>) -> bool {
+        let target = self.get().clipboard_target();
+        let mut event =
+            Event::new(event_type, true, true).with_detail(EventDetail::DataTransfer(data.clone()));
+        self.dispatch_event(target, &mut event)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::html5::editing::Boundary;
+    use crate::html5::events::EventCallback;
+
+    #[derive(Default)]
+    struct MemoryClipboard {
+        data: DataTransfer,
+    }
+
+    impl Clipboard for MemoryClipboard {
+        fn read(&mut self) -> DataTransfer {
+            self.data.clone()
+        }
+
+        fn write(&mut self, data: &DataTransfer) {
+            self.data = data.clone();
+            self.data.set_mode(DataTransferMode::ReadWrite);
+        }
+    }
+
+    fn parse(html: &str) -> (DocumentHandle, Rc<RefCell<MemoryClipboard>>) {
+        let mut document = crate::testing::parse(html);
+
+        let clipboard = Rc::new(RefCell::new(MemoryClipboard::default()));
+        document.get_mut().set_clipboard(clipboard.clone());
+        (document, clipboard)
+    }
+
+    /// Returns the text nodes of the paragraph, in tree order
+    fn texts(document: &DocumentHandle) -> Vec<NodeId> {
+        let doc = document.get();
+        let p = doc.elements_by_tag_name("p")[0];
+        let mut texts = Vec::new();
+        let mut stack = vec![p];
+        while let Some(node_id) = stack.pop() {
+            let node = doc.get_node_by_id(node_id).unwrap();
+            if matches!(node.data, NodeData::Text(_)) {
+                texts.push(node_id);
+            }
+            stack.extend(node.children.iter().rev());
+        }
+        texts
+    }
+
+    fn select(document: &mut DocumentHandle, start: (usize, usize), end: (usize, usize)) {
+        let texts = texts(document);
+        let range = Range::new(
+            Boundary::new(texts[start.0], start.1),
+            Boundary::new(texts[end.0], end.1),
+        );
+        document.get_mut().set_selection(Some(range)).unwrap();
+    }
+
+    fn paragraph(document: &DocumentHandle) -> String {
+        let doc = document.get();
+        let p = doc.elements_by_tag_name("p")[0];
+        serialize(&doc, p, &SerializerOptions::default())
+    }
+
+    #[test]
+    fn copy_selection() {
+        let (mut document, clipboard) = parse("<p>Hello <b>big</b> world</p>");
+
+        // Nothing to copy without a selection
+        assert!(!document.copy());
+
+        select(&mut document, (0, 2), (2, 3));
+        assert!(document.copy());
+        {
+            let data = &clipboard.borrow().data;
+            assert_eq!(data.get_data("text/html"), Some("llo <b>big</b> wo"));
+            assert_eq!(data.get_data("text/plain"), Some("llo big wo"));
+        }
+
+        // Cut does not remove the selection when it is not editable
+        assert!(document.cut());
+        assert_eq!(paragraph(&document), "Hello <b>big</b> world");
+    }
+
+    #[test]
+    fn cut_and_paste() {
+        let (mut document, clipboard) = parse("<p contenteditable>Hello <b>big</b> world</p>");
+
+        select(&mut document, (1, 0), (1, 3));
+        assert!(document.cut());
+        assert_eq!(clipboard.borrow().data.get_data("text/html"), Some("big"));
+        assert_eq!(paragraph(&document), "Hello <b></b> world");
+
+        select(&mut document, (0, 0), (0, 0));
+        assert!(document.paste());
+        assert_eq!(paragraph(&document), "bigHello <b></b> world");
+        let caret = document.get().selection().unwrap();
+        assert!(caret.is_collapsed());
+        assert_eq!(caret.start.offset, 3);
+    }
+
+    #[test]
+    fn canceled_events() {
+        let (mut document, clipboard) = parse("<p contenteditable>Hello world</p>");
+        let p = document.get().elements_by_tag_name("p")[0];
+
+        // A canceled copy writes the data of the listener
+        let on_copy: EventCallback = Rc::new(|_, event| {
+            let data = event.data_transfer().unwrap().clone();
+            data.borrow_mut().set_data("text/plain", "custom");
+            event.prevent_default();
+        });
+        document
+            .get_mut()
+            .add_event_listener(p, "copy", on_copy, Default::default());
+        select(&mut document, (0, 0), (0, 5));
+        assert!(document.copy());
+        assert_eq!(
+            clipboard.borrow().data.get_data("text/plain"),
+            Some("custom")
+        );
+
+        // A canceled paste inserts nothing, but the listener can read the data
+        let pasted = Rc::new(RefCell::new(String::new()));
+        let recorder = pasted.clone();
+        let on_paste: EventCallback = Rc::new(move |_, event| {
+            let data = event.data_transfer().unwrap().clone();
+            *recorder.borrow_mut() = data
+                .borrow()
+                .get_data("text")
+                .unwrap_or_default()
+                .to_string();
+            event.prevent_default();
+        });
+        document
+            .get_mut()
+            .add_event_listener(p, "paste", on_paste, Default::default());
+        assert!(!document.paste());
+        assert_eq!(*pasted.borrow(), "custom");
+        assert_eq!(paragraph(&document), "Hello world");
+    }
+}
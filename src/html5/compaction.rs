@@ -0,0 +1,275 @@
+//! Arena compaction
+//!
+//! Node IDs are never reused, so a document that has been mutated heavily (a long-lived page, an
+//! editor) ends up with IDs spread over a large range, and nodes that are next to each other in
+//! the tree are far apart in the arena. `Document::compact()` renumbers the nodes: the nodes of the
+//! tree get consecutive IDs in tree order, followed by the detached nodes. All state of the
+//! document that refers to nodes (lookups, indexes, listeners, observers, form state, the
+//! selection) is updated.
+//!
+//! Node IDs held outside of the document are not updated: compact() returns the old and new ID
+//! of every node, so embedders can translate the IDs they keep. This includes document task
+//! queues created before the compaction, which predict the IDs of the nodes they create.
+use crate::html5::node::arena::NodeArena;
+use crate::html5::node::{Node, NodeData, NodeId};
+use crate::html5::parser::document::{Document, DocumentHandle, MutationRecord};
+use crate::types::{Error, Result};
+use std::collections::{BTreeSet, HashMap};
+
+/// New ID of every node, by its old ID
+pub type NodeIdMap = HashMap<NodeId, NodeId>;
+
+/// Returns the new ID of the node, or None when the node does not exist anymore
+pub(crate) fn remap(ids: &NodeIdMap, node_id: NodeId) -> Option<NodeId> {
+    ids.get(&node_id).copied()
+}
+
+/// Replaces the node IDs of the keys by their new IDs, and drops the entries of nodes that do not
+/// exist anymore
+pub(crate) fn remap_keys<V>(ids: &NodeIdMap, map: &mut HashMap<NodeId, V>) {
+    *map = std::mem::take(map)
+        .into_iter()
+        .filter_map(|(node_id, value)| Some((remap(ids, node_id)?, value)))
+        .collect();
+}
+
+/// Replaces the node IDs in the set by their new IDs
+pub(crate) fn remap_set(ids: &NodeIdMap, set: &mut BTreeSet<NodeId>) {
+    *set = set
+        .iter()
+        .filter_map(|&node_id| remap(ids, node_id))
+        .collect();
+}
+
+impl MutationRecord {
+    /// Returns the record with the new IDs of its nodes, or None when one of them does not exist
+    /// anymore
+    pub(crate) fn remap(&self, ids: &NodeIdMap) -> Option<MutationRecord> {
+        Some(match self {
+            MutationRecord::NodeCreated(node_id) => {
+                MutationRecord::NodeCreated(remap(ids, *node_id)?)
+            }
+            MutationRecord::ChildList { parent_id, node_id } => MutationRecord::ChildList {
+                parent_id: remap(ids, *parent_id)?,
+                node_id: remap(ids, *node_id)?,
+            },
+            MutationRecord::Attribute { node_id, name } => MutationRecord::Attribute {
+                node_id: remap(ids, *node_id)?,
+                name: name.clone(),
+            },
+            MutationRecord::CharacterData(node_id) => {
+                MutationRecord::CharacterData(remap(ids, *node_id)?)
+            }
+        })
+    }
+}
+
+impl Document {
+    /// Renumbers the nodes so that their IDs are consecutive, in tree order. Returns the new ID
+    /// of every node by its old ID. Fails when the undo history or a transaction is active, as
+    /// their records refer to the old IDs.
+    pub fn compact(&mut self) -> Result<NodeIdMap> {
+        if self.history.is_some() || self.journal.is_some() {
+            return Err(Error::DocumentTask(
+                "cannot compact a document with an active undo history or transaction".to_string(),
+            ));
+        }
+
+        let ids = self.compacted_ids();
+        let mut nodes: Vec<Node> = ids
+            .iter()
+            .filter_map(|(&old_id, _)| self.arena.get_node(old_id).cloned())
+            .collect();
+        nodes.sort_by_key(|node| usize::from(ids[&node.id]));
+        for node in &mut nodes {
+            node.id = ids[&node.id];
+            node.parent = node.parent.and_then(|parent_id| remap(&ids, parent_id));
+            node.children = node
+                .children
+                .iter()
+                .filter_map(|&child_id| remap(&ids, child_id))
+                .collect();
+            if let NodeData::Element(element) = &mut node.data {
+                element.set_id(node.id);
+                if let Some(template_contents) = &mut element.template_contents {
+                    template_contents.host =
+                        remap(&ids, template_contents.host).unwrap_or(template_contents.host);
+                }
+            }
+        }
+        self.arena = NodeArena::from_nodes(nodes, NodeId::from(ids.len()));
+
+        self.named_id_elements = std::mem::take(&mut self.named_id_elements)
+            .into_iter()
+            .filter_map(|(name, node_id)| Some((name, remap(&ids, node_id)?)))
+            .collect();
+        for elements in self
+            .class_elements
+            .values_mut()
+            .chain(self.name_elements.values_mut())
+        {
+            remap_set(&ids, elements);
+        }
+        remap_keys(&ids, &mut self.node_atoms);
+        remap_keys(&ids, &mut self.scroll_offsets);
+        self.base_element = self.base_element.and_then(|id| remap(&ids, id));
+        self.focused = self.focused.and_then(|id| remap(&ids, id));
+        self.selection = self.selection.and_then(|mut range| {
+            range.start.node_id = remap(&ids, range.start.node_id)?;
+            range.end.node_id = remap(&ids, range.end.node_id)?;
+            Some(range)
+        });
+        if let Some(indexes) = &mut self.indexes {
+            indexes.remap_nodes(&ids);
+        }
+        self.clear_query_cache();
+        self.event_listeners.remap_nodes(&ids);
+        self.dialogs.remap_nodes(&ids);
+        self.form_controls.remap_nodes(&ids);
+        self.pointers.remap_nodes(&ids);
+        self.intersection_observers.remap_nodes(&ids);
+        self.resize_observers.remap_nodes(&ids);
+        self.mutation_observers.remap_nodes(&ids);
+
+        Ok(ids)
+    }
+
+    /// Returns the new IDs of the nodes: the nodes of the tree in tree order, followed by the
+    /// detached subtrees in order of their roots
+    fn compacted_ids(&self) -> NodeIdMap {
+        let mut ids = NodeIdMap::new();
+        let add_subtree = |ids: &mut NodeIdMap, root: NodeId| {
+            for node_id in std::iter::once(root).chain(self.descendants(root).map(|node| node.id)) {
+                let new_id = NodeId::from(ids.len());
+                ids.entry(node_id).or_insert(new_id);
+            }
+        };
+
+        add_subtree(&mut ids, NodeId::root());
+        for node in self.arena.nodes() {
+            if !ids.contains_key(&node.id) && node.parent.is_none() {
+                add_subtree(&mut ids, node.id);
+            }
+        }
+        // Nodes with a parent that does not exist anymore
+        for node in self.arena.nodes() {
+            if !ids.contains_key(&node.id) {
+                add_subtree(&mut ids, node.id);
+            }
+        }
+
+        ids
+    }
+}
+
+impl DocumentHandle {
+    /// Renumbers the nodes so that their IDs are consecutive. See `Document::compact()`.
+    pub fn compact(&mut self) -> Result<NodeIdMap> {
+        self.get_mut().compact()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::html5::mutation::MutationObserverInit;
+    use crate::html5::node::{Node, HTML_NAMESPACE};
+    use crate::html5::parser::tree_builder::TreeBuilder;
+    use crate::testing::parse;
+    use std::collections::HashMap;
+    use std::rc::Rc;
+
+    #[test]
+    fn compact() {
+        let mut document = parse("<div id=a class=x><p>one</p></div><div id=b>two</div>");
+        let b = document.get().get_node_by_named_id("b").unwrap().id;
+        for _ in 0..10 {
+            let node_id = document
+                .create_element("span", b, None, HTML_NAMESPACE)
+                .unwrap();
+            document.remove_subtree(node_id).unwrap();
+        }
+        let em = document
+            .create_element("em", b, None, HTML_NAMESPACE)
+            .unwrap();
+        let detached =
+            document
+                .get_mut()
+                .add_new_node(Node::new_element("i", HashMap::new(), HTML_NAMESPACE));
+        let html = document.serialize();
+
+        let ids = document.compact().unwrap();
+        assert_eq!(ids.len(), document.get().arena.count_nodes());
+        assert_eq!(document.serialize(), html);
+
+        // The IDs are consecutive, in tree order, followed by the detached node
+        let doc = document.get();
+        let tree: Vec<NodeId> = std::iter::once(NodeId::root())
+            .chain(doc.descendants(NodeId::root()).map(|node| node.id))
+            .collect();
+        let expected: Vec<NodeId> = (0..tree.len()).map(NodeId::from).collect();
+        assert_eq!(tree, expected);
+        assert_eq!(ids[&detached], NodeId::from(tree.len()));
+        assert_eq!(doc.arena.peek_next_id(), NodeId::from(tree.len() + 1));
+
+        // Lookups refer to the new IDs
+        assert_eq!(doc.get_node_by_named_id("b").unwrap().id, ids[&b]);
+        assert_eq!(doc.get_node_by_id(ids[&em]).unwrap().name, "em");
+        assert_eq!(doc.get_node_by_id(ids[&em]).unwrap().parent, Some(ids[&b]));
+        drop(doc);
+        let a = document.get().get_node_by_named_id("a").unwrap().id;
+        assert_eq!(document.query_selector(".x").unwrap(), Some(a));
+
+        // Compaction is not possible while the undo history refers to the old IDs
+        document.get_mut().enable_history();
+        assert!(document.compact().is_err());
+    }
+
+    #[test]
+    fn compact_remaps_state() {
+        let mut document = parse("<div id=a><input id=i></div>");
+        let div = document.get().get_node_by_named_id("a").unwrap().id;
+        let input = document.get().get_node_by_named_id("i").unwrap().id;
+        for _ in 0..5 {
+            let node_id = document
+                .create_element("span", div, None, HTML_NAMESPACE)
+                .unwrap();
+            document.remove_subtree(node_id).unwrap();
+        }
+
+        document.set_control_value(input, "typed");
+        document.focus(input);
+        let observer = document
+            .get_mut()
+            .create_mutation_observer(Rc::new(|_, _| {}));
+        let options = MutationObserverInit {
+            attributes: true,
+            subtree: true,
+            ..Default::default()
+        };
+        document
+            .get_mut()
+            .observe_mutations(observer, div, options)
+            .unwrap();
+        document.insert_attribute("title", "t", input).unwrap();
+
+        let ids = document.compact().unwrap();
+        let input = ids[&input];
+        let doc = document.get();
+        assert_eq!(doc.control_value(input), "typed");
+        assert_eq!(doc.focused_element(), Some(input));
+        drop(doc);
+
+        // Queued records and observed targets refer to the new IDs
+        let records = document.get_mut().take_mutation_records(observer).unwrap();
+        assert_eq!(
+            records,
+            [MutationRecord::Attribute {
+                node_id: input,
+                name: "title".to_string()
+            }]
+        );
+        document.insert_attribute("lang", "en", input).unwrap();
+        assert!(document.get().has_mutation_records());
+    }
+}
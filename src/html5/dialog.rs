@@ -0,0 +1,312 @@
+//! Dialog elements
+//!
+//! A `<dialog>` element is shown through its `open` attribute. Dialogs shown with
+//! `show_modal_dialog()` are placed in the top layer: they are rendered on top of everything else,
+//! in the order they were shown, and the rest of the document becomes inert while they are open.
+//!
+//! Closing a dialog fires `close`. Requesting it to close (like pressing escape would) fires a
+//! cancelable `cancel` first.
+//!
+//! See: https://html.spec.whatwg.org/multipage/interactive-elements.html#the-dialog-element
+use crate::html5::compaction::{remap, remap_keys, NodeIdMap};
+use crate::html5::events::Event;
+use crate::html5::node::{NodeId, HTML_NAMESPACE};
+use crate::html5::parser::document::{Document, DocumentHandle};
+use crate::html5::parser::tree_builder::TreeBuilder;
+use crate::types::{Error, Result};
+use std::collections::HashMap;
+
+/// State of the dialogs in a document
+#[derive(Debug, Default, PartialEq)]
+pub(crate) struct Dialogs {
+    /// Modal dialogs, from bottom to top
+    top_layer: Vec<NodeId>,
+    /// Return values of the dialogs that have been closed with one
+    return_values: HashMap<NodeId, String>,
+}
+
+impl Dialogs {
+    /// Replaces the IDs of the dialogs by their new IDs
+    pub(crate) fn remap_nodes(&mut self, ids: &NodeIdMap) {
+        self.top_layer = self
+            .top_layer
+            .iter()
+            .filter_map(|&node_id| remap(ids, node_id))
+            .collect();
+        remap_keys(ids, &mut self.return_values);
+    }
+
+    /// Forgets the dialog, as it has been removed from the document
+    pub(crate) fn forget_node(&mut self, node_id: NodeId) {
+        self.top_layer.retain(|id| *id != node_id);
+        self.return_values.remove(&node_id);
+    }
+}
+
+impl Document {
+    /// Returns true when the dialog is open
+    pub fn is_dialog_open(&self, node_id: NodeId) -> bool {
+        self.get_node_by_id(node_id)
+            .is_some_and(|node| is_dialog(self, node_id) && node.get_attribute("open").is_some())
+    }
+
+    /// Returns true when the dialog has been shown with `show_modal_dialog()` and is still open
+    pub fn is_dialog_modal(&self, node_id: NodeId) -> bool {
+        self.dialogs.top_layer.contains(&node_id)
+    }
+
+    /// Returns the return value of the dialog, as given when it was last closed
+    pub fn dialog_return_value(&self, node_id: NodeId) -> Option<&str> {
+        self.dialogs.return_values.get(&node_id).map(String::as_str)
+    }
+
+    /// Returns the elements in the top layer, from bottom to top. These are rendered on top of
+    /// the document (and each other) in this order.
+    pub fn top_layer(&self) -> &[NodeId] {
+        &self.dialogs.top_layer
+    }
+
+    /// Runs the removing steps of the dialogs: modal dialogs that are no longer connected are
+    /// removed from the top layer. They stay open, but are not modal anymore.
+    pub(crate) fn remove_disconnected_dialogs(&mut self) {
+        if self.dialogs.top_layer.is_empty() {
+            return;
+        }
+
+        let top_layer = std::mem::take(&mut self.dialogs.top_layer);
+        self.dialogs.top_layer = top_layer
+            .into_iter()
+            .filter(|&node_id| self.is_connected(node_id))
+            .collect();
+    }
+
+    /// Returns true when the node is blocked by a modal dialog: there is a modal dialog, and the
+    /// node is not inside the topmost one
+    pub(crate) fn is_blocked_by_modal_dialog(&self, node_id: NodeId) -> bool {
+        let Some(dialog_id) = self.dialogs.top_layer.last() else {
+            return false;
+        };
+
+        let mut current = Some(node_id);
+        while let Some(node) = current.and_then(|node_id| self.get_node_by_id(node_id)) {
+            if node.id == *dialog_id {
+                return false;
+            }
+            current = node.parent;
+        }
+
+        true
+    }
+}
+
+impl DocumentHandle {
+    /// Shows the dialog (non-modal). Fails when the dialog is already open as a modal dialog.
+    pub fn show_dialog(&mut self, node_id: NodeId) -> Result<()> {
+        self.check_dialog(node_id)?;
+        if self.get().is_dialog_open(node_id) {
+            if self.get().is_dialog_modal(node_id) {
+                return Err(invalid_state(node_id, "is already open as a modal dialog"));
+            }
+            return Ok(());
+        }
+
+        self.insert_attribute("open", "", node_id)
+    }
+
+    /// Shows the dialog as a modal dialog, on top of the top layer. Fails when the dialog is
+    /// already open as a non-modal dialog, or when it is not connected to the document.
+    pub fn show_modal_dialog(&mut self, node_id: NodeId) -> Result<()> {
+        self.check_dialog(node_id)?;
+        if self.get().is_dialog_open(node_id) {
+            if !self.get().is_dialog_modal(node_id) {
+                return Err(invalid_state(
+                    node_id,
+                    "is already open as a non-modal dialog",
+                ));
+            }
+            return Ok(());
+        }
+        if !self.get().is_connected(node_id) {
+            return Err(invalid_state(node_id, "is not connected"));
+        }
+
+        self.insert_attribute("open", "", node_id)?;
+        self.get_mut().dialogs.top_layer.push(node_id);
+        Ok(())
+    }
+
+    /// Closes the dialog and fires `close`. When a return value is given, it is stored as the
+    /// return value of the dialog. Closing a dialog that is not open does nothing.
+    pub fn close_dialog(&mut self, node_id: NodeId, return_value: Option<&str>) -> Result<()> {
+        self.check_dialog(node_id)?;
+        if !self.get().is_dialog_open(node_id) {
+            return Ok(());
+        }
+
+        self.remove_attribute("open", node_id)?;
+        {
+            let mut doc = self.get_mut();
+            doc.dialogs.top_layer.retain(|id| *id != node_id);
+            if let Some(return_value) = return_value {
+                doc.dialogs
+                    .return_values
+                    .insert(node_id, return_value.to_string());
+            }
+        }
+
+        let mut event = Event::new("close", false, false);
+        self.dispatch_event(node_id, &mut event);
+        Ok(())
+    }
+
+    /// Requests the topmost modal dialog to close, as pressing escape does. Fires `cancel`, and
+    /// closes the dialog unless the event was canceled. Returns true when a dialog was closed.
+    pub fn cancel_dialog(&mut self) -> bool {
+        let Some(dialog_id) = self.get().dialogs.top_layer.last().copied() else {
+            return false;
+        };
+
+        let mut event = Event::new("cancel", false, true);
+        if !self.dispatch_event(dialog_id, &mut event) {
+            return false;
+        }
+
+        self.close_dialog(dialog_id, None).is_ok()
+    }
+
+    fn check_dialog(&self, node_id: NodeId) -> Result<()> {
+        if is_dialog(&self.get(), node_id) {
+            Ok(())
+        } else {
+            Err(Error::DocumentTask(format!(
+                "Node ID {} is not a dialog element",
+                node_id
+            )))
+        }
+    }
+}
+
+fn is_dialog(document: &Document, node_id: NodeId) -> bool {
+    document
+        .get_node_by_id(node_id)
+        .is_some_and(|node| node.is_namespace(HTML_NAMESPACE) && node.name == "dialog")
+}
+
+fn invalid_state(node_id: NodeId, reason: &str) -> Error {
+    Error::DocumentTask(format!("Dialog {} {}", node_id, reason))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::html5::events::EventCallback;
+    use crate::testing::parse;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    fn log_events(document: &mut DocumentHandle, node_id: NodeId, log: &Rc<RefCell<Vec<String>>>) {
+        for event_type in ["close", "cancel"] {
+            let log = log.clone();
+            let callback: EventCallback =
+                Rc::new(move |_, event| log.borrow_mut().push(event.event_type.clone()));
+            document.get_mut().add_event_listener(
+                node_id,
+                event_type,
+                callback,
+                Default::default(),
+            );
+        }
+    }
+
+    #[test]
+    fn show_and_close() {
+        let mut document = parse("<dialog><form>x</form></dialog><p>page</p>");
+        let dialog = document.get().elements_by_tag_name("dialog")[0];
+        let p = document.get().elements_by_tag_name("p")[0];
+        let log = Rc::new(RefCell::new(Vec::new()));
+        log_events(&mut document, dialog, &log);
+
+        document.show_dialog(dialog).unwrap();
+        assert!(document.get().is_dialog_open(dialog));
+        assert!(!document.get().is_dialog_modal(dialog));
+        assert!(document.get().top_layer().is_empty());
+        assert!(!document.get().is_inert(p));
+        assert!(document.show_modal_dialog(dialog).is_err());
+
+        document.close_dialog(dialog, Some("ok")).unwrap();
+        assert!(!document.get().is_dialog_open(dialog));
+        assert_eq!(document.get().dialog_return_value(dialog), Some("ok"));
+        assert_eq!(*log.borrow(), vec!["close"]);
+
+        // Closing a closed dialog does not fire close again
+        document.close_dialog(dialog, None).unwrap();
+        assert_eq!(log.borrow().len(), 1);
+        assert!(document.show_dialog(p).is_err());
+    }
+
+    #[test]
+    fn modal() {
+        let mut document = parse(
+            "<dialog id=a><button id=b1>x</button></dialog>\
+             <dialog id=b><button id=b2>y</button></dialog><p>page</p>",
+        );
+        let named = |document: &DocumentHandle, id: &str| {
+            document.get().get_node_by_named_id(id).unwrap().id
+        };
+        let (a, b) = (named(&document, "a"), named(&document, "b"));
+        let (b1, b2) = (named(&document, "b1"), named(&document, "b2"));
+        let p = document.get().elements_by_tag_name("p")[0];
+        let log = Rc::new(RefCell::new(Vec::new()));
+        log_events(&mut document, b, &log);
+
+        document.show_modal_dialog(a).unwrap();
+        document.show_modal_dialog(b).unwrap();
+        assert_eq!(document.get().top_layer(), &[a, b]);
+        assert!(document.show_dialog(b).is_err());
+
+        // Only the topmost modal dialog can be interacted with
+        assert!(document.get().is_inert(p));
+        assert!(document.get().is_inert(b1));
+        assert!(!document.get().is_inert(b2));
+        assert!(!document.click(b1));
+
+        // Canceling the cancel event keeps the dialog open
+        let cancel: EventCallback = Rc::new(|_, event| event.prevent_default());
+        document
+            .get_mut()
+            .add_event_listener(b, "cancel", cancel, Default::default());
+        assert!(!document.cancel_dialog());
+        assert!(document.get().is_dialog_open(b));
+        assert_eq!(*log.borrow(), vec!["cancel"]);
+
+        document.close_dialog(b, None).unwrap();
+        assert_eq!(document.get().top_layer(), &[a]);
+        assert!(!document.get().is_inert(b1));
+        assert!(document.cancel_dialog());
+        assert!(document.get().top_layer().is_empty());
+        assert!(!document.get().is_inert(p));
+    }
+
+    #[test]
+    fn removed_modal_dialogs() {
+        let mut document = parse("<div><dialog id=a></dialog></div><dialog id=b></dialog><p>x</p>");
+        let named = |document: &DocumentHandle, id: &str| {
+            document.get().get_node_by_named_id(id).unwrap().id
+        };
+        let (a, b) = (named(&document, "a"), named(&document, "b"));
+        let div = document.get().elements_by_tag_name("div")[0];
+        let p = document.get().elements_by_tag_name("p")[0];
+
+        document.show_modal_dialog(a).unwrap();
+        document.show_modal_dialog(b).unwrap();
+        document.remove_subtree(b).unwrap();
+        assert_eq!(document.get().top_layer(), &[a]);
+
+        // Detaching an ancestor removes the dialog from the top layer, but keeps it open
+        document.detach_node_from_parent(div);
+        assert!(document.get().top_layer().is_empty());
+        assert!(document.get().is_dialog_open(a));
+        assert!(!document.get().is_inert(p));
+        assert!(!document.cancel_dialog());
+    }
+}
@@ -0,0 +1,1109 @@
+//! Display lists
+//!
+//! The display list holds the paint operations of a document in paint order (see `stacking`).
+//! The embedder replays it on its paint backend, which implements the [`Painter`] trait.
+//!
+//! Each element paints its outer box shadows, its background color, its background images (see
+//! `background`), its content, its inset box shadows and its border (see `box_decoration`), in
+//! that order. Multiple shadows are painted from the
+//! bottom one to the top one. Outlines are painted last in their stacking context, on top of
+//! everything else in it.
+//!
+//! A stacking context with an opacity below 1, a filter or a blend mode is painted as a group:
+//! its element and contents are painted into an offscreen layer, which is then composited with
+//! the filters, the opacity and the blend mode applied (in that order). Groups with an opacity of
+//! 0 are left out, as they are not visible. The supported filters are `blur()` and
+//! `grayscale()`; a `filter` value with other functions is ignored.
+//!
+//! Elements are clipped by the overflow of their ancestors (see `scroll`): the items of an
+//! element are painted between a push and a pop of the clips of those ancestors. Consecutive
+//! elements share their clips, so they are only pushed and popped when they change. A group is
+//! clipped as a whole by the clips of its element. The scrollbars of a scroll container are
+//! painted right after its border.
+//!
+//! See: https://drafts.fxtf.org/filter-effects/ and https://drafts.fxtf.org/compositing/
+use crate::html5::background::{BackgroundImage, Tiling};
+use crate::html5::box_decoration::{BorderSide, BoxShadow, RoundedRect};
+use crate::html5::element_style::cascaded_value;
+use crate::html5::geometry::Rect;
+use crate::html5::node::NodeId;
+use crate::html5::parser::document::Document;
+use crate::html5::scroll::Scrollbar;
+use crate::html5::stacking::{StackingContext, StackingKind};
+use crate::html5::transform::{parse_length, parse_number, Matrix};
+use crate::types::{Error, Result};
+
+/// A filter function of the `filter` property
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Filter {
+    /// Gaussian blur with the standard deviation in CSS pixels
+    Blur(f32),
+    /// Conversion to grayscale, from 0 (unchanged) to 1 (completely gray)
+    Grayscale(f32),
+}
+
+impl Filter {
+    /// Returns how far the filter spreads the painted pixels, in CSS pixels
+    pub fn spread(&self) -> f32 {
+        match self {
+            // The kernel of a gaussian blur is about 3 standard deviations wide
+            Filter::Blur(deviation) => deviation * 3.0,
+            Filter::Grayscale(_) => 0.0,
+        }
+    }
+}
+
+/// Parses a `filter` value into its functions. `none` has no functions.
+pub fn parse_filter(value: &str) -> Result<Vec<Filter>> {
+    let value = value.trim();
+    if value.eq_ignore_ascii_case("none") {
+        return Ok(Vec::new());
+    }
+
+    let mut filters = Vec::new();
+    let mut rest = value;
+    while !rest.is_empty() {
+        let (Some(open), Some(close)) = (rest.find('('), rest.find(')')) else {
+            return Err(Error::Parse(format!("invalid filter '{value}'")));
+        };
+        if close < open {
+            return Err(Error::Parse(format!("invalid filter '{value}'")));
+        }
+        let name = rest[..open].trim().to_ascii_lowercase();
+        let argument = rest[open + 1..close].trim();
+        let filter = match name.as_str() {
+            "blur" if argument.is_empty() => Filter::Blur(0.0),
+            "blur" => Filter::Blur(parse_length(argument)?.max(0.0)),
+            "grayscale" if argument.is_empty() => Filter::Grayscale(1.0),
+            "grayscale" => {
+                let amount = match argument.strip_suffix('%') {
+                    Some(percentage) => parse_number(percentage)? / 100.0,
+                    None => parse_number(argument)?,
+                };
+                Filter::Grayscale(amount.clamp(0.0, 1.0))
+            }
+            _ => {
+                return Err(Error::Parse(format!(
+                    "unsupported filter function '{name}()'"
+                )))
+            }
+        };
+        filters.push(filter);
+        rest = rest[close + 1..].trim_start();
+    }
+
+    if filters.is_empty() {
+        return Err(Error::Parse("empty filter".to_string()));
+    }
+    Ok(filters)
+}
+
+/// How a group is composited with what is painted below it (the `mix-blend-mode` property)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum BlendMode {
+    #[default]
+    Normal,
+    Multiply,
+    Screen,
+    Overlay,
+    Darken,
+    Lighten,
+    ColorDodge,
+    ColorBurn,
+    HardLight,
+    SoftLight,
+    Difference,
+    Exclusion,
+    Hue,
+    Saturation,
+    Color,
+    Luminosity,
+}
+
+impl BlendMode {
+    /// Returns the blend mode for the keyword, or None when it is unknown
+    pub fn from_keyword(keyword: &str) -> Option<BlendMode> {
+        let mode = match keyword.trim().to_ascii_lowercase().as_str() {
+            "normal" => BlendMode::Normal,
+            "multiply" => BlendMode::Multiply,
+            "screen" => BlendMode::Screen,
+            "overlay" => BlendMode::Overlay,
+            "darken" => BlendMode::Darken,
+            "lighten" => BlendMode::Lighten,
+            "color-dodge" => BlendMode::ColorDodge,
+            "color-burn" => BlendMode::ColorBurn,
+            "hard-light" => BlendMode::HardLight,
+            "soft-light" => BlendMode::SoftLight,
+            "difference" => BlendMode::Difference,
+            "exclusion" => BlendMode::Exclusion,
+            "hue" => BlendMode::Hue,
+            "saturation" => BlendMode::Saturation,
+            "color" => BlendMode::Color,
+            "luminosity" => BlendMode::Luminosity,
+            _ => return None,
+        };
+        Some(mode)
+    }
+}
+
+/// An offscreen layer a group is painted into
+#[derive(Debug, Clone, PartialEq)]
+pub struct Layer {
+    /// The element that creates the group
+    pub element: NodeId,
+    pub opacity: f32,
+    /// Filters that are applied to the layer, in order
+    pub filters: Vec<Filter>,
+    pub blend_mode: BlendMode,
+    /// The part of the viewport the layer covers: the bounding boxes of the elements in the
+    /// group (with their outlines and shadows), grown by the spread of the filters
+    pub bounds: Rect,
+}
+
+/// A paint operation
+#[derive(Debug, Clone, PartialEq)]
+pub enum DisplayItem {
+    /// Starts painting into a new layer
+    PushLayer(Layer),
+    /// Composites the last pushed layer onto the layer below it
+    PopLayer,
+    /// Clips what is painted until the matching `PopClip` to the overflow clip of an element
+    PushClip {
+        element: NodeId,
+        clip: RoundedRect,
+        transform: Matrix,
+    },
+    /// Removes the last pushed clip
+    PopClip,
+    /// Paints the background color of an element in the clip (its border box)
+    BackgroundColor {
+        element: NodeId,
+        color: String,
+        clip: RoundedRect,
+        transform: Matrix,
+    },
+    /// Paints a background image of an element: the image in each of the tiles, clipped to the
+    /// border box
+    BackgroundImage {
+        element: NodeId,
+        image: BackgroundImage,
+        tiling: Tiling,
+        clip: RoundedRect,
+        transform: Matrix,
+    },
+    /// Paints the content of an element. The transform maps the border box to the viewport.
+    Element {
+        element: NodeId,
+        border_box: Rect,
+        transform: Matrix,
+    },
+    /// Paints a box shadow: the (blurred) shape, outside the border box for an outer shadow and
+    /// inside the padding box for an inset shadow
+    BoxShadow {
+        element: NodeId,
+        shadow: BoxShadow,
+        shape: RoundedRect,
+        /// The border box for an outer shadow, or the padding box for an inset shadow
+        clip: RoundedRect,
+        transform: Matrix,
+    },
+    /// Paints the border: the area between the outer and inner edge
+    Border {
+        element: NodeId,
+        outer: RoundedRect,
+        inner: RoundedRect,
+        /// Top, right, bottom and left
+        sides: [BorderSide; 4],
+        transform: Matrix,
+    },
+    /// Paints the outline: the area between the outer and inner edge
+    Outline {
+        element: NodeId,
+        outer: RoundedRect,
+        inner: RoundedRect,
+        side: BorderSide,
+        transform: Matrix,
+    },
+    /// Paints a scrollbar of a scroll container
+    Scrollbar {
+        element: NodeId,
+        scrollbar: Scrollbar,
+        transform: Matrix,
+    },
+}
+
+/// Paint backend of the embedder, which the display list is replayed on
+pub trait Painter {
+    /// Starts painting into an offscreen layer
+    fn push_layer(&mut self, layer: &Layer);
+    /// Composites the offscreen layer with its filters, opacity and blend mode
+    fn pop_layer(&mut self, layer: &Layer);
+    /// Clips what is painted next to the (transformed) clip, on top of the current clips
+    fn push_clip(&mut self, element: NodeId, clip: &RoundedRect, transform: &Matrix);
+    /// Removes the last pushed clip
+    fn pop_clip(&mut self);
+    /// Paints the background color of an element
+    fn paint_background_color(
+        &mut self,
+        element: NodeId,
+        color: &str,
+        clip: &RoundedRect,
+        transform: &Matrix,
+    );
+    /// Paints a background image of an element. Gradients are painted with their shape and color
+    /// stops for the tile (see `Gradient::shape` and `Gradient::color_stops`).
+    fn paint_background_image(
+        &mut self,
+        element: NodeId,
+        image: &BackgroundImage,
+        tiling: &Tiling,
+        clip: &RoundedRect,
+        transform: &Matrix,
+    );
+    /// Paints the content of an element
+    fn paint_element(&mut self, element: NodeId, border_box: &Rect, transform: &Matrix);
+    /// Paints a box shadow of an element (see `DisplayItem::BoxShadow`)
+    fn paint_box_shadow(
+        &mut self,
+        element: NodeId,
+        shadow: &BoxShadow,
+        shape: &RoundedRect,
+        clip: &RoundedRect,
+        transform: &Matrix,
+    );
+    /// Paints the border of an element. The sides meet diagonally at the corners.
+    fn paint_border(
+        &mut self,
+        element: NodeId,
+        outer: &RoundedRect,
+        inner: &RoundedRect,
+        sides: &[BorderSide; 4],
+        transform: &Matrix,
+    );
+    /// Paints the outline of an element
+    fn paint_outline(
+        &mut self,
+        element: NodeId,
+        outer: &RoundedRect,
+        inner: &RoundedRect,
+        side: &BorderSide,
+        transform: &Matrix,
+    );
+    /// Paints a scrollbar of an element: its track, with the thumb on top
+    fn paint_scrollbar(&mut self, element: NodeId, scrollbar: &Scrollbar, transform: &Matrix);
+}
+
+/// The paint operations of a document, in paint order
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DisplayList {
+    pub items: Vec<DisplayItem>,
+}
+
+impl DisplayList {
+    /// Replays the display list on the painter
+    pub fn replay(&self, painter: &mut dyn Painter) {
+        let mut layers = Vec::new();
+        for item in &self.items {
+            match item {
+                DisplayItem::PushLayer(layer) => {
+                    painter.push_layer(layer);
+                    layers.push(layer);
+                }
+                DisplayItem::PopLayer => {
+                    if let Some(layer) = layers.pop() {
+                        painter.pop_layer(layer);
+                    }
+                }
+                DisplayItem::PushClip {
+                    element,
+                    clip,
+                    transform,
+                } => painter.push_clip(*element, clip, transform),
+                DisplayItem::PopClip => painter.pop_clip(),
+                DisplayItem::BackgroundColor {
+                    element,
+                    color,
+                    clip,
+                    transform,
+                } => painter.paint_background_color(*element, color, clip, transform),
+                DisplayItem::BackgroundImage {
+                    element,
+                    image,
+                    tiling,
+                    clip,
+                    transform,
+                } => painter.paint_background_image(*element, image, tiling, clip, transform),
+                DisplayItem::Element {
+                    element,
+                    border_box,
+                    transform,
+                } => painter.paint_element(*element, border_box, transform),
+                DisplayItem::BoxShadow {
+                    element,
+                    shadow,
+                    shape,
+                    clip,
+                    transform,
+                } => painter.paint_box_shadow(*element, shadow, shape, clip, transform),
+                DisplayItem::Border {
+                    element,
+                    outer,
+                    inner,
+                    sides,
+                    transform,
+                } => painter.paint_border(*element, outer, inner, sides, transform),
+                DisplayItem::Outline {
+                    element,
+                    outer,
+                    inner,
+                    side,
+                    transform,
+                } => painter.paint_outline(*element, outer, inner, side, transform),
+                DisplayItem::Scrollbar {
+                    element,
+                    scrollbar,
+                    transform,
+                } => painter.paint_scrollbar(*element, scrollbar, transform),
+            }
+        }
+    }
+}
+
+/// The clips that are pushed while a display list is built
+#[derive(Default)]
+struct ClipStack {
+    /// Elements whose clips are pushed, from the outermost to the innermost
+    elements: Vec<NodeId>,
+    /// Number of clips that were pushed before the current layer, which cannot be popped in it
+    floor: usize,
+}
+
+impl ClipStack {
+    /// Pops the clips down to the given number
+    fn pop_to(&mut self, length: usize, list: &mut DisplayList) {
+        while self.elements.len() > length {
+            self.elements.pop();
+            list.items.push(DisplayItem::PopClip);
+        }
+    }
+}
+
+impl Document {
+    /// Returns the display list of the document, or None when there is no layout or nothing is
+    /// painted
+    pub fn display_list(&self) -> Option<DisplayList> {
+        self.geometry.0.as_ref()?;
+        let tree = self.stacking_context_tree()?;
+        let mut list = DisplayList::default();
+        let mut clips = ClipStack::default();
+        self.build_display_list(&tree, &mut list, &mut Vec::new(), &mut clips);
+        clips.pop_to(0, &mut list);
+        Some(list)
+    }
+
+    /// Pushes and pops clips until the clips of the elements that clip the node are pushed
+    fn update_clips(&self, node_id: NodeId, list: &mut DisplayList, clips: &mut ClipStack) {
+        let ancestors = self.clipping_ancestors(node_id);
+        let shared = clips
+            .elements
+            .iter()
+            .zip(&ancestors)
+            .take_while(|(pushed, ancestor)| pushed == ancestor)
+            .count();
+        clips.pop_to(shared.max(clips.floor), list);
+
+        for ancestor in ancestors {
+            if clips.elements.contains(&ancestor) {
+                continue;
+            }
+            let Some(clip) = self.overflow_clip(ancestor) else {
+                continue;
+            };
+            list.items.push(DisplayItem::PushClip {
+                element: ancestor,
+                clip,
+                transform: self.accumulated_transform(ancestor),
+            });
+            clips.elements.push(ancestor);
+        }
+    }
+
+    /// Returns the layer the stacking context is painted into, or None when it is painted
+    /// directly. Opacity 0 gives a layer with opacity 0, which is not painted at all.
+    fn layer(&self, context: &StackingContext) -> Option<Layer> {
+        if context.kind != StackingKind::Context {
+            return None;
+        }
+        let opacity = self.paint_style(context.element)?.opacity;
+        let declarations = self.declared_style(context.element);
+        let filters = cascaded_value(&declarations, "filter")
+            .and_then(|value| parse_filter(value).ok())
+            .unwrap_or_default();
+        let blend_mode = cascaded_value(&declarations, "mix-blend-mode")
+            .and_then(BlendMode::from_keyword)
+            .unwrap_or_default();
+        if opacity >= 1.0 && filters.is_empty() && blend_mode == BlendMode::Normal {
+            return None;
+        }
+
+        let spread: f32 = filters.iter().map(Filter::spread).sum();
+        let bounds = context
+            .paint_order()
+            .into_iter()
+            .filter_map(|node_id| self.ink_rect(node_id))
+            .reduce(|bounds, rect| bounds.union(&rect))
+            .unwrap_or_default()
+            .inflate([spread; 4]);
+        Some(Layer {
+            element: context.element,
+            opacity,
+            filters,
+            blend_mode,
+            bounds,
+        })
+    }
+
+    /// Returns the part of the viewport the element paints in: its border box with its outline
+    /// and outer shadows, transformed
+    fn ink_rect(&self, node_id: NodeId) -> Option<Rect> {
+        self.bounding_client_rect(node_id)?;
+        let border_box = self.geometry.0.as_ref()?.border_box(node_id)?;
+        let ink = self.box_decoration(node_id).ink_rect(border_box);
+        Some(self.accumulated_transform(node_id).map_rect(&ink))
+    }
+
+    /// Adds the items of the stacking context (or element painted as one) to the list. The
+    /// outlines are collected per stacking context, and painted at its end.
+    fn build_display_list(
+        &self,
+        context: &StackingContext,
+        list: &mut DisplayList,
+        outlines: &mut Vec<DisplayItem>,
+        clips: &mut ClipStack,
+    ) {
+        let layer = self.layer(context);
+        let layered = layer.is_some();
+        let floor = clips.floor;
+        if let Some(layer) = layer {
+            if layer.opacity <= 0.0 {
+                return;
+            }
+            self.update_clips(context.element, list, clips);
+            list.items.push(DisplayItem::PushLayer(layer));
+            clips.floor = clips.elements.len();
+        }
+
+        let mut own_outlines = Vec::new();
+        let outlines = if context.kind == StackingKind::Context {
+            &mut own_outlines
+        } else {
+            outlines
+        };
+
+        self.push_element(context.element, list, outlines, clips);
+        for child in &context.negative {
+            self.build_display_list(child, list, outlines, clips);
+        }
+        for &node_id in &context.blocks {
+            self.push_element(node_id, list, outlines, clips);
+        }
+        for float in &context.floats {
+            self.build_display_list(float, list, outlines, clips);
+        }
+        for &node_id in &context.inlines {
+            self.push_element(node_id, list, outlines, clips);
+        }
+        for child in context.zero.iter().chain(&context.positive) {
+            self.build_display_list(child, list, outlines, clips);
+        }
+
+        for outline in own_outlines {
+            if let DisplayItem::Outline { element, .. } = outline {
+                self.update_clips(element, list, clips);
+            }
+            list.items.push(outline);
+        }
+        if layered {
+            clips.pop_to(clips.floor, list);
+            list.items.push(DisplayItem::PopLayer);
+        }
+        clips.floor = floor;
+    }
+
+    fn push_element(
+        &self,
+        node_id: NodeId,
+        list: &mut DisplayList,
+        outlines: &mut Vec<DisplayItem>,
+        clips: &mut ClipStack,
+    ) {
+        let Some(border_box) = self
+            .geometry
+            .0
+            .as_ref()
+            .and_then(|geometry| geometry.border_box(node_id))
+        else {
+            return;
+        };
+        self.update_clips(node_id, list, clips);
+        let transform = self.accumulated_transform(node_id);
+        let decoration = self.box_decoration(node_id);
+        let rounded = decoration.rounded_border_box(border_box);
+        let widths = decoration.border_widths();
+        let shadow = |shadow: &BoxShadow| DisplayItem::BoxShadow {
+            element: node_id,
+            shadow: shadow.clone(),
+            shape: shadow.shape(&rounded, widths),
+            clip: if shadow.inset {
+                rounded.shrink(widths)
+            } else {
+                rounded
+            },
+            transform,
+        };
+
+        let shadows = decoration.shadows.iter().rev();
+        list.items
+            .extend(shadows.clone().filter(|shadow| !shadow.inset).map(shadow));
+        self.push_background(node_id, &rounded, widths, &transform, list);
+        list.items.push(DisplayItem::Element {
+            element: node_id,
+            border_box,
+            transform,
+        });
+        list.items
+            .extend(shadows.filter(|shadow| shadow.inset).map(shadow));
+        if decoration.has_border() {
+            list.items.push(DisplayItem::Border {
+                element: node_id,
+                outer: rounded,
+                inner: rounded.shrink(widths),
+                sides: decoration.border.clone(),
+                transform,
+            });
+        }
+        list.items
+            .extend(
+                self.scrollbars(node_id)
+                    .into_iter()
+                    .map(|scrollbar| DisplayItem::Scrollbar {
+                        element: node_id,
+                        scrollbar,
+                        transform,
+                    }),
+            );
+
+        let outline_width = decoration.outline_width();
+        if outline_width > 0.0 {
+            let outer = decoration.outline_rect(border_box);
+            outlines.push(DisplayItem::Outline {
+                element: node_id,
+                outer,
+                inner: outer.shrink([outline_width; 4]),
+                side: decoration.outline,
+                transform,
+            });
+        }
+    }
+
+    /// Adds the background color and the background images (from the bottom one to the top one)
+    /// of the element. Images that are not loaded, and images of size 0, are left out.
+    fn push_background(
+        &self,
+        node_id: NodeId,
+        border_box: &RoundedRect,
+        border_widths: [f32; 4],
+        transform: &Matrix,
+        list: &mut DisplayList,
+    ) {
+        let Some(geometry) = self.geometry.0.as_ref() else {
+            return;
+        };
+        let background = self.background(node_id);
+        if background.has_color() {
+            list.items.push(DisplayItem::BackgroundColor {
+                element: node_id,
+                color: background.color.clone(),
+                clip: *border_box,
+                transform: *transform,
+            });
+        }
+
+        let padding_box = border_box.shrink(border_widths).rect;
+        for layer in background.layers().into_iter().rev() {
+            let Some(image) = layer.image.clone() else {
+                continue;
+            };
+            let natural = match &image {
+                BackgroundImage::Url(url) => match geometry.image_size(url) {
+                    Some(size) => Some(size),
+                    None => continue,
+                },
+                BackgroundImage::Gradient(_) => None,
+            };
+            let tiling = layer.tiling(&padding_box, natural);
+            if tiling.tile.width <= 0.0 || tiling.tile.height <= 0.0 {
+                continue;
+            }
+            list.items.push(DisplayItem::BackgroundImage {
+                element: node_id,
+                image,
+                tiling,
+                clip: *border_box,
+                transform: *transform,
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::html5::geometry::{LayoutGeometry, Positioning};
+    use crate::html5::node::NodeData;
+    use crate::html5::parser::document::DocumentHandle;
+    use crate::testing::parse;
+    use std::collections::HashMap;
+    use std::rc::Rc;
+
+    #[test]
+    fn parse_filters() {
+        assert_eq!(parse_filter("none").unwrap(), []);
+        assert_eq!(
+            parse_filter("blur(2px) GRAYSCALE(50%) grayscale() blur()").unwrap(),
+            [
+                Filter::Blur(2.0),
+                Filter::Grayscale(0.5),
+                Filter::Grayscale(1.0),
+                Filter::Blur(0.0),
+            ]
+        );
+        assert_eq!(
+            parse_filter("grayscale(3)").unwrap(),
+            [Filter::Grayscale(1.0)]
+        );
+        assert!(parse_filter("blur(2)").is_err());
+        assert!(parse_filter("sepia(1)").is_err());
+        assert!(parse_filter("blur(1px").is_err());
+
+        assert_eq!(
+            BlendMode::from_keyword("Color-Dodge"),
+            Some(BlendMode::ColorDodge)
+        );
+        assert_eq!(BlendMode::from_keyword("plus-lighter"), None);
+    }
+
+    struct Layout(HashMap<NodeId, Rect>);
+
+    impl LayoutGeometry for Layout {
+        fn border_box(&self, node_id: NodeId) -> Option<Rect> {
+            self.0.get(&node_id).copied()
+        }
+
+        fn viewport(&self) -> Rect {
+            Rect::new(0.0, 0.0, 800.0, 600.0)
+        }
+
+        fn image_size(&self, url: &str) -> Option<(f32, f32)> {
+            url.ends_with("/loaded.png").then_some((10.0, 20.0))
+        }
+    }
+
+    /// Records the calls as text
+    #[derive(Default)]
+    struct Recorder(Vec<String>);
+
+    impl Painter for Recorder {
+        fn push_layer(&mut self, layer: &Layer) {
+            self.0.push(format!("push {}", layer.element));
+        }
+
+        fn pop_layer(&mut self, layer: &Layer) {
+            self.0.push(format!("pop {}", layer.element));
+        }
+
+        fn push_clip(&mut self, element: NodeId, _clip: &RoundedRect, _transform: &Matrix) {
+            self.0.push(format!("clip {element}"));
+        }
+
+        fn pop_clip(&mut self) {
+            self.0.push("unclip".to_string());
+        }
+
+        fn paint_background_color(
+            &mut self,
+            element: NodeId,
+            color: &str,
+            _clip: &RoundedRect,
+            _transform: &Matrix,
+        ) {
+            self.0.push(format!("background {element} {color}"));
+        }
+
+        fn paint_background_image(
+            &mut self,
+            element: NodeId,
+            image: &BackgroundImage,
+            _tiling: &Tiling,
+            _clip: &RoundedRect,
+            _transform: &Matrix,
+        ) {
+            let kind = match image {
+                BackgroundImage::Url(url) => url.as_str(),
+                BackgroundImage::Gradient(_) => "gradient",
+            };
+            self.0.push(format!("image {element} {kind}"));
+        }
+
+        fn paint_element(&mut self, element: NodeId, _border_box: &Rect, _transform: &Matrix) {
+            self.0.push(format!("paint {element}"));
+        }
+
+        fn paint_box_shadow(
+            &mut self,
+            element: NodeId,
+            shadow: &BoxShadow,
+            _shape: &RoundedRect,
+            _clip: &RoundedRect,
+            _transform: &Matrix,
+        ) {
+            self.0.push(format!("shadow {element} {}", shadow.color));
+        }
+
+        fn paint_border(
+            &mut self,
+            element: NodeId,
+            _outer: &RoundedRect,
+            _inner: &RoundedRect,
+            _sides: &[BorderSide; 4],
+            _transform: &Matrix,
+        ) {
+            self.0.push(format!("border {element}"));
+        }
+
+        fn paint_outline(
+            &mut self,
+            element: NodeId,
+            _outer: &RoundedRect,
+            _inner: &RoundedRect,
+            _side: &BorderSide,
+            _transform: &Matrix,
+        ) {
+            self.0.push(format!("outline {element}"));
+        }
+
+        fn paint_scrollbar(
+            &mut self,
+            element: NodeId,
+            _scrollbar: &Scrollbar,
+            _transform: &Matrix,
+        ) {
+            self.0.push(format!("scrollbar {element}"));
+        }
+    }
+
+    fn setup(html: &str, boxes: &[(&str, Rect)]) -> DocumentHandle {
+        let mut document = parse(html);
+
+        let mut layout = HashMap::new();
+        for node in document.get().descendants(NodeId::root()) {
+            if let NodeData::Element(_) = node.data {
+                layout.insert(node.id, Rect::new(0.0, 0.0, 800.0, 600.0));
+            }
+        }
+        for (id, rect) in boxes {
+            let node_id = document.get().get_node_by_named_id(id).unwrap().id;
+            layout.insert(node_id, *rect);
+        }
+        document
+            .get_mut()
+            .set_layout_geometry(Rc::new(Layout(layout)));
+        document
+    }
+
+    #[test]
+    fn layers() {
+        let document = setup(
+            r#"<div id=a style="opacity: 0.5"><p id=b style="filter: blur(2px) sepia(1)"></p></div>
+               <div id=c style="filter: grayscale(1); mix-blend-mode: multiply"></div>
+               <div id=d style="opacity: 0"><p id=e></p></div>"#,
+            &[
+                ("a", Rect::new(0.0, 0.0, 100.0, 50.0)),
+                ("b", Rect::new(0.0, 40.0, 200.0, 20.0)),
+                ("c", Rect::new(10.0, 10.0, 10.0, 10.0)),
+            ],
+        );
+        let doc = document.get();
+        let named = |id: &str| doc.get_node_by_named_id(id).unwrap().id;
+        let (a, b, c) = (named("a"), named("b"), named("c"));
+        let list = doc.display_list().unwrap();
+        let layers: Vec<&Layer> = list
+            .items
+            .iter()
+            .filter_map(|item| match item {
+                DisplayItem::PushLayer(layer) => Some(layer),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(layers.len(), 2);
+        assert_eq!(layers[0].element, a);
+        assert_eq!(layers[0].opacity, 0.5);
+        assert_eq!(layers[0].filters, []);
+        assert_eq!(layers[0].bounds, Rect::new(0.0, 0.0, 200.0, 60.0));
+        assert_eq!(layers[1].element, c);
+        assert_eq!(layers[1].filters, [Filter::Grayscale(1.0)]);
+        assert_eq!(layers[1].blend_mode, BlendMode::Multiply);
+
+        // The unsupported filter is ignored, so b is painted directly. The invisible group of d
+        // is left out.
+        let mut recorder = Recorder::default();
+        list.replay(&mut recorder);
+        let html = doc.get_root().children[0];
+        let body = doc.elements_by_tag_name("body")[0];
+        assert_eq!(
+            recorder.0,
+            [
+                format!("paint {html}"),
+                format!("paint {body}"),
+                format!("push {a}"),
+                format!("paint {a}"),
+                format!("paint {b}"),
+                format!("pop {a}"),
+                format!("push {c}"),
+                format!("paint {c}"),
+                format!("pop {c}"),
+            ]
+        );
+    }
+
+    #[test]
+    fn decorations() {
+        let document = setup(
+            r#"<div id=a style="box-shadow: 1px 1px red, inset 0 0 2px blue, 2px 2px green;
+                                border: 1px solid; outline: 2px solid; border-radius: 4px;
+                                opacity: 0.5">
+                 <p id=b style="outline: 1px dotted"></p>
+                 <p id=c style="position: relative; z-index: 1; outline: 1px solid"></p>
+               </div>"#,
+            &[
+                ("a", Rect::new(10.0, 10.0, 100.0, 50.0)),
+                ("b", Rect::new(10.0, 20.0, 50.0, 10.0)),
+                ("c", Rect::new(10.0, 40.0, 50.0, 10.0)),
+            ],
+        );
+        let doc = document.get();
+        let named = |id: &str| doc.get_node_by_named_id(id).unwrap().id;
+        let (a, b, c) = (named("a"), named("b"), named("c"));
+
+        let list = doc.display_list().unwrap();
+        let mut recorder = Recorder::default();
+        list.replay(&mut recorder);
+        let start = recorder
+            .0
+            .iter()
+            .position(|call| *call == format!("push {a}"))
+            .unwrap();
+        assert_eq!(
+            recorder.0[start..],
+            [
+                format!("push {a}"),
+                format!("shadow {a} green"),
+                format!("shadow {a} red"),
+                format!("paint {a}"),
+                format!("shadow {a} blue"),
+                format!("border {a}"),
+                format!("paint {b}"),
+                format!("paint {c}"),
+                format!("outline {c}"),
+                format!("outline {a}"),
+                format!("outline {b}"),
+                format!("pop {a}"),
+            ]
+        );
+
+        // The layer covers the outline and shadows of a (and its children)
+        let DisplayItem::PushLayer(layer) = &list.items[start] else {
+            panic!("expected a layer");
+        };
+        assert_eq!(layer.bounds, Rect::new(8.0, 8.0, 104.0, 54.0));
+        let DisplayItem::Border { outer, inner, .. } = &list.items[start + 5] else {
+            panic!("expected a border");
+        };
+        assert_eq!(outer.radii, [(4.0, 4.0); 4]);
+        assert_eq!(inner.rect, Rect::new(11.0, 11.0, 98.0, 48.0));
+        assert_eq!(inner.radii, [(3.0, 3.0); 4]);
+    }
+
+    #[test]
+    fn backgrounds() {
+        let document = setup(
+            r#"<div id=a style="background: url(https://example.com/loaded.png) right no-repeat,
+                                            linear-gradient(red, blue),
+                                            url(https://example.com/missing.png) currentcolor;
+                                border: 5px solid; box-shadow: 1px 1px; color: navy"></div>"#,
+            &[("a", Rect::new(0.0, 0.0, 100.0, 50.0))],
+        );
+        let doc = document.get();
+        let a = doc.get_node_by_named_id("a").unwrap().id;
+
+        let list = doc.display_list().unwrap();
+        let mut recorder = Recorder::default();
+        list.replay(&mut recorder);
+        let start = recorder
+            .0
+            .iter()
+            .position(|call| *call == format!("shadow {a} navy"))
+            .unwrap();
+        assert_eq!(
+            recorder.0[start..],
+            [
+                format!("shadow {a} navy"),
+                format!("background {a} navy"),
+                format!("image {a} gradient"),
+                format!("image {a} https://example.com/loaded.png"),
+                format!("paint {a}"),
+                format!("border {a}"),
+            ]
+        );
+
+        // The images are positioned in the padding box (`right` centers vertically), and clipped
+        // to the border box
+        let images: Vec<(&Tiling, &RoundedRect)> = list
+            .items
+            .iter()
+            .filter_map(|item| match item {
+                DisplayItem::BackgroundImage { tiling, clip, .. } => Some((tiling, clip)),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(images[0].0.tile, Rect::new(5.0, 5.0, 90.0, 40.0));
+        assert_eq!(images[1].0.tile, Rect::new(85.0, 15.0, 10.0, 20.0));
+        assert_eq!(images[1].1.rect, Rect::new(0.0, 0.0, 100.0, 50.0));
+        assert_eq!(
+            doc.background_image_urls(),
+            [
+                (a, "https://example.com/loaded.png".to_string()),
+                (a, "https://example.com/missing.png".to_string()),
+            ]
+        );
+    }
+
+    /// Layout with a scroll container: its padding box leaves 10px for a vertical scrollbar,
+    /// and its contents are 100px high. The element with the absolute position is positioned.
+    struct Scrolling {
+        layout: Layout,
+        scroller: NodeId,
+        absolute: NodeId,
+    }
+
+    impl LayoutGeometry for Scrolling {
+        fn border_box(&self, node_id: NodeId) -> Option<Rect> {
+            self.layout.border_box(node_id)
+        }
+
+        fn viewport(&self) -> Rect {
+            self.layout.viewport()
+        }
+
+        fn padding_box(&self, node_id: NodeId) -> Option<Rect> {
+            let border_box = self.border_box(node_id)?;
+            if node_id != self.scroller {
+                return Some(border_box);
+            }
+            Some(border_box.inflate([-1.0, -11.0, -1.0, -1.0]))
+        }
+
+        fn scroll_size(&self, node_id: NodeId) -> Option<(f32, f32)> {
+            (node_id == self.scroller).then_some((88.0, 100.0))
+        }
+
+        fn positioning(&self, node_id: NodeId) -> Positioning {
+            if node_id == self.absolute {
+                Positioning::Absolute
+            } else {
+                Positioning::Static
+            }
+        }
+    }
+
+    #[test]
+    fn overflow() {
+        let mut document = setup(
+            r#"<div id=a style="overflow-y: scroll; border: 1px solid">
+                 <p id=b></p><p id=c style="position: absolute"></p>
+               </div>
+               <div id=d></div>"#,
+            &[
+                ("a", Rect::new(0.0, 0.0, 100.0, 50.0)),
+                ("b", Rect::new(0.0, 40.0, 100.0, 40.0)),
+                ("c", Rect::new(0.0, 100.0, 100.0, 40.0)),
+                ("d", Rect::new(0.0, 50.0, 100.0, 50.0)),
+            ],
+        );
+        let (a, b, c, d) = {
+            let doc = document.get();
+            let named = |id: &str| doc.get_node_by_named_id(id).unwrap().id;
+            (named("a"), named("b"), named("c"), named("d"))
+        };
+        let boxes = document
+            .get()
+            .descendants(NodeId::root())
+            .filter_map(|node| Some((node.id, document.get().bounding_client_rect(node.id)?)))
+            .collect();
+        document.get_mut().set_layout_geometry(Rc::new(Scrolling {
+            layout: Layout(boxes),
+            scroller: a,
+            absolute: c,
+        }));
+
+        // b is clipped by a, the absolutely positioned c escapes the clip
+        let list = document.get().display_list().unwrap();
+        let mut recorder = Recorder::default();
+        list.replay(&mut recorder);
+        let start = recorder
+            .0
+            .iter()
+            .position(|call| *call == format!("paint {a}"))
+            .unwrap();
+        assert_eq!(
+            recorder.0[start..],
+            [
+                format!("paint {a}"),
+                format!("border {a}"),
+                format!("scrollbar {a}"),
+                format!("clip {a}"),
+                format!("paint {b}"),
+                "unclip".to_string(),
+                format!("paint {d}"),
+                format!("paint {c}"),
+            ]
+        );
+        let clip = list.items.iter().find_map(|item| match item {
+            DisplayItem::PushClip { clip, .. } => Some(clip.rect),
+            _ => None,
+        });
+        assert_eq!(clip, Some(Rect::new(1.0, 1.0, 88.0, 48.0)));
+
+        // Scrolling moves b up, and the clip hides the part of it that is still below the
+        // padding box of a
+        assert!(document.scroll_to(a, 0.0, 30.0));
+        let doc = document.get();
+        let list = doc.display_list().unwrap();
+        let transform = |element: NodeId| {
+            list.items.iter().find_map(|item| match item {
+                DisplayItem::Element {
+                    element: painted,
+                    transform,
+                    ..
+                } if *painted == element => Some(transform.map_point(0.0, 0.0)),
+                _ => None,
+            })
+        };
+        assert_eq!(transform(b), Some((0.0, -30.0)));
+        assert_eq!(transform(c), Some((0.0, 0.0)));
+        assert_eq!(doc.element_from_point(50.0, 20.0), Some(b));
+        assert_eq!(doc.element_from_point(50.0, 49.5), Some(a));
+        assert_eq!(doc.element_from_point(50.0, 55.0), Some(d));
+        assert_eq!(doc.element_from_point(50.0, 120.0), Some(c));
+    }
+}
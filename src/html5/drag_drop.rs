@@ -0,0 +1,636 @@
+//! Drag and drop
+//!
+//! A drag operation carries a `DataTransfer` from the node being dragged (or from outside the
+//! document, like files from the file manager) to the node it is dropped on. The embedder drives
+//! the operation from its pointer input: it starts a `DragSession` when the pointer is pressed on
+//! a draggable node and moved, tells the session which node is under the pointer while it moves,
+//! and drops or cancels it when the pointer is released.
+//!
+//! The session fires the events of the drag and drop processing model: `dragstart` on the source,
+//! `drag` on the source and `dragenter` / `dragover` / `dragleave` on the targets while moving,
+//! and finally `drop` on the target and `dragend` on the source. A target accepts the drop by
+//! canceling `dragover`. The data can only be changed in `dragstart` and only be read in `drop`;
+//! during the other events only its types are available.
+//!
+//! See: https://html.spec.whatwg.org/multipage/dnd.html
+use crate::html5::events::{Event, EventDetail};
+use crate::html5::node::{NodeId, HTML_NAMESPACE};
+use crate::html5::parser::document::{Document, DocumentHandle};
+use std::cell::RefCell;
+use std::rc::Rc;
+
+/// Values for the effect allowed by the source
+const EFFECTS_ALLOWED: &[&str] = &[
+    "none",
+    "copy",
+    "copyLink",
+    "copyMove",
+    "link",
+    "linkMove",
+    "move",
+    "all",
+    "uninitialized",
+];
+
+/// Access to the data of a data transfer
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DataTransferMode {
+    /// The data can be read and changed (in dragstart)
+    ReadWrite,
+    /// The data can be read (in drop)
+    ReadOnly,
+    /// Only the types of the data can be read
+    Protected,
+}
+
+/// A file that is being transferred
+#[derive(Debug, Clone, PartialEq)]
+pub struct TransferFile {
+    pub name: String,
+    pub mime_type: String,
+    pub data: Vec<u8>,
+}
+
+/// An item of a data transfer
+#[derive(Debug, Clone, PartialEq)]
+pub enum DataTransferItem {
+    /// Text data of the given format (a mime type like "text/plain")
+    String { format: String, data: String },
+    /// A file
+    File(TransferFile),
+}
+
+/// Data being transferred by drag and drop (or by the clipboard)
+#[derive(Debug, Clone, PartialEq)]
+pub struct DataTransfer {
+    mode: DataTransferMode,
+    items: Vec<DataTransferItem>,
+    drop_effect: String,
+    effect_allowed: String,
+}
+
+impl Default for DataTransfer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl DataTransfer {
+    /// Creates a new, empty data transfer that can be changed
+    pub fn new() -> Self {
+        Self {
+            mode: DataTransferMode::ReadWrite,
+            items: Vec::new(),
+            drop_effect: "none".to_string(),
+            effect_allowed: "uninitialized".to_string(),
+        }
+    }
+
+    /// Returns the access to the data
+    pub fn mode(&self) -> DataTransferMode {
+        self.mode
+    }
+
+    /// Sets the access to the data
+    pub fn set_mode(&mut self, mode: DataTransferMode) {
+        self.mode = mode;
+    }
+
+    /// Returns the items. Only the kind and format of the items may be used in protected mode.
+    pub fn items(&self) -> &[DataTransferItem] {
+        &self.items
+    }
+
+    /// Returns the formats of the string items, followed by "Files" when there are files
+    pub fn types(&self) -> Vec<String> {
+        let mut types: Vec<String> = self
+            .items
+            .iter()
+            .filter_map(|item| match item {
+                DataTransferItem::String { format, .. } => Some(format.clone()),
+                DataTransferItem::File(_) => None,
+            })
+            .collect();
+        if self
+            .items
+            .iter()
+            .any(|item| matches!(item, DataTransferItem::File(_)))
+        {
+            types.push("Files".to_string());
+        }
+        types
+    }
+
+    /// Returns the data of the given format, or None when there is no such data or the data
+    /// cannot be read
+    pub fn get_data(&self, format: &str) -> Option<&str> {
+        if self.mode == DataTransferMode::Protected {
+            return None;
+        }
+
+        let format = normalize_format(format);
+        self.items.iter().find_map(|item| match item {
+            DataTransferItem::String { format: f, data } if *f == format => Some(data.as_str()),
+            _ => None,
+        })
+    }
+
+    /// Sets the data of the given format, replacing the existing data of that format. Returns
+    /// false when the data cannot be changed.
+    pub fn set_data(&mut self, format: &str, data: &str) -> bool {
+        if self.mode != DataTransferMode::ReadWrite {
+            return false;
+        }
+
+        let format = normalize_format(format);
+        self.remove_string(&format);
+        self.items.push(DataTransferItem::String {
+            format,
+            data: data.to_string(),
+        });
+        true
+    }
+
+    /// Removes the data of the given format, or all string data when no format is given. Returns
+    /// false when the data cannot be changed.
+    pub fn clear_data(&mut self, format: Option<&str>) -> bool {
+        if self.mode != DataTransferMode::ReadWrite {
+            return false;
+        }
+
+        match format {
+            Some(format) => self.remove_string(&normalize_format(format)),
+            None => self
+                .items
+                .retain(|item| matches!(item, DataTransferItem::File(_))),
+        }
+        true
+    }
+
+    /// Returns the files. These are empty unless the data can be read.
+    pub fn files(&self) -> Vec<&TransferFile> {
+        if self.mode == DataTransferMode::Protected {
+            return vec![];
+        }
+
+        self.items
+            .iter()
+            .filter_map(|item| match item {
+                DataTransferItem::File(file) => Some(file),
+                DataTransferItem::String { .. } => None,
+            })
+            .collect()
+    }
+
+    /// Adds a file. Returns false when the data cannot be changed.
+    pub fn add_file(&mut self, file: TransferFile) -> bool {
+        if self.mode != DataTransferMode::ReadWrite {
+            return false;
+        }
+
+        self.items.push(DataTransferItem::File(file));
+        true
+    }
+
+    /// Returns the operation the current target performs when the data is dropped ("none",
+    /// "copy", "link" or "move")
+    pub fn drop_effect(&self) -> &str {
+        &self.drop_effect
+    }
+
+    /// Sets the drop effect. Unknown effects are ignored.
+    pub fn set_drop_effect(&mut self, effect: &str) {
+        if matches!(effect, "none" | "copy" | "link" | "move") {
+            self.drop_effect = effect.to_string();
+        }
+    }
+
+    /// Returns the operations the source allows
+    pub fn effect_allowed(&self) -> &str {
+        &self.effect_allowed
+    }
+
+    /// Sets the operations the source allows. This can only be done while the data can be
+    /// changed; unknown values are ignored.
+    pub fn set_effect_allowed(&mut self, effect: &str) {
+        if self.mode == DataTransferMode::ReadWrite && EFFECTS_ALLOWED.contains(&effect) {
+            self.effect_allowed = effect.to_string();
+        }
+    }
+
+    /// Returns the drop effect a target gets by default, given the effects allowed
+    fn default_drop_effect(&self) -> &'static str {
+        match self.effect_allowed.as_str() {
+            "none" => "none",
+            "link" | "linkMove" => "link",
+            "move" => "move",
+            _ => "copy",
+        }
+    }
+
+    /// Returns true when the drop effect is allowed by the source
+    fn is_drop_effect_allowed(&self) -> bool {
+        match self.effect_allowed.as_str() {
+            "all" | "uninitialized" => self.drop_effect != "none",
+            "copyLink" => matches!(self.drop_effect.as_str(), "copy" | "link"),
+            "copyMove" => matches!(self.drop_effect.as_str(), "copy" | "move"),
+            "linkMove" => matches!(self.drop_effect.as_str(), "link" | "move"),
+            allowed => self.drop_effect != "none" && self.drop_effect == allowed,
+        }
+    }
+
+    fn remove_string(&mut self, format: &str) {
+        self.items.retain(
+            |item| !matches!(item, DataTransferItem::String { format: f, .. } if f == format),
+        );
+    }
+}
+
+/// Normalizes a format as given by scripts: lowercase, with "text" and "url" as aliases
+fn normalize_format(format: &str) -> String {
+    match format.to_ascii_lowercase().as_str() {
+        "text" => "text/plain".to_string(),
+        "url" => "text/uri-list".to_string(),
+        format => format.to_string(),
+    }
+}
+
+impl Document {
+    /// Returns true when the node can be dragged: its draggable attribute is "true", or it is an
+    /// image or a link without a draggable attribute of "false"
+    pub fn is_draggable(&self, node_id: NodeId) -> bool {
+        let Some(node) = self.get_node_by_id(node_id) else {
+            return false;
+        };
+        if !node.is_namespace(HTML_NAMESPACE) {
+            return false;
+        }
+
+        match node
+            .get_attribute("draggable")
+            .map(|d| d.to_ascii_lowercase())
+        {
+            Some(draggable) if draggable == "true" => true,
+            Some(draggable) if draggable == "false" => false,
+            _ => node.name == "img" || (node.name == "a" && node.get_attribute("href").is_some()),
+        }
+    }
+
+    /// Returns the node that is dragged when dragging starts on the given node: the node itself
+    /// or its nearest draggable ancestor
+    fn drag_source(&self, node_id: NodeId) -> Option<NodeId> {
+        let mut current = Some(node_id);
+        while let Some(node) = current.and_then(|node_id| self.get_node_by_id(node_id)) {
+            if self.is_draggable(node.id) {
+                return Some(node.id);
+            }
+            current = node.parent;
+        }
+        None
+    }
+}
+
+impl DocumentHandle {
+    /// Starts dragging from the given node. Fires `dragstart` on the draggable node, which can
+    /// fill the data transfer. Returns None when there is nothing to drag or the drag was
+    /// canceled.
+    pub fn start_drag(&mut self, node_id: NodeId) -> Option<DragSession> {
+        let source = self.get().drag_source(node_id)?;
+        if self.get().is_inert(source) {
+            return None;
+        }
+
+        // Links and images drag their address by default
+        let mut data_transfer = DataTransfer::new();
+        {
+            let doc = self.get();
+            let node = doc.get_node_by_id(source)?;
+            let url = match node.name.as_str() {
+                "a" => node.get_attribute("href"),
+                "img" => node.get_attribute("src"),
+                _ => None,
+            };
+            if let Some(url) = url {
+                data_transfer.set_data("text/uri-list", url);
+                data_transfer.set_data("text/plain", url);
+            }
+        }
+
+        let data_transfer = Rc::new(RefCell::new(data_transfer));
+        let mut event = Event::new("dragstart", true, true)
+            .with_detail(EventDetail::DataTransfer(data_transfer.clone()));
+        if !self.dispatch_event(source, &mut event) {
+            return None;
+        }
+        data_transfer
+            .borrow_mut()
+            .set_mode(DataTransferMode::Protected);
+
+        Some(DragSession {
+            source: Some(source),
+            data_transfer,
+            target: None,
+            accepted: false,
+        })
+    }
+}
+
+/// A drag and drop operation in progress
+pub struct DragSession {
+    /// Node being dragged, or None when dragging from outside the document
+    source: Option<NodeId>,
+    data_transfer: Rc<RefCell<DataTransfer>>,
+    /// Node currently under the pointer
+    target: Option<NodeId>,
+    /// True when the current target accepted the drop
+    accepted: bool,
+}
+
+impl DragSession {
+    /// Starts a drag from outside the document, carrying the given data (like files dragged
+    /// from the file manager)
+    pub fn external(mut data_transfer: DataTransfer) -> Self {
+        data_transfer.set_mode(DataTransferMode::Protected);
+        Self {
+            source: None,
+            data_transfer: Rc::new(RefCell::new(data_transfer)),
+            target: None,
+            accepted: false,
+        }
+    }
+
+    /// Returns the data being transferred
+    pub fn data_transfer(&self) -> &Rc<RefCell<DataTransfer>> {
+        &self.data_transfer
+    }
+
+    /// Returns the node currently under the pointer
+    pub fn target(&self) -> Option<NodeId> {
+        self.target
+    }
+
+    /// Moves the drag to the node under the pointer (None when the pointer is not over the
+    /// document). Returns true when the target accepts the drop. Returns false without firing
+    /// events on the target when the source canceled the `drag` event; the drag should then be
+    /// canceled.
+    pub fn drag_to(&mut self, document: &mut DocumentHandle, target: Option<NodeId>) -> bool {
+        if let Some(source) = self.source {
+            if !self.fire(document, "drag", source, true) {
+                self.accepted = false;
+                return false;
+            }
+        }
+
+        let target = target.filter(|target| !document.get().is_inert(*target));
+        if target != self.target {
+            if let Some(new_target) = target {
+                self.fire(document, "dragenter", new_target, true);
+            }
+            if let Some(old_target) = self.target {
+                self.fire(document, "dragleave", old_target, false);
+            }
+            self.target = target;
+        }
+
+        self.accepted = false;
+        let Some(target) = self.target else {
+            self.data_transfer.borrow_mut().set_drop_effect("none");
+            return false;
+        };
+
+        let default_effect = self.data_transfer.borrow().default_drop_effect();
+        self.data_transfer
+            .borrow_mut()
+            .set_drop_effect(default_effect);
+        let accepted = !self.fire(document, "dragover", target, true);
+
+        let mut data_transfer = self.data_transfer.borrow_mut();
+        self.accepted = accepted && data_transfer.is_drop_effect_allowed();
+        if !self.accepted {
+            data_transfer.set_drop_effect("none");
+        }
+        self.accepted
+    }
+
+    /// Drops the data on the current target. Fires `drop` on the target when it accepted the
+    /// drop (and `dragleave` otherwise), followed by `dragend` on the source. Returns true when
+    /// the data was dropped.
+    pub fn drop(mut self, document: &mut DocumentHandle) -> bool {
+        let dropped = match self.target {
+            Some(target) if self.accepted => {
+                self.data_transfer
+                    .borrow_mut()
+                    .set_mode(DataTransferMode::ReadOnly);
+                self.fire(document, "drop", target, true);
+                true
+            }
+            _ => false,
+        };
+
+        self.end(document, dropped);
+        dropped
+    }
+
+    /// Cancels the drag (like pressing escape would). Fires `dragleave` on the current target and
+    /// `dragend` on the source.
+    pub fn cancel(mut self, document: &mut DocumentHandle) {
+        self.end(document, false);
+    }
+
+    fn end(&mut self, document: &mut DocumentHandle, dropped: bool) {
+        if !dropped {
+            if let Some(target) = self.target {
+                self.fire(document, "dragleave", target, false);
+            }
+            self.data_transfer.borrow_mut().set_drop_effect("none");
+        }
+
+        self.data_transfer
+            .borrow_mut()
+            .set_mode(DataTransferMode::Protected);
+        if let Some(source) = self.source {
+            self.fire(document, "dragend", source, false);
+        }
+    }
+
+    /// Fires a drag event carrying the data transfer. Returns false when the event was canceled.
+    fn fire(
+        &self,
+        document: &mut DocumentHandle,
+        event_type: &str,
+        node_id: NodeId,
+        cancelable: bool,
+    ) -> bool {
+        let mut event = Event::new(event_type, true, cancelable)
+            .with_detail(EventDetail::DataTransfer(self.data_transfer.clone()));
+        document.dispatch_event(node_id, &mut event)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::html5::events::EventCallback;
+    use crate::testing::parse;
+
+    type Log = Rc<RefCell<Vec<String>>>;
+
+    fn named(document: &DocumentHandle, id: &str) -> NodeId {
+        document.get().get_node_by_named_id(id).unwrap().id
+    }
+
+    /// Logs the drag events on the node as "type@id", with the data when it can be read
+    fn log_drag_events(document: &mut DocumentHandle, node_id: NodeId, id: &str, log: &Log) {
+        let types = [
+            "dragstart",
+            "drag",
+            "dragenter",
+            "dragover",
+            "dragleave",
+            "drop",
+            "dragend",
+        ];
+        for event_type in types {
+            let log = log.clone();
+            let id = id.to_string();
+            let callback: EventCallback = Rc::new(move |_, event| {
+                if event.current_target != event.target {
+                    return;
+                }
+                let data_transfer = event.data_transfer().unwrap().borrow();
+                let mut entry = format!("{}@{}", event.event_type, id);
+                if let Some(data) = data_transfer.get_data("text") {
+                    entry.push_str(&format!(":{}", data));
+                }
+                log.borrow_mut().push(entry);
+            });
+            document.get_mut().add_event_listener(
+                node_id,
+                event_type,
+                callback,
+                Default::default(),
+            );
+        }
+    }
+
+    #[test]
+    fn drag_and_drop() {
+        let mut document = parse(
+            "<a id=link href=/page><b id=inner>link</b></a><div id=zone></div><p id=other>x</p>",
+        );
+        let log = Log::default();
+        let (link, zone, other) = (
+            named(&document, "link"),
+            named(&document, "zone"),
+            named(&document, "other"),
+        );
+        log_drag_events(&mut document, link, "link", &log);
+        log_drag_events(&mut document, zone, "zone", &log);
+        log_drag_events(&mut document, other, "other", &log);
+
+        // The drop zone accepts the drop by canceling dragover
+        let accept: EventCallback = Rc::new(|_, event| {
+            event
+                .data_transfer()
+                .unwrap()
+                .borrow_mut()
+                .set_drop_effect("move");
+            event.prevent_default();
+        });
+        document
+            .get_mut()
+            .add_event_listener(zone, "dragover", accept, Default::default());
+
+        assert!(!document.get().is_draggable(other));
+        assert!(document.start_drag(other).is_none());
+
+        // Dragging starts on the nearest draggable ancestor
+        let inner = named(&document, "inner");
+        let mut session = document.start_drag(inner).unwrap();
+        assert_eq!(
+            session.data_transfer().borrow().types(),
+            vec!["text/uri-list", "text/plain"]
+        );
+        assert!(!session.drag_to(&mut document, Some(other)));
+        assert!(session.drag_to(&mut document, Some(zone)));
+        assert_eq!(session.data_transfer().borrow().drop_effect(), "move");
+        assert!(session.drop(&mut document));
+
+        assert_eq!(
+            *log.borrow(),
+            vec![
+                "dragstart@link:/page",
+                "drag@link",
+                "dragenter@other",
+                "dragover@other",
+                "drag@link",
+                "dragenter@zone",
+                "dragleave@other",
+                "dragover@zone",
+                "drop@zone:/page",
+                "dragend@link",
+            ]
+        );
+    }
+
+    #[test]
+    fn external_files() {
+        let mut document = parse("<div id=zone></div><p id=other>x</p>");
+        let zone = named(&document, "zone");
+
+        let files = Log::default();
+        let recorder = files.clone();
+        let accept: EventCallback = Rc::new(|_, event| event.prevent_default());
+        let on_drop: EventCallback = Rc::new(move |_, event| {
+            let data_transfer = event.data_transfer().unwrap().borrow();
+            for file in data_transfer.files() {
+                recorder.borrow_mut().push(file.name.clone());
+            }
+        });
+        document
+            .get_mut()
+            .add_event_listener(zone, "dragover", accept, Default::default());
+        document
+            .get_mut()
+            .add_event_listener(zone, "drop", on_drop, Default::default());
+
+        let mut data_transfer = DataTransfer::new();
+        data_transfer.add_file(TransferFile {
+            name: "notes.txt".to_string(),
+            mime_type: "text/plain".to_string(),
+            data: b"hello".to_vec(),
+        });
+
+        // Dropping outside of an accepting target does not drop
+        let mut session = DragSession::external(data_transfer.clone());
+        let other = named(&document, "other");
+        session.drag_to(&mut document, Some(other));
+        assert_eq!(session.data_transfer().borrow().types(), vec!["Files"]);
+        assert!(session.data_transfer().borrow().files().is_empty());
+        assert!(!session.drop(&mut document));
+        assert!(files.borrow().is_empty());
+
+        let mut session = DragSession::external(data_transfer);
+        assert!(session.drag_to(&mut document, Some(zone)));
+        assert!(session.drop(&mut document));
+        assert_eq!(*files.borrow(), vec!["notes.txt"]);
+    }
+
+    #[test]
+    fn data_transfer_modes() {
+        let mut data_transfer = DataTransfer::new();
+        assert!(data_transfer.set_data("Text", "hello"));
+        assert!(data_transfer.set_data("text/html", "<b>hello</b>"));
+        assert!(data_transfer.set_data("text/plain", "bye"));
+        assert_eq!(data_transfer.types(), vec!["text/html", "text/plain"]);
+        assert_eq!(data_transfer.get_data("text"), Some("bye"));
+
+        data_transfer.set_mode(DataTransferMode::Protected);
+        assert_eq!(data_transfer.get_data("text/html"), None);
+        assert!(!data_transfer.clear_data(None));
+
+        data_transfer.set_mode(DataTransferMode::ReadOnly);
+        assert_eq!(data_transfer.get_data("text/html"), Some("<b>hello</b>"));
+        assert!(!data_transfer.set_data("text/plain", "changed"));
+    }
+}
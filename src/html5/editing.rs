@@ -0,0 +1,751 @@
+//! Editing commands
+//!
+//! Commands that edit the document the way a user does in an editable region: typing, deleting
+//! and wrapping a selection in an element. This is the foundation for `contenteditable` support.
+//!
+//! Every command computes its changes from the current document, queues them on a
+//! `DocumentTaskQueue` and flushes the queue before returning, so each command sees the result
+//! of the previous one. Each command is a single transaction in the undo history.
+use crate::html5::node::{Node, NodeData, NodeId, HTML_NAMESPACE};
+use crate::html5::parser::document::{
+    Document, DocumentBuilder, DocumentHandle, DocumentTaskQueue,
+};
+use crate::html5::parser::tree_builder::TreeBuilder;
+use crate::types::{Error, Result};
+use std::collections::HashMap;
+
+/// A position in the document. In a text node the offset counts characters, in any other node it
+/// is the index of the child the position is in front of.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Boundary {
+    pub node_id: NodeId,
+    pub offset: usize,
+}
+
+impl Boundary {
+    pub fn new(node_id: NodeId, offset: usize) -> Self {
+        Self { node_id, offset }
+    }
+}
+
+/// A range of the document between two boundaries, like a selection made by the user
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Range {
+    pub start: Boundary,
+    pub end: Boundary,
+}
+
+impl Range {
+    pub fn new(start: Boundary, end: Boundary) -> Self {
+        Self { start, end }
+    }
+
+    /// Returns an empty range at the given position (like a caret)
+    pub fn collapsed(boundary: Boundary) -> Self {
+        Self {
+            start: boundary,
+            end: boundary,
+        }
+    }
+
+    pub fn is_collapsed(&self) -> bool {
+        self.start == self.end
+    }
+}
+
+/// Performs editing commands on a document
+pub struct Editor {
+    document: DocumentHandle,
+}
+
+impl Editor {
+    pub fn new(document: &DocumentHandle) -> Self {
+        Self {
+            document: Document::clone(document),
+        }
+    }
+
+    /// Splits a text node at the given character offset. The original node keeps the text in
+    /// front of the offset, and the rest is moved into a new text node right after it. Returns the
+    /// ID of the new text node.
+    pub fn split_text_node(&mut self, node_id: NodeId, offset: usize) -> Result<NodeId> {
+        let (text, parent_id, index) = {
+            let doc = self.document.get();
+            let text = text_value(&doc, node_id)?;
+            if offset > text.chars().count() {
+                return Err(Error::DocumentTask(format!(
+                    "Offset {} is outside of text node {}",
+                    offset, node_id
+                )));
+            }
+            let Some((parent_id, index)) = child_index(&doc, node_id) else {
+                return Err(Error::DocumentTask(format!(
+                    "Text node {} has no parent",
+                    node_id
+                )));
+            };
+            (text, parent_id, index)
+        };
+
+        let (head, tail) = split_at_char(&text, offset);
+        let mut queue = DocumentTaskQueue::new(&self.document);
+        queue.set_text(node_id, head);
+        let new_id = queue.insert_text_node(tail, parent_id, Some(index + 1));
+        flush(&mut queue)?;
+
+        Ok(new_id)
+    }
+
+    /// Removes the contents of the range. Nodes that are completely inside the range are removed
+    /// and text nodes at the boundaries are truncated. Elements that are only partially selected
+    /// are kept.
+    pub fn delete_range(&mut self, range: &Range) -> Result<()> {
+        let mut queue = DocumentTaskQueue::new(&self.document);
+        {
+            let doc = self.document.get();
+            let order = TreeOrder::new(&doc);
+            order.validate(&doc, range)?;
+            if range.is_collapsed() {
+                return Ok(());
+            }
+
+            let start_text = text_value(&doc, range.start.node_id).ok();
+            let end_text = text_value(&doc, range.end.node_id).ok();
+
+            match (start_text, end_text) {
+                (Some(text), _) if range.start.node_id == range.end.node_id => {
+                    let (head, _) = split_at_char(&text, range.start.offset);
+                    let (_, tail) = split_at_char(&text, range.end.offset);
+                    queue.set_text(range.start.node_id, &format!("{}{}", head, tail));
+                }
+                (start_text, end_text) => {
+                    if let Some(text) = start_text {
+                        queue.set_text(
+                            range.start.node_id,
+                            split_at_char(&text, range.start.offset).0,
+                        );
+                    }
+                    for node_id in order.contained(&doc, range) {
+                        queue.remove_node(node_id);
+                    }
+                    if let Some(text) = end_text {
+                        queue.set_text(range.end.node_id, split_at_char(&text, range.end.offset).1);
+                    }
+                }
+            }
+        }
+
+        flush(&mut queue)
+    }
+
+    /// Removes the contents of the range and returns a copy of them in a new document, below its
+    /// root (like `Range.extractContents()`). See `Document::clone_range()` for the copy and
+    /// `delete_range()` for what is removed.
+    pub fn extract_range(&mut self, range: &Range) -> Result<DocumentHandle> {
+        let contents = self.document.get().clone_range(range)?;
+        self.delete_range(range)?;
+        Ok(contents)
+    }
+
+    /// Replaces the contents of the range with the given text (like typing over a selection).
+    /// Returns the position right after the inserted text, where the caret goes.
+    pub fn insert_text(&mut self, range: &Range, text: &str) -> Result<Boundary> {
+        self.transaction(|editor| editor.replace_range(range, text))
+    }
+
+    fn replace_range(&mut self, range: &Range, text: &str) -> Result<Boundary> {
+        self.delete_range(range)?;
+
+        let start = range.start;
+        let mut queue = DocumentTaskQueue::new(&self.document);
+        let caret = match text_value(&self.document.get(), start.node_id) {
+            Ok(value) => {
+                let (head, tail) = split_at_char(&value, start.offset);
+                queue.set_text(start.node_id, &format!("{}{}{}", head, text, tail));
+                Boundary::new(start.node_id, start.offset + text.chars().count())
+            }
+            Err(_) if text.is_empty() => start,
+            Err(_) => {
+                let node_id = queue.insert_text_node(text, start.node_id, Some(start.offset));
+                Boundary::new(node_id, text.chars().count())
+            }
+        };
+        flush(&mut queue)?;
+
+        Ok(caret)
+    }
+
+    /// Wraps the contents of the range in a new element with the given tag name (like making a
+    /// selection bold). Text nodes at the boundaries are split when needed. Fails when the range
+    /// only partially selects an element, since that element cannot be wrapped as a whole. Returns
+    /// the ID of the new element.
+    pub fn wrap_selection(&mut self, range: &Range, tag: &str) -> Result<NodeId> {
+        self.transaction(|editor| editor.wrap(range, tag))
+    }
+
+    fn wrap(&mut self, range: &Range, tag: &str) -> Result<NodeId> {
+        {
+            let doc = self.document.get();
+            TreeOrder::new(&doc).validate(&doc, range)?;
+            if container(&doc, range.start) != container(&doc, range.end) {
+                return Err(Error::DocumentTask(
+                    "Cannot wrap a range that partially selects an element".to_string(),
+                ));
+            }
+        }
+
+        // Split the end first, so the start boundary stays valid when both are in the same node
+        let (parent_id, mut end_index, _) = self.child_boundary(range.end)?;
+        let (_, start_index, split) = self.child_boundary(range.start)?;
+        if split {
+            // The new text node from the start is inserted before the end
+            end_index += 1;
+        }
+
+        let children = self
+            .document
+            .get()
+            .get_node_by_id(parent_id)
+            .map(|node| node.children[start_index..end_index].to_vec())
+            .unwrap_or_default();
+
+        let mut queue = DocumentTaskQueue::new(&self.document);
+        let wrapper_id = queue.create_element(tag, parent_id, Some(start_index), HTML_NAMESPACE)?;
+        for child_id in children {
+            queue.move_node(child_id, wrapper_id, None);
+        }
+        flush(&mut queue)?;
+
+        Ok(wrapper_id)
+    }
+
+    /// Runs a command that flushes several times as a single transaction, so it is undone at once
+    fn transaction<T>(&mut self, command: impl FnOnce(&mut Self) -> Result<T>) -> Result<T> {
+        self.document.get_mut().begin_transaction();
+        let result = command(self);
+        self.document.get_mut().end_transaction();
+        result
+    }
+
+    /// Converts a boundary into a position between children, splitting the text node it is in
+    /// when needed. Returns the parent, the index of the child the position is in front of, and
+    /// whether a text node has been split.
+    fn child_boundary(&mut self, boundary: Boundary) -> Result<(NodeId, usize, bool)> {
+        let (length, parent_id, index) = {
+            let doc = self.document.get();
+            let Ok(text) = text_value(&doc, boundary.node_id) else {
+                return Ok((boundary.node_id, boundary.offset, false));
+            };
+            let Some((parent_id, index)) = child_index(&doc, boundary.node_id) else {
+                return Err(Error::DocumentTask(format!(
+                    "Text node {} has no parent",
+                    boundary.node_id
+                )));
+            };
+            (text.chars().count(), parent_id, index)
+        };
+
+        match boundary.offset {
+            0 => Ok((parent_id, index, false)),
+            offset if offset == length => Ok((parent_id, index + 1, false)),
+            offset => {
+                self.split_text_node(boundary.node_id, offset)?;
+                Ok((parent_id, index + 1, true))
+            }
+        }
+    }
+}
+
+impl Document {
+    /// Returns the selection of the document
+    pub fn selection(&self) -> Option<Range> {
+        self.selection
+    }
+
+    /// Sets (or clears) the selection. Fails when the range is not a valid range in the document.
+    pub fn set_selection(&mut self, range: Option<Range>) -> Result<()> {
+        if let Some(range) = &range {
+            TreeOrder::new(self).validate(self, range)?;
+        }
+        self.selection = range;
+        Ok(())
+    }
+
+    /// Returns true when the node is inside an editable region: the nearest ancestor (or the node
+    /// itself) with a contenteditable attribute does not set it to "false"
+    pub fn is_editable(&self, node_id: NodeId) -> bool {
+        let mut current = Some(node_id);
+        while let Some(node) = current.and_then(|node_id| self.get_node_by_id(node_id)) {
+            if let Some(editable) = node.get_attribute("contenteditable") {
+                return !editable.eq_ignore_ascii_case("false");
+            }
+            current = node.parent;
+        }
+        false
+    }
+
+    /// Returns a copy of the contents of the range below the root of a new document that holds a
+    /// fragment (like `Range.cloneContents()`). Elements that are partially inside the range are copied without
+    /// their children outside of it, and text nodes at the boundaries are truncated.
+    pub fn clone_range(&self, range: &Range) -> Result<DocumentHandle> {
+        let order = TreeOrder::new(self);
+        order.validate(self, range)?;
+
+        let mut clone = DocumentBuilder::new_fragment();
+        if range.is_collapsed() {
+            return Ok(clone);
+        }
+
+        let mut target = clone.get_mut();
+        let ancestor = self.common_ancestor(range.start.node_id, range.end.node_id);
+        match self.get_node_by_id(ancestor).map(|node| &node.data) {
+            Some(NodeData::Text(text)) => {
+                let (_, tail) = split_at_char(text.value(), range.start.offset);
+                let (selected, _) = split_at_char(tail, range.end.offset - range.start.offset);
+                target.add_node(Node::new_text(selected), NodeId::root(), None)?;
+            }
+            _ => order.clone_children(self, range, ancestor, &mut target, NodeId::root())?,
+        }
+        drop(target);
+
+        Ok(clone)
+    }
+
+    /// Returns the deepest node that is an ancestor of (or equal to) both nodes
+    fn common_ancestor(&self, first: NodeId, second: NodeId) -> NodeId {
+        let mut ancestors = Vec::new();
+        let mut current = Some(first);
+        while let Some(node) = current.and_then(|node_id| self.get_node_by_id(node_id)) {
+            ancestors.push(node.id);
+            current = node.parent;
+        }
+
+        let mut current = Some(second);
+        while let Some(node) = current.and_then(|node_id| self.get_node_by_id(node_id)) {
+            if ancestors.contains(&node.id) {
+                return node.id;
+            }
+            current = node.parent;
+        }
+        NodeId::root()
+    }
+}
+
+impl DocumentHandle {
+    /// Removes the contents of the range (like `Range.deleteContents()`). When the range is the
+    /// selection, or the selection does not fit the document anymore, the selection collapses to
+    /// the start of the range.
+    pub fn delete_contents(&mut self, range: &Range) -> Result<()> {
+        Editor::new(self).delete_range(range)?;
+        self.collapse_selection(range);
+        Ok(())
+    }
+
+    /// Removes the contents of the range and returns them in a new document (like
+    /// `Range.extractContents()`). The selection is updated like in `delete_contents()`.
+    pub fn extract_contents(&mut self, range: &Range) -> Result<DocumentHandle> {
+        let contents = Editor::new(self).extract_range(range)?;
+        self.collapse_selection(range);
+        Ok(contents)
+    }
+
+    /// Collapses the selection to the start of the removed range when needed
+    fn collapse_selection(&mut self, range: &Range) {
+        let mut doc = self.get_mut();
+        let Some(selection) = doc.selection() else {
+            return;
+        };
+        if selection == *range || doc.set_selection(Some(selection)).is_err() {
+            doc.selection = Some(Range::collapsed(range.start));
+        }
+    }
+}
+
+/// Returns the node whose children the boundary is between: the parent for text nodes, the node
+/// itself otherwise
+fn container(document: &Document, boundary: Boundary) -> Option<NodeId> {
+    match document.get_node_by_id(boundary.node_id) {
+        Some(node) if matches!(node.data, NodeData::Text(_)) => node.parent,
+        Some(_) => Some(boundary.node_id),
+        None => None,
+    }
+}
+
+/// Flushes the queue as a single transaction, so a failing command leaves the document as it was
+fn flush(queue: &mut DocumentTaskQueue) -> Result<()> {
+    queue.flush_transactional()
+}
+
+/// Returns the contents of the text node
+fn text_value(document: &Document, node_id: NodeId) -> Result<String> {
+    match document.get_node_by_id(node_id).map(|node| &node.data) {
+        Some(NodeData::Text(text)) => Ok(text.value().to_string()),
+        Some(_) => Err(Error::DocumentTask(format!(
+            "Node ID {} is not a text node",
+            node_id
+        ))),
+        None => Err(Error::DocumentTask(format!(
+            "Node ID {} not found",
+            node_id
+        ))),
+    }
+}
+
+/// Returns the parent of the node and the index of the node in its children
+fn child_index(document: &Document, node_id: NodeId) -> Option<(NodeId, usize)> {
+    let parent_id = document.get_node_by_id(node_id)?.parent?;
+    let index = document
+        .get_node_by_id(parent_id)?
+        .children
+        .iter()
+        .position(|&id| id == node_id)?;
+    Some((parent_id, index))
+}
+
+/// Splits the text at the given character offset
+fn split_at_char(text: &str, offset: usize) -> (&str, &str) {
+    let index = text
+        .char_indices()
+        .nth(offset)
+        .map_or(text.len(), |(index, _)| index);
+    text.split_at(index)
+}
+
+/// Position of every connected node in tree order, used to compare boundaries
+struct TreeOrder {
+    /// Nodes in tree order
+    nodes: Vec<NodeId>,
+    /// Index of every node in `nodes`, and the index right after its last descendant
+    positions: HashMap<NodeId, (usize, usize)>,
+}
+
+impl TreeOrder {
+    fn new(document: &Document) -> Self {
+        let mut nodes = Vec::new();
+        let mut positions: HashMap<NodeId, (usize, usize)> = HashMap::new();
+
+        // Nodes are pushed again (as exit markers) after their children to find the end of
+        // their subtree
+        let mut stack = vec![(NodeId::root(), false)];
+        while let Some((node_id, exit)) = stack.pop() {
+            if exit {
+                if let Some(position) = positions.get_mut(&node_id) {
+                    position.1 = nodes.len();
+                }
+                continue;
+            }
+            let Some(node) = document.get_node_by_id(node_id) else {
+                continue;
+            };
+
+            positions.insert(node_id, (nodes.len(), nodes.len()));
+            nodes.push(node_id);
+            stack.push((node_id, true));
+            stack.extend(node.children.iter().rev().map(|&id| (id, false)));
+        }
+
+        Self { nodes, positions }
+    }
+
+    /// Returns an error when a boundary is not in the document, its offset is out of bounds, or
+    /// the end comes before the start
+    fn validate(&self, document: &Document, range: &Range) -> Result<()> {
+        for boundary in [range.start, range.end] {
+            let Some(node) = document.get_node_by_id(boundary.node_id) else {
+                return Err(Error::DocumentTask(format!(
+                    "Node ID {} not found",
+                    boundary.node_id
+                )));
+            };
+            if !self.positions.contains_key(&boundary.node_id) {
+                return Err(Error::DocumentTask(format!(
+                    "Node ID {} is not in the document",
+                    boundary.node_id
+                )));
+            }
+            let length = match &node.data {
+                NodeData::Text(text) => text.value().chars().count(),
+                _ => node.children.len(),
+            };
+            if boundary.offset > length {
+                return Err(Error::DocumentTask(format!(
+                    "Offset {} is outside of node {}",
+                    boundary.offset, boundary.node_id
+                )));
+            }
+        }
+
+        let in_order = if range.start.node_id == range.end.node_id {
+            range.start.offset <= range.end.offset
+        } else {
+            self.key(document, range.start) <= self.key(document, range.end)
+        };
+        if !in_order {
+            return Err(Error::DocumentTask(
+                "The end of the range is before its start".to_string(),
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Returns a key to compare boundaries in different nodes. The first part is the index in
+    /// `nodes` that the boundary is in front of; the second part is 1 for boundaries inside a
+    /// text node, which are after the start of that node.
+    fn key(&self, document: &Document, boundary: Boundary) -> (usize, u8) {
+        let (index, end) = self.positions[&boundary.node_id];
+        let Some(node) = document.get_node_by_id(boundary.node_id) else {
+            return (index, 0);
+        };
+        if let NodeData::Text(_) = node.data {
+            return (index, 1);
+        }
+        match node.children.get(boundary.offset) {
+            Some(child_id) => (self.positions[child_id].0, 0),
+            None => (end, 0),
+        }
+    }
+
+    /// Copies the children of the node that are (partially) inside the range to the parent in
+    /// the target document
+    fn clone_children(
+        &self,
+        document: &Document,
+        range: &Range,
+        node_id: NodeId,
+        target: &mut Document,
+        target_parent: NodeId,
+    ) -> Result<()> {
+        let start = self.key(document, range.start);
+        let end = self.key(document, range.end);
+        let Some(node) = document.get_node_by_id(node_id) else {
+            return Ok(());
+        };
+
+        for child_id in &node.children {
+            let (index, subtree_end) = self.positions[child_id];
+            if start >= (subtree_end, 0) || (index, 0) >= end {
+                continue;
+            }
+            let Some(child) = document.get_node_by_id(*child_id) else {
+                continue;
+            };
+
+            if let NodeData::Text(text) = &child.data {
+                let mut value = text.value();
+                if *child_id == range.end.node_id {
+                    value = split_at_char(value, range.end.offset).0;
+                }
+                if *child_id == range.start.node_id {
+                    value = split_at_char(value, range.start.offset).1;
+                }
+                if !value.is_empty() {
+                    target.add_node(Node::new_text(value), target_parent, None)?;
+                }
+                continue;
+            }
+
+            let mut clone = child.clone_without_children();
+            clone.parent = None;
+            clone.is_registered = false;
+            let clone_id = target.add_node(clone, target_parent, None)?;
+            self.clone_children(document, range, *child_id, target, clone_id)?;
+        }
+        Ok(())
+    }
+
+    /// Returns the nodes that are completely inside the range, without their descendants
+    fn contained(&self, document: &Document, range: &Range) -> Vec<NodeId> {
+        let start = self.key(document, range.start);
+        let end = self.key(document, range.end);
+
+        let mut contained = Vec::new();
+        let mut index = start.0 + start.1 as usize;
+        while index < end.0 {
+            let node_id = self.nodes[index];
+            let subtree_end = self.positions[&node_id].1;
+            if (subtree_end, 0) <= end {
+                contained.push(node_id);
+                index = subtree_end;
+            } else {
+                index += 1;
+            }
+        }
+
+        contained
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::html5::serializer::{serialize, SerializerOptions};
+    use crate::testing::parse;
+
+    fn body(document: &DocumentHandle) -> String {
+        let doc = document.get();
+        let body = doc.elements_by_tag_name("body")[0];
+        serialize(&doc, body, &SerializerOptions::default())
+    }
+
+    /// Returns the n-th text node in tree order
+    fn text(document: &DocumentHandle, n: usize) -> NodeId {
+        let doc = document.get();
+        let order = TreeOrder::new(&doc);
+        order
+            .nodes
+            .into_iter()
+            .filter(|&id| text_value(&doc, id).is_ok())
+            .nth(n)
+            .unwrap()
+    }
+
+    #[test]
+    fn split_text_node() {
+        let document = parse("<p>héllo world</p>");
+        let mut editor = Editor::new(&document);
+
+        let hello = text(&document, 0);
+        let world = editor.split_text_node(hello, 6).unwrap();
+        assert_eq!(text_value(&document.get(), hello).unwrap(), "héllo ");
+        assert_eq!(text_value(&document.get(), world).unwrap(), "world");
+        assert_eq!(text(&document, 1), world);
+        assert_eq!(body(&document), "<p>héllo world</p>");
+
+        assert!(editor.split_text_node(world, 6).is_err());
+        let p = document.get().elements_by_tag_name("p")[0];
+        assert!(editor.split_text_node(p, 0).is_err());
+    }
+
+    #[test]
+    fn delete_range() {
+        let document = parse("<p>one <b>two</b> three</p><p>four</p>");
+        let mut editor = Editor::new(&document);
+
+        // Inside a single text node
+        let one = text(&document, 0);
+        editor
+            .delete_range(&Range::new(Boundary::new(one, 1), Boundary::new(one, 3)))
+            .unwrap();
+        assert_eq!(body(&document), "<p>o <b>two</b> three</p><p>four</p>");
+
+        // Across elements: <b> is removed, the paragraphs are only partially selected
+        let four = text(&document, 3);
+        editor
+            .delete_range(&Range::new(Boundary::new(one, 1), Boundary::new(four, 2)))
+            .unwrap();
+        assert_eq!(body(&document), "<p>o</p><p>ur</p>");
+
+        // The end cannot be before the start
+        assert!(editor
+            .delete_range(&Range::new(Boundary::new(four, 2), Boundary::new(one, 0)))
+            .is_err());
+    }
+
+    #[test]
+    fn extract_contents() {
+        let mut document = parse("<p>one <b>two</b> three</p><p>four</p>");
+        let one = text(&document, 0);
+        let four = text(&document, 3);
+        let range = Range::new(Boundary::new(one, 2), Boundary::new(four, 2));
+        document.get_mut().set_selection(Some(range)).unwrap();
+
+        let contents = document.extract_contents(&range).unwrap();
+        let contents = serialize(
+            &contents.get(),
+            NodeId::root(),
+            &SerializerOptions::default(),
+        );
+        assert_eq!(contents, "<p>e <b>two</b> three</p><p>fo</p>");
+        assert_eq!(body(&document), "<p>on</p><p>ur</p>");
+        assert_eq!(
+            document.get().selection(),
+            Some(Range::collapsed(Boundary::new(one, 2)))
+        );
+
+        // Nothing is removed when the range is invalid
+        let range = Range::new(Boundary::new(four, 2), Boundary::new(one, 0));
+        assert!(document.extract_contents(&range).is_err());
+        assert_eq!(body(&document), "<p>on</p><p>ur</p>");
+    }
+
+    #[test]
+    fn delete_contents_is_undone_at_once() {
+        let mut document = parse("<div><p>one</p><p>two</p><p>three</p></div>");
+        document.get_mut().enable_history();
+        let div = document.get().elements_by_tag_name("div")[0];
+        let two = text(&document, 1);
+
+        document
+            .delete_contents(&Range::new(Boundary::new(div, 0), Boundary::new(two, 1)))
+            .unwrap();
+        assert_eq!(body(&document), "<div><p>wo</p><p>three</p></div>");
+
+        document.get_mut().undo();
+        assert_eq!(
+            body(&document),
+            "<div><p>one</p><p>two</p><p>three</p></div>"
+        );
+    }
+
+    #[test]
+    fn insert_text() {
+        let document = parse("<p>hello world</p><p></p>");
+        let mut editor = Editor::new(&document);
+
+        let hello = text(&document, 0);
+        let caret = editor
+            .insert_text(
+                &Range::new(Boundary::new(hello, 6), Boundary::new(hello, 11)),
+                "there",
+            )
+            .unwrap();
+        assert_eq!(caret, Boundary::new(hello, 11));
+        let caret = editor.insert_text(&Range::collapsed(caret), "!").unwrap();
+        assert_eq!(caret, Boundary::new(hello, 12));
+        assert_eq!(body(&document), "<p>hello there!</p><p></p>");
+
+        // Typing in an empty element creates a text node
+        let empty = document.get().elements_by_tag_name("p")[1];
+        let caret = editor
+            .insert_text(&Range::collapsed(Boundary::new(empty, 0)), "new")
+            .unwrap();
+        assert_eq!(text_value(&document.get(), caret.node_id).unwrap(), "new");
+        assert_eq!(body(&document), "<p>hello there!</p><p>new</p>");
+    }
+
+    #[test]
+    fn wrap_selection() {
+        let document = parse("<p>make this bold</p>");
+        let mut editor = Editor::new(&document);
+
+        let make = text(&document, 0);
+        let b = editor
+            .wrap_selection(
+                &Range::new(Boundary::new(make, 5), Boundary::new(make, 9)),
+                "b",
+            )
+            .unwrap();
+        assert_eq!(document.get().get_node_by_id(b).unwrap().name, "b");
+        assert_eq!(body(&document), "<p>make <b>this</b> bold</p>");
+
+        // Wrapping everything in the paragraph, across the <b> element
+        let bold = text(&document, 2);
+        editor
+            .wrap_selection(
+                &Range::new(Boundary::new(make, 0), Boundary::new(bold, 5)),
+                "i",
+            )
+            .unwrap();
+        assert_eq!(body(&document), "<p><i>make <b>this</b> bold</i></p>");
+
+        // The <b> element is only partially selected
+        let this = text(&document, 1);
+        assert!(editor
+            .wrap_selection(
+                &Range::new(Boundary::new(make, 0), Boundary::new(this, 2)),
+                "u"
+            )
+            .is_err());
+    }
+}
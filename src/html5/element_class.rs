@@ -36,6 +36,13 @@ impl ElementClass {
         }
     }
 
+    /// Returns the names of all classes and whether they are active
+    pub(crate) fn iter(&self) -> impl Iterator<Item = (&str, bool)> {
+        self.class_map
+            .iter()
+            .map(|(name, &is_active)| (name.as_str(), is_active))
+    }
+
     /// Count the number of classes (active or inactive)
     /// assigned to an element
     pub fn len(&self) -> usize {
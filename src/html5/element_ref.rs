@@ -0,0 +1,281 @@
+//! Element references
+//!
+//! A read-only layer on top of a [`Document`] for data extraction. An [`ElementRef`] borrows the
+//! document, so elements can be navigated and queried without dealing with node ids:
+//!
+//! ```
+//! use gosub_engine::bytes::{CharIterator, Encoding};
+//! use gosub_engine::html5::parser::document::{Document, DocumentBuilder};
+//! use gosub_engine::html5::parser::Html5Parser;
+//! use gosub_engine::html5::selector::Selector;
+//!
+//! let mut chars = CharIterator::new();
+//! chars.read_from_str("<ul><li><a href='/a'>A</a><li><a href='/b'>B</a></ul>", Some(Encoding::UTF8));
+//! let document = DocumentBuilder::new_document();
+//! Html5Parser::parse_document(&mut chars, Document::clone(&document), None).unwrap();
+//!
+//! let doc = document.get();
+//! let links = Selector::parse("li > a").unwrap();
+//! let hrefs: Vec<_> = doc.select(&links).filter_map(|a| a.attr("href")).collect();
+//! assert_eq!(hrefs, vec!["/a", "/b"]);
+//! ```
+use crate::html5::node::{Node, NodeData, NodeId};
+use crate::html5::parser::document::Document;
+use crate::html5::selector::Selector;
+use crate::html5::serializer::{serialize, serialize_node, SerializerOptions};
+use std::collections::HashMap;
+
+/// Reference to an element in a document
+#[derive(Clone, Copy)]
+pub struct ElementRef<'a> {
+    document: &'a Document,
+    node: &'a Node,
+}
+
+impl PartialEq for ElementRef<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::eq(self.document, other.document) && self.node.id == other.node.id
+    }
+}
+
+impl std::fmt::Debug for ElementRef<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "ElementRef({} <{}>)", self.node.id, self.node.name)
+    }
+}
+
+impl<'a> ElementRef<'a> {
+    /// Returns a reference to the given node, or None when the node is not an element
+    pub fn wrap(document: &'a Document, node_id: NodeId) -> Option<Self> {
+        let node = document.get_node_by_id(node_id)?;
+        match node.data {
+            NodeData::Element(_) => Some(Self { document, node }),
+            _ => None,
+        }
+    }
+
+    /// Node id of the element
+    pub fn id(&self) -> NodeId {
+        self.node.id
+    }
+
+    /// The underlying node
+    pub fn node(&self) -> &'a Node {
+        self.node
+    }
+
+    /// Local name of the element
+    pub fn name(&self) -> &'a str {
+        &self.node.name
+    }
+
+    /// Returns the value of the given attribute
+    pub fn attr(&self, name: &str) -> Option<&'a str> {
+        self.attrs().get(name).map(String::as_str)
+    }
+
+    /// All attributes of the element
+    pub fn attrs(&self) -> &'a HashMap<String, String> {
+        match &self.node.data {
+            NodeData::Element(element) => &element.attributes,
+            _ => unreachable!("element reference to a non-element node"),
+        }
+    }
+
+    /// Returns the concatenated text of all descendant text nodes
+    pub fn text(&self) -> String {
+        self.document.text_content(self.node.id)
+    }
+
+    /// Serializes the children of the element
+    pub fn inner_html(&self) -> String {
+        serialize(self.document, self.node.id, &SerializerOptions::default())
+    }
+
+    /// Serializes the element including its children
+    pub fn html(&self) -> String {
+        serialize_node(self.document, self.node.id, &SerializerOptions::default())
+    }
+
+    /// Returns the parent element (None for the root element)
+    pub fn parent(&self) -> Option<ElementRef<'a>> {
+        Self::wrap(self.document, self.node.parent?)
+    }
+
+    /// Returns the child elements
+    pub fn children(&self) -> impl Iterator<Item = ElementRef<'a>> + 'a {
+        let document = self.document;
+        self.node
+            .children
+            .iter()
+            .filter_map(move |&child| Self::wrap(document, child))
+    }
+
+    /// Returns the previous sibling element
+    pub fn prev_sibling(&self) -> Option<ElementRef<'a>> {
+        let siblings = self.sibling_ids();
+        let position = siblings.iter().position(|&id| id == self.node.id)?;
+        siblings[..position]
+            .iter()
+            .rev()
+            .find_map(|&id| Self::wrap(self.document, id))
+    }
+
+    /// Returns the next sibling element
+    pub fn next_sibling(&self) -> Option<ElementRef<'a>> {
+        let siblings = self.sibling_ids();
+        let position = siblings.iter().position(|&id| id == self.node.id)?;
+        siblings[position + 1..]
+            .iter()
+            .find_map(|&id| Self::wrap(self.document, id))
+    }
+
+    /// Ids of all children of the parent (including this element)
+    fn sibling_ids(&self) -> &'a [NodeId] {
+        self.node
+            .parent
+            .and_then(|parent| self.document.get_node_by_id(parent))
+            .map_or(&[], |parent| parent.children.as_slice())
+    }
+
+    /// Returns true when the element matches the selector
+    pub fn matches(&self, selector: &Selector) -> bool {
+        selector.matches(self.document, self.node.id)
+    }
+
+    /// Returns all descendant elements matching the selector, in tree order
+    pub fn select<'s>(&self, selector: &'s Selector) -> Select<'a, 's> {
+        Select::new(self.document, self.node, selector)
+    }
+}
+
+/// Iterator over the elements matching a selector, in tree order
+pub struct Select<'a, 's> {
+    document: &'a Document,
+    selector: &'s Selector,
+    /// Nodes that still need to be visited, in reverse order
+    stack: Vec<NodeId>,
+}
+
+impl<'a, 's> Select<'a, 's> {
+    fn new(document: &'a Document, scope: &Node, selector: &'s Selector) -> Self {
+        Self {
+            document,
+            selector,
+            stack: scope.children.iter().rev().copied().collect(),
+        }
+    }
+}
+
+impl<'a> Iterator for Select<'a, '_> {
+    type Item = ElementRef<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while let Some(node_id) = self.stack.pop() {
+            let Some(node) = self.document.get_node_by_id(node_id) else {
+                continue;
+            };
+            self.stack.extend(node.children.iter().rev());
+
+            if self.selector.matches(self.document, node_id) {
+                return ElementRef::wrap(self.document, node_id);
+            }
+        }
+        None
+    }
+}
+
+impl Document {
+    /// Returns the root element of the document (normally the html element)
+    pub fn root_element(&self) -> Option<ElementRef<'_>> {
+        self.get_root()
+            .children
+            .iter()
+            .find_map(|&child| ElementRef::wrap(self, child))
+    }
+
+    /// Returns all elements in the document matching the selector, in tree order
+    pub fn select<'s>(&self, selector: &'s Selector) -> Select<'_, 's> {
+        Select::new(self, self.get_root(), selector)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::parse;
+
+    #[test]
+    fn navigate_and_extract() {
+        let document = parse(
+            "<!-- x --><table id=t><tr><th>Name<th>Price<tr><td>Apple<td class=p>1.20<tr><td>Pear<td class=p>0.80</table>",
+        );
+        let doc = document.get();
+
+        let root = doc.root_element().unwrap();
+        assert_eq!(root.name(), "html");
+        assert!(root.parent().is_none());
+
+        let rows = Selector::parse("#t tr").unwrap();
+        let rows: Vec<_> = doc.select(&rows).collect();
+        assert_eq!(rows.len(), 3);
+        assert_eq!(rows[0].parent().unwrap().name(), "tbody");
+        assert_eq!(
+            rows[1]
+                .children()
+                .map(|cell| cell.text())
+                .collect::<Vec<_>>(),
+            vec!["Apple", "1.20"]
+        );
+
+        let prices = Selector::parse("td.p").unwrap();
+        let prices: Vec<_> = rows[2].select(&prices).map(|td| td.text()).collect();
+        assert_eq!(prices, vec!["0.80"]);
+
+        let table = doc
+            .select(&Selector::parse("table").unwrap())
+            .next()
+            .unwrap();
+        assert_eq!(table.attr("id"), Some("t"));
+        assert_eq!(table.attr("class"), None);
+        assert!(table.matches(&Selector::parse("body > #t").unwrap()));
+        assert_eq!(
+            rows[1].html(),
+            "<tr><td>Apple</td><td class=\"p\">1.20</td></tr>"
+        );
+        assert_eq!(
+            rows[1].inner_html(),
+            "<td>Apple</td><td class=\"p\">1.20</td>"
+        );
+    }
+
+    #[test]
+    fn select_excludes_scope() {
+        let document = parse("<div id=a><div id=b><div id=c></div></div></div>");
+        let doc = document.get();
+
+        let divs = Selector::parse("div").unwrap();
+        let a = doc.select(&divs).next().unwrap();
+        assert_eq!(a.attr("id"), Some("a"));
+        assert_eq!(
+            a.select(&divs)
+                .filter_map(|e| e.attr("id"))
+                .collect::<Vec<_>>(),
+            vec!["b", "c"]
+        );
+        assert!(ElementRef::wrap(&doc, NodeId::root()).is_none());
+    }
+
+    #[test]
+    fn siblings_skip_non_elements() {
+        let document = parse("<p id=a></p>text<!-- c --><p id=b></p>");
+        let doc = document.get();
+
+        let b = doc.select(&Selector::parse("#b").unwrap()).next().unwrap();
+        let a = b.prev_sibling().unwrap();
+        assert_eq!(a.attr("id"), Some("a"));
+        assert_eq!(a.next_sibling(), Some(b));
+        assert!(a.prev_sibling().is_none());
+        assert!(b.next_sibling().is_none());
+    }
+}
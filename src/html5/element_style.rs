@@ -0,0 +1,613 @@
+//! Declarations that elements carry themselves
+//!
+//! Besides the style sheets, an element is styled by its own `style` attribute and by the
+//! presentational attributes of legacy HTML (`width`, `bgcolor`, `align`, `border`, ...). Browsers
+//! map the presentational attributes to CSS declarations, called presentational hints, so legacy
+//! pages render correctly without author CSS.
+//!
+//! In the cascade, the presentational hints are author declarations with zero specificity that
+//! come before all other author rules, and the declarations of the `style` attribute win over all
+//! author rules. `declared_style()` returns both in that order.
+//!
+//! See: https://html.spec.whatwg.org/multipage/rendering.html#presentational-hints
+use crate::html5::node::{NodeData, NodeId, HTML_NAMESPACE};
+use crate::html5::parser::document::Document;
+
+/// A CSS declaration (`property: value`)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StyleDeclaration {
+    pub property: String,
+    pub value: String,
+    pub important: bool,
+}
+
+impl StyleDeclaration {
+    pub fn new(property: &str, value: &str) -> Self {
+        Self {
+            property: property.to_string(),
+            value: value.to_string(),
+            important: false,
+        }
+    }
+}
+
+/// Named colors of CSS, which are kept as they are by the legacy color parser
+const NAMED_COLORS: [&str; 148] = [
+    "aliceblue",
+    "antiquewhite",
+    "aqua",
+    "aquamarine",
+    "azure",
+    "beige",
+    "bisque",
+    "black",
+    "blanchedalmond",
+    "blue",
+    "blueviolet",
+    "brown",
+    "burlywood",
+    "cadetblue",
+    "chartreuse",
+    "chocolate",
+    "coral",
+    "cornflowerblue",
+    "cornsilk",
+    "crimson",
+    "cyan",
+    "darkblue",
+    "darkcyan",
+    "darkgoldenrod",
+    "darkgray",
+    "darkgreen",
+    "darkgrey",
+    "darkkhaki",
+    "darkmagenta",
+    "darkolivegreen",
+    "darkorange",
+    "darkorchid",
+    "darkred",
+    "darksalmon",
+    "darkseagreen",
+    "darkslateblue",
+    "darkslategray",
+    "darkslategrey",
+    "darkturquoise",
+    "darkviolet",
+    "deeppink",
+    "deepskyblue",
+    "dimgray",
+    "dimgrey",
+    "dodgerblue",
+    "firebrick",
+    "floralwhite",
+    "forestgreen",
+    "fuchsia",
+    "gainsboro",
+    "ghostwhite",
+    "gold",
+    "goldenrod",
+    "gray",
+    "green",
+    "greenyellow",
+    "grey",
+    "honeydew",
+    "hotpink",
+    "indianred",
+    "indigo",
+    "ivory",
+    "khaki",
+    "lavender",
+    "lavenderblush",
+    "lawngreen",
+    "lemonchiffon",
+    "lightblue",
+    "lightcoral",
+    "lightcyan",
+    "lightgoldenrodyellow",
+    "lightgray",
+    "lightgreen",
+    "lightgrey",
+    "lightpink",
+    "lightsalmon",
+    "lightseagreen",
+    "lightskyblue",
+    "lightslategray",
+    "lightslategrey",
+    "lightsteelblue",
+    "lightyellow",
+    "lime",
+    "limegreen",
+    "linen",
+    "magenta",
+    "maroon",
+    "mediumaquamarine",
+    "mediumblue",
+    "mediumorchid",
+    "mediumpurple",
+    "mediumseagreen",
+    "mediumslateblue",
+    "mediumspringgreen",
+    "mediumturquoise",
+    "mediumvioletred",
+    "midnightblue",
+    "mintcream",
+    "mistyrose",
+    "moccasin",
+    "navajowhite",
+    "navy",
+    "oldlace",
+    "olive",
+    "olivedrab",
+    "orange",
+    "orangered",
+    "orchid",
+    "palegoldenrod",
+    "palegreen",
+    "paleturquoise",
+    "palevioletred",
+    "papayawhip",
+    "peachpuff",
+    "peru",
+    "pink",
+    "plum",
+    "powderblue",
+    "purple",
+    "rebeccapurple",
+    "red",
+    "rosybrown",
+    "royalblue",
+    "saddlebrown",
+    "salmon",
+    "sandybrown",
+    "seagreen",
+    "seashell",
+    "sienna",
+    "silver",
+    "skyblue",
+    "slateblue",
+    "slategray",
+    "slategrey",
+    "snow",
+    "springgreen",
+    "steelblue",
+    "tan",
+    "teal",
+    "thistle",
+    "tomato",
+    "turquoise",
+    "violet",
+    "wheat",
+    "white",
+    "whitesmoke",
+    "yellow",
+    "yellowgreen",
+];
+
+impl Document {
+    /// Returns the declarations of the style attribute of the element
+    pub fn style_attribute_declarations(&self, node_id: NodeId) -> Vec<StyleDeclaration> {
+        self.get_attribute("style", node_id)
+            .map(parse_declarations)
+            .unwrap_or_default()
+    }
+
+    /// Returns the presentational hints of the element: the declarations that follow from its
+    /// presentational attributes
+    pub fn presentational_hints(&self, node_id: NodeId) -> Vec<StyleDeclaration> {
+        let Some(node) = self.get_node_by_id(node_id) else {
+            return Vec::new();
+        };
+        let NodeData::Element(element) = &node.data else {
+            return Vec::new();
+        };
+        if node.namespace.as_deref() != Some(HTML_NAMESPACE) {
+            return Vec::new();
+        }
+
+        let attribute = |name: &str| element.attributes.get(name).map(String::as_str);
+        let name = node.name.as_str();
+        let mut hints = Vec::new();
+
+        // Dimensions
+        let (width, height) = match name {
+            "img" | "iframe" | "embed" | "object" | "video" => (true, true),
+            "table" | "td" | "th" => (true, true),
+            "col" | "hr" => (true, false),
+            _ => (false, false),
+        };
+        let non_zero = matches!(name, "table" | "td" | "th");
+        for (attribute_name, property, mapped) in
+            [("width", "width", width), ("height", "height", height)]
+        {
+            let Some(value) = attribute(attribute_name).filter(|_| mapped) else {
+                continue;
+            };
+            if let Some(dimension) = parse_dimension(value, non_zero) {
+                hints.push(StyleDeclaration::new(property, &dimension));
+            }
+        }
+
+        // Background color
+        if matches!(
+            name,
+            "body" | "table" | "thead" | "tbody" | "tfoot" | "tr" | "td" | "th"
+        ) {
+            if let Some(color) = attribute("bgcolor").and_then(parse_legacy_color) {
+                hints.push(StyleDeclaration::new("background-color", &color));
+            }
+        }
+        if name == "font" {
+            if let Some(color) = attribute("color").and_then(parse_legacy_color) {
+                hints.push(StyleDeclaration::new("color", &color));
+            }
+        }
+
+        // Alignment
+        if let Some(align) = attribute("align").map(|align| align.trim().to_ascii_lowercase()) {
+            match (name, align.as_str()) {
+                ("table", "left" | "right") => {
+                    hints.push(StyleDeclaration::new("float", &align));
+                }
+                ("table", "center") => {
+                    hints.push(StyleDeclaration::new("margin-left", "auto"));
+                    hints.push(StyleDeclaration::new("margin-right", "auto"));
+                }
+                ("img" | "iframe" | "embed" | "object", "left" | "right") => {
+                    hints.push(StyleDeclaration::new("float", &align));
+                }
+                ("img" | "iframe" | "embed" | "object", "top" | "middle" | "bottom") => {
+                    hints.push(StyleDeclaration::new("vertical-align", &align));
+                }
+                (
+                    "div" | "p" | "h1" | "h2" | "h3" | "h4" | "h5" | "h6" | "caption" | "thead"
+                    | "tbody" | "tfoot" | "tr" | "td" | "th",
+                    "left" | "right" | "center" | "justify",
+                ) => {
+                    hints.push(StyleDeclaration::new("text-align", &align));
+                }
+                _ => {}
+            }
+        }
+
+        // Borders
+        if matches!(name, "table" | "img") {
+            if let Some(border) = attribute("border") {
+                let width = parse_non_negative_integer(border).unwrap_or(1);
+                let style = if name == "table" { "outset" } else { "solid" };
+                hints.push(StyleDeclaration::new("border-width", &format!("{width}px")));
+                hints.push(StyleDeclaration::new("border-style", style));
+            }
+        }
+
+        // List markers
+        if matches!(name, "ol" | "ul" | "li") {
+            if let Some(list_style_type) = attribute("type").and_then(list_style_type_hint) {
+                hints.push(StyleDeclaration::new("list-style-type", list_style_type));
+            }
+        }
+
+        hints
+    }
+
+    /// Returns the declarations of the element itself in cascade order: the presentational hints,
+    /// followed by the declarations of the style attribute
+    pub fn declared_style(&self, node_id: NodeId) -> Vec<StyleDeclaration> {
+        let mut declarations = self.presentational_hints(node_id);
+        declarations.extend(self.style_attribute_declarations(node_id));
+        declarations
+    }
+}
+
+/// Returns the value of the property that wins the cascade: the last important declaration, or
+/// otherwise the last declaration
+pub(crate) fn cascaded_value<'a>(
+    declarations: &'a [StyleDeclaration],
+    property: &str,
+) -> Option<&'a str> {
+    let mut matching = declarations
+        .iter()
+        .rev()
+        .filter(|declaration| declaration.property == property);
+    let last = matching.clone().next()?;
+    let winner = matching
+        .find(|declaration| declaration.important)
+        .unwrap_or(last);
+    Some(winner.value.as_str())
+}
+
+/// Parses a list of declarations, like the contents of a style attribute. Invalid declarations
+/// are skipped.
+pub fn parse_declarations(input: &str) -> Vec<StyleDeclaration> {
+    split_outside_blocks(input, ';')
+        .into_iter()
+        .filter_map(|declaration| {
+            let (property, value) = declaration.split_once(':')?;
+            let property = property.trim();
+            if property.is_empty() {
+                return None;
+            }
+            let property = if property.starts_with("--") {
+                property.to_string()
+            } else {
+                property.to_ascii_lowercase()
+            };
+
+            let mut value = value.trim();
+            let mut important = false;
+            if let Some(index) = value.rfind('!') {
+                if value[index + 1..].trim().eq_ignore_ascii_case("important") {
+                    important = true;
+                    value = value[..index].trim_end();
+                }
+            }
+            if value.is_empty() {
+                return None;
+            }
+
+            Some(StyleDeclaration {
+                property,
+                value: value.to_string(),
+                important,
+            })
+        })
+        .collect()
+}
+
+/// Splits the input at the separator, except inside strings and (), [] and {} blocks
+pub(crate) fn split_outside_blocks(input: &str, separator: char) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut depth = 0usize;
+    let mut quote = None;
+    let mut start = 0;
+    let mut chars = input.char_indices();
+    while let Some((index, c)) = chars.next() {
+        match (quote, c) {
+            (Some(_), '\\') => {
+                chars.next();
+            }
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), _) => {}
+            (None, '"' | '\'') => quote = Some(c),
+            (None, '(' | '[' | '{') => depth += 1,
+            (None, ')' | ']' | '}') => depth = depth.saturating_sub(1),
+            (None, c) if c == separator && depth == 0 => {
+                parts.push(&input[start..index]);
+                start = index + c.len_utf8();
+            }
+            _ => {}
+        }
+    }
+    parts.push(&input[start..]);
+    parts
+}
+
+/// Parses a dimension value (like "100" or "50%") into a CSS length. Zero is not allowed when
+/// `non_zero` is set.
+///
+/// See: https://html.spec.whatwg.org/multipage/common-microsyntaxes.html#rules-for-parsing-dimension-values
+fn parse_dimension(value: &str, non_zero: bool) -> Option<String> {
+    let value = value.trim_start_matches(|c: char| c.is_ascii_whitespace());
+    let digits = value.len() - value.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+    if digits == 0 {
+        return None;
+    }
+
+    let mut end = digits;
+    let rest = &value[digits..];
+    if let Some(fraction) = rest.strip_prefix('.') {
+        let fraction_digits = fraction.len()
+            - fraction
+                .trim_start_matches(|c: char| c.is_ascii_digit())
+                .len();
+        if fraction_digits > 0 {
+            end += 1 + fraction_digits;
+        }
+    }
+
+    let number: f64 = value[..end].parse().ok()?;
+    if non_zero && number == 0.0 {
+        return None;
+    }
+    let number = value[..end].trim_end_matches('.');
+    match value[end..].starts_with('%') {
+        true => Some(format!("{number}%")),
+        false => Some(format!("{number}px")),
+    }
+}
+
+/// Returns the list-style-type for the type attribute of a list or list item. The numbering
+/// types are case-sensitive, the bullet types are not.
+fn list_style_type_hint(value: &str) -> Option<&'static str> {
+    match value {
+        "1" => return Some("decimal"),
+        "a" => return Some("lower-alpha"),
+        "A" => return Some("upper-alpha"),
+        "i" => return Some("lower-roman"),
+        "I" => return Some("upper-roman"),
+        _ => {}
+    }
+    ["none", "disc", "circle", "square"]
+        .into_iter()
+        .find(|keyword| value.eq_ignore_ascii_case(keyword))
+}
+
+/// Parses a non-negative integer, ignoring anything after the digits
+fn parse_non_negative_integer(value: &str) -> Option<u32> {
+    let value = value.trim_start_matches(|c: char| c.is_ascii_whitespace());
+    let value = value.strip_prefix('+').unwrap_or(value);
+    let digits = value.len() - value.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+    value[..digits].parse().ok()
+}
+
+/// Parses a legacy color value (like the bgcolor attribute) into a CSS color. Returns None for
+/// values that do not give a color.
+///
+/// See: https://html.spec.whatwg.org/multipage/common-microsyntaxes.html#rules-for-parsing-a-legacy-colour-value
+fn parse_legacy_color(value: &str) -> Option<String> {
+    let value = value.trim_matches(|c: char| c.is_ascii_whitespace());
+    if value.is_empty() || value.eq_ignore_ascii_case("transparent") {
+        return None;
+    }
+    let lowercase = value.to_ascii_lowercase();
+    if NAMED_COLORS.contains(&lowercase.as_str()) {
+        return Some(lowercase);
+    }
+    if value.len() == 4
+        && value.starts_with('#')
+        && value[1..].chars().all(|c| c.is_ascii_hexdigit())
+    {
+        let expanded: String = value[1..].chars().flat_map(|c| [c, c]).collect();
+        return Some(format!("#{}", expanded.to_ascii_lowercase()));
+    }
+
+    // Anything else is turned into hex digits
+    let mut digits: String = value
+        .chars()
+        .flat_map(|c| match c {
+            c if (c as u32) > 0xFFFF => vec!['0', '0'],
+            c => vec![c],
+        })
+        .take(128)
+        .collect();
+    if digits.starts_with('#') {
+        digits.remove(0);
+    }
+    let mut digits: Vec<char> = digits
+        .chars()
+        .map(|c| if c.is_ascii_hexdigit() { c } else { '0' })
+        .collect();
+    while digits.is_empty() || digits.len() % 3 != 0 {
+        digits.push('0');
+    }
+
+    let length = digits.len() / 3;
+    let mut components: Vec<&[char]> = digits.chunks(length).collect();
+    if length > 8 {
+        components = components
+            .into_iter()
+            .map(|component| &component[length - 8..])
+            .collect();
+    }
+    while components[0].len() > 2 && components.iter().all(|component| component[0] == '0') {
+        components = components
+            .into_iter()
+            .map(|component| &component[1..])
+            .collect();
+    }
+
+    let mut color = String::from("#");
+    for component in components {
+        let component: String = component.iter().take(2).collect();
+        color.push_str(&format!("{:0>2}", component));
+    }
+    Some(color.to_ascii_lowercase())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::parse;
+
+    fn declarations(declarations: Vec<StyleDeclaration>) -> Vec<String> {
+        declarations
+            .into_iter()
+            .map(|declaration| {
+                let important = if declaration.important {
+                    " !important"
+                } else {
+                    ""
+                };
+                format!(
+                    "{}: {}{}",
+                    declaration.property, declaration.value, important
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn presentational_hints() {
+        let document = parse(
+            "<body bgcolor=chucknorris><table id=t width=80% height=0 align=center border=2 bgcolor='#0f0'>\
+             <tr align=RIGHT><td id=c width=100.5 height=20px>x</td></tr></table>\
+             <img id=i width=' 50' height=abc align=middle border><p id=p align=justify width=10>",
+        );
+        let doc = document.get();
+        let id = |id: &str| doc.get_node_by_named_id(id).unwrap().id;
+
+        assert_eq!(
+            declarations(doc.presentational_hints(id("t"))),
+            [
+                "width: 80%",
+                "background-color: #00ff00",
+                "margin-left: auto",
+                "margin-right: auto",
+                "border-width: 2px",
+                "border-style: outset"
+            ]
+        );
+        assert_eq!(
+            declarations(doc.presentational_hints(id("c"))),
+            ["width: 100.5px", "height: 20px"]
+        );
+        assert_eq!(
+            declarations(doc.presentational_hints(id("i"))),
+            [
+                "width: 50px",
+                "vertical-align: middle",
+                "border-width: 1px",
+                "border-style: solid"
+            ]
+        );
+        // The width attribute is not presentational on paragraphs
+        assert_eq!(
+            declarations(doc.presentational_hints(id("p"))),
+            ["text-align: justify"]
+        );
+
+        let body = doc.elements_by_tag_name("body")[0];
+        assert_eq!(
+            declarations(doc.presentational_hints(body)),
+            ["background-color: #c00000"]
+        );
+    }
+
+    #[test]
+    fn style_attribute() {
+        let document = parse(
+            "<div id=d width=1 style='COLOR: red; background: url(\"a;b.png\") ; --Custom: x;bad; width: 10px !IMPORTANT'></div>\
+             <img id=i width=20 style='width: 30px'>",
+        );
+        let doc = document.get();
+        let id = |id: &str| doc.get_node_by_named_id(id).unwrap().id;
+
+        assert_eq!(
+            declarations(doc.style_attribute_declarations(id("d"))),
+            [
+                "color: red",
+                "background: url(\"a;b.png\")",
+                "--Custom: x",
+                "width: 10px !important"
+            ]
+        );
+
+        // The style attribute comes after the presentational hints, so it wins
+        assert_eq!(
+            declarations(doc.declared_style(id("i"))),
+            ["width: 20px", "width: 30px"]
+        );
+    }
+
+    #[test]
+    fn legacy_colors() {
+        assert_eq!(parse_legacy_color("Red").as_deref(), Some("red"));
+        assert_eq!(parse_legacy_color("#abc").as_deref(), Some("#aabbcc"));
+        assert_eq!(parse_legacy_color("#123456").as_deref(), Some("#123456"));
+        assert_eq!(
+            parse_legacy_color("ninja turtle").as_deref(),
+            Some("#00a000")
+        );
+        assert_eq!(parse_legacy_color(" transparent "), None);
+        assert_eq!(parse_legacy_color(""), None);
+    }
+}
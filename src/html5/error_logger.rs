@@ -1,5 +1,6 @@
 use crate::bytes::Position;
 use crate::types::ParseError;
+use std::collections::HashSet;
 
 /// Possible parser error enumerated
 pub enum ParserError {
@@ -153,16 +154,29 @@ impl ParserError {
     }
 }
 
+/// Sorts the errors by position in the input stream, then by message
+pub fn sort_errors(errors: &mut [ParseError]) {
+    errors.sort_by(|a, b| {
+        (a.offset, a.line, a.col, &a.message).cmp(&(b.offset, b.line, b.col, &b.message))
+    });
+}
+
 #[derive(Clone)]
 pub struct ErrorLogger {
     /// List of errors that occurred during parsing
     errors: Vec<ParseError>,
+    /// Line, column and message of the logged errors, so duplicates are found without scanning
+    /// all errors (which makes parsing large documents with many errors quadratic)
+    seen: HashSet<(usize, usize, String)>,
 }
 
 impl ErrorLogger {
     /// Creates a new error logger
     pub fn new() -> Self {
-        ErrorLogger { errors: Vec::new() }
+        ErrorLogger {
+            errors: Vec::new(),
+            seen: HashSet::new(),
+        }
     }
 }
 
@@ -178,13 +192,20 @@ impl ErrorLogger {
         self.errors.clone()
     }
 
+    /// Returns the errors in a deterministic order: by position in the input stream, then by
+    /// message. Errors are logged in the order the tokenizer and tree builder find them, which
+    /// is not always the order of their positions.
+    pub fn get_sorted_errors(&self) -> Vec<ParseError> {
+        let mut errors = self.errors.clone();
+        sort_errors(&mut errors);
+        errors
+    }
+
     /// Adds a new error to the error logger
     pub fn add_error(&mut self, pos: Position, message: &str) {
         // Check if the error already exists, if so, don't add it again
-        for err in &self.errors {
-            if err.line == pos.line && err.col == pos.col && err.message == *message {
-                return;
-            }
+        if !self.seen.insert((pos.line, pos.col, message.to_string())) {
+            return;
         }
 
         self.errors.push(ParseError {
@@ -196,6 +217,18 @@ impl ErrorLogger {
 
         // println!("Parse error ({}/{}): {}", pos.line, pos.col, message);
     }
+
+    /// Returns the number of logged errors
+    pub(crate) fn error_count(&self) -> usize {
+        self.errors.len()
+    }
+
+    /// Removes all errors logged after the first `len` errors, so they can be logged again
+    pub(crate) fn truncate(&mut self, len: usize) {
+        for error in self.errors.drain(len..) {
+            self.seen.remove(&(error.line, error.col, error.message));
+        }
+    }
 }
 
 #[cfg(test)]
@@ -271,4 +304,21 @@ mod tests {
 
         assert_eq!(logger.get_errors().len(), 10);
     }
+
+    #[test]
+    fn test_sorted_errors() {
+        let mut logger = ErrorLogger::new();
+
+        logger.add_error(Position::new(8, 1, 9), "b");
+        logger.add_error(Position::new(2, 1, 3), "z");
+        logger.add_error(Position::new(8, 1, 9), "a");
+
+        let messages: Vec<_> = logger
+            .get_sorted_errors()
+            .into_iter()
+            .map(|error| error.message)
+            .collect();
+        assert_eq!(messages, ["z", "a", "b"]);
+        assert_eq!(logger.get_errors()[0].message, "b");
+    }
 }
@@ -0,0 +1,582 @@
+//! DOM events
+//!
+//! Event listeners are registered per node on the document. Events are dispatched through
+//! [`DocumentHandle::dispatch_event`], which follows the capture / target / bubble phases along
+//! the ancestors of the target node.
+//!
+//! Inline event handler attributes (e.g. `onclick="..."`) take part in dispatch as well. They are
+//! registered as listeners when the attribute is set, but only compiled the first time they are
+//! invoked, through the [`EventHandlerCompiler`] of the document (normally the javascript
+//! runtime, but this can be any Rust callback).
+//!
+//! See: https://dom.spec.whatwg.org/#events
+use crate::html5::compaction::{remap_keys, NodeIdMap};
+use crate::html5::drag_drop::DataTransfer;
+use crate::html5::node::{NodeData, NodeId};
+use crate::html5::parser::document::{Document, DocumentHandle};
+use crate::html5::pointer::PointerDetail;
+use crate::html5::scroll::WheelInput;
+use core::fmt;
+use core::fmt::Debug;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+/// Callback that is called when an event is dispatched to a listener
+pub type EventCallback = Rc<dyn Fn(&DocumentHandle, &mut Event)>;
+
+/// Phase of the dispatch an event is currently in
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum EventPhase {
+    None,
+    Capturing,
+    AtTarget,
+    Bubbling,
+}
+
+/// An event that can be dispatched to a node
+#[derive(Debug, Clone, PartialEq)]
+pub struct Event {
+    /// Type of the event (e.g. "click")
+    pub event_type: String,
+    pub bubbles: bool,
+    pub cancelable: bool,
+    /// Node the event is dispatched to
+    pub target: Option<NodeId>,
+    /// Node whose listeners are currently invoked
+    pub current_target: Option<NodeId>,
+    pub phase: EventPhase,
+    /// Extra data of the event, depending on its kind
+    pub detail: EventDetail,
+    default_prevented: bool,
+    propagation_stopped: bool,
+    immediate_propagation_stopped: bool,
+}
+
+/// Extra data that is carried by some kinds of events
+#[derive(Debug, Clone, Default, PartialEq)]
+pub enum EventDetail {
+    #[default]
+    None,
+    /// The data being transferred by a drag and drop or clipboard event
+    DataTransfer(Rc<RefCell<DataTransfer>>),
+    /// The pointer of a pointer event
+    Pointer(PointerDetail),
+    /// The element that loses (or gets) the focus when another element gets (or loses) it
+    Focus(Option<NodeId>),
+    /// The input of a wheel event
+    Wheel(WheelInput),
+}
+
+impl Event {
+    /// Creates a new event of the given type
+    pub fn new(event_type: &str, bubbles: bool, cancelable: bool) -> Self {
+        Self {
+            event_type: event_type.to_string(),
+            bubbles,
+            cancelable,
+            target: None,
+            current_target: None,
+            phase: EventPhase::None,
+            detail: EventDetail::None,
+            default_prevented: false,
+            propagation_stopped: false,
+            immediate_propagation_stopped: false,
+        }
+    }
+
+    /// Sets the extra data of the event
+    pub fn with_detail(mut self, detail: EventDetail) -> Self {
+        self.detail = detail;
+        self
+    }
+
+    /// Returns the data transfer of a drag and drop or clipboard event
+    pub fn data_transfer(&self) -> Option<&Rc<RefCell<DataTransfer>>> {
+        match &self.detail {
+            EventDetail::DataTransfer(data_transfer) => Some(data_transfer),
+            _ => None,
+        }
+    }
+
+    /// Returns the pointer of a pointer event
+    pub fn pointer(&self) -> Option<&PointerDetail> {
+        match &self.detail {
+            EventDetail::Pointer(pointer) => Some(pointer),
+            _ => None,
+        }
+    }
+
+    /// Returns the input of a wheel event
+    pub fn wheel(&self) -> Option<&WheelInput> {
+        match &self.detail {
+            EventDetail::Wheel(input) => Some(input),
+            _ => None,
+        }
+    }
+
+    /// Cancels the default action of the event (when the event is cancelable)
+    pub fn prevent_default(&mut self) {
+        if self.cancelable {
+            self.default_prevented = true;
+        }
+    }
+
+    /// Returns true when the default action of the event has been canceled
+    pub fn default_prevented(&self) -> bool {
+        self.default_prevented
+    }
+
+    /// Stops the event from propagating to other nodes
+    pub fn stop_propagation(&mut self) {
+        self.propagation_stopped = true;
+    }
+
+    /// Stops the event from propagating to other nodes and other listeners on the current node
+    pub fn stop_immediate_propagation(&mut self) {
+        self.propagation_stopped = true;
+        self.immediate_propagation_stopped = true;
+    }
+}
+
+/// Options for registering an event listener
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ListenerOptions {
+    /// Invoke the listener during the capturing phase instead of the bubbling phase
+    pub capture: bool,
+    /// Remove the listener after it has been invoked once
+    pub once: bool,
+}
+
+/// Id of a registered event listener, used for removing it again
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ListenerId(usize);
+
+/// Compiles the source of inline event handler attributes into callbacks
+pub trait EventHandlerCompiler {
+    /// Compiles the source of the handler attribute for the given event type on the given node.
+    /// Returns None when the source could not be compiled, in which case the handler is ignored.
+    fn compile(&mut self, node_id: NodeId, event_type: &str, source: &str)
+        -> Option<EventCallback>;
+}
+
+enum Handler {
+    /// Listener added through add_event_listener()
+    Callback(EventCallback),
+    /// Listener for an inline event handler attribute. The compiled callback is cached together
+    /// with the source it was compiled from.
+    Inline {
+        attribute: String,
+        compiled: Option<(String, Option<EventCallback>)>,
+    },
+}
+
+struct EventListener {
+    id: ListenerId,
+    event_type: String,
+    options: ListenerOptions,
+    handler: Handler,
+}
+
+/// All event listeners registered on the nodes of a document
+#[derive(Default)]
+pub struct EventListeners {
+    listeners: HashMap<NodeId, Vec<EventListener>>,
+    next_id: usize,
+    compiler: Option<Rc<RefCell<dyn EventHandlerCompiler>>>,
+}
+
+impl Debug for EventListeners {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let count = self.listeners.values().map(Vec::len).sum::<usize>();
+        f.debug_struct("EventListeners")
+            .field("count", &count)
+            .finish()
+    }
+}
+
+impl PartialEq for EventListeners {
+    fn eq(&self, other: &Self) -> bool {
+        let ids = |l: &EventListeners| {
+            let mut ids = l
+                .listeners
+                .iter()
+                .flat_map(|(node_id, list)| list.iter().map(move |l| (*node_id, l.id)))
+                .collect::<Vec<_>>();
+            ids.sort_by_key(|(node_id, id)| (node_id.0, id.0));
+            ids
+        };
+        ids(self) == ids(other)
+    }
+}
+
+impl EventListeners {
+    fn add(
+        &mut self,
+        node_id: NodeId,
+        event_type: &str,
+        options: ListenerOptions,
+        handler: Handler,
+    ) -> ListenerId {
+        let id = ListenerId(self.next_id);
+        self.next_id += 1;
+
+        self.listeners
+            .entry(node_id)
+            .or_default()
+            .push(EventListener {
+                id,
+                event_type: event_type.to_string(),
+                options,
+                handler,
+            });
+        id
+    }
+
+    /// Removes all listeners of the node, as it has been removed from the document
+    pub(crate) fn forget_node(&mut self, node_id: NodeId) {
+        self.listeners.remove(&node_id);
+    }
+
+    /// Moves the listeners to the new IDs of their nodes
+    pub(crate) fn remap_nodes(&mut self, ids: &NodeIdMap) {
+        remap_keys(ids, &mut self.listeners);
+    }
+
+    /// Returns the callbacks of the listeners on the node that match the event type and phase.
+    /// Inline handlers are compiled when needed, and "once" listeners are removed.
+    fn take_callbacks(
+        &mut self,
+        node_id: NodeId,
+        event_type: &str,
+        capture: Option<bool>,
+        attributes: &HashMap<String, String>,
+    ) -> Vec<EventCallback> {
+        let Some(listeners) = self.listeners.get_mut(&node_id) else {
+            return Vec::new();
+        };
+
+        let mut callbacks = Vec::new();
+        let mut remove = Vec::new();
+        for listener in listeners.iter_mut() {
+            if listener.event_type != event_type
+                || capture.is_some_and(|c| c != listener.options.capture)
+            {
+                continue;
+            }
+
+            let callback = match &mut listener.handler {
+                Handler::Callback(callback) => Some(callback.clone()),
+                Handler::Inline {
+                    attribute,
+                    compiled,
+                } => {
+                    let Some(source) = attributes.get(attribute.as_str()) else {
+                        continue;
+                    };
+                    match compiled {
+                        Some((compiled_source, callback)) if compiled_source == source => {
+                            callback.clone()
+                        }
+                        _ => {
+                            let callback = self.compiler.as_ref().and_then(|compiler| {
+                                compiler.borrow_mut().compile(node_id, event_type, source)
+                            });
+                            *compiled = Some((source.clone(), callback.clone()));
+                            callback
+                        }
+                    }
+                }
+            };
+
+            if let Some(callback) = callback {
+                callbacks.push(callback);
+                if listener.options.once {
+                    remove.push(listener.id);
+                }
+            }
+        }
+
+        listeners.retain(|l| !remove.contains(&l.id));
+        callbacks
+    }
+}
+
+/// Returns the event type of an inline event handler attribute (e.g. "onclick" results in "click")
+pub(crate) fn inline_handler_event_type(attribute: &str) -> Option<&str> {
+    let event_type = attribute.strip_prefix("on")?;
+    if event_type.is_empty() {
+        return None;
+    }
+    Some(event_type)
+}
+
+impl Document {
+    /// Adds an event listener for the given event type to the node
+    pub fn add_event_listener(
+        &mut self,
+        node_id: NodeId,
+        event_type: &str,
+        callback: EventCallback,
+        options: ListenerOptions,
+    ) -> ListenerId {
+        self.event_listeners
+            .add(node_id, event_type, options, Handler::Callback(callback))
+    }
+
+    /// Removes an event listener from the node. Returns false when the listener was not found.
+    pub fn remove_event_listener(&mut self, node_id: NodeId, listener_id: ListenerId) -> bool {
+        let Some(listeners) = self.event_listeners.listeners.get_mut(&node_id) else {
+            return false;
+        };
+
+        let count = listeners.len();
+        listeners.retain(|l| l.id != listener_id);
+        listeners.len() != count
+    }
+
+    /// Sets the compiler that is used for inline event handler attributes. Without a compiler,
+    /// inline event handlers are ignored.
+    pub fn set_event_handler_compiler(&mut self, compiler: Rc<RefCell<dyn EventHandlerCompiler>>) {
+        self.event_listeners.compiler = Some(compiler);
+    }
+
+    /// Registers an inline event handler for the given attribute, unless the node already has one
+    pub(crate) fn register_inline_handler(&mut self, node_id: NodeId, attribute: &str) {
+        let Some(event_type) = inline_handler_event_type(attribute) else {
+            return;
+        };
+
+        let exists = self
+            .event_listeners
+            .listeners
+            .get(&node_id)
+            .is_some_and(|listeners| {
+                listeners.iter().any(|l| {
+                    matches!(&l.handler, Handler::Inline { attribute: a, .. } if a == attribute)
+                })
+            });
+        if exists {
+            return;
+        }
+
+        self.event_listeners.add(
+            node_id,
+            event_type,
+            ListenerOptions::default(),
+            Handler::Inline {
+                attribute: attribute.to_string(),
+                compiled: None,
+            },
+        );
+    }
+
+    /// Returns the callbacks that should be invoked on the node in the given phase
+    fn event_callbacks(
+        &mut self,
+        node_id: NodeId,
+        event_type: &str,
+        phase: EventPhase,
+    ) -> Vec<EventCallback> {
+        let attributes = match self.get_node_by_id(node_id).map(|n| &n.data) {
+            Some(NodeData::Element(element)) => element.attributes.clone(),
+            _ => HashMap::new(),
+        };
+
+        let capture = match phase {
+            EventPhase::Capturing => Some(true),
+            EventPhase::Bubbling => Some(false),
+            _ => None,
+        };
+
+        self.event_listeners
+            .take_callbacks(node_id, event_type, capture, &attributes)
+    }
+}
+
+impl DocumentHandle {
+    /// Dispatches the event to the target node. Returns false when the event was canceled by
+    /// one of the listeners.
+    pub fn dispatch_event(&self, target: NodeId, event: &mut Event) -> bool {
+        event.target = Some(target);
+        event.propagation_stopped = false;
+        event.immediate_propagation_stopped = false;
+
+        // Path from the target up to the root
+        let mut path = vec![target];
+        let mut current = target;
+        while let Some(parent_id) = self.get().get_node_by_id(current).and_then(|n| n.parent) {
+            path.push(parent_id);
+            current = parent_id;
+        }
+
+        let mut phases = Vec::new();
+        for node_id in path.iter().skip(1).rev() {
+            phases.push((*node_id, EventPhase::Capturing));
+        }
+        phases.push((target, EventPhase::AtTarget));
+        if event.bubbles {
+            for node_id in path.iter().skip(1) {
+                phases.push((*node_id, EventPhase::Bubbling));
+            }
+        }
+
+        for (node_id, phase) in phases {
+            if event.propagation_stopped {
+                break;
+            }
+
+            event.current_target = Some(node_id);
+            event.phase = phase;
+
+            let mut handle = self.clone();
+            let callbacks = handle
+                .get_mut()
+                .event_callbacks(node_id, &event.event_type, phase);
+
+            for callback in callbacks {
+                callback(self, event);
+                if event.immediate_propagation_stopped {
+                    break;
+                }
+            }
+        }
+
+        event.current_target = None;
+        event.phase = EventPhase::None;
+
+        !event.default_prevented
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::parse;
+
+    fn recorder(log: &Rc<RefCell<Vec<String>>>, name: &str) -> EventCallback {
+        let log = log.clone();
+        let name = name.to_string();
+        Rc::new(move |_, event: &mut Event| {
+            log.borrow_mut().push(format!("{}:{:?}", name, event.phase));
+        })
+    }
+
+    #[test]
+    fn capture_target_and_bubble() {
+        let mut document = parse(r#"<div id="outer"><p id="inner">text</p></div>"#);
+        let outer = document.get().get_node_by_named_id("outer").unwrap().id;
+        let inner = document.get().get_node_by_named_id("inner").unwrap().id;
+
+        let log = Rc::new(RefCell::new(Vec::new()));
+        let capture = ListenerOptions {
+            capture: true,
+            ..Default::default()
+        };
+
+        let mut doc = document.get_mut();
+        doc.add_event_listener(outer, "click", recorder(&log, "outer"), capture);
+        doc.add_event_listener(outer, "click", recorder(&log, "outer"), Default::default());
+        doc.add_event_listener(inner, "click", recorder(&log, "inner"), Default::default());
+        doc.add_event_listener(inner, "focus", recorder(&log, "focus"), Default::default());
+        drop(doc);
+
+        let mut event = Event::new("click", true, true);
+        assert!(document.dispatch_event(inner, &mut event));
+        assert_eq!(
+            *log.borrow(),
+            vec!["outer:Capturing", "inner:AtTarget", "outer:Bubbling"]
+        );
+
+        // Non-bubbling events are not seen by bubbling listeners on ancestors
+        log.borrow_mut().clear();
+        let mut event = Event::new("click", false, true);
+        document.dispatch_event(inner, &mut event);
+        assert_eq!(*log.borrow(), vec!["outer:Capturing", "inner:AtTarget"]);
+    }
+
+    #[test]
+    fn stop_propagation_and_prevent_default() {
+        let mut document = parse(r#"<div id="outer"><p id="inner">text</p></div>"#);
+        let outer = document.get().get_node_by_named_id("outer").unwrap().id;
+        let inner = document.get().get_node_by_named_id("inner").unwrap().id;
+
+        let log = Rc::new(RefCell::new(Vec::new()));
+        let mut doc = document.get_mut();
+        doc.add_event_listener(
+            inner,
+            "click",
+            Rc::new(|_, event: &mut Event| {
+                event.prevent_default();
+                event.stop_propagation();
+            }),
+            ListenerOptions {
+                once: true,
+                ..Default::default()
+            },
+        );
+        let listener =
+            doc.add_event_listener(outer, "click", recorder(&log, "outer"), Default::default());
+        drop(doc);
+
+        let mut event = Event::new("click", true, true);
+        assert!(!document.dispatch_event(inner, &mut event));
+        assert!(log.borrow().is_empty());
+
+        // The first listener was only invoked once
+        let mut event = Event::new("click", true, true);
+        assert!(document.dispatch_event(inner, &mut event));
+        assert_eq!(*log.borrow(), vec!["outer:Bubbling"]);
+
+        assert!(document.get_mut().remove_event_listener(outer, listener));
+        assert!(!document.get_mut().remove_event_listener(outer, listener));
+    }
+
+    struct Compiler {
+        compiled: Rc<RefCell<Vec<String>>>,
+    }
+
+    impl EventHandlerCompiler for Compiler {
+        fn compile(
+            &mut self,
+            _node_id: NodeId,
+            event_type: &str,
+            source: &str,
+        ) -> Option<EventCallback> {
+            self.compiled
+                .borrow_mut()
+                .push(format!("{}={}", event_type, source));
+
+            let log = self.compiled.clone();
+            let source = source.to_string();
+            Some(Rc::new(move |_, _| log.borrow_mut().push(source.clone())))
+        }
+    }
+
+    #[test]
+    fn inline_event_handlers() {
+        let mut document = parse(r#"<button id="button" onclick="go()">Go</button>"#);
+        let button = document.get().get_node_by_named_id("button").unwrap().id;
+
+        let log = Rc::new(RefCell::new(Vec::new()));
+        document
+            .get_mut()
+            .set_event_handler_compiler(Rc::new(RefCell::new(Compiler {
+                compiled: log.clone(),
+            })));
+
+        document.dispatch_event(button, &mut Event::new("click", true, true));
+        document.dispatch_event(button, &mut Event::new("click", true, true));
+
+        // Compiled once, executed twice
+        assert_eq!(*log.borrow(), vec!["click=go()", "go()", "go()"]);
+    }
+
+    #[test]
+    fn inline_event_handlers_without_compiler() {
+        let document = parse(r#"<button id="button" onclick="go()">Go</button>"#);
+        let button = document.get().get_node_by_named_id("button").unwrap().id;
+
+        assert!(document.dispatch_event(button, &mut Event::new("click", true, true)));
+    }
+}
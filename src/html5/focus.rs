@@ -0,0 +1,348 @@
+//! Focus
+//!
+//! At most one element of a document has the focus, and receives the keyboard input. Which
+//! elements can get the focus depends on the kind of element (links, form controls, editing
+//! hosts), on their `tabindex` attribute, and on their state: disabled, hidden and inert elements
+//! cannot be focused.
+//!
+//! Moving the focus fires `blur` and `focusout` on the element that loses it, and then `focus` and
+//! `focusin` on the element that gets it. `focusout` and `focusin` bubble, `blur` and `focus` do
+//! not. The related target of the events is the other element.
+//!
+//! The tab order is the order in which the tab key moves through the elements: first the elements
+//! with a positive `tabindex` (lowest first), then the other focusable elements in tree order.
+//! Elements with a negative `tabindex` can be focused, but are not in the tab order.
+//!
+//! See: https://html.spec.whatwg.org/multipage/interaction.html#focus
+use crate::html5::events::{Event, EventDetail};
+use crate::html5::node::{Node, NodeId, HTML_NAMESPACE};
+use crate::html5::parser::document::{Document, DocumentHandle};
+
+impl Document {
+    /// Returns the element that has the focus. An element that has become unfocusable (e.g.
+    /// because it was removed or disabled) no longer has the focus.
+    pub fn focused_element(&self) -> Option<NodeId> {
+        self.focused.filter(|node_id| self.is_focusable(*node_id))
+    }
+
+    /// Returns true when the element can get the focus
+    pub fn is_focusable(&self, node_id: NodeId) -> bool {
+        let Some(node) = self.get_node_by_id(node_id) else {
+            return false;
+        };
+        if !node.is_namespace(HTML_NAMESPACE) || !self.is_connected(node_id) {
+            return false;
+        }
+        if self.is_hidden(node_id) || self.is_inert(node_id) {
+            return false;
+        }
+
+        if is_form_control(node) && self.is_control_disabled(node_id) {
+            return false;
+        }
+        tab_index_attribute(node).is_some() || self.is_focusable_by_default(node)
+    }
+
+    /// Returns the tab index of the element: the value of its `tabindex` attribute, or 0 for
+    /// elements that are focusable by default and -1 for other elements
+    pub fn tab_index(&self, node_id: NodeId) -> i32 {
+        let Some(node) = self.get_node_by_id(node_id) else {
+            return -1;
+        };
+        tab_index_attribute(node).unwrap_or(if self.is_focusable_by_default(node) {
+            0
+        } else {
+            -1
+        })
+    }
+
+    /// Returns the elements in the order the tab key moves through them
+    pub fn tab_order(&self) -> Vec<NodeId> {
+        let mut elements = Vec::new();
+
+        let mut stack = vec![NodeId::root()];
+        while let Some(node_id) = stack.pop() {
+            let Some(node) = self.get_node_by_id(node_id) else {
+                continue;
+            };
+            if self.is_focusable(node_id) {
+                let tab_index = self.tab_index(node_id);
+                if tab_index >= 0 {
+                    elements.push((node_id, tab_index));
+                }
+            }
+            stack.extend(node.children.iter().rev());
+        }
+
+        // Positive tab indexes go first, in ascending order. The sort is stable, so elements with
+        // the same tab index stay in tree order.
+        elements.sort_by_key(|(_, tab_index)| match *tab_index {
+            0 => i32::MAX,
+            tab_index => tab_index,
+        });
+        elements.into_iter().map(|(node_id, _)| node_id).collect()
+    }
+
+    /// Returns true for elements that are focusable without a `tabindex` attribute
+    fn is_focusable_by_default(&self, node: &Node) -> bool {
+        match node.name.as_str() {
+            "a" | "area" => node.get_attribute("href").is_some(),
+            "input" => !node
+                .get_attribute("type")
+                .is_some_and(|t| t.eq_ignore_ascii_case("hidden")),
+            "button" | "select" | "textarea" | "iframe" => true,
+            "summary" => node
+                .parent
+                .is_some_and(|parent_id| self.details_summary(parent_id) == Some(node.id)),
+            // Editing hosts, but not the elements inside them
+            _ => {
+                self.is_editable(node.id)
+                    && !node
+                        .parent
+                        .is_some_and(|parent_id| self.is_editable(parent_id))
+            }
+        }
+    }
+}
+
+impl DocumentHandle {
+    /// Gives the focus to the element. Returns false when the element cannot be focused.
+    pub fn focus(&mut self, node_id: NodeId) -> bool {
+        if !self.get().is_focusable(node_id) {
+            return false;
+        }
+
+        let previous = self.get().focused_element();
+        if previous == Some(node_id) {
+            return true;
+        }
+
+        if let Some(previous) = previous {
+            self.fire_focus_events(previous, Some(node_id), false);
+        }
+        self.get_mut().focused = Some(node_id);
+        self.fire_focus_events(node_id, previous, true);
+        true
+    }
+
+    /// Removes the focus from the element, when it has the focus
+    pub fn blur(&mut self, node_id: NodeId) {
+        if self.get().focused_element() != Some(node_id) {
+            return;
+        }
+
+        self.get_mut().focused = None;
+        self.fire_focus_events(node_id, None, false);
+    }
+
+    /// Moves the focus to the next element in the tab order (or the previous one when going
+    /// backwards), wrapping around at the end. Returns the element that has the focus now.
+    pub fn focus_next(&mut self, backwards: bool) -> Option<NodeId> {
+        let order = self.get().tab_order();
+        if order.is_empty() {
+            return None;
+        }
+
+        let current = self
+            .get()
+            .focused_element()
+            .and_then(|node_id| order.iter().position(|id| *id == node_id));
+        let index = match (current, backwards) {
+            (None, false) => 0,
+            (None, true) => order.len() - 1,
+            (Some(index), false) => (index + 1) % order.len(),
+            (Some(index), true) => (index + order.len() - 1) % order.len(),
+        };
+
+        self.focus(order[index]);
+        self.get().focused_element()
+    }
+
+    /// Runs the focus behavior of activating the node (e.g. with a pointer): the node or its
+    /// nearest focusable ancestor gets the focus. When there is none, the focus is removed.
+    pub(crate) fn focus_from_activation(&mut self, node_id: NodeId) {
+        let mut current = Some(node_id);
+        while let Some(node_id) = current {
+            if self.get().is_focusable(node_id) {
+                self.focus(node_id);
+                return;
+            }
+            current = self.get().get_node_by_id(node_id).and_then(|n| n.parent);
+        }
+
+        let focused = self.get().focused_element();
+        if let Some(focused) = focused {
+            self.blur(focused);
+        }
+    }
+
+    /// Fires `focus` and `focusin` (or `blur` and `focusout`) at the element
+    fn fire_focus_events(&self, node_id: NodeId, related_target: Option<NodeId>, focus: bool) {
+        let (event_type, bubbling_type) = if focus {
+            ("focus", "focusin")
+        } else {
+            ("blur", "focusout")
+        };
+
+        let mut event =
+            Event::new(event_type, false, false).with_detail(EventDetail::Focus(related_target));
+        self.dispatch_event(node_id, &mut event);
+        let mut event =
+            Event::new(bubbling_type, true, false).with_detail(EventDetail::Focus(related_target));
+        self.dispatch_event(node_id, &mut event);
+    }
+}
+
+/// Returns the value of the `tabindex` attribute, when it is a valid integer
+fn tab_index_attribute(node: &Node) -> Option<i32> {
+    node.get_attribute("tabindex")?
+        .trim_matches(|c: char| c.is_ascii_whitespace())
+        .parse()
+        .ok()
+}
+
+fn is_form_control(node: &Node) -> bool {
+    matches!(
+        node.name.as_str(),
+        "button" | "input" | "select" | "textarea"
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::html5::events::{EventCallback, ListenerOptions};
+    use crate::html5::parser::tree_builder::TreeBuilder;
+    use crate::testing::parse;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    fn named(document: &DocumentHandle, id: &str) -> NodeId {
+        document.get().get_node_by_named_id(id).unwrap().id
+    }
+
+    #[test]
+    fn focusable_elements() {
+        let document = parse(
+            "<a id='link' href='/'>link</a><a id='anchor'>anchor</a>\
+             <input id='text'><input id='hidden' type='hidden'>\
+             <button id='disabled' disabled></button>\
+             <fieldset disabled><select id='select'></select></fieldset>\
+             <div id='div'>div</div><span id='span' tabindex='-1'>span</span>\
+             <div id='editor' contenteditable><p id='editable'>text</p></div>\
+             <p hidden><input id='invisible'></p><div inert><button id='inert'></button></div>",
+        );
+        let doc = document.get();
+
+        for id in ["link", "text", "span", "editor"] {
+            assert!(doc.is_focusable(named(&document, id)), "{id}");
+        }
+        for id in [
+            "anchor",
+            "hidden",
+            "disabled",
+            "select",
+            "div",
+            "editable",
+            "invisible",
+            "inert",
+        ] {
+            assert!(!doc.is_focusable(named(&document, id)), "{id}");
+        }
+        assert_eq!(doc.tab_index(named(&document, "span")), -1);
+        assert_eq!(doc.tab_index(named(&document, "text")), 0);
+    }
+
+    #[test]
+    fn tab_order() {
+        let mut document = parse(
+            "<input id='a'><input id='b' tabindex='2'><span id='c' tabindex='-1'></span>\
+             <input id='d'><input id='e' tabindex='1'><button id='f' tabindex='2'></button>",
+        );
+        let ids = ["e", "b", "f", "a", "d"].map(|id| named(&document, id));
+        assert_eq!(document.get().tab_order(), ids);
+
+        assert_eq!(document.focus_next(false), Some(ids[0]));
+        assert_eq!(document.focus_next(false), Some(ids[1]));
+        assert_eq!(document.focus_next(true), Some(ids[0]));
+        assert_eq!(document.focus_next(true), Some(ids[4]));
+        assert_eq!(document.focus_next(false), Some(ids[0]));
+
+        // An element outside the tab order starts over at the beginning
+        let c = named(&document, "c");
+        assert!(document.focus(c));
+        assert_eq!(document.focus_next(false), Some(ids[0]));
+    }
+
+    #[test]
+    fn focus_events() {
+        let mut document = parse("<form id='form'><input id='a'><input id='b'></form><p id='p'>");
+        let (form, a, b, p) = (
+            named(&document, "form"),
+            named(&document, "a"),
+            named(&document, "b"),
+            named(&document, "p"),
+        );
+
+        let log = Rc::new(RefCell::new(Vec::new()));
+        for event_type in ["focus", "blur", "focusin", "focusout"] {
+            let recorder = log.clone();
+            let callback: EventCallback = Rc::new(move |document, event| {
+                let EventDetail::Focus(related_target) = event.detail else {
+                    panic!("focus event without related target");
+                };
+                let name = |id: Option<NodeId>| {
+                    id.and_then(|id| {
+                        document
+                            .get()
+                            .get_node_by_id(id)
+                            .and_then(|node| node.get_attribute("id").cloned())
+                    })
+                    .unwrap_or_default()
+                };
+                recorder.borrow_mut().push(format!(
+                    "{} {} {}",
+                    event.event_type,
+                    name(event.target),
+                    name(related_target)
+                ));
+            });
+            document.get_mut().add_event_listener(
+                form,
+                event_type,
+                callback,
+                ListenerOptions {
+                    capture: true,
+                    once: false,
+                },
+            );
+        }
+
+        assert!(document.focus(a));
+        assert!(document.focus(a));
+        assert!(!document.focus(p));
+        assert!(document.focus(b));
+        document.blur(a);
+        document.blur(b);
+        assert_eq!(document.get().focused_element(), None);
+
+        assert_eq!(
+            *log.borrow(),
+            [
+                "focus a ",
+                "focusin a ",
+                "blur a b",
+                "focusout a b",
+                "focus b a",
+                "focusin b a",
+                "blur b ",
+                "focusout b ",
+            ]
+        );
+
+        // An element that is disabled loses the focus
+        assert!(document.focus(a));
+        document.insert_attribute("disabled", "", a).unwrap();
+        assert_eq!(document.get().focused_element(), None);
+    }
+}
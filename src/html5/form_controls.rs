@@ -0,0 +1,195 @@
+//! Form control state
+//!
+//! The attributes of a form control only hold its default state: `<option selected>` is selected
+//! when the page loads, but the user (or a script) can select another option without changing
+//! any attribute. This module keeps that current state next to the nodes. A control without
+//! state of its own falls back to its default from the attributes.
+use crate::html5::compaction::{remap_keys, NodeIdMap};
+use crate::html5::node::{NodeId, HTML_NAMESPACE};
+use crate::html5::parser::document::{Document, DocumentHandle};
+use std::collections::HashMap;
+
+/// Current state of the form controls in a document
+#[derive(Debug, Default, PartialEq)]
+pub(crate) struct FormControls {
+    /// Selectedness of option elements that has been changed from their default
+    selectedness: HashMap<NodeId, bool>,
+    /// Values of input and textarea elements that have been changed (their dirty value)
+    values: HashMap<NodeId, String>,
+    /// Checkedness of checkboxes and radio buttons that has been changed from their default
+    checkedness: HashMap<NodeId, bool>,
+    /// Custom validity messages
+    custom_validity: HashMap<NodeId, String>,
+}
+
+impl FormControls {
+    /// Returns the selectedness of the option, if it has been changed
+    pub(crate) fn selectedness(&self, option_id: NodeId) -> Option<bool> {
+        self.selectedness.get(&option_id).copied()
+    }
+
+    /// Sets the selectedness of the option
+    pub(crate) fn set_selectedness(&mut self, option_id: NodeId, selected: bool) {
+        self.selectedness.insert(option_id, selected);
+    }
+
+    /// Returns the custom validity message of the control, if it has one
+    pub(crate) fn custom_validity(&self, node_id: NodeId) -> Option<&str> {
+        self.custom_validity.get(&node_id).map(String::as_str)
+    }
+
+    /// Returns true when the value of the control has been changed from its default
+    pub(crate) fn is_dirty(&self, node_id: NodeId) -> bool {
+        self.values.contains_key(&node_id)
+    }
+
+    /// Moves the state of the controls to their new IDs
+    pub(crate) fn remap_nodes(&mut self, ids: &NodeIdMap) {
+        remap_keys(ids, &mut self.selectedness);
+        remap_keys(ids, &mut self.values);
+        remap_keys(ids, &mut self.checkedness);
+        remap_keys(ids, &mut self.custom_validity);
+    }
+
+    /// Forgets the state of the control, as it has been removed from the document
+    pub(crate) fn forget_node(&mut self, node_id: NodeId) {
+        self.selectedness.remove(&node_id);
+        self.values.remove(&node_id);
+        self.checkedness.remove(&node_id);
+        self.custom_validity.remove(&node_id);
+    }
+}
+
+impl Document {
+    /// Returns the current value of an input or textarea element, or the value of a select
+    /// element
+    pub fn control_value(&self, node_id: NodeId) -> String {
+        if let Some(value) = self.form_controls.values.get(&node_id) {
+            return value.clone();
+        }
+
+        let Some(node) = self.get_node_by_id(node_id) else {
+            return String::new();
+        };
+        if !node.is_namespace(HTML_NAMESPACE) {
+            return String::new();
+        }
+        match node.name.as_str() {
+            "textarea" => self.text_content(node_id),
+            "select" => self.select_value(node_id),
+            _ => node.get_attribute("value").cloned().unwrap_or_default(),
+        }
+    }
+
+    /// Returns true when the checkbox or radio button is checked
+    pub fn is_checked(&self, node_id: NodeId) -> bool {
+        self.form_controls
+            .checkedness
+            .get(&node_id)
+            .copied()
+            .unwrap_or_else(|| {
+                self.get_node_by_id(node_id)
+                    .is_some_and(|node| node.get_attribute("checked").is_some())
+            })
+    }
+
+    /// Resets the form control (and the controls below it) to the default state from its
+    /// attributes
+    pub fn reset_form_control(&mut self, node_id: NodeId) {
+        self.form_controls.selectedness.remove(&node_id);
+        self.form_controls.values.remove(&node_id);
+        self.form_controls.checkedness.remove(&node_id);
+        let children = self
+            .get_node_by_id(node_id)
+            .map(|node| node.children.clone())
+            .unwrap_or_default();
+        for child_id in children {
+            self.reset_form_control(child_id);
+        }
+    }
+
+    /// Returns the radio buttons in the same group as the given one (including itself): the
+    /// radio buttons with the same name in the same form
+    pub(crate) fn radio_group(&self, node_id: NodeId) -> Vec<NodeId> {
+        let Some(name) = self
+            .get_node_by_id(node_id)
+            .and_then(|node| node.get_attribute("name"))
+            .filter(|name| !name.is_empty())
+        else {
+            return vec![node_id];
+        };
+
+        let form = self.form_owner(node_id);
+        self.elements_by_tag_name("input")
+            .into_iter()
+            .filter(|id| {
+                self.get_node_by_id(*id).is_some_and(|node| {
+                    node.get_attribute("type")
+                        .is_some_and(|t| t.eq_ignore_ascii_case("radio"))
+                        && node.get_attribute("name") == Some(name)
+                })
+            })
+            .filter(|id| self.form_owner(*id) == form)
+            .collect()
+    }
+
+    /// Returns the form the control belongs to: the form given by its form attribute, or its
+    /// nearest form ancestor
+    pub fn form_owner(&self, node_id: NodeId) -> Option<NodeId> {
+        let node = self.get_node_by_id(node_id)?;
+        if let Some(form_id) = node.get_attribute("form") {
+            return self
+                .get_node_by_named_id(form_id)
+                .filter(|form| form.is_namespace(HTML_NAMESPACE) && form.name == "form")
+                .map(|form| form.id);
+        }
+
+        let mut current = node.parent;
+        while let Some(ancestor) = current.and_then(|id| self.get_node_by_id(id)) {
+            if ancestor.is_namespace(HTML_NAMESPACE) && ancestor.name == "form" {
+                return Some(ancestor.id);
+            }
+            current = ancestor.parent;
+        }
+        None
+    }
+}
+
+impl DocumentHandle {
+    /// Sets the value of an input or textarea element, as if the user typed it
+    pub fn set_control_value(&mut self, node_id: NodeId, value: &str) {
+        self.get_mut()
+            .form_controls
+            .values
+            .insert(node_id, value.to_string());
+    }
+
+    /// Checks or unchecks a checkbox or radio button. Checking a radio button unchecks the other
+    /// radio buttons in its group.
+    pub fn set_checked(&mut self, node_id: NodeId, checked: bool) {
+        let mut doc = self.get_mut();
+        let is_radio = doc.get_node_by_id(node_id).is_some_and(|node| {
+            node.get_attribute("type")
+                .is_some_and(|t| t.eq_ignore_ascii_case("radio"))
+        });
+        if is_radio && checked {
+            for radio_id in doc.radio_group(node_id) {
+                doc.form_controls.checkedness.insert(radio_id, false);
+            }
+        }
+        doc.form_controls.checkedness.insert(node_id, checked);
+    }
+
+    /// Sets a custom validity message on the control, which makes it invalid. An empty message
+    /// makes it valid again.
+    pub fn set_custom_validity(&mut self, node_id: NodeId, message: &str) {
+        let mut doc = self.get_mut();
+        if message.is_empty() {
+            doc.form_controls.custom_validity.remove(&node_id);
+        } else {
+            doc.form_controls
+                .custom_validity
+                .insert(node_id, message.to_string());
+        }
+    }
+}
@@ -0,0 +1,491 @@
+//! Frozen documents
+//!
+//! Analysis that walks a document many times without changing it (crawlers extracting links and
+//! text, linters, diffing) does not need the mutable node arena: its nodes live in a hash map,
+//! and every node owns its name, namespace and attributes as separate allocations.
+//!
+//! `DocumentHandle::freeze()` copies the tree into a `FrozenDocument`, which stores the nodes in
+//! tree order, one column per property (struct of arrays). Names, namespaces and attribute names
+//! are interned, and the text of all text and comment nodes shares a single buffer. The
+//! descendants of a node directly follow it, so walking a subtree is a scan over a range of
+//! indexes. `thaw()` turns the frozen document into a mutable document again, with the same node
+//! IDs.
+//!
+//! Only the nodes that are connected to the root are frozen (including template contents, which
+//! are stored as the children of their template). The undo history, event listeners, observers
+//! and indexes are not part of a frozen document.
+use crate::html5::atoms::{Atom, AtomTable};
+use crate::html5::element_class::ElementClass;
+use crate::html5::node::arena::NodeArena;
+use crate::html5::node::data::comment::CommentData;
+use crate::html5::node::data::doctype::DocTypeData;
+use crate::html5::node::data::document::DocumentData;
+use crate::html5::node::data::element::ElementData;
+use crate::html5::node::data::text::TextData;
+use crate::html5::node::{Node, NodeData, NodeId, NodeTrait, NodeType, HTML_NAMESPACE};
+use crate::html5::parser::document::{Document, DocumentFragment, DocumentHandle, DocumentType};
+use crate::html5::parser::quirks::QuirksMode;
+use crate::html5::util::is_valid_id_attribute_value;
+use crate::net::origin::Origin;
+use std::collections::HashMap;
+use std::ops::Range;
+use url::Url;
+
+/// Marks a node ID that is not part of the frozen document
+const NOT_FROZEN: u32 = u32::MAX;
+
+/// Immutable, compact copy of a document
+#[derive(Debug, PartialEq)]
+pub struct FrozenDocument {
+    doctype: DocumentType,
+    quirks_mode: QuirksMode,
+    holds_fragment: bool,
+    url: Option<Url>,
+    base_element: Option<NodeId>,
+    frozen_base_url: Option<Url>,
+    base_target: Option<String>,
+    /// Next ID of the arena the document was frozen from
+    next_id: NodeId,
+    /// Node names, namespaces and attribute names
+    atoms: AtomTable,
+
+    // The columns below have one entry per node, in tree order. The root is at index 0.
+    ids: Vec<NodeId>,
+    types: Vec<NodeType>,
+    names: Vec<Atom>,
+    namespaces: Vec<Option<Atom>>,
+    parents: Vec<u32>,
+    /// Index after the last descendant of the node
+    subtree_ends: Vec<u32>,
+    /// Range in `text` of text and comment nodes (empty for other nodes)
+    text_ranges: Vec<(u32, u32)>,
+    /// Range in `attributes` of elements (empty for other nodes)
+    attribute_ranges: Vec<(u32, u32)>,
+
+    /// Text of all text and comment nodes
+    text: String,
+    /// Attributes of all elements, sorted by name per element
+    attributes: Vec<(Atom, Box<str>)>,
+    /// Data of the doctype nodes, by index
+    doctypes: Vec<(u32, DocTypeData)>,
+    /// Index of every node, by node ID
+    positions: Vec<u32>,
+}
+
+/// Node of a frozen document
+#[derive(Debug, Clone, Copy)]
+pub struct FrozenNode<'a> {
+    document: &'a FrozenDocument,
+    index: u32,
+}
+
+impl DocumentHandle {
+    /// Returns a frozen copy of the document
+    pub fn freeze(&self) -> FrozenDocument {
+        self.get().freeze()
+    }
+}
+
+impl Document {
+    /// Returns a frozen copy of the document
+    pub fn freeze(&self) -> FrozenDocument {
+        let mut frozen = FrozenDocument {
+            doctype: self.doctype,
+            quirks_mode: self.quirks_mode,
+            holds_fragment: self.holds_fragment,
+            url: self.url.clone(),
+            base_element: self.base_element,
+            frozen_base_url: self.frozen_base_url.clone(),
+            base_target: self.base_target.clone(),
+            next_id: self.arena.peek_next_id(),
+            atoms: AtomTable::default(),
+            ids: Vec::new(),
+            types: Vec::new(),
+            names: Vec::new(),
+            namespaces: Vec::new(),
+            parents: Vec::new(),
+            subtree_ends: Vec::new(),
+            text_ranges: Vec::new(),
+            attribute_ranges: Vec::new(),
+            text: String::new(),
+            attributes: Vec::new(),
+            doctypes: Vec::new(),
+            positions: vec![NOT_FROZEN; usize::from(self.arena.peek_next_id())],
+        };
+
+        // Nodes are added when they are first visited, and their subtree is closed when they are
+        // visited again after their descendants
+        let mut stack = vec![(NodeId::root(), NOT_FROZEN, false)];
+        while let Some((node_id, parent, closing)) = stack.pop() {
+            let Some(node) = self.get_node_by_id(node_id) else {
+                continue;
+            };
+            if closing {
+                let index = frozen.positions[usize::from(node_id)] as usize;
+                frozen.subtree_ends[index] = frozen.ids.len() as u32;
+                continue;
+            }
+
+            let index = frozen.push_node(node, parent);
+            stack.push((node_id, parent, true));
+            stack.extend(node.children.iter().rev().map(|&id| (id, index, false)));
+        }
+
+        frozen
+    }
+}
+
+impl FrozenDocument {
+    fn push_node(&mut self, node: &Node, parent: u32) -> u32 {
+        let index = self.ids.len() as u32;
+        self.positions[usize::from(node.id)] = index;
+        self.ids.push(node.id);
+        self.types.push(node.type_of());
+        self.names.push(self.atoms.intern(&node.name));
+        let namespace = node.namespace.as_ref().map(|ns| self.atoms.intern(ns));
+        self.namespaces.push(namespace);
+        self.parents.push(parent);
+        self.subtree_ends.push(index + 1);
+
+        let text_start = self.text.len() as u32;
+        let attributes_start = self.attributes.len() as u32;
+        match &node.data {
+            NodeData::Text(text) => self.text.push_str(text.value()),
+            NodeData::Comment(comment) => self.text.push_str(comment.value()),
+            NodeData::DocType(doctype) => self.doctypes.push((index, doctype.clone())),
+            NodeData::Element(element) => {
+                // Sorted before interning, so equal documents intern their names in the same order
+                let mut attributes: Vec<(&String, &String)> = element.attributes.iter().collect();
+                attributes.sort();
+                for (name, value) in attributes {
+                    let name = self.atoms.intern(name);
+                    self.attributes.push((name, value.as_str().into()));
+                }
+            }
+            NodeData::Document(_) => {}
+        }
+        self.text_ranges.push((text_start, self.text.len() as u32));
+        self.attribute_ranges
+            .push((attributes_start, self.attributes.len() as u32));
+
+        index
+    }
+
+    /// Returns the root node
+    pub fn root(&self) -> FrozenNode<'_> {
+        FrozenNode {
+            document: self,
+            index: 0,
+        }
+    }
+
+    /// Returns the node with the ID, or None when the node was not frozen
+    pub fn node(&self, node_id: NodeId) -> Option<FrozenNode<'_>> {
+        match self.positions.get(usize::from(node_id)) {
+            Some(&index) if index != NOT_FROZEN => Some(FrozenNode {
+                document: self,
+                index,
+            }),
+            _ => None,
+        }
+    }
+
+    /// Returns all nodes in tree order
+    pub fn nodes(&self) -> impl Iterator<Item = FrozenNode<'_>> {
+        self.range(0..self.ids.len() as u32)
+    }
+
+    /// Returns the elements with the (interned) name in tree order
+    pub fn elements_by_name(&self, name: Atom) -> impl Iterator<Item = FrozenNode<'_>> {
+        self.nodes().filter(move |node| {
+            node.node_type() == NodeType::Element && self.names[node.index as usize] == name
+        })
+    }
+
+    /// Returns the table of the interned names, namespaces and attribute names
+    pub fn atoms(&self) -> &AtomTable {
+        &self.atoms
+    }
+
+    /// Returns the number of nodes
+    pub fn len(&self) -> usize {
+        self.ids.len()
+    }
+
+    /// Returns true when the document has no nodes
+    pub fn is_empty(&self) -> bool {
+        self.ids.is_empty()
+    }
+
+    fn range(&self, range: Range<u32>) -> impl Iterator<Item = FrozenNode<'_>> {
+        range.map(move |index| FrozenNode {
+            document: self,
+            index,
+        })
+    }
+
+    /// Returns a mutable document with the nodes of the frozen document. The nodes keep their IDs.
+    pub fn thaw(&self) -> DocumentHandle {
+        let mut handle = Document::shared();
+        let mut nodes: Vec<Node> = Vec::with_capacity(self.len());
+        let mut named_id_elements = HashMap::new();
+        for node in self.nodes() {
+            let index = node.index as usize;
+            let data = match node.node_type() {
+                NodeType::Document => NodeData::Document(DocumentData::default()),
+                NodeType::DocType => NodeData::DocType(self.doctype_data(node.index)),
+                NodeType::Text => NodeData::Text(TextData::with_value(node.text().unwrap_or(""))),
+                NodeType::Comment => {
+                    NodeData::Comment(CommentData::with_value(node.text().unwrap_or("")))
+                }
+                NodeType::Element => {
+                    let attributes: HashMap<String, String> = node
+                        .attributes()
+                        .map(|(name, value)| (name.to_string(), value.to_string()))
+                        .collect();
+                    if let Some(id) = attributes.get("id") {
+                        if is_valid_id_attribute_value(id) {
+                            named_id_elements.entry(id.clone()).or_insert(node.id());
+                        }
+                    }
+                    let classes = attributes
+                        .get("class")
+                        .map_or_else(ElementClass::new, |class| ElementClass::from_string(class));
+                    let mut element =
+                        ElementData::with_name_and_attributes(node.id(), node.name(), attributes);
+                    element.classes = classes;
+                    if node.name() == "template" && node.namespace() == Some(HTML_NAMESPACE) {
+                        element.template_contents =
+                            Some(DocumentFragment::new(Document::clone(&handle), node.id()));
+                    }
+                    NodeData::Element(Box::new(element))
+                }
+            };
+            nodes.push(Node {
+                id: self.ids[index],
+                parent: node.parent().map(|parent| parent.id()),
+                children: node.children().map(|child| child.id()).collect(),
+                name: node.name().to_string(),
+                namespace: node.namespace().map(str::to_string),
+                data,
+                is_registered: true,
+                source_span: None,
+            });
+        }
+
+        {
+            let mut doc = handle.get_mut();
+            doc.doctype = self.doctype;
+            doc.quirks_mode = self.quirks_mode;
+            doc.holds_fragment = self.holds_fragment;
+            doc.origin = self
+                .url
+                .as_ref()
+                .map_or_else(Origin::new_opaque, Origin::from_url);
+            doc.url = self.url.clone();
+            doc.base_element = self.base_element.filter(|&id| self.node(id).is_some());
+            doc.frozen_base_url = self.frozen_base_url.clone();
+            doc.base_target = self.base_target.clone();
+            doc.arena = NodeArena::from_nodes(nodes, self.next_id);
+            doc.named_id_elements = named_id_elements;
+            doc.rebuild_derived_state();
+        }
+        handle
+    }
+
+    fn doctype_data(&self, index: u32) -> DocTypeData {
+        self.doctypes
+            .iter()
+            .find(|(doctype_index, _)| *doctype_index == index)
+            .map(|(_, data)| data.clone())
+            .unwrap_or_default()
+    }
+}
+
+impl<'a> FrozenNode<'a> {
+    /// Returns the ID of the node in the document it was frozen from
+    pub fn id(&self) -> NodeId {
+        self.document.ids[self.index as usize]
+    }
+
+    pub fn node_type(&self) -> NodeType {
+        self.document.types[self.index as usize]
+    }
+
+    /// Returns the name of the node (empty for text and comment nodes)
+    pub fn name(&self) -> &'a str {
+        self.document
+            .atoms
+            .resolve(self.name_atom())
+            .unwrap_or_default()
+    }
+
+    /// Returns the interned name of the node
+    pub fn name_atom(&self) -> Atom {
+        self.document.names[self.index as usize]
+    }
+
+    pub fn namespace(&self) -> Option<&'a str> {
+        let namespace = self.document.namespaces[self.index as usize]?;
+        self.document.atoms.resolve(namespace)
+    }
+
+    /// Returns the text of text and comment nodes
+    pub fn text(&self) -> Option<&'a str> {
+        match self.node_type() {
+            NodeType::Text | NodeType::Comment => {
+                let (start, end) = self.document.text_ranges[self.index as usize];
+                Some(&self.document.text[start as usize..end as usize])
+            }
+            _ => None,
+        }
+    }
+
+    /// Returns the attributes of an element, sorted by name
+    pub fn attributes(&self) -> impl Iterator<Item = (&'a str, &'a str)> {
+        let document = self.document;
+        let (start, end) = document.attribute_ranges[self.index as usize];
+        document.attributes[start as usize..end as usize]
+            .iter()
+            .map(|(name, value)| {
+                (
+                    document.atoms.resolve(*name).unwrap_or_default(),
+                    value.as_ref(),
+                )
+            })
+    }
+
+    /// Returns the value of the attribute of an element
+    pub fn attribute(&self, name: &str) -> Option<&'a str> {
+        self.attributes()
+            .find(|(attribute, _)| *attribute == name)
+            .map(|(_, value)| value)
+    }
+
+    pub fn parent(&self) -> Option<FrozenNode<'a>> {
+        match self.document.parents[self.index as usize] {
+            NOT_FROZEN => None,
+            index => Some(FrozenNode {
+                document: self.document,
+                index,
+            }),
+        }
+    }
+
+    pub fn children(&self) -> impl Iterator<Item = FrozenNode<'a>> {
+        let document = self.document;
+        let end = document.subtree_ends[self.index as usize];
+        let mut next = self.index + 1;
+        std::iter::from_fn(move || {
+            if next >= end {
+                return None;
+            }
+            let index = next;
+            next = document.subtree_ends[index as usize];
+            Some(FrozenNode { document, index })
+        })
+    }
+
+    /// Returns the descendants of the node (not including the node itself) in tree order
+    pub fn descendants(&self) -> impl Iterator<Item = FrozenNode<'a>> {
+        let end = self.document.subtree_ends[self.index as usize];
+        self.document.range(self.index + 1..end)
+    }
+
+    /// Returns the text of all descendant text nodes in tree order
+    pub fn text_content(&self) -> String {
+        self.descendants()
+            .filter(|node| node.node_type() == NodeType::Text)
+            .filter_map(|node| node.text())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::html5::parser::tree_builder::TreeBuilder;
+    use crate::html5::serializer::{serialize, SerializerOptions};
+    use crate::testing::parse;
+
+    fn html(document: &DocumentHandle) -> String {
+        serialize(
+            &document.get(),
+            NodeId::root(),
+            &SerializerOptions::default(),
+        )
+    }
+
+    #[test]
+    fn traversal() {
+        let document = parse(
+            "<!DOCTYPE html><ul id=list><li class=a>one</li><!-- c --><li title=t>two</li></ul>",
+        );
+        let frozen = document.freeze();
+        let list_id = document.get().get_node_by_named_id("list").unwrap().id;
+
+        let list = frozen.node(list_id).unwrap();
+        assert_eq!(list.name(), "ul");
+        assert_eq!(list.attribute("id"), Some("list"));
+        assert_eq!(list.parent().unwrap().name(), "body");
+        assert_eq!(list.text_content(), "onetwo");
+        let children: Vec<NodeType> = list.children().map(|child| child.node_type()).collect();
+        assert_eq!(
+            children,
+            [NodeType::Element, NodeType::Comment, NodeType::Element]
+        );
+        assert_eq!(list.children().nth(1).unwrap().text(), Some(" c "));
+        assert_eq!(list.descendants().count(), 5);
+
+        let li = frozen.atoms().get("li").unwrap();
+        let titles: Vec<Option<&str>> = frozen
+            .elements_by_name(li)
+            .map(|node| node.attribute("title"))
+            .collect();
+        assert_eq!(titles, [None, Some("t")]);
+
+        assert_eq!(frozen.root().id(), NodeId::root());
+        assert_eq!(frozen.root().descendants().count(), frozen.len() - 1);
+        assert!(frozen.node(NodeId::from(999)).is_none());
+    }
+
+    #[test]
+    fn thaw() {
+        let mut document = parse(
+            "<!DOCTYPE html><p id=a class='x y'>text</p><p>other</p><template id=t>c</template>",
+        );
+        let a = document.get().get_node_by_named_id("a").unwrap().id;
+        let detached = document.query_selector("p:not(#a)").unwrap().unwrap();
+        document.detach_node_from_parent(detached);
+        let frozen = document.freeze();
+        assert!(frozen.node(detached).is_none());
+
+        let mut thawed = frozen.thaw();
+        assert_eq!(html(&thawed), html(&document));
+        assert_eq!(thawed.get().get_node_by_named_id("a").unwrap().id, a);
+        assert_eq!(thawed.query_selector(".y").unwrap(), Some(a));
+        assert!(thawed.get().get_node_by_id(detached).is_none());
+        let template = thawed.get().get_node_by_named_id("t").unwrap().id;
+        assert_eq!(
+            thawed
+                .get()
+                .template_content(template)
+                .unwrap()
+                .children()
+                .len(),
+            1
+        );
+
+        // The thawed document can be changed, and freezing it again gives the same result
+        assert_eq!(thawed.freeze(), frozen);
+        thawed.insert_attribute("title", "t", a).unwrap();
+        assert_eq!(
+            thawed.freeze().node(a).unwrap().attribute("title"),
+            Some("t")
+        );
+        assert!(thawed
+            .get()
+            .get_node_by_id(thawed.get().arena.peek_next_id())
+            .is_none());
+    }
+}
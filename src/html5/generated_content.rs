@@ -0,0 +1,881 @@
+//! Generated content of the `::before` and `::after` pseudo-elements
+//!
+//! An element gets a `::before` box as its first child and an `::after` box as its last child when
+//! a rule for the pseudo-element sets `content` to something else than `normal` or `none`. The text
+//! of the box is made of the items of the `content` value: strings, attribute values of the
+//! element (`attr()`), counters (`counter()` and `counters()`) and quotes.
+//!
+//! Counters are created by `counter-reset`, and changed by `counter-increment` and `counter-set`.
+//! A counter created on an element is in scope for the element, its descendants and its following
+//! siblings (and their descendants). A reset on a descendant creates a nested counter that hides
+//! the outer one; `counters()` joins the values of all nested counters. Lists reset the
+//! `list-item` counter, and every list item sets it to its ordinal value (see `lists`).
+//!
+//! There is no style engine yet, so the rules come from a `StyleSheet` that only knows about the
+//! properties of generated content. The declarations of the matching rules apply in source order,
+//! followed by the declarations of the element itself (see `element_style`). Specificity is not
+//! taken into account.
+//!
+//! See: https://drafts.csswg.org/css-content-3/ and https://drafts.csswg.org/css-lists-3/#auto-numbering
+use crate::html5::element_style::{parse_declarations, split_outside_blocks, StyleDeclaration};
+use crate::html5::lists::ListStyleType;
+use crate::html5::node::{Node, NodeData, NodeId, HTML_NAMESPACE};
+use crate::html5::parser::document::Document;
+use crate::html5::selector::Selector;
+use crate::types::{Error, Result};
+use std::collections::HashMap;
+
+/// Elements that have no pseudo-elements, because their contents are not rendered as children
+const REPLACED_ELEMENTS: [&str; 11] = [
+    "audio", "br", "canvas", "embed", "iframe", "img", "input", "object", "select", "textarea",
+    "video",
+];
+
+/// Quotes used by `open-quote` and `close-quote`, by nesting level. Deeper levels use the last
+/// pair.
+const QUOTES: [(&str, &str); 2] = [("\u{201c}", "\u{201d}"), ("\u{2018}", "\u{2019}")];
+
+/// The pseudo-elements that hold generated content
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PseudoElement {
+    Before,
+    After,
+}
+
+impl PseudoElement {
+    /// Returns the pseudo-element for the name (without colons), or None for other names
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "before" => Some(Self::Before),
+            "after" => Some(Self::After),
+            _ => None,
+        }
+    }
+}
+
+/// An item of a `content` value
+#[derive(Debug, Clone, PartialEq)]
+pub enum ContentItem {
+    /// A quoted string
+    String(String),
+    /// `attr(name)`: the value of the attribute of the element, or nothing when it is not set
+    Attr(String),
+    /// `counter(name, style)`: the value of the innermost counter
+    Counter {
+        name: String,
+        style: ListStyleType,
+    },
+    /// `counters(name, separator, style)`: the values of all counters with the name in scope,
+    /// from the outermost to the innermost
+    Counters {
+        name: String,
+        separator: String,
+        style: ListStyleType,
+    },
+    OpenQuote,
+    CloseQuote,
+    /// Increases the quote nesting level without adding a quote
+    NoOpenQuote,
+    /// Decreases the quote nesting level without adding a quote
+    NoCloseQuote,
+}
+
+/// A parsed `content` value
+#[derive(Debug, Clone, PartialEq)]
+pub enum Content {
+    /// No generated content for `::before` and `::after`
+    Normal,
+    None,
+    Items(Vec<ContentItem>),
+}
+
+impl Content {
+    /// Parses a `content` value. The alternative text after a `/` is ignored.
+    pub fn parse(value: &str) -> Result<Content> {
+        let value = split_outside_blocks(value, '/')[0].trim();
+        match value.to_ascii_lowercase().as_str() {
+            "normal" => return Ok(Content::Normal),
+            "none" => return Ok(Content::None),
+            _ => {}
+        }
+
+        let mut parser = ValueParser {
+            input: value.chars().collect(),
+            pos: 0,
+        };
+        let mut items = Vec::new();
+        loop {
+            parser.skip_whitespace();
+            match parser.peek() {
+                None => break,
+                Some('"' | '\'') => items.push(ContentItem::String(parser.string()?)),
+                Some(_) => {
+                    let name = parser.ident()?.to_ascii_lowercase();
+                    if parser.peek() == Some('(') {
+                        parser.pos += 1;
+                        items.push(function_item(&name, &parser.arguments()?)?);
+                        continue;
+                    }
+                    items.push(match name.as_str() {
+                        "open-quote" => ContentItem::OpenQuote,
+                        "close-quote" => ContentItem::CloseQuote,
+                        "no-open-quote" => ContentItem::NoOpenQuote,
+                        "no-close-quote" => ContentItem::NoCloseQuote,
+                        _ => return Err(Error::Parse(format!("unexpected '{name}' in content"))),
+                    });
+                }
+            }
+        }
+
+        if items.is_empty() {
+            return Err(Error::Parse("empty content value".to_string()));
+        }
+        Ok(Content::Items(items))
+    }
+}
+
+/// Returns the content item of the `attr()`, `counter()` or `counters()` function
+fn function_item(name: &str, arguments: &[String]) -> Result<ContentItem> {
+    let style = |argument: Option<&String>| {
+        argument.map_or(ListStyleType::Decimal, |style| {
+            ListStyleType::from_keyword(style).unwrap_or(ListStyleType::Decimal)
+        })
+    };
+    let is_ident = |argument: &str| {
+        !argument.is_empty() && !argument.starts_with(['"', '\'']) && !argument.contains(' ')
+    };
+
+    match (name, arguments) {
+        ("attr", [attribute]) if is_ident(attribute) => {
+            Ok(ContentItem::Attr(attribute.to_ascii_lowercase()))
+        }
+        ("counter", [counter] | [counter, _]) if is_ident(counter) => Ok(ContentItem::Counter {
+            name: counter.clone(),
+            style: style(arguments.get(1)),
+        }),
+        ("counters", [counter, separator] | [counter, separator, _])
+            if is_ident(counter) && !is_ident(separator) =>
+        {
+            let mut parser = ValueParser {
+                input: separator.chars().collect(),
+                pos: 0,
+            };
+            Ok(ContentItem::Counters {
+                name: counter.clone(),
+                separator: parser.string()?,
+                style: style(arguments.get(2)),
+            })
+        }
+        _ => Err(Error::Parse(format!("invalid {name}() in content"))),
+    }
+}
+
+/// Reads the tokens of a `content` value
+struct ValueParser {
+    input: Vec<char>,
+    pos: usize,
+}
+
+impl ValueParser {
+    fn peek(&self) -> Option<char> {
+        self.input.get(self.pos).copied()
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.peek().is_some_and(|c| c.is_whitespace()) {
+            self.pos += 1;
+        }
+    }
+
+    fn ident(&mut self) -> Result<String> {
+        let start = self.pos;
+        while self
+            .peek()
+            .is_some_and(|c| c.is_alphanumeric() || c == '-' || c == '_' || !c.is_ascii())
+        {
+            self.pos += 1;
+        }
+        if self.pos == start {
+            return Err(Error::Parse(format!(
+                "unexpected character '{}' in content",
+                self.peek().unwrap_or_default()
+            )));
+        }
+        Ok(self.input[start..self.pos].iter().collect())
+    }
+
+    /// Reads a quoted string, with escapes like `\"` and `\201C`
+    fn string(&mut self) -> Result<String> {
+        let Some(quote) = self.peek().filter(|c| matches!(c, '"' | '\'')) else {
+            return Err(Error::Parse("expected a string in content".to_string()));
+        };
+        self.pos += 1;
+
+        let mut value = String::new();
+        loop {
+            let Some(c) = self.peek() else {
+                return Err(Error::Parse("unterminated string in content".to_string()));
+            };
+            self.pos += 1;
+            match c {
+                c if c == quote => return Ok(value),
+                '\\' => value.extend(self.escape()),
+                c => value.push(c),
+            }
+        }
+    }
+
+    /// Reads the escape after a backslash. An escaped newline is removed.
+    fn escape(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.pos += 1;
+        if !c.is_ascii_hexdigit() {
+            return (c != '\n').then_some(c);
+        }
+
+        let mut digits = c.to_string();
+        while digits.len() < 6 && self.peek().is_some_and(|c| c.is_ascii_hexdigit()) {
+            digits.extend(self.peek());
+            self.pos += 1;
+        }
+        if self.peek().is_some_and(|c| c.is_whitespace()) {
+            self.pos += 1;
+        }
+        let code = u32::from_str_radix(&digits, 16).unwrap_or_default();
+        Some(
+            char::from_u32(code)
+                .filter(|&c| c != '\0')
+                .unwrap_or('\u{fffd}'),
+        )
+    }
+
+    /// Reads the comma separated arguments of a function, up to and including the closing
+    /// parenthesis
+    fn arguments(&mut self) -> Result<Vec<String>> {
+        let start = self.pos;
+        let mut quote = None;
+        while let Some(c) = self.peek() {
+            self.pos += 1;
+            match (quote, c) {
+                (Some(_), '\\') => self.pos += 1,
+                (Some(q), c) if c == q => quote = None,
+                (Some(_), _) => {}
+                (None, '"' | '\'') => quote = Some(c),
+                (None, ')') => {
+                    let arguments: String = self.input[start..self.pos - 1].iter().collect();
+                    return Ok(split_outside_blocks(&arguments, ',')
+                        .into_iter()
+                        .map(|argument| argument.trim().to_string())
+                        .collect());
+                }
+                _ => {}
+            }
+        }
+        Err(Error::Parse("unterminated function in content".to_string()))
+    }
+}
+
+/// A style rule for elements or one of their pseudo-elements
+#[derive(Debug, Clone)]
+struct StyleRule {
+    selector: Selector,
+    pseudo: Option<PseudoElement>,
+    declarations: Vec<StyleDeclaration>,
+}
+
+/// The style rules of a style sheet. Rules with unsupported selectors and at-rules are skipped.
+#[derive(Debug, Clone, Default)]
+pub struct StyleSheet {
+    rules: Vec<StyleRule>,
+}
+
+impl StyleSheet {
+    /// Parses the rules of the style sheet
+    pub fn parse(css: &str) -> StyleSheet {
+        let css = strip_comments(css);
+        let mut rules = Vec::new();
+
+        let mut rest = css.as_str();
+        while let Some(open) = rest.find(['{', ';']) {
+            let prelude = rest[..open].trim();
+            if rest[open..].starts_with(';') {
+                // Statement at-rule, like @import
+                rest = &rest[open + 1..];
+                continue;
+            }
+            let block = &rest[open..open + block_length(&rest[open..])];
+            let body = block[1..].strip_suffix('}').unwrap_or(&block[1..]);
+            rest = &rest[open + block.len()..];
+            if prelude.starts_with('@') {
+                continue;
+            }
+
+            let declarations = parse_declarations(body);
+            let Some(selectors) = split_outside_blocks(prelude, ',')
+                .into_iter()
+                .map(parse_rule_selector)
+                .collect::<Option<Vec<_>>>()
+            else {
+                continue;
+            };
+            for (selector, pseudo) in selectors {
+                rules.push(StyleRule {
+                    selector,
+                    pseudo,
+                    declarations: declarations.clone(),
+                });
+            }
+        }
+
+        StyleSheet { rules }
+    }
+
+    /// Returns the style sheet made of the contents of the `style` elements of the document, in
+    /// tree order
+    pub fn from_document(document: &Document) -> StyleSheet {
+        StyleSheet::parse(&style_element_css(document))
+    }
+
+    /// Returns the declarations of the rules that match the element (or its pseudo-element), in
+    /// source order
+    pub fn declarations(
+        &self,
+        document: &Document,
+        node_id: NodeId,
+        pseudo: Option<PseudoElement>,
+    ) -> Vec<StyleDeclaration> {
+        self.rules
+            .iter()
+            .filter(|rule| rule.pseudo == pseudo && rule.selector.matches(document, node_id))
+            .flat_map(|rule| rule.declarations.iter().cloned())
+            .collect()
+    }
+}
+
+/// Returns the contents of the `style` elements of the document, in tree order
+pub(crate) fn style_element_css(document: &Document) -> String {
+    let css: Vec<String> = document
+        .elements_by_tag_name("style")
+        .into_iter()
+        .filter(|&node_id| {
+            document
+                .get_node_by_id(node_id)
+                .is_some_and(|node| node.namespace.as_deref() == Some(HTML_NAMESPACE))
+        })
+        .map(|node_id| document.text_content(node_id))
+        .collect();
+    css.join("\n")
+}
+
+/// Removes the `/* */` comments of the style sheet
+pub(crate) fn strip_comments(css: &str) -> String {
+    let mut output = String::with_capacity(css.len());
+    let mut rest = css;
+    while let Some(start) = rest.find("/*") {
+        output.push_str(&rest[..start]);
+        rest = rest[start + 2..]
+            .find("*/")
+            .map_or("", |end| &rest[start + 2 + end + 2..]);
+    }
+    output.push_str(rest);
+    output
+}
+
+/// Returns the length of the block at the start of the input, including its braces. Unclosed
+/// blocks run to the end of the input.
+pub(crate) fn block_length(input: &str) -> usize {
+    let mut depth = 0usize;
+    let mut quote = None;
+    let mut chars = input.char_indices();
+    while let Some((index, c)) = chars.next() {
+        match (quote, c) {
+            (Some(_), '\\') => {
+                chars.next();
+            }
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), _) => {}
+            (None, '"' | '\'') => quote = Some(c),
+            (None, '{') => depth += 1,
+            (None, '}') => {
+                depth -= 1;
+                if depth == 0 {
+                    return index + 1;
+                }
+            }
+            _ => {}
+        }
+    }
+    input.len()
+}
+
+/// Parses a selector of a rule, which may end with a pseudo-element (`::before`, or `:before` in
+/// the legacy syntax)
+fn parse_rule_selector(selector: &str) -> Option<(Selector, Option<PseudoElement>)> {
+    let selector = selector.trim();
+    let mut pseudo = None;
+    let mut rest = selector;
+    if let Some(index) = selector.rfind(':') {
+        if let Some(pseudo_element) = PseudoElement::from_name(&selector[index + 1..]) {
+            pseudo = Some(pseudo_element);
+            rest = selector[..index]
+                .strip_suffix(':')
+                .unwrap_or(&selector[..index]);
+        }
+    }
+
+    let rest = rest.trim_end();
+    // A pseudo-element without compound selector applies to all elements
+    let rest = if rest.is_empty() || rest.ends_with([' ', '>', '+', '~']) {
+        format!("{rest}*")
+    } else {
+        rest.to_string()
+    };
+    Selector::parse(&rest)
+        .ok()
+        .map(|selector| (selector, pseudo))
+}
+
+/// Returns the values of the property, from the lowest to the highest priority: the normal
+/// declarations in order, followed by the important ones
+fn cascaded_values<'a>(declarations: &'a [StyleDeclaration], property: &str) -> Vec<&'a str> {
+    let matching = declarations
+        .iter()
+        .filter(|declaration| declaration.property == property);
+    let (important, normal): (Vec<_>, Vec<_>) =
+        matching.partition(|declaration| declaration.important);
+    normal
+        .into_iter()
+        .chain(important)
+        .map(|declaration| declaration.value.as_str())
+        .collect()
+}
+
+/// Returns the winning value of the property that is valid according to the parse function
+fn cascaded<T>(
+    declarations: &[StyleDeclaration],
+    property: &str,
+    parse: impl Fn(&str) -> Option<T>,
+) -> Option<T> {
+    cascaded_values(declarations, property)
+        .into_iter()
+        .rev()
+        .find_map(parse)
+}
+
+/// Parses a `counter-reset`, `counter-increment` or `counter-set` value: counter names, each
+/// optionally followed by an integer
+fn parse_counter_changes(value: &str, default: i64) -> Option<Vec<(String, i64)>> {
+    if value.trim().eq_ignore_ascii_case("none") {
+        return Some(Vec::new());
+    }
+
+    let mut changes: Vec<(String, i64)> = Vec::new();
+    for word in value.split_ascii_whitespace() {
+        match (word.parse::<i64>(), changes.last_mut()) {
+            (Ok(number), Some((_, value))) => *value = number,
+            (Ok(_), None) => return None,
+            (Err(_), _) => changes.push((word.to_string(), default)),
+        }
+    }
+    (!changes.is_empty()).then_some(changes)
+}
+
+fn is_display_none(declarations: &[StyleDeclaration]) -> bool {
+    cascaded(declarations, "display", |value| {
+        Some(value.trim().to_ascii_lowercase())
+    })
+    .is_some_and(|display| display == "none")
+}
+
+/// A generated `::before` or `::after` box
+#[derive(Debug, Clone, PartialEq)]
+pub struct GeneratedBox {
+    /// The element that the pseudo-element belongs to
+    pub element: NodeId,
+    pub pseudo: PseudoElement,
+    /// The text of the box
+    pub text: String,
+}
+
+/// A counter instance. The counter goes out of scope when the element that it belongs to is left.
+#[derive(Debug, Clone)]
+struct Counter {
+    name: String,
+    value: i64,
+    scope: NodeId,
+}
+
+/// Walks the document in tree order, keeping the counters and quote depth up to date
+struct Generator<'a> {
+    document: &'a Document,
+    sheet: &'a StyleSheet,
+    counters: Vec<Counter>,
+    quote_depth: usize,
+    boxes: Vec<GeneratedBox>,
+    hidden: Vec<NodeId>,
+}
+
+enum Visit {
+    Enter(NodeId),
+    Exit(NodeId),
+}
+
+impl Generator<'_> {
+    fn run(&mut self) {
+        let mut stack = vec![Visit::Enter(NodeId::root())];
+        while let Some(visit) = stack.pop() {
+            match visit {
+                Visit::Enter(node_id) => {
+                    let Some(node) = self.document.get_node_by_id(node_id) else {
+                        continue;
+                    };
+                    match node.data {
+                        NodeData::Element(_) => {
+                            if !self.enter_element(node) {
+                                self.hidden.push(node_id);
+                                continue;
+                            }
+                        }
+                        NodeData::Document(_) => {}
+                        _ => continue,
+                    }
+                    stack.push(Visit::Exit(node_id));
+                    stack.extend(node.children.iter().rev().map(|&id| Visit::Enter(id)));
+                }
+                Visit::Exit(node_id) => {
+                    if let Some(node) = self.document.get_node_by_id(node_id) {
+                        self.generate(node, PseudoElement::After);
+                    }
+                    self.counters.retain(|counter| counter.scope != node_id);
+                }
+            }
+        }
+    }
+
+    /// Applies the counters of the element and generates its `::before` box. Returns false when
+    /// the element is not displayed.
+    fn enter_element(&mut self, node: &Node) -> bool {
+        let mut declarations = self.sheet.declarations(self.document, node.id, None);
+        declarations.extend(self.document.declared_style(node.id));
+        if is_display_none(&declarations) {
+            return false;
+        }
+
+        let scope = node.parent.unwrap_or_default();
+        let is_html = node.namespace.as_deref() == Some(HTML_NAMESPACE);
+        if is_html && matches!(node.name.as_str(), "ol" | "ul" | "menu") {
+            self.reset("list-item", 0, scope);
+        }
+        self.apply_counters(&declarations, scope);
+        if let Some(ordinal) = self.document.list_item_ordinal(node.id) {
+            self.set("list-item", ordinal, scope);
+        }
+
+        self.generate(node, PseudoElement::Before);
+        true
+    }
+
+    fn apply_counters(&mut self, declarations: &[StyleDeclaration], scope: NodeId) {
+        let changes = |property, default| {
+            cascaded(declarations, property, |value| {
+                parse_counter_changes(value, default)
+            })
+            .unwrap_or_default()
+        };
+        for (name, value) in changes("counter-reset", 0) {
+            self.reset(&name, value, scope);
+        }
+        for (name, value) in changes("counter-increment", 1) {
+            let current = self.innermost(&name, scope).value;
+            self.innermost(&name, scope).value = current.saturating_add(value);
+        }
+        for (name, value) in changes("counter-set", 0) {
+            self.set(&name, value, scope);
+        }
+    }
+
+    /// Creates a new counter. It replaces a counter with the same name that was created by a
+    /// previous sibling.
+    fn reset(&mut self, name: &str, value: i64, scope: NodeId) {
+        if let Some(index) = self
+            .counters
+            .iter()
+            .rposition(|counter| counter.name == name)
+        {
+            if self.counters[index].scope == scope {
+                self.counters.remove(index);
+            }
+        }
+        self.counters.push(Counter {
+            name: name.to_string(),
+            value,
+            scope,
+        });
+    }
+
+    fn set(&mut self, name: &str, value: i64, scope: NodeId) {
+        self.innermost(name, scope).value = value;
+    }
+
+    /// Returns the innermost counter with the name, which is created (with value 0) when there is
+    /// none
+    fn innermost(&mut self, name: &str, scope: NodeId) -> &mut Counter {
+        match self
+            .counters
+            .iter()
+            .rposition(|counter| counter.name == name)
+        {
+            Some(index) => &mut self.counters[index],
+            None => {
+                self.reset(name, 0, scope);
+                self.counters.last_mut().expect("counter was just created")
+            }
+        }
+    }
+
+    /// Generates the box of the pseudo-element of the element, when it has content
+    fn generate(&mut self, node: &Node, pseudo: PseudoElement) {
+        let NodeData::Element(element) = &node.data else {
+            return;
+        };
+        if node.namespace.as_deref() == Some(HTML_NAMESPACE)
+            && REPLACED_ELEMENTS.contains(&node.name.as_str())
+        {
+            return;
+        }
+
+        let declarations = self
+            .sheet
+            .declarations(self.document, node.id, Some(pseudo));
+        let Some(Content::Items(items)) =
+            cascaded(&declarations, "content", |value| Content::parse(value).ok())
+        else {
+            return;
+        };
+        if is_display_none(&declarations) {
+            return;
+        }
+
+        // The pseudo-element is a child of the element
+        self.apply_counters(&declarations, node.id);
+
+        let mut text = String::new();
+        for item in items {
+            match item {
+                ContentItem::String(value) => text.push_str(&value),
+                ContentItem::Attr(name) => {
+                    text.push_str(element.attributes.get(&name).map_or("", String::as_str));
+                }
+                ContentItem::Counter { name, style } => {
+                    let value = self.innermost(&name, node.id).value;
+                    text.push_str(&style.representation(value));
+                }
+                ContentItem::Counters {
+                    name,
+                    separator,
+                    style,
+                } => {
+                    self.innermost(&name, node.id);
+                    let values: Vec<String> = self
+                        .counters
+                        .iter()
+                        .filter(|counter| counter.name == name)
+                        .map(|counter| style.representation(counter.value))
+                        .collect();
+                    text.push_str(&values.join(&separator));
+                }
+                ContentItem::OpenQuote => {
+                    text.push_str(QUOTES[self.quote_depth.min(QUOTES.len() - 1)].0);
+                    self.quote_depth += 1;
+                }
+                ContentItem::CloseQuote => {
+                    if self.quote_depth > 0 {
+                        self.quote_depth -= 1;
+                        text.push_str(QUOTES[self.quote_depth.min(QUOTES.len() - 1)].1);
+                    }
+                }
+                ContentItem::NoOpenQuote => self.quote_depth += 1,
+                ContentItem::NoCloseQuote => {
+                    self.quote_depth = self.quote_depth.saturating_sub(1);
+                }
+            }
+        }
+
+        self.boxes.push(GeneratedBox {
+            element: node.id,
+            pseudo,
+            text,
+        });
+    }
+}
+
+impl Document {
+    /// Returns the generated `::before` and `::after` boxes of the elements of the document that
+    /// are displayed, in tree order
+    pub fn generated_boxes(&self, sheet: &StyleSheet) -> Vec<GeneratedBox> {
+        self.generate_content(sheet).0
+    }
+
+    /// Returns the text of the pseudo-element of the element, or None when it has no box
+    pub fn pseudo_element_text(
+        &self,
+        sheet: &StyleSheet,
+        node_id: NodeId,
+        pseudo: PseudoElement,
+    ) -> Option<String> {
+        self.generated_boxes(sheet)
+            .into_iter()
+            .find(|generated| generated.element == node_id && generated.pseudo == pseudo)
+            .map(|generated| generated.text)
+    }
+
+    /// Returns the text of the node like `text_content()`, with the text of the generated boxes
+    /// around the contents of the elements. Elements that are not displayed are left out.
+    pub fn text_content_with_generated(&self, sheet: &StyleSheet, node_id: NodeId) -> String {
+        let (boxes, hidden) = self.generate_content(sheet);
+        let generated: HashMap<(NodeId, PseudoElement), String> = boxes
+            .into_iter()
+            .map(|generated| ((generated.element, generated.pseudo), generated.text))
+            .collect();
+        let text_of =
+            |node_id, pseudo| generated.get(&(node_id, pseudo)).map_or("", String::as_str);
+
+        let mut text = String::new();
+        let mut stack = vec![Visit::Enter(node_id)];
+        while let Some(visit) = stack.pop() {
+            match visit {
+                Visit::Enter(node_id) => {
+                    let Some(node) = self.get_node_by_id(node_id) else {
+                        continue;
+                    };
+                    if hidden.contains(&node_id) {
+                        continue;
+                    }
+                    if let NodeData::Text(text_data) = &node.data {
+                        text.push_str(text_data.value());
+                    }
+                    text.push_str(text_of(node_id, PseudoElement::Before));
+                    stack.push(Visit::Exit(node_id));
+                    stack.extend(node.children.iter().rev().map(|&id| Visit::Enter(id)));
+                }
+                Visit::Exit(node_id) => text.push_str(text_of(node_id, PseudoElement::After)),
+            }
+        }
+
+        text
+    }
+
+    /// Returns the generated boxes, and the elements that are not displayed
+    fn generate_content(&self, sheet: &StyleSheet) -> (Vec<GeneratedBox>, Vec<NodeId>) {
+        let mut generator = Generator {
+            document: self,
+            sheet,
+            counters: Vec::new(),
+            quote_depth: 0,
+            boxes: Vec::new(),
+            hidden: Vec::new(),
+        };
+        generator.run();
+        (generator.boxes, generator.hidden)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::parse;
+
+    #[test]
+    fn parse_content() {
+        assert_eq!(Content::parse("normal").unwrap(), Content::Normal);
+        assert_eq!(Content::parse(" NONE ").unwrap(), Content::None);
+        assert_eq!(
+            Content::parse(
+                r#""\201C a\"" attr(Title) counter(c, upper-roman) counters(c, '.') open-quote / "alt""#
+            )
+            .unwrap(),
+            Content::Items(vec![
+                ContentItem::String("\u{201c}a\"".to_string()),
+                ContentItem::Attr("title".to_string()),
+                ContentItem::Counter {
+                    name: "c".to_string(),
+                    style: ListStyleType::UpperRoman,
+                },
+                ContentItem::Counters {
+                    name: "c".to_string(),
+                    separator: ".".to_string(),
+                    style: ListStyleType::Decimal,
+                },
+                ContentItem::OpenQuote,
+            ])
+        );
+
+        assert!(Content::parse("counter()").is_err());
+        assert!(Content::parse("counters(c)").is_err());
+        assert!(Content::parse(r#""unterminated"#).is_err());
+        assert!(Content::parse("foo").is_err());
+        assert!(Content::parse("").is_err());
+    }
+
+    #[test]
+    fn generated_content() {
+        let document = parse(
+            r#"<style>
+            /* Section numbers */
+            body { counter-reset: section }
+            h2 { counter-increment: section }
+            h2::before { content: counter(section) ". " }
+            a[href]:after { content: " (" attr(href) ")" }
+            q::before { content: open-quote } q::after { content: close-quote }
+            @media print { h2::before { content: none } }
+            .hidden { display: none }
+            p::before, img::before { content: "" }
+            </style>
+            <h2>One</h2><h2 class=hidden>Hidden</h2><h2>Two</h2>
+            <p><a href="/x">link</a> <q>a <q>b</q></q><img></p>"#,
+        );
+        let doc = document.get();
+        let sheet = StyleSheet::from_document(&doc);
+        let body = doc.elements_by_tag_name("body")[0];
+
+        assert_eq!(
+            doc.text_content_with_generated(&sheet, body).trim(),
+            "1. One2. Two\n            link (/x) \u{201c}a \u{2018}b\u{2019}\u{201d}"
+        );
+
+        // Empty content still generates a box, but replaced elements have no pseudo-elements
+        let p = doc.elements_by_tag_name("p")[0];
+        assert_eq!(
+            doc.pseudo_element_text(&sheet, p, PseudoElement::Before),
+            Some(String::new())
+        );
+        let img = doc.elements_by_tag_name("img")[0];
+        assert!(doc
+            .pseudo_element_text(&sheet, img, PseudoElement::Before)
+            .is_none());
+        let h2 = doc.elements_by_tag_name("h2")[1];
+        assert!(doc
+            .pseudo_element_text(&sheet, h2, PseudoElement::Before)
+            .is_none());
+    }
+
+    #[test]
+    fn nested_counters() {
+        let document = parse(
+            "<ol><li>a<li>b<ol><li>c<li>d</ol><li>e</ol>\
+             <ol class=x start=3 reversed><li>f<li>g</ol>",
+        );
+        let sheet = StyleSheet::parse(
+            "ol { counter-reset: item } li { counter-increment: item }
+             li::before { content: counters(item, '.') ' ' }
+             .x li::before { content: counter(list-item, lower-alpha) ') ' }",
+        );
+        let doc = document.get();
+        let texts: Vec<String> = doc
+            .generated_boxes(&sheet)
+            .into_iter()
+            .map(|generated| generated.text)
+            .collect();
+        assert_eq!(texts, ["1 ", "2 ", "2.1 ", "2.2 ", "3 ", "c) ", "b) "]);
+    }
+}
@@ -0,0 +1,445 @@
+//! Layout geometry
+//!
+//! The engine does not lay out documents itself: the embedder does, and reports the result
+//! through `LayoutGeometry`. The observers that depend on layout (intersections, sizes) ask it for
+//! the boxes of the elements, and so do the element geometry APIs (`bounding_client_rect()`,
+//! `client_width()`, `offset_parent()`, ...). All rectangles are in CSS pixels, relative to the
+//! viewport.
+use crate::html5::node::{NodeData, NodeId, HTML_NAMESPACE};
+use crate::html5::parser::document::Document;
+use crate::html5::stacking::PaintStyle;
+use crate::html5::transform::Matrix;
+use core::fmt;
+use core::fmt::Debug;
+use std::rc::Rc;
+
+/// A rectangle in CSS pixels
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Rect {
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+}
+
+impl Rect {
+    /// Create a new rectangle
+    pub fn new(x: f32, y: f32, width: f32, height: f32) -> Self {
+        Self {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    pub fn right(&self) -> f32 {
+        self.x + self.width
+    }
+
+    pub fn bottom(&self) -> f32 {
+        self.y + self.height
+    }
+
+    pub fn area(&self) -> f32 {
+        self.width * self.height
+    }
+
+    /// Returns the overlap of the rectangles. Rectangles that only touch each other intersect, with
+    /// an empty overlap.
+    pub fn intersection(&self, other: &Rect) -> Option<Rect> {
+        let x = self.x.max(other.x);
+        let y = self.y.max(other.y);
+        let right = self.right().min(other.right());
+        let bottom = self.bottom().min(other.bottom());
+        if right < x || bottom < y {
+            return None;
+        }
+        Some(Rect::new(x, y, right - x, bottom - y))
+    }
+
+    /// Returns the smallest rectangle that contains both rectangles
+    pub fn union(&self, other: &Rect) -> Rect {
+        let x = self.x.min(other.x);
+        let y = self.y.min(other.y);
+        let right = self.right().max(other.right());
+        let bottom = self.bottom().max(other.bottom());
+        Rect::new(x, y, right - x, bottom - y)
+    }
+
+    /// Returns the rectangle grown by the margins (top, right, bottom, left). Negative margins
+    /// shrink it.
+    pub fn inflate(&self, margins: [f32; 4]) -> Rect {
+        let [top, right, bottom, left] = margins;
+        Rect::new(
+            self.x - left,
+            self.y - top,
+            (self.width + left + right).max(0.0),
+            (self.height + top + bottom).max(0.0),
+        )
+    }
+}
+
+/// How an element is positioned (the CSS `position` property), as far as geometry is concerned
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Positioning {
+    /// `position: static`
+    #[default]
+    Static,
+    /// `position: relative` or `sticky`
+    Positioned,
+    /// `position: absolute`
+    Absolute,
+    /// `position: fixed`
+    Fixed,
+}
+
+/// Result of the layout of a document, implemented by the embedder
+pub trait LayoutGeometry {
+    /// Returns the border box of the element, or None when it does not generate a box
+    fn border_box(&self, node_id: NodeId) -> Option<Rect>;
+    /// Returns the visible part of the document
+    fn viewport(&self) -> Rect;
+
+    /// Returns the padding box of the element: the border box without the borders and
+    /// scrollbars. Defaults to the border box.
+    fn padding_box(&self, node_id: NodeId) -> Option<Rect> {
+        self.border_box(node_id)
+    }
+
+    /// Returns the width and height of the content of the element, including the content that
+    /// overflows it. Defaults to None, for content that does not overflow.
+    fn scroll_size(&self, _node_id: NodeId) -> Option<(f32, f32)> {
+        None
+    }
+
+    /// Returns how the element is positioned. Defaults to static.
+    fn positioning(&self, _node_id: NodeId) -> Positioning {
+        Positioning::Static
+    }
+
+    /// Returns the properties of the element that decide its paint order. Defaults to None, in
+    /// which case they follow from the declarations of the element itself (see `stacking`).
+    fn paint_style(&self, _node_id: NodeId) -> Option<PaintStyle> {
+        None
+    }
+
+    /// Returns the transform of the element in viewport coordinates, around its transform
+    /// origin. Defaults to None, in which case it follows from the `transform` and
+    /// `transform-origin` declarations of the element (see `transform`).
+    fn transform(&self, _node_id: NodeId) -> Option<Matrix> {
+        None
+    }
+
+    /// Returns the natural width and height of the image at the (absolute) URL. Defaults to
+    /// None, for images that are not loaded (see `background`).
+    fn image_size(&self, _url: &str) -> Option<(f32, f32)> {
+        None
+    }
+
+    /// Returns the vertical positions inside the element where its contents can be broken
+    /// across pages, like between its lines. Defaults to none, which keeps the contents of the
+    /// element together when they fit on a page (see `pagination`).
+    fn break_points(&self, _node_id: NodeId) -> Vec<f32> {
+        Vec::new()
+    }
+}
+
+/// Layout geometry of a document, if the embedder provided one
+#[derive(Default)]
+pub(crate) struct GeometrySlot(pub(crate) Option<Rc<dyn LayoutGeometry>>);
+
+impl Debug for GeometrySlot {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "GeometrySlot({})", self.0.is_some())
+    }
+}
+
+impl PartialEq for GeometrySlot {
+    fn eq(&self, other: &Self) -> bool {
+        match (&self.0, &other.0) {
+            (Some(a), Some(b)) => std::ptr::eq(Rc::as_ptr(a).cast::<()>(), Rc::as_ptr(b).cast()),
+            (a, b) => a.is_none() && b.is_none(),
+        }
+    }
+}
+
+impl Document {
+    /// Sets the layout geometry the observers use
+    pub fn set_layout_geometry(&mut self, geometry: Rc<dyn LayoutGeometry>) {
+        self.geometry = GeometrySlot(Some(geometry));
+    }
+
+    /// Returns the border box of the element (like `getBoundingClientRect()`), or None when it
+    /// does not generate a box or there is no layout. For transformed elements (or elements in
+    /// transformed ancestors) this is the bounding box of the transformed border box.
+    pub fn bounding_client_rect(&self, node_id: NodeId) -> Option<Rect> {
+        if !self.is_connected(node_id) || self.is_hidden(node_id) {
+            return None;
+        }
+        let border_box = self.geometry.0.as_ref()?.border_box(node_id)?;
+        let transform = self.accumulated_transform(node_id);
+        if transform.is_identity() {
+            return Some(border_box);
+        }
+        Some(transform.map_rect(&border_box))
+    }
+
+    /// Returns the visible part of the document, or None when there is no layout
+    pub fn viewport_rect(&self) -> Option<Rect> {
+        self.geometry.0.as_ref().map(|geometry| geometry.viewport())
+    }
+
+    /// Returns the width of the padding box of the element (like `clientWidth`). For the root
+    /// element this is the width of the viewport. 0 when the element does not generate a box.
+    pub fn client_width(&self, node_id: NodeId) -> f32 {
+        self.client_rect(node_id).map_or(0.0, |rect| rect.width)
+    }
+
+    /// Returns the height of the padding box of the element (like `clientHeight`). See
+    /// `client_width()`.
+    pub fn client_height(&self, node_id: NodeId) -> f32 {
+        self.client_rect(node_id).map_or(0.0, |rect| rect.height)
+    }
+
+    /// Returns the width of the content of the element, including the content that overflows
+    /// it (like `scrollWidth`). Never less than `client_width()`.
+    pub fn scroll_width(&self, node_id: NodeId) -> f32 {
+        let width = self.scroll_size(node_id).map_or(0.0, |(width, _)| width);
+        width.max(self.client_width(node_id))
+    }
+
+    /// Returns the height of the content of the element, including the content that overflows
+    /// it (like `scrollHeight`). Never less than `client_height()`.
+    pub fn scroll_height(&self, node_id: NodeId) -> f32 {
+        let height = self.scroll_size(node_id).map_or(0.0, |(_, height)| height);
+        height.max(self.client_height(node_id))
+    }
+
+    /// Returns the element the offsets of the element are relative to (like `offsetParent`): the
+    /// nearest positioned ancestor, a table cell or table for elements that are not positioned,
+    /// or the body. None for the root element, the body, fixed elements and elements that do not
+    /// generate a box.
+    pub fn offset_parent(&self, node_id: NodeId) -> Option<NodeId> {
+        let geometry = self.geometry.0.as_ref()?;
+        self.bounding_client_rect(node_id)?;
+        let positioning = geometry.positioning(node_id);
+        if self.is_root_element(node_id)
+            || self.is_html_element(node_id, &["body"])
+            || positioning == Positioning::Fixed
+        {
+            return None;
+        }
+
+        let mut current = self.get_node_by_id(node_id)?.parent;
+        while let Some(ancestor) = current.and_then(|node_id| self.get_node_by_id(node_id)) {
+            if geometry.positioning(ancestor.id) != Positioning::Static
+                || self.is_transformed(ancestor.id)
+                || self.is_html_element(ancestor.id, &["body"])
+                || (positioning == Positioning::Static
+                    && self.is_html_element(ancestor.id, &["td", "th", "table"]))
+            {
+                return Some(ancestor.id);
+            }
+            current = ancestor.parent;
+        }
+        None
+    }
+
+    /// Returns the element that is the containing block of the element: for absolutely
+    /// positioned elements the nearest positioned or transformed ancestor (or the root element),
+    /// for fixed elements the nearest transformed ancestor, and the parent element otherwise.
+    /// None when the containing block is the viewport, or there is no layout.
+    pub fn containing_block(&self, node_id: NodeId) -> Option<NodeId> {
+        let geometry = self.geometry.0.as_ref()?;
+        let positioning = geometry.positioning(node_id);
+        let mut current = self.get_node_by_id(node_id)?.parent;
+        while let Some(ancestor) = current.and_then(|node_id| self.get_node_by_id(node_id)) {
+            let NodeData::Element(_) = ancestor.data else {
+                return None;
+            };
+            let contains = match positioning {
+                Positioning::Absolute => {
+                    geometry.positioning(ancestor.id) != Positioning::Static
+                        || self.is_transformed(ancestor.id)
+                        || self.is_root_element(ancestor.id)
+                }
+                Positioning::Fixed => self.is_transformed(ancestor.id),
+                Positioning::Static | Positioning::Positioned => true,
+            };
+            if contains {
+                return Some(ancestor.id);
+            }
+            current = ancestor.parent;
+        }
+        None
+    }
+
+    /// Returns the padding box of the element, or the viewport for the root element
+    fn client_rect(&self, node_id: NodeId) -> Option<Rect> {
+        let geometry = self.geometry.0.as_ref()?;
+        if self.is_root_element(node_id) {
+            return Some(geometry.viewport());
+        }
+        self.bounding_client_rect(node_id)?;
+        geometry.padding_box(node_id)
+    }
+
+    /// Returns the scroll size the layout reports for the element
+    fn scroll_size(&self, node_id: NodeId) -> Option<(f32, f32)> {
+        if !self.is_connected(node_id) || self.is_hidden(node_id) {
+            return None;
+        }
+        self.geometry.0.as_ref()?.scroll_size(node_id)
+    }
+
+    /// Returns true when the node is the element at the root of the document (the `<html>`
+    /// element)
+    pub(crate) fn is_root_element(&self, node_id: NodeId) -> bool {
+        self.get_node_by_id(node_id).is_some_and(|node| {
+            node.parent == Some(NodeId::root()) && matches!(node.data, NodeData::Element(_))
+        })
+    }
+
+    /// Returns true when the node is an HTML element with one of the given names
+    fn is_html_element(&self, node_id: NodeId, names: &[&str]) -> bool {
+        self.get_node_by_id(node_id).is_some_and(|node| {
+            node.namespace.as_deref() == Some(HTML_NAMESPACE) && names.contains(&node.name.as_str())
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::html5::parser::document::DocumentHandle;
+    use crate::testing::parse;
+    use std::collections::HashMap;
+
+    #[derive(Default)]
+    struct Layout {
+        boxes: HashMap<NodeId, Rect>,
+        scroll_sizes: HashMap<NodeId, (f32, f32)>,
+        positioning: HashMap<NodeId, Positioning>,
+    }
+
+    impl LayoutGeometry for Layout {
+        fn border_box(&self, node_id: NodeId) -> Option<Rect> {
+            self.boxes.get(&node_id).copied()
+        }
+
+        fn viewport(&self) -> Rect {
+            Rect::new(0.0, 0.0, 800.0, 600.0)
+        }
+
+        fn padding_box(&self, node_id: NodeId) -> Option<Rect> {
+            // Borders of 1px
+            Some(self.border_box(node_id)?.inflate([-1.0; 4]))
+        }
+
+        fn scroll_size(&self, node_id: NodeId) -> Option<(f32, f32)> {
+            self.scroll_sizes.get(&node_id).copied()
+        }
+
+        fn positioning(&self, node_id: NodeId) -> Positioning {
+            self.positioning.get(&node_id).copied().unwrap_or_default()
+        }
+    }
+
+    /// Parses the html, gives every element a box and returns the elements by id
+    fn setup(
+        html: &str,
+        layout: impl FnOnce(&mut Layout, &dyn Fn(&str) -> NodeId),
+    ) -> (DocumentHandle, HashMap<String, NodeId>) {
+        let mut document = parse(html);
+
+        let doc = document.get();
+        let ids: HashMap<String, NodeId> = doc
+            .descendants(NodeId::root())
+            .filter_map(|node| Some((node.get_attribute("id")?.clone(), node.id)))
+            .collect();
+        let mut boxes = Layout::default();
+        for node in doc.descendants(NodeId::root()) {
+            if let NodeData::Element(_) = node.data {
+                boxes
+                    .boxes
+                    .insert(node.id, Rect::new(0.0, 0.0, 100.0, 50.0));
+            }
+        }
+        layout(&mut boxes, &|id| ids[id]);
+        drop(doc);
+
+        document.get_mut().set_layout_geometry(Rc::new(boxes));
+        (document, ids)
+    }
+
+    #[test]
+    fn client_and_scroll_size() {
+        let (document, ids) = setup("<div id=a></div><div id=b></div>", |layout, id| {
+            layout.scroll_sizes.insert(id("a"), (300.0, 20.0));
+            layout.boxes.remove(&id("b"));
+        });
+        let doc = document.get();
+        let a = ids["a"];
+        assert_eq!(doc.client_width(a), 98.0);
+        assert_eq!(doc.client_height(a), 48.0);
+        assert_eq!(doc.scroll_width(a), 300.0);
+        assert_eq!(doc.scroll_height(a), 48.0);
+
+        // No box
+        assert_eq!(doc.client_width(ids["b"]), 0.0);
+        assert_eq!(doc.scroll_height(ids["b"]), 0.0);
+
+        // The root element reports the viewport
+        let html = doc.elements_by_tag_name("html")[0];
+        assert_eq!(doc.client_width(html), 800.0);
+        assert_eq!(doc.scroll_height(html), 600.0);
+    }
+
+    #[test]
+    fn offset_parent() {
+        let (document, ids) = setup(
+            "<div id=rel><p><span id=a></span></p></div><table><tr><td id=cell><b id=b></b><i id=fixed></i></td></tr></table>",
+            |layout, id| {
+                layout.positioning.insert(id("rel"), Positioning::Positioned);
+                layout.positioning.insert(id("fixed"), Positioning::Fixed);
+            },
+        );
+        let doc = document.get();
+        let body = doc.elements_by_tag_name("body")[0];
+        let html = doc.elements_by_tag_name("html")[0];
+
+        assert_eq!(doc.offset_parent(ids["a"]), Some(ids["rel"]));
+        assert_eq!(doc.offset_parent(ids["rel"]), Some(body));
+        assert_eq!(doc.offset_parent(ids["b"]), Some(ids["cell"]));
+        assert_eq!(doc.offset_parent(ids["fixed"]), None);
+        assert_eq!(doc.offset_parent(body), None);
+        assert_eq!(doc.offset_parent(html), None);
+    }
+
+    #[test]
+    fn intersection() {
+        let a = Rect::new(0.0, 0.0, 100.0, 100.0);
+        let b = Rect::new(50.0, 80.0, 100.0, 100.0);
+        assert_eq!(a.intersection(&b), Some(Rect::new(50.0, 80.0, 50.0, 20.0)));
+        assert_eq!(b.intersection(&a), a.intersection(&b));
+
+        // Touching rectangles intersect, but without area
+        let c = Rect::new(100.0, 0.0, 10.0, 10.0);
+        assert_eq!(a.intersection(&c), Some(Rect::new(100.0, 0.0, 0.0, 10.0)));
+        assert_eq!(a.intersection(&Rect::new(101.0, 0.0, 10.0, 10.0)), None);
+    }
+
+    #[test]
+    fn inflate() {
+        let rect = Rect::new(10.0, 10.0, 100.0, 50.0);
+        assert_eq!(
+            rect.inflate([10.0, 0.0, 20.0, 5.0]),
+            Rect::new(5.0, 0.0, 105.0, 80.0)
+        );
+        assert_eq!(
+            rect.inflate([-30.0, 0.0, -30.0, 0.0]),
+            Rect::new(10.0, 40.0, 100.0, 0.0)
+        );
+    }
+}
@@ -0,0 +1,241 @@
+//! Graph export
+//!
+//! Exports the node tree of a document as a graph, so it can be visualized with tools like
+//! Graphviz ([DOT](https://graphviz.org/doc/info/lang.html)) or Gephi
+//! ([GraphML](http://graphml.graphdrawing.org/)). This is mostly useful when debugging the parser
+//! output on pathological pages.
+use crate::html5::node::{Node, NodeData, NodeId};
+use crate::html5::parser::document::Document;
+use std::fmt::Write;
+
+/// Defines what is exported for every node
+#[derive(Debug, Clone, PartialEq)]
+pub struct GraphOptions {
+    /// Add the attributes of elements to their label
+    pub attributes: bool,
+    /// Maximum number of characters of text and comment nodes. Longer texts are truncated.
+    pub max_text_length: Option<usize>,
+    /// Export text nodes that only contain whitespace
+    pub whitespace_text: bool,
+}
+
+impl Default for GraphOptions {
+    fn default() -> Self {
+        Self {
+            attributes: true,
+            max_text_length: Some(40),
+            whitespace_text: false,
+        }
+    }
+}
+
+/// Exports the tree below (and including) the given node in DOT format
+pub fn to_dot(document: &Document, node_id: NodeId, options: &GraphOptions) -> String {
+    let mut output =
+        String::from("digraph document {\n  node [shape=box, fontname=\"monospace\"];\n");
+
+    for (node, kind, label) in collect_nodes(document, node_id, options) {
+        let style = match kind {
+            "element" => "",
+            "text" => ", style=dashed",
+            _ => ", style=dotted",
+        };
+        _ = writeln!(
+            output,
+            "  n{} [label=\"{}\"{}];",
+            node.id,
+            escape_dot(&label),
+            style
+        );
+        if let Some(parent) = node.parent.filter(|_| node.id != node_id) {
+            _ = writeln!(output, "  n{} -> n{};", parent, node.id);
+        }
+    }
+
+    output.push_str("}\n");
+    output
+}
+
+/// Exports the tree below (and including) the given node in GraphML format
+pub fn to_graphml(document: &Document, node_id: NodeId, options: &GraphOptions) -> String {
+    let mut nodes = String::new();
+    let mut edges = String::new();
+
+    for (node, kind, label) in collect_nodes(document, node_id, options) {
+        _ = writeln!(
+            nodes,
+            "    <node id=\"n{}\"><data key=\"kind\">{}</data><data key=\"label\">{}</data></node>",
+            node.id,
+            kind,
+            escape_xml(&label)
+        );
+        if let Some(parent) = node.parent.filter(|_| node.id != node_id) {
+            _ = writeln!(
+                edges,
+                "    <edge source=\"n{}\" target=\"n{}\"/>",
+                parent, node.id
+            );
+        }
+    }
+
+    format!(
+        concat!(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n",
+            "<graphml xmlns=\"http://graphml.graphdrawing.org/xmlns\">\n",
+            "  <key id=\"kind\" for=\"node\" attr.name=\"kind\" attr.type=\"string\"/>\n",
+            "  <key id=\"label\" for=\"node\" attr.name=\"label\" attr.type=\"string\"/>\n",
+            "  <graph id=\"document\" edgedefault=\"directed\">\n",
+            "{}{}",
+            "  </graph>\n",
+            "</graphml>\n"
+        ),
+        nodes, edges
+    )
+}
+
+/// Returns the exported nodes in tree order, with their kind and label
+fn collect_nodes<'a>(
+    document: &'a Document,
+    node_id: NodeId,
+    options: &GraphOptions,
+) -> Vec<(&'a Node, &'static str, String)> {
+    let mut nodes = vec![];
+    let mut stack = vec![node_id];
+
+    while let Some(node_id) = stack.pop() {
+        let Some(node) = document.get_node_by_id(node_id) else {
+            continue;
+        };
+        stack.extend(node.children.iter().rev());
+
+        let (kind, label) = match &node.data {
+            NodeData::Document(_) => ("document", "#document".to_string()),
+            NodeData::DocType(doctype) => ("doctype", format!("<!DOCTYPE {}>", doctype.name)),
+            NodeData::Element(element) => {
+                let mut label = format!("<{}", node.name);
+                if options.attributes {
+                    let mut attributes: Vec<_> = element.attributes.iter().collect();
+                    attributes.sort();
+                    for (name, value) in attributes {
+                        _ = write!(label, " {}=\"{}\"", name, truncate(value, options));
+                    }
+                }
+                label.push('>');
+                ("element", label)
+            }
+            NodeData::Text(text) => {
+                if !options.whitespace_text && text.value().trim().is_empty() {
+                    continue;
+                }
+                ("text", format!("\"{}\"", truncate(text.value(), options)))
+            }
+            NodeData::Comment(comment) => (
+                "comment",
+                format!("<!-- {} -->", truncate(comment.value(), options)),
+            ),
+        };
+
+        nodes.push((node, kind, label));
+    }
+
+    nodes
+}
+
+fn truncate(text: &str, options: &GraphOptions) -> String {
+    match options.max_text_length {
+        Some(max) if text.chars().count() > max => {
+            format!("{}…", text.chars().take(max).collect::<String>())
+        }
+        _ => text.to_string(),
+    }
+}
+
+fn escape_dot(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => {}
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn escape_xml(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::parse;
+
+    #[test]
+    fn dot_export() {
+        let document = parse("<!DOCTYPE html><p class=\"a\\b\" id=x>Hello \"world\"</p>\n<!--c-->");
+        let options = GraphOptions {
+            max_text_length: Some(8),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            to_dot(&document.get(), NodeId::root(), &options),
+            concat!(
+                "digraph document {\n",
+                "  node [shape=box, fontname=\"monospace\"];\n",
+                "  n0 [label=\"#document\", style=dotted];\n",
+                "  n1 [label=\"<!DOCTYPE html>\", style=dotted];\n",
+                "  n0 -> n1;\n",
+                "  n2 [label=\"<html>\"];\n",
+                "  n0 -> n2;\n",
+                "  n3 [label=\"<head>\"];\n",
+                "  n2 -> n3;\n",
+                "  n4 [label=\"<body>\"];\n",
+                "  n2 -> n4;\n",
+                "  n5 [label=\"<p class=\\\"a\\\\b\\\" id=\\\"x\\\">\"];\n",
+                "  n4 -> n5;\n",
+                "  n6 [label=\"\\\"Hello \\\"w…\\\"\", style=dashed];\n",
+                "  n5 -> n6;\n",
+                "  n8 [label=\"<!-- c -->\", style=dotted];\n",
+                "  n4 -> n8;\n",
+                "}\n",
+            )
+        );
+    }
+
+    #[test]
+    fn graphml_export() {
+        let document = parse("<div a='<&>'> <b>x</b></div>");
+        let doc = document.get();
+        let div = doc.elements_by_tag_name("div")[0];
+
+        let options = GraphOptions {
+            whitespace_text: true,
+            ..Default::default()
+        };
+        let graphml = to_graphml(&doc, div, &options);
+
+        assert!(graphml.contains(
+            "<node id=\"n4\"><data key=\"kind\">element</data><data key=\"label\">&lt;div a=&quot;&lt;&amp;&gt;&quot;&gt;</data></node>"
+        ));
+        assert!(graphml
+            .contains("<data key=\"kind\">text</data><data key=\"label\">&quot; &quot;</data>"));
+        assert!(graphml.contains("<edge source=\"n4\" target=\"n5\"/>"));
+        assert!(graphml.contains("<edge source=\"n6\" target=\"n7\"/>"));
+        assert!(!graphml.contains("target=\"n4\""));
+        assert_eq!(graphml.matches("<node ").count(), 4);
+    }
+}
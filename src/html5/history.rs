@@ -0,0 +1,440 @@
+//! Undo history
+//!
+//! When enabled, every mutation of the document is recorded together with what is needed to
+//! revert it, so editing tools and embedders can offer undo and redo without keeping track of
+//! the changes themselves.
+//!
+//! Mutations are grouped into transactions, and `undo()` reverts a whole transaction at once.
+//! Mutations made outside of a transaction are a transaction on their own. Nodes are never
+//! removed from the arena, so undoing the insertion of a node detaches it, and redoing attaches
+//! the same node again.
+//!
+//! The same records are used for journaling: while a journal is active, all mutations are
+//! recorded (whether the history is enabled or not) so they can be rolled back as a whole, for
+//! instance when a task of a transactional flush fails.
+use crate::html5::element_class::ElementClass;
+use crate::html5::node::{NodeData, NodeId};
+use crate::html5::parser::document::{Document, MutationRecord};
+use std::collections::HashMap;
+
+/// Undo and redo stacks of a document
+#[derive(Debug, Default, PartialEq)]
+pub struct History {
+    undo: Vec<Vec<Operation>>,
+    redo: Vec<Vec<Operation>>,
+    /// Operations of the current transaction
+    transaction: Vec<Operation>,
+    /// Number of nested transactions that have been started but not ended
+    depth: usize,
+}
+
+impl History {
+    /// Moves the operations of the current transaction (if any) to the undo stack
+    fn commit(&mut self) {
+        if !self.transaction.is_empty() {
+            let operations = std::mem::take(&mut self.transaction);
+            self.undo.push(operations);
+        }
+    }
+}
+
+/// Mutations recorded since the journal was started, to roll them back
+#[derive(Debug, Default, PartialEq)]
+pub(crate) struct Journal {
+    operations: Vec<Operation>,
+    /// Length of the current history transaction when the journal was started
+    transaction_len: usize,
+    /// Redo stack of the history when the journal was started (recording clears it)
+    redo: Vec<Vec<Operation>>,
+}
+
+/// Attributes of an element, including the classes which are stored separately
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct AttributeSnapshot {
+    attributes: HashMap<String, String>,
+    classes: ElementClass,
+}
+
+/// A single recorded mutation
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Operation {
+    /// The node has been attached to the parent at the given position
+    Attach {
+        node_id: NodeId,
+        parent_id: NodeId,
+        position: usize,
+    },
+    /// The node has been detached from the parent, where it was at the given position
+    Detach {
+        node_id: NodeId,
+        parent_id: NodeId,
+        position: usize,
+    },
+    /// The attributes of the element have been changed
+    Attributes {
+        node_id: NodeId,
+        old: AttributeSnapshot,
+        new: AttributeSnapshot,
+    },
+    /// The text of the text or comment node has been changed
+    Text {
+        node_id: NodeId,
+        old: String,
+        new: String,
+    },
+}
+
+impl Document {
+    /// Starts recording mutations for undo and redo
+    pub fn enable_history(&mut self) {
+        if self.history.is_none() {
+            self.history = Some(History::default());
+        }
+    }
+
+    /// Stops recording mutations and forgets the recorded ones
+    pub fn disable_history(&mut self) {
+        self.history = None;
+    }
+
+    /// Starts a transaction: all mutations until the matching `end_transaction()` are undone
+    /// (and redone) at once. Transactions can be nested, in which case the outermost one counts.
+    pub fn begin_transaction(&mut self) {
+        if let Some(history) = self.history.as_mut() {
+            history.depth += 1;
+        }
+    }
+
+    /// Ends the transaction started by `begin_transaction()`
+    pub fn end_transaction(&mut self) {
+        if let Some(history) = self.history.as_mut() {
+            history.depth = history.depth.saturating_sub(1);
+            if history.depth == 0 {
+                history.commit();
+            }
+        }
+    }
+
+    /// Returns true when there is a transaction to undo
+    pub fn can_undo(&self) -> bool {
+        self.history
+            .as_ref()
+            .is_some_and(|history| !history.undo.is_empty() || !history.transaction.is_empty())
+    }
+
+    /// Returns true when there is an undone transaction to redo
+    pub fn can_redo(&self) -> bool {
+        self.history
+            .as_ref()
+            .is_some_and(|history| !history.redo.is_empty())
+    }
+
+    /// Reverts the last transaction. A transaction that is still open is ended first. Returns
+    /// false when there is nothing to undo.
+    pub fn undo(&mut self) -> bool {
+        let Some(mut history) = self.history.take() else {
+            return false;
+        };
+        history.depth = 0;
+        history.commit();
+
+        let operations = history.undo.pop();
+        // Reverting mutates the document, which must not be recorded again
+        if let Some(operations) = &operations {
+            for operation in operations.iter().rev() {
+                self.apply_operation(operation, true);
+            }
+        }
+        history.redo.extend(operations.clone());
+
+        self.history = Some(history);
+        operations.is_some()
+    }
+
+    /// Applies the last undone transaction again. Returns false when there is nothing to redo.
+    pub fn redo(&mut self) -> bool {
+        let Some(mut history) = self.history.take() else {
+            return false;
+        };
+
+        let operations = history.redo.pop();
+        if let Some(operations) = &operations {
+            for operation in operations {
+                self.apply_operation(operation, false);
+            }
+        }
+        history.undo.extend(operations.clone());
+
+        self.history = Some(history);
+        operations.is_some()
+    }
+
+    /// Starts recording all mutations, so they can be rolled back with `rollback_journal()`
+    pub(crate) fn start_journal(&mut self) {
+        let (transaction_len, redo) = match &self.history {
+            Some(history) => (history.transaction.len(), history.redo.clone()),
+            None => (0, Vec::new()),
+        };
+        self.journal = Some(Journal {
+            operations: Vec::new(),
+            transaction_len,
+            redo,
+        });
+    }
+
+    /// Stops recording and keeps the mutations
+    pub(crate) fn discard_journal(&mut self) {
+        self.journal = None;
+    }
+
+    /// Stops recording and reverts all mutations since the journal was started. They are
+    /// removed from the history as well, as if they never happened.
+    pub(crate) fn rollback_journal(&mut self) {
+        let Some(journal) = self.journal.take() else {
+            return;
+        };
+
+        // Reverting mutates the document, which must not be recorded again
+        let history = self.history.take();
+        for operation in journal.operations.iter().rev() {
+            self.apply_operation(operation, true);
+        }
+
+        if let Some(mut history) = history {
+            history.transaction.truncate(journal.transaction_len);
+            history.redo = journal.redo;
+            self.history = Some(history);
+        }
+    }
+
+    /// Records an operation (when the history is enabled or a journal is active). New operations
+    /// make the undone transactions impossible to redo.
+    pub(crate) fn record_operation(&mut self, operation: Operation) {
+        if let Some(journal) = self.journal.as_mut() {
+            journal.operations.push(operation.clone());
+        }
+        let Some(history) = self.history.as_mut() else {
+            return;
+        };
+
+        history.redo.clear();
+        history.transaction.push(operation);
+        if history.depth == 0 {
+            history.commit();
+        }
+    }
+
+    /// Returns the attributes of the element, to be passed to `record_attribute_change()` after
+    /// changing them. Returns None when nothing is recorded, to avoid the copy.
+    pub(crate) fn attribute_snapshot(&self, node_id: NodeId) -> Option<AttributeSnapshot> {
+        if self.history.is_none() && self.journal.is_none() {
+            return None;
+        }
+        match &self.get_node_by_id(node_id)?.data {
+            NodeData::Element(element) => Some(AttributeSnapshot {
+                attributes: element.attributes.clone(),
+                classes: element.classes.clone(),
+            }),
+            _ => None,
+        }
+    }
+
+    /// Records the change of the attributes of the element since the snapshot was taken
+    pub(crate) fn record_attribute_change(
+        &mut self,
+        node_id: NodeId,
+        old: Option<AttributeSnapshot>,
+    ) {
+        let Some(old) = old else {
+            return;
+        };
+        let Some(new) = self.attribute_snapshot(node_id) else {
+            return;
+        };
+        if old != new {
+            self.record_operation(Operation::Attributes { node_id, old, new });
+        }
+    }
+
+    /// Applies the operation, or reverts it when `revert` is set
+    fn apply_operation(&mut self, operation: &Operation, revert: bool) {
+        match operation {
+            Operation::Attach {
+                node_id,
+                parent_id,
+                position,
+            }
+            | Operation::Detach {
+                node_id,
+                parent_id,
+                position,
+            } => {
+                let attach = matches!(operation, Operation::Attach { .. }) != revert;
+                if attach {
+                    // The tree was valid at this point before, so this cannot fail
+                    let _ = self.attach_node_to_parent(*node_id, *parent_id, Some(*position));
+                } else {
+                    self.detach_node_from_parent(*node_id);
+                }
+            }
+            Operation::Attributes { node_id, old, new } => {
+                let snapshot = if revert { old } else { new };
+                self.restore_attributes(*node_id, snapshot);
+            }
+            Operation::Text { node_id, old, new } => {
+                let text = if revert { old } else { new };
+                let _ = self.set_character_data(*node_id, text);
+            }
+        }
+    }
+
+    /// Sets the attributes of the element to the snapshot, keeping the ID lookup in sync
+    fn restore_attributes(&mut self, node_id: NodeId, snapshot: &AttributeSnapshot) {
+        self.unindex_element_classes(node_id);
+        self.unindex_element_name(node_id);
+        let Some(NodeData::Element(element)) =
+            self.get_node_by_id_mut(node_id).map(|node| &mut node.data)
+        else {
+            return;
+        };
+
+        let old_attributes =
+            std::mem::replace(&mut element.attributes, snapshot.attributes.clone());
+        element.classes = snapshot.classes.clone();
+        self.index_element_classes(node_id);
+        self.index_element_name(node_id);
+
+        if let Some(old_id) = old_attributes.get("id") {
+            if snapshot.attributes.get("id") != Some(old_id) {
+                self.release_named_id(old_id, node_id);
+            }
+        }
+        if let Some(new_id) = snapshot.attributes.get("id") {
+            self.claim_named_id(new_id, node_id);
+        }
+
+        let mut names: Vec<&String> = old_attributes
+            .keys()
+            .chain(snapshot.attributes.keys())
+            .collect();
+        names.sort();
+        names.dedup();
+        let names: Vec<String> = names.into_iter().cloned().collect();
+        for name in names.into_iter().chain(["class".to_string()]) {
+            self.record_mutation(MutationRecord::Attribute { node_id, name });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::html5::editing::{Boundary, Editor, Range};
+    use crate::html5::node::HTML_NAMESPACE;
+    use crate::html5::parser::document::DocumentHandle;
+    use crate::html5::parser::document::DocumentTaskQueue;
+    use crate::html5::parser::tree_builder::TreeBuilder;
+    use crate::html5::serializer::{serialize, SerializerOptions};
+    use crate::testing::parse;
+
+    fn body(document: &DocumentHandle) -> String {
+        let doc = document.get();
+        let body = doc.elements_by_tag_name("body")[0];
+        serialize(&doc, body, &SerializerOptions::default())
+    }
+
+    #[test]
+    fn undo_and_redo() {
+        let mut document = parse("<p id=a>one</p>");
+        document.get_mut().enable_history();
+        assert!(!document.get().can_undo());
+
+        let p = document.get().elements_by_tag_name("p")[0];
+        let head_id = document.get().elements_by_tag_name("head")[0];
+        let body_id = document.get().elements_by_tag_name("body")[0];
+        document.insert_attribute("id", "b", p).unwrap();
+        document.insert_attribute("title", "x", p).unwrap();
+        document.create_text("two", body_id).unwrap();
+        document.relocate(p, head_id);
+        assert_eq!(body(&document), "two");
+
+        // Relocating is a single transaction
+        assert!(document.get_mut().undo());
+        assert_eq!(body(&document), "<p id=\"b\" title=\"x\">one</p>two");
+        assert!(document.get_mut().undo());
+        assert!(document.get_mut().undo());
+        assert!(document.get_mut().undo());
+        assert_eq!(body(&document), "<p id=\"a\">one</p>");
+        assert_eq!(document.get().get_node_by_named_id("a").unwrap().id, p);
+        assert!(document.get().get_node_by_named_id("b").is_none());
+        assert!(!document.get_mut().undo());
+
+        assert!(document.get_mut().redo());
+        assert!(document.get_mut().redo());
+        assert_eq!(body(&document), "<p id=\"b\" title=\"x\">one</p>");
+        assert_eq!(document.get().get_node_by_named_id("b").unwrap().id, p);
+
+        // A new mutation discards the transactions that can be redone
+        document.create_text("three", body_id).unwrap();
+        assert!(!document.get().can_redo());
+        assert_eq!(body(&document), "<p id=\"b\" title=\"x\">one</p>three");
+    }
+
+    #[test]
+    fn transactions() {
+        let mut document = parse("<p>hello world</p>");
+        document.get_mut().enable_history();
+
+        // Every flush of a task queue is a transaction
+        let body_id = document.get().elements_by_tag_name("body")[0];
+        let mut queue = DocumentTaskQueue::new(&document);
+        let div = queue
+            .create_element("div", body_id, None, HTML_NAMESPACE)
+            .unwrap();
+        queue.create_text("in div", div).unwrap();
+        queue.insert_attribute("title", "t", div).unwrap();
+        assert!(queue.flush().is_empty());
+        assert_eq!(
+            body(&document),
+            "<p>hello world</p><div title=\"t\">in div</div>"
+        );
+        assert!(document.get_mut().undo());
+        assert_eq!(body(&document), "<p>hello world</p>");
+        assert!(document.get_mut().redo());
+
+        // And so is every editing command
+        let text = document
+            .get()
+            .get_node_by_id(document.get().elements_by_tag_name("p")[0])
+            .unwrap()
+            .children[0];
+        let mut editor = Editor::new(&document);
+        editor
+            .wrap_selection(
+                &Range::new(Boundary::new(text, 0), Boundary::new(text, 5)),
+                "b",
+            )
+            .unwrap();
+        assert_eq!(
+            body(&document),
+            "<p><b>hello</b> world</p><div title=\"t\">in div</div>"
+        );
+        assert!(document.get_mut().undo());
+        assert_eq!(
+            body(&document),
+            "<p>hello world</p><div title=\"t\">in div</div>"
+        );
+
+        // Nested transactions are undone at once
+        document.get_mut().begin_transaction();
+        document.get_mut().begin_transaction();
+        document.create_text("1", body_id).unwrap();
+        document.get_mut().end_transaction();
+        document.create_text("2", body_id).unwrap();
+        document.get_mut().end_transaction();
+        assert!(document.get_mut().undo());
+        assert_eq!(
+            body(&document),
+            "<p>hello world</p><div title=\"t\">in div</div>"
+        );
+    }
+}
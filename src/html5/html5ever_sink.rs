@@ -0,0 +1,337 @@
+//! html5ever adapter
+//!
+//! Lets the html5ever parser build a gosub [`Document`]. The [`DocumentSink`] implements
+//! html5ever's `TreeSink` on top of a [`DocumentHandle`], so the resulting tree can be used with
+//! everything that works on gosub documents (serializer, queries, events, etc.).
+//!
+//! This allows users to migrate from html5ever incrementally, and makes it easy to compare the
+//! output of both parsers.
+use crate::html5::element_class::ElementClass;
+use crate::html5::node::data::text::TextData;
+use crate::html5::node::{Node, NodeData, NodeId};
+use crate::html5::parser::document::{Document, DocumentBuilder, DocumentFragment, DocumentHandle};
+use crate::html5::parser::quirks::QuirksMode;
+use html5ever::tendril::{StrTendril, TendrilSink};
+use html5ever::tree_builder::{ElementFlags, NodeOrText, TreeSink};
+use html5ever::{Attribute, ExpandedName, ParseOpts, QualName};
+use std::borrow::Cow;
+use std::collections::HashMap;
+
+/// Parses the given html with html5ever into a new gosub document
+pub fn parse_document(html: &str) -> DocumentHandle {
+    html5ever::parse_document(DocumentSink::new(), ParseOpts::default()).one(html)
+}
+
+/// Builds a gosub document from the output of the html5ever parser
+pub struct DocumentSink {
+    document: DocumentHandle,
+    /// Qualified names of the created elements, as html5ever needs references to them
+    names: HashMap<NodeId, QualName>,
+    /// Parse errors reported by html5ever
+    pub errors: Vec<String>,
+}
+
+impl Default for DocumentSink {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl DocumentSink {
+    /// Creates a sink that builds a new document
+    pub fn new() -> Self {
+        Self::with_document(DocumentBuilder::new_document())
+    }
+
+    /// Creates a sink that adds the parse output to the given (empty) document
+    pub fn with_document(document: DocumentHandle) -> Self {
+        Self {
+            document,
+            names: HashMap::new(),
+            errors: Vec::new(),
+        }
+    }
+
+    /// Inserts the child into the parent before the given sibling (or at the end). Text is merged
+    /// into the previous sibling when that is a text node.
+    fn insert(&mut self, parent: NodeId, before: Option<NodeId>, child: NodeOrText<NodeId>) {
+        if let NodeOrText::AppendNode(node_id) = child {
+            self.document.detach_node_from_parent(node_id);
+        }
+
+        let (position, previous) = {
+            let document = self.document.get();
+            let children = &document
+                .get_node_by_id(parent)
+                .expect("node not found")
+                .children;
+            let position = before
+                .and_then(|before| children.iter().position(|&id| id == before))
+                .unwrap_or(children.len());
+            let previous = position.checked_sub(1).map(|index| children[index]);
+            (position, previous)
+        };
+
+        let result = match child {
+            NodeOrText::AppendNode(node_id) => {
+                self.document
+                    .attach_node_to_parent(node_id, parent, Some(position))
+            }
+            NodeOrText::AppendText(text) => {
+                if let Some(previous) = previous {
+                    let mut document = self.document.get_mut();
+                    let node = document
+                        .get_node_by_id_mut(previous)
+                        .expect("node not found");
+                    if let NodeData::Text(TextData { ref mut value, .. }) = node.data {
+                        value.push_str(&text);
+                        return;
+                    }
+                }
+
+                self.document
+                    .add_node(Node::new_text(&text), parent, Some(position))
+                    .map(|_| ())
+            }
+        };
+        // html5ever only builds trees the DOM allows, so this is a bug that is reported like a
+        // parse error
+        if let Err(error) = result {
+            self.errors.push(error.to_string());
+        }
+    }
+}
+
+/// Returns the attribute name as the gosub parser stores it ("prefix:local" for foreign attributes)
+fn attribute_name(name: &QualName) -> String {
+    match &name.prefix {
+        Some(prefix) => format!("{}:{}", prefix, name.local),
+        None => name.local.to_string(),
+    }
+}
+
+impl TreeSink for DocumentSink {
+    type Handle = NodeId;
+    type Output = DocumentHandle;
+
+    fn finish(self) -> Self::Output {
+        self.document
+    }
+
+    fn parse_error(&mut self, msg: Cow<'static, str>) {
+        self.errors.push(msg.into_owned());
+    }
+
+    fn get_document(&mut self) -> Self::Handle {
+        NodeId::root()
+    }
+
+    fn elem_name<'a>(&'a self, target: &'a Self::Handle) -> ExpandedName<'a> {
+        self.names
+            .get(target)
+            .expect("not an element created by html5ever")
+            .expanded()
+    }
+
+    fn create_element(
+        &mut self,
+        name: QualName,
+        attrs: Vec<Attribute>,
+        _flags: ElementFlags,
+    ) -> Self::Handle {
+        let attributes = attrs
+            .iter()
+            .map(|attr| (attribute_name(&attr.name), attr.value.to_string()))
+            .collect::<HashMap<_, _>>();
+
+        let mut node = Node::new_element(&name.local, attributes, &name.ns);
+        if let NodeData::Element(ref mut element) = node.data {
+            if let Some(class_string) = element.attributes.get("class") {
+                element.classes = ElementClass::from_string(class_string);
+            }
+        }
+
+        let node_id = self.document.get_mut().add_new_node(node);
+        self.names.insert(node_id, name);
+        node_id
+    }
+
+    fn create_comment(&mut self, text: StrTendril) -> Self::Handle {
+        self.document
+            .get_mut()
+            .add_new_node(Node::new_comment(&text))
+    }
+
+    fn create_pi(&mut self, _target: StrTendril, data: StrTendril) -> Self::Handle {
+        // Processing instructions are only created in XML documents, and have no gosub equivalent
+        self.create_comment(data)
+    }
+
+    fn append(&mut self, parent: &Self::Handle, child: NodeOrText<Self::Handle>) {
+        self.insert(*parent, None, child);
+    }
+
+    fn append_based_on_parent_node(
+        &mut self,
+        element: &Self::Handle,
+        prev_element: &Self::Handle,
+        child: NodeOrText<Self::Handle>,
+    ) {
+        let has_parent = self
+            .document
+            .get()
+            .get_node_by_id(*element)
+            .is_some_and(|node| node.parent.is_some());
+
+        if has_parent {
+            self.append_before_sibling(element, child);
+        } else {
+            self.append(prev_element, child);
+        }
+    }
+
+    fn append_doctype_to_document(
+        &mut self,
+        name: StrTendril,
+        public_id: StrTendril,
+        system_id: StrTendril,
+    ) {
+        let node = Node::new_doctype(&name, &public_id, &system_id);
+        if let Err(error) = self.document.add_node(node, NodeId::root(), None) {
+            self.errors.push(error.to_string());
+        }
+    }
+
+    fn get_template_contents(&mut self, target: &Self::Handle) -> Self::Handle {
+        // Like the gosub parser, the template contents are stored as children of the template
+        let handle = Document::clone(&self.document);
+        let mut doc = self.document.get_mut();
+        if let Some(NodeData::Element(element)) =
+            doc.get_node_by_id_mut(*target).map(|n| &mut n.data)
+        {
+            element
+                .template_contents
+                .get_or_insert_with(|| DocumentFragment::new(handle, *target));
+        }
+        *target
+    }
+
+    fn same_node(&self, x: &Self::Handle, y: &Self::Handle) -> bool {
+        x == y
+    }
+
+    fn set_quirks_mode(&mut self, mode: html5ever::tree_builder::QuirksMode) {
+        self.document.get_mut().quirks_mode = match mode {
+            html5ever::tree_builder::QuirksMode::Quirks => QuirksMode::Quirks,
+            html5ever::tree_builder::QuirksMode::LimitedQuirks => QuirksMode::LimitedQuirks,
+            html5ever::tree_builder::QuirksMode::NoQuirks => QuirksMode::NoQuirks,
+        };
+    }
+
+    fn append_before_sibling(
+        &mut self,
+        sibling: &Self::Handle,
+        new_node: NodeOrText<Self::Handle>,
+    ) {
+        let parent = self
+            .document
+            .get()
+            .get_node_by_id(*sibling)
+            .and_then(|node| node.parent)
+            .expect("sibling has no parent");
+
+        self.insert(parent, Some(*sibling), new_node);
+    }
+
+    fn add_attrs_if_missing(&mut self, target: &Self::Handle, attrs: Vec<Attribute>) {
+        let mut document = self.document.get_mut();
+        let Some(node) = document.get_node_by_id_mut(*target) else {
+            return;
+        };
+
+        if let NodeData::Element(ref mut element) = node.data {
+            for attr in attrs {
+                element
+                    .attributes
+                    .entry(attribute_name(&attr.name))
+                    .or_insert_with(|| attr.value.to_string());
+            }
+        }
+    }
+
+    fn remove_from_parent(&mut self, target: &Self::Handle) {
+        self.document.detach_node_from_parent(*target);
+    }
+
+    fn reparent_children(&mut self, node: &Self::Handle, new_parent: &Self::Handle) {
+        let children = self
+            .document
+            .get()
+            .get_node_by_id(*node)
+            .map(|node| node.children.clone())
+            .unwrap_or_default();
+
+        for child in children {
+            self.document.relocate(child, *new_parent);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::html5::serializer::{serialize, SerializerOptions};
+    use crate::testing::parse;
+
+    fn serialize_document(document: &DocumentHandle) -> String {
+        serialize(
+            &document.get(),
+            NodeId::root(),
+            &SerializerOptions::default(),
+        )
+    }
+
+    #[test]
+    fn builds_gosub_document() {
+        let document = parse_document("<!DOCTYPE html><title>x</title><p class='a b' id=p>1<b>2");
+
+        let doc = document.get();
+        assert_eq!(doc.quirks_mode, QuirksMode::NoQuirks);
+
+        let p = doc.get_node_by_named_id("p").expect("p not found");
+        assert_eq!(p.name, "p");
+        if let NodeData::Element(element) = &p.data {
+            assert!(element.classes.contains("b"));
+        }
+        assert_eq!(doc.text_content(p.id), "12");
+    }
+
+    #[test]
+    fn matches_gosub_parser() {
+        for html in [
+            "<!DOCTYPE html><html><head><title>t</title></head><body><p>x</p></body></html>",
+            "<b>1<p>2</b>3</p>",
+            "<table><tr><td>a</td></tr>b</table>",
+            "<p>a<!--c-->b<svg><path xlink:href=x /></svg>",
+            "<html a=1><body b=2><html c=3><body d=4>text",
+            "<template><td>cell</td></template><select><option>1<option>2</select>",
+        ] {
+            assert_eq!(
+                serialize_document(&parse_document(html)),
+                serialize_document(&parse(html)),
+                "{}",
+                html
+            );
+        }
+    }
+
+    #[test]
+    fn template_contents() {
+        let document = parse_document("<template id=t><td>cell</td></template>");
+        let doc = document.get();
+        let template = doc.get_node_by_named_id("t").unwrap().id;
+        let content = doc.template_content(template).unwrap();
+        assert_eq!(content.host(), template);
+        assert_eq!(content.children().len(), 1);
+    }
+}
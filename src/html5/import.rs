@@ -0,0 +1,251 @@
+//! Copying nodes, and moving them between documents
+//!
+//! `clone_node()` copies a node (and optionally its descendants) within the document, with new
+//! IDs, like `Node.cloneNode()`.
+//!
+//! Node IDs belong to the arena of a document, so a node cannot simply be attached in another
+//! document. `import_node()` copies a node into the document like `clone_node()` does, but from
+//! another document (like `Document.importNode()`). `adopt_node()` moves a subtree: it is copied
+//! and then removed from the other document, like `Document.adoptNode()`.
+//!
+//! The copies are not attached to the tree. Their ids and classes become queryable once they are
+//! attached. Event listeners and form state of the original nodes are not carried over.
+use crate::html5::compaction::NodeIdMap;
+use crate::html5::node::{Node, NodeData, NodeId};
+use crate::html5::parser::document::{Document, DocumentHandle};
+use crate::types::{Error, Result};
+
+impl Document {
+    /// Returns the node, and its descendants when `deep` is set, in tree order
+    fn copy_subtree(&self, node_id: NodeId, deep: bool) -> Result<Vec<Node>> {
+        let Some(node) = self.get_node_by_id(node_id) else {
+            return Err(Error::DocumentTask(format!(
+                "Node ID {} not found",
+                node_id
+            )));
+        };
+        if let NodeData::Document(_) = node.data {
+            return Err(Error::DocumentTask(
+                "Cannot import or adopt a document node".to_string(),
+            ));
+        }
+
+        let mut nodes = vec![node.clone()];
+        if deep {
+            nodes.extend(self.descendants(node_id).cloned());
+        }
+        Ok(nodes)
+    }
+
+    /// Copies the node (like `cloneNode()`), together with its descendants when `deep` is set.
+    /// Returns the ID of the copy, which is not attached to the tree.
+    pub fn clone_node(&mut self, node_id: NodeId, deep: bool) -> Result<NodeId> {
+        let nodes = self.copy_subtree(node_id, deep)?;
+        let ids = self.insert_copies(nodes)?;
+        Ok(ids[&node_id])
+    }
+
+    /// Adds the copies of the nodes (in tree order) to the arena, keeping their structure. The
+    /// first node is not attached. Returns the new ID of every node by its old ID.
+    fn insert_copies(&mut self, nodes: Vec<Node>) -> Result<NodeIdMap> {
+        self.begin_transaction();
+
+        let mut ids = NodeIdMap::new();
+        for node in nodes {
+            let old_id = node.id;
+            let parent_id = node
+                .parent
+                .and_then(|parent_id| ids.get(&parent_id).copied());
+
+            let mut copy = node.clone_without_children();
+            copy.parent = None;
+            copy.is_registered = false;
+            let new_id = match parent_id {
+                Some(parent_id) => match self.add_node(copy, parent_id, None) {
+                    Ok(new_id) => new_id,
+                    Err(error) => {
+                        self.end_transaction();
+                        return Err(error);
+                    }
+                },
+                None => self.add_new_node(copy),
+            };
+            if let Some(NodeData::Element(element)) =
+                self.get_node_by_id_mut(new_id).map(|node| &mut node.data)
+            {
+                if let Some(template_contents) = &mut element.template_contents {
+                    template_contents.host = new_id;
+                }
+            }
+            ids.insert(old_id, new_id);
+        }
+
+        self.end_transaction();
+        Ok(ids)
+    }
+}
+
+impl DocumentHandle {
+    /// Copies the node of the other document into this document (like `importNode()`), together
+    /// with its descendants when `deep` is set. Returns the ID of the copy, which is not attached
+    /// to the tree. The other document may be this document.
+    pub fn import_node(
+        &mut self,
+        other: &DocumentHandle,
+        node_id: NodeId,
+        deep: bool,
+    ) -> Result<NodeId> {
+        let nodes = other.get().copy_subtree(node_id, deep)?;
+        let ids = self.insert_copies(nodes)?;
+        Ok(ids[&node_id])
+    }
+
+    /// Moves the node and its descendants from the other document into this document (like
+    /// `adoptNode()`). The node is removed from the other document, and is not attached to the
+    /// tree of this document. Returns the new ID of every moved node by its ID in the other
+    /// document. Adopting a node of this document only detaches it.
+    pub fn adopt_node(&mut self, other: &mut DocumentHandle, node_id: NodeId) -> Result<NodeIdMap> {
+        let nodes = other.get().copy_subtree(node_id, true)?;
+        if self.is_same_document(other) {
+            self.detach_node_from_parent(node_id);
+            return Ok(nodes.iter().map(|node| (node.id, node.id)).collect());
+        }
+
+        let ids = self.insert_copies(nodes)?;
+        other.remove_subtree(node_id)?;
+        Ok(ids)
+    }
+
+    /// Adds the copies of the nodes of another document, and moves their template contents to
+    /// this document
+    fn insert_copies(&mut self, nodes: Vec<Node>) -> Result<NodeIdMap> {
+        let handle = Document::clone(self);
+        let mut doc = self.get_mut();
+        let ids = doc.insert_copies(nodes)?;
+        for &new_id in ids.values() {
+            if let Some(NodeData::Element(element)) =
+                doc.get_node_by_id_mut(new_id).map(|node| &mut node.data)
+            {
+                if let Some(template_contents) = &mut element.template_contents {
+                    template_contents.doc = Document::clone(&handle);
+                }
+            }
+        }
+        Ok(ids)
+    }
+
+    /// Copies the node, together with its descendants when `deep` is set. See
+    /// `Document::clone_node()`.
+    pub fn clone_node(&mut self, node_id: NodeId, deep: bool) -> Result<NodeId> {
+        self.get_mut().clone_node(node_id, deep)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::html5::serializer::{serialize, SerializerOptions};
+    use crate::testing::parse;
+
+    fn html(document: &DocumentHandle, node_id: NodeId) -> String {
+        serialize(&document.get(), node_id, &SerializerOptions::default())
+    }
+
+    #[test]
+    fn clone_node() {
+        let mut document = parse("<div id=a class=x><p>one <b>two</b></p></div>");
+        let div = document.get().get_node_by_named_id("a").unwrap().id;
+        let body = document.get().elements_by_tag_name("body")[0];
+
+        let copy = document.clone_node(div, true).unwrap();
+        assert_eq!(document.get_nodes_by_class_name("x"), vec![div]);
+        document.attach_node_to_parent(copy, body, None).unwrap();
+        assert_eq!(html(&document, copy), html(&document, div));
+        assert_eq!(document.get_nodes_by_class_name("x"), vec![div, copy]);
+
+        // The id keeps referring to the original element
+        assert_eq!(document.get().get_node_by_named_id("a").unwrap().id, div);
+        document.remove_subtree(div).unwrap();
+        assert_eq!(document.get().get_node_by_named_id("a").unwrap().id, copy);
+
+        let shallow = document.clone_node(copy, false).unwrap();
+        let doc = document.get();
+        let node = doc.get_node_by_id(shallow).unwrap();
+        assert!(node.children.is_empty());
+        assert_eq!(node.get_attribute("class").unwrap(), "x");
+    }
+
+    #[test]
+    fn import_node() {
+        let source = parse("<div class=x><p id=b>one <b>two</b></p></div>");
+        let mut target = parse("<main></main>");
+        let div = source.get_nodes_by_class_name("x")[0];
+        let main = target.get().elements_by_tag_name("main")[0];
+
+        let copy = target.import_node(&source, div, true).unwrap();
+        assert!(target.get().get_node_by_named_id("b").is_none());
+        target.attach_node_to_parent(copy, main, None).unwrap();
+        assert_eq!(
+            html(&target, main),
+            r#"<div class="x"><p id="b">one <b>two</b></p></div>"#
+        );
+        assert_eq!(
+            target.get().get_node_by_named_id("b").unwrap().parent,
+            Some(copy)
+        );
+        assert_eq!(target.get_nodes_by_class_name("x"), vec![copy]);
+
+        // The source is not changed, and a shallow import copies only the node
+        assert!(source.get().get_node_by_named_id("b").is_some());
+        let shallow = target.import_node(&source, div, false).unwrap();
+        assert!(target
+            .get()
+            .get_node_by_id(shallow)
+            .unwrap()
+            .children
+            .is_empty());
+
+        assert!(target.import_node(&source, NodeId::root(), true).is_err());
+        assert!(target
+            .import_node(&source, NodeId::from(999), true)
+            .is_err());
+    }
+
+    #[test]
+    fn adopt_node() {
+        let mut source =
+            parse("<ul id=list><li>one</li><li>two</li></ul><template id=t>x</template>");
+        let mut target = parse("");
+        let list = source.get().get_node_by_named_id("list").unwrap().id;
+        let body = target.get().elements_by_tag_name("body")[0];
+
+        let ids = target.adopt_node(&mut source, list).unwrap();
+        assert_eq!(ids.len(), 5);
+        assert!(source.get().get_node_by_id(list).is_none());
+        assert!(source.get().get_node_by_named_id("list").is_none());
+        target
+            .attach_node_to_parent(ids[&list], body, None)
+            .unwrap();
+        assert_eq!(
+            target.get().get_node_by_named_id("list").unwrap().id,
+            ids[&list]
+        );
+        assert_eq!(target.get().text_content(ids[&list]), "onetwo");
+
+        // Template contents belong to the new document
+        let template = source.get().get_node_by_named_id("t").unwrap().id;
+        let ids = target.adopt_node(&mut source, template).unwrap();
+        let doc = target.get();
+        let contents = doc.template_content(ids[&template]).unwrap();
+        assert_eq!(contents.host(), ids[&template]);
+        assert!(contents.doc.is_same_document(&target));
+        drop(doc);
+
+        // Adopting a node of the document itself detaches it
+        let mut same = Document::clone(&target);
+        let moved = target.get().get_node_by_named_id("list").unwrap().id;
+        let ids = target.adopt_node(&mut same, moved).unwrap();
+        assert_eq!(ids[&moved], moved);
+        assert_eq!(target.get().get_node_by_id(moved).unwrap().parent, None);
+    }
+}
@@ -0,0 +1,263 @@
+//! Document indexes
+//!
+//! Optional reverse indexes from tag names and selected attribute names to the elements that have
+//! them. Without indexes, finding all elements of a kind means walking the whole tree; with them,
+//! repeated queries (like a crawler extracting all `<a href>` links) only touch the results.
+//!
+//! The indexes are kept up to date through the mutation records of the document. They contain
+//! every element that is registered in the document, so nodes that are moved around stay indexed,
+//! and only the connected ones are returned by the queries.
+use crate::html5::compaction::{remap_set, NodeIdMap};
+use crate::html5::node::{NodeData, NodeId};
+use crate::html5::parser::document::{Document, MutationRecord};
+use std::collections::{BTreeSet, HashMap, HashSet};
+
+/// Reverse indexes of a document
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DocumentIndexes {
+    /// Names of the attributes that are indexed
+    attributes: HashSet<String>,
+    /// Elements per tag name
+    by_tag_name: HashMap<String, BTreeSet<NodeId>>,
+    /// Elements per indexed attribute name
+    by_attribute: HashMap<String, BTreeSet<NodeId>>,
+}
+
+impl DocumentIndexes {
+    /// Removes all indexed elements, but keeps the names of the indexed attributes
+    pub(crate) fn clear(&mut self) {
+        self.by_tag_name.clear();
+        self.by_attribute.clear();
+    }
+
+    /// Replaces the IDs of the indexed elements by their new IDs
+    pub(crate) fn remap_nodes(&mut self, ids: &NodeIdMap) {
+        for elements in self
+            .by_tag_name
+            .values_mut()
+            .chain(self.by_attribute.values_mut())
+        {
+            remap_set(ids, elements);
+        }
+    }
+
+    /// Removes the element from the indexes, as it has been removed from the document
+    pub(crate) fn forget_node(&mut self, node_id: NodeId) {
+        for elements in self
+            .by_tag_name
+            .values_mut()
+            .chain(self.by_attribute.values_mut())
+        {
+            elements.remove(&node_id);
+        }
+    }
+
+    fn index_element(&mut self, document: &Document, node_id: NodeId) {
+        let Some(node) = document.get_node_by_id(node_id) else {
+            return;
+        };
+        let NodeData::Element(element) = &node.data else {
+            return;
+        };
+
+        self.by_tag_name
+            .entry(node.name.clone())
+            .or_default()
+            .insert(node_id);
+        for name in element.attributes.keys() {
+            self.index_attribute(node_id, name);
+        }
+    }
+
+    fn index_attribute(&mut self, node_id: NodeId, name: &str) {
+        if self.attributes.contains(name) {
+            self.by_attribute
+                .entry(name.to_string())
+                .or_default()
+                .insert(node_id);
+        }
+    }
+}
+
+impl Document {
+    /// Enables the indexes. Tag names are always indexed, attributes only when their name is
+    /// given. The indexes are built from the current nodes and updated on every mutation from
+    /// then on.
+    pub fn enable_indexes(&mut self, attributes: &[&str]) {
+        let mut indexes = DocumentIndexes {
+            attributes: attributes.iter().map(|name| name.to_string()).collect(),
+            ..Default::default()
+        };
+
+        for node in self.arena.nodes() {
+            indexes.index_element(self, node.id);
+        }
+
+        self.indexes = Some(indexes);
+    }
+
+    /// Removes the indexes
+    pub fn disable_indexes(&mut self) {
+        self.indexes = None;
+    }
+
+    /// Returns the connected elements with the given tag name (in any namespace), or None when
+    /// the indexes are not enabled. Elements are returned in order of creation, which is tree
+    /// order unless nodes have been moved.
+    pub fn indexed_elements_by_tag_name(&self, name: &str) -> Option<Vec<NodeId>> {
+        let indexes = self.indexes.as_ref()?;
+        Some(self.connected(indexes.by_tag_name.get(name)))
+    }
+
+    /// Returns the connected elements that have the given attribute, or None when the attribute
+    /// is not indexed. Elements are returned in order of creation.
+    pub fn indexed_elements_with_attribute(&self, name: &str) -> Option<Vec<NodeId>> {
+        let indexes = self.indexes.as_ref()?;
+        if !indexes.attributes.contains(name) {
+            return None;
+        }
+
+        let elements = self.connected(indexes.by_attribute.get(name));
+        Some(
+            elements
+                .into_iter()
+                .filter(|&node_id| {
+                    matches!(
+                        self.get_node_by_id(node_id).map(|node| &node.data),
+                        Some(NodeData::Element(element)) if element.attributes.contains_key(name)
+                    )
+                })
+                .collect(),
+        )
+    }
+
+    /// Returns the names of the indexed attributes, or None when the indexes are not enabled
+    pub(crate) fn indexed_attributes(&self) -> Option<Vec<String>> {
+        let indexes = self.indexes.as_ref()?;
+        let mut attributes: Vec<_> = indexes.attributes.iter().cloned().collect();
+        attributes.sort();
+        Some(attributes)
+    }
+
+    fn connected(&self, elements: Option<&BTreeSet<NodeId>>) -> Vec<NodeId> {
+        elements
+            .into_iter()
+            .flatten()
+            .copied()
+            .filter(|&node_id| self.is_connected(node_id))
+            .collect()
+    }
+
+    /// Updates the indexes (if enabled) for the given mutation
+    pub(crate) fn update_indexes(&mut self, record: &MutationRecord) {
+        let Some(mut indexes) = self.indexes.take() else {
+            return;
+        };
+
+        match record {
+            MutationRecord::NodeCreated(node_id) => indexes.index_element(self, *node_id),
+            MutationRecord::Attribute { node_id, name } => indexes.index_attribute(*node_id, name),
+            // Connection is checked when querying, so moving nodes needs no updates
+            MutationRecord::ChildList { .. } | MutationRecord::CharacterData(_) => {}
+        }
+
+        self.indexes = Some(indexes);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bytes::{CharIterator, Encoding};
+    use crate::html5::node::{Node, HTML_NAMESPACE};
+    use crate::html5::parser::document::{DocumentBuilder, DocumentHandle};
+    use crate::html5::parser::tree_builder::TreeBuilder;
+    use crate::html5::parser::Html5Parser;
+    use crate::testing::parse;
+
+    fn hrefs(document: &DocumentHandle) -> Vec<String> {
+        let doc = document.get();
+        doc.indexed_elements_with_attribute("href")
+            .unwrap()
+            .into_iter()
+            .map(|node_id| match &doc.get_node_by_id(node_id).unwrap().data {
+                NodeData::Element(element) => element.attributes["href"].clone(),
+                _ => unreachable!(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn indexes_are_optional() {
+        let mut document = parse("<a href=x>x</a>");
+        assert_eq!(document.get().indexed_elements_by_tag_name("a"), None);
+
+        document.get_mut().enable_indexes(&[]);
+        assert_eq!(document.get().indexed_elements_with_attribute("href"), None);
+        assert_eq!(
+            document.get().indexed_elements_by_tag_name("a"),
+            Some(document.get().elements_by_tag_name("a"))
+        );
+
+        document.get_mut().disable_indexes();
+        assert_eq!(document.get().indexed_elements_by_tag_name("a"), None);
+    }
+
+    #[test]
+    fn indexes_follow_mutations() {
+        let mut document = parse("<p><a href=/1>1</a><a name=x>2</a></p>");
+        document.get_mut().enable_indexes(&["href"]);
+        assert_eq!(hrefs(&document), vec!["/1"]);
+
+        // New elements
+        let body = document.get().elements_by_tag_name("body")[0];
+        let attributes = HashMap::from([("href".to_string(), "/2".to_string())]);
+        let link = document
+            .add_node(
+                Node::new_element("a", attributes, HTML_NAMESPACE),
+                body,
+                None,
+            )
+            .unwrap();
+        assert_eq!(hrefs(&document), vec!["/1", "/2"]);
+        assert_eq!(
+            document
+                .get()
+                .indexed_elements_by_tag_name("a")
+                .unwrap()
+                .len(),
+            3
+        );
+
+        // New attributes
+        let named = document.get().elements_by_tag_name("a")[1];
+        document.insert_attribute("href", "/3", named).unwrap();
+        assert_eq!(hrefs(&document), vec!["/1", "/3", "/2"]);
+
+        // Removed and re-attached elements
+        document.detach_node_from_parent(link);
+        assert_eq!(hrefs(&document), vec!["/1", "/3"]);
+        let p = document.get().elements_by_tag_name("p")[0];
+        document.relocate(link, p);
+        assert_eq!(hrefs(&document), vec!["/1", "/3", "/2"]);
+    }
+
+    #[test]
+    fn indexes_during_parsing() {
+        let mut document = DocumentBuilder::new_document();
+        document.get_mut().enable_indexes(&["href"]);
+
+        let mut chars = CharIterator::new();
+        chars.read_from_str(
+            "<html href=h><a href=1>x<body href=b><table><a href=2>",
+            Some(Encoding::UTF8),
+        );
+        let _ = Html5Parser::parse_document(&mut chars, Document::clone(&document), None);
+
+        assert_eq!(hrefs(&document).len(), 4);
+        assert_eq!(
+            document.get().indexed_elements_by_tag_name("table"),
+            Some(document.get().elements_by_tag_name("table"))
+        );
+    }
+}
@@ -0,0 +1,373 @@
+//! Convenience insertion methods
+//!
+//! The `append()`, `prepend()`, `before()`, `after()` and `replace_with()` methods of the DOM
+//! (from the `ParentNode` and `ChildNode` mixins). They take a mix of nodes and strings: strings
+//! become new text nodes, and nodes are moved from wherever they are in the tree. This makes
+//! building a tree by hand a lot less verbose than creating and attaching every node:
+//!
+//! ```
+//! use gosub_engine::html5::node::{Node, NodeId, HTML_NAMESPACE};
+//! use gosub_engine::html5::parser::document::DocumentBuilder;
+//! use std::collections::HashMap;
+//!
+//! let mut document = DocumentBuilder::new_document();
+//! let p = document.add_node(Node::new_element("p", HashMap::new(), HTML_NAMESPACE), NodeId::root(), None).unwrap();
+//! let b = document.get_mut().add_new_node(Node::new_element("b", HashMap::new(), HTML_NAMESPACE));
+//! document.append(p, ["Hello ".into(), b.into(), "!".into()]).unwrap();
+//! document.append(b, ["world".into()]).unwrap();
+//! assert_eq!(document.get().text_content(p), "Hello world!");
+//! ```
+//!
+//! Every call is a single transaction in the undo history.
+//!
+//! See: https://dom.spec.whatwg.org/#interface-parentnode
+use crate::html5::node::{Node, NodeId, NodeTrait, NodeType};
+use crate::html5::parser::document::{Document, DocumentHandle};
+use crate::types::{Error, HierarchyError, Result};
+
+/// A node, or a string that is inserted as a text node
+#[derive(Debug, Clone, PartialEq)]
+pub enum NodeOrText {
+    Node(NodeId),
+    Text(String),
+}
+
+impl From<NodeId> for NodeOrText {
+    fn from(node_id: NodeId) -> Self {
+        NodeOrText::Node(node_id)
+    }
+}
+
+impl From<&str> for NodeOrText {
+    fn from(text: &str) -> Self {
+        NodeOrText::Text(text.to_string())
+    }
+}
+
+impl From<String> for NodeOrText {
+    fn from(text: String) -> Self {
+        NodeOrText::Text(text)
+    }
+}
+
+/// Where the nodes are inserted in the parent
+enum Anchor {
+    /// After the child, or in front of the first child when None
+    After(Option<NodeId>),
+    /// In front of the child, or after the last child when None
+    Before(Option<NodeId>),
+}
+
+impl Document {
+    /// Inserts the nodes (and text) into the parent at the anchor. Nodes are moved from where
+    /// they are. Fails (without changing anything) when a node cannot be inserted there.
+    fn insert_nodes(
+        &mut self,
+        parent_id: NodeId,
+        anchor: Anchor,
+        items: &[NodeOrText],
+    ) -> Result<()> {
+        let Some(parent) = self.get_node_by_id(parent_id) else {
+            return Err(HierarchyError::NotFound.into());
+        };
+        // A node that is given more than once ends up at its last position, like when the nodes
+        // are appended one by one to a fragment
+        let items: Vec<&NodeOrText> = items
+            .iter()
+            .enumerate()
+            .filter(|&(index, item)| match item {
+                NodeOrText::Node(node_id) => !items[index + 1..]
+                    .iter()
+                    .any(|later| *later == NodeOrText::Node(*node_id)),
+                NodeOrText::Text(_) => true,
+            })
+            .map(|(_, item)| item)
+            .collect();
+        let mut kinds = Vec::with_capacity(items.len());
+        let mut moved = Vec::new();
+        for item in &items {
+            match item {
+                NodeOrText::Node(node_id) => {
+                    self.check_child(*node_id, parent_id)?;
+                    if let Some(node) = self.get_node_by_id(*node_id) {
+                        kinds.push(node.type_of());
+                    }
+                    moved.push(*node_id);
+                }
+                NodeOrText::Text(_) => {
+                    if parent.type_of() != NodeType::Document
+                        && parent.type_of() != NodeType::Element
+                    {
+                        return Err(HierarchyError::ParentCannotHaveChildren.into());
+                    }
+                    kinds.push(NodeType::Text);
+                }
+            }
+        }
+        // The anchor is looked up in the children that stay in the parent
+        let remaining: Vec<NodeId> = parent
+            .children
+            .iter()
+            .copied()
+            .filter(|child| !moved.contains(child))
+            .collect();
+        let index = |child: NodeId| remaining.iter().position(|&id| id == child);
+        let mut position = match anchor {
+            Anchor::After(None) => Some(0),
+            Anchor::Before(None) => None,
+            Anchor::After(Some(child)) => index(child).map(|index| index + 1),
+            Anchor::Before(Some(child)) => index(child),
+        };
+        self.check_document_children(parent_id, &kinds, &moved, position)?;
+
+        self.begin_transaction();
+        let mut node_ids = Vec::with_capacity(items.len());
+        for item in &items {
+            let node_id = match item {
+                NodeOrText::Node(node_id) => {
+                    self.detach_node_from_parent(*node_id);
+                    *node_id
+                }
+                NodeOrText::Text(text) => self.add_new_node(Node::new_text(text)),
+            };
+            node_ids.push(node_id);
+        }
+
+        let mut result = Ok(());
+        for node_id in node_ids {
+            result = self.attach_node_to_parent(node_id, parent_id, position);
+            if result.is_err() {
+                break;
+            }
+            position = position.map(|index| index + 1);
+        }
+        self.end_transaction();
+
+        result
+    }
+
+    /// Returns the parent of the node and its siblings (including the node), or None when the
+    /// node has no parent
+    fn parent_and_siblings(&self, node_id: NodeId) -> Result<Option<(NodeId, Vec<NodeId>)>> {
+        let Some(node) = self.get_node_by_id(node_id) else {
+            return Err(Error::DocumentTask(format!(
+                "Node ID {} not found",
+                node_id
+            )));
+        };
+        Ok(node.parent.map(|parent_id| {
+            let siblings = self
+                .get_node_by_id(parent_id)
+                .map(|parent| parent.children.clone())
+                .unwrap_or_default();
+            (parent_id, siblings)
+        }))
+    }
+}
+
+/// Returns true when the node is one of the nodes that are inserted
+fn is_inserted(items: &[NodeOrText], node_id: NodeId) -> bool {
+    items.contains(&NodeOrText::Node(node_id))
+}
+
+impl DocumentHandle {
+    /// Inserts the nodes and text after the last child of the parent
+    pub fn append(
+        &mut self,
+        parent_id: NodeId,
+        items: impl IntoIterator<Item = NodeOrText>,
+    ) -> Result<()> {
+        let items: Vec<NodeOrText> = items.into_iter().collect();
+        self.get_mut()
+            .insert_nodes(parent_id, Anchor::Before(None), &items)
+    }
+
+    /// Inserts the nodes and text in front of the first child of the parent
+    pub fn prepend(
+        &mut self,
+        parent_id: NodeId,
+        items: impl IntoIterator<Item = NodeOrText>,
+    ) -> Result<()> {
+        let items: Vec<NodeOrText> = items.into_iter().collect();
+        self.get_mut()
+            .insert_nodes(parent_id, Anchor::After(None), &items)
+    }
+
+    /// Inserts the nodes and text in front of the node. Does nothing when the node has no parent.
+    pub fn before(
+        &mut self,
+        node_id: NodeId,
+        items: impl IntoIterator<Item = NodeOrText>,
+    ) -> Result<()> {
+        let items: Vec<NodeOrText> = items.into_iter().collect();
+        let mut doc = self.get_mut();
+        let Some((parent_id, siblings)) = doc.parent_and_siblings(node_id)? else {
+            return Ok(());
+        };
+
+        // The node itself can be one of the inserted nodes, so insert after the first previous
+        // sibling that stays where it is
+        let index = siblings.iter().position(|&id| id == node_id).unwrap_or(0);
+        let previous = siblings[..index]
+            .iter()
+            .rev()
+            .copied()
+            .find(|&id| !is_inserted(&items, id));
+        doc.insert_nodes(parent_id, Anchor::After(previous), &items)
+    }
+
+    /// Inserts the nodes and text after the node. Does nothing when the node has no parent.
+    pub fn after(
+        &mut self,
+        node_id: NodeId,
+        items: impl IntoIterator<Item = NodeOrText>,
+    ) -> Result<()> {
+        let items: Vec<NodeOrText> = items.into_iter().collect();
+        let mut doc = self.get_mut();
+        let Some((parent_id, siblings)) = doc.parent_and_siblings(node_id)? else {
+            return Ok(());
+        };
+
+        let next = next_sibling_staying(&siblings, node_id, &items);
+        doc.insert_nodes(parent_id, Anchor::Before(next), &items)
+    }
+
+    /// Replaces the node by the nodes and text. The node is detached (not removed from the
+    /// document). Does nothing when the node has no parent.
+    pub fn replace_with(
+        &mut self,
+        node_id: NodeId,
+        items: impl IntoIterator<Item = NodeOrText>,
+    ) -> Result<()> {
+        let items: Vec<NodeOrText> = items.into_iter().collect();
+        let mut doc = self.get_mut();
+        let Some((parent_id, siblings)) = doc.parent_and_siblings(node_id)? else {
+            return Ok(());
+        };
+
+        let next = next_sibling_staying(&siblings, node_id, &items);
+        doc.begin_transaction();
+        let result = doc.insert_nodes(parent_id, Anchor::Before(next), &items);
+        if result.is_ok() && !is_inserted(&items, node_id) {
+            doc.detach_node_from_parent(node_id);
+        }
+        doc.end_transaction();
+        result
+    }
+}
+
+/// Returns the first next sibling of the node that is not one of the inserted nodes
+fn next_sibling_staying(
+    siblings: &[NodeId],
+    node_id: NodeId,
+    items: &[NodeOrText],
+) -> Option<NodeId> {
+    let index = siblings.iter().position(|&id| id == node_id)?;
+    siblings[index + 1..]
+        .iter()
+        .copied()
+        .find(|&id| !is_inserted(items, id))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::html5::node::HTML_NAMESPACE;
+    use crate::html5::parser::document::DocumentBuilder;
+    use crate::html5::parser::tree_builder::TreeBuilder;
+
+    fn setup() -> (DocumentHandle, NodeId, NodeId, NodeId) {
+        let mut document = DocumentBuilder::new_document();
+        let div = document
+            .create_element("div", NodeId::root(), None, HTML_NAMESPACE)
+            .unwrap();
+        let a = document
+            .create_element("a", div, None, HTML_NAMESPACE)
+            .unwrap();
+        let b = document
+            .create_element("b", div, None, HTML_NAMESPACE)
+            .unwrap();
+        (document, div, a, b)
+    }
+
+    fn html(document: &DocumentHandle, node_id: NodeId) -> String {
+        crate::html5::element_ref::ElementRef::wrap(&document.get(), node_id)
+            .unwrap()
+            .inner_html()
+    }
+
+    #[test]
+    fn parent_node_methods() {
+        let (mut document, div, a, b) = setup();
+
+        document.append(div, ["x".into(), a.into()]).unwrap();
+        assert_eq!(html(&document, div), "<b></b>x<a></a>");
+        document
+            .prepend(div, [a.into(), "y".to_string().into()])
+            .unwrap();
+        assert_eq!(html(&document, div), "<a></a>y<b></b>x");
+
+        // Nodes cannot be inserted into themselves, their descendants or non-elements
+        assert!(document.append(a, [div.into()]).is_err());
+        assert!(document.append(a, [a.into()]).is_err());
+        let text = document.get().get_node_by_id(div).unwrap().children[1];
+        assert!(document.append(text, ["z".into()]).is_err());
+        assert!(document.append(div, [NodeId::from(42).into()]).is_err());
+        assert_eq!(html(&document, div), "<a></a>y<b></b>x");
+
+        // A single undo step per call
+        document.get_mut().enable_history();
+        document
+            .append(b, ["1".into(), "2".into(), a.into()])
+            .unwrap();
+        assert_eq!(html(&document, div), "y<b>12<a></a></b>x");
+        assert!(document.get_mut().undo());
+        assert_eq!(html(&document, div), "<a></a>y<b></b>x");
+    }
+
+    #[test]
+    fn duplicate_nodes() {
+        let (mut document, div, a, b) = setup();
+
+        // A node given twice is inserted once, at its last position
+        document.append(div, [b.into(), b.into()]).unwrap();
+        assert_eq!(html(&document, div), "<a></a><b></b>");
+        document
+            .prepend(div, [b.into(), "x".into(), a.into(), b.into()])
+            .unwrap();
+        assert_eq!(html(&document, div), "x<a></a><b></b>");
+        assert_eq!(
+            document.get().get_node_by_id(div).unwrap().children.len(),
+            3
+        );
+    }
+
+    #[test]
+    fn child_node_methods() {
+        let (mut document, div, a, b) = setup();
+
+        document.before(b, ["1".into()]).unwrap();
+        document.after(a, ["2".into()]).unwrap();
+        assert_eq!(html(&document, div), "<a></a>21<b></b>");
+
+        // The node itself can be one of the inserted nodes
+        document
+            .before(b, [b.into(), "3".into(), a.into()])
+            .unwrap();
+        assert_eq!(html(&document, div), "21<b></b>3<a></a>");
+        document.after(a, ["4".into(), a.into()]).unwrap();
+        assert_eq!(html(&document, div), "21<b></b>34<a></a>");
+
+        document.replace_with(b, ["5".into(), a.into()]).unwrap();
+        assert_eq!(html(&document, div), "215<a></a>34");
+        assert_eq!(document.get().get_node_by_id(b).unwrap().parent, None);
+        document.replace_with(a, [a.into(), b.into()]).unwrap();
+        assert_eq!(html(&document, div), "215<a></a><b></b>34");
+
+        // Nothing happens for nodes without a parent
+        let detached = document.get_mut().add_new_node(Node::new_text("t"));
+        document.after(detached, ["6".into()]).unwrap();
+        document.replace_with(detached, ["7".into()]).unwrap();
+        assert_eq!(html(&document, div), "215<a></a><b></b>34");
+    }
+}
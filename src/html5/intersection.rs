@@ -0,0 +1,447 @@
+//! Intersection observers
+//!
+//! An intersection observer watches how much of its target elements is visible inside a root:
+//! the viewport, or an element that contains the targets. Each observer has a list of thresholds
+//! (ratios of the area of the target). Whenever a target crosses one of them, or starts or stops
+//! intersecting the root, the callback of the observer is invoked with an entry for the target.
+//! The first observation of a target is always reported.
+//!
+//! Intersections are computed from the layout geometry of the embedder, which calls
+//! `update_intersection_observations()` after every layout or scroll. Clipping by the overflow
+//! of ancestors is not taken into account.
+//!
+//! See: https://w3c.github.io/IntersectionObserver/
+use crate::html5::compaction::{remap, NodeIdMap};
+use crate::html5::geometry::Rect;
+use crate::html5::node::NodeId;
+use crate::html5::parser::document::{Document, DocumentHandle};
+use crate::types::{Error, Result};
+use core::fmt;
+use core::fmt::Debug;
+use std::rc::Rc;
+
+/// Callback that is invoked with the changed intersections of the targets of an observer
+pub type IntersectionCallback = Rc<dyn Fn(&DocumentHandle, &[IntersectionEntry])>;
+
+/// Id of an intersection observer in a document
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct IntersectionObserverId(usize);
+
+/// Options of an intersection observer
+#[derive(Debug, Clone, PartialEq)]
+pub struct IntersectionObserverOptions {
+    /// Element the targets are intersected with, or the viewport when None
+    pub root: Option<NodeId>,
+    /// Margins (top, right, bottom, left) in pixels that grow (or shrink) the root
+    pub root_margin: [f32; 4],
+    /// Ratios of the area of a target at which the callback is invoked, in ascending order
+    pub thresholds: Vec<f32>,
+}
+
+impl Default for IntersectionObserverOptions {
+    fn default() -> Self {
+        Self {
+            root: None,
+            root_margin: [0.0; 4],
+            thresholds: vec![0.0],
+        }
+    }
+}
+
+/// Intersection of a target with the root of an observer
+#[derive(Debug, Clone, PartialEq)]
+pub struct IntersectionEntry {
+    pub target: NodeId,
+    /// Border box of the target
+    pub bounding_client_rect: Rect,
+    /// Part of the target that is inside the root
+    pub intersection_rect: Rect,
+    /// Root (including its margins) the target is intersected with
+    pub root_bounds: Rect,
+    /// Ratio of the area of the target that is inside the root
+    pub intersection_ratio: f32,
+    pub is_intersecting: bool,
+}
+
+#[derive(PartialEq)]
+struct Observation {
+    target: NodeId,
+    /// Index of the first threshold above the last ratio, or -1 when never computed
+    threshold_index: i32,
+    is_intersecting: bool,
+}
+
+struct IntersectionObserver {
+    options: IntersectionObserverOptions,
+    callback: IntersectionCallback,
+    observations: Vec<Observation>,
+}
+
+/// Intersection observers of a document
+#[derive(Default)]
+pub(crate) struct IntersectionObservers {
+    observers: Vec<Option<IntersectionObserver>>,
+}
+
+impl Debug for IntersectionObservers {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let count = self.observers.iter().flatten().count();
+        write!(f, "IntersectionObservers({count})")
+    }
+}
+
+impl PartialEq for IntersectionObservers {
+    fn eq(&self, other: &Self) -> bool {
+        self.observers.len() == other.observers.len()
+            && self
+                .observers
+                .iter()
+                .zip(&other.observers)
+                .all(|(a, b)| match (a, b) {
+                    (Some(a), Some(b)) => {
+                        a.options == b.options
+                            && a.observations == b.observations
+                            && Rc::ptr_eq(&a.callback, &b.callback)
+                    }
+                    (a, b) => a.is_none() && b.is_none(),
+                })
+    }
+}
+
+impl IntersectionObservers {
+    /// Replaces the roots and targets of the observers by their new IDs
+    pub(crate) fn remap_nodes(&mut self, ids: &NodeIdMap) {
+        for observer in self.observers.iter_mut().flatten() {
+            observer.options.root = observer.options.root.and_then(|root| remap(ids, root));
+            observer.observations.retain_mut(|observation| {
+                remap(ids, observation.target)
+                    .map(|target| observation.target = target)
+                    .is_some()
+            });
+        }
+    }
+
+    /// Stops observing the node, as it has been removed from the document
+    pub(crate) fn forget_node(&mut self, node_id: NodeId) {
+        for observer in self.observers.iter_mut().flatten() {
+            observer
+                .observations
+                .retain(|observation| observation.target != node_id);
+        }
+    }
+}
+
+impl Document {
+    /// Creates an intersection observer. It does not observe anything until targets are added
+    /// with `observe_intersection()`.
+    pub fn create_intersection_observer(
+        &mut self,
+        options: IntersectionObserverOptions,
+        callback: IntersectionCallback,
+    ) -> IntersectionObserverId {
+        let mut options = options;
+        if options.thresholds.is_empty() {
+            options.thresholds.push(0.0);
+        }
+        options.thresholds.sort_by(|a, b| a.total_cmp(b));
+
+        let observers = &mut self.intersection_observers.observers;
+        observers.push(Some(IntersectionObserver {
+            options,
+            callback,
+            observations: Vec::new(),
+        }));
+        IntersectionObserverId(observers.len() - 1)
+    }
+
+    /// Starts observing the target
+    pub fn observe_intersection(
+        &mut self,
+        observer_id: IntersectionObserverId,
+        target: NodeId,
+    ) -> Result<()> {
+        if self.get_node_by_id(target).is_none() {
+            return Err(Error::DocumentTask(format!(
+                "Node ID {} does not exist",
+                target
+            )));
+        }
+
+        let observer = self.intersection_observer_mut(observer_id)?;
+        if !observer.observations.iter().any(|o| o.target == target) {
+            observer.observations.push(Observation {
+                target,
+                threshold_index: -1,
+                is_intersecting: false,
+            });
+        }
+        Ok(())
+    }
+
+    /// Stops observing the target
+    pub fn unobserve_intersection(
+        &mut self,
+        observer_id: IntersectionObserverId,
+        target: NodeId,
+    ) -> Result<()> {
+        let observer = self.intersection_observer_mut(observer_id)?;
+        observer.observations.retain(|o| o.target != target);
+        Ok(())
+    }
+
+    /// Stops observing all targets, and removes the observer
+    pub fn disconnect_intersection_observer(&mut self, observer_id: IntersectionObserverId) {
+        if let Some(observer) = self.intersection_observers.observers.get_mut(observer_id.0) {
+            *observer = None;
+        }
+    }
+
+    /// Computes the intersection of the target with the root (and its margins)
+    pub fn intersection(
+        &self,
+        target: NodeId,
+        options: &IntersectionObserverOptions,
+    ) -> IntersectionEntry {
+        let bounding_client_rect = self.bounding_client_rect(target);
+        let root_bounds = match options.root {
+            Some(root) => self.bounding_client_rect(root),
+            None => self.viewport_rect(),
+        }
+        .map(|rect| rect.inflate(options.root_margin));
+
+        // Targets must be inside an explicit root
+        let in_root = options.root.map_or(true, |root| {
+            root != target && self.is_inclusive_ancestor(root, target)
+        });
+        let intersection_rect = match (bounding_client_rect, root_bounds) {
+            (Some(target_rect), Some(root_rect)) if in_root => target_rect.intersection(&root_rect),
+            _ => None,
+        };
+
+        let bounding_client_rect = bounding_client_rect.unwrap_or_default();
+        let is_intersecting = intersection_rect.is_some();
+        let intersection_ratio = match intersection_rect {
+            Some(rect) if bounding_client_rect.area() > 0.0 => {
+                rect.area() / bounding_client_rect.area()
+            }
+            // Targets without area are either fully inside or fully outside the root
+            Some(_) => 1.0,
+            None => 0.0,
+        };
+
+        IntersectionEntry {
+            target,
+            bounding_client_rect,
+            intersection_rect: intersection_rect.unwrap_or_default(),
+            root_bounds: root_bounds.unwrap_or_default(),
+            intersection_ratio,
+            is_intersecting,
+        }
+    }
+
+    fn intersection_observer_mut(
+        &mut self,
+        observer_id: IntersectionObserverId,
+    ) -> Result<&mut IntersectionObserver> {
+        self.intersection_observers
+            .observers
+            .get_mut(observer_id.0)
+            .and_then(Option::as_mut)
+            .ok_or_else(|| {
+                Error::DocumentTask(format!(
+                    "intersection observer {} does not exist",
+                    observer_id.0
+                ))
+            })
+    }
+
+    fn is_inclusive_ancestor(&self, ancestor: NodeId, node_id: NodeId) -> bool {
+        ancestor == node_id || self.is_ancestor_of(ancestor, node_id)
+    }
+}
+
+impl DocumentHandle {
+    /// Computes the intersections of all observed targets, and invokes the callbacks of the
+    /// observers with the targets that crossed a threshold. Returns the number of callbacks that
+    /// were invoked.
+    pub fn update_intersection_observations(&mut self) -> usize {
+        let mut notifications = Vec::new();
+
+        {
+            let mut doc = self.get_mut();
+            let doc = &mut *doc;
+            let observers = std::mem::take(&mut doc.intersection_observers.observers);
+
+            let mut updated = Vec::with_capacity(observers.len());
+            for mut observer in observers {
+                if let Some(observer) = observer.as_mut() {
+                    let entries = observe(doc, observer);
+                    if !entries.is_empty() {
+                        notifications.push((observer.callback.clone(), entries));
+                    }
+                }
+                updated.push(observer);
+            }
+
+            // Observers created while computing are kept as well
+            updated.append(&mut doc.intersection_observers.observers);
+            doc.intersection_observers.observers = updated;
+        }
+
+        for (callback, entries) in &notifications {
+            callback(self, entries);
+        }
+        notifications.len()
+    }
+}
+
+/// Updates the observations of the observer, and returns the entries of those that changed
+fn observe(document: &Document, observer: &mut IntersectionObserver) -> Vec<IntersectionEntry> {
+    let mut entries = Vec::new();
+
+    for observation in &mut observer.observations {
+        let entry = document.intersection(observation.target, &observer.options);
+
+        let thresholds = &observer.options.thresholds;
+        let threshold_index = if entry.is_intersecting {
+            thresholds
+                .iter()
+                .position(|threshold| *threshold > entry.intersection_ratio)
+                .unwrap_or(thresholds.len()) as i32
+        } else {
+            0
+        };
+
+        if threshold_index != observation.threshold_index
+            || entry.is_intersecting != observation.is_intersecting
+        {
+            observation.threshold_index = threshold_index;
+            observation.is_intersecting = entry.is_intersecting;
+            entries.push(entry);
+        }
+    }
+
+    entries
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{parse, Boxes};
+    use std::cell::RefCell;
+
+    type Log = Rc<RefCell<Vec<(NodeId, f32, bool)>>>;
+
+    fn setup() -> (DocumentHandle, Rc<Boxes>, Log, IntersectionCallback) {
+        let mut document = parse("<div id='scroller'><img id='a'><img id='b'></div><img id='c'>");
+
+        let boxes = Rc::new(Boxes::new(Rect::new(0.0, 0.0, 100.0, 100.0)));
+        document.get_mut().set_layout_geometry(boxes.clone());
+
+        let log: Log = Rc::new(RefCell::new(Vec::new()));
+        let recorder = log.clone();
+        let callback: IntersectionCallback = Rc::new(move |_, entries| {
+            for entry in entries {
+                recorder.borrow_mut().push((
+                    entry.target,
+                    entry.intersection_ratio,
+                    entry.is_intersecting,
+                ));
+            }
+        });
+        (document, boxes, log, callback)
+    }
+
+    fn named(document: &DocumentHandle, id: &str) -> NodeId {
+        document.get().get_node_by_named_id(id).unwrap().id
+    }
+
+    #[test]
+    fn viewport_thresholds() {
+        let (mut document, boxes, log, callback) = setup();
+        let a = named(&document, "a");
+        boxes.set(a, Rect::new(0.0, 200.0, 50.0, 40.0));
+
+        let options = IntersectionObserverOptions {
+            thresholds: vec![1.0, 0.0, 0.5],
+            ..Default::default()
+        };
+        let observer = document
+            .get_mut()
+            .create_intersection_observer(options, callback);
+        document
+            .get_mut()
+            .observe_intersection(observer, a)
+            .unwrap();
+
+        // The first observation is always reported
+        assert_eq!(document.update_intersection_observations(), 1);
+        assert_eq!(log.borrow().as_slice(), [(a, 0.0, false)]);
+
+        // Scrolling a quarter of the image into view
+        boxes.set(a, Rect::new(0.0, 90.0, 50.0, 40.0));
+        assert_eq!(document.update_intersection_observations(), 1);
+        assert_eq!(log.borrow()[1], (a, 0.25, true));
+
+        // Still between the same thresholds
+        boxes.set(a, Rect::new(0.0, 85.0, 50.0, 40.0));
+        assert_eq!(document.update_intersection_observations(), 0);
+
+        boxes.set(a, Rect::new(0.0, 10.0, 50.0, 40.0));
+        assert_eq!(document.update_intersection_observations(), 1);
+        assert_eq!(log.borrow()[2], (a, 1.0, true));
+
+        document
+            .get_mut()
+            .disconnect_intersection_observer(observer);
+        boxes.remove(a);
+        assert_eq!(document.update_intersection_observations(), 0);
+    }
+
+    #[test]
+    fn element_root_with_margin() {
+        let (mut document, boxes, log, callback) = setup();
+        let (scroller, a, b, c) = (
+            named(&document, "scroller"),
+            named(&document, "a"),
+            named(&document, "b"),
+            named(&document, "c"),
+        );
+        boxes.set(scroller, Rect::new(0.0, 0.0, 100.0, 50.0));
+        boxes.set(a, Rect::new(0.0, 40.0, 100.0, 20.0));
+        boxes.set(b, Rect::new(0.0, 100.0, 100.0, 20.0));
+        boxes.set(c, Rect::new(0.0, 0.0, 100.0, 20.0));
+
+        // Images within 60 pixels below the scroller are loaded in advance
+        let options = IntersectionObserverOptions {
+            root: Some(scroller),
+            root_margin: [0.0, 0.0, 60.0, 0.0],
+            ..Default::default()
+        };
+        let observer = document
+            .get_mut()
+            .create_intersection_observer(options, callback);
+        for target in [a, b, c] {
+            document
+                .get_mut()
+                .observe_intersection(observer, target)
+                .unwrap();
+        }
+
+        document.update_intersection_observations();
+        // The third image is not inside the root
+        assert_eq!(
+            log.borrow().as_slice(),
+            [(a, 1.0, true), (b, 0.5, true), (c, 0.0, false)]
+        );
+
+        document
+            .get_mut()
+            .unobserve_intersection(observer, b)
+            .unwrap();
+        boxes.remove(a);
+        document.update_intersection_observations();
+        assert_eq!(log.borrow()[3], (a, 0.0, false));
+        assert_eq!(log.borrow().len(), 4);
+    }
+}
@@ -0,0 +1,243 @@
+//! Document lifecycle
+//!
+//! A document goes through three readiness states. It is `loading` while it is being parsed, and
+//! becomes `interactive` when the parser stops. After the deferred scripts have been executed,
+//! `DOMContentLoaded` is fired. Once all resources that delay the load event (images,
+//! stylesheets, async scripts, ...) have been loaded as well, the document is `complete` and
+//! `load` is fired. Every change of the state fires `readystatechange`.
+//!
+//! The parser drives the first part. Resource loading is up to the embedder (or the script
+//! handler for async scripts), which reports it through `delay_load()` and `resource_loaded()`.
+//! All events are dispatched to the document node, so embedders can wait for a readiness point by
+//! adding a listener to the root node.
+//!
+//! See: https://html.spec.whatwg.org/multipage/parsing.html#the-end
+use crate::html5::events::Event;
+use crate::html5::node::NodeId;
+use crate::html5::parser::document::{Document, DocumentHandle};
+
+/// Readiness of a document (as in `document.readyState`)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DocumentReadyState {
+    /// The document is being parsed
+    #[default]
+    Loading,
+    /// The document has been parsed, but resources are still loading
+    Interactive,
+    /// The document and all its resources have been loaded
+    Complete,
+}
+
+impl DocumentReadyState {
+    /// Returns the state as used by `document.readyState`
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DocumentReadyState::Loading => "loading",
+            DocumentReadyState::Interactive => "interactive",
+            DocumentReadyState::Complete => "complete",
+        }
+    }
+}
+
+/// Lifecycle state of a document
+#[derive(Debug, Default, PartialEq)]
+pub(crate) struct Lifecycle {
+    state: DocumentReadyState,
+    /// True once DOMContentLoaded has been fired
+    content_loaded: bool,
+    /// Number of resources that delay the load event
+    pending_loads: usize,
+}
+
+impl Document {
+    /// Returns the readiness of the document
+    pub fn ready_state(&self) -> DocumentReadyState {
+        self.lifecycle.state
+    }
+}
+
+impl DocumentHandle {
+    /// Marks the start of loading a resource that delays the load event. Every call must be
+    /// followed by a call to `resource_loaded()`, also when loading fails.
+    pub fn delay_load(&mut self) {
+        self.get_mut().lifecycle.pending_loads += 1;
+    }
+
+    /// Marks a resource from `delay_load()` as loaded. When this was the last one and the document
+    /// has been parsed, the document is complete.
+    pub fn resource_loaded(&mut self) {
+        {
+            let mut doc = self.get_mut();
+            doc.lifecycle.pending_loads = doc.lifecycle.pending_loads.saturating_sub(1);
+        }
+        self.complete_if_loaded();
+    }
+
+    /// Changes the readiness of the document and fires `readystatechange`
+    pub(crate) fn set_ready_state(&mut self, state: DocumentReadyState) {
+        if self.get().lifecycle.state == state {
+            return;
+        }
+        self.get_mut().lifecycle.state = state;
+
+        let mut event = Event::new("readystatechange", false, false);
+        self.dispatch_event(NodeId::root(), &mut event);
+    }
+
+    /// Fires `DOMContentLoaded`. Called by the parser after the deferred scripts have been
+    /// executed.
+    pub(crate) fn dom_content_loaded(&mut self) {
+        self.get_mut().lifecycle.content_loaded = true;
+
+        let mut event = Event::new("DOMContentLoaded", true, false);
+        self.dispatch_event(NodeId::root(), &mut event);
+
+        self.complete_if_loaded();
+    }
+
+    /// Completes the document and fires `load` when parsing has finished and no resources are
+    /// pending anymore
+    fn complete_if_loaded(&mut self) {
+        let ready = {
+            let doc = self.get();
+            doc.lifecycle.content_loaded
+                && doc.lifecycle.pending_loads == 0
+                && doc.lifecycle.state != DocumentReadyState::Complete
+        };
+        if !ready {
+            return;
+        }
+
+        self.set_ready_state(DocumentReadyState::Complete);
+        let mut event = Event::new("load", false, false);
+        self.dispatch_event(NodeId::root(), &mut event);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bytes::{CharIterator, Encoding};
+    use crate::html5::events::{EventCallback, ListenerOptions};
+    use crate::html5::parser::document::DocumentBuilder;
+    use crate::html5::parser::limits::ParserLimits;
+    use crate::html5::parser::script::{Script, ScriptHandler, ScriptTiming};
+    use crate::html5::parser::{Html5Parser, Html5ParserOptions};
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    type Log = Rc<RefCell<Vec<String>>>;
+
+    /// Logs the scripts with the readiness at the time they run. Async scripts delay the load
+    /// event until the test finishes them.
+    struct LoggingScriptHandler {
+        log: Log,
+    }
+
+    impl ScriptHandler for LoggingScriptHandler {
+        fn execute(&mut self, document: &DocumentHandle, script: &Script) {
+            self.log.borrow_mut().push(format!(
+                "{:?}:{}",
+                script.timing,
+                document.get().ready_state().as_str()
+            ));
+        }
+
+        fn schedule_async(&mut self, document: &DocumentHandle, script: &Script) {
+            assert_eq!(script.timing, ScriptTiming::Async);
+            Document::clone(document).delay_load();
+        }
+    }
+
+    fn parse_with_listeners(html: &str, log: &Log) -> DocumentHandle {
+        let mut document = DocumentBuilder::new_document();
+        for event_type in ["readystatechange", "DOMContentLoaded", "load"] {
+            let log = log.clone();
+            let callback: EventCallback = Rc::new(move |document, event| {
+                log.borrow_mut().push(format!(
+                    "{}:{}",
+                    event.event_type,
+                    document.get().ready_state().as_str()
+                ));
+            });
+            document.get_mut().add_event_listener(
+                NodeId::root(),
+                event_type,
+                callback,
+                ListenerOptions::default(),
+            );
+        }
+
+        let options = Html5ParserOptions {
+            scripting_enabled: true,
+            script_handler: Some(Rc::new(RefCell::new(LoggingScriptHandler {
+                log: log.clone(),
+            }))),
+            tree_sink: None,
+            event_handler: None,
+            limits: ParserLimits::default(),
+        };
+        let mut chars = CharIterator::new();
+        chars.read_from_str(html, Some(Encoding::UTF8));
+        let _ = Html5Parser::parse_document(&mut chars, Document::clone(&document), Some(options));
+
+        document
+    }
+
+    #[test]
+    fn readiness_events() {
+        let log = Log::default();
+        let document = parse_with_listeners(
+            "<script>inline()</script><script defer src=d.js></script><p>x</p>",
+            &log,
+        );
+
+        assert_eq!(document.get().ready_state(), DocumentReadyState::Complete);
+        assert_eq!(
+            *log.borrow(),
+            vec![
+                "ParserBlocking:loading",
+                "readystatechange:interactive",
+                "Defer:interactive",
+                "DOMContentLoaded:interactive",
+                "readystatechange:complete",
+                "load:complete",
+            ]
+        );
+    }
+
+    #[test]
+    fn resources_delay_load() {
+        let log = Log::default();
+        let mut document = parse_with_listeners(
+            "<script async src=a.js></script><script async src=b.js></script>",
+            &log,
+        );
+
+        assert_eq!(
+            document.get().ready_state(),
+            DocumentReadyState::Interactive
+        );
+        assert_eq!(log.borrow().last().unwrap(), "DOMContentLoaded:interactive");
+
+        document.resource_loaded();
+        assert_eq!(
+            document.get().ready_state(),
+            DocumentReadyState::Interactive
+        );
+        document.resource_loaded();
+        assert_eq!(document.get().ready_state(), DocumentReadyState::Complete);
+        assert_eq!(log.borrow().last().unwrap(), "load:complete");
+
+        // The load event is only fired once
+        document.delay_load();
+        document.resource_loaded();
+        assert_eq!(
+            log.borrow()
+                .iter()
+                .filter(|entry| *entry == "load:complete")
+                .count(),
+            1
+        );
+    }
+}
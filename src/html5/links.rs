@@ -0,0 +1,257 @@
+//! Link extraction
+//!
+//! Collects the resources a document refers to: hyperlinks, stylesheets, scripts and alternate
+//! feeds (RSS / Atom). All locations are resolved against the base URL of the document.
+use crate::html5::node::{Node, NodeId, HTML_NAMESPACE};
+use crate::html5::parser::document::Document;
+use url::Url;
+
+/// Mime types of `<link rel="alternate">` elements that point to a feed
+const FEED_TYPES: [&str; 4] = [
+    "application/rss+xml",
+    "application/atom+xml",
+    "application/feed+json",
+    "application/json+feed",
+];
+
+/// Kind of resource a link refers to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LinkKind {
+    /// `<a href>` and `<area href>`
+    Hyperlink,
+    /// `<link rel="stylesheet">`
+    Stylesheet,
+    /// `<script src>`
+    Script,
+    /// `<link rel="alternate">` with a feed mime type
+    Feed,
+}
+
+/// Defines which kinds of links are returned by [`Document::links`]
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct LinkFilter {
+    pub hyperlinks: bool,
+    pub stylesheets: bool,
+    pub scripts: bool,
+    pub feeds: bool,
+}
+
+impl LinkFilter {
+    /// Filter that matches all kinds of links
+    pub fn all() -> Self {
+        Self {
+            hyperlinks: true,
+            stylesheets: true,
+            scripts: true,
+            feeds: true,
+        }
+    }
+
+    /// Returns the filter with the given kind of links included
+    pub fn with(mut self, kind: LinkKind) -> Self {
+        match kind {
+            LinkKind::Hyperlink => self.hyperlinks = true,
+            LinkKind::Stylesheet => self.stylesheets = true,
+            LinkKind::Script => self.scripts = true,
+            LinkKind::Feed => self.feeds = true,
+        }
+        self
+    }
+
+    /// Returns true when the given kind of link passes the filter
+    pub fn matches(&self, kind: LinkKind) -> bool {
+        match kind {
+            LinkKind::Hyperlink => self.hyperlinks,
+            LinkKind::Stylesheet => self.stylesheets,
+            LinkKind::Script => self.scripts,
+            LinkKind::Feed => self.feeds,
+        }
+    }
+}
+
+/// A link found in the document
+#[derive(Debug, Clone, PartialEq)]
+pub struct Link {
+    pub kind: LinkKind,
+    /// Element that defines the link
+    pub node_id: NodeId,
+    /// Location as found in the href or src attribute
+    pub href: String,
+    /// Absolute location, or None when the location could not be resolved
+    pub url: Option<Url>,
+    /// Lowercase link relations as found in the rel attribute
+    pub rel: Vec<String>,
+    /// Mime type as found in the type attribute
+    pub mime_type: Option<String>,
+    /// Value of the title attribute
+    pub title: Option<String>,
+}
+
+impl Document {
+    /// Returns all links in the document that pass the filter, in tree order
+    pub fn links(&self, filter: LinkFilter) -> Vec<Link> {
+        let base_url = self.base_url();
+        let mut links = Vec::new();
+
+        let mut stack = vec![NodeId::root()];
+        while let Some(node_id) = stack.pop() {
+            let Some(node) = self.get_node_by_id(node_id) else {
+                continue;
+            };
+            stack.extend(node.children.iter().rev());
+
+            let Some((kind, href)) = link_kind(node) else {
+                continue;
+            };
+            if !filter.matches(kind) {
+                continue;
+            }
+
+            let url = match &base_url {
+                Some(base) => base.join(href.trim()).ok(),
+                None => Url::parse(href.trim()).ok(),
+            };
+
+            links.push(Link {
+                kind,
+                node_id,
+                href: href.clone(),
+                url,
+                rel: rel_list(node),
+                mime_type: node.get_attribute("type").cloned(),
+                title: node.get_attribute("title").cloned(),
+            });
+        }
+
+        links
+    }
+}
+
+/// Returns the kind of link and its location when the node defines a link
+fn link_kind(node: &Node) -> Option<(LinkKind, &String)> {
+    if !node.is_namespace(HTML_NAMESPACE) {
+        return None;
+    }
+
+    match node.name.as_str() {
+        "a" | "area" => Some((LinkKind::Hyperlink, node.get_attribute("href")?)),
+        "script" => Some((LinkKind::Script, node.get_attribute("src")?)),
+        "link" => {
+            let href = node.get_attribute("href")?;
+            let rel = rel_list(node);
+
+            if rel.iter().any(|r| r == "stylesheet") {
+                return Some((LinkKind::Stylesheet, href));
+            }
+
+            let mime_type = node.get_attribute("type")?.trim().to_ascii_lowercase();
+            if rel.iter().any(|r| r == "alternate") && FEED_TYPES.contains(&mime_type.as_str()) {
+                return Some((LinkKind::Feed, href));
+            }
+
+            None
+        }
+        _ => None,
+    }
+}
+
+fn rel_list(node: &Node) -> Vec<String> {
+    node.get_attribute("rel")
+        .map(|rel| {
+            rel.split_ascii_whitespace()
+                .map(|r| r.to_ascii_lowercase())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bytes::{CharIterator, Encoding};
+    use crate::html5::parser::document::{DocumentBuilder, DocumentHandle};
+    use crate::html5::parser::Html5Parser;
+
+    fn parse(html: &str, url: Option<&str>) -> DocumentHandle {
+        let mut chars = CharIterator::new();
+        chars.read_from_str(html, Some(Encoding::UTF8));
+
+        let mut document = DocumentBuilder::new_document();
+        document.get_mut().url = url.map(|u| Url::parse(u).unwrap());
+        let _ = Html5Parser::parse_document(&mut chars, Document::clone(&document), None);
+        document
+    }
+
+    const HTML: &str = r#"<html><head>
+        <link rel="stylesheet" href="/css/site.css">
+        <link rel="alternate" type="application/rss+xml" title="News" href="feed.xml">
+        <link rel="alternate" hreflang="nl" href="/nl/">
+        <script src="app.js"></script>
+        <script>inline()</script>
+        </head><body>
+        <a href="../about.html" rel="nofollow Author">About</a>
+        <a name="anchor">no href</a>
+        <a href="https://example.org/">External</a>
+        </body></html>"#;
+
+    #[test]
+    fn all_links() {
+        let document = parse(HTML, Some("https://example.com/blog/post.html"));
+
+        let links = document.get().links(LinkFilter::all());
+        let urls = links
+            .iter()
+            .map(|l| (l.kind, l.url.as_ref().unwrap().as_str()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            urls,
+            vec![
+                (LinkKind::Stylesheet, "https://example.com/css/site.css"),
+                (LinkKind::Feed, "https://example.com/blog/feed.xml"),
+                (LinkKind::Script, "https://example.com/blog/app.js"),
+                (LinkKind::Hyperlink, "https://example.com/about.html"),
+                (LinkKind::Hyperlink, "https://example.org/"),
+            ]
+        );
+
+        assert_eq!(links[1].title, Some("News".to_string()));
+        assert_eq!(links[3].rel, vec!["nofollow", "author"]);
+        assert_eq!(links[3].href, "../about.html");
+    }
+
+    #[test]
+    fn filtered_links() {
+        let document = parse(HTML, Some("https://example.com/"));
+
+        let links = document
+            .get()
+            .links(LinkFilter::default().with(LinkKind::Feed));
+        assert_eq!(links.len(), 1);
+        assert_eq!(links[0].mime_type, Some("application/rss+xml".to_string()));
+    }
+
+    #[test]
+    fn links_resolved_against_base() {
+        let document = parse(
+            r#"<head><base href="https://cdn.example.com/assets/"></head>
+               <body><a href="img/logo.png">logo</a></body>"#,
+            Some("https://example.com/"),
+        );
+
+        let links = document.get().links(LinkFilter::all());
+        assert_eq!(
+            links[0].url.as_ref().unwrap().as_str(),
+            "https://cdn.example.com/assets/img/logo.png"
+        );
+    }
+
+    #[test]
+    fn unresolved_links_without_url() {
+        let document = parse(HTML, None);
+
+        let links = document.get().links(LinkFilter::all());
+        assert_eq!(links.len(), 5);
+        assert!(links[0].url.is_none());
+        assert!(links[4].url.is_some());
+    }
+}
@@ -0,0 +1,373 @@
+//! List item numbering and markers
+//!
+//! List items are numbered by the `list-item` counter. HTML defines its value as the ordinal
+//! value of the item: it starts at the `start` attribute of the list (or the number of items of a
+//! `reversed` list), can be set with the `value` attribute of an item, and goes up (or down for
+//! reversed lists) by one for every item.
+//!
+//! The marker of an item is the ordinal value formatted in the `list-style-type` of the item, which
+//! is inherited: it comes from the style attribute and presentational hints of the item and its
+//! ancestors (see `element_style`), or otherwise from the defaults of the user agent style sheet.
+//!
+//! See: https://html.spec.whatwg.org/multipage/grouping-content.html#ordinal-value
+use crate::html5::node::{Node, NodeData, NodeId, HTML_NAMESPACE};
+use crate::html5::parser::document::Document;
+
+/// The styles of list item markers
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ListStyleType {
+    None,
+    Disc,
+    Circle,
+    Square,
+    Decimal,
+    LowerAlpha,
+    UpperAlpha,
+    LowerRoman,
+    UpperRoman,
+}
+
+impl ListStyleType {
+    /// Returns the style for a `list-style-type` keyword, or None for unsupported keywords
+    pub fn from_keyword(keyword: &str) -> Option<Self> {
+        let style = match keyword.to_ascii_lowercase().as_str() {
+            "none" => Self::None,
+            "disc" => Self::Disc,
+            "circle" => Self::Circle,
+            "square" => Self::Square,
+            "decimal" => Self::Decimal,
+            "lower-alpha" | "lower-latin" => Self::LowerAlpha,
+            "upper-alpha" | "upper-latin" => Self::UpperAlpha,
+            "lower-roman" => Self::LowerRoman,
+            "upper-roman" => Self::UpperRoman,
+            _ => return None,
+        };
+        Some(style)
+    }
+
+    /// Returns the marker text for the ordinal value, including the suffix (like "3. " or "• ").
+    /// Values that cannot be written in the style fall back to decimal.
+    pub fn marker(&self, ordinal: i64) -> String {
+        match self {
+            Self::None => String::new(),
+            Self::Disc | Self::Circle | Self::Square => {
+                format!("{} ", self.representation(ordinal))
+            }
+            _ => format!("{}. ", self.representation(ordinal)),
+        }
+    }
+
+    /// Returns the value written in the style without a suffix (like "3" or "•"), as used by the
+    /// `counter()` function. Values that cannot be written in the style fall back to decimal.
+    pub fn representation(&self, value: i64) -> String {
+        match self {
+            Self::None => String::new(),
+            Self::Disc => "•".to_string(),
+            Self::Circle => "◦".to_string(),
+            Self::Square => "▪".to_string(),
+            Self::Decimal => value.to_string(),
+            Self::LowerAlpha => alphabetic(value).unwrap_or(value.to_string()),
+            Self::UpperAlpha => {
+                alphabetic(value).map_or(value.to_string(), |text| text.to_ascii_uppercase())
+            }
+            Self::LowerRoman => {
+                roman(value).map_or(value.to_string(), |text| text.to_ascii_lowercase())
+            }
+            Self::UpperRoman => roman(value).unwrap_or(value.to_string()),
+        }
+    }
+}
+
+/// Writes the value as a, b, ..., z, aa, ab, ... Only for values of 1 and up.
+fn alphabetic(value: i64) -> Option<String> {
+    if value < 1 {
+        return None;
+    }
+    let mut value = value;
+    let mut letters = Vec::new();
+    while value > 0 {
+        value -= 1;
+        letters.push(char::from(b'a' + (value % 26) as u8));
+        value /= 26;
+    }
+    Some(letters.into_iter().rev().collect())
+}
+
+/// Writes the value in roman numerals. Only for values from 1 to 3999.
+fn roman(value: i64) -> Option<String> {
+    const NUMERALS: [(i64, &str); 13] = [
+        (1000, "M"),
+        (900, "CM"),
+        (500, "D"),
+        (400, "CD"),
+        (100, "C"),
+        (90, "XC"),
+        (50, "L"),
+        (40, "XL"),
+        (10, "X"),
+        (9, "IX"),
+        (5, "V"),
+        (4, "IV"),
+        (1, "I"),
+    ];
+    if !(1..=3999).contains(&value) {
+        return None;
+    }
+    let mut value = value;
+    let mut text = String::new();
+    for (numeral_value, numeral) in NUMERALS {
+        while value >= numeral_value {
+            text.push_str(numeral);
+            value -= numeral_value;
+        }
+    }
+    Some(text)
+}
+
+/// Parses an integer the way HTML does: leading whitespace is skipped, and anything after the
+/// digits is ignored
+fn parse_integer(value: &str) -> Option<i64> {
+    let value = value.trim_start_matches(|c: char| c.is_ascii_whitespace());
+    let (sign, value) = match value.strip_prefix('-') {
+        Some(rest) => (-1, rest),
+        None => (1, value.strip_prefix('+').unwrap_or(value)),
+    };
+    let digits = value.len() - value.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+    value[..digits]
+        .parse::<i64>()
+        .ok()
+        .map(|value| sign * value)
+}
+
+fn is_html(node: &Node, names: &[&str]) -> bool {
+    node.namespace.as_deref() == Some(HTML_NAMESPACE) && names.contains(&node.name.as_str())
+}
+
+fn is_list(node: &Node) -> bool {
+    is_html(node, &["ol", "ul", "menu"])
+}
+
+impl Document {
+    /// Returns the ordinal value of the list item (the value of its `list-item` counter), or None
+    /// when the node is not a list item
+    pub fn list_item_ordinal(&self, node_id: NodeId) -> Option<i64> {
+        let node = self.get_node_by_id(node_id)?;
+        if !is_html(node, &["li"]) {
+            return None;
+        }
+        let owner = self.list_owner(node_id)?;
+        let items = self.owned_list_items(owner);
+
+        let owner_node = self.get_node_by_id(owner)?;
+        let reversed =
+            is_html(owner_node, &["ol"]) && owner_node.get_attribute("reversed").is_some();
+        let start = is_html(owner_node, &["ol"])
+            .then(|| {
+                owner_node
+                    .get_attribute("start")
+                    .and_then(|start| parse_integer(start))
+            })
+            .flatten();
+        let mut numbering = match (start, reversed) {
+            (Some(start), _) => start,
+            (None, true) => items.len() as i64,
+            (None, false) => 1,
+        };
+
+        for item in items {
+            if let Some(value) = self
+                .get_node_by_id(item)
+                .and_then(|item| item.get_attribute("value"))
+                .and_then(|value| parse_integer(value))
+            {
+                numbering = value;
+            }
+            if item == node_id {
+                return Some(numbering);
+            }
+            numbering += if reversed { -1 } else { 1 };
+        }
+        None
+    }
+
+    /// Returns the list-style-type of the element: the last `list-style-type` (or `list-style`)
+    /// declaration of the element or its nearest ancestor that has one, or the default of the
+    /// user agent style sheet for the list it is in
+    pub fn list_style_type(&self, node_id: NodeId) -> ListStyleType {
+        let mut current = Some(node_id);
+        while let Some(node) = current.and_then(|node_id| self.get_node_by_id(node_id)) {
+            if let NodeData::Element(_) = node.data {
+                let declared =
+                    self.declared_style(node.id)
+                        .into_iter()
+                        .rev()
+                        .find_map(|declaration| match declaration.property.as_str() {
+                            "list-style-type" => {
+                                ListStyleType::from_keyword(declaration.value.trim())
+                            }
+                            "list-style" => declaration
+                                .value
+                                .split_ascii_whitespace()
+                                .find_map(ListStyleType::from_keyword),
+                            _ => None,
+                        });
+                if let Some(style) = declared {
+                    return style;
+                }
+            }
+            current = node.parent;
+        }
+
+        // The user agent style sheet numbers ordered lists, and uses other bullets for nested
+        // unordered lists
+        let mut depth = 0;
+        let mut innermost = None;
+        let mut current = Some(node_id);
+        while let Some(node) = current.and_then(|node_id| self.get_node_by_id(node_id)) {
+            if is_list(node) {
+                depth += 1;
+                innermost.get_or_insert(node.name.as_str());
+            }
+            current = node.parent;
+        }
+        match (innermost, depth) {
+            (Some("ol"), _) => ListStyleType::Decimal,
+            (Some(_), 1) => ListStyleType::Disc,
+            (Some(_), 2) => ListStyleType::Circle,
+            (Some(_), _) => ListStyleType::Square,
+            (None, _) => ListStyleType::Disc,
+        }
+    }
+
+    /// Returns the marker text of the list item (like the contents of its `::marker` box), or
+    /// None when the node is not a list item
+    pub fn list_marker(&self, node_id: NodeId) -> Option<String> {
+        let ordinal = self.list_item_ordinal(node_id)?;
+        Some(self.list_style_type(node_id).marker(ordinal))
+    }
+
+    /// Returns the text of the node like `text_content()`, with the markers in front of the list
+    /// items and every list item on its own line
+    pub fn text_content_with_markers(&self, node_id: NodeId) -> String {
+        let mut text = String::new();
+
+        let mut stack = vec![node_id];
+        while let Some(node_id) = stack.pop() {
+            let Some(node) = self.get_node_by_id(node_id) else {
+                continue;
+            };
+
+            match &node.data {
+                NodeData::Text(text_data) => text.push_str(text_data.value()),
+                NodeData::Element(_) => {
+                    if let Some(marker) = self.list_marker(node_id) {
+                        if !text.is_empty() && !text.ends_with('\n') {
+                            text.push('\n');
+                        }
+                        text.push_str(&marker);
+                    }
+                }
+                _ => {}
+            }
+
+            stack.extend(node.children.iter().rev());
+        }
+
+        text
+    }
+
+    /// Returns the list that owns the list item: its nearest ol, ul or menu ancestor, or its
+    /// parent when there is none
+    fn list_owner(&self, node_id: NodeId) -> Option<NodeId> {
+        let parent = self.get_node_by_id(node_id)?.parent?;
+        let mut current = Some(parent);
+        while let Some(node) = current.and_then(|node_id| self.get_node_by_id(node_id)) {
+            if is_list(node) {
+                return Some(node.id);
+            }
+            current = node.parent;
+        }
+        Some(parent)
+    }
+
+    /// Returns the list items owned by the list, in tree order. Items of nested lists belong to
+    /// those lists.
+    fn owned_list_items(&self, owner: NodeId) -> Vec<NodeId> {
+        let mut items = Vec::new();
+        let mut stack: Vec<NodeId> = self
+            .get_node_by_id(owner)
+            .map(|node| node.children.iter().rev().copied().collect())
+            .unwrap_or_default();
+        while let Some(node_id) = stack.pop() {
+            let Some(node) = self.get_node_by_id(node_id) else {
+                continue;
+            };
+            if is_list(node) {
+                continue;
+            }
+            if is_html(node, &["li"]) {
+                items.push(node_id);
+            }
+            stack.extend(node.children.iter().rev());
+        }
+        items
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::html5::parser::document::DocumentHandle;
+    use crate::testing::parse;
+
+    fn markers(document: &DocumentHandle) -> Vec<String> {
+        let doc = document.get();
+        doc.elements_by_tag_name("li")
+            .into_iter()
+            .map(|li| doc.list_marker(li).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn ordinal_values() {
+        let document = parse(
+            "<ol start=3><li>a<li value=10>b<li>c<ol reversed><li>x<li>y</ol><li>d</ol>\
+             <ol reversed start=2><li>e<li>f<li>g</ol>",
+        );
+        assert_eq!(
+            markers(&document),
+            ["3. ", "10. ", "11. ", "2. ", "1. ", "12. ", "2. ", "1. ", "0. "]
+        );
+    }
+
+    #[test]
+    fn list_style_types() {
+        let document = parse(
+            "<ul><li>a<ul><li>b<ul><li>c</ul></ul></ul>\
+             <ol type=A><li>d<li type=i>e<li style='list-style: upper-roman inside'>f</ol>\
+             <ol style='list-style-type: none' start=28 type=a><li>g</ol>\
+             <ol start=28 type=a><li>h</ol>",
+        );
+        assert_eq!(
+            markers(&document),
+            ["• ", "◦ ", "▪ ", "A. ", "ii. ", "III. ", "", "ab. "]
+        );
+
+        let doc = document.get();
+        let body = doc.elements_by_tag_name("body")[0];
+        let text = doc.text_content_with_markers(body);
+        assert!(
+            text.starts_with("• a\n◦ b\n▪ c\nA. d\nii. e\nIII. f\ng\nab. h"),
+            "{text}"
+        );
+    }
+
+    #[test]
+    fn marker_formats() {
+        assert_eq!(ListStyleType::LowerAlpha.marker(702), "zz. ");
+        assert_eq!(ListStyleType::LowerAlpha.marker(0), "0. ");
+        assert_eq!(ListStyleType::UpperRoman.marker(1994), "MCMXCIV. ");
+        assert_eq!(ListStyleType::LowerRoman.marker(4000), "4000. ");
+        assert_eq!(ListStyleType::Decimal.marker(-2), "-2. ");
+    }
+}
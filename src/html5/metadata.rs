@@ -0,0 +1,279 @@
+//! Page metadata extraction
+//!
+//! Collects the metadata that almost every consumer of a page is interested in (title,
+//! description, canonical url, favicons and social media cards) into a single structure.
+use crate::html5::parser::document::Document;
+
+/// Icon as defined by a `<link rel="icon">` (or one of its variants)
+#[derive(Debug, Clone, PartialEq)]
+pub struct Favicon {
+    /// Location of the icon as found in the href attribute
+    pub href: String,
+    /// Relation of the link (e.g. "icon" or "apple-touch-icon")
+    pub rel: String,
+    /// Sizes the icon is available in
+    pub sizes: Vec<IconSize>,
+    /// Mime type as found in the type attribute
+    pub mime_type: Option<String>,
+}
+
+/// Size of an icon as found in the sizes attribute of a link element
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum IconSize {
+    /// The icon is scalable (sizes="any")
+    Any,
+    /// Width and height in pixels
+    Pixels(u32, u32),
+}
+
+/// Open Graph properties (`<meta property="og:...">`)
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct OpenGraph {
+    pub title: Option<String>,
+    pub description: Option<String>,
+    pub url: Option<String>,
+    pub site_name: Option<String>,
+    /// Value of og:type
+    pub kind: Option<String>,
+    pub locale: Option<String>,
+    /// All og:image values in document order
+    pub images: Vec<String>,
+    /// All og: properties (including the ones above) in document order
+    pub properties: Vec<(String, String)>,
+}
+
+/// Twitter card properties (`<meta name="twitter:...">`)
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TwitterCard {
+    pub card: Option<String>,
+    pub site: Option<String>,
+    pub creator: Option<String>,
+    pub title: Option<String>,
+    pub description: Option<String>,
+    pub image: Option<String>,
+    /// All twitter: properties (including the ones above) in document order
+    pub properties: Vec<(String, String)>,
+}
+
+/// Metadata of a page
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PageMetadata {
+    /// Title of the document with whitespace stripped and collapsed
+    pub title: Option<String>,
+    /// Value of the description meta element
+    pub description: Option<String>,
+    /// Location of the canonical url as found in `<link rel="canonical">`
+    pub canonical_url: Option<String>,
+    /// All icons defined by the document in document order
+    pub favicons: Vec<Favicon>,
+    pub open_graph: OpenGraph,
+    pub twitter_card: TwitterCard,
+}
+
+/// Link relations that define an icon for the page
+const ICON_RELATIONS: [&str; 4] = [
+    "icon",
+    "apple-touch-icon",
+    "apple-touch-icon-precomposed",
+    "mask-icon",
+];
+
+impl Document {
+    /// Collects the metadata of the document
+    pub fn metadata(&self) -> PageMetadata {
+        let mut metadata = PageMetadata {
+            title: self.title(),
+            ..Default::default()
+        };
+
+        for node_id in self.elements_by_tag_name("meta") {
+            let Some(node) = self.get_node_by_id(node_id) else {
+                continue;
+            };
+            let Some(content) = node.get_attribute("content") else {
+                continue;
+            };
+
+            // Open graph uses the "property" attribute, but "name" is often used as well
+            let Some(key) = node
+                .get_attribute("property")
+                .or_else(|| node.get_attribute("name"))
+            else {
+                continue;
+            };
+            let key = key.trim().to_ascii_lowercase();
+
+            if key == "description" {
+                metadata.description.get_or_insert_with(|| content.clone());
+            } else if let Some(property) = key.strip_prefix("og:") {
+                let og = &mut metadata.open_graph;
+                match property {
+                    "title" => set_once(&mut og.title, content),
+                    "description" => set_once(&mut og.description, content),
+                    "url" => set_once(&mut og.url, content),
+                    "site_name" => set_once(&mut og.site_name, content),
+                    "type" => set_once(&mut og.kind, content),
+                    "locale" => set_once(&mut og.locale, content),
+                    "image" | "image:url" => og.images.push(content.clone()),
+                    _ => {}
+                }
+                og.properties.push((key.clone(), content.clone()));
+            } else if let Some(property) = key.strip_prefix("twitter:") {
+                let twitter = &mut metadata.twitter_card;
+                match property {
+                    "card" => set_once(&mut twitter.card, content),
+                    "site" => set_once(&mut twitter.site, content),
+                    "creator" => set_once(&mut twitter.creator, content),
+                    "title" => set_once(&mut twitter.title, content),
+                    "description" => set_once(&mut twitter.description, content),
+                    "image" | "image:src" => set_once(&mut twitter.image, content),
+                    _ => {}
+                }
+                twitter.properties.push((key.clone(), content.clone()));
+            }
+        }
+
+        for node_id in self.elements_by_tag_name("link") {
+            let Some(node) = self.get_node_by_id(node_id) else {
+                continue;
+            };
+            let (Some(rel), Some(href)) = (node.get_attribute("rel"), node.get_attribute("href"))
+            else {
+                continue;
+            };
+            let rel = rel.to_ascii_lowercase();
+
+            if rel.split_ascii_whitespace().any(|r| r == "canonical") {
+                metadata
+                    .canonical_url
+                    .get_or_insert_with(|| href.trim().to_string());
+            }
+
+            if let Some(icon_rel) = rel
+                .split_ascii_whitespace()
+                .find(|r| ICON_RELATIONS.contains(r))
+            {
+                metadata.favicons.push(Favicon {
+                    href: href.trim().to_string(),
+                    rel: icon_rel.to_string(),
+                    sizes: node
+                        .get_attribute("sizes")
+                        .map(|s| parse_sizes(s))
+                        .unwrap_or_default(),
+                    mime_type: node.get_attribute("type").cloned(),
+                });
+            }
+        }
+
+        metadata
+    }
+
+    /// Returns the title of the document as defined by the first `<title>` element, with leading
+    /// and trailing whitespace stripped and inner whitespace collapsed.
+    pub fn title(&self) -> Option<String> {
+        let node_id = *self.elements_by_tag_name("title").first()?;
+        let text = self.text_content(node_id);
+
+        Some(text.split_ascii_whitespace().collect::<Vec<_>>().join(" "))
+    }
+}
+
+/// Sets the value only when no earlier value has been found
+fn set_once(target: &mut Option<String>, value: &str) {
+    if target.is_none() {
+        *target = Some(value.to_string());
+    }
+}
+
+/// Parses a sizes attribute (e.g. "16x16 32X32 any"). Invalid sizes are ignored.
+fn parse_sizes(sizes: &str) -> Vec<IconSize> {
+    sizes
+        .split_ascii_whitespace()
+        .filter_map(|size| {
+            if size.eq_ignore_ascii_case("any") {
+                return Some(IconSize::Any);
+            }
+
+            let (width, height) = size.split_once(['x', 'X'])?;
+            Some(IconSize::Pixels(width.parse().ok()?, height.parse().ok()?))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::parse;
+
+    #[test]
+    fn extract_metadata() {
+        let document = parse(
+            r#"<html><head>
+            <title>
+                My   page
+            </title>
+            <meta name="description" content="A page about things">
+            <link rel="canonical" href="https://example.com/page">
+            <link rel="shortcut icon" href="/favicon.ico">
+            <link rel="icon" href="/icon.png" sizes="16x16 32X32" type="image/png">
+            <link rel="apple-touch-icon" href="/touch.png" sizes="180x180">
+            <link rel="stylesheet" href="/style.css">
+            <meta property="og:title" content="OG title">
+            <meta property="og:type" content="article">
+            <meta property="og:image" content="/one.png">
+            <meta property="og:image" content="/two.png">
+            <meta name="twitter:card" content="summary">
+            <meta name="twitter:site" content="@gosub">
+            </head><body><title>not this one</title></body></html>"#,
+        );
+
+        let metadata = document.get().metadata();
+        assert_eq!(metadata.title, Some("My page".to_string()));
+        assert_eq!(
+            metadata.description,
+            Some("A page about things".to_string())
+        );
+        assert_eq!(
+            metadata.canonical_url,
+            Some("https://example.com/page".to_string())
+        );
+
+        assert_eq!(metadata.favicons.len(), 3);
+        assert_eq!(metadata.favicons[0].href, "/favicon.ico");
+        assert_eq!(metadata.favicons[0].rel, "icon");
+        assert!(metadata.favicons[0].sizes.is_empty());
+        assert_eq!(
+            metadata.favicons[1].sizes,
+            vec![IconSize::Pixels(16, 16), IconSize::Pixels(32, 32)]
+        );
+        assert_eq!(
+            metadata.favicons[1].mime_type,
+            Some("image/png".to_string())
+        );
+        assert_eq!(metadata.favicons[2].rel, "apple-touch-icon");
+
+        assert_eq!(metadata.open_graph.title, Some("OG title".to_string()));
+        assert_eq!(metadata.open_graph.kind, Some("article".to_string()));
+        assert_eq!(metadata.open_graph.images, vec!["/one.png", "/two.png"]);
+        assert_eq!(metadata.open_graph.properties.len(), 4);
+
+        assert_eq!(metadata.twitter_card.card, Some("summary".to_string()));
+        assert_eq!(metadata.twitter_card.site, Some("@gosub".to_string()));
+    }
+
+    #[test]
+    fn empty_metadata() {
+        let document = parse("<p>hello</p>");
+
+        let metadata = document.get().metadata();
+        assert_eq!(metadata, PageMetadata::default());
+    }
+
+    #[test]
+    fn icon_sizes() {
+        assert_eq!(
+            parse_sizes("any 16x16 foo 0x"),
+            vec![IconSize::Any, IconSize::Pixels(16, 16)]
+        );
+    }
+}
@@ -0,0 +1,393 @@
+//! Mutation observers
+//!
+//! A mutation observer is notified of the changes to the tree below the nodes it observes: added
+//! and removed children, changed attributes and changed text. The mutation records of the
+//! document are queued for every observer that is interested in them, and delivered in batches:
+//! `deliver_mutation_records()` invokes the callbacks with the records queued since the last
+//! delivery. The document task queue delivers the records after every flush.
+//!
+//! Creating a node is not observed, as the node is not part of the tree yet: attaching it to a
+//! parent is.
+//!
+//! See: https://dom.spec.whatwg.org/#mutation-observers
+use crate::html5::compaction::{remap, NodeIdMap};
+use crate::html5::node::NodeId;
+use crate::html5::parser::document::{Document, DocumentHandle, MutationRecord};
+use crate::types::{Error, Result};
+use core::fmt;
+use core::fmt::Debug;
+use std::rc::Rc;
+
+/// Callback that is invoked with the mutation records of an observer
+pub type MutationCallback = Rc<dyn Fn(&DocumentHandle, &[MutationRecord])>;
+
+/// Id of a mutation observer in a document
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct MutationObserverId(usize);
+
+/// Which mutations of a target are observed
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct MutationObserverInit {
+    /// Children that are added to or removed from the target
+    pub child_list: bool,
+    /// Attributes of the target that are changed
+    pub attributes: bool,
+    /// Text of the target that is changed
+    pub character_data: bool,
+    /// Observe the mutations of all descendants of the target as well
+    pub subtree: bool,
+}
+
+struct MutationObserver {
+    callback: MutationCallback,
+    targets: Vec<(NodeId, MutationObserverInit)>,
+    /// Records that have not been delivered yet
+    records: Vec<MutationRecord>,
+}
+
+/// Mutation observers of a document
+#[derive(Default)]
+pub(crate) struct MutationObservers {
+    observers: Vec<Option<MutationObserver>>,
+}
+
+impl Debug for MutationObservers {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let count = self.observers.iter().flatten().count();
+        write!(f, "MutationObservers({count})")
+    }
+}
+
+impl PartialEq for MutationObservers {
+    fn eq(&self, other: &Self) -> bool {
+        self.observers.len() == other.observers.len()
+            && self
+                .observers
+                .iter()
+                .zip(&other.observers)
+                .all(|(a, b)| match (a, b) {
+                    (Some(a), Some(b)) => {
+                        a.targets == b.targets
+                            && a.records == b.records
+                            && Rc::ptr_eq(&a.callback, &b.callback)
+                    }
+                    (a, b) => a.is_none() && b.is_none(),
+                })
+    }
+}
+
+impl MutationObservers {
+    /// Replaces the targets and the nodes of the queued records by their new IDs
+    pub(crate) fn remap_nodes(&mut self, ids: &NodeIdMap) {
+        for observer in self.observers.iter_mut().flatten() {
+            observer.targets = observer
+                .targets
+                .iter()
+                .filter_map(|&(target, options)| Some((remap(ids, target)?, options)))
+                .collect();
+            observer.records = observer
+                .records
+                .iter()
+                .filter_map(|record| record.remap(ids))
+                .collect();
+        }
+    }
+
+    /// Drops the records that have not been delivered yet
+    pub(crate) fn clear_records(&mut self) {
+        for observer in self.observers.iter_mut().flatten() {
+            observer.records.clear();
+        }
+    }
+
+    /// Stops observing the node, as it has been removed from the document. Records that have
+    /// not been delivered yet are kept.
+    pub(crate) fn forget_node(&mut self, node_id: NodeId) {
+        for observer in self.observers.iter_mut().flatten() {
+            observer.targets.retain(|(target, _)| *target != node_id);
+        }
+    }
+}
+
+impl Document {
+    /// Creates a mutation observer. It does not observe anything until targets are added with
+    /// `observe_mutations()`.
+    pub fn create_mutation_observer(&mut self, callback: MutationCallback) -> MutationObserverId {
+        let observers = &mut self.mutation_observers.observers;
+        observers.push(Some(MutationObserver {
+            callback,
+            targets: Vec::new(),
+            records: Vec::new(),
+        }));
+        MutationObserverId(observers.len() - 1)
+    }
+
+    /// Starts observing the mutations of the target. Observing a target again replaces its
+    /// options. Fails when none of child list, attributes or character data is observed.
+    pub fn observe_mutations(
+        &mut self,
+        observer_id: MutationObserverId,
+        target: NodeId,
+        options: MutationObserverInit,
+    ) -> Result<()> {
+        if self.get_node_by_id(target).is_none() {
+            return Err(Error::DocumentTask(format!(
+                "Node ID {} does not exist",
+                target
+            )));
+        }
+        if !options.child_list && !options.attributes && !options.character_data {
+            return Err(Error::DocumentTask(
+                "mutation observer must observe child list, attributes or character data"
+                    .to_string(),
+            ));
+        }
+
+        let observer = self.mutation_observer_mut(observer_id)?;
+        match observer.targets.iter_mut().find(|(id, _)| *id == target) {
+            Some((_, current)) => *current = options,
+            None => observer.targets.push((target, options)),
+        }
+        Ok(())
+    }
+
+    /// Stops observing all targets, and removes the observer. Records that have not been
+    /// delivered are dropped.
+    pub fn disconnect_mutation_observer(&mut self, observer_id: MutationObserverId) {
+        if let Some(observer) = self.mutation_observers.observers.get_mut(observer_id.0) {
+            *observer = None;
+        }
+    }
+
+    /// Returns (and removes) the records of the observer that have not been delivered yet
+    pub fn take_mutation_records(
+        &mut self,
+        observer_id: MutationObserverId,
+    ) -> Result<Vec<MutationRecord>> {
+        let observer = self.mutation_observer_mut(observer_id)?;
+        Ok(std::mem::take(&mut observer.records))
+    }
+
+    /// Returns true when records are waiting to be delivered
+    pub fn has_mutation_records(&self) -> bool {
+        self.mutation_observers
+            .observers
+            .iter()
+            .flatten()
+            .any(|observer| !observer.records.is_empty())
+    }
+
+    /// Queues the record for every observer that observes the mutation
+    pub(crate) fn queue_mutation_record(&mut self, record: &MutationRecord) {
+        if self.mutation_observers.observers.is_empty() {
+            return;
+        }
+
+        let (target, observes): (NodeId, fn(&MutationObserverInit) -> bool) = match record {
+            MutationRecord::NodeCreated(_) => return,
+            MutationRecord::ChildList { parent_id, .. } => (*parent_id, |o| o.child_list),
+            MutationRecord::Attribute { node_id, .. } => (*node_id, |o| o.attributes),
+            MutationRecord::CharacterData(node_id) => (*node_id, |o| o.character_data),
+        };
+
+        // The target itself and its ancestors, which observe it when they observe their subtree
+        let mut ancestors = vec![target];
+        ancestors.extend(self.ancestors(target).map(|node| node.id));
+
+        for observer in self.mutation_observers.observers.iter_mut().flatten() {
+            let interested = observer.targets.iter().any(|(node_id, options)| {
+                observes(options)
+                    && (*node_id == target || (options.subtree && ancestors.contains(node_id)))
+            });
+            if interested {
+                observer.records.push(record.clone());
+            }
+        }
+    }
+
+    fn mutation_observer_mut(
+        &mut self,
+        observer_id: MutationObserverId,
+    ) -> Result<&mut MutationObserver> {
+        self.mutation_observers
+            .observers
+            .get_mut(observer_id.0)
+            .and_then(Option::as_mut)
+            .ok_or_else(|| {
+                Error::DocumentTask(format!(
+                    "mutation observer {} does not exist",
+                    observer_id.0
+                ))
+            })
+    }
+}
+
+impl DocumentHandle {
+    /// Delivers the queued mutation records: invokes the callback of every observer with the
+    /// records queued since the last delivery. Mutations done by the callbacks are delivered as
+    /// well, until no records are left. Returns the number of callbacks that were invoked.
+    pub fn deliver_mutation_records(&mut self) -> usize {
+        let mut invoked = 0;
+
+        loop {
+            let notifications: Vec<(MutationCallback, Vec<MutationRecord>)> = self
+                .get_mut()
+                .mutation_observers
+                .observers
+                .iter_mut()
+                .flatten()
+                .filter(|observer| !observer.records.is_empty())
+                .map(|observer| {
+                    (
+                        observer.callback.clone(),
+                        std::mem::take(&mut observer.records),
+                    )
+                })
+                .collect();
+            if notifications.is_empty() {
+                break;
+            }
+
+            for (callback, records) in &notifications {
+                callback(self, records);
+                invoked += 1;
+            }
+        }
+
+        invoked
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::html5::node::{Node, HTML_NAMESPACE};
+    use crate::html5::parser::document::DocumentTaskQueue;
+    use crate::html5::parser::tree_builder::TreeBuilder;
+    use crate::testing::parse;
+    use std::cell::RefCell;
+    use std::collections::HashMap;
+
+    fn setup() -> (DocumentHandle, NodeId, NodeId) {
+        let document = parse("<div id='outer'><p id='inner'>text</p></div>");
+
+        let outer = document.get().get_node_by_named_id("outer").unwrap().id;
+        let inner = document.get().get_node_by_named_id("inner").unwrap().id;
+        (document, outer, inner)
+    }
+
+    fn recorder() -> (MutationCallback, Rc<RefCell<Vec<Vec<MutationRecord>>>>) {
+        let log = Rc::new(RefCell::new(Vec::new()));
+        let recorder = log.clone();
+        let callback: MutationCallback =
+            Rc::new(move |_, records| recorder.borrow_mut().push(records.to_vec()));
+        (callback, log)
+    }
+
+    #[test]
+    fn filters() {
+        let (mut document, outer, inner) = setup();
+        let (callback, log) = recorder();
+        let observer = document.get_mut().create_mutation_observer(callback);
+
+        let options = MutationObserverInit {
+            child_list: true,
+            ..Default::default()
+        };
+        document
+            .get_mut()
+            .observe_mutations(observer, outer, options)
+            .unwrap();
+        assert!(document
+            .get_mut()
+            .observe_mutations(observer, outer, MutationObserverInit::default())
+            .is_err());
+
+        // Attributes are not observed, and children of the inner element are not in the subtree
+        document.insert_attribute("title", "x", outer).unwrap();
+        let span = document
+            .get_mut()
+            .add_node(
+                Node::new_element("span", HashMap::new(), HTML_NAMESPACE),
+                inner,
+                None,
+            )
+            .unwrap();
+        assert!(!document.get().has_mutation_records());
+
+        let em = document
+            .get_mut()
+            .add_node(
+                Node::new_element("em", HashMap::new(), HTML_NAMESPACE),
+                outer,
+                None,
+            )
+            .unwrap();
+        document.get_mut().relocate(span, outer);
+
+        let subtree = MutationObserverInit {
+            attributes: true,
+            subtree: true,
+            ..Default::default()
+        };
+        document
+            .get_mut()
+            .observe_mutations(observer, outer, subtree)
+            .unwrap();
+        document.insert_attribute("lang", "en", inner).unwrap();
+        document.get_mut().detach_node_from_parent(em);
+
+        assert_eq!(document.deliver_mutation_records(), 1);
+        assert_eq!(document.deliver_mutation_records(), 0);
+        assert_eq!(
+            *log.borrow(),
+            [vec![
+                MutationRecord::ChildList {
+                    parent_id: outer,
+                    node_id: em
+                },
+                MutationRecord::ChildList {
+                    parent_id: outer,
+                    node_id: span
+                },
+                MutationRecord::Attribute {
+                    node_id: inner,
+                    name: "lang".to_string()
+                },
+            ]]
+        );
+    }
+
+    #[test]
+    fn task_queue_delivers_batches() {
+        let (mut document, outer, inner) = setup();
+        let (callback, log) = recorder();
+        let observer = document.get_mut().create_mutation_observer(callback);
+        let options = MutationObserverInit {
+            child_list: true,
+            attributes: true,
+            character_data: true,
+            subtree: true,
+        };
+        document
+            .get_mut()
+            .observe_mutations(observer, outer, options)
+            .unwrap();
+
+        let mut task_queue = DocumentTaskQueue::new(&document);
+        let text_id = document.get().get_node_by_id(inner).unwrap().children[0];
+        task_queue.append_to_existing_text(text_id, "!");
+        let _ = task_queue.insert_attribute("class", "a", inner);
+        assert!(task_queue.flush().is_empty());
+
+        // Both tasks are delivered in a single batch
+        assert_eq!(log.borrow().len(), 1);
+        assert_eq!(log.borrow()[0][0], MutationRecord::CharacterData(text_id));
+
+        document.get_mut().disconnect_mutation_observer(observer);
+        task_queue.remove_attribute("class", inner);
+        assert!(task_queue.flush().is_empty());
+        assert_eq!(log.borrow().len(), 1);
+        assert!(document.get_mut().take_mutation_records(observer).is_err());
+    }
+}
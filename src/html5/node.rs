@@ -1,4 +1,4 @@
-use super::parser::document::{Document, DocumentHandle};
+use crate::bytes::Span;
 use crate::html5::node::data::comment::CommentData;
 use crate::html5::node::data::doctype::DocTypeData;
 use crate::html5::node::data::document::DocumentData;
@@ -19,7 +19,7 @@ pub mod arena;
 pub mod data;
 
 /// Different types of nodes
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum NodeType {
     Document,
     DocType,
@@ -44,7 +44,7 @@ pub enum NodeData {
 }
 
 /// Id used to identify a node
-#[derive(Copy, Debug, Default, Eq, Hash, PartialEq, Display, PartialOrd)]
+#[derive(Copy, Debug, Default, Eq, Hash, PartialEq, Display, PartialOrd, Ord)]
 pub struct NodeId(pub(crate) usize);
 
 impl From<NodeId> for usize {
@@ -106,8 +106,9 @@ impl NodeId {
     }
 }
 
-/// Node structure that resembles a DOM node
-#[derive(PartialEq)]
+/// Node structure that resembles a DOM node. Nodes do not refer to a document themselves: they
+/// can be created standalone and belong to the document whose arena they are registered in.
+#[derive(Clone, PartialEq)]
 pub struct Node {
     /// ID of the node, 0 is always the root / document node
     pub id: NodeId,
@@ -121,14 +122,37 @@ pub struct Node {
     pub namespace: Option<String>,
     /// actual data of the node
     pub data: NodeData,
-    /// pointer to document this node is attached to
-    pub document: DocumentHandle,
 
     // Returns true when the given node is registered into an arena
     pub is_registered: bool,
+    /// Part of the input the node was parsed from (see `source_span()`)
+    pub(crate) source_span: Option<Span>,
 }
 
 impl Node {
+    /// Clones the node without its children. The parser inspects the name, namespace and data of
+    /// nodes for almost every token, and cloning the children of large elements (like a body with
+    /// many thousands of children) each time makes parsing quadratic.
+    pub(crate) fn clone_without_children(&self) -> Node {
+        Node {
+            id: self.id,
+            parent: self.parent,
+            children: Vec::new(),
+            name: self.name.clone(),
+            namespace: self.namespace.clone(),
+            data: self.data.clone(),
+            is_registered: self.is_registered,
+            source_span: self.source_span,
+        }
+    }
+
+    /// Returns the part of the input the node was parsed from: the start tag of an element, or
+    /// the whole comment, doctype or text. Returns None for nodes that were not parsed, and for
+    /// nodes the parser created on its own (like an implied `<body>`).
+    pub fn source_span(&self) -> Option<Span> {
+        self.source_span
+    }
+
     /// Returns true when the given node is of the given namespace
     pub(crate) fn is_namespace(&self, namespace: &str) -> bool {
         self.namespace == Some(namespace.into())
@@ -156,6 +180,23 @@ impl Node {
             && ["foreignObject", "desc", "title"].contains(&self.name.as_str())
     }
 
+    /// Returns the namespace of the attribute with the given (qualified) name. Attributes of HTML
+    /// elements have no namespace, and neither have most attributes of foreign elements, except
+    /// for the `xlink:`, `xml:` and `xmlns` attributes the parser adjusts.
+    /// See: https://html.spec.whatwg.org/multipage/parsing.html#adjust-foreign-attributes
+    pub fn attribute_namespace(&self, name: &str) -> Option<&'static str> {
+        if self.namespace.is_none() || self.is_namespace(HTML_NAMESPACE) {
+            return None;
+        }
+        match name {
+            "xlink:actuate" | "xlink:arcrole" | "xlink:href" | "xlink:role" | "xlink:show"
+            | "xlink:title" | "xlink:type" => Some(XLINK_NAMESPACE),
+            "xml:lang" | "xml:space" => Some(XML_NAMESPACE),
+            "xmlns" | "xmlns:xlink" => Some(XMLNS_NAMESPACE),
+            _ => None,
+        }
+    }
+
     /// Returns true if the given node is a mathml integration point
     /// See: https://html.spec.whatwg.org/multipage/parsing.html#mathml-text-integration-point
     pub(crate) fn is_mathml_integration_point(&self) -> bool {
@@ -218,24 +259,9 @@ impl Node {
     }
 }
 
-impl Clone for Node {
-    fn clone(&self) -> Self {
-        Node {
-            id: self.id,
-            parent: self.parent,
-            children: self.children.clone(),
-            name: self.name.clone(),
-            namespace: self.namespace.clone(),
-            data: self.data.clone(),
-            document: Document::clone(&self.document),
-            is_registered: self.is_registered,
-        }
-    }
-}
-
 impl Node {
     /// Create a new document node
-    pub fn new_document(document: &DocumentHandle) -> Self {
+    pub fn new_document() -> Self {
         Node {
             id: Default::default(),
             parent: None,
@@ -243,17 +269,12 @@ impl Node {
             data: NodeData::Document(DocumentData::new()),
             name: "".to_string(),
             namespace: None,
-            document: Document::clone(document),
             is_registered: false,
+            source_span: None,
         }
     }
 
-    pub fn new_doctype(
-        document: &DocumentHandle,
-        name: &str,
-        pub_identifier: &str,
-        sys_identifier: &str,
-    ) -> Self {
+    pub fn new_doctype(name: &str, pub_identifier: &str, sys_identifier: &str) -> Self {
         Node {
             id: Default::default(),
             parent: None,
@@ -261,37 +282,31 @@ impl Node {
             data: NodeData::DocType(DocTypeData::new(name, pub_identifier, sys_identifier)),
             name: "".to_string(),
             namespace: None,
-            document: Document::clone(document),
             is_registered: false,
+            source_span: None,
         }
     }
 
     /// Create a new element node with the given name and attributes and namespace
-    pub fn new_element(
-        document: &DocumentHandle,
-        name: &str,
-        attributes: HashMap<String, String>,
-        namespace: &str,
-    ) -> Self {
+    pub fn new_element(name: &str, attributes: HashMap<String, String>, namespace: &str) -> Self {
         Node {
             id: Default::default(),
             parent: None,
             children: vec![],
             data: NodeData::Element(Box::new(ElementData::with_name_and_attributes(
                 Default::default(),
-                Document::clone(document),
                 name,
                 attributes,
             ))),
             name: name.to_string(),
             namespace: Some(namespace.into()),
-            document: Document::clone(document),
             is_registered: false,
+            source_span: None,
         }
     }
 
     /// Creates a new comment node
-    pub fn new_comment(document: &DocumentHandle, value: &str) -> Self {
+    pub fn new_comment(value: &str) -> Self {
         Node {
             id: Default::default(),
             parent: None,
@@ -299,13 +314,13 @@ impl Node {
             data: NodeData::Comment(CommentData::with_value(value)),
             name: "".to_string(),
             namespace: None,
-            document: Document::clone(document),
             is_registered: false,
+            source_span: None,
         }
     }
 
     /// Creates a new text node
-    pub fn new_text(document: &DocumentHandle, value: &str) -> Self {
+    pub fn new_text(value: &str) -> Self {
         Node {
             id: Default::default(),
             parent: None,
@@ -313,8 +328,8 @@ impl Node {
             data: NodeData::Text(TextData::with_value(value)),
             name: "".to_string(),
             namespace: None,
-            document: Document::clone(document),
             is_registered: false,
+            source_span: None,
         }
     }
 
@@ -349,6 +364,15 @@ impl Node {
     pub fn is_registered(&self) -> bool {
         self.is_registered
     }
+
+    /// Returns the value of the given attribute, or None when the attribute is not set or this
+    /// node is not an element
+    pub fn get_attribute(&self, name: &str) -> Option<&String> {
+        match &self.data {
+            NodeData::Element(element) => element.attributes.get(name),
+            _ => None,
+        }
+    }
 }
 
 pub trait NodeTrait {
@@ -471,12 +495,10 @@ pub static SPECIAL_SVG_ELEMENTS: [&str; 3] = ["foreignObject", "desc", "title"];
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::html5::parser::document::Document;
 
     #[test]
     fn new_document() {
-        let document = Document::shared();
-        let node = Node::new_document(&document);
+        let node = Node::new_document();
         assert_eq!(node.id, NodeId::default());
         assert_eq!(node.parent, None);
         assert!(node.children.is_empty());
@@ -492,8 +514,7 @@ mod tests {
     fn new_element() {
         let mut attributes = HashMap::new();
         attributes.insert("id".to_string(), "test".to_string());
-        let document = Document::shared();
-        let node = Node::new_element(&document, "div", attributes.clone(), HTML_NAMESPACE);
+        let node = Node::new_element("div", attributes.clone(), HTML_NAMESPACE);
         assert_eq!(node.id, NodeId::default());
         assert_eq!(node.parent, None);
         assert!(node.children.is_empty());
@@ -509,8 +530,7 @@ mod tests {
 
     #[test]
     fn new_comment() {
-        let document = Document::shared();
-        let node = Node::new_comment(&document, "test");
+        let node = Node::new_comment("test");
         assert_eq!(node.id, NodeId::default());
         assert_eq!(node.parent, None);
         assert!(node.children.is_empty());
@@ -524,8 +544,7 @@ mod tests {
 
     #[test]
     fn new_text() {
-        let document = Document::shared();
-        let node = Node::new_text(&document, "test");
+        let node = Node::new_text("test");
         assert_eq!(node.id, NodeId::default());
         assert_eq!(node.parent, None);
         assert!(node.children.is_empty());
@@ -541,71 +560,65 @@ mod tests {
     fn is_special() {
         let mut attributes = HashMap::new();
         attributes.insert("id".to_string(), "test".to_string());
-        let document = Document::shared();
-        let node = Node::new_element(&document, "div", attributes, HTML_NAMESPACE);
+        let node = Node::new_element("div", attributes, HTML_NAMESPACE);
         assert!(node.is_special());
     }
 
     #[test]
     fn type_of() {
-        let document = Document::shared();
-        let node = Node::new_document(&document);
+        let node = Node::new_document();
         assert_eq!(node.type_of(), NodeType::Document);
-        let node = Node::new_text(&document, "test");
+        let node = Node::new_text("test");
         assert_eq!(node.type_of(), NodeType::Text);
-        let node = Node::new_comment(&document, "test");
+        let node = Node::new_comment("test");
         assert_eq!(node.type_of(), NodeType::Comment);
         let mut attributes = HashMap::new();
         attributes.insert("id".to_string(), "test".to_string());
-        let node = Node::new_element(&document, "div", attributes, HTML_NAMESPACE);
+        let node = Node::new_element("div", attributes, HTML_NAMESPACE);
         assert_eq!(node.type_of(), NodeType::Element);
     }
 
     #[test]
     fn special_html_elements() {
-        let document = Document::shared();
         for element in SPECIAL_HTML_ELEMENTS.iter() {
             let mut attributes = HashMap::new();
             attributes.insert("id".to_string(), "test".to_string());
-            let node = Node::new_element(&document, element, attributes, HTML_NAMESPACE);
+            let node = Node::new_element(element, attributes, HTML_NAMESPACE);
             assert!(node.is_special());
         }
     }
 
     #[test]
     fn special_mathml_elements() {
-        let document = Document::shared();
         for element in SPECIAL_MATHML_ELEMENTS.iter() {
             let mut attributes = HashMap::new();
             attributes.insert("id".to_string(), "test".to_string());
-            let node = Node::new_element(&document, element, attributes, MATHML_NAMESPACE);
+            let node = Node::new_element(element, attributes, MATHML_NAMESPACE);
             assert!(node.is_special());
         }
     }
 
     #[test]
     fn special_svg_elements() {
-        let document = Document::shared();
         for element in SPECIAL_SVG_ELEMENTS.iter() {
             let mut attributes = HashMap::new();
             attributes.insert("id".to_string(), "test".to_string());
-            let node = Node::new_element(&document, element, attributes, SVG_NAMESPACE);
+            let node = Node::new_element(element, attributes, SVG_NAMESPACE);
             assert!(node.is_special());
         }
     }
 
     #[test]
     fn type_of_node() {
-        let document = Document::shared();
-        let node = Node::new_document(&document);
+        let node = Node::new_document();
         assert_eq!(node.type_of(), NodeType::Document);
-        let node = Node::new_text(&document, "test");
+        let node = Node::new_text("test");
         assert_eq!(node.type_of(), NodeType::Text);
-        let node = Node::new_comment(&document, "test");
+        let node = Node::new_comment("test");
         assert_eq!(node.type_of(), NodeType::Comment);
         let mut attributes = HashMap::new();
         attributes.insert("id".to_string(), "test".to_string());
-        let node = Node::new_element(&document, "div", attributes, HTML_NAMESPACE);
+        let node = Node::new_element("div", attributes, HTML_NAMESPACE);
         assert_eq!(node.type_of(), NodeType::Element);
     }
 }
@@ -11,9 +11,10 @@ pub struct NodeArena {
     /// Order of nodes
     ///
     /// Note that the order of nodes isn't directly needed for functionality, but merely present
-    /// for debugging purposes.
+    /// for debugging purposes. It can contain the ids of removed nodes, until it is compacted.
     order: Vec<NodeId>,
-    /// Next node ID to use
+    /// Next node ID to use. Ids only go up, so the id of a removed node is never used again: a
+    /// stale id finds no node instead of a new one.
     next_id: NodeId,
 }
 
@@ -33,6 +34,21 @@ impl NodeArena {
         }
     }
 
+    /// Creates an arena from registered nodes (in order of registration)
+    pub(crate) fn from_nodes(nodes: Vec<Node>, next_id: NodeId) -> Self {
+        let order = nodes.iter().map(|node| node.id).collect();
+        Self {
+            nodes: nodes.into_iter().map(|node| (node.id, node)).collect(),
+            order,
+            next_id,
+        }
+    }
+
+    /// Returns the nodes in order of registration
+    pub(crate) fn nodes(&self) -> impl Iterator<Item = &Node> {
+        self.order.iter().filter_map(|id| self.nodes.get(id))
+    }
+
     /// Count the number of nodes registered in the arena
     pub(crate) fn count_nodes(&self) -> usize {
         self.nodes.len()
@@ -71,11 +87,27 @@ impl NodeArena {
         id
     }
 
+    /// Removes the node from the arena and returns it (unregistered, so it can be registered
+    /// again under a new id). Its id is not reused. Note that this does not update the parent or
+    /// children of the node: use `Document::remove_subtree()` to remove nodes from a document.
+    pub fn remove_node(&mut self, node_id: NodeId) -> Option<Node> {
+        let mut node = self.nodes.remove(&node_id)?;
+        node.is_registered = false;
+
+        // Compact the order once it mostly consists of removed nodes
+        if self.order.len() > 32 && self.order.len() > 2 * self.nodes.len() {
+            let nodes = &self.nodes;
+            self.order.retain(|id| nodes.contains_key(id));
+        }
+
+        Some(node)
+    }
+
     /// Prints the list of nodes in sequential order. This makes debugging a bit easier, but should
     /// be removed.
     pub(crate) fn print_nodes(&self) {
-        for id in self.order.iter() {
-            println!("({}): {:?}", id, self.nodes.get(id).expect("node"));
+        for node in self.nodes() {
+            println!("({}): {:?}", node.id, node);
         }
     }
 }
@@ -96,7 +128,7 @@ mod tests {
     fn register_node() {
         let mut doc = Document::shared();
 
-        let node = Node::new_element(&doc, "test", HashMap::new(), HTML_NAMESPACE);
+        let node = Node::new_element("test", HashMap::new(), HTML_NAMESPACE);
         let mut document = doc.get_mut();
         let id = document.arena.register_node(node);
 
@@ -110,7 +142,7 @@ mod tests {
     fn register_node_twice() {
         let mut doc = Document::shared();
 
-        let node = Node::new_element(&doc, "test", HashMap::new(), HTML_NAMESPACE);
+        let node = Node::new_element("test", HashMap::new(), HTML_NAMESPACE);
         let mut document = doc.get_mut();
         document.arena.register_node(node);
 
@@ -121,7 +153,7 @@ mod tests {
     #[test]
     fn get_node() {
         let mut doc = Document::shared();
-        let node = Node::new_element(&doc, "test", HashMap::new(), HTML_NAMESPACE);
+        let node = Node::new_element("test", HashMap::new(), HTML_NAMESPACE);
 
         let mut document = doc.get_mut();
         let id = document.arena.register_node(node);
@@ -133,7 +165,7 @@ mod tests {
     #[test]
     fn get_node_mut() {
         let mut doc = Document::shared();
-        let node = Node::new_element(&doc, "test", HashMap::new(), HTML_NAMESPACE);
+        let node = Node::new_element("test", HashMap::new(), HTML_NAMESPACE);
 
         let mut document = doc.get_mut();
 
@@ -143,16 +175,45 @@ mod tests {
         assert_eq!(node.unwrap().name, "test");
     }
 
+    #[test]
+    fn remove_node() {
+        let mut arena = NodeArena::new();
+        let mut ids = Vec::new();
+        for i in 0..40 {
+            let node = Node::new_element(&format!("n{}", i), HashMap::new(), HTML_NAMESPACE);
+            ids.push(arena.register_node(node));
+        }
+
+        let removed = arena.remove_node(ids[1]).unwrap();
+        assert_eq!(removed.name, "n1");
+        assert!(!removed.is_registered);
+        assert!(arena.get_node(ids[1]).is_none());
+        assert!(arena.remove_node(ids[1]).is_none());
+
+        // The removed id is not reused, and the node can be registered again
+        let id = arena.register_node(removed);
+        assert_eq!(id, NodeId(40));
+        assert!(arena.get_node(ids[1]).is_none());
+
+        for &id in &ids[2..] {
+            arena.remove_node(id);
+        }
+        assert_eq!(arena.count_nodes(), 2);
+        assert!(arena.order.len() < 40);
+        let names: Vec<_> = arena.nodes().map(|node| node.name.as_str()).collect();
+        assert_eq!(names, ["n0", "n1"]);
+    }
+
     #[test]
     fn register_node_through_document() {
         let mut doc = Document::shared();
 
-        let parent = Node::new_element(&doc, "parent", HashMap::new(), HTML_NAMESPACE);
-        let child = Node::new_element(&doc, "child", HashMap::new(), HTML_NAMESPACE);
+        let parent = Node::new_element("parent", HashMap::new(), HTML_NAMESPACE);
+        let child = Node::new_element("child", HashMap::new(), HTML_NAMESPACE);
 
         let mut document = doc.get_mut();
         let parent_id = document.arena.register_node(parent);
-        let child_id = document.add_node(child, parent_id, None);
+        let child_id = document.add_node(child, parent_id, None).unwrap();
 
         let parent = document.get_node_by_id(parent_id);
         assert!(parent.is_some());
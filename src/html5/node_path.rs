@@ -0,0 +1,160 @@
+//! Stable node paths
+//!
+//! A `NodeId` is only meaningful inside the document that assigned it: ids depend on the order
+//! in which nodes were created, and are never reused. External tools (devtools frontends, test
+//! baselines, remote debugging protocols) need a way to refer to a node that survives parsing
+//! the same document again, or sending the reference to another process.
+//!
+//! A node path is the list of child indexes from the root of the document down to the node. It
+//! is written as `/0/1/3` (the root itself is `/`), and parsing the same html always gives the
+//! same paths. A path changes when the tree before the node changes.
+use crate::html5::node::NodeId;
+use crate::html5::parser::document::Document;
+use crate::types::{Error, Result};
+use core::fmt;
+use std::str::FromStr;
+
+/// Path of child indexes from the root of the document to a node
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct NodePath(Vec<usize>);
+
+impl NodePath {
+    /// Returns the path of the root node
+    pub fn root() -> Self {
+        Self::default()
+    }
+
+    /// Returns the child indexes from the root down to the node
+    pub fn indexes(&self) -> &[usize] {
+        &self.0
+    }
+
+    /// Returns the path of the child at the index
+    pub fn child(&self, index: usize) -> Self {
+        let mut indexes = self.0.clone();
+        indexes.push(index);
+        Self(indexes)
+    }
+
+    /// Returns the path of the parent, or None for the root
+    pub fn parent(&self) -> Option<Self> {
+        let (_, indexes) = self.0.split_last()?;
+        Some(Self(indexes.to_vec()))
+    }
+}
+
+impl From<Vec<usize>> for NodePath {
+    fn from(indexes: Vec<usize>) -> Self {
+        Self(indexes)
+    }
+}
+
+impl fmt::Display for NodePath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.0.is_empty() {
+            return write!(f, "/");
+        }
+        for index in &self.0 {
+            write!(f, "/{index}")?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for NodePath {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let Some(rest) = s.strip_prefix('/') else {
+            return Err(Error::Parse(format!("node path must start with '/': {s}")));
+        };
+        if rest.is_empty() {
+            return Ok(Self::root());
+        }
+        rest.split('/')
+            .map(|index| {
+                index
+                    .parse()
+                    .map_err(|_| Error::Parse(format!("invalid index in node path: {s}")))
+            })
+            .collect::<Result<Vec<usize>>>()
+            .map(Self)
+    }
+}
+
+impl Document {
+    /// Returns the path of the node, or None when the node does not exist or is not connected
+    /// to the root of the document
+    pub fn node_path(&self, node_id: NodeId) -> Option<NodePath> {
+        let mut indexes = Vec::new();
+        let mut current = self.get_node_by_id(node_id)?;
+        while let Some(parent_id) = current.parent {
+            let parent = self.get_node_by_id(parent_id)?;
+            indexes.push(parent.children.iter().position(|&id| id == current.id)?);
+            current = parent;
+        }
+        if !current.id.is_root() {
+            return None;
+        }
+
+        indexes.reverse();
+        Some(NodePath(indexes))
+    }
+
+    /// Returns the node at the path, or None when there is no such node
+    pub fn node_by_path(&self, path: &NodePath) -> Option<NodeId> {
+        path.0.iter().try_fold(NodeId::root(), |node_id, &index| {
+            self.get_node_by_id(node_id)?.children.get(index).copied()
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::parse;
+
+    #[test]
+    fn display_and_parse() {
+        let path = NodePath::from(vec![0, 1, 3]);
+        assert_eq!(path.to_string(), "/0/1/3");
+        assert_eq!("/0/1/3".parse::<NodePath>().unwrap(), path);
+        assert_eq!(NodePath::root().to_string(), "/");
+        assert_eq!("/".parse::<NodePath>().unwrap(), NodePath::root());
+        assert_eq!(path.parent(), Some(NodePath::from(vec![0, 1])));
+        assert_eq!(NodePath::root().child(2).to_string(), "/2");
+
+        for invalid in ["", "0/1", "/0/", "//", "/a", "/-1"] {
+            assert!(invalid.parse::<NodePath>().is_err(), "{invalid}");
+        }
+    }
+
+    #[test]
+    fn stable_across_parses() {
+        let html = "<div><p>one</p><!-- c --><p id=target>two</p></div>";
+        let mut first = parse(html);
+        let target = first.get().get_node_by_named_id("target").unwrap().id;
+        let path = first.get().node_path(target).unwrap();
+        assert_eq!(path.to_string(), "/0/1/0/2");
+        assert_eq!(first.get().node_by_path(&path), Some(target));
+
+        // The path is resolved in another document through its string form
+        let second = parse(html);
+        let path = path.to_string().parse().unwrap();
+        let node_id = second.get().node_by_path(&path).unwrap();
+        assert_eq!(
+            second.get().get_node_by_named_id("target").unwrap().id,
+            node_id
+        );
+
+        assert_eq!(
+            first.get().node_path(NodeId::root()),
+            Some(NodePath::root())
+        );
+        assert_eq!(first.get().node_by_path(&NodePath::from(vec![0, 9])), None);
+        assert_eq!(first.get().node_path(NodeId::from(999)), None);
+
+        first.detach_node_from_parent(target);
+        assert_eq!(first.get().node_path(target), None);
+    }
+}
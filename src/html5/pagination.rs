@@ -0,0 +1,841 @@
+//! Pagination
+//!
+//! For print media (like the PDF backend of the embedder), a document is broken into pages. The
+//! embedder lays the document out as one long page, as wide as the page area, and `paginate()`
+//! breaks that layout into page boxes: for every page it returns the fragments of the elements
+//! on it, positioned in the page.
+//!
+//! The page boxes follow from the `@page` rules in the `style` elements of the document: their
+//! `size` and `margin` declarations, for all pages or for the pages that match `:first`, `:left`,
+//! `:right` or `:blank`. The first page is a right page, and the sides alternate from there.
+//! Pages have the given size when no rule sets one, and no margins when no rule sets them. Named
+//! pages are not supported.
+//!
+//! A page ends at a forced break: `break-before` or `break-after` set to `page`, `left` or
+//! `right` (or the legacy `page-break-before` and `page-break-after`). A blank page is inserted
+//! when a break to a left or right page would otherwise end up on the wrong side. Otherwise the
+//! page ends at the last possible break that fits on it: between boxes, or inside a box at a
+//! break point the layout reports (like between lines, see `LayoutGeometry::break_points()`).
+//! Breaks inside boxes with `break-inside: avoid` that fit on a page are not possible, and
+//! neither are breaks at the edges of boxes with `break-before: avoid` or `break-after: avoid`.
+//! When there is no possible break at all, the contents are cut at the end of the page.
+//!
+//! Fixed boxes are repeated on every page.
+//!
+//! See: https://drafts.csswg.org/css-page-3/ and https://drafts.csswg.org/css-break-3/
+use crate::html5::box_decoration::{four_sides, words};
+use crate::html5::element_style::{cascaded_value, parse_declarations, StyleDeclaration};
+use crate::html5::generated_content::{block_length, strip_comments, style_element_css};
+use crate::html5::geometry::{Positioning, Rect};
+use crate::html5::node::{NodeData, NodeId};
+use crate::html5::parser::document::Document;
+use crate::html5::transform::{parse_length, LengthPercentage};
+
+/// CSS pixels per millimeter
+const MM: f32 = 96.0 / 25.4;
+
+/// Size of a page in CSS pixels
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PageSize {
+    pub width: f32,
+    pub height: f32,
+}
+
+impl PageSize {
+    /// ISO A5 (148mm by 210mm)
+    pub const A5: PageSize = PageSize::new(148.0 * MM, 210.0 * MM);
+    /// ISO A4 (210mm by 297mm)
+    pub const A4: PageSize = PageSize::new(210.0 * MM, 297.0 * MM);
+    /// ISO A3 (297mm by 420mm)
+    pub const A3: PageSize = PageSize::new(297.0 * MM, 420.0 * MM);
+    /// North American letter (8.5in by 11in)
+    pub const LETTER: PageSize = PageSize::new(816.0, 1056.0);
+    /// North American legal (8.5in by 14in)
+    pub const LEGAL: PageSize = PageSize::new(816.0, 1344.0);
+
+    pub const fn new(width: f32, height: f32) -> Self {
+        Self { width, height }
+    }
+
+    /// Returns the (portrait) page size with the name, as used in the `size` property
+    pub fn from_name(name: &str) -> Option<PageSize> {
+        let size = match name.to_ascii_lowercase().as_str() {
+            "a5" => PageSize::A5,
+            "a4" => PageSize::A4,
+            "a3" => PageSize::A3,
+            "letter" => PageSize::LETTER,
+            "legal" => PageSize::LEGAL,
+            _ => return None,
+        };
+        Some(size)
+    }
+
+    /// Parses a `size` value. `auto` and an orientation on its own use the default size. Returns
+    /// None for invalid values.
+    pub fn parse(value: &str, default: PageSize) -> Option<PageSize> {
+        let mut size = None;
+        let mut lengths = Vec::new();
+        let mut landscape = None;
+        for word in words(value) {
+            match word.to_ascii_lowercase().as_str() {
+                "auto" if size.is_none() && lengths.is_empty() => size = Some(default),
+                "portrait" if landscape.is_none() => landscape = Some(false),
+                "landscape" if landscape.is_none() => landscape = Some(true),
+                _ => {
+                    if let Some(named) = PageSize::from_name(word).filter(|_| size.is_none()) {
+                        size = Some(named);
+                    } else {
+                        lengths.push(parse_length(word).ok().filter(|length| *length > 0.0)?);
+                    }
+                }
+            }
+        }
+
+        let size = match (size, &lengths[..]) {
+            (None, []) if landscape.is_some() => default,
+            (Some(size), []) => size,
+            (None, [length]) if landscape.is_none() => PageSize::new(*length, *length),
+            (None, [width, height]) if landscape.is_none() => PageSize::new(*width, *height),
+            _ => return None,
+        };
+        Some(match landscape {
+            Some(true) if size.height > size.width => PageSize::new(size.height, size.width),
+            Some(false) if size.width > size.height => PageSize::new(size.height, size.width),
+            _ => size,
+        })
+    }
+}
+
+/// Side of a page in a spread
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PageSide {
+    Left,
+    Right,
+}
+
+/// A pseudo-class of a `@page` rule
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PageSelector {
+    First,
+    Left,
+    Right,
+    Blank,
+}
+
+/// A `@page` rule
+#[derive(Debug, Clone, PartialEq)]
+pub struct PageRule {
+    /// The pages the rule applies to must match all of these (so all pages when empty)
+    pub selectors: Vec<PageSelector>,
+    pub declarations: Vec<StyleDeclaration>,
+}
+
+impl PageRule {
+    /// Parses the `@page` rules of a style sheet. Other rules, `@page` rules of named pages and
+    /// the margin boxes in `@page` rules are skipped.
+    pub fn parse_all(css: &str) -> Vec<PageRule> {
+        let css = strip_comments(css);
+        let mut rules = Vec::new();
+
+        let mut rest = css.as_str();
+        while let Some(open) = rest.find(['{', ';']) {
+            let prelude = rest[..open].trim();
+            if rest[open..].starts_with(';') {
+                rest = &rest[open + 1..];
+                continue;
+            }
+            let block = &rest[open..open + block_length(&rest[open..])];
+            let body = block[1..].strip_suffix('}').unwrap_or(&block[1..]);
+            rest = &rest[open + block.len()..];
+
+            let Some(selectors) = prelude
+                .get(..5)
+                .filter(|at| at.eq_ignore_ascii_case("@page"))
+                .map(|_| &prelude[5..])
+            else {
+                continue;
+            };
+            let declarations = parse_declarations(&without_blocks(body));
+            for selector in selectors.split(',') {
+                if let Some(selectors) = parse_page_selector(selector) {
+                    rules.push(PageRule {
+                        selectors,
+                        declarations: declarations.clone(),
+                    });
+                }
+            }
+        }
+        rules
+    }
+
+    /// Returns true when the rule applies to the page
+    fn matches(&self, number: usize, side: PageSide, blank: bool) -> bool {
+        self.selectors.iter().all(|selector| match selector {
+            PageSelector::First => number == 1,
+            PageSelector::Left => side == PageSide::Left,
+            PageSelector::Right => side == PageSide::Right,
+            PageSelector::Blank => blank,
+        })
+    }
+
+    /// Returns the specificity of the rule: `:first` and `:blank` weigh more than `:left` and
+    /// `:right`
+    fn specificity(&self) -> (usize, usize) {
+        let first = self
+            .selectors
+            .iter()
+            .filter(|selector| matches!(selector, PageSelector::First | PageSelector::Blank))
+            .count();
+        (first, self.selectors.len() - first)
+    }
+}
+
+/// Parses the selector of a `@page` rule (after `@page`). Returns None for named pages and
+/// unknown pseudo-classes.
+fn parse_page_selector(selector: &str) -> Option<Vec<PageSelector>> {
+    let selector = selector.trim();
+    if selector.is_empty() {
+        return Some(Vec::new());
+    }
+    selector
+        .strip_prefix(':')?
+        .split(':')
+        .map(|pseudo| match pseudo.trim().to_ascii_lowercase().as_str() {
+            "first" => Some(PageSelector::First),
+            "left" => Some(PageSelector::Left),
+            "right" => Some(PageSelector::Right),
+            "blank" => Some(PageSelector::Blank),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Removes the nested blocks (like the margin boxes of a `@page` rule) from the declarations
+fn without_blocks(body: &str) -> String {
+    let mut output = String::with_capacity(body.len());
+    let mut rest = body;
+    while let Some(at) = rest.find('@') {
+        output.push_str(&rest[..at]);
+        rest = &rest[at..];
+        let Some(open) = rest.find('{') else {
+            return output;
+        };
+        rest = &rest[open + block_length(&rest[open..])..];
+    }
+    output.push_str(rest);
+    output
+}
+
+/// A page
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PageBox {
+    /// Number of the page, starting at 1
+    pub number: usize,
+    pub side: PageSide,
+    /// True for a page that was inserted to get the next page on the right side
+    pub blank: bool,
+    pub size: PageSize,
+    /// Top, right, bottom and left
+    pub margins: [f32; 4],
+}
+
+impl PageBox {
+    /// Returns the page area: the page without its margins, in page coordinates
+    pub fn page_area(&self) -> Rect {
+        let [top, right, bottom, left] = self.margins;
+        Rect::new(
+            left,
+            top,
+            (self.size.width - left - right).max(0.0),
+            (self.size.height - top - bottom).max(0.0),
+        )
+    }
+}
+
+/// The part of an element that is on a page, with the parts of its descendants
+#[derive(Debug, Clone, PartialEq)]
+pub struct Fragment {
+    pub node_id: NodeId,
+    /// The part of the border box that is on the page, in page coordinates
+    pub rect: Rect,
+    /// True when the element started on an earlier page
+    pub continued: bool,
+    /// True when the element continues on a later page
+    pub continues: bool,
+    pub children: Vec<Fragment>,
+}
+
+/// A page with the fragments of the elements on it
+#[derive(Debug, Clone, PartialEq)]
+pub struct PageFragmentTree {
+    pub page: PageBox,
+    /// Fragments of the elements on the page that have no ancestor on it (normally just the
+    /// root element)
+    pub fragments: Vec<Fragment>,
+}
+
+impl PageFragmentTree {
+    /// Returns the elements that have a fragment on the page, in tree order
+    pub fn elements(&self) -> Vec<NodeId> {
+        let mut elements = Vec::new();
+        let mut stack: Vec<&Fragment> = self.fragments.iter().rev().collect();
+        while let Some(fragment) = stack.pop() {
+            elements.push(fragment.node_id);
+            stack.extend(fragment.children.iter().rev());
+        }
+        elements
+    }
+}
+
+/// A break between boxes, as set by `break-before` and `break-after`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum BreakBetween {
+    #[default]
+    Auto,
+    Avoid,
+    /// A forced break
+    Page,
+    /// A forced break, to a left page
+    Left,
+    /// A forced break, to a right page
+    Right,
+}
+
+impl BreakBetween {
+    /// Returns the break for the keyword of `break-before`, `break-after` or their legacy
+    /// `page-break-` versions. Column and region breaks are not supported, and are auto.
+    pub fn from_keyword(keyword: &str) -> Option<BreakBetween> {
+        let value = match keyword.trim().to_ascii_lowercase().as_str() {
+            "auto" | "avoid-column" | "column" | "avoid-region" | "region" => BreakBetween::Auto,
+            "avoid" | "avoid-page" => BreakBetween::Avoid,
+            "page" | "always" | "all" => BreakBetween::Page,
+            "left" | "verso" => BreakBetween::Left,
+            "right" | "recto" => BreakBetween::Right,
+            _ => return None,
+        };
+        Some(value)
+    }
+
+    /// Returns true for a forced break
+    pub fn is_forced(&self) -> bool {
+        matches!(
+            self,
+            BreakBetween::Page | BreakBetween::Left | BreakBetween::Right
+        )
+    }
+
+    /// Returns the side of the page that follows the break, if it must be on a certain side
+    fn side(&self) -> Option<PageSide> {
+        match self {
+            BreakBetween::Left => Some(PageSide::Left),
+            BreakBetween::Right => Some(PageSide::Right),
+            _ => None,
+        }
+    }
+}
+
+/// An element box in the layout that is broken into pages
+struct FlowBox {
+    rect: Rect,
+    before: BreakBetween,
+    after: BreakBetween,
+    avoid_inside: bool,
+    break_points: Vec<f32>,
+}
+
+/// The part of the layout that is on a page, and where it goes on the page
+struct Slice {
+    top: f32,
+    bottom: f32,
+    /// Position in the layout that is at the left of the page area
+    left: f32,
+    area: Rect,
+}
+
+/// Breaks the layout of the document into pages of the size (unless the `@page` rules set
+/// another size). Returns no pages when there is no layout.
+pub fn paginate(document: &Document, page_size: PageSize) -> Vec<PageFragmentTree> {
+    let mut boxes = Vec::new();
+    document.flow_boxes(NodeId::root(), &mut boxes);
+    let Some(flow) = boxes
+        .iter()
+        .map(|flow_box| flow_box.rect)
+        .reduce(|flow, rect| flow.union(&rect))
+    else {
+        return Vec::new();
+    };
+    let rules = document.page_rules();
+
+    // Forced breaks at the start of the layout only decide the side of the first page
+    let mut forced: Vec<(f32, BreakBetween)> = boxes
+        .iter()
+        .flat_map(|flow_box| {
+            [
+                (flow_box.rect.y, flow_box.before),
+                (flow_box.rect.bottom(), flow_box.after),
+            ]
+        })
+        .filter(|(y, value)| value.is_forced() && *y < flow.bottom())
+        .collect();
+    forced.sort_by(|a, b| a.0.total_cmp(&b.0));
+    let mut required_side = forced
+        .iter()
+        .take_while(|(y, _)| *y <= flow.y)
+        .find_map(|(_, value)| value.side());
+
+    let mut pages = Vec::new();
+    let mut top = flow.y;
+    loop {
+        let number = pages.len() + 1;
+        let side = if number % 2 == 1 {
+            PageSide::Right
+        } else {
+            PageSide::Left
+        };
+        if required_side
+            .take()
+            .is_some_and(|required| required != side)
+        {
+            pages.push(PageFragmentTree {
+                page: document.page_box(&rules, number, side, true, page_size),
+                fragments: Vec::new(),
+            });
+            continue;
+        }
+
+        let page = document.page_box(&rules, number, side, false, page_size);
+        // A page without room for contents still takes some, so pagination ends
+        let height = page.page_area().height.max(1.0);
+        let mut bottom = top + height;
+        if let Some((y, value)) = forced.iter().find(|(y, _)| *y > top && *y <= bottom) {
+            bottom = *y;
+            required_side = value.side();
+        } else if bottom < flow.bottom() {
+            bottom = best_break(&boxes, top, bottom, height);
+        }
+
+        let slice = Slice {
+            top,
+            bottom,
+            left: flow.x,
+            area: page.page_area(),
+        };
+        let mut fragments = Vec::new();
+        document.fragments(NodeId::root(), &slice, flow.y, &mut fragments);
+        pages.push(PageFragmentTree { page, fragments });
+
+        if bottom >= flow.bottom() {
+            return pages;
+        }
+        top = bottom;
+    }
+}
+
+/// Returns the last possible break after the top of the page, and at or before its natural end
+/// (see the module documentation), or the natural end when there is none
+fn best_break(boxes: &[FlowBox], top: f32, bottom: f32, height: f32) -> f32 {
+    let possible = |y: f32| {
+        !boxes.iter().any(|flow_box| {
+            let rect = flow_box.rect;
+            (flow_box.avoid_inside && rect.height <= height && rect.y < y && y < rect.bottom())
+                || (flow_box.before == BreakBetween::Avoid && rect.y == y)
+                || (flow_box.after == BreakBetween::Avoid && rect.bottom() == y)
+        })
+    };
+    boxes
+        .iter()
+        .flat_map(|flow_box| {
+            [flow_box.rect.y, flow_box.rect.bottom()]
+                .into_iter()
+                .chain(flow_box.break_points.iter().copied())
+        })
+        .filter(|&y| y > top && y <= bottom && possible(y))
+        .reduce(f32::max)
+        .unwrap_or(bottom)
+}
+
+impl Document {
+    /// Returns the `@page` rules in the `style` elements of the document
+    pub fn page_rules(&self) -> Vec<PageRule> {
+        PageRule::parse_all(&style_element_css(self))
+    }
+
+    /// Returns the break before the element (`break-before`, or `page-break-before`)
+    pub fn break_before(&self, node_id: NodeId) -> BreakBetween {
+        self.break_between(node_id, "break-before", "page-break-before")
+    }
+
+    /// Returns the break after the element (`break-after`, or `page-break-after`)
+    pub fn break_after(&self, node_id: NodeId) -> BreakBetween {
+        self.break_between(node_id, "break-after", "page-break-after")
+    }
+
+    /// Returns true when pages should not break inside the element (`break-inside` or
+    /// `page-break-inside` set to `avoid`)
+    pub fn avoids_break_inside(&self, node_id: NodeId) -> bool {
+        let declarations = self.declared_style(node_id);
+        let value = cascaded_value(&declarations, "break-inside")
+            .or_else(|| cascaded_value(&declarations, "page-break-inside"));
+        value.is_some_and(|value| {
+            matches!(
+                value.trim().to_ascii_lowercase().as_str(),
+                "avoid" | "avoid-page"
+            )
+        })
+    }
+
+    fn break_between(&self, node_id: NodeId, property: &str, legacy: &str) -> BreakBetween {
+        let declarations = self.declared_style(node_id);
+        cascaded_value(&declarations, property)
+            .or_else(|| cascaded_value(&declarations, legacy))
+            .and_then(BreakBetween::from_keyword)
+            .unwrap_or_default()
+    }
+
+    /// Returns the page box of the page, with the declarations of the rules that match it
+    /// applied in order of specificity
+    fn page_box(
+        &self,
+        rules: &[PageRule],
+        number: usize,
+        side: PageSide,
+        blank: bool,
+        default_size: PageSize,
+    ) -> PageBox {
+        let mut matching: Vec<&PageRule> = rules
+            .iter()
+            .filter(|rule| rule.matches(number, side, blank))
+            .collect();
+        matching.sort_by_key(|rule| rule.specificity());
+        let declarations: Vec<StyleDeclaration> = matching
+            .into_iter()
+            .flat_map(|rule| rule.declarations.iter().cloned())
+            .collect();
+
+        let size = cascaded_value(&declarations, "size")
+            .and_then(|value| PageSize::parse(value, default_size))
+            .unwrap_or(default_size);
+        let parse = |value: &str| LengthPercentage::parse(value).ok();
+        let mut margins = cascaded_value(&declarations, "margin")
+            .and_then(|value| four_sides(value, parse))
+            .unwrap_or_default();
+        for (index, property) in ["margin-top", "margin-right", "margin-bottom", "margin-left"]
+            .into_iter()
+            .enumerate()
+        {
+            if let Some(margin) = cascaded_value(&declarations, property).and_then(parse) {
+                margins[index] = margin;
+            }
+        }
+
+        PageBox {
+            number,
+            side,
+            blank,
+            size,
+            margins: [
+                margins[0].resolve(size.height),
+                margins[1].resolve(size.width),
+                margins[2].resolve(size.height),
+                margins[3].resolve(size.width),
+            ],
+        }
+    }
+
+    /// Adds the boxes of the displayed elements below the node (except fixed ones) in tree order
+    fn flow_boxes(&self, node_id: NodeId, boxes: &mut Vec<FlowBox>) {
+        let Some(geometry) = self.geometry.0.as_ref() else {
+            return;
+        };
+        let Some(node) = self.get_node_by_id(node_id) else {
+            return;
+        };
+        for &child in &node.children {
+            if !self.is_flowing_element(child) || geometry.positioning(child) == Positioning::Fixed
+            {
+                continue;
+            }
+            if let Some(rect) = geometry.border_box(child) {
+                boxes.push(FlowBox {
+                    rect,
+                    before: self.break_before(child),
+                    after: self.break_after(child),
+                    avoid_inside: self.avoids_break_inside(child),
+                    break_points: geometry.break_points(child),
+                });
+            }
+            self.flow_boxes(child, boxes);
+        }
+    }
+
+    /// Adds the fragments of the elements below the node that are on the page
+    fn fragments(&self, node_id: NodeId, slice: &Slice, flow_top: f32, out: &mut Vec<Fragment>) {
+        let Some(geometry) = self.geometry.0.as_ref() else {
+            return;
+        };
+        let Some(node) = self.get_node_by_id(node_id) else {
+            return;
+        };
+        for &child in &node.children {
+            if !self.is_flowing_element(child) {
+                continue;
+            }
+            // Fixed boxes are on every page, as a whole
+            let fixed;
+            let slice = if geometry.positioning(child) == Positioning::Fixed {
+                fixed = Slice {
+                    top: flow_top,
+                    bottom: f32::INFINITY,
+                    ..*slice
+                };
+                &fixed
+            } else {
+                slice
+            };
+
+            let Some(rect) = geometry.border_box(child).filter(|rect| {
+                // Empty boxes are on the page they start on
+                rect.y < slice.bottom && (rect.bottom() > slice.top || rect.y >= slice.top)
+            }) else {
+                self.fragments(child, slice, flow_top, out);
+                continue;
+            };
+            let start = rect.y.max(slice.top);
+            let end = rect.bottom().min(slice.bottom);
+            let mut children = Vec::new();
+            self.fragments(child, slice, flow_top, &mut children);
+            out.push(Fragment {
+                node_id: child,
+                rect: Rect::new(
+                    slice.area.x + rect.x - slice.left,
+                    slice.area.y + start - slice.top,
+                    rect.width,
+                    end - start,
+                ),
+                continued: rect.y < slice.top,
+                continues: rect.bottom() > slice.bottom,
+                children,
+            });
+        }
+    }
+
+    /// Returns true when the node is an element that is not hidden
+    fn is_flowing_element(&self, node_id: NodeId) -> bool {
+        self.get_node_by_id(node_id)
+            .is_some_and(|node| matches!(node.data, NodeData::Element(_)))
+            && !self.is_hidden(node_id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::html5::geometry::LayoutGeometry;
+    use crate::html5::parser::document::DocumentHandle;
+    use crate::testing::parse;
+    use std::collections::HashMap;
+    use std::rc::Rc;
+
+    #[derive(Default)]
+    struct Layout {
+        boxes: HashMap<NodeId, Rect>,
+        break_points: HashMap<NodeId, Vec<f32>>,
+        fixed: Vec<NodeId>,
+    }
+
+    impl LayoutGeometry for Layout {
+        fn border_box(&self, node_id: NodeId) -> Option<Rect> {
+            self.boxes.get(&node_id).copied()
+        }
+
+        fn viewport(&self) -> Rect {
+            Rect::new(0.0, 0.0, 800.0, 600.0)
+        }
+
+        fn positioning(&self, node_id: NodeId) -> Positioning {
+            if self.fixed.contains(&node_id) {
+                Positioning::Fixed
+            } else {
+                Positioning::Static
+            }
+        }
+
+        fn break_points(&self, node_id: NodeId) -> Vec<f32> {
+            self.break_points.get(&node_id).cloned().unwrap_or_default()
+        }
+    }
+
+    /// Parses the document, and lays out the elements with an id as a stack of boxes of the
+    /// heights, 500 wide. The html and body elements hold all of them.
+    fn setup(
+        html: &str,
+        heights: &[(&str, f32)],
+        layout: impl FnOnce(&mut Layout, &dyn Fn(&str) -> NodeId),
+    ) -> (DocumentHandle, HashMap<String, NodeId>) {
+        let mut document = parse(html);
+
+        let doc = document.get();
+        let ids: HashMap<String, NodeId> = doc
+            .descendants(NodeId::root())
+            .filter_map(|node| Some((node.get_attribute("id")?.clone(), node.id)))
+            .collect();
+        let mut boxes = Layout::default();
+        let mut y = 0.0;
+        for (id, height) in heights {
+            boxes
+                .boxes
+                .insert(ids[*id], Rect::new(0.0, y, 500.0, *height));
+            y += height;
+        }
+        for name in ["html", "body"] {
+            let node_id = doc.elements_by_tag_name(name)[0];
+            boxes.boxes.insert(node_id, Rect::new(0.0, 0.0, 500.0, y));
+        }
+        layout(&mut boxes, &|id| ids[id]);
+        drop(doc);
+
+        document.get_mut().set_layout_geometry(Rc::new(boxes));
+        (document, ids)
+    }
+
+    #[test]
+    fn page_boxes() {
+        assert_eq!(
+            PageSize::parse("A4 landscape", PageSize::LETTER),
+            Some(PageSize::new(297.0 * MM, 210.0 * MM))
+        );
+        assert_eq!(
+            PageSize::parse("landscape", PageSize::LETTER),
+            Some(PageSize::new(1056.0, 816.0))
+        );
+        assert_eq!(
+            PageSize::parse("5in 7in", PageSize::LETTER),
+            Some(PageSize::new(480.0, 672.0))
+        );
+        assert_eq!(PageSize::parse("auto", PageSize::A5), Some(PageSize::A5));
+        assert_eq!(PageSize::parse("5in portrait 7in", PageSize::A5), None);
+        assert_eq!(PageSize::parse("huge", PageSize::A5), None);
+
+        let (document, _) = setup(
+            r#"<style>
+                 @page { margin: 10px 20px; size: 400px 600px; @top-center { content: "x" } }
+                 @page :first { margin-top: 10% }
+                 @page :left { margin-left: 50px }
+                 @page chapter { margin: 0 }
+                 @media print { @page { margin: 0 } }
+               </style>
+               <p id=a style="break-after: page"></p><p id=b></p><p id=c></p>"#,
+            &[("a", 100.0), ("b", 100.0), ("c", 100.0)],
+            |_, _| {},
+        );
+        let doc = document.get();
+        assert_eq!(doc.page_rules().len(), 3);
+        let c = doc.get_node_by_named_id("c").unwrap().id;
+        assert_eq!(doc.break_before(c), BreakBetween::Auto);
+
+        let pages = paginate(&doc, PageSize::A4);
+        assert_eq!(pages.len(), 2);
+        let first = pages[0].page;
+        assert_eq!(first.size, PageSize::new(400.0, 600.0));
+        assert_eq!(first.side, PageSide::Right);
+        assert_eq!(first.margins, [60.0, 20.0, 10.0, 20.0]);
+        assert_eq!(first.page_area(), Rect::new(20.0, 60.0, 360.0, 530.0));
+        let second = pages[1].page;
+        assert_eq!((second.number, second.side), (2, PageSide::Left));
+        assert_eq!(second.margins, [10.0, 20.0, 10.0, 50.0]);
+    }
+
+    #[test]
+    fn breaks() {
+        let (document, ids) = setup(
+            r#"<p id=a></p>
+               <div id=keep style="break-inside: avoid"><p id=b></p><p id=c></p></div>
+               <p id=lines></p>
+               <h2 id=heading style="page-break-before: right; break-after: avoid"></h2>
+               <p id=d></p>"#,
+            &[
+                ("a", 300.0),
+                ("keep", 400.0),
+                ("lines", 500.0),
+                ("heading", 50.0),
+                ("d", 1200.0),
+            ],
+            |layout, id| {
+                // The kept box holds b and c
+                layout
+                    .boxes
+                    .insert(id("b"), Rect::new(0.0, 300.0, 500.0, 200.0));
+                layout
+                    .boxes
+                    .insert(id("c"), Rect::new(0.0, 500.0, 500.0, 200.0));
+                layout
+                    .break_points
+                    .insert(id("lines"), vec![800.0, 900.0, 1000.0, 1100.0]);
+            },
+        );
+        let doc = document.get();
+        let pages = paginate(&doc, PageSize::new(500.0, 600.0));
+        let elements = |page: &PageFragmentTree| -> Vec<String> {
+            let names: HashMap<NodeId, &String> = ids.iter().map(|(k, v)| (*v, k)).collect();
+            page.elements()
+                .iter()
+                .filter_map(|node_id| names.get(node_id).map(|name| name.to_string()))
+                .collect()
+        };
+
+        // The kept box does not fit after a, so it moves to the next page, followed by the
+        // lines that fit
+        assert_eq!(elements(&pages[0]), ["a"]);
+        assert_eq!(elements(&pages[1]), ["keep", "b", "c", "lines"]);
+        assert_eq!(elements(&pages[2]), ["lines"]);
+        // The heading goes to a right page, so a blank page comes first. As the heading avoids a
+        // break after it, it goes along with d, which does not fit and is cut.
+        assert!(pages[3].page.blank);
+        assert_eq!(pages[3].fragments, []);
+        assert_eq!(elements(&pages[4]), ["heading", "d"]);
+        assert_eq!(pages[4].page.side, PageSide::Right);
+        assert_eq!(elements(&pages[5]), ["d"]);
+        assert_eq!(elements(&pages[6]), ["d"]);
+        assert_eq!(pages.len(), 7);
+
+        // The lines are broken at the last break point that fits
+        let body = &pages[1].fragments[0].children[0];
+        let lines = body.children.last().unwrap();
+        assert_eq!(lines.rect, Rect::new(0.0, 400.0, 500.0, 200.0));
+        assert!(lines.continues && !lines.continued);
+    }
+
+    #[test]
+    fn fragments() {
+        let (document, ids) = setup(
+            r#"<style>@page { margin: 50px }</style>
+               <header id=header></header><p id=a></p><p id=b></p>"#,
+            &[("a", 200.0), ("b", 300.0)],
+            |layout, id| {
+                layout
+                    .boxes
+                    .insert(id("header"), Rect::new(0.0, 0.0, 500.0, 20.0));
+                layout.fixed.push(id("header"));
+            },
+        );
+        let doc = document.get();
+        let pages = paginate(&doc, PageSize::new(600.0, 400.0));
+        assert_eq!(pages.len(), 2);
+
+        // The fixed header is on every page
+        for page in &pages {
+            let body = &page.fragments[0].children[0];
+            assert_eq!(body.children[0].node_id, ids["header"]);
+            assert_eq!(body.children[0].rect, Rect::new(50.0, 50.0, 500.0, 20.0));
+        }
+
+        // b does not fit on the first page, and has no break points
+        let html = &pages[1].fragments[0];
+        assert!(html.continued && !html.continues);
+        assert_eq!(html.rect, Rect::new(50.0, 50.0, 500.0, 300.0));
+        let b = &html.children[0].children[1];
+        assert_eq!(b.node_id, ids["b"]);
+        assert_eq!(b.rect, Rect::new(50.0, 50.0, 500.0, 300.0));
+        assert!(!b.continued && !b.continues);
+    }
+}
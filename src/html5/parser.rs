@@ -1,24 +1,37 @@
 mod attr_replacements;
 pub mod document;
-mod quirks;
+pub mod encoding;
+pub mod events;
+pub mod limits;
+pub mod quirks;
+pub mod resilient;
+pub mod script;
 pub mod tree_builder;
+pub mod tree_sink;
 
 // ------------------------------------------------------------
 
 use self::document::DocumentHandle;
 use super::node::NodeId;
-use crate::bytes::CharIterator;
+use crate::bytes::{CharIterator, Encoding, Span};
 use crate::html5::error_logger::{ErrorLogger, ParserError};
+use crate::html5::lifecycle::DocumentReadyState;
 use crate::html5::node::{Node, NodeData, HTML_NAMESPACE, MATHML_NAMESPACE, SVG_NAMESPACE};
 use crate::html5::parser::attr_replacements::{
-    MATHML_ADJUSTMENTS, SVG_ADJUSTMENTS_ATTRIBUTES, SVG_ADJUSTMENTS_TAGS, XML_ADJUSTMENTS,
+    MATHML_ADJUSTMENTS, SVG_ADJUSTMENTS_ATTRIBUTES, SVG_ADJUSTMENTS_TAGS,
 };
-use crate::html5::parser::document::{Document, DocumentBuilder, DocumentFragment, DocumentType};
+use crate::html5::parser::document::{
+    Document, DocumentBuilder, DocumentFragment, DocumentType, MutationRecord,
+};
+use crate::html5::parser::events::ParserEventHandler;
+use crate::html5::parser::limits::{Limit, ParserLimits};
 use crate::html5::parser::quirks::QuirksMode;
+use crate::html5::parser::script::{Script, ScriptHandler};
+use crate::html5::parser::tree_sink::TreeSink;
 use crate::html5::tokenizer::state::State;
 use crate::html5::tokenizer::token::Token;
 use crate::html5::tokenizer::{ParserData, Tokenizer, CHAR_REPLACEMENT};
-use crate::types::{ParseError, Result};
+use crate::types::{Error, ParseError, Result};
 use alloc::rc::Rc;
 use core::cell::RefCell;
 use core::option::Option::Some;
@@ -88,13 +101,15 @@ impl VecExtensions<NodeId> for Vec<NodeId> {
     }
 }
 
+// The macros below return a copy of the node without its children (see
+// `Node::clone_without_children`). Children must be read from the document itself.
 macro_rules! get_node_by_id {
     ($doc_handle:expr, $id:expr) => {
         $doc_handle
             .get()
             .get_node_by_id($id)
             .expect("Node not found")
-            .clone()
+            .clone_without_children()
     };
 }
 
@@ -106,7 +121,7 @@ macro_rules! current_node {
             .get()
             .get_node_by_id(*current_node_idx)
             .expect("Current node not found")
-            .clone()
+            .clone_without_children()
     }};
 }
 
@@ -117,7 +132,7 @@ macro_rules! open_elements_get {
             .get()
             .get_node_by_id($self.open_elements[$idx])
             .expect("Current node not found")
-            .clone()
+            .clone_without_children()
     }};
 }
 
@@ -142,12 +157,27 @@ impl ActiveElement {
 
 pub struct Html5ParserOptions {
     pub scripting_enabled: bool,
+    /// Handler that executes the scripts found in the document. When not set, scripts are not
+    /// executed.
+    pub script_handler: Option<Rc<RefCell<dyn ScriptHandler>>>,
+    /// Sink that receives all tree mutations done by the parser. When not set, the parse output
+    /// is only available through the document.
+    pub tree_sink: Option<Rc<RefCell<dyn TreeSink>>>,
+    /// Handler that receives the events of the parser (like a restart because of an encoding
+    /// change)
+    pub event_handler: Option<Rc<RefCell<dyn ParserEventHandler>>>,
+    /// Limits on the size of the document, for parsing untrusted input
+    pub limits: ParserLimits,
 }
 
 impl Default for Html5ParserOptions {
     fn default() -> Self {
         Html5ParserOptions {
             scripting_enabled: true,
+            script_handler: None,
+            tree_sink: None,
+            event_handler: None,
+            limits: ParserLimits::default(),
         }
     }
 }
@@ -166,6 +196,8 @@ pub struct Html5Parser<'chars> {
     parser_cannot_change_mode: bool,
     /// Current token from the tokenizer
     current_token: Token,
+    /// Span of the current token in the input stream
+    token_span: Option<Span>,
     /// If true, the current token should be processed again
     reprocess_token: bool,
     /// Stack of open elements
@@ -188,7 +220,7 @@ pub struct Html5Parser<'chars> {
     ack_self_closing: bool,
     /// List of active formatting elements or markers
     active_formatting_elements: Vec<ActiveElement>,
-    /// Is the current parsing a fragment case. If so, the context_node_id and context_doc should be set as well.
+    /// Is the current parsing a fragment case. If so, the context_node should be set as well.
     is_fragment_case: bool,
     /// A reference to the document we are parsing
     document: DocumentHandle,
@@ -206,10 +238,22 @@ pub struct Html5Parser<'chars> {
     token_queue: Vec<Token>,
     /// When true, the parser is finished and should not consume more tokens (there aren't any)
     parser_finished: bool,
-    /// Context node id for fragment parsing
-    context_node_id: Option<NodeId>,
-    /// Context node document for fragment parsing (we don't want to keep Option<Node> as this clones a whole node
-    context_doc: Option<DocumentHandle>,
+    /// Context node for fragment parsing
+    context_node: Option<Node>,
+    /// Handler that executes scripts
+    script_handler: Option<Rc<RefCell<dyn ScriptHandler>>>,
+    /// Scripts that will be executed when parsing has finished
+    deferred_scripts: Vec<Script>,
+    /// Sink that receives all tree mutations
+    tree_sink: Option<Rc<RefCell<dyn TreeSink>>>,
+    /// Handler that receives the parser events
+    event_handler: Option<Rc<RefCell<dyn ParserEventHandler>>>,
+    /// When true, the document is parsed again from the start once parsing has stopped
+    restart_requested: bool,
+    /// Limits on the size of the document
+    limits: ParserLimits,
+    /// Limits that have been exceeded (and reported) so far
+    exceeded_limits: Vec<Limit>,
 }
 
 /// Defines the scopes for in_scope()
@@ -230,11 +274,14 @@ enum DispatcherMode {
 impl<'chars> Html5Parser<'chars> {
     // Initializes the parser for whole document parsing
     fn init(
-        tokenizer: Tokenizer<'chars>,
+        mut tokenizer: Tokenizer<'chars>,
         document: DocumentHandle,
         error_logger: Rc<RefCell<ErrorLogger>>,
         options: Option<Html5ParserOptions>,
     ) -> Self {
+        let options = options.unwrap_or_default();
+        tokenizer.set_limits(&options.limits);
+
         Html5Parser {
             tokenizer,
             insertion_mode: InsertionMode::Initial,
@@ -242,11 +289,12 @@ impl<'chars> Html5Parser<'chars> {
             template_insertion_mode: vec![],
             parser_cannot_change_mode: false,
             current_token: Token::Eof,
+            token_span: None,
             reprocess_token: false,
             open_elements: Vec::new(),
             head_element: None,
             form_element: None,
-            scripting_enabled: options.unwrap_or_default().scripting_enabled,
+            scripting_enabled: options.scripting_enabled,
             frameset_ok: true,
             foster_parenting: false,
             script_already_started: false,
@@ -262,8 +310,14 @@ impl<'chars> Html5Parser<'chars> {
             ignore_lf: false,
             token_queue: vec![],
             parser_finished: false,
-            context_node_id: None,
-            context_doc: None,
+            context_node: None,
+            script_handler: options.script_handler,
+            deferred_scripts: Vec::new(),
+            tree_sink: options.tree_sink,
+            event_handler: options.event_handler,
+            restart_requested: false,
+            limits: options.limits,
+            exceeded_limits: Vec::new(),
         }
     }
 
@@ -281,6 +335,7 @@ impl<'chars> Html5Parser<'chars> {
             template_insertion_mode: vec![],
             parser_cannot_change_mode: false,
             current_token: Token::Eof,
+            token_span: None,
             reprocess_token: false,
             open_elements: Vec::new(),
             head_element: None,
@@ -301,17 +356,87 @@ impl<'chars> Html5Parser<'chars> {
             ignore_lf: false,
             token_queue: vec![],
             parser_finished: false,
-            context_node_id: None,
-            context_doc: None,
+            context_node: None,
+            script_handler: None,
+            deferred_scripts: Vec::new(),
+            tree_sink: None,
+            event_handler: None,
+            restart_requested: false,
+            limits: ParserLimits::default(),
+            exceeded_limits: Vec::new(),
         }
     }
 
     /// Parses a fragment of HTML instead of a whole document. It will run the parser in a slightly different mode.
     /// This is used for parsing innerHTML and document fragments.
     pub fn parse_fragment(
+        chars: &mut CharIterator,
+        document: DocumentHandle,
+        context_node: &Node,
+        options: Option<Html5ParserOptions>,
+    ) -> Result<Vec<ParseError>> {
+        let context_document = Document::clone(&document);
+        Self::parse_fragment_with_context(chars, document, context_node, &context_document, options)
+    }
+
+    /// Parses the input as the children of the context element, like setting its `innerHTML`.
+    /// The context element (which must exist in the document) determines how the input is
+    /// tokenized and which insertion mode the parser starts in: the input of a `<textarea>` is
+    /// text, and `<td>` is only an element in a table row.
+    ///
+    /// The document is not changed: the parsed nodes are returned in a document fragment that
+    /// has the context element as its host. The top-level nodes are the `children()` of the
+    /// fragment.
+    pub fn parse_fragment_for_context(
+        document: &DocumentHandle,
+        context: NodeId,
+        input: &str,
+        options: Option<Html5ParserOptions>,
+    ) -> Result<(DocumentFragment, Vec<ParseError>)> {
+        let context_node = match document.get().get_node_by_id(context) {
+            Some(node) if matches!(node.data, NodeData::Element(_)) => {
+                node.clone_without_children()
+            }
+            Some(_) => {
+                return Err(Error::DocumentTask(format!(
+                    "Node ID {} is not an element",
+                    context
+                )))
+            }
+            None => {
+                return Err(Error::DocumentTask(format!(
+                    "Node ID {} not found",
+                    context
+                )))
+            }
+        };
+
+        let mut chars = CharIterator::new();
+        chars.read_from_str(input, Some(Encoding::UTF8));
+
+        let mut fragment_document = DocumentBuilder::new_document_fragment(document);
+        let errors = Self::parse_fragment_with_context(
+            &mut chars,
+            Document::clone(&fragment_document),
+            &context_node,
+            document,
+            options,
+        )?;
+
+        let arena = std::mem::take(&mut fragment_document.get_mut().arena);
+        Ok((
+            DocumentFragment::with_arena(Document::clone(document), context, arena),
+            errors,
+        ))
+    }
+
+    /// Parses a fragment into the document. The context node is part of the context document,
+    /// which is the document itself for the fragment test cases.
+    fn parse_fragment_with_context(
         chars: &mut CharIterator,
         mut document: DocumentHandle,
         context_node: &Node,
+        context_document: &DocumentHandle,
         options: Option<Html5ParserOptions>,
     ) -> Result<Vec<ParseError>> {
         // https://html.spec.whatwg.org/multipage/parsing.html#parsing-html-fragments
@@ -320,7 +445,7 @@ impl<'chars> Html5Parser<'chars> {
         document.get_mut().doctype = DocumentType::HTML;
 
         // 2.
-        document.get_mut().quirks_mode = context_node.document.get().quirks_mode;
+        // The quirks mode of the context document is set by DocumentBuilder::new_document_fragment()
 
         // 3.
         let error_logger = Rc::new(RefCell::new(ErrorLogger::new()));
@@ -364,18 +489,19 @@ impl<'chars> Html5Parser<'chars> {
         parser.reset_insertion_mode();
 
         // 11. Set the parser's form element pointer to the nearest node to the context element that is a form element (going straight up the ancestor chain, and including the element itself, if it is a form element), if any. (If there is no such form element, the form element pointer keeps its initial value, null.)
-        let mut node = context_node.clone();
-        loop {
-            if node.name == "form" {
-                parser.form_element = Some(node.id);
-                break;
-            }
-
-            if node.parent.is_none() {
+        let mut node = Some(context_node.clone());
+        while let Some(current) = node {
+            if current.name == "form" {
+                parser.form_element = Some(current.id);
                 break;
             }
 
-            node = get_node_by_id!(document, node.parent.unwrap());
+            node = current.parent.and_then(|parent_id| {
+                context_document
+                    .get()
+                    .get_node_by_id(parent_id)
+                    .map(Node::clone_without_children)
+            });
         }
 
         // 13. / 14.
@@ -400,19 +526,88 @@ impl<'chars> Html5Parser<'chars> {
         parser.do_parse()
     }
 
+    /// Creates a parser for a document whose input arrives in chunks (like a network response).
+    /// The chunks are passed to `feed()` as they arrive and `finish()` is called after the last
+    /// one. The input must be UTF-8; a chunk may end in the middle of a character or a tag, in
+    /// which case the parser continues when the rest arrives.
+    ///
+    /// The parser inserts nodes into the document directly, so after each call to `feed()` the
+    /// document contains all nodes of the input that has been tokenized completely, and can be
+    /// rendered or inspected while loading.
+    pub fn new_streaming(
+        chars: &'chars mut CharIterator,
+        document: DocumentHandle,
+        options: Option<Html5ParserOptions>,
+    ) -> Self {
+        chars.open();
+
+        let error_logger = Rc::new(RefCell::new(ErrorLogger::new()));
+        let tokenizer = Tokenizer::new(chars, None, error_logger.clone());
+        Html5Parser::init(tokenizer, document, error_logger, options)
+    }
+
+    /// Parses the next chunk of the input, as far as it can be parsed. The remainder is kept
+    /// until more input arrives.
+    pub fn feed(&mut self, bytes: &[u8]) {
+        self.tokenizer.chars.append_bytes(bytes);
+        self.run();
+    }
+
+    /// Parses the remainder of the input after the last chunk has been fed, and finishes the
+    /// document
+    pub fn finish(mut self) -> Result<Vec<ParseError>> {
+        self.tokenizer.chars.close();
+        self.do_parse()
+    }
+
     /// Internal parser function that does the actual parsing
     fn do_parse(&mut self) -> Result<Vec<ParseError>> {
+        self.run();
+
+        // https://html.spec.whatwg.org/multipage/parsing.html#the-end
+        if !self.is_fragment_case {
+            self.document
+                .set_ready_state(DocumentReadyState::Interactive);
+        }
+        self.run_deferred_scripts();
+        if !self.is_fragment_case {
+            self.document.dom_content_loaded();
+        }
+
+        let errors = self.errors();
+        self.document.get_mut().parse_errors = errors.clone();
+        Ok(errors)
+    }
+
+    /// Returns the parse errors found so far, in the order they were found. After parsing, the
+    /// errors are also available from the document (see `Document::parse_errors`).
+    pub fn errors(&self) -> Vec<ParseError> {
+        self.error_logger.borrow().get_errors()
+    }
+
+    /// Runs the tree construction until parsing has stopped, or until the input runs out while
+    /// more input can still arrive
+    fn run(&mut self) {
         let mut dispatcher_mode = DispatcherMode::Html;
 
         loop {
             // When the parser is signalled to finish, we break our main parser loop
             if self.parser_finished {
+                if self.restart_requested {
+                    self.restart();
+                    continue;
+                }
                 break;
             }
 
             // If reprocess_token is true, we should process the same token again
             if !self.reprocess_token {
-                self.current_token = self.fetch_next_token();
+                let Some(token) = self.fetch_streamed_token() else {
+                    // Wait for more input
+                    return;
+                };
+                self.current_token = self.apply_limits(token);
+                self.ack_self_closing = false;
 
                 // If we reprocess a given token, the dispatcher mode should stay the same and
                 // should not be re-evaluated
@@ -431,12 +626,22 @@ impl<'chars> Html5Parser<'chars> {
                 }
             }
 
+            // A self-closing flag on a start tag is only allowed on void and foreign elements
+            if !self.reprocess_token && !self.ack_self_closing {
+                if let Token::StartTag {
+                    is_self_closing: true,
+                    ..
+                } = self.current_token
+                {
+                    self.parse_error(
+                        ParserError::NonVoidHtmlElementStartTagWithTrailingSolidus.as_str(),
+                    );
+                }
+            }
+
             #[cfg(feature = "debug_parser")]
             self.display_debug_info();
         }
-
-        let result = Ok(self.error_logger.borrow().get_errors().clone());
-        result
     }
 
     // Process token in foreign content (svg, mathml)
@@ -545,7 +750,6 @@ impl<'chars> Html5Parser<'chars> {
                     self.adjust_svg_attributes(&mut current_token);
                 }
 
-                self.adjust_foreign_attributes(&mut current_token);
                 self.insert_foreign_element(
                     &current_token,
                     acn.namespace.expect("namespace").as_str(),
@@ -635,9 +839,8 @@ impl<'chars> Html5Parser<'chars> {
     fn process_html_content(&mut self) {
         if self.ignore_lf {
             if let Token::Text(value) = &self.current_token {
-                if value.starts_with('\n') {
-                    // We don't need to skip 1 char, but we can skip 1 byte, as we just checked for \n
-                    self.current_token = Token::Text(value.chars().skip(1).collect::<String>());
+                if let Some(value) = value.strip_prefix('\n') {
+                    self.current_token = Token::Text(value.to_string());
                 }
             }
             self.ignore_lf = false;
@@ -997,7 +1200,7 @@ impl<'chars> Html5Parser<'chars> {
                     Token::EndTag { name, .. } if name == "script" => {
                         // @todo: If the active speculative HTML parser is null and the JavaScript execution context stack is empty, then perform a microtask checkpoint.
 
-                        let _script = current_node!(self);
+                        let script_id = current_node!(self).id;
 
                         self.open_elements.pop();
                         self.insertion_mode = self.original_insertion_mode;
@@ -1007,7 +1210,7 @@ impl<'chars> Html5Parser<'chars> {
 
                         self.script_nesting_level += 1;
 
-                        // do script stuff
+                        self.run_script(script_id);
 
                         self.script_nesting_level -= 1;
                         if self.script_nesting_level == 0 {
@@ -1705,6 +1908,37 @@ impl<'chars> Html5Parser<'chars> {
 
     fn set_quirks_mode(&mut self, mode: QuirksMode) {
         self.document.get_mut().quirks_mode = mode;
+        self.notify_tree_sink(|sink| sink.set_quirks_mode(mode));
+    }
+
+    /// Adds the given attributes to the element, except the ones it already has
+    fn add_missing_attributes(&mut self, node_id: NodeId, attributes: &HashMap<String, String>) {
+        let mut added = HashMap::new();
+
+        let mut doc = self.document.get_mut();
+        let snapshot = doc.attribute_snapshot(node_id);
+        if let Some(node) = doc.get_node_by_id_mut(node_id) {
+            if let NodeData::Element(element) = &mut node.data {
+                for (key, value) in attributes {
+                    if !element.attributes.contains_key(key) {
+                        element.attributes.insert(key.to_owned(), value.to_owned());
+                        added.insert(key.to_owned(), value.to_owned());
+                    }
+                }
+            }
+        }
+        for name in added.keys() {
+            doc.record_mutation(MutationRecord::Attribute {
+                node_id,
+                name: name.clone(),
+            });
+        }
+        doc.record_attribute_change(node_id, snapshot);
+        drop(doc);
+
+        if !added.is_empty() {
+            self.notify_tree_sink(|sink| sink.add_attributes_if_missing(node_id, &added));
+        }
     }
 
     fn is_iframesrcdoc(&self) -> bool {
@@ -1818,6 +2052,18 @@ impl<'chars> Html5Parser<'chars> {
 
     /// Create a new node that is not connected or attached to the document arena
     fn create_node(&self, token: &Token, namespace: &str) -> Node {
+        let mut node = self.create_node_without_span(token, namespace);
+        // Nodes that are made from the current token (or from its text) get its span. Nodes the
+        // parser creates on its own, like implied elements, have no span.
+        node.source_span = match (token, &self.current_token) {
+            (Token::Text(_), Token::Text(_)) => self.token_span,
+            _ if *token == self.current_token => self.token_span,
+            _ => None,
+        };
+        node
+    }
+
+    fn create_node_without_span(&self, token: &Token, namespace: &str) -> Node {
         match token {
             Token::DocType {
                 name,
@@ -1825,19 +2071,16 @@ impl<'chars> Html5Parser<'chars> {
                 sys_identifier,
                 ..
             } => Node::new_doctype(
-                &self.document,
                 &name.clone().unwrap_or_default(),
                 &pub_identifier.clone().unwrap_or_default(),
                 &sys_identifier.clone().unwrap_or_default(),
             ),
             Token::StartTag {
                 name, attributes, ..
-            } => Node::new_element(&self.document, name, attributes.clone(), namespace),
-            Token::EndTag { name, .. } => {
-                Node::new_element(&self.document, name, HashMap::new(), namespace)
-            }
-            Token::Comment(value) => Node::new_comment(&self.document, value),
-            Token::Text(value) => Node::new_text(&self.document, value.to_string().as_str()),
+            } => Node::new_element(name, attributes.clone(), namespace),
+            Token::EndTag { name, .. } => Node::new_element(name, HashMap::new(), namespace),
+            Token::Comment(value) => Node::new_comment(value),
+            Token::Text(value) => Node::new_text(value.to_string().as_str()),
             Token::Eof => {
                 panic!("EOF token not allowed");
             }
@@ -1898,11 +2141,7 @@ impl<'chars> Html5Parser<'chars> {
 
                 // fragment case
                 if self.is_fragment_case {
-                    node = get_node_by_id!(
-                        self.context_doc.clone().expect("context_doc not found"),
-                        self.context_node_id.expect("context_node_id not found")
-                    )
-                    .clone();
+                    node = self.context_node.clone().expect("context_node not found");
                 }
             }
             match node.name.as_str() {
@@ -2153,17 +2392,7 @@ impl<'chars> Html5Parser<'chars> {
 
                 // Add attributes to html element
                 let first_node_id = *self.open_elements.first().unwrap();
-                let mut doc = self.document.get_mut();
-                let first_node = doc
-                    .get_node_by_id_mut(first_node_id)
-                    .expect("node not found");
-                if let NodeData::Element(element) = &mut first_node.data {
-                    for (key, value) in attributes {
-                        if !element.attributes.contains_key(key) {
-                            element.attributes.insert(key.to_owned(), value.to_owned());
-                        }
-                    }
-                };
+                self.add_missing_attributes(first_node_id, attributes);
             }
             Token::StartTag { name, .. }
                 if name == "base"
@@ -2203,19 +2432,8 @@ impl<'chars> Html5Parser<'chars> {
                     node.name == "body" && node.is_namespace(HTML_NAMESPACE)
                 });
 
-                if let Some(body_node_id) = body_node_id {
-                    let mut doc = self.document.get_mut();
-                    let body_node = doc
-                        .get_node_by_id_mut(*body_node_id)
-                        .expect("node not found");
-
-                    if let NodeData::Element(element) = &mut body_node.data {
-                        for (key, value) in attributes {
-                            if !element.attributes.contains_key(key) {
-                                element.attributes.insert(key.to_owned(), value.to_owned());
-                            }
-                        }
-                    }
+                if let Some(body_node_id) = body_node_id.copied() {
+                    self.add_missing_attributes(body_node_id, attributes);
                 }
             }
             Token::StartTag { name, .. } if name == "frameset" => {
@@ -2238,6 +2456,7 @@ impl<'chars> Html5Parser<'chars> {
                         self.document
                             .get_mut()
                             .detach_node_from_parent(second_node_id);
+                        self.notify_tree_sink(|sink| sink.remove_from_parent(second_node_id));
                     }
                 }
 
@@ -2895,7 +3114,6 @@ impl<'chars> Html5Parser<'chars> {
                     is_self_closing: *is_self_closing,
                 };
                 self.adjust_mathml_attributes(&mut token);
-                self.adjust_foreign_attributes(&mut token);
 
                 self.insert_foreign_element(&token, MATHML_NAMESPACE);
 
@@ -2918,7 +3136,6 @@ impl<'chars> Html5Parser<'chars> {
                 };
 
                 self.adjust_svg_attributes(&mut token);
-                self.adjust_foreign_attributes(&mut token);
                 self.insert_foreign_element(&token, SVG_NAMESPACE);
 
                 if *is_self_closing {
@@ -2980,10 +3197,17 @@ impl<'chars> Html5Parser<'chars> {
                 is_self_closing,
                 ..
             } if name == "base" || name == "basefont" || name == "bgsound" || name == "link" => {
+                let is_base = name == "base";
                 self.acknowledge_closing_tag(*is_self_closing);
 
-                self.insert_html_element(&self.current_token.clone());
+                let node_id = self.insert_html_element(&self.current_token.clone());
                 self.open_elements.pop();
+
+                // Only the first base elements with an href or target define the base URL and
+                // target of the document
+                if is_base {
+                    self.document.get_mut().freeze_base_element(node_id);
+                }
             }
             Token::StartTag {
                 name,
@@ -2992,11 +3216,15 @@ impl<'chars> Html5Parser<'chars> {
             } if name == "meta" => {
                 self.acknowledge_closing_tag(*is_self_closing);
 
-                self.insert_html_element(&self.current_token.clone());
+                let node_id = self.insert_html_element(&self.current_token.clone());
                 self.open_elements.pop();
 
-                // @TODO: if active speculative html parser is null then...
-                // we probably want to change the encoding if the element has a charset attribute and the current encoding is "tentative"
+                let mut document = self.document.get_mut();
+                document.apply_meta_content_security_policy(node_id);
+                document.apply_meta_referrer_policy(node_id);
+                drop(document);
+
+                self.apply_meta_charset(node_id);
             }
             Token::StartTag { name, .. } if name == "title" => {
                 self.parse_rcdata();
@@ -3015,6 +3243,7 @@ impl<'chars> Html5Parser<'chars> {
                 let insert_position = self.appropriate_place_insert(None);
                 let node = self.create_node(&self.current_token.clone(), HTML_NAMESPACE);
                 let node_id = self.document.get_mut().add_new_node(node);
+                self.notify_node_created(node_id);
                 self.insert_element_helper(node_id, insert_position);
 
                 // TODO Set the element's parser document to the Document, and set the element's force async to false.
@@ -3068,6 +3297,7 @@ impl<'chars> Html5Parser<'chars> {
                         data.template_contents = Some(DocumentFragment::new(doc, current_node_id));
                     }
                 }
+                self.notify_tree_sink(|sink| sink.template_contents(node_id));
             }
             Token::EndTag { name, .. } if name == "template" => {
                 if !self.open_elements_has("template") {
@@ -3703,22 +3933,6 @@ impl<'chars> Html5Parser<'chars> {
         }
     }
 
-    fn adjust_foreign_attributes(&self, token: &mut Token) {
-        if let Token::StartTag { attributes, .. } = token {
-            let mut new_attributes = HashMap::new();
-            for (name, value) in attributes.iter() {
-                if XML_ADJUSTMENTS.contains_key(name) {
-                    let (prefix, local_name, _namespace) =
-                        XML_ADJUSTMENTS.get(name).expect("cml adjustments");
-                    new_attributes.insert(format!("{} {}", prefix, local_name), value.clone());
-                } else {
-                    new_attributes.insert(name.clone(), value.clone());
-                }
-            }
-            *attributes = new_attributes;
-        }
-    }
-
     /// Switch the parser and tokenizer to the RAWTEXT state
     fn parse_raw_data(&mut self) {
         self.insert_html_element(&self.current_token.clone());
@@ -3835,10 +4049,11 @@ impl<'chars> Html5Parser<'chars> {
     fn fetch_next_token(&mut self) -> Token {
         // If there are no tokens to fetch, fetch the next token from the tokenizer
         if self.token_queue.is_empty() {
-            let token = self
+            let (token, span) = self
                 .tokenizer
-                .next_token(self.parser_data())
+                .next_token_with_span(self.parser_data())
                 .expect("tokenizer error");
+            self.token_span = Some(span);
 
             if let Token::Text(value) = token {
                 self.token_queue.push(Token::Text(value));
@@ -3857,14 +4072,31 @@ impl<'chars> Html5Parser<'chars> {
         token.expect("no token found")
     }
 
+    /// Fetches the next token like `fetch_next_token()`. When the input is still open and the
+    /// tokenizer runs out of it before the token is complete, the tokenizer is restored to where
+    /// the token started and None is returned.
+    fn fetch_streamed_token(&mut self) -> Option<Token> {
+        if !self.token_queue.is_empty() || !self.tokenizer.chars.is_open() {
+            return Some(self.fetch_next_token());
+        }
+
+        let checkpoint = self.tokenizer.checkpoint();
+        self.tokenizer.chars.take_reached_open_end();
+
+        let token = self.fetch_next_token();
+        if self.tokenizer.chars.take_reached_open_end() {
+            self.tokenizer.restore(checkpoint);
+            self.token_queue.clear();
+            return None;
+        }
+
+        Some(token)
+    }
+
     fn get_adjusted_current_node(&self) -> Node {
         if self.is_fragment_case && self.open_elements.len() == 1 {
             // fragment case
-            return get_node_by_id!(
-                self.context_doc.clone().expect("context doc not found"),
-                self.context_node_id.expect("context node not found")
-            )
-            .clone();
+            return self.context_node.clone().expect("context node not found");
         }
 
         current_node!(self)
@@ -3884,8 +4116,9 @@ impl<'chars> Html5Parser<'chars> {
         }
 
         if acn.is_mathml_integration_point()
-            && (!self.current_token.is_start_tag("mglyph")
-                && !self.current_token.is_start_tag("malignmark"))
+            && self.current_token.is_any_start_tag()
+            && !self.current_token.is_start_tag("mglyph")
+            && !self.current_token.is_start_tag("malignmark")
         {
             return DispatcherMode::Html;
         }
@@ -3963,8 +4196,7 @@ impl<'chars> Html5Parser<'chars> {
     // Initialize all parser settings for parsing a fragment case
     fn initialize_fragment_case(&mut self, context_node: &Node) {
         self.is_fragment_case = true;
-        self.context_doc = Some(Document::clone(&context_node.document));
-        self.context_node_id = Some(context_node.id);
+        self.context_node = Some(context_node.clone());
         self.tokenizer
             .set_state(self.find_initial_state_for_context(context_node));
     }
@@ -4058,15 +4290,14 @@ impl<'chars> Html5Parser<'chars> {
 mod test {
     use super::*;
     use crate::bytes::Encoding;
+    use crate::html5::node::XLINK_NAMESPACE;
     use crate::html5::parser::document::DocumentBuilder;
+    use crate::html5::serializer;
 
     macro_rules! node_create {
         ($self:expr, $name:expr) => {{
-            let node = Node::new_element(&$self.document, $name, HashMap::new(), HTML_NAMESPACE);
-            let node_id = $self
-                .document
-                .get_mut()
-                .add_node(node, NodeId::root(), None);
+            let node = Node::new_element($name, HashMap::new(), HTML_NAMESPACE);
+            let node_id = $self.document.get_mut().add_new_node(node);
             $self.open_elements.push(node_id);
         }};
     }
@@ -4290,16 +4521,21 @@ mod test {
 
     #[test]
     fn reconstruct_formatting() {
-        let mut chars = CharIterator::new();
-        chars.read_from_str(
-            "<p><b>bold<i>bold and italic</b>italic</i></p>",
-            Some(Encoding::UTF8),
+        assert_eq!(
+            parse_body("<p><b>bold<i>bold and italic</b>italic</i></p>"),
+            "<p><b>bold<i>bold and italic</i></b><i>italic</i></p>"
+        );
+        // Formatting elements that were closed by a block are reopened in the next block, with
+        // their attributes
+        assert_eq!(
+            parse_body(r#"<p><b class="x"><i>one</p><p>two"#),
+            r#"<p><b class="x"><i>one</i></b></p><p><b class="x"><i>two</i></b></p>"#
+        );
+        // Text after a misnested close tag keeps the formatting that is still active
+        assert_eq!(
+            parse_body("<b><i>one</b>two</i>three"),
+            "<b><i>one</i></b><i>two</i>three"
         );
-
-        let document = DocumentBuilder::new_document();
-        let _ = Html5Parser::parse_document(&mut chars, Document::clone(&document), None);
-
-        println!("{}", document);
     }
 
     #[test]
@@ -4397,4 +4633,469 @@ mod test {
         assert_eq!(div.id, NodeId::from(4));
         assert_eq!(div.name, "div");
     }
+
+    #[test]
+    fn first_base_element_is_frozen() {
+        let mut chars = CharIterator::new();
+        chars.read_from_str(
+            "<html><head><base href=\"/docs/\" target=\"_blank\"></head>\
+             <body><base href=\"https://other.example.com/\" target=\"_self\"></body></html>",
+            Some(Encoding::UTF8),
+        );
+
+        let mut document = DocumentBuilder::new_document();
+        document.get_mut().url = Some("https://example.com/index.html".parse().unwrap());
+        let errors =
+            Html5Parser::parse_document(&mut chars, Document::clone(&document), None).unwrap();
+
+        // Later base elements are ignored, which is not a parse error
+        assert!(errors.iter().all(|e| !e.message.contains("base")));
+
+        let doc_read = document.get();
+        assert_eq!(
+            doc_read.base_url().unwrap().as_str(),
+            "https://example.com/docs/"
+        );
+        assert_eq!(doc_read.base_target, Some("_blank".to_string()));
+        assert_eq!(
+            doc_read.resolve_url("page.html").unwrap().as_str(),
+            "https://example.com/docs/page.html"
+        );
+    }
+
+    #[test]
+    fn base_url_and_target_from_different_elements() {
+        let mut chars = CharIterator::new();
+        chars.read_from_str(
+            "<html><head><base target=x><base href=\"/y/\"><base href=/z/ target=z></head></html>",
+            Some(Encoding::UTF8),
+        );
+
+        let mut document = DocumentBuilder::new_document();
+        document.get_mut().url = Some("https://example.com/index.html".parse().unwrap());
+        let errors =
+            Html5Parser::parse_document(&mut chars, Document::clone(&document), None).unwrap();
+
+        let doc_read = document.get();
+        assert_eq!(
+            doc_read.base_url().unwrap().as_str(),
+            "https://example.com/y/"
+        );
+        assert_eq!(doc_read.base_target, Some("x".to_string()));
+        assert!(errors.iter().all(|e| !e.message.contains("base")));
+    }
+
+    #[test]
+    fn base_element_without_attributes() {
+        let mut chars = CharIterator::new();
+        chars.read_from_str(
+            "<!DOCTYPE html><html><head><base id=x></head></html>",
+            Some(Encoding::UTF8),
+        );
+
+        let mut document = DocumentBuilder::new_document();
+        document.get_mut().url = Some("https://example.com/index.html".parse().unwrap());
+        let errors =
+            Html5Parser::parse_document(&mut chars, Document::clone(&document), None).unwrap();
+
+        assert!(errors.is_empty());
+        let doc_read = document.get();
+        assert_eq!(
+            doc_read.base_url().unwrap().as_str(),
+            "https://example.com/index.html"
+        );
+        assert_eq!(doc_read.base_target, None);
+    }
+
+    #[test]
+    fn unacknowledged_self_closing_flag() {
+        let solidus_errors = |html: &str| {
+            let mut chars = CharIterator::new();
+            chars.read_from_str(html, Some(Encoding::UTF8));
+            let document = DocumentBuilder::new_document();
+            Html5Parser::parse_document(&mut chars, document, None)
+                .unwrap()
+                .iter()
+                .filter(|e| e.message == "non-void-html-element-start-tag-with-trailing-solidus")
+                .count()
+        };
+
+        assert_eq!(solidus_errors("<!DOCTYPE html><br/><img/><input/>"), 0);
+        assert_eq!(solidus_errors("<!DOCTYPE html><svg><circle/></svg>"), 0);
+        assert_eq!(solidus_errors("<!DOCTYPE html><div/><span/>"), 2);
+    }
+
+    #[test]
+    fn raw_text_and_rcdata_contents() {
+        let mut chars = CharIterator::new();
+        chars.read_from_str(
+            "<title>a <b> &amp; </titl></title>\
+             <style>p > a { content: \"</p>\" } </styl></style>\
+             <script><!--<script>x = '</script>'; --></script>\
+             <textarea>&lt;b&gt; &amp;</textarea>\
+             <xmp><p>&amp;</xmp><iframe><b></iframe><noembed></a></noembed>",
+            Some(Encoding::UTF8),
+        );
+        let document = DocumentBuilder::new_document();
+        let _ = Html5Parser::parse_document(&mut chars, Document::clone(&document), None);
+
+        let doc_read = document.get();
+        let text = |tag: &str| doc_read.text_content(doc_read.elements_by_tag_name(tag)[0]);
+
+        assert_eq!(text("title"), "a <b> & </titl>");
+        assert_eq!(text("style"), "p > a { content: \"</p>\" } </styl>");
+        assert_eq!(text("script"), "<!--<script>x = '</script>'; -->");
+        assert_eq!(text("textarea"), "<b> &");
+        assert_eq!(text("xmp"), "<p>&amp;");
+        assert_eq!(text("iframe"), "<b>");
+        assert_eq!(text("noembed"), "</a>");
+        assert!(doc_read.elements_by_tag_name("b").is_empty());
+        assert!(doc_read.elements_by_tag_name("p").is_empty());
+    }
+
+    #[test]
+    fn leading_newline_and_plaintext() {
+        let mut chars = CharIterator::new();
+        chars.read_from_str(
+            "<pre>\n\nfirst</pre><listing>\nsecond</listing><textarea>\n</textarea>\
+             <pre>\n</pre><plaintext></plaintext><p>&amp;</p>",
+            Some(Encoding::UTF8),
+        );
+        let document = DocumentBuilder::new_document();
+        let _ = Html5Parser::parse_document(&mut chars, Document::clone(&document), None);
+
+        let doc_read = document.get();
+        let element = |tag: &str, idx: usize| doc_read.elements_by_tag_name(tag)[idx];
+
+        assert_eq!(doc_read.text_content(element("pre", 0)), "\nfirst");
+        assert_eq!(doc_read.text_content(element("listing", 0)), "second");
+        let textarea = doc_read.get_node_by_id(element("textarea", 0)).unwrap();
+        assert!(textarea.children.is_empty());
+        let pre = doc_read.get_node_by_id(element("pre", 1)).unwrap();
+        assert!(pre.children.is_empty());
+
+        // Everything after <plaintext> is text, including end tags
+        assert_eq!(
+            doc_read.text_content(element("plaintext", 0)),
+            "</plaintext><p>&amp;</p>"
+        );
+        assert!(doc_read.elements_by_tag_name("p").is_empty());
+    }
+
+    #[test]
+    fn truncated_documents() {
+        let cases = [
+            (
+                "<title>x",
+                "<html><head><title>x</title></head><body></body></html>",
+            ),
+            (
+                "<script>a",
+                "<html><head><script>a</script></head><body></body></html>",
+            ),
+            (
+                "<table><tr><td>x",
+                "<html><head></head><body><table><tbody><tr><td>x</td></tr></tbody></table></body></html>",
+            ),
+            (
+                "<svg><p",
+                "<html><head></head><body><svg></svg></body></html>",
+            ),
+            (
+                "<a href='x",
+                "<html><head></head><body></body></html>",
+            ),
+            (
+                "<p>a<!--c",
+                "<html><head></head><body><p>a<!--c--></p></body></html>",
+            ),
+            (
+                "<frameset><frame",
+                "<html><head></head><frameset></frameset></html>",
+            ),
+        ];
+
+        for (html, expected) in cases {
+            let mut chars = CharIterator::new();
+            chars.read_from_str(html, Some(Encoding::UTF8));
+            let document = DocumentBuilder::new_document();
+            let _ = Html5Parser::parse_document(&mut chars, Document::clone(&document), None);
+
+            let output = serializer::serialize(
+                &document.get(),
+                NodeId::root(),
+                &serializer::SerializerOptions::default(),
+            );
+            assert_eq!(output, expected, "tree for {html:?}");
+        }
+    }
+
+    #[test]
+    fn streaming_parse() {
+        let html = "<!DOCTYPE html>\r\n<html><head><title>caf\u{e9} &amp; more</title></head>\r\n<body><p class=\"a b\">\u{1f600} text</p><!-- comment --><script>if (a < b) {}</script>tail</body></html>";
+
+        let mut chars = CharIterator::new();
+        chars.read_from_str(html, Some(Encoding::UTF8));
+        let whole = DocumentBuilder::new_document();
+        let whole_errors =
+            Html5Parser::parse_document(&mut chars, Document::clone(&whole), None).unwrap();
+
+        // Every chunk size splits characters, tags, character references and CRLFs somewhere
+        for size in 1..8 {
+            let mut chars = CharIterator::new();
+            let document = DocumentBuilder::new_document();
+            let mut parser =
+                Html5Parser::new_streaming(&mut chars, Document::clone(&document), None);
+            for chunk in html.as_bytes().chunks(size) {
+                parser.feed(chunk);
+            }
+            let errors = parser.finish().unwrap();
+
+            assert_eq!(document.serialize(), whole.serialize(), "chunk size {size}");
+            assert_eq!(errors, whole_errors, "chunk size {size}");
+        }
+    }
+
+    #[test]
+    fn streaming_partial_tree() {
+        let mut chars = CharIterator::new();
+        let document = DocumentBuilder::new_document();
+        let mut parser = Html5Parser::new_streaming(&mut chars, Document::clone(&document), None);
+
+        parser.feed(b"<html><body><p id=\"first\">one</p><p id=\"sec");
+        assert!(document.get().get_node_by_named_id("first").is_some());
+        assert!(document.get().get_node_by_named_id("second").is_none());
+
+        parser.feed(b"ond\">two</p>");
+        assert!(document.get().get_node_by_named_id("second").is_some());
+
+        parser.finish().unwrap();
+        assert_eq!(
+            document.serialize(),
+            "<html><head></head><body><p id=\"first\">one</p><p id=\"second\">two</p></body></html>"
+        );
+    }
+
+    #[test]
+    fn parse_errors_on_document() {
+        let mut chars = CharIterator::new();
+        let document = DocumentBuilder::new_document();
+        let mut parser = Html5Parser::new_streaming(&mut chars, Document::clone(&document), None);
+
+        parser.feed(b"<p a=1 a=2>\n");
+        let errors = parser.errors();
+        let codes: Vec<&str> = errors.iter().map(|error| error.message.as_str()).collect();
+        assert_eq!(
+            codes,
+            ["duplicate-attribute", "expected-doctype-but-got-start-tag"]
+        );
+        assert_eq!((errors[0].line, errors[0].col, errors[0].offset), (1, 9, 8));
+        assert!(document.get().parse_errors().is_empty());
+
+        parser.feed(b"</b>");
+        let errors = parser.finish().unwrap();
+        assert_eq!(errors.len(), 3);
+        assert_eq!(errors[2].line, 2);
+        assert_eq!(document.get().parse_errors(), errors);
+    }
+
+    #[test]
+    fn source_spans() {
+        let html = "<p id=a>x&amp;\u{e9}\ny</p><!--c-->";
+        let mut chars = CharIterator::new();
+        chars.read_from_str(html, Some(Encoding::UTF8));
+        let document = DocumentBuilder::new_document();
+        let _ = Html5Parser::parse_document(&mut chars, Document::clone(&document), None);
+
+        let doc = document.get();
+        let source = |node: &Node| {
+            let span = node.source_span().unwrap();
+            (&html[span.start_byte..span.end_byte], span)
+        };
+        let p = doc.get_node_by_named_id("a").unwrap();
+        let (text, span) = source(p);
+        assert_eq!(text, "<p id=a>");
+        assert_eq!((span.start.offset, span.end.offset), (0, 8));
+
+        // Text tokens are merged into one text node
+        let text_node = doc.get_node_by_id(p.children[0]).unwrap();
+        let (text, span) = source(text_node);
+        assert_eq!(text, "x&amp;\u{e9}\ny");
+        assert_eq!((span.end.offset, span.end.line, span.end.col), (17, 2, 2));
+        assert_eq!(span.end_byte, 18);
+
+        let body = doc.get_node_by_id(p.parent.unwrap()).unwrap();
+        assert!(body.source_span().is_none());
+        let comment = doc.get_node_by_id(body.children[1]).unwrap();
+        assert_eq!(source(comment).0, "<!--c-->");
+    }
+
+    #[test]
+    fn fragment_for_context() {
+        let mut chars = CharIterator::new();
+        chars.read_from_str(
+            "<table><tr id=row></tr></table><textarea id=ta></textarea><div id=d></div>",
+            Some(Encoding::UTF8),
+        );
+        let document = DocumentBuilder::new_document();
+        let _ = Html5Parser::parse_document(&mut chars, Document::clone(&document), None);
+        let html = document.serialize();
+        let named = |id: &str| document.get().get_node_by_named_id(id).unwrap().id;
+
+        let fragment_names = |context: NodeId, input: &str| {
+            let (fragment, _) =
+                Html5Parser::parse_fragment_for_context(&document, context, input, None).unwrap();
+            assert_eq!(fragment.host(), context);
+            fragment
+                .children()
+                .into_iter()
+                .map(|id| {
+                    let node = fragment.get_node_by_id(id).unwrap();
+                    match &node.data {
+                        NodeData::Text(text) => text.value().to_string(),
+                        _ => node.name.clone(),
+                    }
+                })
+                .collect::<Vec<_>>()
+        };
+
+        // The context decides on the insertion mode and the tokenizer state
+        assert_eq!(
+            fragment_names(named("row"), "<td>a</td><td>b"),
+            ["td", "td"]
+        );
+        assert_eq!(fragment_names(named("d"), "<td>a</td><i>b</i>"), ["a", "i"]);
+        assert_eq!(fragment_names(named("ta"), "<b>x</b>"), ["<b>x</b>"]);
+        assert_eq!(fragment_names(named("d"), "<p>one<p>two"), ["p", "p"]);
+
+        // The document itself is not changed
+        assert_eq!(document.serialize(), html);
+
+        // The context must be an element
+        assert!(
+            Html5Parser::parse_fragment_for_context(&document, NodeId::root(), "", None).is_err()
+        );
+        assert!(
+            Html5Parser::parse_fragment_for_context(&document, NodeId::from(999), "", None)
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn template_content() {
+        let mut chars = CharIterator::new();
+        chars.read_from_str(
+            "<template id=t><td>cell</td>text</template><p id=p></p>",
+            Some(Encoding::UTF8),
+        );
+        let document = DocumentBuilder::new_document();
+        let _ = Html5Parser::parse_document(&mut chars, Document::clone(&document), None);
+
+        let doc = document.get();
+        let template = doc.get_node_by_named_id("t").unwrap().id;
+        let content = doc.template_content(template).unwrap();
+        assert_eq!(content.host(), template);
+        let children = content.children();
+        assert_eq!(children.len(), 2);
+        assert_eq!(doc.get_node_by_id(children[0]).unwrap().name, "td");
+
+        let p = doc.get_node_by_named_id("p").unwrap().id;
+        assert!(doc.template_content(p).is_none());
+        assert!(doc.template_content(NodeId::root()).is_none());
+    }
+
+    fn parse_body(html: &str) -> String {
+        let mut chars = CharIterator::new();
+        chars.read_from_str(html, Some(Encoding::UTF8));
+        let document = DocumentBuilder::new_document();
+        let _ = Html5Parser::parse_document(&mut chars, Document::clone(&document), None);
+
+        let doc = document.get();
+        let body = doc.elements_by_tag_name("body")[0];
+        serializer::serialize(&doc, body, &serializer::SerializerOptions::default())
+    }
+
+    #[test]
+    fn adoption_agency() {
+        assert_eq!(parse_body("<b><i></b></i>"), "<b><i></i></b>");
+        assert_eq!(
+            parse_body("<p><b><i>a</b>b</i>c"),
+            "<p><b><i>a</i></b><i>b</i>c</p>"
+        );
+        // The furthest block is moved out of the formatting element, and gets a clone of it
+        assert_eq!(parse_body("<a>1<p>2</a>3</p>"), "<a>1</a><p><a>2</a>3</p>");
+        // Noah's Ark: at most three equal elements are reopened
+        assert_eq!(
+            parse_body("<p><b><b><b><b>x</p>y"),
+            "<p><b><b><b><b>x</b></b></b></b></p><b><b><b>y</b></b></b>"
+        );
+    }
+
+    #[test]
+    fn formatting_markers() {
+        // Formatting elements before a marker are not reopened in a table cell, and end tags in
+        // the cell do not close them
+        assert_eq!(
+            parse_body("<b>1<table><tr><td>2</b>3</td></tr></table>4"),
+            "<b>1<table><tbody><tr><td>23</td></tr></tbody></table>4</b>"
+        );
+        assert_eq!(
+            parse_body("<i>1<table><tr><td><i>2</td></tr></table>3"),
+            "<i>1<table><tbody><tr><td><i>2</i></td></tr></tbody></table>3</i>"
+        );
+    }
+
+    #[test]
+    fn foreign_content() {
+        let mut chars = CharIterator::new();
+        chars.read_from_str(
+            r##"<svg viewbox="0 0 1 1"><a xlink:href="#x"><foreignobject><p>a</p></foreignobject></a></svg>
+                <math><mi><b>x</b></mi><mtext></mtext></math>"##,
+            Some(Encoding::UTF8),
+        );
+        let document = DocumentBuilder::new_document();
+        let _ = Html5Parser::parse_document(&mut chars, Document::clone(&document), None);
+
+        let doc = document.get();
+        let element = |name: &str| {
+            let node_id = doc
+                .descendants(NodeId::root())
+                .find(|node| node.name == name)
+                .map(|node| node.id)
+                .expect(name);
+            doc.get_node_by_id(node_id).unwrap()
+        };
+        let svg = element("svg");
+        assert!(svg.is_namespace(SVG_NAMESPACE));
+        assert!(svg.get_attribute("viewBox").is_some());
+        let link = element("a");
+        assert!(link.is_namespace(SVG_NAMESPACE));
+        assert_eq!(
+            link.get_attribute("xlink:href").map(String::as_str),
+            Some("#x")
+        );
+        assert_eq!(
+            link.attribute_namespace("xlink:href"),
+            Some(XLINK_NAMESPACE)
+        );
+        assert!(element("foreignObject").is_namespace(SVG_NAMESPACE));
+        assert!(element("p").is_namespace(HTML_NAMESPACE));
+        assert!(element("mi").is_namespace(MATHML_NAMESPACE));
+        assert!(element("b").is_namespace(HTML_NAMESPACE));
+
+        // HTML tags break out of foreign content, and end tags in text integration points do not
+        // leave it
+        assert_eq!(
+            parse_body("<svg><g><p>a</p></g></svg>"),
+            "<svg><g></g></svg><p>a</p>"
+        );
+        assert_eq!(
+            parse_body("<math><mi>a</mi><mo>b</mo></math>c"),
+            "<math><mi>a</mi><mo>b</mo></math>c"
+        );
+        assert_eq!(
+            parse_body(r##"<svg><a xlink:href="#x"></a></svg>"##),
+            r##"<svg><a xlink:href="#x"></a></svg>"##
+        );
+    }
 }
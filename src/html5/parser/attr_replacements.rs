@@ -102,17 +102,3 @@ pub static SVG_ADJUSTMENTS_ATTRIBUTES: phf::Map<&str, &str> = phf::phf_map! {
     "ychannelselector" => "yChannelSelector",
     "zoomandpan" => "zoomAndPan",
 };
-
-pub static XML_ADJUSTMENTS: phf::Map<&str, (&str, &str, &str)> = phf::phf_map! {
-    "xlink:actuate" => ("xlink", "actuate", "http://www.w3.org/1999/xlink"),
-    "xlink:arcrole" => ("xlink", "arcrole", "http://www.w3.org/1999/xlink"),
-    "xlink:href" => ("xlink", "href", "http://www.w3.org/1999/xlink"),
-    "xlink:role" => ("xlink", "role", "http://www.w3.org/1999/xlink"),
-    "xlink:show" => ("xlink", "show", "http://www.w3.org/1999/xlink"),
-    "xlink:title" => ("xlink", "title", "http://www.w3.org/1999/xlink"),
-    "xlink:type" => ("xlink", "type", "http://www.w3.org/1999/xlink"),
-    "xml:lang" => ("xml", "lang", "http://www.w3.org/XML/1998/namespace"),
-    "xml:space" => ("xml", "space", "http://www.w3.org/XML/1998/namespace"),
-    "xmlns" => ("xmlns", "", "http://www.w3.org/2000/xmlns/"),
-    "xmlns:xlink" => ("xmlns", "xlink", "http://www.w3.org/2000/xmlns/"),
-};
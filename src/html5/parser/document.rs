@@ -1,20 +1,41 @@
+use crate::html5::animation_frame::AnimationFrames;
+use crate::html5::atoms::{Atom, AtomTable};
+use crate::html5::clipboard::ClipboardSlot;
+use crate::html5::dialog::Dialogs;
+use crate::html5::editing::Range;
 use crate::html5::element_class::ElementClass;
+use crate::html5::events::{inline_handler_event_type, EventListeners};
+use crate::html5::form_controls::FormControls;
+use crate::html5::geometry::GeometrySlot;
+use crate::html5::history::{History, Journal, Operation};
+use crate::html5::index::DocumentIndexes;
+use crate::html5::intersection::IntersectionObservers;
+use crate::html5::lifecycle::Lifecycle;
+use crate::html5::mutation::MutationObservers;
 use crate::html5::node::arena::NodeArena;
 use crate::html5::node::data::doctype::DocTypeData;
 use crate::html5::node::data::{comment::CommentData, text::TextData};
 use crate::html5::node::HTML_NAMESPACE;
-use crate::html5::node::{Node, NodeData, NodeId};
+use crate::html5::node::{Node, NodeData, NodeId, NodeTrait, NodeType};
 use crate::html5::parser::quirks::QuirksMode;
 use crate::html5::parser::tree_builder::TreeBuilder;
+use crate::html5::pointer::Pointers;
+use crate::html5::query_cache::QueryCache;
+use crate::html5::resize::ResizeObservers;
 use crate::html5::util::is_valid_id_attribute_value;
-use crate::types::{Error, Result};
+use crate::net::cookies::CookieJar;
+use crate::net::csp::{ContentSecurityPolicy, DiagnosticsSlot};
+use crate::net::origin::Origin;
+use crate::net::referrer::ReferrerPolicy;
+use crate::types::{Error, HierarchyError, ParseError, Result};
 use alloc::rc::Rc;
 use core::fmt;
 use core::fmt::Debug;
 use std::cell::RefCell;
-use std::collections::HashMap;
+use std::collections::{BTreeSet, HashMap};
 use std::fmt::Display;
 use std::ops::{Deref, DerefMut};
+use url::Url;
 
 /// Type of the given document
 #[derive(PartialEq, Debug, Copy, Clone)]
@@ -29,11 +50,11 @@ pub enum DocumentType {
 #[derive(PartialEq)]
 pub struct DocumentFragment {
     /// Node elements inside this fragment
-    arena: NodeArena,
+    pub(crate) arena: NodeArena,
     /// Document handle of the parent
     pub doc: DocumentHandle,
     /// Host node on which this fragment is attached
-    host: NodeId,
+    pub(crate) host: NodeId,
 }
 
 impl Clone for DocumentFragment {
@@ -62,6 +83,37 @@ impl DocumentFragment {
             host,
         }
     }
+
+    /// Creates a document fragment with the nodes in the arena. The top-level nodes of the
+    /// fragment are the children of the (html) root node of the arena.
+    pub(crate) fn with_arena(doc: DocumentHandle, host: NodeId, arena: NodeArena) -> Self {
+        Self { arena, doc, host }
+    }
+
+    /// Returns the node on which the fragment is attached
+    pub fn host(&self) -> NodeId {
+        self.host
+    }
+
+    /// Returns the node with the ID in the fragment. The nodes of template contents are stored
+    /// in the document instead.
+    pub fn get_node_by_id(&self, node_id: NodeId) -> Option<&Node> {
+        self.arena.get_node(node_id)
+    }
+
+    /// Returns the top-level nodes of the fragment. For template contents these are the children
+    /// of the template element in the document.
+    pub fn children(&self) -> Vec<NodeId> {
+        match self.arena.get_node(NodeId::root()) {
+            Some(root) => root.children.clone(),
+            None => self
+                .doc
+                .get()
+                .get_node_by_id(self.host)
+                .map(|host| host.children.clone())
+                .unwrap_or_default(),
+        }
+    }
 }
 
 /// Enum of tasks that can be performed to add or update
@@ -80,6 +132,7 @@ pub enum DocumentTask {
     CreateText {
         content: String,
         parent_id: NodeId,
+        position: Option<usize>,
     },
     CreateComment {
         content: String,
@@ -90,6 +143,65 @@ pub enum DocumentTask {
         value: String,
         element_id: NodeId,
     },
+    SetText {
+        node_id: NodeId,
+        content: String,
+    },
+    MoveNode {
+        node_id: NodeId,
+        parent_id: NodeId,
+        position: Option<usize>,
+    },
+    /// Removes the node (and its descendants) from the tree and frees them (see
+    /// `Document::remove_subtree()`). The node must be in the tree.
+    RemoveNode {
+        node_id: NodeId,
+    },
+    /// Separates the node from its parent, if it has one
+    DetachFromParent {
+        node_id: NodeId,
+    },
+    /// Adds text to the end of an existing text node (used to coalesce character tokens)
+    AppendToExistingText {
+        node_id: NodeId,
+        content: String,
+    },
+    RemoveAttribute {
+        key: String,
+        element_id: NodeId,
+    },
+    /// Moves the node into the parent of the sibling, right before the sibling
+    InsertBefore {
+        node_id: NodeId,
+        sibling_id: NodeId,
+    },
+    /// Moves the node before the table, or to the end of the fallback parent when the table has
+    /// no parent (see `TreeBuilder::foster_parent()`)
+    FosterParent {
+        node_id: NodeId,
+        table_id: NodeId,
+        fallback_parent_id: NodeId,
+    },
+    /// Adds a shallow copy of the node (see `Document::clone_node()`) to the parent
+    CloneNode {
+        node_id: NodeId,
+        parent_id: NodeId,
+        position: Option<usize>,
+    },
+}
+
+/// A change to the nodes of a document. Every mutation of the document is recorded, so derived
+/// data (like the indexes) can be kept up to date.
+#[derive(Debug, Clone, PartialEq)]
+pub enum MutationRecord {
+    /// A node has been registered in the document (but not yet attached to a parent)
+    NodeCreated(NodeId),
+    /// A child has been added to or removed from the parent
+    ChildList { parent_id: NodeId, node_id: NodeId },
+    /// An attribute of an element has been set
+    Attribute { node_id: NodeId, name: String },
+    /// The text of a text or comment node has been changed
+    CharacterData(NodeId),
 }
 
 /// Queue of tasks that will mutate the document to add/update
@@ -99,6 +211,10 @@ pub enum DocumentTask {
 /// Once tasks are queued up, a call to flush() will commit all changes
 /// to the DOM. If there are errors during the application of these changes,
 /// flush() will return a list of the errors encountered but execution is not halted.
+/// flush_transactional() stops at the first error instead, and rolls back all changes of the
+/// flush.
+/// After a flush, the mutation records of the flushed tasks are delivered to the mutation
+/// observers of the document.
 ///
 /// create_element() will generate and return a new NodeId for the parser to keep
 /// track of the current context node and optionally store this in a list of open elements.
@@ -125,40 +241,281 @@ impl DocumentTaskQueue {
         self.tasks.is_empty()
     }
 
-    fn flush(&mut self) -> Vec<String> {
+    /// Performs all queued tasks and returns the errors encountered
+    pub fn flush(&mut self) -> Vec<String> {
         let mut errors = Vec::new();
+        self.document.get_mut().begin_transaction();
         for current_task in &self.tasks {
-            match current_task {
-                DocumentTask::CreateElement {
-                    name,
-                    parent_id,
-                    position,
-                    namespace,
-                } => {
-                    self.document
-                        .create_element(name, *parent_id, *position, namespace);
-                }
-                DocumentTask::CreateText { content, parent_id } => {
-                    self.document.create_text(content, *parent_id);
-                }
-                DocumentTask::CreateComment { content, parent_id } => {
-                    self.document.create_comment(content, *parent_id);
-                }
-                DocumentTask::InsertAttribute {
-                    key,
-                    value,
-                    element_id,
-                } => {
-                    if let Err(err) = self.document.insert_attribute(key, value, *element_id) {
-                        errors.push(err.to_string());
-                    }
-                }
+            if let Err(err) = Self::perform_task(&mut self.document, current_task) {
+                errors.push(err.to_string());
             }
         }
         self.tasks.clear();
+        self.document.get_mut().end_transaction();
+        self.document.deliver_mutation_records();
 
         errors
     }
+
+    /// Performs all queued tasks as a single transaction: when a task fails, the tasks that have
+    /// already been performed are rolled back and the remaining tasks are not performed, so the
+    /// document is left as it was before the flush. The queue is cleared in either case.
+    ///
+    /// Nodes created by the rolled back tasks stay registered in the arena (detached from the
+    /// tree), so the node ids handed out by the queue remain valid.
+    pub fn flush_transactional(&mut self) -> Result<()> {
+        let mut result = Ok(());
+        self.document.get_mut().begin_transaction();
+        self.document.get_mut().start_journal();
+        for (index, current_task) in self.tasks.iter().enumerate() {
+            if let Err(err) = Self::perform_task(&mut self.document, current_task) {
+                result = Err(Error::DocumentTaskRolledBack {
+                    index,
+                    message: err.to_string(),
+                });
+                break;
+            }
+        }
+
+        if result.is_err() {
+            self.document.get_mut().rollback_journal();
+        } else {
+            self.document.get_mut().discard_journal();
+        }
+        self.tasks.clear();
+        self.document.get_mut().end_transaction();
+        self.document.deliver_mutation_records();
+
+        result
+    }
+
+    fn perform_task(document: &mut DocumentHandle, task: &DocumentTask) -> Result<()> {
+        match task {
+            DocumentTask::CreateElement {
+                name,
+                parent_id,
+                position,
+                namespace,
+            } => {
+                document.create_element(name, *parent_id, *position, namespace)?;
+            }
+            DocumentTask::CreateText {
+                content,
+                parent_id,
+                position,
+            } => {
+                document.add_node(Node::new_text(content), *parent_id, *position)?;
+            }
+            DocumentTask::CreateComment { content, parent_id } => {
+                document.create_comment(content, *parent_id)?
+            }
+            DocumentTask::InsertAttribute {
+                key,
+                value,
+                element_id,
+            } => document.insert_attribute(key, value, *element_id)?,
+            DocumentTask::SetText { node_id, content } => {
+                document.get_mut().set_text_data(*node_id, content)?
+            }
+            DocumentTask::MoveNode {
+                node_id,
+                parent_id,
+                position,
+            } => Self::move_node_now(document, *node_id, *parent_id, *position)?,
+            DocumentTask::RemoveNode { node_id } => Self::remove_node_now(document, *node_id)?,
+            DocumentTask::DetachFromParent { node_id } => {
+                if document.get().get_node_by_id(*node_id).is_none() {
+                    return Err(Error::DocumentTask(format!(
+                        "Node ID {} not found",
+                        node_id
+                    )));
+                }
+                document.detach_node_from_parent(*node_id);
+            }
+            DocumentTask::AppendToExistingText { node_id, content } => {
+                document.get_mut().append_text_data(*node_id, content)?
+            }
+            DocumentTask::RemoveAttribute { key, element_id } => {
+                document.remove_attribute(key, *element_id)?
+            }
+            DocumentTask::InsertBefore {
+                node_id,
+                sibling_id,
+            } => document.insert_before(*node_id, *sibling_id)?,
+            DocumentTask::FosterParent {
+                node_id,
+                table_id,
+                fallback_parent_id,
+            } => document.foster_parent(*node_id, *table_id, *fallback_parent_id)?,
+            DocumentTask::CloneNode {
+                node_id,
+                parent_id,
+                position,
+            } => {
+                // The copy takes its ID before the parent is checked, so the IDs stay in sync
+                let copy = document.clone_node(*node_id, false)?;
+                if document.get().get_node_by_id(*parent_id).is_none() {
+                    return Err(Error::DocumentTask(format!(
+                        "Node ID {} not found",
+                        parent_id
+                    )));
+                }
+                document.attach_node_to_parent(copy, *parent_id, *position)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn remove_node_now(document: &mut DocumentHandle, node_id: NodeId) -> Result<()> {
+        let parent = document
+            .get()
+            .get_node_by_id(node_id)
+            .map(|node| node.parent);
+        match parent {
+            None => Err(Error::DocumentTask(format!(
+                "Node ID {} not found",
+                node_id
+            ))),
+            Some(None) => Err(Error::DocumentTask(format!(
+                "Cannot remove node {}: node is not in the tree",
+                node_id
+            ))),
+            Some(Some(_)) => document.get_mut().remove_subtree(node_id),
+        }
+    }
+
+    fn move_node_now(
+        document: &mut DocumentHandle,
+        node_id: NodeId,
+        parent_id: NodeId,
+        position: Option<usize>,
+    ) -> Result<()> {
+        {
+            let doc = document.get();
+            if doc.get_node_by_id(node_id).is_none() || doc.get_node_by_id(parent_id).is_none() {
+                return Err(Error::DocumentTask(format!(
+                    "Cannot move node {} to {}: node not found",
+                    node_id, parent_id
+                )));
+            }
+            if parent_id == node_id || doc.has_cyclic_reference(node_id, parent_id) {
+                return Err(Error::DocumentTask(format!(
+                    "Cannot move node {} into its own descendant {}",
+                    node_id, parent_id
+                )));
+            }
+        }
+
+        document
+            .get()
+            .check_insertion(node_id, parent_id, position)?;
+
+        document.detach_node_from_parent(node_id);
+        document.attach_node_to_parent(node_id, parent_id, position)
+    }
+
+    /// Queues a new text node at the given position in the children of the parent (or at the
+    /// end when no position is given) and returns the ID it will get
+    pub fn insert_text_node(
+        &mut self,
+        content: &str,
+        parent_id: NodeId,
+        position: Option<usize>,
+    ) -> NodeId {
+        self.tasks.push(DocumentTask::CreateText {
+            content: content.to_owned(),
+            parent_id,
+            position,
+        });
+        self.generate_node_id()
+    }
+
+    /// Queues replacing the contents of a text node
+    pub fn set_text(&mut self, node_id: NodeId, content: &str) {
+        self.tasks.push(DocumentTask::SetText {
+            node_id,
+            content: content.to_owned(),
+        });
+    }
+
+    /// Queues moving a node to the given position in the children of the parent (or to the end
+    /// when no position is given)
+    pub fn move_node(&mut self, node_id: NodeId, parent_id: NodeId, position: Option<usize>) {
+        self.tasks.push(DocumentTask::MoveNode {
+            node_id,
+            parent_id,
+            position,
+        });
+    }
+
+    /// Queues removing a node (and its descendants) from the tree
+    pub fn remove_node(&mut self, node_id: NodeId) {
+        self.tasks.push(DocumentTask::RemoveNode { node_id });
+    }
+
+    /// Queues separating a node from its parent. Unlike `remove_node()`, this is not an error
+    /// when the node has no parent.
+    pub fn detach_from_parent(&mut self, node_id: NodeId) {
+        self.tasks.push(DocumentTask::DetachFromParent { node_id });
+    }
+
+    /// Queues adding text to the end of an existing text node
+    pub fn append_to_existing_text(&mut self, node_id: NodeId, content: &str) {
+        self.tasks.push(DocumentTask::AppendToExistingText {
+            node_id,
+            content: content.to_owned(),
+        });
+    }
+
+    /// Queues removing an attribute from an element
+    pub fn remove_attribute(&mut self, key: &str, element_id: NodeId) {
+        self.tasks.push(DocumentTask::RemoveAttribute {
+            key: key.to_owned(),
+            element_id,
+        });
+    }
+
+    /// Queues a shallow copy of the node (without its children) at the given position in the
+    /// children of the parent (or at the end when no position is given) and returns the ID it
+    /// will get. The node must be in the document or be created by a task in the queue.
+    pub fn clone_node(
+        &mut self,
+        node_id: NodeId,
+        parent_id: NodeId,
+        position: Option<usize>,
+    ) -> Result<NodeId> {
+        let known = {
+            let doc = self.document.get();
+            doc.get_node_by_id(node_id).is_some()
+                || (node_id >= doc.arena.peek_next_id() && node_id < self.next_node_id)
+        };
+        if !known {
+            return Err(Error::DocumentTask(format!(
+                "Node ID {} not found",
+                node_id
+            )));
+        }
+        if node_id == NodeId::root() {
+            return Err(Error::DocumentTask(
+                "Cannot clone the document node".to_string(),
+            ));
+        }
+
+        self.tasks.push(DocumentTask::CloneNode {
+            node_id,
+            parent_id,
+            position,
+        });
+        Ok(self.generate_node_id())
+    }
+
+    /// Returns the ID the next registered node will get. Every created node takes an ID from
+    /// the arena, so this must be called for all of them to keep the IDs in sync.
+    fn generate_node_id(&mut self) -> NodeId {
+        let new_id = self.next_node_id;
+        self.next_node_id = self.next_node_id.next();
+        new_id
+    }
 }
 
 // See tree_builder.rs for method comments
@@ -169,34 +526,31 @@ impl TreeBuilder for DocumentTaskQueue {
         parent_id: NodeId,
         position: Option<usize>,
         namespace: &str,
-    ) -> NodeId {
+    ) -> Result<NodeId> {
         let element = DocumentTask::CreateElement {
             name: name.to_owned(),
             parent_id,
             position,
             namespace: namespace.to_owned(),
         };
-        let new_id = self.next_node_id;
-        self.next_node_id = self.next_node_id.next();
         self.tasks.push(element);
 
-        new_id
+        Ok(self.generate_node_id())
     }
 
-    fn create_text(&mut self, content: &str, parent_id: NodeId) {
-        let text = DocumentTask::CreateText {
-            content: content.to_owned(),
-            parent_id,
-        };
-        self.tasks.push(text);
+    fn create_text(&mut self, content: &str, parent_id: NodeId) -> Result<()> {
+        self.insert_text_node(content, parent_id, None);
+        Ok(())
     }
 
-    fn create_comment(&mut self, content: &str, parent_id: NodeId) {
+    fn create_comment(&mut self, content: &str, parent_id: NodeId) -> Result<()> {
         let comment = DocumentTask::CreateComment {
             content: content.to_owned(),
             parent_id,
         };
         self.tasks.push(comment);
+        self.generate_node_id();
+        Ok(())
     }
 
     fn insert_attribute(&mut self, key: &str, value: &str, element_id: NodeId) -> Result<()> {
@@ -208,6 +562,28 @@ impl TreeBuilder for DocumentTaskQueue {
         self.tasks.push(attribute);
         Ok(())
     }
+
+    fn insert_before(&mut self, node_id: NodeId, sibling_id: NodeId) -> Result<()> {
+        self.tasks.push(DocumentTask::InsertBefore {
+            node_id,
+            sibling_id,
+        });
+        Ok(())
+    }
+
+    fn foster_parent(
+        &mut self,
+        node_id: NodeId,
+        table_id: NodeId,
+        fallback_parent_id: NodeId,
+    ) -> Result<()> {
+        self.tasks.push(DocumentTask::FosterParent {
+            node_id,
+            table_id,
+            fallback_parent_id,
+        });
+        Ok(())
+    }
 }
 
 impl DocumentTaskQueue {
@@ -228,11 +604,79 @@ pub struct Document {
     /// Holds and owns all nodes in the document
     pub(crate) arena: NodeArena,
     /// HTML elements with ID (e.g., <div id="myid">)
-    named_id_elements: HashMap<String, NodeId>,
+    pub(crate) named_id_elements: HashMap<String, NodeId>,
+    /// Connected elements per class name (e.g., <div class="myclass">)
+    pub(crate) class_elements: HashMap<String, BTreeSet<NodeId>>,
+    /// Connected HTML elements per name attribute (e.g., <input name="email">)
+    pub(crate) name_elements: HashMap<String, BTreeSet<NodeId>>,
     /// Document type of this document
     pub doctype: DocumentType,
     /// Quirks mode of this document
     pub quirks_mode: QuirksMode,
+    /// The root holds a fragment (like the contents of a range) instead of a document, so its
+    /// children are not limited to a single doctype and a single element
+    pub(crate) holds_fragment: bool,
+    /// Address of the document (if known)
+    pub url: Option<Url>,
+    /// Origin of the document, which is opaque until it is set for the URL of the document
+    pub(crate) origin: Origin,
+    /// Content security policies of the document
+    pub(crate) csp: ContentSecurityPolicy,
+    /// Receives violations of the content security policies, if the embedder provided a sink
+    pub(crate) diagnostics: DiagnosticsSlot,
+    /// Referrer policy for the requests made by the document
+    pub(crate) referrer_policy: ReferrerPolicy,
+    /// The first `<base>` element with an href attribute found while parsing. Later ones are
+    /// ignored.
+    pub(crate) base_element: Option<NodeId>,
+    /// Base URL as frozen by the first base element with a valid href attribute
+    pub(crate) frozen_base_url: Option<Url>,
+    /// Default target for hyperlinks as set by the first base element with a target attribute
+    pub base_target: Option<String>,
+    /// Event listeners registered on the nodes of this document
+    pub(crate) event_listeners: EventListeners,
+    /// Cookie jar used for document.cookie
+    pub(crate) cookie_jar: Option<Rc<RefCell<CookieJar>>>,
+    /// Reverse indexes of tag and attribute names (when enabled)
+    pub(crate) indexes: Option<DocumentIndexes>,
+    /// Cached results of selector queries
+    pub(crate) query_cache: RefCell<QueryCache>,
+    /// Undo and redo stacks (when enabled)
+    pub(crate) history: Option<History>,
+    /// Mutations to roll back when a transactional flush fails
+    pub(crate) journal: Option<Journal>,
+    /// Readiness of the document
+    pub(crate) lifecycle: Lifecycle,
+    /// Interned node names
+    pub(crate) atoms: AtomTable,
+    /// Atom of the name of every node
+    pub(crate) node_atoms: HashMap<NodeId, Atom>,
+    /// Top layer and return values of dialogs
+    pub(crate) dialogs: Dialogs,
+    /// Current state of the form controls
+    pub(crate) form_controls: FormControls,
+    /// Element that has the focus
+    pub(crate) focused: Option<NodeId>,
+    /// Selected range of the document
+    pub(crate) selection: Option<Range>,
+    /// Clipboard of the system, as provided by the embedder
+    pub(crate) clipboard: ClipboardSlot,
+    /// Pointers of the input devices, and the hit tester to find their targets
+    pub(crate) pointers: Pointers,
+    /// Layout of the document, as provided by the embedder
+    pub(crate) geometry: GeometrySlot,
+    /// How far the scroll containers have scrolled their contents (see `scroll`)
+    pub(crate) scroll_offsets: HashMap<NodeId, (f32, f32)>,
+    /// Intersection observers of the document
+    pub(crate) intersection_observers: IntersectionObservers,
+    /// Resize observers of the document
+    pub(crate) resize_observers: ResizeObservers,
+    /// Mutation observers of the document
+    pub(crate) mutation_observers: MutationObservers,
+    /// Callbacks waiting for the next animation frame
+    pub(crate) animation_frames: AnimationFrames,
+    /// Errors found while parsing the document
+    pub(crate) parse_errors: Vec<ParseError>,
 }
 
 impl Default for Document {
@@ -241,8 +685,41 @@ impl Default for Document {
         Self {
             arena: NodeArena::new(),
             named_id_elements: HashMap::new(),
+            class_elements: HashMap::new(),
+            name_elements: HashMap::new(),
             doctype: DocumentType::HTML,
             quirks_mode: QuirksMode::NoQuirks,
+            holds_fragment: false,
+            url: None,
+            origin: Origin::new_opaque(),
+            csp: ContentSecurityPolicy::default(),
+            diagnostics: DiagnosticsSlot::default(),
+            referrer_policy: ReferrerPolicy::default(),
+            base_element: None,
+            frozen_base_url: None,
+            base_target: None,
+            event_listeners: EventListeners::default(),
+            cookie_jar: None,
+            indexes: None,
+            query_cache: RefCell::default(),
+            history: None,
+            journal: None,
+            lifecycle: Lifecycle::default(),
+            atoms: AtomTable::default(),
+            node_atoms: HashMap::new(),
+            dialogs: Dialogs::default(),
+            form_controls: FormControls::default(),
+            focused: None,
+            selection: None,
+            clipboard: ClipboardSlot::default(),
+            pointers: Pointers::default(),
+            geometry: GeometrySlot::default(),
+            scroll_offsets: HashMap::new(),
+            intersection_observers: IntersectionObservers::default(),
+            resize_observers: ResizeObservers::default(),
+            mutation_observers: MutationObservers::default(),
+            animation_frames: AnimationFrames::default(),
+            parse_errors: Vec::new(),
         }
     }
 }
@@ -254,9 +731,91 @@ impl Document {
         Self {
             arena,
             named_id_elements: HashMap::new(),
+            class_elements: HashMap::new(),
+            name_elements: HashMap::new(),
             doctype: DocumentType::HTML,
             quirks_mode: QuirksMode::NoQuirks,
+            holds_fragment: false,
+            url: None,
+            origin: Origin::new_opaque(),
+            csp: ContentSecurityPolicy::default(),
+            diagnostics: DiagnosticsSlot::default(),
+            referrer_policy: ReferrerPolicy::default(),
+            base_element: None,
+            frozen_base_url: None,
+            base_target: None,
+            event_listeners: EventListeners::default(),
+            cookie_jar: None,
+            indexes: None,
+            query_cache: RefCell::default(),
+            history: None,
+            journal: None,
+            lifecycle: Lifecycle::default(),
+            atoms: AtomTable::default(),
+            node_atoms: HashMap::new(),
+            dialogs: Dialogs::default(),
+            form_controls: FormControls::default(),
+            focused: None,
+            selection: None,
+            clipboard: ClipboardSlot::default(),
+            pointers: Pointers::default(),
+            geometry: GeometrySlot::default(),
+            scroll_offsets: HashMap::new(),
+            intersection_observers: IntersectionObservers::default(),
+            resize_observers: ResizeObservers::default(),
+            mutation_observers: MutationObservers::default(),
+            animation_frames: AnimationFrames::default(),
+            parse_errors: Vec::new(),
+        }
+    }
+
+    /// Removes all nodes and everything that was derived from them, so the document can be parsed
+    /// again from the start. What the embedder set up before parsing (like the URL, origin,
+    /// security policies, cookie jar, enabled indexes, the listeners on the document, the slots
+    /// and the observers) is kept.
+    pub(crate) fn clear_for_reparse(&mut self) {
+        let node_ids: Vec<NodeId> = self
+            .arena
+            .nodes()
+            .map(|node| node.id)
+            .filter(|node_id| !node_id.is_root())
+            .collect();
+        for node_id in node_ids {
+            self.forget_node(node_id);
         }
+        // The records refer to nodes whose IDs are given to the nodes of the new tree
+        self.mutation_observers.clear_records();
+
+        self.arena = NodeArena::new();
+        self.named_id_elements.clear();
+        self.class_elements.clear();
+        self.name_elements.clear();
+        self.doctype = DocumentType::HTML;
+        self.quirks_mode = QuirksMode::NoQuirks;
+        self.base_element = None;
+        self.frozen_base_url = None;
+        self.base_target = None;
+        if let Some(indexes) = &mut self.indexes {
+            indexes.clear();
+        }
+        self.clear_query_cache();
+        if self.history.is_some() {
+            self.history = Some(History::default());
+        }
+        self.journal = None;
+        self.node_atoms.clear();
+        self.parse_errors.clear();
+
+        let node_id = self.arena.register_node(Node::new_document());
+        self.intern_node_name(node_id);
+    }
+
+    /// Returns the errors found while parsing the document (or the last fragment parsed into
+    /// it), in the order they were found. Each error has the code of the spec (like
+    /// `duplicate-attribute`) or a description of the tree construction error as its message,
+    /// and the line, column and offset of the input where it was found.
+    pub fn parse_errors(&self) -> &[ParseError] {
+        &self.parse_errors
     }
 
     /// Returns a shared reference-counted handle for the document
@@ -279,6 +838,16 @@ impl Document {
         self.arena.get_node(node_id)
     }
 
+    /// Returns the contents of the template element, or None when the node is not a template
+    /// element. The parser inserts the contents into the fragment, which stores them as the
+    /// children of the template element.
+    pub fn template_content(&self, node_id: NodeId) -> Option<&DocumentFragment> {
+        match &self.get_node_by_id(node_id)?.data {
+            NodeData::Element(element) => element.template_contents.as_ref(),
+            _ => None,
+        }
+    }
+
     /// Fetches a mutable node by id or returns None when no node with this ID is found
     pub fn get_node_by_id_mut(&mut self, node_id: NodeId) -> Option<&mut Node> {
         self.arena.get_node_mut(node_id)
@@ -296,15 +865,275 @@ impl Document {
         self.arena.get_node_mut(*node_id)
     }
 
+    /// Returns the connected elements that have all of the given (space-separated) class names,
+    /// like `getElementsByClassName()`. Elements are returned in order of creation, which is tree
+    /// order unless nodes have been moved.
+    pub fn get_nodes_by_class_name(&self, class_names: &str) -> Vec<NodeId> {
+        let names: Vec<&str> = class_names.split_ascii_whitespace().collect();
+        let Some(elements) = names
+            .first()
+            .and_then(|name| self.class_elements.get(*name))
+        else {
+            return Vec::new();
+        };
+
+        elements
+            .iter()
+            .copied()
+            .filter(
+                |&node_id| match self.get_node_by_id(node_id).map(|node| &node.data) {
+                    Some(NodeData::Element(element)) => {
+                        names.iter().all(|name| element.classes.is_active(name))
+                    }
+                    _ => false,
+                },
+            )
+            .collect()
+    }
+
+    /// Adds the classes of the node and all its descendants to the class lookup, when the node is
+    /// connected
+    pub(crate) fn index_classes(&mut self, node_id: NodeId) {
+        let classes = self.class_entries(node_id, true);
+        self.add_class_entries(node_id, classes);
+    }
+
+    /// Removes the classes of the node and all its descendants from the class lookup
+    pub(crate) fn unindex_classes(&mut self, node_id: NodeId) {
+        let classes = self.class_entries(node_id, true);
+        self.remove_class_entries(classes);
+    }
+
+    /// Adds the classes of the element itself to the class lookup, when it is connected
+    pub(crate) fn index_element_classes(&mut self, node_id: NodeId) {
+        let classes = self.class_entries(node_id, false);
+        self.add_class_entries(node_id, classes);
+    }
+
+    /// Removes the classes of the element itself from the class lookup
+    pub(crate) fn unindex_element_classes(&mut self, node_id: NodeId) {
+        let classes = self.class_entries(node_id, false);
+        self.remove_class_entries(classes);
+    }
+
+    /// Returns the class names of the node, and of all its descendants when `subtree` is set
+    fn class_entries(&self, node_id: NodeId, subtree: bool) -> Vec<(String, NodeId)> {
+        let mut classes = Vec::new();
+        let mut stack = vec![node_id];
+        while let Some(node_id) = stack.pop() {
+            let Some(node) = self.get_node_by_id(node_id) else {
+                continue;
+            };
+            if let NodeData::Element(element) = &node.data {
+                classes.extend(
+                    element
+                        .classes
+                        .iter()
+                        .map(|(name, _)| (name.to_string(), node_id)),
+                );
+            }
+            if subtree {
+                stack.extend(&node.children);
+            }
+        }
+        classes
+    }
+
+    fn add_class_entries(&mut self, node_id: NodeId, classes: Vec<(String, NodeId)>) {
+        if classes.is_empty() || !self.is_connected(node_id) {
+            return;
+        }
+        add_lookup_entries(&mut self.class_elements, classes);
+    }
+
+    fn remove_class_entries(&mut self, classes: Vec<(String, NodeId)>) {
+        remove_lookup_entries(&mut self.class_elements, classes);
+    }
+
+    /// Returns the connected HTML elements with the given name attribute, like
+    /// `getElementsByName()`. Elements are returned in order of creation, which is tree order
+    /// unless nodes have been moved.
+    pub fn get_elements_by_name(&self, name: &str) -> Vec<NodeId> {
+        self.name_elements
+            .get(name)
+            .map(|elements| elements.iter().copied().collect())
+            .unwrap_or_default()
+    }
+
+    /// Adds the name attributes of the node and all its descendants to the name lookup, when the
+    /// node is connected
+    pub(crate) fn index_names(&mut self, node_id: NodeId) {
+        let names = self.name_entries(node_id, true);
+        if !names.is_empty() && self.is_connected(node_id) {
+            add_lookup_entries(&mut self.name_elements, names);
+        }
+    }
+
+    /// Removes the name attributes of the node and all its descendants from the name lookup
+    pub(crate) fn unindex_names(&mut self, node_id: NodeId) {
+        let names = self.name_entries(node_id, true);
+        remove_lookup_entries(&mut self.name_elements, names);
+    }
+
+    /// Adds the name attribute of the element itself to the name lookup, when it is connected
+    pub(crate) fn index_element_name(&mut self, node_id: NodeId) {
+        let names = self.name_entries(node_id, false);
+        if !names.is_empty() && self.is_connected(node_id) {
+            add_lookup_entries(&mut self.name_elements, names);
+        }
+    }
+
+    /// Removes the name attribute of the element itself from the name lookup
+    pub(crate) fn unindex_element_name(&mut self, node_id: NodeId) {
+        let names = self.name_entries(node_id, false);
+        remove_lookup_entries(&mut self.name_elements, names);
+    }
+
+    /// Returns the name attributes of the HTML element, and of all its descendants when `subtree`
+    /// is set
+    fn name_entries(&self, node_id: NodeId, subtree: bool) -> Vec<(String, NodeId)> {
+        let mut nodes: Vec<&Node> = self.get_node_by_id(node_id).into_iter().collect();
+        if subtree {
+            nodes.extend(self.descendants(node_id));
+        }
+        nodes
+            .into_iter()
+            .filter(|node| node.namespace.as_deref() == Some(HTML_NAMESPACE))
+            .filter_map(|node| Some((node.get_attribute("name")?.clone(), node.id)))
+            .collect()
+    }
+
+    /// Returns the id attributes of the node and all its descendants, in tree order
+    fn named_id_entries(&self, node_id: NodeId) -> Vec<(String, NodeId)> {
+        self.get_node_by_id(node_id)
+            .into_iter()
+            .chain(self.descendants(node_id))
+            .filter_map(|node| Some((node.get_attribute("id")?.clone(), node.id)))
+            .collect()
+    }
+
+    /// Adds the ids of the node and all its descendants to the id lookup, when the node is
+    /// connected. Ids that are already taken keep referring to their element.
+    pub(crate) fn index_named_ids(&mut self, node_id: NodeId) {
+        if !self.is_connected(node_id) {
+            return;
+        }
+        for (named_id, node_id) in self.named_id_entries(node_id) {
+            self.claim_named_id(&named_id, node_id);
+        }
+    }
+
+    /// Removes the ids of the node and all its descendants from the id lookup. The node must not
+    /// be connected anymore.
+    pub(crate) fn unindex_named_ids(&mut self, node_id: NodeId) {
+        let entries = self.named_id_entries(node_id);
+        self.release_named_ids(entries);
+    }
+
+    /// Adds the id of the element to the id lookup, when the element is connected and the id is
+    /// valid. When other elements have the same id, the first one in tree order is found by it.
+    pub(crate) fn claim_named_id(&mut self, named_id: &str, node_id: NodeId) {
+        if !is_valid_id_attribute_value(named_id) || !self.is_connected(node_id) {
+            return;
+        }
+        let claimed = match self.named_id_elements.get(named_id) {
+            Some(&holder) => holder != node_id && self.precedes(node_id, holder),
+            None => true,
+        };
+        if claimed {
+            self.named_id_elements.insert(named_id.to_string(), node_id);
+        }
+    }
+
+    /// Returns true when the first node comes before the second node in tree order. Both nodes
+    /// must be in the same tree.
+    fn precedes(&self, first: NodeId, second: NodeId) -> bool {
+        let path = |node_id: NodeId| {
+            let mut path: Vec<NodeId> = self.ancestors(node_id).map(|node| node.id).collect();
+            path.reverse();
+            path.push(node_id);
+            path
+        };
+        let (first_path, second_path) = (path(first), path(second));
+        let common = first_path
+            .iter()
+            .zip(&second_path)
+            .take_while(|(a, b)| a == b)
+            .count();
+
+        match (first_path.get(common), second_path.get(common)) {
+            // An ancestor comes before its descendants
+            (None, Some(_)) => true,
+            (Some(&a), Some(&b)) if common > 0 => {
+                self.get_node_by_id(first_path[common - 1])
+                    .and_then(|parent| parent.children.iter().find(|&&id| id == a || id == b))
+                    == Some(&a)
+            }
+            _ => false,
+        }
+    }
+
+    /// Removes the id from the id lookup when it refers to the node. The first other connected
+    /// element (in tree order) that has the id takes over.
+    pub(crate) fn release_named_id(&mut self, named_id: &str, node_id: NodeId) {
+        self.release_named_ids(vec![(named_id.to_string(), node_id)]);
+    }
+
+    fn release_named_ids(&mut self, entries: Vec<(String, NodeId)>) {
+        let mut released = Vec::new();
+        for (named_id, node_id) in entries {
+            if self.named_id_elements.get(&named_id) == Some(&node_id) {
+                self.named_id_elements.remove(&named_id);
+                released.push((named_id, node_id));
+            }
+        }
+        if released.is_empty() {
+            return;
+        }
+
+        // A single walk over the tree finds the elements that take over the released ids
+        let mut next = Vec::new();
+        for node in self.descendants(NodeId::root()) {
+            let Some(named_id) = node.get_attribute("id") else {
+                continue;
+            };
+            if let Some(index) = released
+                .iter()
+                .position(|(released_id, node_id)| released_id == named_id && *node_id != node.id)
+            {
+                next.push((released.swap_remove(index).0, node.id));
+                if released.is_empty() {
+                    break;
+                }
+            }
+        }
+        self.named_id_elements.extend(next);
+    }
+
+    /// Returns the value of the attribute of the element, or None when it is not set or the node
+    /// is not an element
+    pub fn get_attribute(&self, key: &str, element_id: NodeId) -> Option<&str> {
+        self.get_node_by_id(element_id)?
+            .get_attribute(key)
+            .map(String::as_str)
+    }
+
+    /// Returns true when the attribute is set on the element
+    pub fn has_attribute(&self, key: &str, element_id: NodeId) -> bool {
+        self.get_attribute(key, element_id).is_some()
+    }
+
     pub fn add_new_node(&mut self, node: Node) -> NodeId {
-        // if a node contains attributes when adding to the tree,
-        // be sure to handle the special attributes "id" and "class"
-        // which need to by queryable by the DOM
-        let mut node_named_id: Option<String> = None;
+        // The "id" and "class" attributes become queryable once the node is attached to the tree
+        let mut inline_handlers = Vec::new();
         if let NodeData::Element(element) = &node.data {
-            if let Some(named_id) = element.attributes.get("id") {
-                node_named_id = Some(named_id.clone());
-            }
+            inline_handlers = element
+                .attributes
+                .keys()
+                .filter(|key| inline_handler_event_type(key).is_some())
+                .cloned()
+                .collect::<Vec<_>>();
+            inline_handlers.sort();
         }
 
         // Register the node if needed
@@ -321,58 +1150,72 @@ impl Document {
             }
         }
 
-        // make named_id (if present) queryable in DOM if it's not mapped already
-        if let Some(node_named_id) = node_named_id {
-            if !self.named_id_elements.contains_key(&node_named_id)
-                && is_valid_id_attribute_value(&node_named_id)
-            {
-                self.named_id_elements
-                    .insert(node_named_id.to_owned(), node_id);
-            }
+        self.intern_node_name(node_id);
+
+        // inline event handler attributes (onclick etc.) take part in event dispatch
+        for attribute in inline_handlers {
+            self.register_inline_handler(node_id, &attribute);
         }
 
+        self.record_mutation(MutationRecord::NodeCreated(node_id));
+
         node_id
     }
 
     /// Inserts a node to the parent node at the given position in the children (or none
-    /// to add at the end). Will automatically register the node if not done so already
-    pub fn add_node(&mut self, node: Node, parent_id: NodeId, position: Option<usize>) -> NodeId {
+    /// to add at the end). Will automatically register the node if not done so already. Fails
+    /// (without registering the node) when it cannot be a child of the parent, see
+    /// `check_insertion()`.
+    pub fn add_node(
+        &mut self,
+        node: Node,
+        parent_id: NodeId,
+        position: Option<usize>,
+    ) -> Result<NodeId> {
+        if !node.is_registered {
+            self.check_new_child(node.type_of(), parent_id, position)?;
+        }
         let node_id = self.add_new_node(node);
 
-        self.attach_node_to_parent(node_id, parent_id, position);
+        self.attach_node_to_parent(node_id, parent_id, position)?;
 
-        node_id
+        Ok(node_id)
     }
 
-    /// Relocates a node to another parent node
-    pub fn relocate(&mut self, node_id: NodeId, parent_id: NodeId) {
-        let node = self.arena.get_node_mut(node_id).unwrap();
-        if !node.is_registered {
-            panic!("Node is not registered to the arena");
-        }
-
-        if node.parent.is_some() && node.parent.unwrap() == parent_id {
+    /// Relocates a node to another parent node. Returns false (and leaves the node where it is)
+    /// when either node does not exist, or when the node cannot be a child of the parent (see
+    /// `check_insertion()`).
+    pub fn relocate(&mut self, node_id: NodeId, parent_id: NodeId) -> bool {
+        let Some(node) = self.arena.get_node(node_id) else {
+            return false;
+        };
+        if node.parent == Some(parent_id) {
             // Nothing to do when we want to relocate to its own parent
-            return;
+            return true;
+        }
+        if self.check_insertion(node_id, parent_id, None).is_err() {
+            return false;
         }
 
+        self.begin_transaction();
         self.detach_node_from_parent(node_id);
-        self.attach_node_to_parent(node_id, parent_id, None);
+        let _ = self.attach_node_to_parent(node_id, parent_id, None);
+        self.end_transaction();
+        true
     }
 
     /// Adds the node as a child the parent node. If position is given, it will be inserted as a
-    /// child at that given position
+    /// child at that given position. Fails (without changing anything) when the node cannot be a
+    /// child of the parent, see `check_insertion()`.
     pub fn attach_node_to_parent(
         &mut self,
         node_id: NodeId,
         parent_id: NodeId,
         position: Option<usize>,
-    ) -> bool {
-        //check if any children of node have parent as child
-        if parent_id == node_id || self.has_cyclic_reference(node_id, parent_id) {
-            return false;
-        }
+    ) -> Result<()> {
+        self.check_insertion(node_id, parent_id, position)?;
 
+        let mut attached_at = None;
         if let Some(parent_node) = self.get_node_by_id_mut(parent_id) {
             // Make sure position can never be larger than the number of children in the parent
             if let Some(mut position) = position {
@@ -380,89 +1223,496 @@ impl Document {
                     position = parent_node.children.len();
                 }
                 parent_node.children.insert(position, node_id);
+                attached_at = Some(position);
             } else {
                 // No position given, add to end of the children list
                 parent_node.children.push(node_id);
+                attached_at = Some(parent_node.children.len() - 1);
             }
         }
 
-        let node = self.arena.get_node_mut(node_id).unwrap();
-        node.parent = Some(parent_id);
+        if let Some(node) = self.arena.get_node_mut(node_id) {
+            node.parent = Some(parent_id);
+        }
+
+        self.index_classes(node_id);
+        self.index_names(node_id);
+        self.index_named_ids(node_id);
+        self.record_mutation(MutationRecord::ChildList { parent_id, node_id });
+        if let Some(position) = attached_at {
+            self.record_operation(Operation::Attach {
+                node_id,
+                parent_id,
+                position,
+            });
+        }
 
-        true
+        Ok(())
+    }
+
+    /// Checks that the node can be a child of the parent at the position (or at the end), like
+    /// the pre-insertion validity of the DOM: both nodes exist, the parent is not the node itself
+    /// or one of its descendants, and only elements and the document can have children. The
+    /// children of the document are a single doctype in front of a single element, and comments
+    /// (unless the document holds a fragment).
+    ///
+    /// See: https://dom.spec.whatwg.org/#concept-node-ensure-pre-insertion-validity
+    pub fn check_insertion(
+        &self,
+        node_id: NodeId,
+        parent_id: NodeId,
+        position: Option<usize>,
+    ) -> std::result::Result<(), HierarchyError> {
+        self.check_child(node_id, parent_id)?;
+        let kind = self
+            .arena
+            .get_node(node_id)
+            .map_or(NodeType::Text, |node| node.type_of());
+        self.check_document_children(parent_id, &[kind], &[node_id], position)
+    }
+
+    /// Checks that the node can be a child of the parent, without looking at the other children
+    /// of the parent
+    pub(crate) fn check_child(
+        &self,
+        node_id: NodeId,
+        parent_id: NodeId,
+    ) -> std::result::Result<(), HierarchyError> {
+        let (Some(node), Some(parent)) =
+            (self.arena.get_node(node_id), self.arena.get_node(parent_id))
+        else {
+            return Err(HierarchyError::NotFound);
+        };
+        if node.type_of() == NodeType::Document {
+            return Err(HierarchyError::DocumentNode);
+        }
+        //check if any children of node have parent as child
+        if parent_id == node_id || self.has_cyclic_reference(node_id, parent_id) {
+            return Err(HierarchyError::Cycle);
+        }
+
+        check_kinds(parent.type_of(), node.type_of())
+    }
+
+    /// Checks that a new node of the kind can be a child of the parent at the position (or at
+    /// the end)
+    fn check_new_child(
+        &self,
+        kind: NodeType,
+        parent_id: NodeId,
+        position: Option<usize>,
+    ) -> std::result::Result<(), HierarchyError> {
+        let Some(parent) = self.arena.get_node(parent_id) else {
+            return Err(HierarchyError::NotFound);
+        };
+        if kind == NodeType::Document {
+            return Err(HierarchyError::DocumentNode);
+        }
+        check_kinds(parent.type_of(), kind)?;
+        self.check_document_children(parent_id, &[kind], &[], position)
+    }
+
+    /// Checks the children the document would have when nodes of the given kinds are inserted
+    /// at the position (or at the end), after the moved nodes have been taken out of it. Other
+    /// parents (and a document holding a fragment) can have any children.
+    pub(crate) fn check_document_children(
+        &self,
+        parent_id: NodeId,
+        kinds: &[NodeType],
+        moved: &[NodeId],
+        position: Option<usize>,
+    ) -> std::result::Result<(), HierarchyError> {
+        let Some(parent) = self.arena.get_node(parent_id) else {
+            return Err(HierarchyError::NotFound);
+        };
+        if parent.type_of() != NodeType::Document || self.holds_fragment {
+            return Ok(());
+        }
+
+        let mut children: Vec<NodeType> = parent
+            .children
+            .iter()
+            .filter(|child| !moved.contains(child))
+            .filter_map(|&child| self.arena.get_node(child).map(|child| child.type_of()))
+            .collect();
+        let position = position.map_or(children.len(), |position| position.min(children.len()));
+        children.splice(position..position, kinds.iter().copied());
+
+        let mut doctype = false;
+        let mut element = false;
+        for kind in children {
+            match kind {
+                NodeType::Text => return Err(HierarchyError::TextInDocument),
+                NodeType::DocType if doctype => return Err(HierarchyError::SecondDoctype),
+                NodeType::DocType if element => return Err(HierarchyError::DoctypeAfterElement),
+                NodeType::DocType => doctype = true,
+                NodeType::Element if element => return Err(HierarchyError::SecondElement),
+                NodeType::Element => element = true,
+                _ => {}
+            }
+        }
+        Ok(())
     }
 
     /// Separates the given node from its parent node (if any)
     pub fn detach_node_from_parent(&mut self, node_id: NodeId) {
-        let parent = self.get_node_by_id(node_id).expect("node not found").parent;
+        let Some(parent) = self.get_node_by_id(node_id).map(|node| node.parent) else {
+            return;
+        };
 
         if let Some(parent_id) = parent {
-            let parent_node = self
-                .get_node_by_id_mut(parent_id)
-                .expect("parent node not found");
+            if self.get_node_by_id(parent_id).is_none() {
+                return;
+            }
+            self.unindex_classes(node_id);
+            self.unindex_names(node_id);
+
+            let Some(parent_node) = self.get_node_by_id_mut(parent_id) else {
+                return;
+            };
+            let position = parent_node.children.iter().position(|&id| id == node_id);
             parent_node.children.retain(|&id| id != node_id);
 
-            let node = self.get_node_by_id_mut(node_id).expect("node not found");
-            node.parent = None;
+            if let Some(node) = self.get_node_by_id_mut(node_id) {
+                node.parent = None;
+            }
+            self.unindex_named_ids(node_id);
+            self.remove_disconnected_dialogs();
+
+            self.record_mutation(MutationRecord::ChildList { parent_id, node_id });
+            if let Some(position) = position {
+                self.record_operation(Operation::Detach {
+                    node_id,
+                    parent_id,
+                    position,
+                });
+            }
+        }
+    }
+
+    /// Removes the node and all its descendants from the document: the node is detached from its
+    /// parent, and the nodes are removed from the arena, which frees them. Their ids are not
+    /// reused, so stale ids will not find any node.
+    ///
+    /// When the history is enabled (or a transactional flush is running), the nodes are only
+    /// detached: undoing the removal needs them.
+    pub fn remove_subtree(&mut self, node_id: NodeId) -> Result<()> {
+        if self.get_node_by_id(node_id).is_none() {
+            return Err(Error::DocumentTask(format!(
+                "Node ID {} not found",
+                node_id
+            )));
+        }
+        if node_id.is_root() {
+            return Err(Error::DocumentTask(
+                "Cannot remove the root node".to_string(),
+            ));
+        }
+
+        self.detach_node_from_parent(node_id);
+        if self.history.is_some() || self.journal.is_some() {
+            return Ok(());
+        }
+
+        let mut stack = vec![node_id];
+        while let Some(node_id) = stack.pop() {
+            let Some(node) = self.arena.remove_node(node_id) else {
+                continue;
+            };
+            stack.extend(&node.children);
+            self.forget_node(node_id);
+        }
+
+        Ok(())
+    }
+
+    /// Removes the state that the document keeps per node (like its listeners, the state of
+    /// form controls and observations) for a node that has been freed
+    fn forget_node(&mut self, node_id: NodeId) {
+        self.node_atoms.remove(&node_id);
+        self.scroll_offsets.remove(&node_id);
+        if let Some(indexes) = self.indexes.as_mut() {
+            indexes.forget_node(node_id);
+        }
+        if self.focused == Some(node_id) {
+            self.focused = None;
+        }
+        if self
+            .selection
+            .is_some_and(|range| range.start.node_id == node_id || range.end.node_id == node_id)
+        {
+            self.selection = None;
+        }
+        self.event_listeners.forget_node(node_id);
+        self.dialogs.forget_node(node_id);
+        self.form_controls.forget_node(node_id);
+        self.pointers.forget_node(node_id);
+        self.intersection_observers.forget_node(node_id);
+        self.resize_observers.forget_node(node_id);
+        self.mutation_observers.forget_node(node_id);
+    }
+
+    /// Replaces the contents of a text node
+    pub fn set_text_data(&mut self, node_id: NodeId, content: &str) -> Result<()> {
+        match self.get_node_by_id(node_id).map(|node| &node.data) {
+            Some(NodeData::Text(_)) | None => self.set_character_data(node_id, content),
+            Some(_) => Err(Error::DocumentTask(format!(
+                "Node ID {} is not a text node",
+                node_id
+            ))),
+        }
+    }
+
+    /// Replaces the contents of a comment node
+    pub fn set_comment_data(&mut self, node_id: NodeId, content: &str) -> Result<()> {
+        match self.get_node_by_id(node_id).map(|node| &node.data) {
+            Some(NodeData::Comment(_)) | None => self.set_character_data(node_id, content),
+            Some(_) => Err(Error::DocumentTask(format!(
+                "Node ID {} is not a comment node",
+                node_id
+            ))),
         }
     }
 
-    /// returns the root node
+    /// Replaces the contents of a text or comment node
+    pub(crate) fn set_character_data(&mut self, node_id: NodeId, content: &str) -> Result<()> {
+        let Some(node) = self.get_node_by_id_mut(node_id) else {
+            return Err(Error::DocumentTask(format!(
+                "Node ID {} not found",
+                node_id
+            )));
+        };
+        let value = match &mut node.data {
+            NodeData::Text(text) => &mut text.value,
+            NodeData::Comment(comment) => &mut comment.value,
+            _ => {
+                return Err(Error::DocumentTask(format!(
+                    "Node ID {} is not a text or comment node",
+                    node_id
+                )))
+            }
+        };
+
+        let old = std::mem::replace(value, content.to_string());
+        self.record_mutation(MutationRecord::CharacterData(node_id));
+        self.record_operation(Operation::Text {
+            node_id,
+            old,
+            new: content.to_string(),
+        });
+
+        Ok(())
+    }
+
+    /// Adds text to the end of a text node
+    pub fn append_text_data(&mut self, node_id: NodeId, content: &str) -> Result<()> {
+        let mut text = match self.get_node_by_id(node_id).map(|node| &node.data) {
+            Some(NodeData::Text(text)) => text.value().to_string(),
+            Some(_) => {
+                return Err(Error::DocumentTask(format!(
+                    "Node ID {} is not a text node",
+                    node_id
+                )))
+            }
+            None => {
+                return Err(Error::DocumentTask(format!(
+                    "Node ID {} not found",
+                    node_id
+                )))
+            }
+        };
+        text.push_str(content);
+        self.set_text_data(node_id, &text)
+    }
+
+    /// Records a mutation of the document
+    pub(crate) fn record_mutation(&mut self, record: MutationRecord) {
+        self.update_indexes(&record);
+        self.invalidate_query_cache(&record);
+        self.queue_mutation_record(&record);
+    }
+
+    /// returns the root node. Documents are created with a root node that is never removed, so
+    /// this only panics on a document that was not created by the DocumentBuilder.
     pub fn get_root(&self) -> &Node {
         self.arena
             .get_node(NodeId::root())
             .expect("Root node not found !?")
     }
 
-    /// Returns true when the given parent_id is a child of the node_id
+    /// Returns true when the given parent_id is a descendant of the node_id, so attaching the
+    /// node to the parent would create a cycle
     pub fn has_cyclic_reference(&self, node_id: NodeId, parent_id: NodeId) -> bool {
-        has_child_recursive(&self.arena, node_id, parent_id)
+        self.is_ancestor_of(node_id, parent_id)
     }
-}
 
-/// Returns true when the parent node has the child node as a child, or if any of the children of
-/// the parent node have the child node as a child.
-fn has_child_recursive(arena: &NodeArena, parent_id: NodeId, child_id: NodeId) -> bool {
-    let node = arena.get_node(parent_id).cloned();
-    if node.is_none() {
-        return false;
+    /// Returns true when `ancestor` is an ancestor of the node (not the node itself). This walks
+    /// up the parents of the node, so it only depends on the depth of the tree and not on the
+    /// size of the subtree below the ancestor.
+    pub fn is_ancestor_of(&self, ancestor: NodeId, node_id: NodeId) -> bool {
+        self.ancestors(node_id).any(|node| node.id == ancestor)
     }
 
-    let node = node.unwrap();
-    for id in node.children.iter() {
-        if *id == child_id {
-            return true;
+    /// Returns true when the node is part of the document tree (the root node is one of its
+    /// ancestors)
+    pub fn is_connected(&self, node_id: NodeId) -> bool {
+        let mut current = node_id;
+        loop {
+            if current.is_root() {
+                return self.arena.get_node(current).is_some();
+            }
+            match self.arena.get_node(current).and_then(|node| node.parent) {
+                Some(parent_id) => current = parent_id,
+                None => return false,
+            }
         }
-        let child = arena.get_node(*id).cloned();
-        if has_child(arena, child, child_id) {
-            return true;
+    }
+
+    /// Returns the ids of the children of the node that are elements (skipping text, comment and
+    /// other nodes), in tree order
+    pub fn child_elements(&self, node_id: NodeId) -> Vec<NodeId> {
+        self.element_children(node_id).collect()
+    }
+
+    /// Returns the first child of the node that is an element
+    pub fn first_element_child(&self, node_id: NodeId) -> Option<NodeId> {
+        self.element_children(node_id).next()
+    }
+
+    /// Returns the last child of the node that is an element
+    pub fn last_element_child(&self, node_id: NodeId) -> Option<NodeId> {
+        self.element_children(node_id).last()
+    }
+
+    /// Returns the ids of the child elements of the node with the given tag name (compared ASCII
+    /// case-insensitively), like the rows of a table section with `"tr"`
+    pub fn element_children_by_tag(&self, node_id: NodeId, tag: &str) -> Vec<NodeId> {
+        self.element_children(node_id)
+            .filter(|&child| {
+                self.arena
+                    .get_node(child)
+                    .is_some_and(|node| node.name.eq_ignore_ascii_case(tag))
+            })
+            .collect()
+    }
+
+    fn element_children(&self, node_id: NodeId) -> impl DoubleEndedIterator<Item = NodeId> + '_ {
+        let children = self
+            .arena
+            .get_node(node_id)
+            .map_or(&[][..], |node| node.children.as_slice());
+        children.iter().copied().filter(|&child| {
+            self.arena
+                .get_node(child)
+                .is_some_and(|node| matches!(node.data, NodeData::Element(_)))
+        })
+    }
+
+    /// Returns the ids of all HTML elements with the given (lowercase) tag name in tree order
+    pub(crate) fn elements_by_tag_name(&self, name: &str) -> Vec<NodeId> {
+        let mut result = Vec::new();
+
+        let mut stack = vec![NodeId::root()];
+        while let Some(node_id) = stack.pop() {
+            let Some(node) = self.arena.get_node(node_id) else {
+                continue;
+            };
+
+            if node.name == name && node.is_namespace(HTML_NAMESPACE) {
+                result.push(node_id);
+            }
+
+            // Push children in reverse so they are popped in tree order
+            stack.extend(node.children.iter().rev());
         }
+
+        result
     }
-    false
-}
 
-fn has_child(arena: &NodeArena, parent: Option<Node>, child_id: NodeId) -> bool {
-    let parent_node = if let Some(node) = parent {
-        node
-    } else {
-        return false;
-    };
+    /// Returns the concatenation of all text nodes that are descendants of the given node
+    pub fn text_content(&self, node_id: NodeId) -> String {
+        let mut text = String::new();
+
+        let mut stack = vec![node_id];
+        while let Some(node_id) = stack.pop() {
+            let Some(node) = self.arena.get_node(node_id) else {
+                continue;
+            };
+
+            if let NodeData::Text(TextData { value }) = &node.data {
+                text.push_str(value);
+            }
+
+            stack.extend(node.children.iter().rev());
+        }
 
-    if parent_node.children.is_empty() {
-        return false;
+        text
     }
 
-    for id in parent_node.children {
-        if id == child_id {
-            return true;
+    /// Freezes the base URL and base target of the document from the given base element. The
+    /// base URL comes from the first base element with an href attribute, and the base target
+    /// from the first one with a target attribute.
+    pub(crate) fn freeze_base_element(&mut self, node_id: NodeId) {
+        let Some(node) = self.get_node_by_id(node_id) else {
+            return;
+        };
+        let href = node
+            .get_attribute("href")
+            .map(|href| href.trim().to_string());
+        let target = node.get_attribute("target").cloned();
+
+        if let Some(href) = href.filter(|_| self.base_element.is_none()) {
+            self.base_element = Some(node_id);
+            self.frozen_base_url = match &self.url {
+                Some(url) => url.join(&href).ok(),
+                None => Url::parse(&href).ok(),
+            };
         }
-        let node = arena.get_node(id).cloned();
-        if has_child(arena, node, child_id) {
-            return true;
+        if let Some(target) = target.filter(|_| self.base_target.is_none()) {
+            self.base_target = Some(target);
         }
     }
 
-    false
+    /// Returns the base URL of the document. When a base element has been frozen during parsing,
+    /// its URL is used. Otherwise this is the href of the first `<base>` element with an href
+    /// attribute (resolved against the document URL), or the document URL itself.
+    pub fn base_url(&self) -> Option<Url> {
+        if self.base_element.is_some() {
+            return self.frozen_base_url.clone().or_else(|| self.url.clone());
+        }
+
+        let href = self
+            .elements_by_tag_name("base")
+            .into_iter()
+            .filter_map(|node_id| self.get_node_by_id(node_id))
+            .find_map(|node| node.get_attribute("href"));
+
+        match (href, &self.url) {
+            (Some(href), Some(url)) => url.join(href.trim()).ok().or_else(|| Some(url.clone())),
+            (Some(href), None) => Url::parse(href.trim()).ok(),
+            (None, url) => url.clone(),
+        }
+    }
+
+    /// Resolves a (possibly relative) URL against the base URL of the document. Returns None when
+    /// the URL is invalid, or when it is relative and the document has no base URL.
+    pub fn resolve_url(&self, url: &str) -> Option<Url> {
+        let url = url.trim();
+        match self.base_url() {
+            Some(base) => base.join(url).ok(),
+            None => Url::parse(url).ok(),
+        }
+    }
+}
+
+/// Checks that a node of the kind can be a child of a parent of the kind: only elements and the
+/// document have children, and a doctype can only be a child of the document
+fn check_kinds(parent: NodeType, node: NodeType) -> std::result::Result<(), HierarchyError> {
+    match (parent, node) {
+        (NodeType::Element, NodeType::DocType) => Err(HierarchyError::DoctypeOutsideDocument),
+        (NodeType::Element | NodeType::Document, _) => Ok(()),
+        _ => Err(HierarchyError::ParentCannotHaveChildren),
+    }
 }
 
 impl Document {
@@ -528,11 +1778,38 @@ impl Document {
 
 impl Display for Document {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        self.print_tree(self.get_root(), "".to_string(), true, f);
+        if let Some(root) = self.arena.get_node(NodeId::root()) {
+            self.print_tree(root, "".to_string(), true, f);
+        }
         Ok(())
     }
 }
 
+/// Adds the elements to a lookup of elements per (class or name) attribute value
+fn add_lookup_entries(
+    lookup: &mut HashMap<String, BTreeSet<NodeId>>,
+    entries: Vec<(String, NodeId)>,
+) {
+    for (value, node_id) in entries {
+        lookup.entry(value).or_default().insert(node_id);
+    }
+}
+
+/// Removes the elements from a lookup of elements per attribute value
+fn remove_lookup_entries(
+    lookup: &mut HashMap<String, BTreeSet<NodeId>>,
+    entries: Vec<(String, NodeId)>,
+) {
+    for (value, node_id) in entries {
+        if let Some(elements) = lookup.get_mut(&value) {
+            elements.remove(&node_id);
+            if elements.is_empty() {
+                lookup.remove(&value);
+            }
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct DocumentHandle(Rc<RefCell<Document>>);
 
@@ -571,32 +1848,91 @@ impl DocumentHandle {
         self.0.borrow_mut()
     }
 
+    /// Returns true when both handles refer to the same document (instead of equal documents)
+    pub(crate) fn is_same_document(&self, other: &DocumentHandle) -> bool {
+        Rc::ptr_eq(&self.0, &other.0)
+    }
+
     /// Attaches a node to the parent node at the given position in the children (or none
-    /// to add at the end).
+    /// to add at the end). See `Document::attach_node_to_parent()`.
     pub fn attach_node_to_parent(
         &mut self,
         node_id: NodeId,
         parent_id: NodeId,
         position: Option<usize>,
-    ) -> bool {
+    ) -> Result<()> {
         self.get_mut()
             .attach_node_to_parent(node_id, parent_id, position)
     }
 
-    /// Separates the given node from its parent node (if any)
-    pub fn detach_node_from_parent(&mut self, node_id: NodeId) {
-        self.get_mut().detach_node_from_parent(node_id)
+    /// Separates the given node from its parent node (if any)
+    pub fn detach_node_from_parent(&mut self, node_id: NodeId) {
+        self.get_mut().detach_node_from_parent(node_id)
+    }
+
+    /// Sets the doctype of the document. An existing doctype is replaced by the new one, which is
+    /// otherwise inserted in front of the document element. Returns the ID of the new doctype.
+    pub fn set_doctype(
+        &mut self,
+        name: &str,
+        pub_identifier: &str,
+        sys_identifier: &str,
+    ) -> Result<NodeId> {
+        let mut doc = self.get_mut();
+        let children = doc.get_root().children.clone();
+        let kind = |data: fn(&NodeData) -> bool| {
+            children.iter().position(|&child| {
+                doc.get_node_by_id(child)
+                    .is_some_and(|child| data(&child.data))
+            })
+        };
+        let existing = kind(|data| matches!(data, NodeData::DocType(_)));
+        let position = existing.or_else(|| kind(|data| matches!(data, NodeData::Element(_))));
+
+        doc.begin_transaction();
+        if let Some(index) = existing {
+            doc.detach_node_from_parent(children[index]);
+        }
+        let node = Node::new_doctype(name, pub_identifier, sys_identifier);
+        let result = doc.add_node(node, NodeId::root(), position);
+        doc.end_transaction();
+
+        result
+    }
+
+    /// Removes the node and its descendants from the document. See
+    /// `Document::remove_subtree()`.
+    pub fn remove_subtree(&mut self, node_id: NodeId) -> Result<()> {
+        self.get_mut().remove_subtree(node_id)
+    }
+
+    /// Returns the connected elements that have all of the given class names. See
+    /// `Document::get_nodes_by_class_name()`.
+    pub fn get_nodes_by_class_name(&self, class_names: &str) -> Vec<NodeId> {
+        self.get().get_nodes_by_class_name(class_names)
+    }
+
+    /// Returns the connected HTML elements with the given name attribute. See
+    /// `Document::get_elements_by_name()`.
+    pub fn get_elements_by_name(&self, name: &str) -> Vec<NodeId> {
+        self.get().get_elements_by_name(name)
     }
 
     /// Inserts a node to the parent node at the given position in the children (or none
     /// to add at the end). Will automatically register the node if not done so already
-    /// Returns the node ID of the inserted node
-    pub fn add_node(&mut self, node: Node, parent_id: NodeId, position: Option<usize>) -> NodeId {
+    /// Returns the node ID of the inserted node. See `Document::add_node()`.
+    pub fn add_node(
+        &mut self,
+        node: Node,
+        parent_id: NodeId,
+        position: Option<usize>,
+    ) -> Result<NodeId> {
         self.get_mut().add_node(node, parent_id, position)
     }
 
-    /// Relocates a node to another parent node
-    pub fn relocate(&mut self, node_id: NodeId, parent_id: NodeId) {
+    /// Relocates a node to another parent node. Returns false when the node cannot be moved
+    /// there.
+    pub fn relocate(&mut self, node_id: NodeId, parent_id: NodeId) -> bool {
         self.get_mut().relocate(node_id, parent_id)
     }
 
@@ -605,6 +1941,11 @@ impl DocumentHandle {
         self.get().has_cyclic_reference(node_id, parent_id)
     }
 
+    /// Returns true when `ancestor` is an ancestor of the node. See `Document::is_ancestor_of()`.
+    pub fn is_ancestor_of(&self, ancestor: NodeId, node_id: NodeId) -> bool {
+        self.get().is_ancestor_of(ancestor, node_id)
+    }
+
     fn insert_id_attribute(&mut self, value: &str, element_id: NodeId) -> Result<()> {
         if !is_valid_id_attribute_value(value) {
             return Err(Error::DocumentTask(format!(
@@ -613,15 +1954,8 @@ impl DocumentHandle {
             )));
         }
 
-        // an ID must be tied to only one element
-        if self.get().named_id_elements.contains_key(value) {
-            return Err(Error::DocumentTask(format!(
-                "ID '{}' already exists in DOM",
-                value
-            )));
-        }
-
         let mut doc = self.get_mut();
+        let snapshot = doc.attribute_snapshot(element_id);
         let data = &mut doc
             .get_node_by_id_mut(element_id)
             .ok_or(Error::DocumentTask(format!(
@@ -642,14 +1976,23 @@ impl DocumentHandle {
             )));
         };
 
-        old_id.map(|id| doc.named_id_elements.remove(&id));
-        doc.named_id_elements.insert(value.to_owned(), element_id);
+        if let Some(old_id) = old_id {
+            doc.release_named_id(&old_id, element_id);
+        }
+        doc.claim_named_id(value, element_id);
+        doc.record_mutation(MutationRecord::Attribute {
+            node_id: element_id,
+            name: "id".to_string(),
+        });
+        doc.record_attribute_change(element_id, snapshot);
 
         Ok(())
     }
 
     fn insert_class_attribute(&mut self, value: &str, element_id: NodeId) -> Result<()> {
         let mut doc = self.get_mut();
+        let snapshot = doc.attribute_snapshot(element_id);
+        doc.unindex_element_classes(element_id);
         let node = doc
             .get_node_by_id_mut(element_id)
             .ok_or(Error::DocumentTask(format!(
@@ -665,6 +2008,13 @@ impl DocumentHandle {
             )));
         }
 
+        doc.index_element_classes(element_id);
+        doc.record_mutation(MutationRecord::Attribute {
+            node_id: element_id,
+            name: "class".to_string(),
+        });
+        doc.record_attribute_change(element_id, snapshot);
+
         Ok(())
     }
 
@@ -675,6 +2025,10 @@ impl DocumentHandle {
         element_id: NodeId,
     ) -> Result<()> {
         let mut doc = self.get_mut();
+        let snapshot = doc.attribute_snapshot(element_id);
+        if key == "name" {
+            doc.unindex_element_name(element_id);
+        }
         let node = doc
             .get_node_by_id_mut(element_id)
             .ok_or(Error::DocumentTask(format!(
@@ -690,8 +2044,86 @@ impl DocumentHandle {
             )));
         }
 
+        if key == "name" {
+            doc.index_element_name(element_id);
+        }
+        doc.register_inline_handler(element_id, key);
+        doc.record_mutation(MutationRecord::Attribute {
+            node_id: element_id,
+            name: key.to_string(),
+        });
+        doc.record_attribute_change(element_id, snapshot);
+
+        Ok(())
+    }
+
+    /// Removes an attribute from an element node. Removing an attribute that is not set is not
+    /// an error. If node is not an element, returns an Err()
+    pub fn remove_attribute(&mut self, key: &str, element_id: NodeId) -> Result<()> {
+        let mut doc = self.get_mut();
+        let snapshot = doc.attribute_snapshot(element_id);
+        if key == "name" {
+            doc.unindex_element_name(element_id);
+        }
+        let node = doc
+            .get_node_by_id_mut(element_id)
+            .ok_or(Error::DocumentTask(format!(
+                "Node ID {} not found",
+                element_id
+            )))?;
+        let NodeData::Element(element) = &mut node.data else {
+            return Err(Error::DocumentTask(format!(
+                "Node ID {} is not an element",
+                element_id
+            )));
+        };
+
+        let removed = element.attributes.remove(key);
+        if key == "class" {
+            doc.unindex_element_classes(element_id);
+            if let Some(NodeData::Element(element)) = doc
+                .get_node_by_id_mut(element_id)
+                .map(|node| &mut node.data)
+            {
+                element.classes = ElementClass::new();
+            }
+        }
+
+        if let Some(old_id) = removed.filter(|_| key == "id") {
+            doc.release_named_id(&old_id, element_id);
+        }
+        doc.record_mutation(MutationRecord::Attribute {
+            node_id: element_id,
+            name: key.to_string(),
+        });
+        doc.record_attribute_change(element_id, snapshot);
+
         Ok(())
     }
+
+    /// Sets an attribute of an element node, keeping the id and class lookups in sync. Same as
+    /// `insert_attribute()`.
+    pub fn set_attribute(&mut self, key: &str, value: &str, element_id: NodeId) -> Result<()> {
+        self.insert_attribute(key, value, element_id)
+    }
+
+    /// Toggles an attribute of an element node (like `toggleAttribute()`): removes it when it is
+    /// set and adds it with an empty value when it is not. With `force`, the attribute is only
+    /// added (true) or only removed (false). Returns true when the attribute is set afterwards.
+    pub fn toggle_attribute(
+        &mut self,
+        key: &str,
+        force: Option<bool>,
+        element_id: NodeId,
+    ) -> Result<bool> {
+        let is_set = self.get().has_attribute(key, element_id);
+        match (is_set, force) {
+            (false, None | Some(true)) => self.insert_attribute(key, "", element_id)?,
+            (true, None | Some(false)) => self.remove_attribute(key, element_id)?,
+            _ => {}
+        }
+        Ok(self.get().has_attribute(key, element_id))
+    }
 }
 
 impl TreeBuilder for DocumentHandle {
@@ -702,21 +2134,23 @@ impl TreeBuilder for DocumentHandle {
         parent_id: NodeId,
         position: Option<usize>,
         namespace: &str,
-    ) -> NodeId {
-        let new_element = Node::new_element(self, name, HashMap::new(), namespace);
+    ) -> Result<NodeId> {
+        let new_element = Node::new_element(name, HashMap::new(), namespace);
         self.add_node(new_element, parent_id, position)
     }
 
     /// Creates and attaches a new text node to the document
-    fn create_text(&mut self, content: &str, parent_id: NodeId) {
-        let new_text = Node::new_text(self, content);
-        self.add_node(new_text, parent_id, None);
+    fn create_text(&mut self, content: &str, parent_id: NodeId) -> Result<()> {
+        let new_text = Node::new_text(content);
+        self.add_node(new_text, parent_id, None)?;
+        Ok(())
     }
 
     /// Creates and attaches a new comment node to the document
-    fn create_comment(&mut self, content: &str, parent_id: NodeId) {
-        let new_comment = Node::new_comment(self, content);
-        self.add_node(new_comment, parent_id, None);
+    fn create_comment(&mut self, content: &str, parent_id: NodeId) -> Result<()> {
+        let new_comment = Node::new_comment(content);
+        self.add_node(new_comment, parent_id, None)?;
+        Ok(())
     }
 
     /// Inserts an attribute to an element node.
@@ -728,6 +2162,59 @@ impl TreeBuilder for DocumentHandle {
             _ => self.insert_generic_attribute(key, value, element_id),
         }
     }
+
+    /// Moves the node right before the sibling. Returns an Err() when the sibling has no parent
+    /// or when the node would become its own descendant.
+    fn insert_before(&mut self, node_id: NodeId, sibling_id: NodeId) -> Result<()> {
+        let position = {
+            let doc = self.get();
+            let parent_id = doc
+                .get_node_by_id(sibling_id)
+                .ok_or(Error::DocumentTask(format!(
+                    "Node ID {} not found",
+                    sibling_id
+                )))?
+                .parent;
+            parent_id.and_then(|parent_id| {
+                let parent = doc.get_node_by_id(parent_id)?;
+                let position = parent
+                    .children
+                    .iter()
+                    .filter(|&&child| child != node_id)
+                    .position(|&child| child == sibling_id)?;
+                Some((parent_id, position))
+            })
+        };
+        let Some((parent_id, position)) = position.filter(|_| node_id != sibling_id) else {
+            return Err(Error::DocumentTask(format!(
+                "Cannot insert node {} before {}: node is not in the tree",
+                node_id, sibling_id
+            )));
+        };
+
+        DocumentTaskQueue::move_node_now(self, node_id, parent_id, Some(position))
+    }
+
+    /// Foster parents the node before the table, or at the end of the fallback parent
+    fn foster_parent(
+        &mut self,
+        node_id: NodeId,
+        table_id: NodeId,
+        fallback_parent_id: NodeId,
+    ) -> Result<()> {
+        let table_parent = self
+            .get()
+            .get_node_by_id(table_id)
+            .ok_or(Error::DocumentTask(format!(
+                "Node ID {} not found",
+                table_id
+            )))?
+            .parent;
+        match table_parent {
+            Some(_) => self.insert_before(node_id, table_id),
+            None => DocumentTaskQueue::move_node_now(self, node_id, fallback_parent_id, None),
+        }
+    }
 }
 
 /// This struct will be used to create a fully initialized document or document fragment
@@ -738,29 +2225,33 @@ impl DocumentBuilder {
     pub fn new_document() -> DocumentHandle {
         let mut doc = Document::shared();
 
-        let handle = &Document::clone(&doc);
-        let node = Node::new_document(handle);
-        doc.get_mut().arena.register_node(node);
+        let node = Node::new_document();
+        let node_id = doc.get_mut().arena.register_node(node);
+        doc.get_mut().intern_node_name(node_id);
 
         doc
     }
 
-    /// Creates a new document fragment with the context as the root node
-    pub fn new_document_fragment(context: Node) -> DocumentHandle {
+    /// Creates a new document whose root holds a fragment (like the contents of a range) instead
+    /// of a document, so it can have any number of children of any kind
+    pub fn new_fragment() -> DocumentHandle {
+        let mut doc = Self::new_document();
+        doc.get_mut().holds_fragment = true;
+        doc
+    }
+
+    /// Creates a new document for parsing a fragment in the given context document
+    pub fn new_document_fragment(context_document: &DocumentHandle) -> DocumentHandle {
         let mut doc = Document::shared();
         doc.get_mut().doctype = DocumentType::HTML;
+        doc.get_mut().quirks_mode = context_document.get().quirks_mode;
 
-        if context.document.get().quirks_mode == QuirksMode::Quirks {
-            doc.get_mut().quirks_mode = QuirksMode::Quirks;
-        } else if context.document.get().quirks_mode == QuirksMode::LimitedQuirks {
-            doc.get_mut().quirks_mode = QuirksMode::LimitedQuirks;
-        }
-
-        // @TODO: Set tokenizer state based on context element
+        // The tokenizer state follows from the context element, see Html5Parser::parse_fragment()
 
-        let html_node = Node::new_element(&doc, "html", HashMap::new(), HTML_NAMESPACE);
+        let html_node = Node::new_element("html", HashMap::new(), HTML_NAMESPACE);
         // doc.get_mut().arena.register_node(html_node);
-        doc.add_node(html_node, NodeId::root(), None);
+        // The html element becomes the root node of the fragment document
+        doc.get_mut().add_new_node(html_node);
 
         doc
     }
@@ -768,27 +2259,35 @@ impl DocumentBuilder {
 
 #[cfg(test)]
 mod tests {
-    use crate::html5::node::{NodeTrait, NodeType, HTML_NAMESPACE};
-    use crate::html5::parser::document::{DocumentBuilder, DocumentTaskQueue};
+    use crate::html5::element_class::ElementClass;
+    use crate::html5::node::{NodeTrait, NodeType, HTML_NAMESPACE, SVG_NAMESPACE};
+    use crate::html5::parser::document::{DocumentBuilder, DocumentHandle, DocumentTaskQueue};
     use crate::html5::parser::tree_builder::TreeBuilder;
     use crate::html5::parser::{Node, NodeData, NodeId};
+    use crate::types::{Error, HierarchyError};
     use std::collections::HashMap;
 
     #[test]
     fn relocate() {
         let mut document = DocumentBuilder::new_document();
 
-        let parent = Node::new_element(&document, "parent", HashMap::new(), HTML_NAMESPACE);
-        let node1 = Node::new_element(&document, "div1", HashMap::new(), HTML_NAMESPACE);
-        let node2 = Node::new_element(&document, "div2", HashMap::new(), HTML_NAMESPACE);
-        let node3 = Node::new_element(&document, "div3", HashMap::new(), HTML_NAMESPACE);
-        let node3_1 = Node::new_element(&document, "div3_1", HashMap::new(), HTML_NAMESPACE);
-
-        let parent_id = document.get_mut().add_node(parent, NodeId::from(0), None);
-        let node1_id = document.get_mut().add_node(node1, parent_id, None);
-        let node2_id = document.get_mut().add_node(node2, parent_id, None);
-        let node3_id = document.get_mut().add_node(node3, parent_id, None);
-        let node3_1_id = document.get_mut().add_node(node3_1, node3_id, None);
+        let parent = Node::new_element("parent", HashMap::new(), HTML_NAMESPACE);
+        let node1 = Node::new_element("div1", HashMap::new(), HTML_NAMESPACE);
+        let node2 = Node::new_element("div2", HashMap::new(), HTML_NAMESPACE);
+        let node3 = Node::new_element("div3", HashMap::new(), HTML_NAMESPACE);
+        let node3_1 = Node::new_element("div3_1", HashMap::new(), HTML_NAMESPACE);
+
+        let parent_id = document
+            .get_mut()
+            .add_node(parent, NodeId::from(0), None)
+            .unwrap();
+        let node1_id = document.get_mut().add_node(node1, parent_id, None).unwrap();
+        let node2_id = document.get_mut().add_node(node2, parent_id, None).unwrap();
+        let node3_id = document.get_mut().add_node(node3, parent_id, None).unwrap();
+        let node3_1_id = document
+            .get_mut()
+            .add_node(node3_1, node3_id, None)
+            .unwrap();
 
         assert_eq!(
             format!("{}", document),
@@ -828,33 +2327,51 @@ mod tests {
 
     #[test]
     fn duplicate_named_id_elements() {
-        let mut document = DocumentBuilder::new_document();
-
-        let div_1 = document.create_element("div", NodeId::root(), None, HTML_NAMESPACE);
-        let div_2 = document.create_element("div", NodeId::root(), None, HTML_NAMESPACE);
-
-        // when adding duplicate IDs, our current implementation will prevent duplicates.
-        let mut res = document.insert_attribute("id", "myid", div_1);
-        assert!(res.is_ok());
-
-        res = document.insert_attribute("id", "myid", div_2);
-        assert!(res.is_err());
-        if let Err(err) = res {
-            assert_eq!(
-                err.to_string(),
-                "document task error: ID 'myid' already exists in DOM"
-            );
-        }
+        let mut document = DocumentBuilder::new_fragment();
+
+        let div_1 = document
+            .create_element("div", NodeId::root(), None, HTML_NAMESPACE)
+            .unwrap();
+        let div_2 = document
+            .create_element("div", NodeId::root(), None, HTML_NAMESPACE)
+            .unwrap();
+
+        // Setting an id never fails, even when it is the same or another element has it
+        document.insert_attribute("id", "myid", div_1).unwrap();
+        document.insert_attribute("id", "myid", div_1).unwrap();
+        document.insert_attribute("id", "myid", div_2).unwrap();
+        assert_eq!(
+            document
+                .get()
+                .get_node_by_id(div_2)
+                .unwrap()
+                .get_attribute("id"),
+            Some(&"myid".to_string())
+        );
 
+        // The first element in tree order is found by the id
+        assert_eq!(
+            document.get().get_node_by_named_id("myid").unwrap().id,
+            div_1
+        );
+        document.insert_attribute("id", "myid", div_1).unwrap();
         assert_eq!(
             document.get().get_node_by_named_id("myid").unwrap().id,
             div_1
         );
 
-        // when div_1's ID changes, "myid" should be removed from the DOM
-        res = document.insert_attribute("id", "newid", div_1);
-        assert!(res.is_ok());
-        assert!(document.get().get_node_by_named_id("myid").is_none());
+        // when div_1's ID changes, div_2 is found by "myid"
+        document.insert_attribute("id", "newid", div_1).unwrap();
+        assert_eq!(
+            document.get().get_node_by_named_id("myid").unwrap().id,
+            div_2
+        );
+        document.insert_attribute("id", "myid", div_1).unwrap();
+        assert_eq!(
+            document.get().get_node_by_named_id("myid").unwrap().id,
+            div_1
+        );
+        document.insert_attribute("id", "newid", div_1).unwrap();
         assert_eq!(
             document.get().get_node_by_named_id("newid").unwrap().id,
             div_1
@@ -863,13 +2380,19 @@ mod tests {
 
     #[test]
     fn verify_node_ids_in_element_data() {
-        let mut document = DocumentBuilder::new_document();
+        let mut document = DocumentBuilder::new_fragment();
 
-        let node1 = Node::new_element(&document, "div", HashMap::new(), HTML_NAMESPACE);
-        let node2 = Node::new_element(&document, "div", HashMap::new(), HTML_NAMESPACE);
+        let node1 = Node::new_element("div", HashMap::new(), HTML_NAMESPACE);
+        let node2 = Node::new_element("div", HashMap::new(), HTML_NAMESPACE);
 
-        document.get_mut().add_node(node1, NodeId::from(0), None);
-        document.get_mut().add_node(node2, NodeId::from(0), None);
+        document
+            .get_mut()
+            .add_node(node1, NodeId::from(0), None)
+            .unwrap();
+        document
+            .get_mut()
+            .add_node(node2, NodeId::from(0), None)
+            .unwrap();
 
         let doc_ptr = document.get();
 
@@ -889,6 +2412,26 @@ mod tests {
         assert_eq!(element2.node_id, NodeId::from(2));
     }
 
+    #[test]
+    fn standalone_node_in_multiple_documents() {
+        let node = Node::new_element("p", HashMap::new(), HTML_NAMESPACE);
+        assert!(!node.is_registered());
+
+        let mut first = DocumentBuilder::new_document();
+        let mut second = DocumentBuilder::new_fragment();
+        second
+            .create_element("div", NodeId::root(), None, HTML_NAMESPACE)
+            .unwrap();
+
+        let first_id = first.add_node(node.clone(), NodeId::root(), None).unwrap();
+        let second_id = second.add_node(node, NodeId::root(), None).unwrap();
+
+        assert_eq!(first_id, NodeId::from(1));
+        assert_eq!(second_id, NodeId::from(2));
+        assert_eq!(first.get().get_node_by_id(first_id).unwrap().name, "p");
+        assert_eq!(second.get().get_node_by_id(second_id).unwrap().name, "p");
+    }
+
     #[test]
     fn document_task_queue() {
         let document = DocumentBuilder::new_document();
@@ -907,15 +2450,21 @@ mod tests {
         let mut task_queue = DocumentTaskQueue::new(&document);
 
         // NOTE: only elements return the ID
-        let div_id = task_queue.create_element("div", NodeId::root(), None, HTML_NAMESPACE);
+        let div_id = task_queue
+            .create_element("div", NodeId::root(), None, HTML_NAMESPACE)
+            .unwrap();
         assert_eq!(div_id, NodeId::from(1));
 
-        let p_id = task_queue.create_element("p", div_id, None, HTML_NAMESPACE);
+        let p_id = task_queue
+            .create_element("p", div_id, None, HTML_NAMESPACE)
+            .unwrap();
         assert_eq!(p_id, NodeId::from(2));
 
-        task_queue.create_comment("comment inside p", p_id);
-        task_queue.create_text("hey", p_id);
-        task_queue.create_comment("comment inside div", div_id);
+        task_queue.create_comment("comment inside p", p_id).unwrap();
+        task_queue.create_text("hey", p_id).unwrap();
+        task_queue
+            .create_comment("comment inside div", div_id)
+            .unwrap();
 
         // at this point, the DOM should have NO nodes (besides root)
         assert_eq!(document.get().arena.count_nodes(), 1);
@@ -997,13 +2546,377 @@ mod tests {
         assert_eq!(p_element.attributes.get("id").unwrap(), "myid");
     }
 
+    #[test]
+    fn task_queue_ids_after_text_nodes() {
+        let document = DocumentBuilder::new_document();
+
+        let mut task_queue = DocumentTaskQueue::new(&document);
+        let div_id = task_queue
+            .create_element("div", NodeId::root(), None, HTML_NAMESPACE)
+            .unwrap();
+        task_queue.create_text("text", div_id).unwrap();
+        task_queue.create_comment("comment", div_id).unwrap();
+        let text_id = task_queue.insert_text_node("first", div_id, Some(0));
+        let p_id = task_queue
+            .create_element("p", div_id, None, HTML_NAMESPACE)
+            .unwrap();
+        assert!(task_queue.flush().is_empty());
+
+        let doc_read = document.get();
+        assert_eq!(doc_read.get_node_by_id(p_id).unwrap().name, "p");
+        assert_eq!(
+            doc_read.get_node_by_id(div_id).unwrap().children[0],
+            text_id
+        );
+        assert_eq!(doc_read.text_content(div_id), "firsttext");
+    }
+
+    #[test]
+    fn task_queue_clone_node() {
+        let document = DocumentBuilder::new_document();
+
+        let mut task_queue = DocumentTaskQueue::new(&document);
+        let div_id = task_queue
+            .create_element("div", NodeId::root(), None, HTML_NAMESPACE)
+            .unwrap();
+        let b_id = task_queue
+            .create_element("b", div_id, None, HTML_NAMESPACE)
+            .unwrap();
+        let _ = task_queue.insert_attribute("class", "x", b_id);
+        let _ = task_queue.insert_attribute("title", "y", b_id);
+        task_queue.create_text("bold", b_id).unwrap();
+        // Nodes created by the queue can be cloned before the flush
+        let copy_id = task_queue.clone_node(b_id, div_id, None).unwrap();
+        let text_id = task_queue.insert_text_node("text", copy_id, None);
+        assert!(task_queue.flush().is_empty());
+
+        {
+            let doc_read = document.get();
+            let copy = doc_read.get_node_by_id(copy_id).unwrap();
+            assert_eq!(copy.name, "b");
+            assert_eq!(copy.children, [text_id]);
+            assert_eq!(copy.get_attribute("title").unwrap(), "y");
+            assert_eq!(
+                doc_read.get_node_by_id(div_id).unwrap().children,
+                [b_id, copy_id]
+            );
+            assert_eq!(doc_read.text_content(div_id), "boldtext");
+        }
+        assert_eq!(document.get_nodes_by_class_name("x"), [b_id, copy_id]);
+
+        assert!(task_queue
+            .clone_node(NodeId::from(99), div_id, None)
+            .is_err());
+        assert!(task_queue.clone_node(NodeId::root(), div_id, None).is_err());
+        assert!(task_queue.is_empty());
+
+        // A missing parent fails the task, but the copy keeps its ID
+        let orphan_id = task_queue.clone_node(b_id, NodeId::from(99), None).unwrap();
+        let next_id = task_queue
+            .create_element("i", div_id, None, HTML_NAMESPACE)
+            .unwrap();
+        assert_eq!(
+            task_queue.flush(),
+            ["document task error: Node ID 99 not found"]
+        );
+        let doc_read = document.get();
+        assert_eq!(doc_read.get_node_by_id(orphan_id).unwrap().parent, None);
+        assert_eq!(doc_read.get_node_by_id(next_id).unwrap().name, "i");
+    }
+
+    #[test]
+    fn task_queue_foster_parenting() {
+        let document = DocumentBuilder::new_document();
+
+        let mut task_queue = DocumentTaskQueue::new(&document);
+        let body_id = task_queue
+            .create_element("body", NodeId::root(), None, HTML_NAMESPACE)
+            .unwrap();
+        let p_id = task_queue
+            .create_element("p", body_id, None, HTML_NAMESPACE)
+            .unwrap();
+        let table_id = task_queue
+            .create_element("table", body_id, None, HTML_NAMESPACE)
+            .unwrap();
+        // Content that ended up in the table is moved in front of it, in order
+        let text_id = task_queue.insert_text_node("text", table_id, None);
+        let div_id = task_queue
+            .create_element("div", table_id, None, HTML_NAMESPACE)
+            .unwrap();
+        task_queue
+            .foster_parent(text_id, table_id, body_id)
+            .unwrap();
+        task_queue.foster_parent(div_id, table_id, body_id).unwrap();
+        assert!(task_queue.flush().is_empty());
+        assert_eq!(
+            document.get().get_node_by_id(body_id).unwrap().children,
+            [p_id, text_id, div_id, table_id]
+        );
+        assert!(document
+            .get()
+            .get_node_by_id(table_id)
+            .unwrap()
+            .children
+            .is_empty());
+
+        // A table without parent sends its content to the fallback parent
+        task_queue.detach_from_parent(table_id);
+        task_queue.foster_parent(div_id, table_id, p_id).unwrap();
+        task_queue.insert_before(text_id, div_id).unwrap();
+        assert!(task_queue.flush().is_empty());
+        assert_eq!(
+            document.get().get_node_by_id(p_id).unwrap().children,
+            [text_id, div_id]
+        );
+
+        task_queue.insert_before(p_id, table_id).unwrap();
+        task_queue.insert_before(body_id, div_id).unwrap();
+        task_queue
+            .foster_parent(div_id, NodeId::from(99), body_id)
+            .unwrap();
+        assert_eq!(
+            task_queue.flush(),
+            [
+                "document task error: Cannot insert node 2 before 3: node is not in the tree",
+                "document task error: Cannot move node 1 into its own descendant 2",
+                "document task error: Node ID 99 not found",
+            ]
+        );
+    }
+
+    #[test]
+    fn task_queue_removal_and_text_tasks() {
+        let document = DocumentBuilder::new_document();
+
+        let mut task_queue = DocumentTaskQueue::new(&document);
+        let div_id = task_queue
+            .create_element("div", NodeId::root(), None, HTML_NAMESPACE)
+            .unwrap();
+        let p_id = task_queue
+            .create_element("p", div_id, None, HTML_NAMESPACE)
+            .unwrap();
+        let text_id = task_queue.insert_text_node("one", div_id, None);
+        let _ = task_queue.insert_attribute("title", "x", div_id);
+        assert!(task_queue.flush().is_empty());
+
+        task_queue.append_to_existing_text(text_id, " two");
+        task_queue.remove_attribute("title", div_id);
+        task_queue.remove_attribute("title", div_id);
+        task_queue.detach_from_parent(p_id);
+        task_queue.detach_from_parent(p_id);
+        assert!(task_queue.flush().is_empty());
+
+        {
+            let doc_read = document.get();
+            assert_eq!(doc_read.text_content(div_id), "one two");
+            assert_eq!(doc_read.get_node_by_id(div_id).unwrap().children, [text_id]);
+            assert!(doc_read
+                .get_node_by_id(div_id)
+                .unwrap()
+                .get_attribute("title")
+                .is_none());
+        }
+
+        // Every failing task reports its own error, and the other tasks are still performed
+        task_queue.remove_node(p_id);
+        task_queue.append_to_existing_text(div_id, "text");
+        task_queue.remove_attribute("title", text_id);
+        task_queue.detach_from_parent(NodeId::from(42));
+        task_queue.remove_node(text_id);
+        let errors = task_queue.flush();
+        assert_eq!(
+            errors,
+            [
+                "document task error: Cannot remove node 2: node is not in the tree",
+                "document task error: Node ID 1 is not a text node",
+                "document task error: Node ID 3 is not an element",
+                "document task error: Node ID 42 not found",
+            ]
+        );
+        assert!(document
+            .get()
+            .get_node_by_id(div_id)
+            .unwrap()
+            .children
+            .is_empty());
+    }
+
+    #[test]
+    fn task_queue_transactional_flush() {
+        let document = DocumentBuilder::new_document();
+
+        let mut task_queue = DocumentTaskQueue::new(&document);
+        let div_id = task_queue
+            .create_element("div", NodeId::root(), None, HTML_NAMESPACE)
+            .unwrap();
+        let text_id = task_queue.insert_text_node("one", div_id, None);
+        let _ = task_queue.insert_attribute("id", "main", div_id);
+        assert!(task_queue.flush_transactional().is_ok());
+        let before = document.get().to_string();
+
+        // All tasks before the failing one are rolled back, the ones after it are not performed
+        let p_id = task_queue
+            .create_element("p", div_id, None, HTML_NAMESPACE)
+            .unwrap();
+        let _ = task_queue.insert_attribute("id", "para", p_id);
+        task_queue.append_to_existing_text(text_id, " two");
+        task_queue.remove_attribute("id", div_id);
+        task_queue.move_node(text_id, p_id, None);
+        task_queue.remove_node(NodeId::from(42));
+        task_queue.remove_node(div_id);
+        match task_queue.flush_transactional() {
+            Err(Error::DocumentTaskRolledBack { index, message }) => {
+                assert_eq!(index, 5);
+                assert_eq!(message, "document task error: Node ID 42 not found");
+            }
+            result => panic!("unexpected result: {:?}", result),
+        }
+        assert!(task_queue.is_empty());
+
+        let doc_read = document.get();
+        assert_eq!(doc_read.to_string(), before);
+        assert_eq!(doc_read.get_node_by_id(div_id).unwrap().children, [text_id]);
+        assert_eq!(doc_read.get_node_by_id(p_id).unwrap().parent, None);
+        assert_eq!(doc_read.get_node_by_named_id("main").unwrap().id, div_id);
+        assert!(doc_read.get_node_by_named_id("para").is_none());
+    }
+
+    #[test]
+    fn task_queue_transactional_flush_history() {
+        let mut document = DocumentBuilder::new_document();
+        document.get_mut().enable_history();
+
+        let mut task_queue = DocumentTaskQueue::new(&document);
+        let div_id = task_queue
+            .create_element("div", NodeId::root(), None, HTML_NAMESPACE)
+            .unwrap();
+        assert!(task_queue.flush_transactional().is_ok());
+
+        task_queue.insert_text_node("text", div_id, None);
+        task_queue.remove_node(NodeId::from(42));
+        assert!(task_queue.flush_transactional().is_err());
+
+        // The rolled back flush is not part of the history
+        assert!(document.get_mut().undo());
+        assert!(document
+            .get()
+            .get_node_by_id(NodeId::root())
+            .unwrap()
+            .children
+            .is_empty());
+        assert!(!document.get_mut().undo());
+    }
+
+    #[test]
+    fn remove_subtree() {
+        let mut document = DocumentBuilder::new_document();
+        let div = document
+            .create_element("div", NodeId::root(), None, HTML_NAMESPACE)
+            .unwrap();
+        let p = document
+            .create_element("p", div, None, HTML_NAMESPACE)
+            .unwrap();
+        document.insert_attribute("id", "para", p).unwrap();
+        document.create_text("text", p).unwrap();
+        let span = document
+            .create_element("span", div, None, HTML_NAMESPACE)
+            .unwrap();
+        document.get_mut().enable_indexes(&[]);
+        assert_eq!(document.get().arena.count_nodes(), 5);
+
+        document.remove_subtree(p).unwrap();
+        {
+            let doc_read = document.get();
+            assert_eq!(doc_read.arena.count_nodes(), 3);
+            assert_eq!(doc_read.get_node_by_id(div).unwrap().children, [span]);
+            assert!(doc_read.get_node_by_id(p).is_none());
+            assert!(doc_read.get_node_by_named_id("para").is_none());
+            assert_eq!(doc_read.indexed_elements_by_tag_name("p"), Some(vec![]));
+        }
+        assert!(document.remove_subtree(p).is_err());
+        assert!(document.remove_subtree(NodeId::root()).is_err());
+
+        // Stale ids never refer to new nodes
+        let em = document
+            .create_element("em", div, None, HTML_NAMESPACE)
+            .unwrap();
+        assert!(em > p);
+        assert!(document.get().get_node_by_id(p).is_none());
+
+        // With the history enabled, nodes are kept so the removal can be undone
+        document.get_mut().enable_history();
+        document.remove_subtree(span).unwrap();
+        assert_eq!(document.get().get_node_by_id(span).unwrap().parent, None);
+        assert!(document.get_mut().undo());
+        assert_eq!(
+            document.get().get_node_by_id(div).unwrap().children,
+            [span, em]
+        );
+    }
+
+    #[test]
+    fn is_ancestor_of() {
+        let mut document = DocumentBuilder::new_document();
+        let div = document
+            .create_element("div", NodeId::root(), None, HTML_NAMESPACE)
+            .unwrap();
+        let p = document
+            .create_element("p", div, None, HTML_NAMESPACE)
+            .unwrap();
+        let span = document
+            .create_element("span", p, None, HTML_NAMESPACE)
+            .unwrap();
+
+        let doc_read = document.get();
+        assert!(doc_read.is_ancestor_of(div, span));
+        assert!(doc_read.is_ancestor_of(NodeId::root(), span));
+        assert!(!doc_read.is_ancestor_of(span, div));
+        assert!(!doc_read.is_ancestor_of(span, span));
+        assert!(!doc_read.is_ancestor_of(div, NodeId::from(42)));
+        assert!(doc_read.has_cyclic_reference(div, span));
+        assert!(!doc_read.has_cyclic_reference(span, div));
+    }
+
+    #[test]
+    fn element_children() {
+        let mut document = DocumentBuilder::new_document();
+        let table = document
+            .create_element("table", NodeId::root(), None, HTML_NAMESPACE)
+            .unwrap();
+        document.create_text("\n", table).unwrap();
+        let caption = document
+            .create_element("caption", table, None, HTML_NAMESPACE)
+            .unwrap();
+        let tr1 = document
+            .create_element("tr", table, None, HTML_NAMESPACE)
+            .unwrap();
+        document.create_comment("row", table).unwrap();
+        let tr2 = document
+            .create_element("TR", table, None, HTML_NAMESPACE)
+            .unwrap();
+        document.create_text("\n", table).unwrap();
+
+        let doc_read = document.get();
+        assert_eq!(doc_read.child_elements(table), [caption, tr1, tr2]);
+        assert_eq!(doc_read.first_element_child(table), Some(caption));
+        assert_eq!(doc_read.last_element_child(table), Some(tr2));
+        assert_eq!(doc_read.element_children_by_tag(table, "tr"), [tr1, tr2]);
+        assert!(doc_read.element_children_by_tag(table, "td").is_empty());
+
+        assert!(doc_read.child_elements(tr1).is_empty());
+        assert_eq!(doc_read.first_element_child(tr1), None);
+        assert_eq!(doc_read.last_element_child(NodeId::from(42)), None);
+    }
+
     #[test]
     fn task_queue_insert_attribute_failues() {
         let document = DocumentBuilder::new_document();
 
         let mut task_queue = DocumentTaskQueue::new(&document);
-        let div_id = task_queue.create_element("div", NodeId::root(), None, HTML_NAMESPACE);
-        task_queue.create_comment("content", div_id); // this is NodeId::from(2)
+        let div_id = task_queue
+            .create_element("div", NodeId::root(), None, HTML_NAMESPACE)
+            .unwrap();
+        task_queue.create_comment("content", div_id).unwrap(); // this is NodeId::from(2)
         task_queue.flush();
 
         // NOTE: inserting attribute in task queue always succeeds
@@ -1019,26 +2932,22 @@ mod tests {
         for error in &errors {
             println!("{}", error);
         }
-        assert_eq!(errors.len(), 6);
+        assert_eq!(errors.len(), 5);
         assert_eq!(
             errors[0],
-            "document task error: ID 'myid' already exists in DOM",
-        );
-        assert_eq!(
-            errors[1],
             "document task error: Node ID 2 is not an element",
         );
-        assert_eq!(errors[2], "document task error: Node ID 42 not found");
+        assert_eq!(errors[1], "document task error: Node ID 42 not found");
         assert_eq!(
-            errors[3],
+            errors[2],
             "document task error: Attribute value 'my id' did not pass validation",
         );
         assert_eq!(
-            errors[4],
+            errors[3],
             "document task error: Attribute value '123' did not pass validation",
         );
         assert_eq!(
-            errors[5],
+            errors[4],
             "document task error: Attribute value '' did not pass validation",
         );
 
@@ -1065,15 +2974,21 @@ mod tests {
         // </div>
 
         // NOTE: only elements return the ID
-        let div_id = document.create_element("div", NodeId::root(), None, HTML_NAMESPACE);
+        let div_id = document
+            .create_element("div", NodeId::root(), None, HTML_NAMESPACE)
+            .unwrap();
         assert_eq!(div_id, NodeId::from(1));
 
-        let p_id = document.create_element("p", div_id, None, HTML_NAMESPACE);
+        let p_id = document
+            .create_element("p", div_id, None, HTML_NAMESPACE)
+            .unwrap();
         assert_eq!(p_id, NodeId::from(2));
 
-        document.create_comment("comment inside p", p_id);
-        document.create_text("hey", p_id);
-        document.create_comment("comment inside div", div_id);
+        document.create_comment("comment inside p", p_id).unwrap();
+        document.create_text("hey", p_id).unwrap();
+        document
+            .create_comment("comment inside div", div_id)
+            .unwrap();
 
         let res = document.insert_attribute("id", "myid", p_id);
         assert!(res.is_ok());
@@ -1136,7 +3051,9 @@ mod tests {
     #[test]
     fn insert_generic_attribute() {
         let mut doc = DocumentBuilder::new_document();
-        let div_id = doc.create_element("div", NodeId::root(), None, HTML_NAMESPACE);
+        let div_id = doc
+            .create_element("div", NodeId::root(), None, HTML_NAMESPACE)
+            .unwrap();
         let res = doc.insert_attribute("key", "value", div_id);
         assert!(res.is_ok());
         let doc_read = doc.get();
@@ -1150,7 +3067,9 @@ mod tests {
     fn task_queue_insert_generic_attribute() {
         let doc = DocumentBuilder::new_document();
         let mut task_queue = DocumentTaskQueue::new(&doc);
-        let div_id = task_queue.create_element("div", NodeId::root(), None, HTML_NAMESPACE);
+        let div_id = task_queue
+            .create_element("div", NodeId::root(), None, HTML_NAMESPACE)
+            .unwrap();
         let _ = task_queue.insert_attribute("key", "value", div_id);
         let errors = task_queue.flush();
         assert!(errors.is_empty());
@@ -1164,7 +3083,9 @@ mod tests {
     #[test]
     fn insert_class_attribute() {
         let mut doc = DocumentBuilder::new_document();
-        let div_id = doc.create_element("div", NodeId::root(), None, HTML_NAMESPACE);
+        let div_id = doc
+            .create_element("div", NodeId::root(), None, HTML_NAMESPACE)
+            .unwrap();
         let res = doc.insert_attribute("class", "one two three", div_id);
         assert!(res.is_ok());
         let doc_read = doc.get();
@@ -1180,7 +3101,9 @@ mod tests {
     fn task_queue_insert_class_attribute() {
         let doc = DocumentBuilder::new_document();
         let mut task_queue = DocumentTaskQueue::new(&doc);
-        let div_id = task_queue.create_element("div", NodeId::root(), None, HTML_NAMESPACE);
+        let div_id = task_queue
+            .create_element("div", NodeId::root(), None, HTML_NAMESPACE)
+            .unwrap();
         let _ = task_queue.insert_attribute("class", "one two three", div_id);
         let errors = task_queue.flush();
         assert!(errors.is_empty());
@@ -1192,4 +3115,322 @@ mod tests {
         assert!(element.classes.contains("two"));
         assert!(element.classes.contains("three"));
     }
+
+    #[test]
+    fn get_nodes_by_class_name() {
+        let mut doc = DocumentBuilder::new_document();
+        let div_id = doc
+            .create_element("div", NodeId::root(), None, HTML_NAMESPACE)
+            .unwrap();
+        let p_id = doc
+            .create_element("p", div_id, None, HTML_NAMESPACE)
+            .unwrap();
+        let span_id = doc
+            .create_element("span", div_id, None, HTML_NAMESPACE)
+            .unwrap();
+        doc.insert_attribute("class", "one two", div_id).unwrap();
+        doc.insert_attribute("class", "two", p_id).unwrap();
+        doc.insert_attribute("class", "two one", span_id).unwrap();
+
+        assert_eq!(
+            doc.get_nodes_by_class_name("two"),
+            vec![div_id, p_id, span_id]
+        );
+        assert_eq!(
+            doc.get_nodes_by_class_name(" one  two "),
+            vec![div_id, span_id]
+        );
+        assert!(doc.get_nodes_by_class_name("three").is_empty());
+        assert!(doc.get_nodes_by_class_name("").is_empty());
+
+        // Updating and removing the class attribute
+        doc.insert_attribute("class", "three", span_id).unwrap();
+        assert_eq!(doc.get_nodes_by_class_name("one"), vec![div_id]);
+        assert_eq!(doc.get_nodes_by_class_name("three"), vec![span_id]);
+        doc.remove_attribute("class", span_id).unwrap();
+        assert!(doc.get_nodes_by_class_name("three").is_empty());
+        assert!(!doc.get().class_elements.contains_key("three"));
+    }
+
+    #[test]
+    fn class_lookup_follows_connection() {
+        let mut doc = DocumentBuilder::new_document();
+        let div_id = doc
+            .create_element("div", NodeId::root(), None, HTML_NAMESPACE)
+            .unwrap();
+        let mut attributes = HashMap::new();
+        attributes.insert("class".to_string(), "item".to_string());
+        let mut node = Node::new_element("li", attributes, HTML_NAMESPACE);
+        if let NodeData::Element(element) = &mut node.data {
+            element.classes = ElementClass::from_string("item");
+        }
+
+        // Nodes that are not connected are not found
+        let li_id = doc.get_mut().add_new_node(node);
+        assert!(doc.get_nodes_by_class_name("item").is_empty());
+        doc.attach_node_to_parent(li_id, div_id, None).unwrap();
+        assert_eq!(doc.get_nodes_by_class_name("item"), vec![li_id]);
+
+        // Detaching an ancestor removes the whole subtree
+        doc.detach_node_from_parent(div_id);
+        assert!(doc.get_nodes_by_class_name("item").is_empty());
+        doc.attach_node_to_parent(div_id, NodeId::root(), None)
+            .unwrap();
+        assert_eq!(doc.get_nodes_by_class_name("item"), vec![li_id]);
+    }
+
+    #[test]
+    fn name_lookup_follows_mutations() {
+        let mut doc = DocumentBuilder::new_fragment();
+        doc.get_mut().enable_history();
+        let form_id = doc
+            .create_element("form", NodeId::root(), None, HTML_NAMESPACE)
+            .unwrap();
+        let input_id = doc
+            .create_element("input", form_id, None, HTML_NAMESPACE)
+            .unwrap();
+        let img_id = doc
+            .create_element("img", NodeId::root(), None, HTML_NAMESPACE)
+            .unwrap();
+        let svg_id = doc
+            .create_element("svg", NodeId::root(), None, SVG_NAMESPACE)
+            .unwrap();
+        doc.insert_attribute("name", "q", input_id).unwrap();
+        doc.insert_attribute("name", "q", img_id).unwrap();
+        doc.insert_attribute("name", "q", svg_id).unwrap();
+
+        // Only HTML elements are found
+        assert_eq!(doc.get_elements_by_name("q"), vec![input_id, img_id]);
+        assert!(doc.get_elements_by_name("other").is_empty());
+
+        // Changing and removing the attribute
+        doc.set_attribute("name", "logo", img_id).unwrap();
+        assert_eq!(doc.get_elements_by_name("q"), vec![input_id]);
+        assert_eq!(doc.get_elements_by_name("logo"), vec![img_id]);
+        doc.remove_attribute("name", img_id).unwrap();
+        assert!(doc.get_elements_by_name("logo").is_empty());
+        doc.get_mut().undo();
+        assert_eq!(doc.get_elements_by_name("logo"), vec![img_id]);
+
+        // Detached elements are not found
+        doc.detach_node_from_parent(form_id);
+        assert!(doc.get_elements_by_name("q").is_empty());
+        doc.attach_node_to_parent(form_id, NodeId::root(), None)
+            .unwrap();
+        assert_eq!(doc.get_elements_by_name("q"), vec![input_id]);
+    }
+
+    #[test]
+    fn attributes_keep_id_lookup_in_sync() {
+        let mut doc = DocumentBuilder::new_fragment();
+        let div_id = doc
+            .create_element("div", NodeId::root(), None, HTML_NAMESPACE)
+            .unwrap();
+        let p_id = doc
+            .create_element("p", div_id, None, HTML_NAMESPACE)
+            .unwrap();
+
+        doc.set_attribute("id", "a", p_id).unwrap();
+        assert_eq!(doc.get().get_attribute("id", p_id), Some("a"));
+        assert!(doc.get().has_attribute("id", p_id));
+        assert!(!doc.get().has_attribute("title", p_id));
+        assert_eq!(doc.get().get_attribute("id", NodeId::from(42)), None);
+
+        // Changing the id releases the old one
+        doc.set_attribute("id", "b", p_id).unwrap();
+        assert!(doc.get().get_node_by_named_id("a").is_none());
+        assert_eq!(doc.get().get_node_by_named_id("b").unwrap().id, p_id);
+
+        // Detached elements are not found, and are found again when attached
+        doc.detach_node_from_parent(div_id);
+        assert!(doc.get().get_node_by_named_id("b").is_none());
+        doc.attach_node_to_parent(div_id, NodeId::root(), None)
+            .unwrap();
+        assert_eq!(doc.get().get_node_by_named_id("b").unwrap().id, p_id);
+
+        // Another element with the same id takes over when the id is released
+        let mut attributes = HashMap::new();
+        attributes.insert("id".to_string(), "b".to_string());
+        let span_id = doc
+            .add_node(
+                Node::new_element("span", attributes, HTML_NAMESPACE),
+                NodeId::root(),
+                None,
+            )
+            .unwrap();
+        assert_eq!(doc.get().get_node_by_named_id("b").unwrap().id, p_id);
+        doc.remove_attribute("id", p_id).unwrap();
+        assert_eq!(doc.get().get_node_by_named_id("b").unwrap().id, span_id);
+        doc.remove_subtree(span_id).unwrap();
+        assert!(doc.get().get_node_by_named_id("b").is_none());
+
+        // Toggling
+        assert!(doc.toggle_attribute("hidden", None, div_id).unwrap());
+        assert_eq!(doc.get().get_attribute("hidden", div_id), Some(""));
+        assert!(doc.toggle_attribute("hidden", Some(true), div_id).unwrap());
+        assert!(!doc.toggle_attribute("hidden", None, div_id).unwrap());
+        assert!(!doc.toggle_attribute("hidden", Some(false), div_id).unwrap());
+        assert!(doc
+            .toggle_attribute("hidden", None, NodeId::from(42))
+            .is_err());
+    }
+
+    #[test]
+    fn doctype_and_document_element() {
+        let mut doc = DocumentBuilder::new_document();
+        let comment_id = doc
+            .add_node(Node::new_comment("first"), NodeId::root(), None)
+            .unwrap();
+        let html_id = doc
+            .create_element("html", NodeId::root(), None, HTML_NAMESPACE)
+            .unwrap();
+
+        // A second element, or a doctype after the element, is not attached
+        let body =
+            doc.get_mut()
+                .add_new_node(Node::new_element("body", HashMap::new(), HTML_NAMESPACE));
+        assert!(matches!(
+            doc.attach_node_to_parent(body, NodeId::root(), None),
+            Err(Error::Hierarchy(HierarchyError::SecondElement))
+        ));
+        let doctype = doc
+            .get_mut()
+            .add_new_node(Node::new_doctype("html", "", ""));
+        assert!(matches!(
+            doc.attach_node_to_parent(doctype, NodeId::root(), None),
+            Err(Error::Hierarchy(HierarchyError::DoctypeAfterElement))
+        ));
+        assert!(doc
+            .attach_node_to_parent(doctype, NodeId::root(), Some(1))
+            .is_ok());
+        assert!(matches!(
+            doc.attach_node_to_parent(html_id, NodeId::root(), Some(0)),
+            Err(Error::Hierarchy(HierarchyError::DoctypeAfterElement))
+        ));
+
+        // Setting the doctype replaces the existing one, at the same position
+        doc.get_mut().enable_history();
+        let doctype_id = doc
+            .set_doctype("html", "-//W3C//DTD HTML 4.01//EN", "")
+            .unwrap();
+        assert_eq!(
+            doc.get().get_root().children,
+            vec![comment_id, doctype_id, html_id]
+        );
+        match &doc.get().get_node_by_id(doctype_id).unwrap().data {
+            NodeData::DocType(data) => {
+                assert_eq!(data.pub_identifier, "-//W3C//DTD HTML 4.01//EN")
+            }
+            _ => panic!("not a doctype"),
+        }
+        assert!(doc.get_mut().undo());
+        assert_eq!(
+            doc.get().get_root().children,
+            vec![comment_id, doctype, html_id]
+        );
+
+        // Without a doctype, it goes in front of the element
+        doc.detach_node_from_parent(doctype);
+        let doctype_id = doc.set_doctype("html", "", "").unwrap();
+        assert_eq!(
+            doc.get().get_root().children,
+            vec![comment_id, doctype_id, html_id]
+        );
+
+        // Documents that hold a fragment have no such limits
+        let mut fragment = DocumentBuilder::new_fragment();
+        fragment
+            .create_element("p", NodeId::root(), None, HTML_NAMESPACE)
+            .unwrap();
+        fragment
+            .create_element("p", NodeId::root(), None, HTML_NAMESPACE)
+            .unwrap();
+        assert_eq!(fragment.get().get_root().children.len(), 2);
+    }
+
+    #[test]
+    fn hierarchy_errors() {
+        let mut doc = DocumentBuilder::new_document();
+        let html_id = doc
+            .create_element("html", NodeId::root(), None, HTML_NAMESPACE)
+            .unwrap();
+        let text_id = doc.get_mut().add_new_node(Node::new_text("text"));
+        let div = Node::new_element("div", HashMap::new(), HTML_NAMESPACE);
+        let div_id = doc.get_mut().add_new_node(div);
+        let doctype_id = doc
+            .get_mut()
+            .add_new_node(Node::new_doctype("html", "", ""));
+
+        let check = |doc: &DocumentHandle, node_id, parent_id| {
+            doc.get().check_insertion(node_id, parent_id, None)
+        };
+        assert_eq!(
+            check(&doc, text_id, NodeId::root()),
+            Err(HierarchyError::TextInDocument)
+        );
+        assert_eq!(
+            check(&doc, div_id, text_id),
+            Err(HierarchyError::ParentCannotHaveChildren)
+        );
+        assert_eq!(
+            check(&doc, doctype_id, html_id),
+            Err(HierarchyError::DoctypeOutsideDocument)
+        );
+        assert_eq!(check(&doc, html_id, html_id), Err(HierarchyError::Cycle));
+        assert_eq!(
+            check(&doc, NodeId::root(), html_id),
+            Err(HierarchyError::DocumentNode)
+        );
+        assert_eq!(
+            check(&doc, NodeId::from(42), html_id),
+            Err(HierarchyError::NotFound)
+        );
+        assert_eq!(check(&doc, text_id, html_id), Ok(()));
+
+        // Failing attaches (and insertions) leave the tree alone
+        assert!(matches!(
+            doc.attach_node_to_parent(text_id, NodeId::root(), None),
+            Err(Error::Hierarchy(HierarchyError::TextInDocument))
+        ));
+        assert!(doc
+            .append(NodeId::root(), [div_id.into(), "text".into()])
+            .is_err());
+        assert!(doc.prepend(NodeId::root(), [doctype_id.into()]).is_ok());
+        assert_eq!(doc.get().get_root().children, vec![doctype_id, html_id]);
+        assert_eq!(doc.get().get_node_by_id(div_id).unwrap().parent, None);
+
+        // A document holding a fragment can have any children
+        let mut fragment = DocumentBuilder::new_fragment();
+        let text_id = fragment.get_mut().add_new_node(Node::new_text("text"));
+        assert!(fragment
+            .attach_node_to_parent(text_id, NodeId::root(), None)
+            .is_ok());
+    }
+
+    #[test]
+    fn comment_data() {
+        let mut doc = DocumentBuilder::new_document();
+        let comment_id = doc
+            .add_node(Node::new_comment("one"), NodeId::root(), None)
+            .unwrap();
+        let html_id = doc
+            .create_element("html", NodeId::root(), None, HTML_NAMESPACE)
+            .unwrap();
+        doc.get_mut().enable_history();
+
+        doc.get_mut().set_comment_data(comment_id, "two").unwrap();
+        doc.get_mut().insert_data(comment_id, 3, " three").unwrap();
+        assert!(doc.get_mut().set_comment_data(html_id, "x").is_err());
+        assert!(doc.get_mut().set_text_data(comment_id, "x").is_err());
+        assert!(doc.get_mut().split_text(comment_id, 1).is_err());
+        let comment =
+            |doc: &DocumentHandle| match &doc.get().get_node_by_id(comment_id).unwrap().data {
+                NodeData::Comment(comment) => comment.value().to_string(),
+                _ => panic!("not a comment"),
+            };
+        assert_eq!(comment(&doc), "two three");
+
+        assert!(doc.get_mut().undo());
+        assert_eq!(comment(&doc), "two");
+    }
 }
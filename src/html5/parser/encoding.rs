@@ -0,0 +1,213 @@
+//! Encoding changes while parsing
+//!
+//! When the encoding of the input is only tentative (it was guessed, or found by the prescan),
+//! a `<meta charset>` that is found while parsing makes the declared encoding certain. When the
+//! input that has been parsed so far means the same in the declared encoding, decoding simply
+//! continues in the new encoding. Otherwise the document is parsed again from the start, and a
+//! [`ParserEvent`] tells the embedder why.
+//!
+//! See: https://html.spec.whatwg.org/multipage/parsing.html#changing-the-encoding-while-parsing
+use crate::bytes::prescan::{extract_charset_from_content, prescan_encoding};
+use crate::bytes::{Encoding, EncodingChange};
+use crate::html5::node::NodeId;
+use crate::html5::parser::events::ParserEvent;
+use crate::html5::parser::{Html5Parser, InsertionMode};
+use crate::html5::tokenizer::token::Token;
+
+impl Html5Parser<'_> {
+    /// Changes the encoding to the one declared by the meta element (if any)
+    pub(crate) fn apply_meta_charset(&mut self, node_id: NodeId) {
+        if self.is_fragment_case {
+            return;
+        }
+
+        let encoding = {
+            let document = self.document.get();
+            let Some(node) = document.get_node_by_id(node_id) else {
+                return;
+            };
+
+            if let Some(charset) = node.get_attribute("charset") {
+                prescan_encoding(&charset.to_ascii_lowercase())
+            } else if node
+                .get_attribute("http-equiv")
+                .is_some_and(|value| value.eq_ignore_ascii_case("content-type"))
+            {
+                node.get_attribute("content")
+                    .and_then(|content| extract_charset_from_content(content))
+                    .and_then(|label| prescan_encoding(&label.to_ascii_lowercase()))
+            } else {
+                None
+            }
+        };
+
+        if let Some(encoding) = encoding {
+            self.change_encoding(encoding);
+        }
+    }
+
+    fn change_encoding(&mut self, encoding: Encoding) {
+        let from = self.tokenizer.chars.encoding;
+        let change = self.tokenizer.chars.change_encoding(encoding);
+        if change == EncodingChange::Ignored {
+            return;
+        }
+
+        self.notify_event(ParserEvent::EncodingChanged {
+            from,
+            to: encoding,
+            change,
+        });
+
+        if change == EncodingChange::Restart {
+            self.restart_requested = true;
+            self.stop_parsing();
+        }
+    }
+
+    /// Removes everything that has been parsed, and resets the parser and tokenizer, so the
+    /// (rewound) input is parsed again from the start
+    pub(crate) fn restart(&mut self) {
+        self.document.get_mut().clear_for_reparse();
+        self.tokenizer.reset();
+        self.error_logger.borrow_mut().truncate(0);
+
+        self.insertion_mode = InsertionMode::Initial;
+        self.original_insertion_mode = InsertionMode::Initial;
+        self.template_insertion_mode.clear();
+        self.parser_cannot_change_mode = false;
+        self.current_token = Token::Eof;
+        self.reprocess_token = false;
+        self.open_elements.clear();
+        self.head_element = None;
+        self.form_element = None;
+        self.frameset_ok = true;
+        self.foster_parenting = false;
+        self.script_already_started = false;
+        self.pending_table_character_tokens.clear();
+        self.ack_self_closing = false;
+        self.active_formatting_elements.clear();
+        self.script_nesting_level = 0;
+        self.parser_pause_flag = false;
+        self.insertion_point = None;
+        self.ignore_lf = false;
+        self.token_queue.clear();
+        self.parser_finished = false;
+        self.deferred_scripts.clear();
+        self.restart_requested = false;
+        self.exceeded_limits.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::bytes::{CharIterator, Encoding, EncodingChange};
+    use crate::html5::events::EventCallback;
+    use crate::html5::node::NodeId;
+    use crate::html5::parser::document::{Document, DocumentBuilder, DocumentHandle};
+    use crate::html5::parser::events::{ParserEvent, ParserEventHandler};
+    use crate::html5::parser::{Html5Parser, Html5ParserOptions};
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    #[derive(Default)]
+    struct Recorder(Vec<ParserEvent>);
+
+    impl ParserEventHandler for Recorder {
+        fn handle_event(&mut self, event: &ParserEvent) {
+            self.0.push(event.clone());
+        }
+    }
+
+    fn parse(bytes: &[u8]) -> (DocumentHandle, Vec<ParserEvent>) {
+        parse_into(DocumentBuilder::new_document(), bytes)
+    }
+
+    fn parse_into(document: DocumentHandle, bytes: &[u8]) -> (DocumentHandle, Vec<ParserEvent>) {
+        let recorder = Rc::new(RefCell::new(Recorder::default()));
+        let options = Html5ParserOptions {
+            event_handler: Some(recorder.clone()),
+            ..Default::default()
+        };
+
+        let mut chars = CharIterator::new();
+        let mut parser =
+            Html5Parser::new_streaming(&mut chars, Document::clone(&document), Some(options));
+        parser.feed(bytes);
+        parser.finish().unwrap();
+
+        let events = recorder.borrow().0.clone();
+        (document, events)
+    }
+
+    fn changed(change: EncodingChange) -> Vec<ParserEvent> {
+        vec![ParserEvent::EncodingChanged {
+            from: Encoding::UTF8,
+            to: Encoding::Windows1252,
+            change,
+        }]
+    }
+
+    #[test]
+    fn switch_decoder() {
+        let (document, events) =
+            parse(b"<html><head><meta charset=\"latin1\"><title>caf\xe9</title></head></html>");
+        assert_eq!(events, changed(EncodingChange::Switched));
+        assert_eq!(
+            document.serialize(),
+            "<html><head><meta charset=\"latin1\"><title>café</title></head><body></body></html>"
+        );
+
+        let (_, events) = parse(b"<meta charset=utf-8><p>caf\xc3\xa9</p>");
+        assert_eq!(
+            events,
+            vec![ParserEvent::EncodingChanged {
+                from: Encoding::UTF8,
+                to: Encoding::UTF8,
+                change: EncodingChange::Confirmed,
+            }]
+        );
+    }
+
+    #[test]
+    fn reparse() {
+        let (document, events) = parse(
+            b"<p id=\"a\">caf\xc3\xa9</p><meta http-equiv=\"Content-Type\" content=\"text/html; charset=windows-1252\"><p>end</p>",
+        );
+        assert_eq!(events, changed(EncodingChange::Restart));
+        assert_eq!(
+            document.serialize(),
+            "<html><head></head><body><p id=\"a\">cafÃ©</p><meta content=\"text/html; charset=windows-1252\" http-equiv=\"Content-Type\"><p>end</p></body></html>"
+        );
+        assert!(document.get().get_node_by_named_id("a").is_some());
+
+        // Strings are already decoded, so their encoding cannot change
+        let mut chars = CharIterator::new();
+        chars.read_from_str("<p>caf\u{e9}</p><meta charset=latin1>", None);
+        let document = DocumentBuilder::new_document();
+        let _ = Html5Parser::parse_document(&mut chars, Document::clone(&document), None);
+        assert!(document.serialize().contains("caf\u{e9}"));
+    }
+
+    #[test]
+    fn reparse_keeps_listeners() {
+        let mut document = DocumentBuilder::new_document();
+        let fired = Rc::new(RefCell::new(0));
+        let counter = fired.clone();
+        let callback: EventCallback = Rc::new(move |_, _| *counter.borrow_mut() += 1);
+        document.get_mut().add_event_listener(
+            NodeId::root(),
+            "DOMContentLoaded",
+            callback,
+            Default::default(),
+        );
+
+        let (document, events) = parse_into(
+            document,
+            b"<p>caf\xc3\xa9</p><meta charset=windows-1252><p>end</p>",
+        );
+        assert_eq!(events, changed(EncodingChange::Restart));
+        assert_eq!(*fired.borrow(), 1);
+        assert_eq!(document.get().elements_by_tag_name("p").len(), 2);
+    }
+}
@@ -0,0 +1,37 @@
+//! Parser events
+//!
+//! Next to building the tree, the parser reports events that embedders may need to act on, but
+//! that are not part of the tree itself (like the parser starting over because the document
+//! declared another character encoding than the one it was decoded with).
+use crate::bytes::{Encoding, EncodingChange};
+use crate::html5::parser::limits::Limit;
+use crate::html5::parser::Html5Parser;
+
+/// Event reported by the parser
+#[derive(Debug, Clone, PartialEq)]
+pub enum ParserEvent {
+    /// The document declared an encoding while parsing (in a `<meta>` element) and the encoding
+    /// of the input has been changed. When the change is `EncodingChange::Restart`, all nodes
+    /// created so far have been removed and the document is parsed again from the start.
+    EncodingChanged {
+        from: Encoding,
+        to: Encoding,
+        change: EncodingChange,
+    },
+    /// Input has been truncated because it exceeded one of the limits of the parser
+    LimitExceeded(Limit),
+}
+
+/// Receives the events reported by the parser
+pub trait ParserEventHandler {
+    fn handle_event(&mut self, event: &ParserEvent);
+}
+
+impl Html5Parser<'_> {
+    /// Reports the event to the event handler (if any)
+    pub(crate) fn notify_event(&self, event: ParserEvent) {
+        if let Some(handler) = &self.event_handler {
+            handler.borrow_mut().handle_event(&event);
+        }
+    }
+}
@@ -3,6 +3,7 @@ use crate::html5::node::data::text::TextData;
 use crate::html5::node::{Node, NodeData, NodeId, HTML_NAMESPACE};
 use crate::html5::parser::{ActiveElement, Html5Parser, Scope};
 use crate::html5::tokenizer::token::Token;
+use crate::types::Error;
 use std::collections::HashMap;
 
 use super::document::{Document, DocumentHandle};
@@ -39,22 +40,20 @@ impl Html5Parser<'_> {
         self.open_elements.iter().position(|x| x == node_id)
     }
 
+    /// Returns the last formatting element with the subject as tag name, between the end of the
+    /// list of active formatting elements and its last marker
     fn find_format_element_index(&self, subject: &str) -> Option<(usize, NodeId)> {
-        self.active_formatting_elements
-            .iter()
-            .enumerate()
-            .rev()
-            .find_map(|(i, &node_id)| {
-                if let ActiveElement::Node(node_id) = node_id {
+        for (i, &entry) in self.active_formatting_elements.iter().enumerate().rev() {
+            match entry {
+                ActiveElement::Marker => return None,
+                ActiveElement::Node(node_id) => {
                     if get_node_by_id!(self.document, node_id).name == subject {
-                        Some((i, node_id))
-                    } else {
-                        None
+                        return Some((i, node_id));
                     }
-                } else {
-                    None
                 }
-            })
+            }
+        }
+        None
     }
 
     fn find_further_block_index(&self, format_ele_position: usize) -> Option<(usize, NodeId)> {
@@ -79,13 +78,20 @@ impl Html5Parser<'_> {
                 before,
             } => {
                 let mut doc = handle;
-                let parent_node = get_node_by_id!(doc, parent);
-                let position = parent_node.children.iter().position(|&x| x == before);
-                doc.attach_node_to_parent(node, parent, position);
+                let position = child_position(&doc, parent, before);
+                match doc.attach_node_to_parent(node, parent, position) {
+                    Ok(()) => self.notify_tree_sink(|sink| {
+                        sink.append_child(parent, node, position.map(|_| before))
+                    }),
+                    Err(error) => self.hierarchy_error(error),
+                }
             }
             InsertionPositionMode::LastChild { handle, parent } => {
                 let mut doc = handle;
-                doc.attach_node_to_parent(node, parent, None);
+                match doc.attach_node_to_parent(node, parent, None) {
+                    Ok(()) => self.notify_tree_sink(|sink| sink.append_child(parent, node, None)),
+                    Err(error) => self.hierarchy_error(error),
+                }
             }
         }
     }
@@ -98,55 +104,122 @@ impl Html5Parser<'_> {
                 before,
             } => {
                 let mut doc = handle;
-                let parent_node = get_node_by_id!(doc, parent);
-                let position = parent_node.children.iter().position(|&x| x == before);
+                let position = child_position(&doc, parent, before);
                 match position {
                     None | Some(0) => {
-                        let node = self.create_node(token, HTML_NAMESPACE);
-                        doc.add_node(node, parent, position);
+                        let Some(token) = self.limit_text_token(token) else {
+                            return;
+                        };
+                        let node = self.create_node(&token, HTML_NAMESPACE);
+                        match doc.add_node(node, parent, position) {
+                            Ok(node_id) => {
+                                self.notify_text_inserted(node_id, parent, position.map(|_| before))
+                            }
+                            Err(error) => self.hierarchy_error(error),
+                        }
                     }
                     Some(index) => {
-                        let last_node_id = parent_node.children[index - 1];
-                        if let NodeData::Text(TextData { ref mut value, .. }) = doc
-                            .get_mut()
-                            .get_node_by_id_mut(last_node_id)
+                        let last_node_id = doc
+                            .get()
+                            .get_node_by_id(parent)
                             .expect("node not found")
-                            .data
-                        {
-                            value.push_str(&token.to_string());
+                            .children[index - 1];
+                        if self.append_to_text_node(&mut doc, last_node_id, token) {
                             return;
-                        };
+                        }
 
-                        let node = self.create_node(token, HTML_NAMESPACE);
-                        doc.add_node(node, parent, Some(index));
+                        let Some(token) = self.limit_text_token(token) else {
+                            return;
+                        };
+                        let node = self.create_node(&token, HTML_NAMESPACE);
+                        match doc.add_node(node, parent, Some(index)) {
+                            Ok(node_id) => self.notify_text_inserted(node_id, parent, Some(before)),
+                            Err(error) => self.hierarchy_error(error),
+                        }
                     }
                 }
             }
             InsertionPositionMode::LastChild { handle, parent } => {
                 let mut doc = handle;
-                let parent_node = get_node_by_id!(doc, parent);
-                if let Some(last_node_id) = parent_node.children.last() {
-                    if let NodeData::Text(TextData { ref mut value, .. }) = self
-                        .document
-                        .get_mut()
-                        .get_node_by_id_mut(*last_node_id)
-                        .expect("node not found")
-                        .data
-                    {
-                        value.push_str(&token.to_string());
+                let last_child = doc
+                    .get()
+                    .get_node_by_id(parent)
+                    .expect("node not found")
+                    .children
+                    .last()
+                    .copied();
+                if let Some(last_node_id) = last_child {
+                    if self.append_to_text_node(&mut doc, last_node_id, token) {
                         return;
-                    };
-                    let node = self.create_node(token, HTML_NAMESPACE);
-                    doc.add_node(node, parent, None);
-                    return;
+                    }
                 }
 
-                let node = self.create_node(token, HTML_NAMESPACE);
-                doc.add_node(node, parent, None);
+                let Some(token) = self.limit_text_token(token) else {
+                    return;
+                };
+                let node = self.create_node(&token, HTML_NAMESPACE);
+                match doc.add_node(node, parent, None) {
+                    Ok(node_id) => self.notify_text_inserted(node_id, parent, None),
+                    Err(error) => self.hierarchy_error(error),
+                }
             }
         }
     }
 
+    /// Appends the text of the token to the node when it is a text node. Returns false when the
+    /// node is not a text node.
+    fn append_to_text_node(
+        &mut self,
+        doc: &mut DocumentHandle,
+        node_id: NodeId,
+        token: &Token,
+    ) -> bool {
+        let current = match &doc
+            .get()
+            .get_node_by_id(node_id)
+            .expect("node not found")
+            .data
+        {
+            NodeData::Text(TextData { value, .. }) => value.len(),
+            _ => return false,
+        };
+
+        let Some(text) = self.limit_text(current, token.to_string()) else {
+            return true;
+        };
+        let token_span = self.token_span;
+        let mut doc_mut = doc.get_mut();
+        let node = doc_mut.get_node_by_id_mut(node_id).expect("node not found");
+        if let NodeData::Text(TextData { ref mut value, .. }) = node.data {
+            value.push_str(&text);
+        }
+        // The text node now runs up to the end of the current token
+        node.source_span = match (node.source_span, token_span) {
+            (Some(span), Some(token_span)) => Some(span.to(&token_span)),
+            (span, token_span) => span.or(token_span),
+        };
+        drop(doc_mut);
+        self.notify_tree_sink(|sink| sink.append_text(node_id, &text));
+        true
+    }
+
+    /// Returns the text token truncated to the text size limit, or None when nothing is left
+    fn limit_text_token(&mut self, token: &Token) -> Option<Token> {
+        self.limit_text(0, token.to_string()).map(Token::Text)
+    }
+
+    /// Tree construction only builds trees the DOM allows, so a node that cannot be attached is a
+    /// bug in the parser. It is reported as a parse error, and not to the tree sink.
+    fn hierarchy_error(&self, error: Error) {
+        self.parse_error(&error.to_string());
+    }
+
+    /// Reports a newly created text node that has been inserted into the parent to the tree sink
+    fn notify_text_inserted(&self, node_id: NodeId, parent: NodeId, before: Option<NodeId>) {
+        self.notify_node_created(node_id);
+        self.notify_tree_sink(|sink| sink.append_child(parent, node_id, before));
+    }
+
     pub fn insert_html_element(&mut self, token: &Token) -> NodeId {
         self.insert_element_from_token(token, None, Some(HTML_NAMESPACE))
     }
@@ -201,6 +274,8 @@ impl Html5Parser<'_> {
 
     pub fn insert_element(&mut self, node: Node, override_node: Option<NodeId>) -> NodeId {
         let node_id = self.document.get_mut().add_new_node(node);
+        self.notify_node_created(node_id);
+        self.associate_with_form_element(node_id);
         let insert_position = self.appropriate_place_insert(override_node);
         self.insert_element_helper(node_id, insert_position);
 
@@ -216,23 +291,45 @@ impl Html5Parser<'_> {
 
     pub fn insert_doctype_element(&mut self, token: &Token) {
         let node = self.create_node(token, HTML_NAMESPACE);
-        self.document.get_mut().add_node(node, NodeId::root(), None);
+        let result = self.document.get_mut().add_node(node, NodeId::root(), None);
+        match result {
+            Ok(node_id) => {
+                self.notify_node_created(node_id);
+                self.notify_tree_sink(|sink| sink.append_child(NodeId::root(), node_id, None));
+            }
+            Err(error) => self.hierarchy_error(error),
+        }
     }
 
     pub fn insert_document_element(&mut self, token: &Token) {
         let node = self.create_node(token, HTML_NAMESPACE);
-        let node_id = self.document.get_mut().add_node(node, NodeId::root(), None);
-        self.open_elements.push(node_id);
+        let result = self.document.get_mut().add_node(node, NodeId::root(), None);
+        match result {
+            Ok(node_id) => {
+                self.notify_node_created(node_id);
+                self.notify_tree_sink(|sink| sink.append_child(NodeId::root(), node_id, None));
+                self.open_elements.push(node_id);
+            }
+            Err(error) => self.hierarchy_error(error),
+        }
     }
 
     pub fn insert_comment_element(&mut self, token: &Token, insert_position: Option<NodeId>) {
         let node = self.create_node(token, HTML_NAMESPACE);
         match insert_position {
             Some(position) => {
-                self.document.get_mut().add_node(node, position, None);
+                let result = self.document.get_mut().add_node(node, position, None);
+                match result {
+                    Ok(node_id) => {
+                        self.notify_node_created(node_id);
+                        self.notify_tree_sink(|sink| sink.append_child(position, node_id, None));
+                    }
+                    Err(error) => self.hierarchy_error(error),
+                }
             }
             None => {
                 let node_id = self.document.get_mut().add_new_node(node);
+                self.notify_node_created(node_id);
                 let insert_position = self.appropriate_place_insert(None);
                 self.insert_element_helper(node_id, insert_position);
             }
@@ -441,13 +538,10 @@ impl Html5Parser<'_> {
                     NodeData::Element(element) => element.attributes.clone(),
                     _ => HashMap::new(),
                 };
-                let replacement_node = Node::new_element(
-                    &self.document,
-                    &element.name,
-                    node_attributes,
-                    HTML_NAMESPACE,
-                );
+                let replacement_node =
+                    Node::new_element(&element.name, node_attributes, HTML_NAMESPACE);
                 let replace_node_id = self.document.get_mut().add_new_node(replacement_node);
+                self.notify_node_created(replace_node_id);
 
                 self.active_formatting_elements[node_active_position] =
                     ActiveElement::Node(replace_node_id);
@@ -463,8 +557,16 @@ impl Html5Parser<'_> {
 
                 // step 4.13.8
                 self.document.detach_node_from_parent(last_node_id);
-                self.document
-                    .attach_node_to_parent(last_node_id, replace_node_id, None);
+                self.notify_tree_sink(|sink| sink.remove_from_parent(last_node_id));
+                let result =
+                    self.document
+                        .attach_node_to_parent(last_node_id, replace_node_id, None);
+                match result {
+                    Ok(()) => self.notify_tree_sink(|sink| {
+                        sink.append_child(replace_node_id, last_node_id, None)
+                    }),
+                    Err(error) => self.hierarchy_error(error),
+                }
 
                 // step 4.13.9
                 last_node_id = node_id;
@@ -472,6 +574,7 @@ impl Html5Parser<'_> {
 
             // step 4.14
             self.document.detach_node_from_parent(last_node_id);
+            self.notify_tree_sink(|sink| sink.remove_from_parent(last_node_id));
             let insert_position = self.appropriate_place_insert(Some(common_ancestor));
             self.insert_element_helper(last_node_id, insert_position);
 
@@ -481,7 +584,6 @@ impl Html5Parser<'_> {
                 _ => HashMap::new(),
             };
             let new_format_node: Node = Node::new_element(
-                &self.document,
                 &format_elem_node.name,
                 format_elem_attributes,
                 HTML_NAMESPACE,
@@ -492,15 +594,33 @@ impl Html5Parser<'_> {
                 .document
                 .get_mut()
                 .add_new_node(new_format_node.clone());
-            let further_block_node = get_node_by_id!(self.document, further_block_node_id);
-            for child in further_block_node.children.iter() {
-                self.document.get_mut().relocate(*child, new_node_id);
+            let further_block_children = self
+                .document
+                .get()
+                .get_node_by_id(further_block_node_id)
+                .expect("node not found")
+                .children
+                .clone();
+            self.notify_node_created(new_node_id);
+            for child in further_block_children {
+                self.document.get_mut().relocate(child, new_node_id);
             }
+            self.notify_tree_sink(|sink| {
+                sink.reparent_children(further_block_node_id, new_node_id)
+            });
 
             // step 4.17
-            self.document
-                .get_mut()
-                .attach_node_to_parent(new_node_id, further_block_node_id, None);
+            let result = self.document.get_mut().attach_node_to_parent(
+                new_node_id,
+                further_block_node_id,
+                None,
+            );
+            match result {
+                Ok(()) => self.notify_tree_sink(|sink| {
+                    sink.append_child(further_block_node_id, new_node_id, None)
+                }),
+                Err(error) => self.hierarchy_error(error),
+            }
 
             // step 4.18
             match bookmark_node_id {
@@ -531,3 +651,13 @@ impl Html5Parser<'_> {
         }
     }
 }
+
+/// Returns the position of the child in the children of the parent
+fn child_position(doc: &DocumentHandle, parent: NodeId, child: NodeId) -> Option<usize> {
+    doc.get()
+        .get_node_by_id(parent)
+        .expect("node not found")
+        .children
+        .iter()
+        .position(|&x| x == child)
+}
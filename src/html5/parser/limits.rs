@@ -0,0 +1,231 @@
+//! Parser limits
+//!
+//! Services that parse HTML from untrusted sources need to bound the work (and memory) a single
+//! document can take. The limits of the parser cap the number of nodes, the attributes of an
+//! element and the size of attribute values and text. Input that exceeds a limit is truncated
+//! instead of rejected: the document is built up to the limit, and the parser reports which
+//! limits have been exceeded (as a parse error and a `ParserEvent::LimitExceeded`).
+use crate::html5::parser::events::ParserEvent;
+use crate::html5::parser::Html5Parser;
+use crate::html5::tokenizer::token::Token;
+use std::fmt;
+
+/// Limits of the parser. Limits that are not set are unbounded.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ParserLimits {
+    /// Maximum number of nodes in the document. When reached, the rest of the input is ignored
+    /// (the parser acts as if the input ended there).
+    pub max_nodes: Option<usize>,
+    /// Maximum number of attributes of an element. Later attributes are dropped.
+    pub max_attributes: Option<usize>,
+    /// Maximum size (in bytes) of an attribute value. Longer values are truncated.
+    pub max_attribute_length: Option<usize>,
+    /// Maximum size (in bytes) of a text node or comment. Longer text is truncated.
+    pub max_text_size: Option<usize>,
+}
+
+impl ParserLimits {
+    /// Limits that are generous for real-world pages, but stop hostile input from exhausting
+    /// memory
+    pub fn untrusted() -> Self {
+        Self {
+            max_nodes: Some(500_000),
+            max_attributes: Some(256),
+            max_attribute_length: Some(64 * 1024),
+            max_text_size: Some(1024 * 1024),
+        }
+    }
+}
+
+/// A limit of the parser
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Limit {
+    Nodes,
+    Attributes,
+    AttributeLength,
+    TextSize,
+}
+
+impl fmt::Display for Limit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Limit::Nodes => "max-nodes",
+            Limit::Attributes => "max-attributes",
+            Limit::AttributeLength => "max-attribute-length",
+            Limit::TextSize => "max-text-size",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Truncates the text to at most `max` bytes, without splitting a character. Returns true when
+/// the text was longer.
+pub(crate) fn truncate_text(text: &mut String, max: usize) -> bool {
+    if text.len() <= max {
+        return false;
+    }
+
+    let mut end = max;
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+    text.truncate(end);
+    true
+}
+
+impl Html5Parser<'_> {
+    /// Reports that input has been truncated because of the limit. Every limit is reported once
+    /// per parse.
+    pub(crate) fn limit_exceeded(&mut self, limit: Limit) {
+        if self.exceeded_limits.contains(&limit) {
+            return;
+        }
+        self.exceeded_limits.push(limit);
+        self.parse_error(&format!("limit-exceeded-{}", limit));
+        self.notify_event(ParserEvent::LimitExceeded(limit));
+    }
+
+    /// Applies the limits to a token fetched from the tokenizer. Once the document has reached the
+    /// maximum number of nodes, the token is replaced by the end of the input.
+    pub(crate) fn apply_limits(&mut self, mut token: Token) -> Token {
+        // The tokenizer truncates the attributes, as only it knows their order
+        for limit in std::mem::take(&mut self.tokenizer.exceeded_limits) {
+            self.limit_exceeded(limit);
+        }
+
+        if let Some(max_nodes) = self.limits.max_nodes {
+            if self.document.get().arena.count_nodes() >= max_nodes {
+                if !matches!(token, Token::Eof) {
+                    self.limit_exceeded(Limit::Nodes);
+                }
+                return Token::Eof;
+            }
+        }
+
+        if let (Token::Comment(text), Some(max)) = (&mut token, self.limits.max_text_size) {
+            if truncate_text(text, max) {
+                self.limit_exceeded(Limit::TextSize);
+            }
+        }
+
+        token
+    }
+
+    /// Returns the part of the text that can be added to a text node of `current` bytes, or
+    /// None when the node is full
+    pub(crate) fn limit_text(&mut self, current: usize, mut text: String) -> Option<String> {
+        let Some(max) = self.limits.max_text_size else {
+            return Some(text);
+        };
+
+        if truncate_text(&mut text, max.saturating_sub(current)) {
+            self.limit_exceeded(Limit::TextSize);
+        }
+        (!text.is_empty()).then_some(text)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bytes::{CharIterator, Confidence, Encoding};
+    use crate::html5::parser::document::{Document, DocumentBuilder, DocumentHandle};
+    use crate::html5::parser::events::ParserEventHandler;
+    use crate::html5::parser::Html5ParserOptions;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    #[derive(Default)]
+    struct Recorder(Vec<ParserEvent>);
+
+    impl ParserEventHandler for Recorder {
+        fn handle_event(&mut self, event: &ParserEvent) {
+            self.0.push(event.clone());
+        }
+    }
+
+    fn parse(html: &str, limits: ParserLimits) -> (DocumentHandle, Vec<String>, Vec<ParserEvent>) {
+        let recorder = Rc::new(RefCell::new(Recorder::default()));
+        let options = Html5ParserOptions {
+            event_handler: Some(recorder.clone()),
+            limits,
+            ..Default::default()
+        };
+
+        let mut chars = CharIterator::new();
+        chars.read_from_str(html, Some(Encoding::UTF8));
+        chars.set_confidence(Confidence::Certain);
+        let document = DocumentBuilder::new_document();
+        let errors =
+            Html5Parser::parse_document(&mut chars, Document::clone(&document), Some(options))
+                .unwrap()
+                .into_iter()
+                .map(|error| error.message)
+                .filter(|message| message.starts_with("limit-exceeded"))
+                .collect();
+
+        let events = recorder.borrow().0.clone();
+        (document, errors, events)
+    }
+
+    #[test]
+    fn truncate_attributes_and_text() {
+        let limits = ParserLimits {
+            max_attributes: Some(2),
+            max_attribute_length: Some(4),
+            max_text_size: Some(6),
+            ..Default::default()
+        };
+        let (document, errors, events) = parse(
+            "<p a=1 b=lengthy c=3 a=4>één twee</p><p>ok</p><!--commentary-->",
+            limits,
+        );
+        assert_eq!(
+            document.serialize(),
+            "<html><head></head><body><p a=\"1\" b=\"leng\">één </p><p>ok</p><!--commen--></body></html>"
+        );
+        assert_eq!(
+            errors,
+            [
+                "limit-exceeded-max-attribute-length",
+                "limit-exceeded-max-attributes",
+                "limit-exceeded-max-text-size",
+            ]
+        );
+        assert_eq!(
+            events,
+            [
+                ParserEvent::LimitExceeded(Limit::AttributeLength),
+                ParserEvent::LimitExceeded(Limit::Attributes),
+                ParserEvent::LimitExceeded(Limit::TextSize),
+            ]
+        );
+
+        // Without limits, nothing is truncated
+        let (document, errors, events) =
+            parse("<p a=1 b=lengthy c=3>één twee</p>", ParserLimits::default());
+        assert_eq!(
+            document.serialize(),
+            "<html><head></head><body><p a=\"1\" b=\"lengthy\" c=\"3\">één twee</p></body></html>"
+        );
+        assert!(errors.is_empty());
+        assert!(events.is_empty());
+    }
+
+    #[test]
+    fn max_nodes() {
+        let limits = ParserLimits {
+            max_nodes: Some(8),
+            ..Default::default()
+        };
+        let (document, errors, events) =
+            parse("<div><p>one</p><p>two</p><p>three</p></div>", limits);
+        // The document, html, head, body, div, p, text and p fill the budget
+        assert_eq!(
+            document.serialize(),
+            "<html><head></head><body><div><p>one</p><p></p></div></body></html>"
+        );
+        assert_eq!(errors, ["limit-exceeded-max-nodes"]);
+        assert_eq!(events, [ParserEvent::LimitExceeded(Limit::Nodes)]);
+    }
+}
@@ -20,6 +20,14 @@ impl Html5Parser<'_> {
             return QuirksMode::Quirks;
         }
 
+        // Quirks conditions take precedence over the limited quirks conditions below
+        if let Some(value) = &sys_identifier {
+            let sys_id = value.to_lowercase();
+            if QUIRKS_SYS_IDENTIFIER_EQ.contains(&sys_id.as_str()) {
+                return QuirksMode::Quirks;
+            }
+        }
+
         if let Some(value) = pub_identifer {
             let pub_id = value.to_lowercase();
             if QUIRKS_PUB_IDENTIFIER_EQ.contains(&pub_id.as_str()) {
@@ -57,16 +65,6 @@ impl Html5Parser<'_> {
             }
         }
 
-        if let Some(value) = sys_identifier {
-            let sys_id = value.to_lowercase();
-            if QUIRKS_SYS_IDENTIFIER_EQ
-                .iter()
-                .any(|&prefix| sys_id.as_str().starts_with(prefix))
-            {
-                return QuirksMode::Quirks;
-            }
-        }
-
         QuirksMode::NoQuirks
     }
 }
@@ -359,4 +357,31 @@ mod tests {
             QuirksMode::Quirks
         );
     }
+
+    #[test]
+    fn test_quirks_mode_sys_identifier() {
+        let chars = &mut CharIterator::new();
+        let parser = Html5Parser::new_parser(chars);
+
+        // The legacy system identifier forces quirks mode, even with a limited quirks public id
+        assert_eq!(
+            parser.identify_quirks_mode(
+                &Some("html".to_string()),
+                Some("-//W3C//DTD XHTML 1.0 Transitional//EN".to_string()),
+                Some("http://www.ibm.com/data/dtd/v11/IBMXHTML1-transitional.dtd".to_string()),
+                false
+            ),
+            QuirksMode::Quirks
+        );
+        // Only an exact match counts
+        assert_eq!(
+            parser.identify_quirks_mode(
+                &Some("html".to_string()),
+                None,
+                Some("http://www.ibm.com/data/dtd/v11/ibmxhtml1-transitional.dtd2".to_string()),
+                false
+            ),
+            QuirksMode::NoQuirks
+        );
+    }
 }
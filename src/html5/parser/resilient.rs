@@ -0,0 +1,186 @@
+//! Panic-isolated parsing
+//!
+//! Internal errors of the parser (a node that cannot be found, an `unwrap` on an unexpected
+//! state) are bugs, but one bad page should not take down a process that parses many of them,
+//! like a crawler. `Html5Parser::parse_resilient()` isolates these failures: it returns the part
+//! of the document that was built up to the failure, together with a report of what went wrong.
+use crate::bytes::CharIterator;
+use crate::html5::error_logger::ErrorLogger;
+use crate::html5::parser::document::{Document, DocumentBuilder, DocumentHandle};
+use crate::html5::parser::{Html5Parser, Html5ParserOptions};
+use crate::html5::tokenizer::Tokenizer;
+use crate::types::ParseError;
+use std::any::Any;
+use std::cell::RefCell;
+use std::fmt;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::rc::Rc;
+
+/// How parsing failed
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FailureKind {
+    /// The parser panicked
+    Panic,
+    /// The parser returned an error
+    Error,
+}
+
+/// Report of a parse that did not reach the end of the input
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseFailure {
+    pub kind: FailureKind,
+    /// Panic or error message
+    pub message: String,
+    /// Offset in the input stream that the tokenizer had reached
+    pub offset: usize,
+}
+
+impl fmt::Display for ParseFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let kind = match self.kind {
+            FailureKind::Panic => "panicked",
+            FailureKind::Error => "failed",
+        };
+        write!(
+            f,
+            "parser {} at offset {}: {}",
+            kind, self.offset, self.message
+        )
+    }
+}
+
+/// Result of a resilient parse
+pub struct ResilientParse {
+    /// The document, which is only partially built when parsing failed
+    pub document: DocumentHandle,
+    /// Parse errors found in the input (up to the failure)
+    pub errors: Vec<ParseError>,
+    /// Why parsing stopped early, if it did
+    pub failure: Option<ParseFailure>,
+}
+
+impl ResilientParse {
+    /// Returns true when the whole input has been parsed
+    pub fn is_complete(&self) -> bool {
+        self.failure.is_none()
+    }
+}
+
+impl Html5Parser<'_> {
+    /// Parses the input chars into a new document like `parse_document()`, but never panics.
+    /// When the parser fails, the document built so far is returned together with the failure.
+    ///
+    /// Note that the panic hook still runs, so the default hook prints the panic message to
+    /// stderr.
+    pub fn parse_resilient(
+        chars: &mut CharIterator,
+        options: Option<Html5ParserOptions>,
+    ) -> ResilientParse {
+        let document = DocumentBuilder::new_document();
+        // Created here, so the errors found before a panic are not lost
+        let error_logger = Rc::new(RefCell::new(ErrorLogger::new()));
+
+        let result = catch_unwind(AssertUnwindSafe(|| {
+            let tokenizer = Tokenizer::new(chars, None, error_logger.clone());
+            let mut parser = Html5Parser::init(
+                tokenizer,
+                Document::clone(&document),
+                error_logger.clone(),
+                options,
+            );
+            parser.do_parse()
+        }));
+
+        let failure = match result {
+            Ok(Ok(_)) => None,
+            Ok(Err(err)) => Some((FailureKind::Error, err.to_string())),
+            Err(payload) => Some((FailureKind::Panic, panic_message(payload.as_ref()))),
+        }
+        .map(|(kind, message)| ParseFailure {
+            kind,
+            message,
+            offset: chars.tell(),
+        });
+
+        // The logger might still be borrowed if the panic happened while it was
+        let errors = error_logger
+            .try_borrow()
+            .map(|logger| logger.get_errors())
+            .unwrap_or_default();
+
+        ResilientParse {
+            document,
+            errors,
+            failure,
+        }
+    }
+}
+
+/// Returns the message of a panic payload
+fn panic_message(payload: &(dyn Any + Send)) -> String {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message.to_string()
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        "unknown panic".to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bytes::Encoding;
+    use crate::html5::parser::limits::ParserLimits;
+    use crate::html5::parser::script::{Script, ScriptHandler};
+
+    struct PanickingScriptHandler;
+
+    impl ScriptHandler for PanickingScriptHandler {
+        fn execute(&mut self, _document: &DocumentHandle, _script: &Script) {
+            panic!("script handler failed");
+        }
+    }
+
+    fn parse(html: &str, options: Option<Html5ParserOptions>) -> ResilientParse {
+        let mut chars = CharIterator::new();
+        chars.read_from_str(html, Some(Encoding::UTF8));
+        Html5Parser::parse_resilient(&mut chars, options)
+    }
+
+    #[test]
+    fn complete_parse() {
+        let result = parse("<p>hello</p>", None);
+        assert!(result.is_complete());
+        assert_eq!(result.errors.len(), 1);
+        assert_eq!(result.document.get().elements_by_tag_name("p").len(), 1);
+    }
+
+    #[test]
+    fn panic_returns_partial_document() {
+        let options = Html5ParserOptions {
+            scripting_enabled: true,
+            script_handler: Some(Rc::new(RefCell::new(PanickingScriptHandler))),
+            tree_sink: None,
+            event_handler: None,
+            limits: ParserLimits::default(),
+        };
+        let html = "<p>before</p><script>boom()</script><p>after</p>";
+        let result = parse(html, Some(options));
+
+        let failure = result.failure.as_ref().unwrap();
+        assert_eq!(failure.kind, FailureKind::Panic);
+        assert_eq!(failure.message, "script handler failed");
+        assert!(failure.offset > html.find("</script>").unwrap());
+        assert!(failure.offset < html.find("after").unwrap());
+        assert!(failure.to_string().starts_with("parser panicked at offset"));
+
+        // The document up to the script is available, and can still be used
+        let mut document = Document::clone(&result.document);
+        let p = document.get().elements_by_tag_name("p");
+        assert_eq!(p.len(), 1);
+        assert_eq!(document.get().text_content(p[0]), "before");
+        assert!(!document.relocate(p[0], p[0]));
+        assert!(document.get().is_connected(p[0]));
+    }
+}
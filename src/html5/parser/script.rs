@@ -0,0 +1,286 @@
+//! Script element processing
+//!
+//! The parser does not execute scripts itself. Instead, every `<script>` element that is
+//! inserted by the parser is "prepared": its type and timing are determined, and it is handed
+//! over to a [`ScriptHandler`] (normally the javascript runtime, but this can be any Rust
+//! callback) at the right moment:
+//!
+//! - parser-blocking scripts (inline scripts, and external scripts without async or defer) are
+//!   executed when their end tag is found. Tree construction is paused until the handler returns.
+//! - deferred scripts (defer attribute, and modules without async) are executed in document
+//!   order after the whole document has been parsed.
+//! - async scripts are handed to the handler directly, and should be executed as soon as they
+//!   are fetched.
+//!
+//! Scripts that are not allowed by the content security policy of the document are never handed
+//! to the handler.
+//!
+//! See: https://html.spec.whatwg.org/multipage/scripting.html#prepare-the-script-element
+use crate::html5::node::NodeId;
+use crate::html5::parser::document::DocumentHandle;
+use crate::html5::parser::Html5Parser;
+use crate::net::csp::ResourceKind;
+use url::Url;
+
+/// Mime types (essences) that define a classic javascript script
+const JAVASCRIPT_MIME_TYPES: [&str; 16] = [
+    "application/ecmascript",
+    "application/javascript",
+    "application/x-ecmascript",
+    "application/x-javascript",
+    "text/ecmascript",
+    "text/javascript",
+    "text/javascript1.0",
+    "text/javascript1.1",
+    "text/javascript1.2",
+    "text/javascript1.3",
+    "text/javascript1.4",
+    "text/javascript1.5",
+    "text/jscript",
+    "text/livescript",
+    "text/x-ecmascript",
+    "text/x-javascript",
+];
+
+/// Type of script
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ScriptKind {
+    /// Classic javascript
+    Classic,
+    /// Javascript module (type="module")
+    Module,
+}
+
+/// Defines when a script should be executed
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ScriptTiming {
+    /// Executed directly; parsing is paused until the script has been executed
+    ParserBlocking,
+    /// Executed in document order after parsing has finished
+    Defer,
+    /// Executed as soon as the script is fetched, independent of the parser
+    Async,
+}
+
+/// Source of a script
+#[derive(Debug, Clone, PartialEq)]
+pub enum ScriptSource {
+    /// Contents of an inline script element
+    Inline(String),
+    /// External script as found in the src attribute
+    External {
+        src: String,
+        /// Location resolved against the base URL of the document
+        url: Option<Url>,
+    },
+}
+
+/// A prepared script element that is ready to be executed
+#[derive(Debug, Clone, PartialEq)]
+pub struct Script {
+    /// The script element
+    pub node_id: NodeId,
+    pub kind: ScriptKind,
+    pub timing: ScriptTiming,
+    pub source: ScriptSource,
+}
+
+/// Hook that executes scripts found by the parser
+pub trait ScriptHandler {
+    /// Executes the given script. For external scripts, the handler is responsible for fetching
+    /// the source. When the script is parser-blocking, tree construction resumes after this
+    /// function returns.
+    fn execute(&mut self, document: &DocumentHandle, script: &Script);
+
+    /// Called for async scripts when they are found. The script should be executed as soon as
+    /// its source has been fetched, which is up to the handler. By default, it is executed
+    /// right away.
+    fn schedule_async(&mut self, document: &DocumentHandle, script: &Script) {
+        self.execute(document, script);
+    }
+}
+
+impl Html5Parser<'_> {
+    /// Prepares the script element and returns the script when it should be executed
+    pub(crate) fn prepare_script(&self, node_id: NodeId) -> Option<Script> {
+        if !self.scripting_enabled || self.is_fragment_case {
+            return None;
+        }
+
+        let doc = self.document.get();
+        let node = doc.get_node_by_id(node_id)?;
+
+        let kind = match node.get_attribute("type").map(|t| t.trim()) {
+            None | Some("") => ScriptKind::Classic,
+            Some(t) if t.eq_ignore_ascii_case("module") => ScriptKind::Module,
+            Some(t) if JAVASCRIPT_MIME_TYPES.contains(&t.to_ascii_lowercase().as_str()) => {
+                ScriptKind::Classic
+            }
+            // Data blocks and unknown script types are never executed
+            Some(_) => return None,
+        };
+
+        // Browsers that support modules ignore classic scripts with the nomodule attribute
+        if kind == ScriptKind::Classic && node.get_attribute("nomodule").is_some() {
+            return None;
+        }
+
+        let is_async = node.get_attribute("async").is_some();
+        let is_defer = node.get_attribute("defer").is_some();
+
+        let (source, timing) = match node.get_attribute("src") {
+            Some(src) => {
+                if src.trim().is_empty() {
+                    return None;
+                }
+                let url = doc.resolve_url(src);
+                if url
+                    .as_ref()
+                    .is_some_and(|url| !doc.allows_resource(url, ResourceKind::Script))
+                {
+                    return None;
+                }
+                let source = ScriptSource::External {
+                    src: src.clone(),
+                    url,
+                };
+
+                let timing = match kind {
+                    _ if is_async => ScriptTiming::Async,
+                    ScriptKind::Module => ScriptTiming::Defer,
+                    ScriptKind::Classic if is_defer => ScriptTiming::Defer,
+                    ScriptKind::Classic => ScriptTiming::ParserBlocking,
+                };
+                (source, timing)
+            }
+            None => {
+                let text = doc.text_content(node_id);
+                if text.is_empty() || !doc.allows_inline(ResourceKind::Script, node_id) {
+                    return None;
+                }
+
+                // The defer attribute has no effect on inline scripts
+                let timing = match kind {
+                    ScriptKind::Module if is_async => ScriptTiming::Async,
+                    ScriptKind::Module => ScriptTiming::Defer,
+                    ScriptKind::Classic => ScriptTiming::ParserBlocking,
+                };
+                (ScriptSource::Inline(text), timing)
+            }
+        };
+
+        Some(Script {
+            node_id,
+            kind,
+            timing,
+            source,
+        })
+    }
+
+    /// Prepares the script element and executes it, or queues it for later execution
+    pub(crate) fn run_script(&mut self, node_id: NodeId) {
+        let Some(script) = self.prepare_script(node_id) else {
+            return;
+        };
+        let Some(handler) = self.script_handler.clone() else {
+            return;
+        };
+
+        match script.timing {
+            ScriptTiming::ParserBlocking => handler.borrow_mut().execute(&self.document, &script),
+            ScriptTiming::Async => handler.borrow_mut().schedule_async(&self.document, &script),
+            ScriptTiming::Defer => self.deferred_scripts.push(script),
+        }
+    }
+
+    /// Executes all deferred scripts in document order. Called when parsing has finished.
+    pub(crate) fn run_deferred_scripts(&mut self) {
+        let scripts = std::mem::take(&mut self.deferred_scripts);
+        let Some(handler) = self.script_handler.clone() else {
+            return;
+        };
+
+        for script in scripts {
+            handler.borrow_mut().execute(&self.document, &script);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bytes::{CharIterator, Encoding};
+    use crate::html5::parser::document::{Document, DocumentBuilder};
+    use crate::html5::parser::Html5ParserOptions;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    /// Records the executed scripts together with the number of nodes in the document at the
+    /// moment of execution
+    #[derive(Default)]
+    struct Recorder {
+        executed: Vec<(String, usize)>,
+    }
+
+    impl ScriptHandler for Recorder {
+        fn execute(&mut self, document: &DocumentHandle, script: &Script) {
+            let name = match &script.source {
+                ScriptSource::Inline(text) => text.clone(),
+                ScriptSource::External { src, .. } => src.clone(),
+            };
+            self.executed
+                .push((name, document.get().arena.count_nodes()));
+        }
+    }
+
+    fn parse(html: &str, scripting_enabled: bool) -> Vec<(String, usize)> {
+        let mut chars = CharIterator::new();
+        chars.read_from_str(html, Some(Encoding::UTF8));
+
+        let recorder = Rc::new(RefCell::new(Recorder::default()));
+        let options = Html5ParserOptions {
+            scripting_enabled,
+            script_handler: Some(recorder.clone()),
+            ..Default::default()
+        };
+
+        let document = DocumentBuilder::new_document();
+        let _ = Html5Parser::parse_document(&mut chars, Document::clone(&document), Some(options));
+
+        let executed = recorder.borrow().executed.clone();
+        executed
+    }
+
+    #[test]
+    fn script_ordering() {
+        let executed = parse(
+            r#"<html><head>
+            <script defer src="deferred.js"></script>
+            <script>inline</script>
+            <script async src="async.js"></script>
+            <script type="module">module</script>
+            <script src="blocking.js"></script>
+            <script type="application/ld+json">{}</script>
+            <script nomodule src="legacy.js"></script>
+            </head><body><p>content</p></body></html>"#,
+            true,
+        );
+
+        let names = executed.iter().map(|(n, _)| n.as_str()).collect::<Vec<_>>();
+        assert_eq!(
+            names,
+            vec!["inline", "async.js", "blocking.js", "deferred.js", "module"]
+        );
+
+        // Blocking scripts run before the rest of the document is parsed, deferred scripts after
+        assert!(executed[0].1 < executed[2].1);
+        assert!(executed[2].1 < executed[3].1);
+        assert_eq!(executed[3].1, executed[4].1);
+    }
+
+    #[test]
+    fn scripting_disabled() {
+        let executed = parse("<script>inline</script><script src=a.js></script>", false);
+        assert!(executed.is_empty());
+    }
+}
@@ -14,14 +14,27 @@ pub trait TreeBuilder {
         parent_id: NodeId,
         position: Option<usize>,
         namespace: &str,
-    ) -> NodeId;
+    ) -> Result<NodeId>;
 
     /// Create a new text node with the given content and append it to a parent.
-    fn create_text(&mut self, content: &str, parent_id: NodeId);
+    fn create_text(&mut self, content: &str, parent_id: NodeId) -> Result<()>;
 
     /// Create a new comment node with the given content and append it to a parent.
-    fn create_comment(&mut self, content: &str, parent_id: NodeId);
+    fn create_comment(&mut self, content: &str, parent_id: NodeId) -> Result<()>;
 
     /// Insert/update an attribute for an element node.
     fn insert_attribute(&mut self, key: &str, value: &str, element_id: NodeId) -> Result<()>;
+
+    /// Move a node into the parent of the sibling, right before the sibling.
+    fn insert_before(&mut self, node_id: NodeId, sibling_id: NodeId) -> Result<()>;
+
+    /// Foster parent a node that is misplaced inside a table: it is inserted right before the
+    /// table, or appended to the fallback parent (the element before the table in the stack of
+    /// open elements) when the table has no parent. Text nodes are not merged with adjacent text.
+    fn foster_parent(
+        &mut self,
+        node_id: NodeId,
+        table_id: NodeId,
+        fallback_parent_id: NodeId,
+    ) -> Result<()>;
 }
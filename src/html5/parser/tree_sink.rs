@@ -0,0 +1,309 @@
+//! Tree sink
+//!
+//! The parser always builds its own [`Document`](crate::html5::parser::document::Document), as
+//! the tree construction algorithm needs to inspect the tree it is building. Next to that, every
+//! change the parser makes to the tree is reported to an optional [`TreeSink`]. This allows
+//! alternative DOM backends to build their own tree from the parse output, and tests to record
+//! exactly what the parser did.
+//!
+//! Nodes are identified by the node ids of the parser's document. The document (root) node
+//! always exists and has [`NodeId::root()`] as its id.
+use crate::html5::node::{NodeData, NodeId, HTML_NAMESPACE};
+use crate::html5::parser::quirks::QuirksMode;
+use crate::html5::parser::Html5Parser;
+use std::collections::HashMap;
+
+/// Form-associated elements, which are associated with the form element pointer when created
+const FORM_ASSOCIATED_ELEMENTS: [&str; 8] = [
+    "button", "fieldset", "img", "input", "object", "output", "select", "textarea",
+];
+
+/// Form-associated elements that can override their form owner with a form attribute
+const LISTED_ELEMENTS: [&str; 7] = [
+    "button", "fieldset", "input", "object", "output", "select", "textarea",
+];
+
+/// Receives all tree mutations done by the parser
+pub trait TreeSink {
+    /// A new element has been created. It is not yet attached to the tree.
+    fn create_element(
+        &mut self,
+        node_id: NodeId,
+        name: &str,
+        namespace: &str,
+        attributes: &HashMap<String, String>,
+    );
+
+    /// A new text node has been created. It is not yet attached to the tree.
+    fn create_text(&mut self, node_id: NodeId, text: &str);
+
+    /// Text has been added to the end of an existing text node
+    fn append_text(&mut self, node_id: NodeId, text: &str);
+
+    /// A new comment node has been created. It is not yet attached to the tree.
+    fn create_comment(&mut self, node_id: NodeId, text: &str);
+
+    /// A new doctype node has been created. It is not yet attached to the tree.
+    fn create_doctype(
+        &mut self,
+        node_id: NodeId,
+        name: &str,
+        pub_identifier: &str,
+        sys_identifier: &str,
+    );
+
+    /// The child is appended to the parent, or inserted before the given sibling
+    fn append_child(&mut self, parent_id: NodeId, child_id: NodeId, before: Option<NodeId>);
+
+    /// The node is removed from its parent (but may be appended somewhere else later)
+    fn remove_from_parent(&mut self, node_id: NodeId);
+
+    /// All children of the node are moved to the end of the new parent
+    fn reparent_children(&mut self, node_id: NodeId, new_parent_id: NodeId);
+
+    /// The attributes that the element did not have yet have been added to it
+    fn add_attributes_if_missing(&mut self, node_id: NodeId, attributes: &HashMap<String, String>);
+
+    /// The element is a template. The nodes that are appended to it are part of its template
+    /// contents, not of its children.
+    fn template_contents(&mut self, _template_id: NodeId) {}
+
+    /// The element is associated with the given form element (its form owner)
+    fn associate_with_form(&mut self, _node_id: NodeId, _form_id: NodeId) {}
+
+    /// The quirks mode of the document has been set
+    fn set_quirks_mode(&mut self, _mode: QuirksMode) {}
+}
+
+impl Html5Parser<'_> {
+    /// Calls the tree sink (if any)
+    pub(crate) fn notify_tree_sink(&self, f: impl FnOnce(&mut dyn TreeSink)) {
+        if let Some(tree_sink) = &self.tree_sink {
+            f(&mut *tree_sink.borrow_mut());
+        }
+    }
+
+    /// Reports the creation of the given (unattached) node to the tree sink
+    pub(crate) fn notify_node_created(&self, node_id: NodeId) {
+        if self.tree_sink.is_none() {
+            return;
+        }
+
+        let document = self.document.get();
+        let Some(node) = document.get_node_by_id(node_id) else {
+            return;
+        };
+
+        self.notify_tree_sink(|sink| match &node.data {
+            NodeData::Element(element) => sink.create_element(
+                node_id,
+                &node.name,
+                node.namespace.as_deref().unwrap_or_default(),
+                &element.attributes,
+            ),
+            NodeData::Text(text) => sink.create_text(node_id, text.value()),
+            NodeData::Comment(comment) => sink.create_comment(node_id, comment.value()),
+            NodeData::DocType(doctype) => sink.create_doctype(
+                node_id,
+                &doctype.name,
+                &doctype.pub_identifier,
+                &doctype.sys_identifier,
+            ),
+            NodeData::Document(_) => {}
+        });
+    }
+
+    /// Associates a newly created form-associated element with the form element pointer
+    ///
+    /// See: https://html.spec.whatwg.org/multipage/parsing.html#create-an-element-for-the-token
+    pub(crate) fn associate_with_form_element(&self, node_id: NodeId) {
+        let Some(form_id) = self.form_element else {
+            return;
+        };
+        if self.tree_sink.is_none() || self.open_elements_has("template") {
+            return;
+        }
+
+        let document = self.document.get();
+        let Some(node) = document.get_node_by_id(node_id) else {
+            return;
+        };
+        if !node.is_namespace(HTML_NAMESPACE)
+            || !FORM_ASSOCIATED_ELEMENTS.contains(&node.name.as_str())
+        {
+            return;
+        }
+        if LISTED_ELEMENTS.contains(&node.name.as_str()) && node.get_attribute("form").is_some() {
+            return;
+        }
+
+        self.notify_tree_sink(|sink| sink.associate_with_form(node_id, form_id));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bytes::{CharIterator, Encoding};
+    use crate::html5::parser::document::{Document, DocumentBuilder};
+    use crate::html5::parser::Html5ParserOptions;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    #[derive(Default)]
+    struct Recorder {
+        log: Vec<String>,
+    }
+
+    impl TreeSink for Recorder {
+        fn create_element(
+            &mut self,
+            node_id: NodeId,
+            name: &str,
+            _namespace: &str,
+            attributes: &HashMap<String, String>,
+        ) {
+            let mut attributes: Vec<_> = attributes.iter().collect();
+            attributes.sort();
+            self.log
+                .push(format!("element {} {} {:?}", node_id, name, attributes));
+        }
+
+        fn create_text(&mut self, node_id: NodeId, text: &str) {
+            self.log.push(format!("text {} {:?}", node_id, text));
+        }
+
+        fn append_text(&mut self, node_id: NodeId, text: &str) {
+            self.log.push(format!("append text {} {:?}", node_id, text));
+        }
+
+        fn create_comment(&mut self, node_id: NodeId, text: &str) {
+            self.log.push(format!("comment {} {:?}", node_id, text));
+        }
+
+        fn create_doctype(&mut self, node_id: NodeId, name: &str, _: &str, _: &str) {
+            self.log.push(format!("doctype {} {}", node_id, name));
+        }
+
+        fn append_child(&mut self, parent_id: NodeId, child_id: NodeId, before: Option<NodeId>) {
+            match before {
+                Some(before) => self.log.push(format!(
+                    "insert {} in {} before {}",
+                    child_id, parent_id, before
+                )),
+                None => self
+                    .log
+                    .push(format!("append {} to {}", child_id, parent_id)),
+            }
+        }
+
+        fn remove_from_parent(&mut self, node_id: NodeId) {
+            self.log.push(format!("remove {}", node_id));
+        }
+
+        fn reparent_children(&mut self, node_id: NodeId, new_parent_id: NodeId) {
+            self.log
+                .push(format!("reparent {} to {}", node_id, new_parent_id));
+        }
+
+        fn add_attributes_if_missing(
+            &mut self,
+            node_id: NodeId,
+            attributes: &HashMap<String, String>,
+        ) {
+            let mut attributes: Vec<_> = attributes.iter().collect();
+            attributes.sort();
+            self.log
+                .push(format!("attributes {} {:?}", node_id, attributes));
+        }
+
+        fn template_contents(&mut self, template_id: NodeId) {
+            self.log.push(format!("template {}", template_id));
+        }
+
+        fn associate_with_form(&mut self, node_id: NodeId, form_id: NodeId) {
+            self.log.push(format!("form {} {}", node_id, form_id));
+        }
+
+        fn set_quirks_mode(&mut self, mode: QuirksMode) {
+            self.log.push(format!("quirks {:?}", mode));
+        }
+    }
+
+    fn record(html: &str) -> Vec<String> {
+        let mut chars = CharIterator::new();
+        chars.read_from_str(html, Some(Encoding::UTF8));
+
+        let recorder = Rc::new(RefCell::new(Recorder::default()));
+        let options = Html5ParserOptions {
+            tree_sink: Some(recorder.clone()),
+            ..Default::default()
+        };
+
+        let document = DocumentBuilder::new_document();
+        let _ = Html5Parser::parse_document(&mut chars, Document::clone(&document), Some(options));
+
+        let log = recorder.borrow().log.clone();
+        log
+    }
+
+    #[test]
+    fn records_tree_construction() {
+        assert_eq!(
+            record("<!DOCTYPE html><!--c--><p id=a>x<b>y</b>z</p><body class=c>"),
+            vec![
+                "doctype 1 html",
+                "append 1 to 0",
+                "quirks NoQuirks",
+                "comment 2 \"c\"",
+                "append 2 to 0",
+                "element 3 html []",
+                "append 3 to 0",
+                "element 4 head []",
+                "append 4 to 3",
+                "element 5 body []",
+                "append 5 to 3",
+                "element 6 p [(\"id\", \"a\")]",
+                "append 6 to 5",
+                "text 7 \"x\"",
+                "append 7 to 6",
+                "element 8 b []",
+                "append 8 to 6",
+                "text 9 \"y\"",
+                "append 9 to 8",
+                "text 10 \"z\"",
+                "append 10 to 6",
+                "attributes 5 [(\"class\", \"c\")]",
+            ]
+        );
+    }
+
+    #[test]
+    fn records_adoption_agency() {
+        let log = record("<b>1<p>2</b>3");
+        let start = log.iter().position(|l| l == "text 7 \"2\"").unwrap();
+        assert_eq!(
+            log[start..],
+            vec![
+                "text 7 \"2\"",
+                "append 7 to 6",
+                "remove 6",
+                "append 6 to 3",
+                "element 8 b []",
+                "reparent 6 to 8",
+                "append 8 to 6",
+                "text 9 \"3\"",
+                "append 9 to 6",
+            ]
+        );
+    }
+
+    #[test]
+    fn records_form_association_and_templates() {
+        let log = record("<form id=f><input><input form=other><template><input></template>");
+        assert!(log.contains(&"form 5 4".to_string()));
+        assert!(!log.iter().any(|l| l.starts_with("form 6 ")));
+        assert!(log.contains(&"template 7".to_string()));
+        assert!(!log.iter().any(|l| l.starts_with("form 8 ")));
+    }
+}
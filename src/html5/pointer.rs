@@ -0,0 +1,571 @@
+//! Pointer events
+//!
+//! Mouse, pen and touch input all arrive as pointer input from the embedder: a pointer (with an
+//! id, a type and a position) goes down, moves and goes up. The node the input is for is found
+//! through the `HitTester` of the embedder, which knows where everything was laid out. Without a
+//! hit tester, all input goes to the document.
+//!
+//! A pointer can be captured by a node, after which all its events go to that node, wherever the
+//! pointer is. Touch pointers are captured implicitly by the node they went down on. A pointer
+//! going down moves the focus to the (nearest focusable ancestor of the) node, unless the
+//! `pointerdown` event is canceled.
+//!
+//! Moves are not dispatched right away: input devices (touch screens especially) report far more
+//! moves than a page can handle. They are collected per pointer, and `flush_pointer_moves()` (which
+//! the embedder calls once per frame) fires a single `pointermove` per pointer, carrying all the
+//! collected positions as its coalesced events.
+//!
+//! See: https://w3c.github.io/pointerevents/
+use crate::html5::compaction::{remap, NodeIdMap};
+use crate::html5::events::{Event, EventDetail};
+use crate::html5::node::NodeId;
+use crate::html5::parser::document::{Document, DocumentHandle};
+use crate::types::{Error, Result};
+use core::fmt;
+use core::fmt::Debug;
+use std::rc::Rc;
+
+/// Kind of device a pointer is
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PointerType {
+    Mouse,
+    Pen,
+    Touch,
+}
+
+impl PointerType {
+    /// Returns the pointer type as used in the `pointerType` attribute
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PointerType::Mouse => "mouse",
+            PointerType::Pen => "pen",
+            PointerType::Touch => "touch",
+        }
+    }
+}
+
+/// Pointer input as reported by the embedder
+#[derive(Debug, Clone, PartialEq)]
+pub struct PointerInput {
+    /// Id of the pointer, which stays the same from down to up (e.g. one per finger)
+    pub pointer_id: u32,
+    pub pointer_type: PointerType,
+    /// Position of the pointer in the viewport
+    pub x: f64,
+    pub y: f64,
+    /// Buttons that are pressed, as a bitmask (1 is the primary button, or a touch contact)
+    pub buttons: u16,
+    /// Pressure of the pointer, between 0.0 and 1.0
+    pub pressure: f32,
+}
+
+impl PointerInput {
+    /// Creates pointer input at the given position, without any buttons pressed
+    pub fn new(pointer_id: u32, pointer_type: PointerType, x: f64, y: f64) -> Self {
+        Self {
+            pointer_id,
+            pointer_type,
+            x,
+            y,
+            buttons: 0,
+            pressure: 0.0,
+        }
+    }
+}
+
+/// Extra data of a pointer event
+#[derive(Debug, Clone, PartialEq)]
+pub struct PointerDetail {
+    /// The (last) input of the pointer
+    pub input: PointerInput,
+    /// True for the first pointer of its type that is active (e.g. the first finger)
+    pub is_primary: bool,
+    /// All the moves that were coalesced into this event, in order
+    pub coalesced: Vec<PointerInput>,
+}
+
+/// Finds the node at a position in the viewport, implemented by the embedder
+pub trait HitTester {
+    /// Returns the topmost node at the position, if any
+    fn hit_test(&self, x: f64, y: f64) -> Option<NodeId>;
+}
+
+/// A pointer that is known to the document
+#[derive(Debug, Clone, PartialEq)]
+struct ActivePointer {
+    pointer_id: u32,
+    pointer_type: PointerType,
+    is_primary: bool,
+    /// Node the pointer is currently over
+    hovered: Option<NodeId>,
+    /// Node that has captured the pointer
+    capture: Option<NodeId>,
+    /// Moves that have not been dispatched yet
+    pending_moves: Vec<PointerInput>,
+}
+
+/// Pointer state of a document
+#[derive(Default)]
+pub(crate) struct Pointers {
+    hit_tester: Option<Rc<dyn HitTester>>,
+    active: Vec<ActivePointer>,
+}
+
+impl Debug for Pointers {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Pointers")
+            .field("hit_tester", &self.hit_tester.is_some())
+            .field("active", &self.active)
+            .finish()
+    }
+}
+
+impl PartialEq for Pointers {
+    fn eq(&self, other: &Self) -> bool {
+        let same_hit_tester = match (&self.hit_tester, &other.hit_tester) {
+            (Some(a), Some(b)) => std::ptr::eq(Rc::as_ptr(a).cast::<()>(), Rc::as_ptr(b).cast()),
+            (a, b) => a.is_none() && b.is_none(),
+        };
+        same_hit_tester && self.active == other.active
+    }
+}
+
+impl Pointers {
+    /// Replaces the hovered and capturing nodes of the pointers by their new IDs
+    pub(crate) fn remap_nodes(&mut self, ids: &NodeIdMap) {
+        for pointer in &mut self.active {
+            pointer.hovered = pointer.hovered.and_then(|node_id| remap(ids, node_id));
+            pointer.capture = pointer.capture.and_then(|node_id| remap(ids, node_id));
+        }
+    }
+
+    /// Forgets the node as the hovered or capturing node of the pointers, as it has been removed
+    /// from the document
+    pub(crate) fn forget_node(&mut self, node_id: NodeId) {
+        for pointer in &mut self.active {
+            pointer.hovered = pointer.hovered.filter(|&id| id != node_id);
+            pointer.capture = pointer.capture.filter(|&id| id != node_id);
+        }
+    }
+
+    fn get(&self, pointer_id: u32) -> Option<&ActivePointer> {
+        self.active.iter().find(|p| p.pointer_id == pointer_id)
+    }
+
+    fn get_mut(&mut self, pointer_id: u32) -> Option<&mut ActivePointer> {
+        self.active.iter_mut().find(|p| p.pointer_id == pointer_id)
+    }
+
+    /// Returns the pointer of the input, and starts tracking it when it is new
+    fn track(&mut self, input: &PointerInput) -> &mut ActivePointer {
+        if let Some(index) = self
+            .active
+            .iter()
+            .position(|p| p.pointer_id == input.pointer_id)
+        {
+            return &mut self.active[index];
+        }
+
+        let is_primary = !self
+            .active
+            .iter()
+            .any(|p| p.pointer_type == input.pointer_type && p.is_primary);
+        self.active.push(ActivePointer {
+            pointer_id: input.pointer_id,
+            pointer_type: input.pointer_type,
+            is_primary,
+            hovered: None,
+            capture: None,
+            pending_moves: Vec::new(),
+        });
+        self.active.last_mut().expect("pointer was just added")
+    }
+
+    fn untrack(&mut self, pointer_id: u32) {
+        self.active.retain(|p| p.pointer_id != pointer_id);
+    }
+}
+
+impl Document {
+    /// Sets the hit tester that finds the target nodes of pointer input
+    pub fn set_hit_tester(&mut self, hit_tester: Rc<dyn HitTester>) {
+        self.pointers.hit_tester = Some(hit_tester);
+    }
+
+    /// Returns true when the node has captured the pointer
+    pub fn has_pointer_capture(&self, node_id: NodeId, pointer_id: u32) -> bool {
+        self.pointers
+            .get(pointer_id)
+            .is_some_and(|p| p.capture == Some(node_id))
+    }
+
+    /// Returns the ids of the pointers the document currently knows about
+    pub fn active_pointers(&self) -> Vec<u32> {
+        self.pointers.active.iter().map(|p| p.pointer_id).collect()
+    }
+
+    /// Returns the node the input is for: the node that captured the pointer, or the node at the
+    /// position of the input
+    fn pointer_target(&self, input: &PointerInput) -> NodeId {
+        if let Some(capture) = self.pointers.get(input.pointer_id).and_then(|p| p.capture) {
+            return capture;
+        }
+        self.hit_test(input.x, input.y)
+    }
+
+    /// Returns the node at the position in the viewport according to the hit tester, or the
+    /// document when there is none
+    pub(crate) fn hit_test(&self, x: f64, y: f64) -> NodeId {
+        self.pointers
+            .hit_tester
+            .as_ref()
+            .and_then(|hit_tester| hit_tester.hit_test(x, y))
+            .filter(|node_id| self.is_connected(*node_id))
+            .unwrap_or(NodeId::root())
+    }
+}
+
+impl DocumentHandle {
+    /// Handles a pointer going down (a button press, or a finger touching the screen). Returns
+    /// false when the `pointerdown` event was canceled.
+    pub fn pointer_down(&mut self, input: PointerInput) -> bool {
+        self.get_mut().pointers.track(&input);
+        self.flush_pending_moves(input.pointer_id);
+
+        let target = self.get().pointer_target(&input);
+        self.update_hover(&input, Some(target));
+        let not_canceled = self.fire_pointer_event("pointerdown", target, &input, Vec::new());
+        if not_canceled {
+            self.focus_from_activation(target);
+        }
+
+        if input.pointer_type == PointerType::Touch {
+            let _ = self.set_pointer_capture(input.pointer_id, target);
+        }
+        not_canceled
+    }
+
+    /// Handles a pointer moving. The move is dispatched on the next `flush_pointer_moves()`.
+    pub fn pointer_move(&mut self, input: PointerInput) {
+        self.get_mut()
+            .pointers
+            .track(&input)
+            .pending_moves
+            .push(input);
+    }
+
+    /// Dispatches the collected moves: a single `pointermove` event per pointer, with all its moves
+    /// as coalesced events
+    pub fn flush_pointer_moves(&mut self) {
+        let pointer_ids = self.get().active_pointers();
+        for pointer_id in pointer_ids {
+            self.flush_pending_moves(pointer_id);
+        }
+    }
+
+    /// Handles a pointer going up (a button release, or a finger leaving the screen). Returns
+    /// false when the `pointerup` event was canceled.
+    pub fn pointer_up(&mut self, input: PointerInput) -> bool {
+        self.get_mut().pointers.track(&input);
+        self.flush_pending_moves(input.pointer_id);
+
+        let target = self.get().pointer_target(&input);
+        let not_canceled = self.fire_pointer_event("pointerup", target, &input, Vec::new());
+        self.end_pointer(&input);
+        not_canceled
+    }
+
+    /// Handles the embedder taking over a pointer (e.g. for scrolling or a gesture), after which
+    /// no more events are fired for it
+    pub fn pointer_cancel(&mut self, pointer_id: u32) {
+        let Some(pointer) = self.get().pointers.get(pointer_id).cloned() else {
+            return;
+        };
+
+        // The last known input of the pointer
+        let input = pointer.pending_moves.last().cloned().unwrap_or_else(|| {
+            PointerInput::new(pointer_id, pointer.pointer_type, f64::NAN, f64::NAN)
+        });
+        let target = pointer
+            .capture
+            .or(pointer.hovered)
+            .unwrap_or(NodeId::root());
+
+        if let Some(p) = self.get_mut().pointers.get_mut(pointer_id) {
+            p.pending_moves.clear();
+        }
+        self.fire_pointer_event("pointercancel", target, &input, Vec::new());
+        self.end_pointer(&input);
+    }
+
+    /// Captures the pointer, so all its events go to the node. Fires `gotpointercapture`, and
+    /// `lostpointercapture` on the node that had captured the pointer before.
+    pub fn set_pointer_capture(&mut self, pointer_id: u32, node_id: NodeId) -> Result<()> {
+        let Some(pointer) = self.get().pointers.get(pointer_id).cloned() else {
+            return Err(Error::DocumentTask(format!(
+                "pointer {pointer_id} is not active"
+            )));
+        };
+        if !self.get().is_connected(node_id) {
+            return Err(Error::DocumentTask(format!(
+                "node {node_id} is not connected"
+            )));
+        }
+        if pointer.capture == Some(node_id) {
+            return Ok(());
+        }
+
+        let input = PointerInput::new(pointer_id, pointer.pointer_type, f64::NAN, f64::NAN);
+        if let Some(previous) = pointer.capture {
+            self.fire_pointer_event("lostpointercapture", previous, &input, Vec::new());
+        }
+        if let Some(p) = self.get_mut().pointers.get_mut(pointer_id) {
+            p.capture = Some(node_id);
+        }
+        self.fire_pointer_event("gotpointercapture", node_id, &input, Vec::new());
+        Ok(())
+    }
+
+    /// Releases the capture of the pointer by the node. Fires `lostpointercapture`.
+    pub fn release_pointer_capture(&mut self, pointer_id: u32, node_id: NodeId) {
+        let Some(pointer) = self.get().pointers.get(pointer_id).cloned() else {
+            return;
+        };
+        if pointer.capture != Some(node_id) {
+            return;
+        }
+
+        if let Some(p) = self.get_mut().pointers.get_mut(pointer_id) {
+            p.capture = None;
+        }
+        let input = PointerInput::new(pointer_id, pointer.pointer_type, f64::NAN, f64::NAN);
+        self.fire_pointer_event("lostpointercapture", node_id, &input, Vec::new());
+    }
+
+    /// Dispatches the collected moves of the pointer as a single `pointermove` event
+    fn flush_pending_moves(&mut self, pointer_id: u32) {
+        let moves = match self.get_mut().pointers.get_mut(pointer_id) {
+            Some(pointer) => std::mem::take(&mut pointer.pending_moves),
+            None => return,
+        };
+        let Some(input) = moves.last().cloned() else {
+            return;
+        };
+
+        let target = self.get().pointer_target(&input);
+        self.update_hover(&input, Some(target));
+        self.fire_pointer_event("pointermove", target, &input, moves);
+    }
+
+    /// Releases the capture of the pointer after it went up or was canceled. Touch pointers are
+    /// gone after that, so they leave the node they were over.
+    fn end_pointer(&mut self, input: &PointerInput) {
+        let capture = self
+            .get()
+            .pointers
+            .get(input.pointer_id)
+            .and_then(|p| p.capture);
+        if let Some(capture) = capture {
+            self.release_pointer_capture(input.pointer_id, capture);
+        }
+
+        if input.pointer_type == PointerType::Touch {
+            self.update_hover(input, None);
+            self.get_mut().pointers.untrack(input.pointer_id);
+        }
+    }
+
+    /// Fires `pointerout` and `pointerover` when the pointer moved to another node
+    fn update_hover(&mut self, input: &PointerInput, target: Option<NodeId>) {
+        let previous = match self.get_mut().pointers.get_mut(input.pointer_id) {
+            Some(pointer) => std::mem::replace(&mut pointer.hovered, target),
+            None => return,
+        };
+        if previous == target {
+            return;
+        }
+
+        if let Some(previous) = previous.filter(|id| self.get().is_connected(*id)) {
+            self.fire_pointer_event("pointerout", previous, input, Vec::new());
+        }
+        if let Some(target) = target {
+            self.fire_pointer_event("pointerover", target, input, Vec::new());
+        }
+    }
+
+    /// Fires a pointer event. Returns false when the event was canceled.
+    fn fire_pointer_event(
+        &self,
+        event_type: &str,
+        target: NodeId,
+        input: &PointerInput,
+        coalesced: Vec<PointerInput>,
+    ) -> bool {
+        let is_primary = self
+            .get()
+            .pointers
+            .get(input.pointer_id)
+            .is_some_and(|p| p.is_primary);
+
+        // The capture events and pointercancel cannot be canceled
+        let (bubbles, cancelable) = match event_type {
+            "gotpointercapture" | "lostpointercapture" | "pointercancel" => (true, false),
+            _ => (true, true),
+        };
+        let mut event = Event::new(event_type, bubbles, cancelable).with_detail(
+            EventDetail::Pointer(PointerDetail {
+                input: input.clone(),
+                is_primary,
+                coalesced,
+            }),
+        );
+        self.dispatch_event(target, &mut event)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::html5::events::EventCallback;
+    use crate::testing::parse;
+    use std::cell::RefCell;
+
+    /// Hit tester with two areas: x below 100 is the first div, the rest is the second div
+    struct Columns(NodeId, NodeId);
+
+    impl HitTester for Columns {
+        fn hit_test(&self, x: f64, _y: f64) -> Option<NodeId> {
+            Some(if x < 100.0 { self.0 } else { self.1 })
+        }
+    }
+
+    type Log = Rc<RefCell<Vec<String>>>;
+
+    /// Parses two divs, and logs the pointer events that reach the body
+    fn setup() -> (DocumentHandle, NodeId, NodeId, Log) {
+        let mut document = parse("<body><div id='a'></div><div id='b'></div></body>");
+
+        let (a, b, body) = {
+            let doc = document.get();
+            let divs = doc.elements_by_tag_name("div");
+            (divs[0], divs[1], doc.elements_by_tag_name("body")[0])
+        };
+        document.get_mut().set_hit_tester(Rc::new(Columns(a, b)));
+
+        let log: Log = Rc::new(RefCell::new(Vec::new()));
+        for event_type in [
+            "pointerdown",
+            "pointermove",
+            "pointerup",
+            "pointercancel",
+            "pointerover",
+            "pointerout",
+            "gotpointercapture",
+            "lostpointercapture",
+        ] {
+            let recorder = log.clone();
+            let callback: EventCallback = Rc::new(move |_, event| {
+                let EventDetail::Pointer(detail) = &event.detail else {
+                    panic!("pointer event without pointer detail");
+                };
+                let target = if event.target == Some(a) { "a" } else { "b" };
+                recorder.borrow_mut().push(format!(
+                    "{} {} {}",
+                    event.event_type,
+                    target,
+                    detail.coalesced.len()
+                ));
+            });
+            document
+                .get_mut()
+                .add_event_listener(body, event_type, callback, Default::default());
+        }
+        (document, a, b, log)
+    }
+
+    #[test]
+    fn mouse_events_follow_hit_testing() {
+        let (mut document, _, _, log) = setup();
+
+        document.pointer_move(PointerInput::new(1, PointerType::Mouse, 10.0, 0.0));
+        document.pointer_move(PointerInput::new(1, PointerType::Mouse, 150.0, 0.0));
+        assert!(log.borrow().is_empty());
+
+        document.flush_pointer_moves();
+        assert!(document.pointer_down(PointerInput::new(1, PointerType::Mouse, 20.0, 0.0)));
+        assert!(document.pointer_up(PointerInput::new(1, PointerType::Mouse, 20.0, 0.0)));
+        assert_eq!(
+            *log.borrow(),
+            [
+                "pointerover b 0",
+                "pointermove b 2",
+                "pointerout b 0",
+                "pointerover a 0",
+                "pointerdown a 0",
+                "pointerup a 0",
+            ]
+        );
+
+        // The mouse stays known after it goes up, touch pointers do not
+        assert_eq!(document.get().active_pointers(), [1]);
+    }
+
+    #[test]
+    fn touch_is_captured_and_coalesced() {
+        let (mut document, a, b, log) = setup();
+
+        document.pointer_down(PointerInput::new(7, PointerType::Touch, 10.0, 0.0));
+        assert!(document.get().has_pointer_capture(a, 7));
+
+        // The finger moves over the second div, but the events still go to the first one
+        for x in [50.0, 120.0, 180.0] {
+            document.pointer_move(PointerInput::new(7, PointerType::Touch, x, 0.0));
+        }
+        document.flush_pointer_moves();
+        document.pointer_up(PointerInput::new(7, PointerType::Touch, 180.0, 0.0));
+
+        assert_eq!(
+            *log.borrow(),
+            [
+                "pointerover a 0",
+                "pointerdown a 0",
+                "gotpointercapture a 0",
+                "pointermove a 3",
+                "pointerup a 0",
+                "lostpointercapture a 0",
+                "pointerout a 0",
+            ]
+        );
+        assert!(!document.get().has_pointer_capture(a, 7));
+        assert!(document.get().active_pointers().is_empty());
+
+        // Capturing needs an active pointer
+        assert!(document.set_pointer_capture(7, b).is_err());
+    }
+
+    #[test]
+    fn capture_and_cancel() {
+        let (mut document, a, b, log) = setup();
+
+        document.pointer_down(PointerInput::new(2, PointerType::Pen, 10.0, 0.0));
+        document.pointer_down(PointerInput::new(3, PointerType::Pen, 150.0, 0.0));
+        assert!(document.get().pointers.get(2).unwrap().is_primary);
+        assert!(!document.get().pointers.get(3).unwrap().is_primary);
+
+        document.set_pointer_capture(2, b).unwrap();
+        document.set_pointer_capture(2, a).unwrap();
+        document.pointer_move(PointerInput::new(2, PointerType::Pen, 150.0, 0.0));
+        document.pointer_cancel(2);
+
+        assert_eq!(
+            log.borrow()[4..],
+            [
+                "gotpointercapture b 0",
+                "lostpointercapture b 0",
+                "gotpointercapture a 0",
+                "pointercancel a 0",
+                "lostpointercapture a 0",
+            ]
+        );
+    }
+}
@@ -0,0 +1,250 @@
+//! Selector query cache
+//!
+//! Caches the results of selector queries on a document, keyed by the selector string. Read-heavy
+//! workloads (like scrapers running the same queries over and over) get repeated queries almost
+//! for free.
+//!
+//! Cached results are invalidated by the mutation records of the document, but only when the
+//! mutation could change them: a new text node does not affect `a[href]`, and changing the `class`
+//! of an element only affects selectors that look at classes. Note that nodes changed directly
+//! (through `get_node_by_id_mut`) do not generate mutation records, so the cache must be cleared
+//! manually after doing so.
+use crate::html5::node::NodeId;
+use crate::html5::parser::document::{Document, DocumentHandle, MutationRecord};
+use crate::html5::selector::{Dependencies, Selector};
+use crate::types::Result;
+use std::collections::HashMap;
+
+/// Cached results of selector queries
+#[derive(Debug, Default, PartialEq)]
+pub struct QueryCache {
+    entries: HashMap<String, CacheEntry>,
+}
+
+#[derive(Debug, PartialEq)]
+struct CacheEntry {
+    selector: Selector,
+    dependencies: Dependencies,
+    /// Matching elements in tree order
+    results: Vec<NodeId>,
+}
+
+impl CacheEntry {
+    /// Returns true when the mutation could change the results
+    fn is_affected_by(&self, document: &Document, record: &MutationRecord) -> bool {
+        match record {
+            // New nodes are not part of the tree yet
+            MutationRecord::NodeCreated(_) => false,
+            MutationRecord::ChildList { parent_id, node_id } => {
+                if !document.is_connected(*parent_id) {
+                    return false;
+                }
+                if self.dependencies.structural {
+                    return true;
+                }
+                // Only the added or removed elements themselves can start or stop matching
+                self.subtree_matches(document, *node_id)
+            }
+            MutationRecord::Attribute { node_id, name } => {
+                if !self.dependencies.attributes.contains(name) || !document.is_connected(*node_id)
+                {
+                    return false;
+                }
+                if self.dependencies.structural {
+                    return true;
+                }
+                // Only the element itself can start or stop matching
+                self.results.contains(node_id) || self.selector.matches(document, *node_id)
+            }
+            // Text only matters for `:empty`
+            MutationRecord::CharacterData(node_id) => {
+                self.dependencies.structural && document.is_connected(*node_id)
+            }
+        }
+    }
+
+    /// Returns true when the node or any of its descendants matches the selector
+    fn subtree_matches(&self, document: &Document, node_id: NodeId) -> bool {
+        let mut stack = vec![node_id];
+        while let Some(node_id) = stack.pop() {
+            if self.selector.matches(document, node_id) {
+                return true;
+            }
+            if let Some(node) = document.get_node_by_id(node_id) {
+                stack.extend(&node.children);
+            }
+        }
+        false
+    }
+}
+
+impl Document {
+    /// Returns the elements matching the selector, in tree order. The results are cached until a
+    /// mutation of the document could change them.
+    pub fn select_cached(&self, selector: &str) -> Result<Vec<NodeId>> {
+        if let Some(entry) = self.query_cache.borrow().entries.get(selector) {
+            return Ok(entry.results.clone());
+        }
+
+        let parsed = Selector::parse(selector)?;
+        let results: Vec<NodeId> = self.select(&parsed).map(|element| element.id()).collect();
+
+        self.query_cache.borrow_mut().entries.insert(
+            selector.to_string(),
+            CacheEntry {
+                dependencies: parsed.dependencies(),
+                selector: parsed,
+                results: results.clone(),
+            },
+        );
+
+        Ok(results)
+    }
+
+    /// Returns the first element matching the selector (like `querySelector()`), or None when no
+    /// element matches. Fails when the selector cannot be parsed.
+    pub fn query_selector(&self, selector: &str) -> Result<Option<NodeId>> {
+        Ok(self.select_cached(selector)?.first().copied())
+    }
+
+    /// Returns all elements matching the selector in tree order (like `querySelectorAll()`). Fails
+    /// when the selector cannot be parsed.
+    pub fn query_selector_all(&self, selector: &str) -> Result<Vec<NodeId>> {
+        self.select_cached(selector)
+    }
+
+    /// Removes all cached query results
+    pub fn clear_query_cache(&self) {
+        self.query_cache.borrow_mut().entries.clear();
+    }
+
+    /// Removes the cached query results that could be changed by the mutation
+    pub(crate) fn invalidate_query_cache(&mut self, record: &MutationRecord) {
+        let mut cache = self.query_cache.take();
+        if !cache.entries.is_empty() {
+            cache
+                .entries
+                .retain(|_, entry| !entry.is_affected_by(self, record));
+        }
+        self.query_cache.replace(cache);
+    }
+}
+
+impl DocumentHandle {
+    /// Returns the first element matching the selector. See `Document::query_selector()`.
+    pub fn query_selector(&self, selector: &str) -> Result<Option<NodeId>> {
+        self.get().query_selector(selector)
+    }
+
+    /// Returns all elements matching the selector. See `Document::query_selector_all()`.
+    pub fn query_selector_all(&self, selector: &str) -> Result<Vec<NodeId>> {
+        self.get().query_selector_all(selector)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::html5::node::{Node, HTML_NAMESPACE};
+    use crate::html5::parser::document::DocumentHandle;
+    use crate::html5::parser::tree_builder::TreeBuilder;
+    use crate::testing::parse;
+
+    fn is_cached(document: &DocumentHandle, selector: &str) -> bool {
+        document
+            .get()
+            .query_cache
+            .borrow()
+            .entries
+            .contains_key(selector)
+    }
+
+    #[test]
+    fn caches_results() {
+        let document = parse("<a href=1>1</a><a>2</a><a href=3>3</a>");
+        let doc = document.get();
+
+        let links = doc.select_cached("a[href]").unwrap();
+        assert_eq!(links.len(), 2);
+        assert!(is_cached(&document, "a[href]"));
+        assert_eq!(doc.select_cached("a[href]").unwrap(), links);
+
+        assert!(doc.select_cached("a[").is_err());
+        assert!(!is_cached(&document, "a["));
+
+        doc.clear_query_cache();
+        assert!(!is_cached(&document, "a[href]"));
+    }
+
+    #[test]
+    fn invalidated_by_relevant_mutations_only() {
+        let mut document = parse("<ul><li><a href=1>1</a></ul><p id=p><a>2</a></p>");
+        let (p, unlinked) = {
+            let doc = document.get();
+            (
+                doc.get_node_by_named_id("p").unwrap().id,
+                doc.elements_by_tag_name("a")[1],
+            )
+        };
+        for selector in ["a[href]", "li > a", "p"] {
+            document.get().select_cached(selector).unwrap();
+        }
+
+        // Text does not affect any of them, except the structural selector
+        document.create_text("text", p).unwrap();
+        assert!(is_cached(&document, "a[href]"));
+        assert!(is_cached(&document, "p"));
+        assert!(!is_cached(&document, "li > a"));
+        document.get().select_cached("li > a").unwrap();
+
+        // Nodes that are not connected do not affect anything
+        let div = document.get_mut().add_new_node(Node::new_element(
+            "div",
+            HashMap::new(),
+            HTML_NAMESPACE,
+        ));
+        document
+            .create_element("p", div, None, HTML_NAMESPACE)
+            .unwrap();
+        assert!(is_cached(&document, "p"));
+
+        // A matching element is added
+        document.relocate(div, p);
+        assert!(!is_cached(&document, "p"));
+        assert!(is_cached(&document, "a[href]"));
+        assert_eq!(document.get().select_cached("p").unwrap().len(), 2);
+
+        // Attributes only affect selectors that depend on them
+        document.insert_attribute("title", "x", unlinked).unwrap();
+        assert!(is_cached(&document, "a[href]"));
+        document.insert_attribute("href", "2", unlinked).unwrap();
+        assert!(!is_cached(&document, "a[href]"));
+        assert!(is_cached(&document, "p"));
+        assert_eq!(document.get().select_cached("a[href]").unwrap().len(), 2);
+    }
+
+    #[test]
+    fn query_selector() {
+        let document = parse(
+            r#"<ul id=list><li class="item active">1<li class=item>2</ul><p class=item>3</p>"#,
+        );
+        let (items, active) = {
+            let doc = document.get();
+            let items = doc.elements_by_tag_name("li");
+            (items.clone(), items[0])
+        };
+
+        assert_eq!(document.query_selector("li.active").unwrap(), Some(active));
+        assert_eq!(
+            document.query_selector("#list > .item").unwrap(),
+            Some(active)
+        );
+        assert_eq!(document.query_selector("li:not(.item)").unwrap(), None);
+        assert_eq!(document.query_selector_all("ul .item").unwrap(), items);
+        assert_eq!(document.query_selector_all(".item").unwrap().len(), 3);
+        assert_eq!(document.query_selector_all("p, li").unwrap().len(), 3);
+
+        assert!(document.query_selector("li..item").is_err());
+        assert!(document.query_selector_all("").is_err());
+    }
+}
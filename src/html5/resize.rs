@@ -0,0 +1,363 @@
+//! Resize observers
+//!
+//! A resize observer is notified when the size of the border box of one of its target elements
+//! changes between layouts. Observations are delivered after layout, as part of updating the
+//! rendering: `deliver_resize_observations()` invokes the callbacks with the targets whose size
+//! changed since they were last reported. The first observation of a target is reported unless
+//! its size is zero.
+//!
+//! Callbacks can change the layout again, so delivery repeats, but only for targets that are
+//! deeper in the tree than the ones reported before. That ends the loop, even when the callbacks
+//! keep changing sizes. Changes that are left over are reported with an `error` event on the
+//! document, and delivered on the next update.
+//!
+//! See: https://drafts.csswg.org/resize-observer/
+use crate::html5::compaction::{remap, NodeIdMap};
+use crate::html5::events::Event;
+use crate::html5::geometry::Rect;
+use crate::html5::node::NodeId;
+use crate::html5::parser::document::{Document, DocumentHandle};
+use crate::types::{Error, Result};
+use core::fmt;
+use core::fmt::Debug;
+use std::rc::Rc;
+
+/// Callback that is invoked with the targets of an observer that were resized
+pub type ResizeCallback = Rc<dyn Fn(&DocumentHandle, &[ResizeEntry])>;
+
+/// Id of a resize observer in a document
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ResizeObserverId(usize);
+
+/// A target that was resized
+#[derive(Debug, Clone, PartialEq)]
+pub struct ResizeEntry {
+    pub target: NodeId,
+    /// Border box of the target after the resize
+    pub border_box: Rect,
+}
+
+#[derive(PartialEq)]
+struct Observation {
+    target: NodeId,
+    /// Size (width, height) of the border box when it was last reported
+    last_reported_size: (f32, f32),
+}
+
+struct ResizeObserver {
+    callback: ResizeCallback,
+    observations: Vec<Observation>,
+}
+
+/// Resize observers of a document
+#[derive(Default)]
+pub(crate) struct ResizeObservers {
+    observers: Vec<Option<ResizeObserver>>,
+}
+
+impl Debug for ResizeObservers {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let count = self.observers.iter().flatten().count();
+        write!(f, "ResizeObservers({count})")
+    }
+}
+
+impl PartialEq for ResizeObservers {
+    fn eq(&self, other: &Self) -> bool {
+        self.observers.len() == other.observers.len()
+            && self
+                .observers
+                .iter()
+                .zip(&other.observers)
+                .all(|(a, b)| match (a, b) {
+                    (Some(a), Some(b)) => {
+                        a.observations == b.observations && Rc::ptr_eq(&a.callback, &b.callback)
+                    }
+                    (a, b) => a.is_none() && b.is_none(),
+                })
+    }
+}
+
+impl ResizeObservers {
+    /// Replaces the targets of the observers by their new IDs
+    pub(crate) fn remap_nodes(&mut self, ids: &NodeIdMap) {
+        for observer in self.observers.iter_mut().flatten() {
+            observer.observations.retain_mut(|observation| {
+                remap(ids, observation.target)
+                    .map(|target| observation.target = target)
+                    .is_some()
+            });
+        }
+    }
+
+    /// Stops observing the node, as it has been removed from the document
+    pub(crate) fn forget_node(&mut self, node_id: NodeId) {
+        for observer in self.observers.iter_mut().flatten() {
+            observer
+                .observations
+                .retain(|observation| observation.target != node_id);
+        }
+    }
+}
+
+impl Document {
+    /// Creates a resize observer. It does not observe anything until targets are added with
+    /// `observe_resize()`.
+    pub fn create_resize_observer(&mut self, callback: ResizeCallback) -> ResizeObserverId {
+        let observers = &mut self.resize_observers.observers;
+        observers.push(Some(ResizeObserver {
+            callback,
+            observations: Vec::new(),
+        }));
+        ResizeObserverId(observers.len() - 1)
+    }
+
+    /// Starts observing the size of the target
+    pub fn observe_resize(&mut self, observer_id: ResizeObserverId, target: NodeId) -> Result<()> {
+        if self.get_node_by_id(target).is_none() {
+            return Err(Error::DocumentTask(format!(
+                "Node ID {} does not exist",
+                target
+            )));
+        }
+
+        let observer = self.resize_observer_mut(observer_id)?;
+        if !observer.observations.iter().any(|o| o.target == target) {
+            observer.observations.push(Observation {
+                target,
+                last_reported_size: (0.0, 0.0),
+            });
+        }
+        Ok(())
+    }
+
+    /// Stops observing the size of the target
+    pub fn unobserve_resize(
+        &mut self,
+        observer_id: ResizeObserverId,
+        target: NodeId,
+    ) -> Result<()> {
+        let observer = self.resize_observer_mut(observer_id)?;
+        observer.observations.retain(|o| o.target != target);
+        Ok(())
+    }
+
+    /// Stops observing all targets, and removes the observer
+    pub fn disconnect_resize_observer(&mut self, observer_id: ResizeObserverId) {
+        if let Some(observer) = self.resize_observers.observers.get_mut(observer_id.0) {
+            *observer = None;
+        }
+    }
+
+    /// Returns true when one of the observed targets changed size since it was last reported
+    pub fn has_resize_observations(&self) -> bool {
+        self.resize_observers
+            .observers
+            .iter()
+            .flatten()
+            .flat_map(|observer| &observer.observations)
+            .any(|observation| self.resized_box(observation).is_some())
+    }
+
+    fn resize_observer_mut(
+        &mut self,
+        observer_id: ResizeObserverId,
+    ) -> Result<&mut ResizeObserver> {
+        self.resize_observers
+            .observers
+            .get_mut(observer_id.0)
+            .and_then(Option::as_mut)
+            .ok_or_else(|| {
+                Error::DocumentTask(format!("resize observer {} does not exist", observer_id.0))
+            })
+    }
+
+    /// Returns the border box of the target when its size differs from the last reported size
+    fn resized_box(&self, observation: &Observation) -> Option<Rect> {
+        let border_box = self
+            .bounding_client_rect(observation.target)
+            .unwrap_or_default();
+        ((border_box.width, border_box.height) != observation.last_reported_size)
+            .then_some(border_box)
+    }
+
+    /// Returns the number of ancestors of the node
+    fn node_depth(&self, node_id: NodeId) -> usize {
+        self.ancestors(node_id).count()
+    }
+}
+
+impl DocumentHandle {
+    /// Delivers the resize observations: invokes the callbacks of the observers with their
+    /// resized targets, repeating for deeper targets while the callbacks cause new resizes.
+    /// Returns the number of callbacks that were invoked.
+    pub fn deliver_resize_observations(&mut self) -> usize {
+        let mut invoked = 0;
+        let mut depth = 0;
+
+        loop {
+            let notifications = self.gather_resize_observations(depth);
+            if notifications.is_empty() {
+                break;
+            }
+
+            let mut shallowest = usize::MAX;
+            for (callback, entries) in &notifications {
+                for entry in entries {
+                    shallowest = shallowest.min(self.get().node_depth(entry.target));
+                }
+                callback(self, entries);
+                invoked += 1;
+            }
+            depth = shallowest;
+        }
+
+        if self.get().has_resize_observations() {
+            let mut event = Event::new("error", false, false);
+            self.dispatch_event(NodeId::root(), &mut event);
+        }
+        invoked
+    }
+
+    /// Collects the resized targets deeper than the depth (in the tree) per observer, and records
+    /// their sizes as reported
+    fn gather_resize_observations(
+        &mut self,
+        depth: usize,
+    ) -> Vec<(ResizeCallback, Vec<ResizeEntry>)> {
+        let mut doc = self.get_mut();
+        let doc = &mut *doc;
+        let mut observers = std::mem::take(&mut doc.resize_observers.observers);
+
+        let mut notifications = Vec::new();
+        for observer in observers.iter_mut().flatten() {
+            let mut entries = Vec::new();
+            for observation in &mut observer.observations {
+                if doc.node_depth(observation.target) <= depth {
+                    continue;
+                }
+                if let Some(border_box) = doc.resized_box(observation) {
+                    observation.last_reported_size = (border_box.width, border_box.height);
+                    entries.push(ResizeEntry {
+                        target: observation.target,
+                        border_box,
+                    });
+                }
+            }
+            if !entries.is_empty() {
+                notifications.push((observer.callback.clone(), entries));
+            }
+        }
+
+        // Observers created while gathering are kept as well
+        observers.append(&mut doc.resize_observers.observers);
+        doc.resize_observers.observers = observers;
+        notifications
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::html5::events::EventCallback;
+    use crate::testing::{parse, Boxes};
+    use std::cell::RefCell;
+
+    fn setup() -> (DocumentHandle, Rc<Boxes>, NodeId, NodeId) {
+        let mut document = parse("<div id='outer'><div id='inner'></div></div>");
+
+        let boxes = Rc::new(Boxes::new(Rect::new(0.0, 0.0, 800.0, 600.0)));
+        document.get_mut().set_layout_geometry(boxes.clone());
+
+        let outer = document.get().get_node_by_named_id("outer").unwrap().id;
+        let inner = document.get().get_node_by_named_id("inner").unwrap().id;
+        (document, boxes, outer, inner)
+    }
+
+    #[test]
+    fn reports_size_changes() {
+        let (mut document, boxes, outer, inner) = setup();
+        boxes.set(outer, Rect::new(0.0, 0.0, 100.0, 50.0));
+
+        let log = Rc::new(RefCell::new(Vec::new()));
+        let recorder = log.clone();
+        let callback: ResizeCallback = Rc::new(move |_, entries| {
+            recorder.borrow_mut().push(
+                entries
+                    .iter()
+                    .map(|e| (e.target, e.border_box.width))
+                    .collect::<Vec<_>>(),
+            );
+        });
+        let observer = document.get_mut().create_resize_observer(callback);
+        for target in [outer, inner] {
+            document.get_mut().observe_resize(observer, target).unwrap();
+        }
+
+        // The inner div has no box, so it has a size of zero and is not reported
+        assert_eq!(document.deliver_resize_observations(), 1);
+        assert_eq!(document.deliver_resize_observations(), 0);
+
+        // Moving without resizing is not reported
+        boxes.set(outer, Rect::new(10.0, 10.0, 100.0, 50.0));
+        boxes.set(inner, Rect::new(0.0, 0.0, 20.0, 20.0));
+        assert_eq!(document.deliver_resize_observations(), 1);
+
+        document
+            .get_mut()
+            .unobserve_resize(observer, inner)
+            .unwrap();
+        boxes.set(inner, Rect::new(0.0, 0.0, 40.0, 20.0));
+        assert!(!document.get().has_resize_observations());
+
+        assert_eq!(*log.borrow(), [vec![(outer, 100.0)], vec![(inner, 20.0)]]);
+    }
+
+    #[test]
+    fn resize_loop_is_limited() {
+        let (mut document, boxes, outer, inner) = setup();
+        boxes.set(outer, Rect::new(0.0, 0.0, 100.0, 100.0));
+        boxes.set(inner, Rect::new(0.0, 0.0, 50.0, 50.0));
+
+        // Every callback grows both divs, which would never end
+        let calls = Rc::new(RefCell::new(0));
+        let counter = calls.clone();
+        let layout = boxes.clone();
+        let callback: ResizeCallback = Rc::new(move |_, entries| {
+            *counter.borrow_mut() += 1;
+            for entry in entries {
+                let rect = entry.border_box;
+                layout.set(outer, Rect::new(0.0, 0.0, rect.width + 10.0, rect.height));
+                layout.set(inner, Rect::new(0.0, 0.0, rect.width + 10.0, rect.height));
+            }
+        });
+        let observer = document.get_mut().create_resize_observer(callback);
+        for target in [outer, inner] {
+            document.get_mut().observe_resize(observer, target).unwrap();
+        }
+
+        let errors = Rc::new(RefCell::new(0));
+        let counter = errors.clone();
+        let on_error: EventCallback = Rc::new(move |_, _| *counter.borrow_mut() += 1);
+        document.get_mut().add_event_listener(
+            NodeId::root(),
+            "error",
+            on_error,
+            Default::default(),
+        );
+
+        // Both divs at first, then only the inner div as it is deeper than the outer one
+        assert_eq!(document.deliver_resize_observations(), 2);
+        assert_eq!(*calls.borrow(), 2);
+        assert_eq!(*errors.borrow(), 1);
+
+        // The skipped changes are delivered on the next update
+        assert_eq!(document.deliver_resize_observations(), 2);
+        assert_eq!(*calls.borrow(), 4);
+        assert_eq!(*errors.borrow(), 2);
+
+        document.get_mut().disconnect_resize_observer(observer);
+        assert_eq!(document.deliver_resize_observations(), 0);
+        assert_eq!(*errors.borrow(), 2);
+    }
+}
@@ -0,0 +1,598 @@
+//! Overflow and scrolling
+//!
+//! An element whose `overflow` is not `visible` clips the contents it is the containing block of
+//! (directly, or through other elements) to its padding box. Absolutely positioned descendants
+//! whose containing block is outside of it escape the clip, and so do fixed descendants. Unless
+//! the overflow is `clip`, the element is a scroll container: its contents can be scrolled, which
+//! moves them up and left by its scroll offset. `overflow: hidden` only scrolls programmatically,
+//! `scroll` and `auto` also scroll on wheel input.
+//!
+//! The engine keeps the scroll offsets, and applies them in `accumulated_transform()`, so the
+//! element geometry, hit testing and the display list all see the scrolled positions. How far an
+//! element can scroll follows from the scroll size the layout geometry reports.
+//!
+//! Scrollbars take the space the layout reserved for them between the padding box and the right
+//! and bottom borders. With `overflow: scroll` they are always shown, with `overflow: auto` only
+//! when the contents overflow. Elements without reserved space get no scrollbars.
+//!
+//! The overflow of the root element applies to the viewport, which the embedder scrolls: wheel
+//! input that no element scrolls is left to it.
+//!
+//! See: https://drafts.csswg.org/css-overflow-3/
+use crate::html5::box_decoration::{words, RoundedRect};
+use crate::html5::element_style::cascaded_value;
+use crate::html5::events::{Event, EventDetail};
+use crate::html5::geometry::Rect;
+use crate::html5::node::{NodeData, NodeId};
+use crate::html5::parser::document::{Document, DocumentHandle};
+
+/// Shortest length of a scrollbar thumb, so it stays easy to grab
+pub const MIN_THUMB_LENGTH: f32 = 16.0;
+
+/// Value of `overflow-x` or `overflow-y`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Overflow {
+    #[default]
+    Visible,
+    Hidden,
+    Clip,
+    Scroll,
+    Auto,
+}
+
+impl Overflow {
+    /// Returns the overflow for the keyword, or None when it is unknown
+    pub fn from_keyword(keyword: &str) -> Option<Overflow> {
+        let overflow = match keyword.trim().to_ascii_lowercase().as_str() {
+            "visible" => Overflow::Visible,
+            "hidden" => Overflow::Hidden,
+            "clip" => Overflow::Clip,
+            "scroll" => Overflow::Scroll,
+            "auto" => Overflow::Auto,
+            _ => return None,
+        };
+        Some(overflow)
+    }
+
+    /// Returns true when the user can scroll in this direction
+    pub fn is_user_scrollable(&self) -> bool {
+        matches!(self, Overflow::Scroll | Overflow::Auto)
+    }
+}
+
+/// The overflow of an element in both directions
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct OverflowStyle {
+    pub x: Overflow,
+    pub y: Overflow,
+}
+
+impl OverflowStyle {
+    /// Returns the overflow for the values of `overflow-x` and `overflow-y`. A box cannot scroll
+    /// in one direction only: when the other direction scrolls, `visible` becomes `auto` and
+    /// `clip` becomes `hidden`.
+    pub fn new(x: Overflow, y: Overflow) -> Self {
+        let scrolls = |overflow: Overflow| {
+            matches!(
+                overflow,
+                Overflow::Hidden | Overflow::Scroll | Overflow::Auto
+            )
+        };
+        let resolve = |overflow: Overflow, other: Overflow| match overflow {
+            Overflow::Visible if scrolls(other) => Overflow::Auto,
+            Overflow::Clip if scrolls(other) => Overflow::Hidden,
+            overflow => overflow,
+        };
+        Self {
+            x: resolve(x, y),
+            y: resolve(y, x),
+        }
+    }
+
+    /// Parses an `overflow` value: one keyword for both directions, or one for each
+    pub fn parse(value: &str) -> Option<Self> {
+        let keywords = words(value)
+            .map(Overflow::from_keyword)
+            .collect::<Option<Vec<_>>>()?;
+        match keywords[..] {
+            [both] => Some(Self::new(both, both)),
+            [x, y] => Some(Self::new(x, y)),
+            _ => None,
+        }
+    }
+
+    /// Returns true when the contents are clipped (in at least one direction)
+    pub fn clips(&self) -> bool {
+        self.x != Overflow::Visible || self.y != Overflow::Visible
+    }
+
+    /// Returns true when the box is a scroll container
+    pub fn is_scroll_container(&self) -> bool {
+        !matches!(self.x, Overflow::Visible | Overflow::Clip)
+    }
+}
+
+/// Direction of a scrollbar
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScrollbarOrientation {
+    Horizontal,
+    Vertical,
+}
+
+/// A scrollbar of a scroll container, in the coordinates of its (untransformed) border box
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Scrollbar {
+    pub orientation: ScrollbarOrientation,
+    /// The whole scrollbar
+    pub track: Rect,
+    /// The part of the track that stands for the visible part of the contents
+    pub thumb: Rect,
+}
+
+/// Wheel input as reported by the embedder
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct WheelInput {
+    /// Position of the pointer in the viewport
+    pub x: f64,
+    pub y: f64,
+    /// Distance to scroll in CSS pixels. Positive values scroll right and down.
+    pub delta_x: f64,
+    pub delta_y: f64,
+}
+
+impl Document {
+    /// Returns the overflow of the element, from its `overflow`, `overflow-x` and `overflow-y`
+    /// declarations. Visible for other nodes.
+    pub fn overflow(&self, node_id: NodeId) -> OverflowStyle {
+        let Some(NodeData::Element(_)) = self.get_node_by_id(node_id).map(|node| &node.data) else {
+            return OverflowStyle::default();
+        };
+        let declarations = self.declared_style(node_id);
+        let mut overflow = cascaded_value(&declarations, "overflow")
+            .and_then(OverflowStyle::parse)
+            .unwrap_or_default();
+        let longhand =
+            |property| cascaded_value(&declarations, property).and_then(Overflow::from_keyword);
+        if let Some(x) = longhand("overflow-x") {
+            overflow.x = x;
+        }
+        if let Some(y) = longhand("overflow-y") {
+            overflow.y = y;
+        }
+        OverflowStyle::new(overflow.x, overflow.y)
+    }
+
+    /// Returns true when the element can be scrolled. The root element never is, as its overflow
+    /// applies to the viewport.
+    pub fn is_scroll_container(&self, node_id: NodeId) -> bool {
+        !self.is_root_element(node_id)
+            && !self.is_hidden(node_id)
+            && self.overflow(node_id).is_scroll_container()
+    }
+
+    /// Returns how far the contents of the element are scrolled to the left and up (like
+    /// `scrollLeft` and `scrollTop`). Never more than the element can scroll.
+    pub fn scroll_offset(&self, node_id: NodeId) -> (f32, f32) {
+        let Some(&(x, y)) = self.scroll_offsets.get(&node_id) else {
+            return (0.0, 0.0);
+        };
+        if !self.is_scroll_container(node_id) {
+            return (0.0, 0.0);
+        }
+        let (max_x, max_y) = self.max_scroll_offset(node_id);
+        (x.clamp(0.0, max_x), y.clamp(0.0, max_y))
+    }
+
+    /// Returns how far the contents of the element can be scrolled: the size of the contents that
+    /// do not fit in the padding box
+    pub fn max_scroll_offset(&self, node_id: NodeId) -> (f32, f32) {
+        let Some(geometry) = self.geometry.0.as_ref() else {
+            return (0.0, 0.0);
+        };
+        let (Some(padding_box), Some((width, height))) =
+            (geometry.padding_box(node_id), geometry.scroll_size(node_id))
+        else {
+            return (0.0, 0.0);
+        };
+        (
+            (width - padding_box.width).max(0.0),
+            (height - padding_box.height).max(0.0),
+        )
+    }
+
+    /// Returns the scrollbars the scroll container shows
+    pub fn scrollbars(&self, node_id: NodeId) -> Vec<Scrollbar> {
+        let Some(geometry) = self.geometry.0.as_ref() else {
+            return Vec::new();
+        };
+        let (Some(border_box), Some(padding_box)) =
+            (geometry.border_box(node_id), geometry.padding_box(node_id))
+        else {
+            return Vec::new();
+        };
+        if !self.is_scroll_container(node_id) {
+            return Vec::new();
+        }
+
+        let overflow = self.overflow(node_id);
+        let inner = border_box.inflate(self.box_decoration(node_id).border_widths().map(|w| -w));
+        let (offset_x, offset_y) = self.scroll_offset(node_id);
+        let (max_x, max_y) = self.max_scroll_offset(node_id);
+        let shown = |overflow: Overflow, max: f32| {
+            overflow == Overflow::Scroll || (overflow == Overflow::Auto && max > 0.0)
+        };
+        // Returns the start and length of the thumb on a track of the length
+        let thumb = |length: f32, visible: f32, offset: f32, max: f32| {
+            let thumb_length = (length * visible / (visible + max))
+                .max(MIN_THUMB_LENGTH)
+                .min(length);
+            let start = if max > 0.0 {
+                offset / max * (length - thumb_length)
+            } else {
+                0.0
+            };
+            (start, thumb_length)
+        };
+
+        let mut scrollbars = Vec::new();
+        let width = inner.right() - padding_box.right();
+        if width > 0.0 && shown(overflow.y, max_y) {
+            let track = Rect::new(
+                padding_box.right(),
+                padding_box.y,
+                width,
+                padding_box.height,
+            );
+            let (start, length) = thumb(track.height, padding_box.height, offset_y, max_y);
+            scrollbars.push(Scrollbar {
+                orientation: ScrollbarOrientation::Vertical,
+                track,
+                thumb: Rect::new(track.x, track.y + start, track.width, length),
+            });
+        }
+        let height = inner.bottom() - padding_box.bottom();
+        if height > 0.0 && shown(overflow.x, max_x) {
+            let track = Rect::new(
+                padding_box.x,
+                padding_box.bottom(),
+                padding_box.width,
+                height,
+            );
+            let (start, length) = thumb(track.width, padding_box.width, offset_x, max_x);
+            scrollbars.push(Scrollbar {
+                orientation: ScrollbarOrientation::Horizontal,
+                track,
+                thumb: Rect::new(track.x + start, track.y, length, track.height),
+            });
+        }
+        scrollbars
+    }
+
+    /// Returns the area the element clips its contents to (in the coordinates of its
+    /// untransformed border box), or None when it does not clip them. In a direction that is not
+    /// clipped, the area covers the viewport.
+    pub fn overflow_clip(&self, node_id: NodeId) -> Option<RoundedRect> {
+        let overflow = self.overflow(node_id);
+        if !overflow.clips() || self.is_root_element(node_id) {
+            return None;
+        }
+        let geometry = self.geometry.0.as_ref()?;
+        let border_box = geometry.border_box(node_id)?;
+        let mut clip = geometry.padding_box(node_id)?;
+        let decoration = self.box_decoration(node_id);
+        let mut radii = decoration
+            .rounded_border_box(border_box)
+            .shrink(decoration.border_widths())
+            .radii;
+
+        let viewport = geometry.viewport();
+        if overflow.x == Overflow::Visible {
+            let right = clip.right().max(viewport.right());
+            clip.x = clip.x.min(viewport.x);
+            clip.width = right - clip.x;
+            radii = [(0.0, 0.0); 4];
+        }
+        if overflow.y == Overflow::Visible {
+            let bottom = clip.bottom().max(viewport.bottom());
+            clip.y = clip.y.min(viewport.y);
+            clip.height = bottom - clip.y;
+            radii = [(0.0, 0.0); 4];
+        }
+        Some(RoundedRect::new(clip, radii))
+    }
+
+    /// Returns the elements that clip the node, from the outermost to the innermost: the
+    /// elements with a clipping overflow in its chain of containing blocks
+    pub(crate) fn clipping_ancestors(&self, node_id: NodeId) -> Vec<NodeId> {
+        let mut ancestors: Vec<NodeId> =
+            std::iter::successors(self.containing_block(node_id), |&ancestor| {
+                self.containing_block(ancestor)
+            })
+            .filter(|&ancestor| self.overflow_clip(ancestor).is_some())
+            .collect();
+        ancestors.reverse();
+        ancestors
+    }
+
+    /// Returns the scroll containers in the chain of containing blocks of the node that have
+    /// scrolled its contents
+    pub(crate) fn scrolling_ancestors(&self, node_id: NodeId) -> Vec<NodeId> {
+        if self.scroll_offsets.is_empty() {
+            return Vec::new();
+        }
+        std::iter::successors(self.containing_block(node_id), |&ancestor| {
+            self.containing_block(ancestor)
+        })
+        .filter(|&ancestor| self.scroll_offset(ancestor) != (0.0, 0.0))
+        .collect()
+    }
+
+    /// Returns true when the position in the viewport is outside the clip of one of the
+    /// elements that clip the node
+    pub(crate) fn is_clipped_at(&self, node_id: NodeId, x: f32, y: f32) -> bool {
+        self.clipping_ancestors(node_id)
+            .into_iter()
+            .any(|ancestor| {
+                let (Some(clip), Some(inverse)) = (
+                    self.overflow_clip(ancestor),
+                    self.accumulated_transform(ancestor).inverse(),
+                ) else {
+                    return true;
+                };
+                let (x, y) = inverse.map_point(x, y);
+                !clip.contains(x, y)
+            })
+    }
+}
+
+impl DocumentHandle {
+    /// Scrolls the contents of the scroll container to the offset (like `scrollTo()`), limited
+    /// to how far it can scroll. Fires `scroll` at the element when the offset changed, and
+    /// returns whether it did.
+    pub fn scroll_to(&mut self, node_id: NodeId, x: f32, y: f32) -> bool {
+        let (previous, (max_x, max_y)) = {
+            let doc = self.get();
+            if !doc.is_scroll_container(node_id) {
+                return false;
+            }
+            (doc.scroll_offset(node_id), doc.max_scroll_offset(node_id))
+        };
+        let offset = (x.clamp(0.0, max_x), y.clamp(0.0, max_y));
+        if offset == previous {
+            return false;
+        }
+
+        self.get_mut().scroll_offsets.insert(node_id, offset);
+        let mut event = Event::new("scroll", false, false);
+        self.dispatch_event(node_id, &mut event);
+        true
+    }
+
+    /// Scrolls the contents of the scroll container by the distance (like `scrollBy()`). See
+    /// `scroll_to()`.
+    pub fn scroll_by(&mut self, node_id: NodeId, delta_x: f32, delta_y: f32) -> bool {
+        let (x, y) = self.get().scroll_offset(node_id);
+        self.scroll_to(node_id, x + delta_x, y + delta_y)
+    }
+
+    /// Handles wheel input: fires `wheel` at the node under the pointer, and unless it is
+    /// canceled, scrolls the nearest scroll container (of the node and its containing blocks)
+    /// that can still scroll in the direction of the wheel. Returns false when the input was not
+    /// handled, in which case the embedder can scroll the viewport.
+    pub fn wheel(&mut self, input: WheelInput) -> bool {
+        let target = self.get().hit_test(input.x, input.y);
+        let mut event = Event::new("wheel", true, true).with_detail(EventDetail::Wheel(input));
+        if !self.dispatch_event(target, &mut event) {
+            return true;
+        }
+
+        let candidates: Vec<NodeId> = {
+            let doc = self.get();
+            std::iter::successors(Some(target), |&node_id| doc.containing_block(node_id))
+                .filter(|&node_id| doc.is_scroll_container(node_id))
+                .collect()
+        };
+        for node_id in candidates {
+            let overflow = self.get().overflow(node_id);
+            let delta_x = if overflow.x.is_user_scrollable() {
+                input.delta_x as f32
+            } else {
+                0.0
+            };
+            let delta_y = if overflow.y.is_user_scrollable() {
+                input.delta_y as f32
+            } else {
+                0.0
+            };
+            if self.scroll_by(node_id, delta_x, delta_y) {
+                return true;
+            }
+        }
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::html5::events::EventCallback;
+    use crate::html5::geometry::LayoutGeometry;
+    use crate::html5::pointer::HitTester;
+    use crate::testing::parse;
+    use std::cell::RefCell;
+    use std::collections::HashMap;
+    use std::rc::Rc;
+
+    /// Every element is 100x100, and leaves 10px on every side for a border, and 10px more on the
+    /// right and bottom for scrollbars. The contents of "outer" and "inner" are 300x150.
+    struct Layout(Vec<NodeId>);
+
+    impl LayoutGeometry for Layout {
+        fn border_box(&self, _node_id: NodeId) -> Option<Rect> {
+            Some(Rect::new(0.0, 0.0, 100.0, 100.0))
+        }
+
+        fn viewport(&self) -> Rect {
+            Rect::new(0.0, 0.0, 800.0, 600.0)
+        }
+
+        fn padding_box(&self, _node_id: NodeId) -> Option<Rect> {
+            Some(Rect::new(10.0, 10.0, 70.0, 70.0))
+        }
+
+        fn scroll_size(&self, node_id: NodeId) -> Option<(f32, f32)> {
+            self.0.contains(&node_id).then_some((300.0, 150.0))
+        }
+    }
+
+    /// Everything is hit at the innermost element
+    struct Innermost(NodeId);
+
+    impl HitTester for Innermost {
+        fn hit_test(&self, _x: f64, _y: f64) -> Option<NodeId> {
+            Some(self.0)
+        }
+    }
+
+    fn setup(html: &str) -> (DocumentHandle, HashMap<String, NodeId>) {
+        let mut document = parse(html);
+
+        let ids: HashMap<String, NodeId> = document
+            .get()
+            .descendants(NodeId::root())
+            .filter_map(|node| Some((node.get_attribute("id")?.clone(), node.id)))
+            .collect();
+        let scrolling = ["outer", "inner"].iter().filter_map(|id| ids.get(*id));
+        let layout = Layout(scrolling.copied().collect());
+        document.get_mut().set_layout_geometry(Rc::new(layout));
+        (document, ids)
+    }
+
+    #[test]
+    fn overflow_values() {
+        let (document, ids) = setup(
+            r#"<div id=a style="overflow: hidden"></div>
+               <div id=b style="overflow: clip visible"></div>
+               <div id=c style="overflow: scroll; overflow-y: visible"></div>
+               <div id=d style="overflow-x: clip; overflow-y: auto"></div>
+               <div id=e style="overflow: sideways"></div>"#,
+        );
+        let doc = document.get();
+        let overflow = |id: &str| doc.overflow(ids[id]);
+        let style = |x, y| OverflowStyle { x, y };
+
+        assert_eq!(overflow("a"), style(Overflow::Hidden, Overflow::Hidden));
+        assert_eq!(overflow("b"), style(Overflow::Clip, Overflow::Visible));
+        assert_eq!(overflow("c"), style(Overflow::Scroll, Overflow::Auto));
+        assert_eq!(overflow("d"), style(Overflow::Hidden, Overflow::Auto));
+        assert_eq!(overflow("e"), OverflowStyle::default());
+        assert!(doc.is_scroll_container(ids["a"]));
+        assert!(!doc.is_scroll_container(ids["b"]));
+        assert!(!doc.is_scroll_container(ids["e"]));
+
+        // b only clips horizontally
+        let clip = doc.overflow_clip(ids["b"]).unwrap();
+        assert_eq!(clip.rect, Rect::new(10.0, 0.0, 70.0, 600.0));
+        assert!(doc.overflow_clip(ids["e"]).is_none());
+        let html = doc.elements_by_tag_name("html")[0];
+        assert!(doc.overflow_clip(html).is_none());
+    }
+
+    #[test]
+    fn scrollbars() {
+        let (mut document, ids) =
+            setup(r#"<div id=outer style="overflow: scroll auto; border: 10px solid"></div>"#);
+        let outer = ids["outer"];
+        assert_eq!(document.get().max_scroll_offset(outer), (230.0, 80.0));
+
+        assert!(document.scroll_to(outer, 115.0, 500.0));
+        assert_eq!(document.get().scroll_offset(outer), (115.0, 80.0));
+        let scrollbars = document.get().scrollbars(outer);
+        assert_eq!(scrollbars.len(), 2);
+        // The vertical thumb shows 70 of 150 pixels, and is at the end of the track
+        assert_eq!(scrollbars[0].orientation, ScrollbarOrientation::Vertical);
+        assert_eq!(scrollbars[0].track, Rect::new(80.0, 10.0, 10.0, 70.0));
+        assert!((scrollbars[0].thumb.height - 70.0 * 70.0 / 150.0).abs() < 0.001);
+        assert_eq!(scrollbars[0].thumb.bottom(), 80.0);
+        // The horizontal thumb is in the middle of its track
+        let thumb = scrollbars[1].thumb;
+        assert_eq!(scrollbars[1].track, Rect::new(10.0, 80.0, 70.0, 10.0));
+        assert!((thumb.x - 10.0 - (70.0 - thumb.width) / 2.0).abs() < 0.001);
+    }
+
+    #[test]
+    fn wheel_scrolls_nearest_container() {
+        let (mut document, ids) = setup(
+            r#"<div id=outer style="overflow: auto">
+                 <div id=hidden style="overflow: hidden">
+                   <div id=inner style="overflow-y: scroll"><p id=p></p></div>
+                 </div>
+               </div>"#,
+        );
+        let (outer, inner, p) = (ids["outer"], ids["inner"], ids["p"]);
+        document.get_mut().set_hit_tester(Rc::new(Innermost(p)));
+
+        let log = Rc::new(RefCell::new(Vec::new()));
+        for (id, event_type) in [("outer", "scroll"), ("inner", "scroll"), ("p", "wheel")] {
+            let recorder = log.clone();
+            let callback: EventCallback = Rc::new(move |doc, event| {
+                let target = event.target.unwrap();
+                let (x, y) = doc.get().scroll_offset(target);
+                recorder
+                    .borrow_mut()
+                    .push(format!("{} {} {x} {y}", event.event_type, target));
+                if event.event_type == "wheel" && event.wheel().unwrap().delta_y < 0.0 {
+                    event.prevent_default();
+                }
+            });
+            document.get_mut().add_event_listener(
+                ids[id],
+                event_type,
+                callback,
+                Default::default(),
+            );
+        }
+        let wheel = |delta_x, delta_y| WheelInput {
+            x: 5.0,
+            y: 5.0,
+            delta_x,
+            delta_y,
+        };
+
+        // The inner container scrolls until it reaches its end, then the outer one takes over.
+        // The hidden one in between is skipped.
+        assert!(document.wheel(wheel(0.0, 50.0)));
+        assert!(document.wheel(wheel(0.0, 50.0)));
+        assert!(document.wheel(wheel(0.0, 50.0)));
+        assert_eq!(document.get().scroll_offset(inner), (0.0, 80.0));
+        assert_eq!(document.get().scroll_offset(outer), (0.0, 50.0));
+        assert_eq!(document.get().scroll_offset(ids["hidden"]), (0.0, 0.0));
+
+        // Scrolled contents move up
+        let p_box = document.get().bounding_client_rect(p).unwrap();
+        assert_eq!((p_box.x, p_box.y), (0.0, -130.0));
+
+        // Canceled wheel events do not scroll, but are handled
+        assert!(document.wheel(wheel(0.0, -50.0)));
+        assert_eq!(document.get().scroll_offset(outer), (0.0, 50.0));
+        assert_eq!(
+            *log.borrow(),
+            [
+                format!("wheel {p} 0 0"),
+                format!("scroll {inner} 0 50"),
+                format!("wheel {p} 0 0"),
+                format!("scroll {inner} 0 80"),
+                format!("wheel {p} 0 0"),
+                format!("scroll {outer} 0 50"),
+                format!("wheel {p} 0 0"),
+            ]
+        );
+
+        // The inner container scrolls horizontally as well, as `visible` became `auto`
+        assert!(document.wheel(wheel(500.0, 0.0)));
+        assert_eq!(document.get().scroll_offset(inner), (230.0, 80.0));
+        assert!(document.wheel(wheel(500.0, 0.0)));
+        assert_eq!(document.get().scroll_offset(outer), (230.0, 50.0));
+        assert!(!document.wheel(wheel(500.0, 0.0)));
+    }
+}
@@ -0,0 +1,366 @@
+//! Select elements
+//!
+//! Typed access to `<select>` elements and their `<option>` and `<optgroup>` children. The
+//! options of a select are its option children and the option children of its optgroup children.
+//! Which options are selected is kept in the form control state, and starts out from the
+//! `selected` attributes.
+//!
+//! Changing the selection through this API fires `input` and `change` on the select, the same as
+//! when the user picks an option.
+//!
+//! See: https://html.spec.whatwg.org/multipage/form-elements.html#the-select-element
+use crate::html5::events::Event;
+use crate::html5::node::{Node, NodeId, HTML_NAMESPACE};
+use crate::html5::parser::document::{Document, DocumentHandle};
+use crate::types::{Error, Result};
+
+impl Document {
+    /// Returns the options of the select element
+    pub fn select_options(&self, select_id: NodeId) -> Vec<NodeId> {
+        let Some(select) = self
+            .get_node_by_id(select_id)
+            .filter(|n| is_html(n, "select"))
+        else {
+            return vec![];
+        };
+
+        let mut options = Vec::new();
+        for child_id in &select.children {
+            let Some(child) = self.get_node_by_id(*child_id) else {
+                continue;
+            };
+            if is_html(child, "option") {
+                options.push(*child_id);
+            } else if is_html(child, "optgroup") {
+                options.extend(child.children.iter().copied().filter(|id| {
+                    self.get_node_by_id(*id)
+                        .is_some_and(|node| is_html(node, "option"))
+                }));
+            }
+        }
+        options
+    }
+
+    /// Returns true when multiple options of the select element can be selected
+    pub fn is_select_multiple(&self, select_id: NodeId) -> bool {
+        self.get_node_by_id(select_id)
+            .is_some_and(|node| node.get_attribute("multiple").is_some())
+    }
+
+    /// Returns the selected options of the select element, in tree order
+    pub fn selected_options(&self, select_id: NodeId) -> Vec<NodeId> {
+        let options = self.select_options(select_id);
+        let mut selected: Vec<NodeId> = options
+            .iter()
+            .copied()
+            .filter(|option_id| self.option_selectedness(*option_id))
+            .collect();
+
+        if self.is_select_multiple(select_id) {
+            return selected;
+        }
+
+        // A single select shows only one option. When none is selected by default this is the
+        // first option that is not disabled, and when several are selected the last one wins.
+        let is_default = options
+            .iter()
+            .all(|option_id| self.form_controls.selectedness(*option_id).is_none());
+        if selected.is_empty() && is_default && self.select_display_size(select_id) == 1 {
+            selected.extend(
+                options
+                    .iter()
+                    .copied()
+                    .find(|option_id| !self.is_option_disabled(*option_id)),
+            );
+        }
+        selected.split_off(selected.len().saturating_sub(1))
+    }
+
+    /// Returns the index of the first selected option of the select element
+    pub fn selected_index(&self, select_id: NodeId) -> Option<usize> {
+        let selected = *self.selected_options(select_id).first()?;
+        self.select_options(select_id)
+            .iter()
+            .position(|option_id| *option_id == selected)
+    }
+
+    /// Returns the value of the first selected option of the select element, or an empty string
+    /// when no option is selected
+    pub fn select_value(&self, select_id: NodeId) -> String {
+        self.selected_options(select_id)
+            .first()
+            .map(|option_id| self.option_value(*option_id))
+            .unwrap_or_default()
+    }
+
+    /// Returns the value of the option element: its value attribute, or its text otherwise
+    pub fn option_value(&self, option_id: NodeId) -> String {
+        match self
+            .get_node_by_id(option_id)
+            .and_then(|node| node.get_attribute("value"))
+        {
+            Some(value) => value.clone(),
+            None => self.option_text(option_id),
+        }
+    }
+
+    /// Returns the text of the option element, with whitespace stripped and collapsed
+    pub fn option_text(&self, option_id: NodeId) -> String {
+        self.text_content(option_id)
+            .split_ascii_whitespace()
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    /// Returns true when the option element or its optgroup is disabled
+    pub fn is_option_disabled(&self, option_id: NodeId) -> bool {
+        let Some(option) = self.get_node_by_id(option_id) else {
+            return false;
+        };
+        if option.get_attribute("disabled").is_some() {
+            return true;
+        }
+
+        option
+            .parent
+            .and_then(|parent_id| self.get_node_by_id(parent_id))
+            .is_some_and(|parent| {
+                is_html(parent, "optgroup") && parent.get_attribute("disabled").is_some()
+            })
+    }
+
+    /// Returns the select element the option belongs to
+    pub fn option_select(&self, option_id: NodeId) -> Option<NodeId> {
+        let mut parent_id = self.get_node_by_id(option_id)?.parent?;
+        let mut parent = self.get_node_by_id(parent_id)?;
+        if is_html(parent, "optgroup") {
+            parent_id = parent.parent?;
+            parent = self.get_node_by_id(parent_id)?;
+        }
+        is_html(parent, "select").then_some(parent_id)
+    }
+
+    /// Returns the selectedness of the option: its current state, or the selected attribute
+    fn option_selectedness(&self, option_id: NodeId) -> bool {
+        self.form_controls
+            .selectedness(option_id)
+            .unwrap_or_else(|| {
+                self.get_node_by_id(option_id)
+                    .is_some_and(|node| node.get_attribute("selected").is_some())
+            })
+    }
+
+    /// Returns the number of options shown at once (1 for a drop-down box)
+    fn select_display_size(&self, select_id: NodeId) -> usize {
+        let size = self
+            .get_node_by_id(select_id)
+            .and_then(|node| node.get_attribute("size"))
+            .and_then(|size| size.trim().parse::<usize>().ok())
+            .filter(|size| *size > 0);
+        match size {
+            Some(size) => size,
+            None if self.is_select_multiple(select_id) => 4,
+            None => 1,
+        }
+    }
+}
+
+impl DocumentHandle {
+    /// Selects the option at the index (deselecting all other options), or deselects all options
+    /// when no index is given
+    pub fn set_selected_index(&mut self, select_id: NodeId, index: Option<usize>) -> Result<()> {
+        let options = self.checked_options(select_id)?;
+        let selected = match index {
+            Some(index) => vec![*options.get(index).ok_or_else(|| {
+                Error::DocumentTask(format!("Select {} has no option {}", select_id, index))
+            })?],
+            None => vec![],
+        };
+        self.update_selection(select_id, &selected);
+        Ok(())
+    }
+
+    /// Selects the first option with the given value (deselecting all other options). Returns
+    /// false when there is no such option, in which case no option is selected.
+    pub fn set_select_value(&mut self, select_id: NodeId, value: &str) -> Result<bool> {
+        let options = self.checked_options(select_id)?;
+        let option = options
+            .into_iter()
+            .find(|option_id| self.get().option_value(*option_id) == value);
+        self.update_selection(select_id, &Vec::from_iter(option));
+        Ok(option.is_some())
+    }
+
+    /// Selects or deselects the option. In a select element that is not multiple, selecting an
+    /// option deselects the others.
+    pub fn set_option_selected(&mut self, option_id: NodeId, selected: bool) -> Result<()> {
+        let select_id = self.get().option_select(option_id).ok_or_else(|| {
+            Error::DocumentTask(format!(
+                "Node ID {} is not an option of a select",
+                option_id
+            ))
+        })?;
+
+        let mut selection = if self.get().is_select_multiple(select_id) {
+            self.get().selected_options(select_id)
+        } else {
+            vec![]
+        };
+        selection.retain(|id| *id != option_id);
+        if selected {
+            selection.push(option_id);
+        }
+        self.update_selection(select_id, &selection);
+        Ok(())
+    }
+
+    /// Picks the option like a user would: disabled options cannot be picked, and in a multiple
+    /// select the option is toggled. Returns false when the option could not be picked.
+    pub fn pick_option(&mut self, option_id: NodeId) -> bool {
+        if self.get().is_option_disabled(option_id) || self.get().is_inert(option_id) {
+            return false;
+        }
+        let Some(select_id) = self.get().option_select(option_id) else {
+            return false;
+        };
+        if self.get().is_inert(select_id) {
+            return false;
+        }
+
+        let selected = if self.get().is_select_multiple(select_id) {
+            !self.get().selected_options(select_id).contains(&option_id)
+        } else {
+            true
+        };
+        self.set_option_selected(option_id, selected).is_ok()
+    }
+
+    fn checked_options(&self, select_id: NodeId) -> Result<Vec<NodeId>> {
+        let is_select = self
+            .get()
+            .get_node_by_id(select_id)
+            .is_some_and(|node| is_html(node, "select"));
+        if !is_select {
+            return Err(Error::DocumentTask(format!(
+                "Node ID {} is not a select element",
+                select_id
+            )));
+        }
+        Ok(self.get().select_options(select_id))
+    }
+
+    /// Sets the selected options of the select element, and fires `input` and `change` when the
+    /// selection changed
+    fn update_selection(&mut self, select_id: NodeId, selected: &[NodeId]) {
+        let before = self.get().selected_options(select_id);
+        {
+            let mut doc = self.get_mut();
+            for option_id in doc.select_options(select_id) {
+                doc.form_controls
+                    .set_selectedness(option_id, selected.contains(&option_id));
+            }
+        }
+        if self.get().selected_options(select_id) == before {
+            return;
+        }
+
+        for event_type in ["input", "change"] {
+            let mut event = Event::new(event_type, true, false);
+            self.dispatch_event(select_id, &mut event);
+        }
+    }
+}
+
+fn is_html(node: &Node, name: &str) -> bool {
+    node.is_namespace(HTML_NAMESPACE) && node.name == name
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::html5::events::EventCallback;
+    use crate::testing::parse;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    fn count_changes(document: &mut DocumentHandle, select_id: NodeId) -> Rc<RefCell<usize>> {
+        let changes = Rc::new(RefCell::new(0));
+        let counter = changes.clone();
+        let callback: EventCallback = Rc::new(move |_, _| *counter.borrow_mut() += 1);
+        document
+            .get_mut()
+            .add_event_listener(select_id, "change", callback, Default::default());
+        changes
+    }
+
+    #[test]
+    fn single_select() {
+        let mut document = parse(
+            "<select><option disabled>none</option><option>  first   one </option>\
+             <optgroup label=g><option value=2>second</option></optgroup></select>",
+        );
+        let select = document.get().elements_by_tag_name("select")[0];
+        let options = document.get().select_options(select);
+        let changes = count_changes(&mut document, select);
+
+        // Without a selected option the first enabled option is selected
+        assert_eq!(options.len(), 3);
+        assert!(!document.get().is_select_multiple(select));
+        assert_eq!(document.get().selected_index(select), Some(1));
+        assert_eq!(document.get().select_value(select), "first one");
+        assert_eq!(document.get().option_select(options[2]), Some(select));
+
+        assert!(document.set_select_value(select, "2").unwrap());
+        assert_eq!(document.get().selected_options(select), vec![options[2]]);
+        assert_eq!(*changes.borrow(), 1);
+
+        // Selecting the same option again does not change anything
+        document.set_selected_index(select, Some(2)).unwrap();
+        assert_eq!(*changes.borrow(), 1);
+        assert!(document.set_selected_index(select, Some(5)).is_err());
+
+        // Disabled options cannot be picked by the user, but can be selected by the API
+        assert!(!document.pick_option(options[0]));
+        document.set_option_selected(options[0], true).unwrap();
+        assert_eq!(document.get().selected_index(select), Some(0));
+        assert_eq!(*changes.borrow(), 2);
+
+        // Deselecting all options leaves the select without selection
+        document.set_selected_index(select, None).unwrap();
+        assert_eq!(document.get().selected_index(select), None);
+
+        document.get_mut().reset_form_control(select);
+        assert_eq!(document.get().selected_index(select), Some(1));
+    }
+
+    #[test]
+    fn multiple_select() {
+        let mut document = parse(
+            "<select multiple><option selected>a</option><option>b</option>\
+             <option selected>c</option></select>",
+        );
+        let select = document.get().elements_by_tag_name("select")[0];
+        let options = document.get().select_options(select);
+        let changes = count_changes(&mut document, select);
+
+        assert_eq!(
+            document.get().selected_options(select),
+            vec![options[0], options[2]]
+        );
+        assert_eq!(document.get().select_value(select), "a");
+
+        // Picking toggles options
+        assert!(document.pick_option(options[1]));
+        assert!(document.pick_option(options[0]));
+        assert_eq!(
+            document.get().selected_options(select),
+            vec![options[1], options[2]]
+        );
+        assert_eq!(*changes.borrow(), 2);
+
+        document.set_selected_index(select, None).unwrap();
+        assert!(document.get().selected_options(select).is_empty());
+        assert_eq!(document.get().selected_index(select), None);
+        assert_eq!(document.get().select_value(select), "");
+    }
+}
@@ -0,0 +1,895 @@
+//! CSS selectors
+//!
+//! Parses selector lists (like `ul > li.active a[href^="https:"]`) and matches them against the
+//! elements of a document. The following selectors are supported:
+//!
+//! - type (`p`), universal (`*`), id (`#main`) and class (`.item`) selectors
+//! - attribute selectors: `[a]`, `[a=v]`, `[a~=v]`, `[a|=v]`, `[a^=v]`, `[a$=v]`, `[a*=v]`,
+//!   optionally with an `i` or `s` flag
+//! - the descendant (` `), child (`>`), next-sibling (`+`) and subsequent-sibling (`~`)
+//!   combinators
+//! - the structural pseudo-classes `:root`, `:empty`, `:first-child`, `:last-child`,
+//!   `:only-child`, `:first-of-type`, `:last-of-type`, `:only-of-type`, `:nth-child()`,
+//!   `:nth-last-child()`, `:nth-of-type()` and `:nth-last-of-type()`
+//! - the logical pseudo-classes `:not()`, `:is()` and `:where()`
+//! - the input pseudo-classes `:link`, `:checked`, `:disabled` and `:enabled`
+//!
+//! Pseudo-elements never match an element, so they are rejected by the parser.
+//!
+//! See: https://drafts.csswg.org/selectors-4/
+use crate::html5::node::{Node, NodeData, NodeId, HTML_NAMESPACE};
+use crate::html5::parser::document::Document;
+use crate::types::{Error, Result};
+use std::collections::HashSet;
+
+/// Elements that can be disabled
+const DISABLEABLE_ELEMENTS: [&str; 7] = [
+    "button", "fieldset", "input", "optgroup", "option", "select", "textarea",
+];
+
+/// A parsed selector list. An element matches when it matches any of the selectors in the list.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Selector {
+    selectors: Vec<ComplexSelector>,
+}
+
+/// Compound selectors joined by combinators (from left to right)
+#[derive(Debug, Clone, PartialEq)]
+struct ComplexSelector {
+    compounds: Vec<Vec<SimpleSelector>>,
+    /// The combinator between compounds[i] and compounds[i + 1]
+    combinators: Vec<Combinator>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Combinator {
+    Descendant,
+    Child,
+    NextSibling,
+    SubsequentSibling,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum SimpleSelector {
+    Universal,
+    Type(String),
+    Id(String),
+    Class(String),
+    Attribute {
+        name: String,
+        operator: Option<AttributeOperator>,
+        value: String,
+        case_insensitive: bool,
+    },
+    PseudoClass(PseudoClass),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum AttributeOperator {
+    /// `=`
+    Equals,
+    /// `~=`
+    Includes,
+    /// `|=`
+    DashMatch,
+    /// `^=`
+    Prefix,
+    /// `$=`
+    Suffix,
+    /// `*=`
+    Substring,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum PseudoClass {
+    Root,
+    Empty,
+    /// `:nth-child(an+b)` and friends. `of_type` only counts siblings with the same name,
+    /// `from_end` counts from the last sibling.
+    Nth {
+        a: i32,
+        b: i32,
+        of_type: bool,
+        from_end: bool,
+    },
+    /// `:only-child` and `:only-of-type`
+    Only {
+        of_type: bool,
+    },
+    Not(Selector),
+    /// `:is()` and `:where()`
+    Is(Selector),
+    Link,
+    Checked,
+    Disabled,
+    Enabled,
+}
+
+impl Selector {
+    /// Parses the given selector list
+    pub fn parse(selector: &str) -> Result<Selector> {
+        let mut parser = SelectorParser {
+            input: selector.chars().collect(),
+            pos: 0,
+        };
+
+        let selector = parser.selector_list()?;
+        parser.skip_whitespace();
+        if let Some(c) = parser.peek() {
+            return Err(parser.error(&format!("unexpected character '{}'", c)));
+        }
+
+        Ok(selector)
+    }
+
+    /// Returns true when the given node is an element that matches the selector
+    pub fn matches(&self, document: &Document, node_id: NodeId) -> bool {
+        let Some(node) = get_element(document, node_id) else {
+            return false;
+        };
+
+        self.selectors
+            .iter()
+            .any(|selector| matches_complex(document, selector, selector.compounds.len() - 1, node))
+    }
+}
+
+/// What matching a selector depends on, besides the name of the element
+#[derive(Debug, Clone, Default, PartialEq)]
+pub(crate) struct Dependencies {
+    /// Names of the attributes that are read
+    pub(crate) attributes: HashSet<String>,
+    /// True when matching looks at other nodes than the element itself (ancestors, siblings or
+    /// children)
+    pub(crate) structural: bool,
+}
+
+impl Selector {
+    /// Returns what matching the selector depends on
+    pub(crate) fn dependencies(&self) -> Dependencies {
+        let mut dependencies = Dependencies::default();
+        self.collect_dependencies(&mut dependencies);
+        dependencies
+    }
+
+    fn collect_dependencies(&self, dependencies: &mut Dependencies) {
+        for selector in &self.selectors {
+            if selector.compounds.len() > 1 {
+                dependencies.structural = true;
+            }
+
+            for simple in selector.compounds.iter().flatten() {
+                let attributes: &[&str] = match simple {
+                    SimpleSelector::Universal | SimpleSelector::Type(_) => &[],
+                    SimpleSelector::Id(_) => &["id"],
+                    SimpleSelector::Class(_) => &["class"],
+                    SimpleSelector::Attribute { name, .. } => {
+                        dependencies.attributes.insert(name.clone());
+                        &[]
+                    }
+                    SimpleSelector::PseudoClass(pseudo_class) => match pseudo_class {
+                        PseudoClass::Root
+                        | PseudoClass::Empty
+                        | PseudoClass::Nth { .. }
+                        | PseudoClass::Only { .. } => {
+                            dependencies.structural = true;
+                            &[]
+                        }
+                        PseudoClass::Not(selector) | PseudoClass::Is(selector) => {
+                            selector.collect_dependencies(dependencies);
+                            &[]
+                        }
+                        PseudoClass::Link => &["href"],
+                        PseudoClass::Checked => &["type", "checked", "selected"],
+                        PseudoClass::Disabled | PseudoClass::Enabled => &["disabled"],
+                    },
+                };
+                dependencies
+                    .attributes
+                    .extend(attributes.iter().map(|name| name.to_string()));
+            }
+        }
+    }
+}
+
+/// Returns the node when it is an element
+fn get_element(document: &Document, node_id: NodeId) -> Option<&Node> {
+    document
+        .get_node_by_id(node_id)
+        .filter(|node| matches!(node.data, NodeData::Element(_)))
+}
+
+fn get_attribute<'a>(node: &'a Node, name: &str) -> Option<&'a str> {
+    match &node.data {
+        NodeData::Element(element) => element.attributes.get(name).map(String::as_str),
+        _ => None,
+    }
+}
+
+fn parent_element<'a>(document: &'a Document, node: &Node) -> Option<&'a Node> {
+    get_element(document, node.parent?)
+}
+
+/// Returns the element siblings of the node (including the node itself), in tree order
+fn sibling_elements<'a>(document: &'a Document, node: &Node) -> Vec<&'a Node> {
+    let Some(parent) = node.parent.and_then(|id| document.get_node_by_id(id)) else {
+        return vec![];
+    };
+
+    parent
+        .children
+        .iter()
+        .filter_map(|&id| get_element(document, id))
+        .collect()
+}
+
+fn matches_complex(
+    document: &Document,
+    selector: &ComplexSelector,
+    index: usize,
+    node: &Node,
+) -> bool {
+    if !selector.compounds[index]
+        .iter()
+        .all(|simple| matches_simple(document, simple, node))
+    {
+        return false;
+    }
+    if index == 0 {
+        return true;
+    }
+
+    match selector.combinators[index - 1] {
+        Combinator::Child => parent_element(document, node)
+            .is_some_and(|parent| matches_complex(document, selector, index - 1, parent)),
+        Combinator::Descendant => {
+            let mut current = parent_element(document, node);
+            while let Some(ancestor) = current {
+                if matches_complex(document, selector, index - 1, ancestor) {
+                    return true;
+                }
+                current = parent_element(document, ancestor);
+            }
+            false
+        }
+        Combinator::NextSibling => {
+            let siblings = sibling_elements(document, node);
+            let position = siblings.iter().position(|n| n.id == node.id);
+            position
+                .and_then(|position| position.checked_sub(1))
+                .is_some_and(|previous| {
+                    matches_complex(document, selector, index - 1, siblings[previous])
+                })
+        }
+        Combinator::SubsequentSibling => sibling_elements(document, node)
+            .into_iter()
+            .take_while(|n| n.id != node.id)
+            .any(|sibling| matches_complex(document, selector, index - 1, sibling)),
+    }
+}
+
+fn matches_simple(document: &Document, selector: &SimpleSelector, node: &Node) -> bool {
+    match selector {
+        SimpleSelector::Universal => true,
+        SimpleSelector::Type(name) => {
+            if node.is_namespace(HTML_NAMESPACE) {
+                node.name.eq_ignore_ascii_case(name)
+            } else {
+                node.name == *name
+            }
+        }
+        SimpleSelector::Id(id) => get_attribute(node, "id") == Some(id.as_str()),
+        SimpleSelector::Class(class) => get_attribute(node, "class")
+            .is_some_and(|classes| classes.split_ascii_whitespace().any(|c| c == class)),
+        SimpleSelector::Attribute {
+            name,
+            operator,
+            value,
+            case_insensitive,
+        } => {
+            let Some(actual) = get_attribute(node, name) else {
+                return false;
+            };
+            let Some(operator) = operator else {
+                return true;
+            };
+
+            let (actual, value) = if *case_insensitive {
+                (actual.to_ascii_lowercase(), value.to_ascii_lowercase())
+            } else {
+                (actual.to_string(), value.clone())
+            };
+
+            match operator {
+                AttributeOperator::Equals => actual == value,
+                AttributeOperator::Includes => {
+                    !value.is_empty() && actual.split_ascii_whitespace().any(|v| v == value)
+                }
+                AttributeOperator::DashMatch => {
+                    actual == value || actual.starts_with(&format!("{}-", value))
+                }
+                AttributeOperator::Prefix => !value.is_empty() && actual.starts_with(&value),
+                AttributeOperator::Suffix => !value.is_empty() && actual.ends_with(&value),
+                AttributeOperator::Substring => !value.is_empty() && actual.contains(&value),
+            }
+        }
+        SimpleSelector::PseudoClass(pseudo_class) => {
+            matches_pseudo_class(document, pseudo_class, node)
+        }
+    }
+}
+
+fn matches_pseudo_class(document: &Document, pseudo_class: &PseudoClass, node: &Node) -> bool {
+    match pseudo_class {
+        PseudoClass::Root => node.parent == Some(NodeId::root()),
+        PseudoClass::Empty => node.children.iter().all(|&id| {
+            document
+                .get_node_by_id(id)
+                .map_or(true, |child| match &child.data {
+                    NodeData::Element(_) => false,
+                    NodeData::Text(text) => text.value().is_empty(),
+                    _ => true,
+                })
+        }),
+        PseudoClass::Nth {
+            a,
+            b,
+            of_type,
+            from_end,
+        } => {
+            let mut siblings = sibling_elements(document, node);
+            if *of_type {
+                siblings.retain(|sibling| {
+                    sibling.name == node.name && sibling.namespace == node.namespace
+                });
+            }
+            if *from_end {
+                siblings.reverse();
+            }
+
+            match siblings.iter().position(|sibling| sibling.id == node.id) {
+                Some(position) => nth_matches(*a, *b, position as i32 + 1),
+                None => false,
+            }
+        }
+        PseudoClass::Only { of_type } => {
+            sibling_elements(document, node)
+                .iter()
+                .filter(|sibling| {
+                    !of_type || (sibling.name == node.name && sibling.namespace == node.namespace)
+                })
+                .count()
+                == 1
+        }
+        PseudoClass::Not(selector) => !selector.matches(document, node.id),
+        PseudoClass::Is(selector) => selector.matches(document, node.id),
+        PseudoClass::Link => {
+            node.is_namespace(HTML_NAMESPACE)
+                && (node.name == "a" || node.name == "area")
+                && get_attribute(node, "href").is_some()
+        }
+        PseudoClass::Checked => {
+            if !node.is_namespace(HTML_NAMESPACE) {
+                return false;
+            }
+            match node.name.as_str() {
+                "input" => {
+                    let input_type = get_attribute(node, "type").unwrap_or_default();
+                    (input_type.eq_ignore_ascii_case("checkbox")
+                        || input_type.eq_ignore_ascii_case("radio"))
+                        && get_attribute(node, "checked").is_some()
+                }
+                "option" => get_attribute(node, "selected").is_some(),
+                _ => false,
+            }
+        }
+        PseudoClass::Disabled => {
+            node.is_namespace(HTML_NAMESPACE)
+                && DISABLEABLE_ELEMENTS.contains(&node.name.as_str())
+                && get_attribute(node, "disabled").is_some()
+        }
+        PseudoClass::Enabled => {
+            node.is_namespace(HTML_NAMESPACE)
+                && DISABLEABLE_ELEMENTS.contains(&node.name.as_str())
+                && get_attribute(node, "disabled").is_none()
+        }
+    }
+}
+
+/// Returns true when there is an n >= 0 such that a*n + b == index
+fn nth_matches(a: i32, b: i32, index: i32) -> bool {
+    if a == 0 {
+        return index == b;
+    }
+
+    let diff = index - b;
+    diff % a == 0 && diff / a >= 0
+}
+
+struct SelectorParser {
+    input: Vec<char>,
+    pos: usize,
+}
+
+impl SelectorParser {
+    fn error(&self, message: &str) -> Error {
+        Error::Parse(format!("invalid selector at {}: {}", self.pos, message))
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.input.get(self.pos).copied()
+    }
+
+    fn peek_at(&self, offset: usize) -> Option<char> {
+        self.input.get(self.pos + offset).copied()
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.peek();
+        self.pos += 1;
+        c
+    }
+
+    fn expect(&mut self, expected: char) -> Result<()> {
+        match self.next() {
+            Some(c) if c == expected => Ok(()),
+            _ => Err(self.error(&format!("expected '{}'", expected))),
+        }
+    }
+
+    /// Skips whitespace, and returns true when there was any
+    fn skip_whitespace(&mut self) -> bool {
+        let start = self.pos;
+        while self.peek().is_some_and(|c| c.is_ascii_whitespace()) {
+            self.pos += 1;
+        }
+        self.pos > start
+    }
+
+    fn selector_list(&mut self) -> Result<Selector> {
+        let mut selectors = vec![self.complex_selector()?];
+
+        loop {
+            self.skip_whitespace();
+            if self.peek() != Some(',') {
+                break;
+            }
+            self.pos += 1;
+            selectors.push(self.complex_selector()?);
+        }
+
+        Ok(Selector { selectors })
+    }
+
+    fn complex_selector(&mut self) -> Result<ComplexSelector> {
+        self.skip_whitespace();
+
+        let mut compounds = vec![self.compound_selector()?];
+        let mut combinators = vec![];
+
+        loop {
+            let had_whitespace = self.skip_whitespace();
+            let combinator = match self.peek() {
+                Some('>') => Combinator::Child,
+                Some('+') => Combinator::NextSibling,
+                Some('~') => Combinator::SubsequentSibling,
+                Some(',' | ')') | None => break,
+                Some(_) if had_whitespace => Combinator::Descendant,
+                Some(c) => return Err(self.error(&format!("unexpected character '{}'", c))),
+            };
+            if combinator != Combinator::Descendant {
+                self.pos += 1;
+                self.skip_whitespace();
+            }
+
+            combinators.push(combinator);
+            compounds.push(self.compound_selector()?);
+        }
+
+        Ok(ComplexSelector {
+            compounds,
+            combinators,
+        })
+    }
+
+    fn compound_selector(&mut self) -> Result<Vec<SimpleSelector>> {
+        let mut compound = vec![];
+
+        if self.peek() == Some('*') {
+            self.pos += 1;
+            compound.push(SimpleSelector::Universal);
+        } else if self.is_ident_start() {
+            compound.push(SimpleSelector::Type(self.ident()?));
+        }
+
+        loop {
+            match self.peek() {
+                Some('#') => {
+                    self.pos += 1;
+                    compound.push(SimpleSelector::Id(self.name()?));
+                }
+                Some('.') => {
+                    self.pos += 1;
+                    compound.push(SimpleSelector::Class(self.ident()?));
+                }
+                Some('[') => {
+                    self.pos += 1;
+                    compound.push(self.attribute_selector()?);
+                }
+                Some(':') => {
+                    self.pos += 1;
+                    if self.peek() == Some(':') {
+                        return Err(self.error("pseudo-elements are not supported"));
+                    }
+                    compound.push(SimpleSelector::PseudoClass(self.pseudo_class()?));
+                }
+                _ => break,
+            }
+        }
+
+        if compound.is_empty() {
+            return Err(self.error("expected a selector"));
+        }
+
+        Ok(compound)
+    }
+
+    fn attribute_selector(&mut self) -> Result<SimpleSelector> {
+        self.skip_whitespace();
+        let name = self.ident()?.to_ascii_lowercase();
+        self.skip_whitespace();
+
+        let operator = match (self.peek(), self.peek_at(1)) {
+            (Some(']'), _) => None,
+            (Some('='), _) => Some(AttributeOperator::Equals),
+            (Some('~'), Some('=')) => Some(AttributeOperator::Includes),
+            (Some('|'), Some('=')) => Some(AttributeOperator::DashMatch),
+            (Some('^'), Some('=')) => Some(AttributeOperator::Prefix),
+            (Some('$'), Some('=')) => Some(AttributeOperator::Suffix),
+            (Some('*'), Some('=')) => Some(AttributeOperator::Substring),
+            _ => return Err(self.error("expected an attribute operator")),
+        };
+
+        let mut value = String::new();
+        let mut case_insensitive = false;
+
+        if let Some(operator) = operator {
+            self.pos += if operator == AttributeOperator::Equals {
+                1
+            } else {
+                2
+            };
+            self.skip_whitespace();
+
+            value = match self.peek() {
+                Some(quote @ ('"' | '\'')) => self.string(quote)?,
+                _ => self.ident()?,
+            };
+
+            self.skip_whitespace();
+            match self.peek() {
+                Some('i' | 'I') => {
+                    self.pos += 1;
+                    case_insensitive = true;
+                }
+                Some('s' | 'S') => self.pos += 1,
+                _ => {}
+            }
+            self.skip_whitespace();
+        }
+
+        self.expect(']')?;
+
+        Ok(SimpleSelector::Attribute {
+            name,
+            operator,
+            value,
+            case_insensitive,
+        })
+    }
+
+    fn pseudo_class(&mut self) -> Result<PseudoClass> {
+        let name = self.ident()?.to_ascii_lowercase();
+
+        if self.peek() != Some('(') {
+            let nth = |of_type, from_end| PseudoClass::Nth {
+                a: 0,
+                b: 1,
+                of_type,
+                from_end,
+            };
+            return match name.as_str() {
+                "root" => Ok(PseudoClass::Root),
+                "empty" => Ok(PseudoClass::Empty),
+                "first-child" => Ok(nth(false, false)),
+                "last-child" => Ok(nth(false, true)),
+                "first-of-type" => Ok(nth(true, false)),
+                "last-of-type" => Ok(nth(true, true)),
+                "only-child" => Ok(PseudoClass::Only { of_type: false }),
+                "only-of-type" => Ok(PseudoClass::Only { of_type: true }),
+                "link" | "any-link" => Ok(PseudoClass::Link),
+                "checked" => Ok(PseudoClass::Checked),
+                "disabled" => Ok(PseudoClass::Disabled),
+                "enabled" => Ok(PseudoClass::Enabled),
+                _ => Err(self.error(&format!("unsupported pseudo-class ':{}'", name))),
+            };
+        }
+
+        self.pos += 1;
+        let pseudo_class = match name.as_str() {
+            "not" => PseudoClass::Not(self.selector_list()?),
+            "is" | "where" => PseudoClass::Is(self.selector_list()?),
+            "nth-child" | "nth-last-child" | "nth-of-type" | "nth-last-of-type" => {
+                let (a, b) = self.an_plus_b()?;
+                PseudoClass::Nth {
+                    a,
+                    b,
+                    of_type: name.ends_with("of-type"),
+                    from_end: name.starts_with("nth-last"),
+                }
+            }
+            _ => return Err(self.error(&format!("unsupported pseudo-class ':{}()'", name))),
+        };
+        self.skip_whitespace();
+        self.expect(')')?;
+
+        Ok(pseudo_class)
+    }
+
+    /// Parses the An+B microsyntax (up to the closing parenthesis)
+    ///
+    /// See: https://drafts.csswg.org/css-syntax-3/#anb-microsyntax
+    fn an_plus_b(&mut self) -> Result<(i32, i32)> {
+        let start = self.pos;
+        while self.peek().is_some_and(|c| c != ')') {
+            self.pos += 1;
+        }
+
+        let value: String = self.input[start..self.pos]
+            .iter()
+            .filter(|c| !c.is_ascii_whitespace())
+            .collect::<String>()
+            .to_ascii_lowercase();
+
+        let parse_int = |s: &str| {
+            s.parse::<i32>()
+                .map_err(|_| self.error("invalid An+B value"))
+        };
+
+        match value.as_str() {
+            "odd" => Ok((2, 1)),
+            "even" => Ok((2, 0)),
+            _ => match value.split_once('n') {
+                Some((a, b)) => {
+                    let a = match a {
+                        "" | "+" => 1,
+                        "-" => -1,
+                        a => parse_int(a)?,
+                    };
+                    let b = match b {
+                        "" => 0,
+                        b if b.starts_with(['+', '-']) => parse_int(b)?,
+                        _ => return Err(self.error("invalid An+B value")),
+                    };
+                    Ok((a, b))
+                }
+                None => Ok((0, parse_int(&value)?)),
+            },
+        }
+    }
+
+    fn is_name_char(c: char) -> bool {
+        c.is_ascii_alphanumeric() || c == '-' || c == '_' || !c.is_ascii()
+    }
+
+    fn is_ident_start(&self) -> bool {
+        match (self.peek(), self.peek_at(1)) {
+            (Some('-'), Some(c)) => {
+                c == '-' || c == '\\' || (Self::is_name_char(c) && !c.is_ascii_digit())
+            }
+            (Some('\\'), _) => true,
+            (Some(c), _) => Self::is_name_char(c) && !c.is_ascii_digit() && c != '-',
+            _ => false,
+        }
+    }
+
+    fn ident(&mut self) -> Result<String> {
+        if !self.is_ident_start() {
+            return Err(self.error("expected an identifier"));
+        }
+        self.name()
+    }
+
+    /// Consumes a sequence of name characters (and escapes)
+    fn name(&mut self) -> Result<String> {
+        let mut name = String::new();
+
+        while let Some(c) = self.peek() {
+            if c == '\\' {
+                self.pos += 1;
+                name.push(self.escape()?);
+            } else if Self::is_name_char(c) {
+                self.pos += 1;
+                name.push(c);
+            } else {
+                break;
+            }
+        }
+
+        if name.is_empty() {
+            return Err(self.error("expected a name"));
+        }
+        Ok(name)
+    }
+
+    /// Consumes an escape sequence (after the backslash)
+    fn escape(&mut self) -> Result<char> {
+        let start = self.pos;
+        while self.pos - start < 6 && self.peek().is_some_and(|c| c.is_ascii_hexdigit()) {
+            self.pos += 1;
+        }
+
+        if self.pos == start {
+            return self
+                .next()
+                .ok_or_else(|| self.error("unexpected end of escape"));
+        }
+
+        let hex: String = self.input[start..self.pos].iter().collect();
+        if self.peek().is_some_and(|c| c.is_ascii_whitespace()) {
+            self.pos += 1;
+        }
+
+        Ok(u32::from_str_radix(&hex, 16)
+            .ok()
+            .and_then(char::from_u32)
+            .filter(|&c| c != '\0')
+            .unwrap_or(char::REPLACEMENT_CHARACTER))
+    }
+
+    fn string(&mut self, quote: char) -> Result<String> {
+        self.pos += 1;
+        let mut value = String::new();
+
+        loop {
+            match self.next() {
+                Some(c) if c == quote => return Ok(value),
+                Some('\\') => value.push(self.escape()?),
+                Some(c) => value.push(c),
+                None => return Err(self.error("unterminated string")),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::html5::parser::document::DocumentHandle;
+    use crate::testing::parse;
+
+    /// Returns the ids of all elements matching the selector, in tree order
+    fn select(document: &DocumentHandle, selector: &str) -> Vec<String> {
+        let selector = Selector::parse(selector).expect("invalid selector");
+        let doc = document.get();
+
+        let mut result = vec![];
+        let mut stack = vec![NodeId::root()];
+        while let Some(node_id) = stack.pop() {
+            let node = doc.get_node_by_id(node_id).unwrap();
+            if selector.matches(&doc, node_id) {
+                result.push(get_attribute(node, "id").unwrap_or("?").to_string());
+            }
+            stack.extend(node.children.iter().rev());
+        }
+        result
+    }
+
+    #[test]
+    fn simple_selectors() {
+        let document = parse(
+            r#"<div id=a class="x y"><p id=b class=x lang=en-US title="Hello World"></p>
+            <P id=c data-v=""></P><svg id=d><foreignObject id=e /></svg></div>"#,
+        );
+
+        assert_eq!(select(&document, "p"), vec!["b", "c"]);
+        assert_eq!(select(&document, "DIV"), vec!["a"]);
+        assert_eq!(select(&document, "foreignObject"), vec!["e"]);
+        assert_eq!(select(&document, "foreignobject"), Vec::<String>::new());
+        assert_eq!(select(&document, "#c"), vec!["c"]);
+        assert_eq!(select(&document, ".x"), vec!["a", "b"]);
+        assert_eq!(select(&document, ".x.y"), vec!["a"]);
+        assert_eq!(select(&document, "p.x, #d"), vec!["b", "d"]);
+        assert_eq!(select(&document, "[data-v]"), vec!["c"]);
+        assert_eq!(select(&document, "[lang|=en]"), vec!["b"]);
+        assert_eq!(select(&document, "[title~=World]"), vec!["b"]);
+        assert_eq!(select(&document, "[title^='hello' i]"), vec!["b"]);
+        assert_eq!(select(&document, "[title$=\"World\"]"), vec!["b"]);
+        assert_eq!(select(&document, "[title*=o\\ W]"), vec!["b"]);
+        assert_eq!(select(&document, "[data-v^='']"), Vec::<String>::new());
+        assert_eq!(select(&document, "#a *").len(), 4);
+    }
+
+    #[test]
+    fn combinators() {
+        let document = parse(
+            "<ul id=a><li id=b><a id=c></a></li><li id=d></li><li id=e><ul id=f><li id=g></li></ul></li></ul>",
+        );
+
+        assert_eq!(select(&document, "ul li"), vec!["b", "d", "e", "g"]);
+        assert_eq!(select(&document, "#a > li"), vec!["b", "d", "e"]);
+        assert_eq!(select(&document, "#a>li>a"), vec!["c"]);
+        assert_eq!(select(&document, "li + li"), vec!["d", "e"]);
+        assert_eq!(select(&document, "#b ~ li"), vec!["d", "e"]);
+        assert_eq!(select(&document, "ul ul > li"), vec!["g"]);
+        assert_eq!(select(&document, "body > ul li li"), vec!["g"]);
+    }
+
+    #[test]
+    fn pseudo_classes() {
+        let document = parse(
+            r#"<div id=a><p id=b></p><span id=c>x</span><p id=d><!-- c --></p><p id=e></p></div>
+            <form id=f><input id=g type=checkbox checked><input id=h disabled><a id=i href=x></a><a id=j></a></form>"#,
+        );
+
+        assert_eq!(select(&document, ":root"), vec!["?"]);
+        assert_eq!(select(&document, "#a > :first-child"), vec!["b"]);
+        assert_eq!(select(&document, "#a > :last-child"), vec!["e"]);
+        assert_eq!(select(&document, "#a > p:first-of-type"), vec!["b"]);
+        assert_eq!(select(&document, "#a > :only-of-type"), vec!["c"]);
+        assert_eq!(select(&document, "#a > :nth-child(2n+1)"), vec!["b", "d"]);
+        assert_eq!(select(&document, "#a > :nth-child(even)"), vec!["c", "e"]);
+        assert_eq!(
+            select(&document, "#a > :nth-last-child(-n + 2)"),
+            vec!["d", "e"]
+        );
+        assert_eq!(select(&document, "#a > p:nth-of-type(2)"), vec!["d"]);
+        assert_eq!(select(&document, "#a > :empty"), vec!["b", "d", "e"]);
+        assert_eq!(select(&document, "#a > :not(p)"), vec!["c"]);
+        assert_eq!(select(&document, "#a > :is(#b, #c)"), vec!["b", "c"]);
+        assert_eq!(select(&document, ":checked"), vec!["g"]);
+        assert_eq!(select(&document, ":disabled"), vec!["h"]);
+        assert_eq!(select(&document, "input:enabled"), vec!["g"]);
+        assert_eq!(select(&document, ":link"), vec!["i"]);
+    }
+
+    #[test]
+    fn invalid_selectors() {
+        for selector in [
+            "",
+            "p,",
+            "> p",
+            "p >",
+            "#",
+            ".1a",
+            "[a=]",
+            "[a",
+            "p::before",
+            ":hover",
+            ":nth-child(x)",
+            "a b)",
+            "p $",
+        ] {
+            assert!(Selector::parse(selector).is_err(), "{}", selector);
+        }
+    }
+
+    #[test]
+    fn dependencies() {
+        let dependencies = Selector::parse("a#x.y[data-z], :not(:link)")
+            .unwrap()
+            .dependencies();
+        let mut attributes: Vec<_> = dependencies.attributes.into_iter().collect();
+        attributes.sort();
+        assert_eq!(attributes, vec!["class", "data-z", "href", "id"]);
+        assert!(!dependencies.structural);
+
+        for selector in ["ul li", "li:first-child", ":is(p:empty)", "a, b + c"] {
+            assert!(
+                Selector::parse(selector).unwrap().dependencies().structural,
+                "{}",
+                selector
+            );
+        }
+    }
+}
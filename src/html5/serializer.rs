@@ -0,0 +1,536 @@
+//! HTML serializer
+//!
+//! Turns (parts of) a document back into HTML markup. By default the output follows the
+//! "serializing HTML fragments" algorithm, but the serializer can also minify its output by
+//! dropping optional end tags, collapsing whitespace, and removing comments and attributes that
+//! have their default value.
+//!
+//! See: https://html.spec.whatwg.org/multipage/parsing.html#serialising-html-fragments
+use crate::html5::node::data::comment::CommentData;
+use crate::html5::node::data::doctype::DocTypeData;
+use crate::html5::node::data::text::TextData;
+use crate::html5::node::{Node, NodeData, NodeId, HTML_NAMESPACE};
+use crate::html5::parser::document::{Document, DocumentHandle};
+
+/// Elements that never have any contents, and thus no end tag
+const VOID_ELEMENTS: [&str; 18] = [
+    "area", "base", "basefont", "bgsound", "br", "col", "embed", "frame", "hr", "img", "input",
+    "keygen", "link", "meta", "param", "source", "track", "wbr",
+];
+
+/// Elements whose text contents are serialized without escaping
+const RAW_TEXT_ELEMENTS: [&str; 7] = [
+    "style",
+    "script",
+    "xmp",
+    "iframe",
+    "noembed",
+    "noframes",
+    "plaintext",
+];
+
+/// Elements in which whitespace is significant and must not be collapsed
+const PREFORMATTED_ELEMENTS: [&str; 4] = ["pre", "textarea", "listing", "plaintext"];
+
+/// Elements in which whitespace-only text does not render, and can be removed when minifying
+const WHITESPACE_INSENSITIVE_ELEMENTS: [&str; 16] = [
+    "html", "head", "table", "caption", "colgroup", "thead", "tbody", "tfoot", "tr", "ul", "ol",
+    "dl", "select", "optgroup", "datalist", "frameset",
+];
+
+/// Elements that close an open `<p>` element, so the `</p>` before them can be omitted
+const CLOSES_PARAGRAPH: [&str; 31] = [
+    "address",
+    "article",
+    "aside",
+    "blockquote",
+    "details",
+    "dialog",
+    "div",
+    "dl",
+    "fieldset",
+    "figcaption",
+    "figure",
+    "footer",
+    "form",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "header",
+    "hgroup",
+    "hr",
+    "main",
+    "menu",
+    "nav",
+    "ol",
+    "p",
+    "pre",
+    "section",
+    "table",
+    "ul",
+];
+
+/// Attribute values that are equal to the value an element has when the attribute is missing
+/// (element, attribute, default value)
+const DEFAULT_ATTRIBUTES: [(&str, &str, &str); 16] = [
+    ("area", "shape", "rect"),
+    ("button", "type", "submit"),
+    ("form", "autocomplete", "on"),
+    ("form", "enctype", "application/x-www-form-urlencoded"),
+    ("form", "method", "get"),
+    ("input", "type", "text"),
+    ("link", "type", "text/css"),
+    ("script", "language", "javascript"),
+    ("script", "type", "text/javascript"),
+    ("style", "type", "text/css"),
+    ("td", "colspan", "1"),
+    ("td", "rowspan", "1"),
+    ("textarea", "wrap", "soft"),
+    ("th", "colspan", "1"),
+    ("th", "rowspan", "1"),
+    ("track", "kind", "subtitles"),
+];
+
+/// Options for the serializer. The default options produce regular (non-minified) output.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct SerializerOptions {
+    /// Do not output comments
+    pub remove_comments: bool,
+    /// Collapse runs of whitespace into a single space, and remove whitespace-only text where
+    /// it does not render (e.g. between table rows)
+    pub collapse_whitespace: bool,
+    /// Omit end tags that the parser will imply (e.g. `</li>` or `</p>`)
+    pub drop_optional_end_tags: bool,
+    /// Remove attributes that have their default value (e.g. `<input type="text">`)
+    pub remove_default_attributes: bool,
+}
+
+impl SerializerOptions {
+    /// Options that minify the output as much as possible
+    pub fn minified() -> Self {
+        Self {
+            remove_comments: true,
+            collapse_whitespace: true,
+            drop_optional_end_tags: true,
+            remove_default_attributes: true,
+        }
+    }
+}
+
+/// Serializes the children of the given node. When called with the root node, this serializes
+/// the whole document.
+pub fn serialize(document: &Document, node_id: NodeId, options: &SerializerOptions) -> String {
+    let mut serializer = Serializer::new(document, options);
+    if let Some(node) = document.get_node_by_id(node_id) {
+        serializer.enter(node);
+        serializer.serialize_children(node);
+    }
+    serializer.output
+}
+
+/// Serializes the given node itself, including its children (like `outerHTML`)
+pub fn serialize_node(document: &Document, node_id: NodeId, options: &SerializerOptions) -> String {
+    let mut serializer = Serializer::new(document, options);
+    if let Some(node) = document.get_node_by_id(node_id) {
+        let parent = node.parent.and_then(|id| document.get_node_by_id(id));
+        if let Some(parent) = parent {
+            serializer.enter(parent);
+        }
+        serializer.serialize_node(node, None, parent);
+    }
+    serializer.output
+}
+
+impl Document {
+    /// Serializes the whole document to HTML that parses back into the same tree
+    pub fn serialize(&self) -> String {
+        serialize(self, NodeId::root(), &SerializerOptions::default())
+    }
+}
+
+impl DocumentHandle {
+    /// Serializes the whole document to HTML. See `Document::serialize()`.
+    pub fn serialize(&self) -> String {
+        self.get().serialize()
+    }
+}
+
+struct Serializer<'a> {
+    document: &'a Document,
+    options: &'a SerializerOptions,
+    output: String,
+    /// Number of preformatted elements we are in. Whitespace is kept as-is when non-zero.
+    preformatted: usize,
+}
+
+impl<'a> Serializer<'a> {
+    fn new(document: &'a Document, options: &'a SerializerOptions) -> Self {
+        Self {
+            document,
+            options,
+            output: String::new(),
+            preformatted: 0,
+        }
+    }
+
+    /// Sets up the whitespace state for serializing inside the given node
+    fn enter(&mut self, node: &Node) {
+        let mut current = Some(node);
+        while let Some(node) = current {
+            if is_preformatted(node) {
+                self.preformatted += 1;
+            }
+            current = node.parent.and_then(|id| self.document.get_node_by_id(id));
+        }
+    }
+
+    fn serialize_children(&mut self, node: &Node) {
+        let children = self.emitted_children(node);
+        for (i, child) in children.iter().enumerate() {
+            self.serialize_node(child, children.get(i + 1).copied(), Some(node));
+        }
+    }
+
+    /// Returns the children of the node that end up in the output
+    fn emitted_children(&self, node: &'a Node) -> Vec<&'a Node> {
+        node.children
+            .iter()
+            .filter_map(|id| self.document.get_node_by_id(*id))
+            .filter(|child| match &child.data {
+                NodeData::Comment(_) => !self.options.remove_comments,
+                NodeData::Text(TextData { value }) => {
+                    !(self.options.collapse_whitespace
+                        && self.preformatted == 0
+                        && is_whitespace(value)
+                        && (node.id.is_root()
+                            || is_html_element(node, &WHITESPACE_INSENSITIVE_ELEMENTS)))
+                }
+                _ => true,
+            })
+            .collect()
+    }
+
+    fn serialize_node(&mut self, node: &Node, next: Option<&Node>, parent: Option<&Node>) {
+        match &node.data {
+            NodeData::Document(_) => self.serialize_children(node),
+            NodeData::DocType(DocTypeData {
+                name,
+                pub_identifier,
+                sys_identifier,
+            }) => {
+                self.output.push_str("<!DOCTYPE ");
+                self.output.push_str(name);
+                // Legacy identifiers are kept, as they decide the quirks mode of the document
+                if !pub_identifier.is_empty() {
+                    self.output.push_str(" PUBLIC \"");
+                    self.output.push_str(pub_identifier);
+                    self.output.push('"');
+                    if !sys_identifier.is_empty() {
+                        self.output.push_str(" \"");
+                        self.output.push_str(sys_identifier);
+                        self.output.push('"');
+                    }
+                } else if !sys_identifier.is_empty() {
+                    self.output.push_str(" SYSTEM \"");
+                    self.output.push_str(sys_identifier);
+                    self.output.push('"');
+                }
+                self.output.push('>');
+            }
+            NodeData::Comment(CommentData { value }) => {
+                self.output.push_str("<!--");
+                self.output.push_str(value);
+                self.output.push_str("-->");
+            }
+            NodeData::Text(TextData { value }) => {
+                if parent.is_some_and(|p| is_html_element(p, &RAW_TEXT_ELEMENTS)) {
+                    self.output.push_str(value);
+                } else if self.options.collapse_whitespace && self.preformatted == 0 {
+                    let mut collapsed = collapse_whitespace(value);
+                    // Adjacent text nodes (e.g. around a removed comment) share their whitespace
+                    if self.output.ends_with(' ') && collapsed.starts_with(' ') {
+                        collapsed.remove(0);
+                    }
+                    self.output.push_str(&escape_text(&collapsed));
+                } else {
+                    self.output.push_str(&escape_text(value));
+                }
+            }
+            NodeData::Element(element) => {
+                self.output.push('<');
+                self.output.push_str(&node.name);
+
+                let mut attributes: Vec<_> = element.attributes.iter().collect();
+                attributes.sort();
+                for (name, value) in attributes {
+                    if self.options.remove_default_attributes
+                        && is_default_attribute(node, name, value)
+                    {
+                        continue;
+                    }
+                    self.output.push(' ');
+                    self.output.push_str(name);
+                    self.output.push_str("=\"");
+                    self.output.push_str(&escape_attribute(value));
+                    self.output.push('"');
+                }
+                self.output.push('>');
+
+                if is_html_element(node, &VOID_ELEMENTS) {
+                    return;
+                }
+
+                let preformatted = is_preformatted(node);
+                if preformatted {
+                    self.preformatted += 1;
+                }
+                self.serialize_children(node);
+                if preformatted {
+                    self.preformatted -= 1;
+                }
+
+                if self.options.drop_optional_end_tags && can_omit_end_tag(node, next, parent) {
+                    return;
+                }
+                self.output.push_str("</");
+                self.output.push_str(&node.name);
+                self.output.push('>');
+            }
+        }
+    }
+}
+
+/// Returns true when the end tag of the given element can be omitted, based on the node that
+/// follows it in the output.
+///
+/// See: https://html.spec.whatwg.org/multipage/syntax.html#optional-tags
+fn can_omit_end_tag(node: &Node, next: Option<&Node>, parent: Option<&Node>) -> bool {
+    if !node.is_namespace(HTML_NAMESPACE) {
+        return false;
+    }
+
+    let next_is = |names: &[&str]| next.is_some_and(|next| is_html_element(next, names));
+    let next_is_space_or_comment = match next.map(|next| &next.data) {
+        Some(NodeData::Comment(_)) => true,
+        Some(NodeData::Text(TextData { value })) => {
+            value.starts_with(|c: char| c.is_ascii_whitespace())
+        }
+        _ => false,
+    };
+    let next_is_comment = next.is_some_and(|next| matches!(next.data, NodeData::Comment(_)));
+
+    match node.name.as_str() {
+        "html" | "body" => !next_is_comment,
+        "head" | "colgroup" | "caption" => !next_is_space_or_comment,
+        "li" => next.is_none() || next_is(&["li"]),
+        "dt" => next_is(&["dt", "dd"]),
+        "dd" => next.is_none() || next_is(&["dt", "dd"]),
+        "rt" | "rp" => next.is_none() || next_is(&["rt", "rp"]),
+        "optgroup" => next.is_none() || next_is(&["optgroup", "hr"]),
+        "option" => next.is_none() || next_is(&["option", "optgroup", "hr"]),
+        "thead" => next_is(&["tbody", "tfoot"]),
+        "tbody" => next.is_none() || next_is(&["tbody", "tfoot"]),
+        "tfoot" => next.is_none(),
+        "tr" => next.is_none() || next_is(&["tr"]),
+        "td" | "th" => next.is_none() || next_is(&["td", "th"]),
+        "p" => {
+            if next.is_some() {
+                return next_is(&CLOSES_PARAGRAPH);
+            }
+            // At the end of its parent, the paragraph can only be implicitly closed when the
+            // parent is not an element that can contain a paragraph inside a phrasing context
+            parent.is_some_and(|parent| {
+                !is_html_element(
+                    parent,
+                    &["a", "audio", "del", "ins", "map", "noscript", "video"],
+                ) && !parent.name.contains('-')
+            })
+        }
+        _ => false,
+    }
+}
+
+fn is_html_element(node: &Node, names: &[&str]) -> bool {
+    matches!(node.data, NodeData::Element(_))
+        && node.is_namespace(HTML_NAMESPACE)
+        && names.contains(&node.name.as_str())
+}
+
+fn is_preformatted(node: &Node) -> bool {
+    is_html_element(node, &PREFORMATTED_ELEMENTS) || is_html_element(node, &RAW_TEXT_ELEMENTS)
+}
+
+fn is_default_attribute(node: &Node, name: &str, value: &str) -> bool {
+    node.is_namespace(HTML_NAMESPACE)
+        && DEFAULT_ATTRIBUTES
+            .iter()
+            .any(|(element, attribute, default)| {
+                *element == node.name
+                    && *attribute == name
+                    && value
+                        .trim_matches(|c: char| c.is_ascii_whitespace())
+                        .eq_ignore_ascii_case(default)
+            })
+}
+
+fn is_whitespace(value: &str) -> bool {
+    value.chars().all(|c| c.is_ascii_whitespace())
+}
+
+/// Collapses every run of ASCII whitespace into a single space
+fn collapse_whitespace(value: &str) -> String {
+    let mut result = String::with_capacity(value.len());
+    let mut in_whitespace = false;
+    for c in value.chars() {
+        if c.is_ascii_whitespace() {
+            if !in_whitespace {
+                result.push(' ');
+            }
+            in_whitespace = true;
+        } else {
+            result.push(c);
+            in_whitespace = false;
+        }
+    }
+    result
+}
+
+fn escape_text(value: &str) -> String {
+    let mut result = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '&' => result.push_str("&amp;"),
+            '\u{00A0}' => result.push_str("&nbsp;"),
+            '<' => result.push_str("&lt;"),
+            '>' => result.push_str("&gt;"),
+            c => result.push(c),
+        }
+    }
+    result
+}
+
+fn escape_attribute(value: &str) -> String {
+    let mut result = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '&' => result.push_str("&amp;"),
+            '\u{00A0}' => result.push_str("&nbsp;"),
+            '"' => result.push_str("&quot;"),
+            '<' => result.push_str("&lt;"),
+            '>' => result.push_str("&gt;"),
+            c => result.push(c),
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::html5::parser::quirks::QuirksMode;
+    use crate::testing::parse;
+
+    #[test]
+    fn serialize_document() {
+        let document = parse(
+            "<!DOCTYPE html><title>A &amp; B</title><!-- note --><p class=x id='a\"b'>1 &lt; 2<br>\
+             <script>if (a < b) {}</script>",
+        );
+        assert_eq!(
+            serialize(
+                &document.get(),
+                NodeId::root(),
+                &SerializerOptions::default()
+            ),
+            "<!DOCTYPE html><html><head><title>A &amp; B</title><!-- note --></head><body>\
+             <p class=\"x\" id=\"a&quot;b\">1 &lt; 2<br><script>if (a < b) {}</script></p>\
+             </body></html>"
+        );
+    }
+
+    #[test]
+    fn serialize_legacy_doctype() {
+        let html = "<!DOCTYPE html PUBLIC \"-//W3C//DTD HTML 4.01 Transitional//EN\" \
+                    \"http://www.w3.org/TR/html4/loose.dtd\">";
+        let document = parse(html);
+        assert_eq!(document.get().quirks_mode, QuirksMode::LimitedQuirks);
+
+        let output = serialize(
+            &document.get(),
+            NodeId::root(),
+            &SerializerOptions::default(),
+        );
+        assert!(output.starts_with(html));
+        assert_eq!(parse(&output).get().quirks_mode, QuirksMode::LimitedQuirks);
+
+        let document = parse("<!doctype html system 'about:legacy-compat'>");
+        assert!(serialize(
+            &document.get(),
+            NodeId::root(),
+            &SerializerOptions::default()
+        )
+        .starts_with("<!DOCTYPE html SYSTEM \"about:legacy-compat\">"));
+    }
+
+    #[test]
+    fn serialize_single_node() {
+        let document = parse("<ul><li>one<li>two</ul>");
+        let ul = document.get().elements_by_tag_name("ul")[0];
+        assert_eq!(
+            serialize_node(&document.get(), ul, &SerializerOptions::default()),
+            "<ul><li>one</li><li>two</li></ul>"
+        );
+        assert_eq!(
+            serialize(&document.get(), ul, &SerializerOptions::minified()),
+            "<li>one<li>two"
+        );
+    }
+
+    #[test]
+    fn minified_output() {
+        let document = parse(
+            "<!DOCTYPE html>\n<html>\n  <head>\n    <title>Test</title>\n    \
+             <style type=\"text/css\">a  {  }</style>\n  </head>\n  <body>\n    \
+             <!-- comment -->\n    <p>Some   text\n    here</p>\n    <p>More</p>\n    \
+             <table>\n      <tr><td colspan=1>a</td><td>b</td></tr>\n    </table>\n    \
+             <pre>  keep\n  this </pre>\n    <input type=TEXT name=q>\n  </body>\n</html>\n",
+        );
+        assert_eq!(
+            serialize(
+                &document.get(),
+                NodeId::root(),
+                &SerializerOptions::minified()
+            ),
+            "<!DOCTYPE html><html><head><title>Test</title><style>a  {  }</style><body> \
+             <p>Some text here</p> <p>More</p> <table><tbody><tr><td>a<td>b</table> \
+             <pre>  keep\n  this </pre> <input name=\"q\"> "
+        );
+    }
+
+    #[test]
+    fn optional_end_tags_only() {
+        let options = SerializerOptions {
+            drop_optional_end_tags: true,
+            ..Default::default()
+        };
+        let document = parse("<dl><dt>a</dt><dd>b</dd></dl><a><p>x</p></a><p>y</p><span>z</span>");
+        assert_eq!(
+            serialize(&document.get(), NodeId::root(), &options),
+            "<html><head><body><dl><dt>a<dd>b</dl><a><p>x</p></a><p>y</p><span>z</span>"
+        );
+    }
+
+    #[test]
+    fn serialize_round_trip() {
+        let document = parse(
+            "<!DOCTYPE html><table><tr><td>a<td>b</table><p>x<b>y<i>z</b>w</i>\
+             <textarea>\n line</textarea><style>p > a {}</style><img alt='\"&'>",
+        );
+        let html = document.serialize();
+        assert_eq!(html, document.get().serialize());
+        assert_eq!(parse(&html).serialize(), html);
+    }
+}
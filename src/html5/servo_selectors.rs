@@ -0,0 +1,430 @@
+//! Servo selectors
+//!
+//! Implements the traits of the [selectors](https://docs.rs/selectors) crate (the selector
+//! engine used by servo and firefox) for [`ElementRef`], so its parser and matcher can be used
+//! on gosub documents as an alternative to the native [`Selector`](crate::html5::selector::Selector).
+//!
+//! Dynamic pseudo-classes like `:hover` are parsed, but never match, as there is no user
+//! interaction state. Pseudo-elements are not supported.
+use crate::html5::element_ref::ElementRef;
+use crate::html5::node::{NodeId, HTML_NAMESPACE};
+use crate::types::{Error, Result};
+use cssparser::{CowRcStr, ParseError, SourceLocation, ToCss};
+use precomputed_hash::PrecomputedHash;
+use selectors::attr::{AttrSelectorOperation, CaseSensitivity, NamespaceConstraint};
+use selectors::context::{
+    IgnoreNthChildForInvalidation, MatchingContext, MatchingMode, NeedsSelectorFlags, QuirksMode,
+};
+use selectors::matching::{matches_selector_list, ElementSelectorFlags};
+use selectors::parser::{ParseRelative, SelectorParseErrorKind};
+use selectors::{Element, NthIndexCache, OpaqueElement, SelectorList};
+use std::fmt;
+
+/// Selector implementation for gosub documents
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GosubSelectorImpl;
+
+/// Identifier (names, ids and classes) in a selector
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CssIdentifier(pub String);
+
+/// Attribute value in a selector
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CssValue(pub String);
+
+impl From<&str> for CssIdentifier {
+    fn from(value: &str) -> Self {
+        Self(value.to_string())
+    }
+}
+
+impl ToCss for CssIdentifier {
+    fn to_css<W: fmt::Write>(&self, dest: &mut W) -> fmt::Result {
+        cssparser::serialize_identifier(&self.0, dest)
+    }
+}
+
+impl PrecomputedHash for CssIdentifier {
+    fn precomputed_hash(&self) -> u32 {
+        // FNV-1a, only used for the bloom filter of the matcher
+        self.0.bytes().fold(0x811c9dc5, |hash, b| {
+            (hash ^ b as u32).wrapping_mul(0x01000193)
+        })
+    }
+}
+
+impl From<&str> for CssValue {
+    fn from(value: &str) -> Self {
+        Self(value.to_string())
+    }
+}
+
+impl AsRef<str> for CssValue {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl ToCss for CssValue {
+    fn to_css<W: fmt::Write>(&self, dest: &mut W) -> fmt::Result {
+        cssparser::serialize_string(&self.0, dest)
+    }
+}
+
+/// Pseudo-classes that are not tree-structural
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PseudoClass {
+    AnyLink,
+    Link,
+    Visited,
+    Checked,
+    Disabled,
+    Enabled,
+    Hover,
+    Active,
+    Focus,
+}
+
+impl ToCss for PseudoClass {
+    fn to_css<W: fmt::Write>(&self, dest: &mut W) -> fmt::Result {
+        dest.write_str(match self {
+            PseudoClass::AnyLink => ":any-link",
+            PseudoClass::Link => ":link",
+            PseudoClass::Visited => ":visited",
+            PseudoClass::Checked => ":checked",
+            PseudoClass::Disabled => ":disabled",
+            PseudoClass::Enabled => ":enabled",
+            PseudoClass::Hover => ":hover",
+            PseudoClass::Active => ":active",
+            PseudoClass::Focus => ":focus",
+        })
+    }
+}
+
+impl selectors::parser::NonTSPseudoClass for PseudoClass {
+    type Impl = GosubSelectorImpl;
+
+    fn is_active_or_hover(&self) -> bool {
+        matches!(self, PseudoClass::Active | PseudoClass::Hover)
+    }
+
+    fn is_user_action_state(&self) -> bool {
+        matches!(
+            self,
+            PseudoClass::Active | PseudoClass::Hover | PseudoClass::Focus
+        )
+    }
+}
+
+/// Pseudo-elements (none are supported)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PseudoElement {}
+
+impl ToCss for PseudoElement {
+    fn to_css<W: fmt::Write>(&self, _dest: &mut W) -> fmt::Result {
+        match *self {}
+    }
+}
+
+impl selectors::parser::PseudoElement for PseudoElement {
+    type Impl = GosubSelectorImpl;
+}
+
+impl selectors::SelectorImpl for GosubSelectorImpl {
+    type ExtraMatchingData<'a> = ();
+    type AttrValue = CssValue;
+    type Identifier = CssIdentifier;
+    type LocalName = CssIdentifier;
+    type NamespaceUrl = CssIdentifier;
+    type NamespacePrefix = CssIdentifier;
+    type BorrowedNamespaceUrl = CssIdentifier;
+    type BorrowedLocalName = CssIdentifier;
+    type NonTSPseudoClass = PseudoClass;
+    type PseudoElement = PseudoElement;
+}
+
+/// Parser for the pseudo-classes supported by gosub
+struct SelectorParser;
+
+impl<'i> selectors::Parser<'i> for SelectorParser {
+    type Impl = GosubSelectorImpl;
+    type Error = SelectorParseErrorKind<'i>;
+
+    fn parse_is_and_where(&self) -> bool {
+        true
+    }
+
+    fn parse_non_ts_pseudo_class(
+        &self,
+        location: SourceLocation,
+        name: CowRcStr<'i>,
+    ) -> std::result::Result<PseudoClass, ParseError<'i, Self::Error>> {
+        let pseudo_class = match name.to_ascii_lowercase().as_str() {
+            "any-link" => PseudoClass::AnyLink,
+            "link" => PseudoClass::Link,
+            "visited" => PseudoClass::Visited,
+            "checked" => PseudoClass::Checked,
+            "disabled" => PseudoClass::Disabled,
+            "enabled" => PseudoClass::Enabled,
+            "hover" => PseudoClass::Hover,
+            "active" => PseudoClass::Active,
+            "focus" => PseudoClass::Focus,
+            _ => {
+                return Err(location.new_custom_error(
+                    SelectorParseErrorKind::UnsupportedPseudoClassOrElement(name),
+                ))
+            }
+        };
+        Ok(pseudo_class)
+    }
+}
+
+/// Parses a selector list with the servo selectors parser
+pub fn parse(selector: &str) -> Result<SelectorList<GosubSelectorImpl>> {
+    let mut input = cssparser::ParserInput::new(selector);
+    let mut parser = cssparser::Parser::new(&mut input);
+
+    SelectorList::parse(&SelectorParser, &mut parser, ParseRelative::No)
+        .map_err(|e| Error::Parse(format!("invalid selector: {:?}", e.kind)))
+}
+
+/// Returns true when the element matches any of the selectors in the list
+pub fn matches(selectors: &SelectorList<GosubSelectorImpl>, element: &ElementRef) -> bool {
+    let mut nth_index_cache = NthIndexCache::default();
+    let mut context = MatchingContext::new(
+        MatchingMode::Normal,
+        None,
+        &mut nth_index_cache,
+        QuirksMode::NoQuirks,
+        NeedsSelectorFlags::No,
+        IgnoreNthChildForInvalidation::No,
+    );
+
+    matches_selector_list(selectors, element, &mut context)
+}
+
+impl ElementRef<'_> {
+    fn is_html(&self) -> bool {
+        self.node().is_namespace(HTML_NAMESPACE)
+    }
+
+    fn is_disableable(&self) -> bool {
+        self.is_html()
+            && matches!(
+                self.name(),
+                "button" | "fieldset" | "input" | "optgroup" | "option" | "select" | "textarea"
+            )
+    }
+}
+
+impl Element for ElementRef<'_> {
+    type Impl = GosubSelectorImpl;
+
+    fn opaque(&self) -> OpaqueElement {
+        OpaqueElement::new(self.node())
+    }
+
+    fn parent_element(&self) -> Option<Self> {
+        self.parent()
+    }
+
+    fn parent_node_is_shadow_root(&self) -> bool {
+        false
+    }
+
+    fn containing_shadow_host(&self) -> Option<Self> {
+        None
+    }
+
+    fn is_pseudo_element(&self) -> bool {
+        false
+    }
+
+    fn prev_sibling_element(&self) -> Option<Self> {
+        self.prev_sibling()
+    }
+
+    fn next_sibling_element(&self) -> Option<Self> {
+        self.next_sibling()
+    }
+
+    fn first_element_child(&self) -> Option<Self> {
+        self.children().next()
+    }
+
+    fn is_html_element_in_html_document(&self) -> bool {
+        self.is_html()
+    }
+
+    fn has_local_name(&self, local_name: &CssIdentifier) -> bool {
+        self.name() == local_name.0
+    }
+
+    fn has_namespace(&self, ns: &CssIdentifier) -> bool {
+        self.node().namespace.as_deref().unwrap_or_default() == ns.0
+    }
+
+    fn is_same_type(&self, other: &Self) -> bool {
+        self.name() == other.name() && self.node().namespace == other.node().namespace
+    }
+
+    fn attr_matches(
+        &self,
+        ns: &NamespaceConstraint<&CssIdentifier>,
+        local_name: &CssIdentifier,
+        operation: &AttrSelectorOperation<&CssValue>,
+    ) -> bool {
+        // Attributes in other namespaces are stored with their prefix, which cannot be
+        // derived from the namespace url
+        if let NamespaceConstraint::Specific(ns) = ns {
+            if !ns.0.is_empty() {
+                return false;
+            }
+        }
+
+        self.attr(&local_name.0)
+            .is_some_and(|value| operation.eval_str(value))
+    }
+
+    fn match_non_ts_pseudo_class(
+        &self,
+        pseudo_class: &PseudoClass,
+        _context: &mut MatchingContext<Self::Impl>,
+    ) -> bool {
+        match pseudo_class {
+            PseudoClass::AnyLink | PseudoClass::Link => self.is_link(),
+            PseudoClass::Checked => {
+                self.is_html()
+                    && match self.name() {
+                        "input" => {
+                            let input_type = self.attr("type").unwrap_or_default();
+                            (input_type.eq_ignore_ascii_case("checkbox")
+                                || input_type.eq_ignore_ascii_case("radio"))
+                                && self.attr("checked").is_some()
+                        }
+                        "option" => self.attr("selected").is_some(),
+                        _ => false,
+                    }
+            }
+            PseudoClass::Disabled => self.is_disableable() && self.attr("disabled").is_some(),
+            PseudoClass::Enabled => self.is_disableable() && self.attr("disabled").is_none(),
+            PseudoClass::Visited
+            | PseudoClass::Hover
+            | PseudoClass::Active
+            | PseudoClass::Focus => false,
+        }
+    }
+
+    fn match_pseudo_element(
+        &self,
+        pseudo_element: &PseudoElement,
+        _context: &mut MatchingContext<Self::Impl>,
+    ) -> bool {
+        match *pseudo_element {}
+    }
+
+    fn apply_selector_flags(&self, _flags: ElementSelectorFlags) {}
+
+    fn is_link(&self) -> bool {
+        self.is_html()
+            && matches!(self.name(), "a" | "area" | "link")
+            && self.attr("href").is_some()
+    }
+
+    fn is_html_slot_element(&self) -> bool {
+        self.is_html() && self.name() == "slot"
+    }
+
+    fn has_id(&self, id: &CssIdentifier, case_sensitivity: CaseSensitivity) -> bool {
+        self.attr("id")
+            .is_some_and(|value| case_sensitivity.eq(value.as_bytes(), id.0.as_bytes()))
+    }
+
+    fn has_class(&self, name: &CssIdentifier, case_sensitivity: CaseSensitivity) -> bool {
+        self.attr("class").is_some_and(|classes| {
+            classes
+                .split_ascii_whitespace()
+                .any(|class| case_sensitivity.eq(class.as_bytes(), name.0.as_bytes()))
+        })
+    }
+
+    fn imported_part(&self, _name: &CssIdentifier) -> Option<CssIdentifier> {
+        None
+    }
+
+    fn is_part(&self, _name: &CssIdentifier) -> bool {
+        false
+    }
+
+    fn is_empty(&self) -> bool {
+        // Without child elements, the text content only consists of the child text nodes
+        self.children().next().is_none() && self.text().is_empty()
+    }
+
+    fn is_root(&self) -> bool {
+        self.node().parent == Some(NodeId::root())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::html5::selector::Selector;
+    use crate::testing::parse as parse_html;
+
+    #[test]
+    fn matches_like_native_selectors() {
+        let document = parse_html(
+            r#"<ul id=a class="x y"><li id=b><a id=c href=x>1</a></li><li id=d lang=en-GB></li>
+            <li id=e><input id=f type=radio checked><input id=g disabled></li><li id=h><!-- c --></li></ul>"#,
+        );
+        let doc = document.get();
+        let all = Selector::parse("*").unwrap();
+
+        for selector in [
+            "li",
+            "#a > li",
+            "ul li:first-child a",
+            "li + li",
+            "#b ~ li",
+            ".x.y",
+            "[lang|=en]",
+            "[href^=x]",
+            "li:nth-child(2n+1)",
+            "li:nth-last-of-type(1)",
+            ":root",
+            "li:empty",
+            ":not(li, ul)",
+            ":is(#c, #d)",
+            ":link",
+            ":checked",
+            "input:enabled",
+            ":disabled",
+        ] {
+            let servo = parse(selector).unwrap();
+            let native = Selector::parse(selector).unwrap();
+
+            let expected: Vec<_> = doc.select(&native).map(|e| e.id()).collect();
+            let actual: Vec<_> = doc
+                .select(&all)
+                .filter(|e| matches(&servo, e))
+                .map(|e| e.id())
+                .collect();
+            assert_eq!(actual, expected, "{}", selector);
+            assert!(!expected.is_empty(), "{}", selector);
+        }
+    }
+
+    #[test]
+    fn dynamic_pseudo_classes_and_errors() {
+        let document = parse_html("<a href=x>link</a>");
+        let doc = document.get();
+        let a = doc.select(&Selector::parse("a").unwrap()).next().unwrap();
+
+        assert!(matches(&parse("a:any-link").unwrap(), &a));
+        assert!(!matches(&parse("a:hover").unwrap(), &a));
+        assert!(parse("a:unknown").is_err());
+        assert!(parse("a::before").is_err());
+        assert!(parse("a >").is_err());
+    }
+}
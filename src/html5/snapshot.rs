@@ -0,0 +1,805 @@
+//! Binary document snapshots
+//!
+//! A compact binary format for complete documents: the node arena (including the source spans of
+//! the nodes), the ID lookup, the quirks mode, the base URL state and the parse errors. Loading a snapshot is much faster than parsing
+//! the HTML again, which helps crawler pipelines and test fixtures that work with the same pages
+//! over and over.
+//!
+//! The format starts with a magic string and a version, followed by a table of the strings that
+//! repeat a lot (tag names, namespaces, attribute and class names), which are referred to by their
+//! index. All numbers are stored as LEB128 varints, and node IDs relative to a nearby node, so
+//! most of them take a single byte. Derived state (parents, inline event handlers and the optional
+//! indexes) is rebuilt on load; the undo history and cookie jar are not part of the snapshot.
+use crate::bytes::{Position, Span};
+use crate::html5::element_class::ElementClass;
+use crate::html5::node::arena::NodeArena;
+use crate::html5::node::data::comment::CommentData;
+use crate::html5::node::data::doctype::DocTypeData;
+use crate::html5::node::data::document::DocumentData;
+use crate::html5::node::data::element::ElementData;
+use crate::html5::node::data::text::TextData;
+use crate::html5::node::{Node, NodeData, NodeId};
+use crate::html5::parser::document::{
+    Document, DocumentBuilder, DocumentFragment, DocumentHandle, DocumentType,
+};
+use crate::html5::parser::quirks::QuirksMode;
+use crate::net::origin::Origin;
+use crate::types::{Error, ParseError, Result};
+use std::collections::HashMap;
+use url::Url;
+
+/// Identifies a snapshot
+const MAGIC: &[u8; 8] = b"GOSUBDOC";
+/// Version of the format, to be increased on every incompatible change
+const VERSION: u8 = 2;
+
+const NODE_DOCUMENT: u8 = 0;
+const NODE_DOCTYPE: u8 = 1;
+const NODE_TEXT: u8 = 2;
+const NODE_COMMENT: u8 = 3;
+const NODE_ELEMENT: u8 = 4;
+
+const SPAN_NONE: usize = 0;
+const SPAN_FULL: usize = 1;
+/// A span on the line the previous span ended on, without multibyte characters. Only the gap
+/// after the previous span (added to this tag) and the length are stored.
+const SPAN_SHORT: usize = 2;
+
+/// What the first source span is stored relative to
+const NO_SPAN: Span = Span {
+    start: Position {
+        offset: 0,
+        line: 1,
+        col: 1,
+    },
+    end: Position {
+        offset: 0,
+        line: 1,
+        col: 1,
+    },
+    start_byte: 0,
+    end_byte: 0,
+};
+
+impl Document {
+    /// Writes the document and the errors found while parsing it into a snapshot
+    pub fn to_snapshot(&self, errors: &[ParseError]) -> Vec<u8> {
+        let mut body = Writer::default();
+
+        body.u8(match self.doctype {
+            DocumentType::HTML => 0,
+            DocumentType::IframeSrcDoc => 1,
+        });
+        body.u8(match self.quirks_mode {
+            QuirksMode::Quirks => 0,
+            QuirksMode::LimitedQuirks => 1,
+            QuirksMode::NoQuirks => 2,
+        });
+        body.option(self.url.as_ref(), |w, url| w.string(url.as_str()));
+        body.option(self.base_element, |w, id| w.node_id(id));
+        body.option(self.frozen_base_url.as_ref(), |w, url| {
+            w.string(url.as_str())
+        });
+        body.option(self.base_target.as_ref(), |w, target| w.string(target));
+
+        body.arena(&self.arena);
+
+        let mut named_ids: Vec<_> = self.named_id_elements.iter().collect();
+        named_ids.sort();
+        body.usize(named_ids.len());
+        for (name, &node_id) in named_ids {
+            body.string(name);
+            body.node_id(node_id);
+        }
+
+        body.option(self.indexed_attributes(), |w, attributes| {
+            w.usize(attributes.len());
+            for attribute in attributes {
+                w.interned(&attribute);
+            }
+        });
+
+        body.usize(errors.len());
+        for error in errors {
+            body.interned(&error.message);
+            body.usize(error.line);
+            body.usize(error.col);
+            body.usize(error.offset);
+        }
+
+        let mut snapshot = Writer::default();
+        snapshot.bytes.extend_from_slice(MAGIC);
+        snapshot.u8(VERSION);
+        snapshot.usize(body.strings.len());
+        for string in &body.strings {
+            snapshot.string(string);
+        }
+        snapshot.bytes.extend(body.bytes);
+
+        snapshot.bytes
+    }
+}
+
+impl DocumentBuilder {
+    /// Loads a document from a snapshot. Returns the document and the errors found while parsing
+    /// it originally.
+    pub fn from_snapshot(snapshot: &[u8]) -> Result<(DocumentHandle, Vec<ParseError>)> {
+        let mut reader = Reader::new(snapshot);
+        if reader.take(MAGIC.len())? != MAGIC {
+            return Err(Error::Snapshot("not a document snapshot".to_string()));
+        }
+        let version = reader.u8()?;
+        if version != VERSION {
+            return Err(Error::Snapshot(format!(
+                "unsupported snapshot version {}",
+                version
+            )));
+        }
+        let string_count = reader.usize()?;
+        for _ in 0..string_count {
+            let string = reader.string()?;
+            reader.strings.push(string);
+        }
+
+        let mut handle = Document::shared();
+        let doctype = match reader.u8()? {
+            0 => DocumentType::HTML,
+            1 => DocumentType::IframeSrcDoc,
+            tag => return Err(invalid("document type", tag)),
+        };
+        let quirks_mode = match reader.u8()? {
+            0 => QuirksMode::Quirks,
+            1 => QuirksMode::LimitedQuirks,
+            2 => QuirksMode::NoQuirks,
+            tag => return Err(invalid("quirks mode", tag)),
+        };
+        let url = reader.option(|r| r.url())?;
+        let base_element = reader.option(|r| r.node_id())?;
+        let frozen_base_url = reader.option(|r| r.url())?;
+        let base_target = reader.option(|r| r.string())?;
+
+        let arena = reader.arena(&handle)?;
+
+        let mut named_id_elements = HashMap::new();
+        for _ in 0..reader.usize()? {
+            let name = reader.string()?;
+            named_id_elements.insert(name, reader.node_id()?);
+        }
+
+        let indexed_attributes = reader.option(|r| {
+            let count = r.usize()?;
+            (0..count).map(|_| r.interned()).collect::<Result<Vec<_>>>()
+        })?;
+
+        let mut errors = Vec::new();
+        for _ in 0..reader.usize()? {
+            errors.push(ParseError {
+                message: reader.interned()?,
+                line: reader.usize()?,
+                col: reader.usize()?,
+                offset: reader.usize()?,
+            });
+        }
+
+        if !reader.is_empty() {
+            return Err(Error::Snapshot(
+                "unexpected data after snapshot".to_string(),
+            ));
+        }
+
+        {
+            let mut doc = handle.get_mut();
+            doc.doctype = doctype;
+            doc.quirks_mode = quirks_mode;
+            // Opaque origins cannot be restored, a restored document gets a new one
+            doc.origin = url
+                .as_ref()
+                .map_or_else(Origin::new_opaque, Origin::from_url);
+            doc.url = url;
+            doc.base_element = base_element;
+            doc.frozen_base_url = frozen_base_url;
+            doc.base_target = base_target;
+            doc.arena = arena;
+            doc.named_id_elements = named_id_elements;
+            doc.parse_errors = errors.clone();
+            doc.rebuild_derived_state();
+
+            if let Some(attributes) = indexed_attributes {
+                let attributes: Vec<&str> = attributes.iter().map(String::as_str).collect();
+                doc.enable_indexes(&attributes);
+            }
+        }
+
+        Ok((handle, errors))
+    }
+}
+
+impl Document {
+    /// Rebuilds the state that follows from the nodes in the arena: the class and name lookups,
+    /// the inline event handlers and the interned node names. Used when the arena is replaced as
+    /// a whole.
+    pub(crate) fn rebuild_derived_state(&mut self) {
+        self.class_elements.clear();
+        self.index_classes(NodeId::root());
+        self.name_elements.clear();
+        self.index_names(NodeId::root());
+
+        // Inline event handlers follow from the attributes
+        let handlers: Vec<(NodeId, String)> = self
+            .arena
+            .nodes()
+            .flat_map(|node| match &node.data {
+                NodeData::Element(element) => element
+                    .attributes
+                    .keys()
+                    .map(|name| (node.id, name.clone()))
+                    .collect(),
+                _ => vec![],
+            })
+            .collect();
+        for (node_id, attribute) in handlers {
+            self.register_inline_handler(node_id, &attribute);
+        }
+
+        let node_ids: Vec<NodeId> = self.arena.nodes().map(|node| node.id).collect();
+        for node_id in node_ids {
+            self.intern_node_name(node_id);
+        }
+    }
+}
+
+fn invalid(what: &str, tag: u8) -> Error {
+    Error::Snapshot(format!("invalid {} {}", what, tag))
+}
+
+/// Writes the values of a snapshot
+#[derive(Default)]
+struct Writer {
+    bytes: Vec<u8>,
+    /// Interned strings in order of their index
+    strings: Vec<String>,
+    string_indexes: HashMap<String, usize>,
+    /// Source span of the last node that had one, later spans are stored relative to it
+    previous_span: Option<Span>,
+}
+
+impl Writer {
+    fn u8(&mut self, value: u8) {
+        self.bytes.push(value);
+    }
+
+    fn bool(&mut self, value: bool) {
+        self.u8(value as u8);
+    }
+
+    fn usize(&mut self, mut value: usize) {
+        loop {
+            let byte = (value & 0x7f) as u8;
+            value >>= 7;
+            if value == 0 {
+                self.bytes.push(byte);
+                return;
+            }
+            self.bytes.push(byte | 0x80);
+        }
+    }
+
+    fn node_id(&mut self, node_id: NodeId) {
+        self.usize(node_id.into());
+    }
+
+    fn string(&mut self, value: &str) {
+        self.usize(value.len());
+        self.bytes.extend_from_slice(value.as_bytes());
+    }
+
+    /// Writes the difference between two node IDs, which is small for related nodes
+    fn delta(&mut self, from: NodeId, to: NodeId) {
+        self.relative(from.into(), to.into());
+    }
+
+    /// Writes a number relative to another one
+    fn relative(&mut self, from: usize, to: usize) {
+        let delta = to as i64 - from as i64;
+        // Zigzag encoding keeps small negative numbers small
+        self.usize(((delta << 1) ^ (delta >> 63)) as usize);
+    }
+
+    /// Writes a source span (or its absence). Its start is stored relative to the end of the
+    /// previous span, and its end relative to its start, which keeps the numbers small.
+    fn span(&mut self, span: Option<Span>) {
+        let Some(span) = span else {
+            self.usize(SPAN_NONE);
+            return;
+        };
+        let previous = self.previous_span.unwrap_or(NO_SPAN);
+        self.previous_span = Some(span);
+
+        let gap = span.start.offset.wrapping_sub(previous.end.offset);
+        let length = span.end.offset.wrapping_sub(span.start.offset);
+        if span.start.line == previous.end.line
+            && span.end.line == span.start.line
+            && span.start.col == previous.end.col.wrapping_add(gap)
+            && span.end.col == span.start.col.wrapping_add(length)
+            && span.start_byte == previous.end_byte.wrapping_add(gap)
+            && span.end_byte == span.start_byte.wrapping_add(length)
+            && gap <= span.start.offset
+            && length <= span.end.offset
+        {
+            self.usize(SPAN_SHORT + gap);
+            self.usize(length);
+            return;
+        }
+
+        self.usize(SPAN_FULL);
+        self.relative(previous.end.offset, span.start.offset);
+        self.relative(previous.end.line, span.start.line);
+        self.usize(span.start.col);
+        self.relative(previous.end_byte, span.start_byte);
+        self.relative(span.start.offset, span.end.offset);
+        self.relative(span.start.line, span.end.line);
+        self.usize(span.end.col);
+        self.relative(span.start_byte, span.end_byte);
+    }
+
+    /// Writes a string that is likely to repeat as an index into the string table
+    fn interned(&mut self, value: &str) {
+        let index = self.intern(value);
+        self.usize(index);
+    }
+
+    /// Returns the index of the string in the string table
+    fn intern(&mut self, value: &str) -> usize {
+        if let Some(&index) = self.string_indexes.get(value) {
+            return index;
+        }
+        let index = self.strings.len();
+        self.strings.push(value.to_string());
+        self.string_indexes.insert(value.to_string(), index);
+        index
+    }
+
+    fn option<T>(&mut self, value: Option<T>, write: impl FnOnce(&mut Self, T)) {
+        match value {
+            Some(value) => {
+                self.bool(true);
+                write(self, value);
+            }
+            None => self.bool(false),
+        }
+    }
+
+    fn arena(&mut self, arena: &NodeArena) {
+        self.node_id(arena.peek_next_id());
+        self.usize(arena.count_nodes());
+        let mut previous_id = NodeId::root();
+        for node in arena.nodes() {
+            self.node(node, previous_id);
+            previous_id = node.id;
+        }
+    }
+
+    fn node(&mut self, node: &Node, previous_id: NodeId) {
+        // Parents are not stored, they follow from the children
+        self.delta(previous_id, node.id);
+        self.usize(node.children.len());
+        let mut previous_child_id = node.id;
+        for &child_id in &node.children {
+            self.delta(previous_child_id, child_id);
+            previous_child_id = child_id;
+        }
+        self.interned(&node.name);
+        match &node.namespace {
+            Some(namespace) => {
+                let index = self.intern(namespace);
+                self.usize(index + 1);
+            }
+            None => self.usize(0),
+        }
+        self.span(node.source_span());
+
+        match &node.data {
+            NodeData::Document(_) => self.u8(NODE_DOCUMENT),
+            NodeData::DocType(doctype) => {
+                self.u8(NODE_DOCTYPE);
+                self.string(&doctype.name);
+                self.string(&doctype.pub_identifier);
+                self.string(&doctype.sys_identifier);
+            }
+            NodeData::Text(text) => {
+                self.u8(NODE_TEXT);
+                self.string(&text.value);
+            }
+            NodeData::Comment(comment) => {
+                self.u8(NODE_COMMENT);
+                self.string(&comment.value);
+            }
+            NodeData::Element(element) => {
+                self.u8(NODE_ELEMENT);
+
+                let mut attributes: Vec<_> = element.attributes.iter().collect();
+                attributes.sort();
+                self.usize(attributes.len());
+                for (name, value) in attributes {
+                    self.interned(name);
+                    self.string(value);
+                }
+
+                let mut classes: Vec<_> = element.classes.iter().collect();
+                classes.sort();
+                self.usize(classes.len());
+                for (name, is_active) in classes {
+                    self.interned(name);
+                    self.bool(is_active);
+                }
+
+                self.bool(element.force_async);
+                self.option(element.template_contents.as_ref(), |w, fragment| {
+                    w.node_id(fragment.host);
+                    w.arena(&fragment.arena);
+                });
+            }
+        }
+    }
+}
+
+/// Reads the values of a snapshot
+struct Reader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+    /// The string table
+    strings: Vec<String>,
+    /// Source span of the last node that had one
+    previous_span: Option<Span>,
+}
+
+impl<'a> Reader<'a> {
+    fn new(bytes: &'a [u8]) -> Self {
+        Self {
+            bytes,
+            position: 0,
+            strings: Vec::new(),
+            previous_span: None,
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.position == self.bytes.len()
+    }
+
+    fn take(&mut self, length: usize) -> Result<&'a [u8]> {
+        let end = self
+            .position
+            .checked_add(length)
+            .filter(|&end| end <= self.bytes.len())
+            .ok_or_else(|| Error::Snapshot("unexpected end of snapshot".to_string()))?;
+        let bytes = &self.bytes[self.position..end];
+        self.position = end;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn bool(&mut self) -> Result<bool> {
+        match self.u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            tag => Err(invalid("boolean", tag)),
+        }
+    }
+
+    fn usize(&mut self) -> Result<usize> {
+        let mut value: usize = 0;
+        let mut shift = 0;
+        loop {
+            let byte = self.u8()?;
+            if shift >= usize::BITS {
+                return Err(Error::Snapshot("number too large".to_string()));
+            }
+            value |= ((byte & 0x7f) as usize) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+            shift += 7;
+        }
+    }
+
+    fn node_id(&mut self) -> Result<NodeId> {
+        Ok(NodeId::from(self.usize()?))
+    }
+
+    fn string(&mut self) -> Result<String> {
+        let length = self.usize()?;
+        Ok(String::from_utf8(self.take(length)?.to_vec())?)
+    }
+
+    /// Reads a node ID that is stored relative to the given one
+    fn delta(&mut self, from: NodeId) -> Result<NodeId> {
+        self.relative(from.into())
+            .map(NodeId::from)
+            .map_err(|_| Error::Snapshot("invalid node id".to_string()))
+    }
+
+    /// Reads a number that is stored relative to the given one
+    fn relative(&mut self, from: usize) -> Result<usize> {
+        let zigzag = self.usize()? as i64;
+        let delta = (zigzag >> 1) ^ -(zigzag & 1);
+        usize::try_from(from as i64 + delta)
+            .map_err(|_| Error::Snapshot(format!("invalid relative number {}", delta)))
+    }
+
+    /// Reads a source span, see [`Writer::span`]
+    fn span(&mut self) -> Result<Option<Span>> {
+        let previous = self.previous_span.unwrap_or(NO_SPAN);
+        let span = match self.usize()? {
+            SPAN_NONE => return Ok(None),
+            SPAN_FULL => {
+                let start = Position::new(
+                    self.relative(previous.end.offset)?,
+                    self.relative(previous.end.line)?,
+                    self.usize()?,
+                );
+                let start_byte = self.relative(previous.end_byte)?;
+                let end = Position::new(
+                    self.relative(start.offset)?,
+                    self.relative(start.line)?,
+                    self.usize()?,
+                );
+                Span {
+                    start,
+                    end,
+                    start_byte,
+                    end_byte: self.relative(start_byte)?,
+                }
+            }
+            tag => {
+                let gap = tag - SPAN_SHORT;
+                let length = self.usize()?;
+                let advance = |position: Position, byte: usize, by: usize| match (
+                    position.offset.checked_add(by),
+                    position.col.checked_add(by),
+                    byte.checked_add(by),
+                ) {
+                    (Some(offset), Some(col), Some(byte)) => {
+                        Ok((Position::new(offset, position.line, col), byte))
+                    }
+                    _ => Err(Error::Snapshot("invalid span".to_string())),
+                };
+                let (start, start_byte) = advance(previous.end, previous.end_byte, gap)?;
+                let (end, end_byte) = advance(start, start_byte, length)?;
+                Span {
+                    start,
+                    end,
+                    start_byte,
+                    end_byte,
+                }
+            }
+        };
+        self.previous_span = Some(span);
+        Ok(Some(span))
+    }
+
+    fn interned(&mut self) -> Result<String> {
+        let index = self.usize()?;
+        self.string_at(index)
+    }
+
+    fn string_at(&self, index: usize) -> Result<String> {
+        self.strings
+            .get(index)
+            .cloned()
+            .ok_or_else(|| Error::Snapshot(format!("invalid string index {}", index)))
+    }
+
+    fn url(&mut self) -> Result<Url> {
+        let url = self.string()?;
+        Url::parse(&url).map_err(|e| Error::Snapshot(format!("invalid url {}: {}", url, e)))
+    }
+
+    fn option<T>(&mut self, read: impl FnOnce(&mut Self) -> Result<T>) -> Result<Option<T>> {
+        if self.bool()? {
+            Ok(Some(read(self)?))
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Reads an arena. Template contents refer to the document they belong to.
+    fn arena(&mut self, document: &DocumentHandle) -> Result<NodeArena> {
+        let next_id = self.node_id()?;
+        let count = self.usize()?;
+
+        // Do not trust the count for the allocation, the snapshot might be corrupt
+        let mut nodes = Vec::with_capacity(count.min(self.bytes.len()));
+        let mut parents = HashMap::new();
+        let mut previous_id = NodeId::root();
+        for _ in 0..count {
+            let node = self.node(document, previous_id)?;
+            if usize::from(node.id) >= usize::from(next_id) {
+                return Err(Error::Snapshot(format!("invalid node id {}", node.id)));
+            }
+            for &child_id in &node.children {
+                parents.insert(child_id, node.id);
+            }
+            previous_id = node.id;
+            nodes.push(node);
+        }
+        for node in &mut nodes {
+            node.parent = parents.get(&node.id).copied();
+        }
+
+        Ok(NodeArena::from_nodes(nodes, next_id))
+    }
+
+    fn node(&mut self, document: &DocumentHandle, previous_id: NodeId) -> Result<Node> {
+        let id = self.delta(previous_id)?;
+        let child_count = self.usize()?;
+        let mut children = Vec::with_capacity(child_count.min(self.bytes.len()));
+        let mut previous_child_id = id;
+        for _ in 0..child_count {
+            previous_child_id = self.delta(previous_child_id)?;
+            children.push(previous_child_id);
+        }
+        let name = self.interned()?;
+        let namespace = match self.usize()? {
+            0 => None,
+            index => Some(self.string_at(index - 1)?),
+        };
+        let source_span = self.span()?;
+
+        let data = match self.u8()? {
+            NODE_DOCUMENT => NodeData::Document(DocumentData::default()),
+            NODE_DOCTYPE => NodeData::DocType(DocTypeData {
+                name: self.string()?,
+                pub_identifier: self.string()?,
+                sys_identifier: self.string()?,
+            }),
+            NODE_TEXT => NodeData::Text(TextData::with_value(&self.string()?)),
+            NODE_COMMENT => NodeData::Comment(CommentData::with_value(&self.string()?)),
+            NODE_ELEMENT => {
+                let mut attributes = HashMap::new();
+                for _ in 0..self.usize()? {
+                    let name = self.interned()?;
+                    attributes.insert(name, self.string()?);
+                }
+
+                let mut classes = ElementClass::new();
+                for _ in 0..self.usize()? {
+                    let name = self.interned()?;
+                    classes.add(&name);
+                    classes.set_active(&name, self.bool()?);
+                }
+
+                let force_async = self.bool()?;
+                let template_contents = self.option(|r| {
+                    let host = r.node_id()?;
+                    let mut fragment = DocumentFragment::new(Document::clone(document), host);
+                    fragment.arena = r.arena(document)?;
+                    Ok(fragment)
+                })?;
+
+                NodeData::Element(Box::new(ElementData {
+                    node_id: id,
+                    name: name.clone(),
+                    attributes,
+                    classes,
+                    force_async,
+                    template_contents,
+                }))
+            }
+            tag => return Err(invalid("node type", tag)),
+        };
+
+        Ok(Node {
+            id,
+            parent: None,
+            children,
+            name,
+            namespace,
+            data,
+            is_registered: true,
+            source_span,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bytes::{CharIterator, Encoding};
+    use crate::html5::parser::Html5Parser;
+    use crate::html5::serializer::{serialize, SerializerOptions};
+    use crate::testing::stress::generate_document;
+
+    fn parse(html: &str) -> (DocumentHandle, Vec<ParseError>) {
+        let mut chars = CharIterator::new();
+        chars.read_from_str(html, Some(Encoding::UTF8));
+
+        let document = DocumentBuilder::new_document();
+        let errors =
+            Html5Parser::parse_document(&mut chars, Document::clone(&document), None).unwrap();
+        (document, errors)
+    }
+
+    fn html(document: &DocumentHandle) -> String {
+        serialize(
+            &document.get(),
+            NodeId::root(),
+            &SerializerOptions::default(),
+        )
+    }
+
+    #[test]
+    fn roundtrip() {
+        let (mut document, errors) = parse(
+            "<html><head><base href='http://example.com/' target=_top></head>\
+             <p id=a class='x y' onclick=go()>text<!-- comment --><b>bold</b>\
+             <svg><circle r=1 /></svg><template><i>in template</i></template>",
+        );
+        assert!(!errors.is_empty());
+        document.get_mut().enable_indexes(&["onclick"]);
+
+        let snapshot = document.get().to_snapshot(&errors);
+        let (loaded, loaded_errors) = DocumentBuilder::from_snapshot(&snapshot).unwrap();
+
+        assert_eq!(loaded_errors, errors);
+        assert_eq!(html(&loaded), html(&document));
+
+        let doc = document.get();
+        let loaded_doc = loaded.get();
+        assert_eq!(loaded_doc.quirks_mode, doc.quirks_mode);
+        assert_eq!(loaded_doc.base_target, doc.base_target);
+        assert_eq!(loaded_doc.frozen_base_url, doc.frozen_base_url);
+        assert_eq!(loaded_doc.named_id_elements, doc.named_id_elements);
+        assert_eq!(loaded_doc.event_listeners, doc.event_listeners);
+        assert_eq!(
+            loaded_doc.indexed_elements_with_attribute("onclick"),
+            doc.indexed_elements_with_attribute("onclick")
+        );
+        for (node, loaded_node) in doc.arena.nodes().zip(loaded_doc.arena.nodes()) {
+            assert_eq!(loaded_node.id, node.id);
+            assert_eq!(loaded_node.parent, node.parent);
+            assert_eq!(loaded_node.children, node.children);
+            assert_eq!(loaded_node.namespace, node.namespace);
+            if let (NodeData::Element(loaded), NodeData::Element(element)) =
+                (&loaded_node.data, &node.data)
+            {
+                assert_eq!(loaded.classes, element.classes);
+                assert_eq!(
+                    loaded.template_contents.is_some(),
+                    element.template_contents.is_some()
+                );
+            }
+        }
+        assert_eq!(loaded_doc.arena.peek_next_id(), doc.arena.peek_next_id());
+    }
+
+    #[test]
+    fn large_document() {
+        let html = generate_document(100_000);
+        let (document, errors) = parse(&html);
+        let snapshot = document.get().to_snapshot(&errors);
+        // IDs, parents and names are not stored in full
+        assert!(snapshot.len() < html.len() * 2);
+
+        let (loaded, _) = DocumentBuilder::from_snapshot(&snapshot).unwrap();
+        assert_eq!(loaded.get().arena, document.get().arena);
+    }
+
+    #[test]
+    fn invalid_snapshots() {
+        let (document, errors) = parse("<p>hello</p>");
+        let snapshot = document.get().to_snapshot(&errors);
+
+        assert!(DocumentBuilder::from_snapshot(b"").is_err());
+        assert!(DocumentBuilder::from_snapshot(b"GOSUBDOC\x63").is_err());
+        for length in 0..snapshot.len() {
+            assert!(DocumentBuilder::from_snapshot(&snapshot[..length]).is_err());
+        }
+        let mut extended = snapshot.clone();
+        extended.push(0);
+        assert!(DocumentBuilder::from_snapshot(&extended).is_err());
+    }
+}
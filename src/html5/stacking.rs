@@ -0,0 +1,626 @@
+//! Stacking contexts and paint order
+//!
+//! Elements are painted in the order of CSS 2.1 Appendix E. A stacking context paints its own
+//! background and borders, then the child stacking contexts with a negative z-index, its
+//! block-level descendants, its floats, its inline-level descendants, the child stacking contexts
+//! with z-index 0 together with the positioned descendants with `z-index: auto` (in tree order),
+//! and finally the child stacking contexts with a positive z-index. Child stacking contexts are
+//! ordered by z-index, and by tree order for equal z-indexes.
+//!
+//! Floats and positioned elements with `z-index: auto` are painted as if they created a stacking
+//! context, except that the positioned elements and stacking contexts inside them take part in the
+//! enclosing stacking context.
+//!
+//! A stacking context is created by the root element, positioned elements with a z-index, fixed
+//! elements, flex and grid items with a z-index, elements with an opacity below 1, and elements
+//! with effects like transforms and filters.
+//!
+//! The properties come from the layout (`LayoutGeometry::paint_style()`), or otherwise from the
+//! declarations of the element itself (see `element_style`) and the defaults of the user agent
+//! style sheet.
+//!
+//! See: https://www.w3.org/TR/CSS21/zindex.html
+use crate::html5::element_style::{cascaded_value, StyleDeclaration};
+use crate::html5::geometry::Positioning;
+use crate::html5::node::{Node, NodeData, NodeId, HTML_NAMESPACE};
+use crate::html5::parser::document::Document;
+use crate::html5::transform::parse_transform;
+use std::fmt::Write;
+
+/// Elements that the user agent style sheet does not display
+const HIDDEN_ELEMENTS: [&str; 11] = [
+    "area", "base", "datalist", "head", "link", "meta", "noembed", "param", "script", "style",
+    "title",
+];
+
+/// Elements that are inline-level in the user agent style sheet
+const INLINE_ELEMENTS: [&str; 45] = [
+    "a", "abbr", "audio", "b", "bdi", "bdo", "big", "br", "button", "canvas", "cite", "code",
+    "data", "del", "dfn", "em", "embed", "font", "i", "iframe", "img", "input", "ins", "kbd",
+    "label", "mark", "meter", "object", "output", "picture", "progress", "q", "s", "samp",
+    "select", "small", "span", "strike", "strong", "sub", "sup", "textarea", "time", "u", "var",
+];
+
+/// Properties that create a stacking context when they are not `none`
+const EFFECT_PROPERTIES: [&str; 6] = [
+    "transform",
+    "filter",
+    "backdrop-filter",
+    "clip-path",
+    "mask",
+    "perspective",
+];
+
+/// How an element takes part in the layout, as far as painting is concerned
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DisplayKind {
+    /// `display: none`: the element and its descendants are not painted
+    None,
+    #[default]
+    Block,
+    Inline,
+}
+
+/// The properties of an element that decide its paint order
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PaintStyle {
+    pub display: DisplayKind,
+    /// The element lays out its children as flex or grid items
+    pub flex_container: bool,
+    pub positioning: Positioning,
+    /// None for `z-index: auto`
+    pub z_index: Option<i32>,
+    pub opacity: f32,
+    pub float: bool,
+    /// The element has a transform, filter, clip path, mask, blend mode, isolation or paint
+    /// containment (or announces one with `will-change`)
+    pub effects: bool,
+    /// The element has a transform, which makes it the containing block of its positioned
+    /// descendants
+    pub transformed: bool,
+}
+
+impl Default for PaintStyle {
+    fn default() -> Self {
+        Self {
+            display: DisplayKind::Block,
+            flex_container: false,
+            positioning: Positioning::Static,
+            z_index: None,
+            opacity: 1.0,
+            float: false,
+            effects: false,
+            transformed: false,
+        }
+    }
+}
+
+impl PaintStyle {
+    /// Applies the declarations to the style. Unknown values are ignored.
+    fn apply(&mut self, declarations: &[StyleDeclaration]) {
+        let value = |property| {
+            cascaded_value(declarations, property).map(|value| value.trim().to_ascii_lowercase())
+        };
+
+        if let Some(display) = value("display") {
+            let keyword = display.split_ascii_whitespace().next().unwrap_or_default();
+            self.display = match keyword {
+                "none" => DisplayKind::None,
+                "inline" | "inline-block" | "inline-flex" | "inline-grid" | "inline-table" => {
+                    DisplayKind::Inline
+                }
+                _ => DisplayKind::Block,
+            };
+            self.flex_container = display.contains("flex") || display.contains("grid");
+        }
+
+        let mut out_of_flow = false;
+        if let Some(position) = value("position") {
+            match position.as_str() {
+                "static" => self.positioning = Positioning::Static,
+                "relative" | "sticky" => self.positioning = Positioning::Positioned,
+                "absolute" => {
+                    self.positioning = Positioning::Absolute;
+                    out_of_flow = true;
+                }
+                "fixed" => {
+                    self.positioning = Positioning::Fixed;
+                    out_of_flow = true;
+                }
+                _ => {}
+            }
+        }
+
+        match value("z-index").as_deref() {
+            Some("auto") => self.z_index = None,
+            Some(z_index) => {
+                if let Ok(z_index) = z_index.parse() {
+                    self.z_index = Some(z_index);
+                }
+            }
+            None => {}
+        }
+
+        if let Some(opacity) = value("opacity") {
+            let parsed = match opacity.strip_suffix('%') {
+                Some(percentage) => percentage.parse::<f32>().map(|value| value / 100.0),
+                None => opacity.parse::<f32>(),
+            };
+            if let Ok(opacity) = parsed {
+                self.opacity = opacity.clamp(0.0, 1.0);
+            }
+        }
+
+        if let Some(float) = value("float") {
+            self.float = matches!(
+                float.as_str(),
+                "left" | "right" | "inline-start" | "inline-end"
+            );
+        }
+        // Absolutely positioned elements do not float
+        if out_of_flow {
+            self.float = false;
+        }
+
+        if let Some(transform) = value("transform") {
+            self.transformed =
+                parse_transform(&transform).is_ok_and(|functions| !functions.is_empty());
+        }
+
+        let will_change = value("will-change").unwrap_or_default();
+        self.effects |= EFFECT_PROPERTIES.iter().any(|&property| {
+            value(property).is_some_and(|value| value != "none")
+                || will_change.split(',').any(|name| name.trim() == property)
+        });
+        self.effects |= value("mix-blend-mode").is_some_and(|value| value != "normal")
+            || value("isolation").is_some_and(|value| value == "isolate")
+            || value("contain").is_some_and(|value| {
+                ["strict", "content", "paint", "layout"]
+                    .iter()
+                    .any(|keyword| value.split_ascii_whitespace().any(|word| word == *keyword))
+            })
+            || will_change
+                .split(',')
+                .any(|name| name.trim() == "opacity" || name.trim() == "isolation");
+    }
+}
+
+/// Why an element is painted as a unit
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StackingKind {
+    /// The element creates a stacking context
+    Context,
+    /// A positioned element with `z-index: auto`
+    Positioned,
+    /// A float
+    Float,
+}
+
+/// A stacking context, or an element that is painted as if it created one. The lists hold the
+/// contents in the order of the paint layers.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StackingContext {
+    pub element: NodeId,
+    pub kind: StackingKind,
+    /// The z-index of a stacking context (0 for the others)
+    pub z_index: i32,
+    /// Child stacking contexts with a negative z-index, in paint order
+    pub negative: Vec<StackingContext>,
+    /// Block-level descendants that are not positioned, in tree order
+    pub blocks: Vec<NodeId>,
+    /// Floats, in tree order
+    pub floats: Vec<StackingContext>,
+    /// Inline-level descendants that are not positioned, in tree order
+    pub inlines: Vec<NodeId>,
+    /// Child stacking contexts with z-index 0 and positioned descendants with `z-index: auto`,
+    /// in tree order
+    pub zero: Vec<StackingContext>,
+    /// Child stacking contexts with a positive z-index, in paint order
+    pub positive: Vec<StackingContext>,
+}
+
+impl StackingContext {
+    fn new(element: NodeId, kind: StackingKind, z_index: i32) -> Self {
+        Self {
+            element,
+            kind,
+            z_index,
+            negative: Vec::new(),
+            blocks: Vec::new(),
+            floats: Vec::new(),
+            inlines: Vec::new(),
+            zero: Vec::new(),
+            positive: Vec::new(),
+        }
+    }
+
+    /// Returns the elements in the order they are painted
+    pub fn paint_order(&self) -> Vec<NodeId> {
+        let mut order = Vec::new();
+        self.collect_paint_order(&mut order);
+        order
+    }
+
+    fn collect_paint_order(&self, order: &mut Vec<NodeId>) {
+        order.push(self.element);
+        for context in &self.negative {
+            context.collect_paint_order(order);
+        }
+        order.extend(&self.blocks);
+        for float in &self.floats {
+            float.collect_paint_order(order);
+        }
+        order.extend(&self.inlines);
+        for context in self.zero.iter().chain(&self.positive) {
+            context.collect_paint_order(order);
+        }
+    }
+
+    /// Returns the tree as indented text, for debugging. Elements are shown by their tag name
+    /// and id.
+    pub fn dump(&self, document: &Document) -> String {
+        let mut output = String::new();
+        self.dump_into(document, 0, &mut output);
+        output
+    }
+
+    fn dump_into(&self, document: &Document, depth: usize, output: &mut String) {
+        let indent = "  ".repeat(depth);
+        let kind = match self.kind {
+            StackingKind::Context => format!("stacking context, z-index {}", self.z_index),
+            StackingKind::Positioned => "positioned".to_string(),
+            StackingKind::Float => "float".to_string(),
+        };
+        let _ = writeln!(output, "{indent}{} ({kind})", label(document, self.element));
+
+        for (layer, elements) in [("blocks", &self.blocks), ("inlines", &self.inlines)] {
+            if !elements.is_empty() {
+                let labels: Vec<String> = elements
+                    .iter()
+                    .map(|&node_id| label(document, node_id))
+                    .collect();
+                let _ = writeln!(output, "{indent}  {layer}: {}", labels.join(", "));
+            }
+        }
+        for context in self
+            .negative
+            .iter()
+            .chain(&self.floats)
+            .chain(&self.zero)
+            .chain(&self.positive)
+        {
+            context.dump_into(document, depth + 1, output);
+        }
+    }
+}
+
+/// Returns the tag name of the element, with its id when it has one
+fn label(document: &Document, node_id: NodeId) -> String {
+    let Some(node) = document.get_node_by_id(node_id) else {
+        return node_id.to_string();
+    };
+    match node.get_attribute("id") {
+        Some(id) => format!("{}#{id}", node.name),
+        None => node.name.clone(),
+    }
+}
+
+impl Document {
+    /// Returns the paint style of the element, or None when the node is not an element
+    pub fn paint_style(&self, node_id: NodeId) -> Option<PaintStyle> {
+        let node = self.get_node_by_id(node_id)?;
+        let NodeData::Element(_) = node.data else {
+            return None;
+        };
+        if let Some(style) = self
+            .geometry
+            .0
+            .as_ref()
+            .and_then(|geometry| geometry.paint_style(node_id))
+        {
+            return Some(style);
+        }
+
+        let mut style = PaintStyle {
+            display: default_display(node),
+            ..PaintStyle::default()
+        };
+        style.apply(&self.declared_style(node_id));
+        if self.is_hidden(node_id) {
+            style.display = DisplayKind::None;
+        }
+        Some(style)
+    }
+
+    /// Returns the stacking context of the root element with all the elements that are painted,
+    /// or None when there is no root element or it is not displayed
+    pub fn stacking_context_tree(&self) -> Option<StackingContext> {
+        let root_id = self
+            .get_node_by_id(NodeId::root())?
+            .children
+            .iter()
+            .copied()
+            .find(|&node_id| self.paint_style(node_id).is_some())?;
+        let root_style = self.paint_style(root_id)?;
+        if root_style.display == DisplayKind::None {
+            return None;
+        }
+
+        // The contexts are built in a flat list first, and linked by their indexes
+        let mut groups = vec![Group::new(root_id, StackingKind::Context, 0)];
+        // (node, parent is a flex or grid container, nearest stacking context, current group)
+        let mut stack: Vec<(NodeId, bool, usize, usize)> = Vec::new();
+        self.push_children(&mut stack, root_id, root_style.flex_container, 0, 0);
+
+        while let Some((node_id, flex_item, context, group)) = stack.pop() {
+            let Some(style) = self.paint_style(node_id) else {
+                continue;
+            };
+            if style.display == DisplayKind::None {
+                continue;
+            }
+
+            let creates_context = style.positioning == Positioning::Fixed
+                || (style.z_index.is_some()
+                    && (matches!(
+                        style.positioning,
+                        Positioning::Positioned | Positioning::Absolute
+                    ) || flex_item))
+                || style.opacity < 1.0
+                || style.effects;
+            let (child_context, child_group) = if creates_context {
+                let z_index = style.z_index.unwrap_or(0);
+                let index = groups.len();
+                groups.push(Group::new(node_id, StackingKind::Context, z_index));
+                let parent = &mut groups[context];
+                match z_index {
+                    z if z < 0 => parent.negative.push(index),
+                    0 => parent.zero.push(index),
+                    _ => parent.positive.push(index),
+                }
+                (index, index)
+            } else if style.positioning != Positioning::Static {
+                let index = groups.len();
+                groups.push(Group::new(node_id, StackingKind::Positioned, 0));
+                groups[context].zero.push(index);
+                (context, index)
+            } else if style.float {
+                let index = groups.len();
+                groups.push(Group::new(node_id, StackingKind::Float, 0));
+                groups[group].floats.push(index);
+                (context, index)
+            } else {
+                match style.display {
+                    DisplayKind::Inline => groups[group].inlines.push(node_id),
+                    _ => groups[group].blocks.push(node_id),
+                }
+                (context, group)
+            };
+
+            self.push_children(
+                &mut stack,
+                node_id,
+                style.flex_container,
+                child_context,
+                child_group,
+            );
+        }
+
+        Some(build_context(&groups, 0))
+    }
+
+    fn push_children(
+        &self,
+        stack: &mut Vec<(NodeId, bool, usize, usize)>,
+        node_id: NodeId,
+        flex_container: bool,
+        context: usize,
+        group: usize,
+    ) {
+        if let Some(node) = self.get_node_by_id(node_id) {
+            stack.extend(
+                node.children
+                    .iter()
+                    .rev()
+                    .map(|&child_id| (child_id, flex_container, context, group)),
+            );
+        }
+    }
+}
+
+/// Returns the display of the element in the user agent style sheet
+fn default_display(node: &Node) -> DisplayKind {
+    if node.namespace.as_deref() != Some(HTML_NAMESPACE) {
+        // SVG and MathML elements are laid out inline
+        return DisplayKind::Inline;
+    }
+    let name = node.name.as_str();
+    if HIDDEN_ELEMENTS.contains(&name) {
+        DisplayKind::None
+    } else if INLINE_ELEMENTS.contains(&name) {
+        DisplayKind::Inline
+    } else {
+        DisplayKind::Block
+    }
+}
+
+/// A stacking context under construction, which refers to the other groups by index
+struct Group {
+    element: NodeId,
+    kind: StackingKind,
+    z_index: i32,
+    negative: Vec<usize>,
+    blocks: Vec<NodeId>,
+    floats: Vec<usize>,
+    inlines: Vec<NodeId>,
+    zero: Vec<usize>,
+    positive: Vec<usize>,
+}
+
+impl Group {
+    fn new(element: NodeId, kind: StackingKind, z_index: i32) -> Self {
+        Self {
+            element,
+            kind,
+            z_index,
+            negative: Vec::new(),
+            blocks: Vec::new(),
+            floats: Vec::new(),
+            inlines: Vec::new(),
+            zero: Vec::new(),
+            positive: Vec::new(),
+        }
+    }
+}
+
+fn build_context(groups: &[Group], index: usize) -> StackingContext {
+    let group = &groups[index];
+    let build = |indexes: &[usize]| -> Vec<StackingContext> {
+        indexes
+            .iter()
+            .map(|&index| build_context(groups, index))
+            .collect()
+    };
+
+    let mut context = StackingContext::new(group.element, group.kind, group.z_index);
+    context.negative = build(&group.negative);
+    context.blocks = group.blocks.clone();
+    context.floats = build(&group.floats);
+    context.inlines = group.inlines.clone();
+    context.zero = build(&group.zero);
+    context.positive = build(&group.positive);
+    // Stable sorts keep the tree order for equal z-indexes
+    context.negative.sort_by_key(|child| child.z_index);
+    context.positive.sort_by_key(|child| child.z_index);
+    context
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::html5::element_style::parse_declarations;
+    use crate::html5::geometry::{LayoutGeometry, Rect};
+    use crate::testing::parse;
+    use std::rc::Rc;
+
+    fn labels(document: &Document, node_ids: &[NodeId]) -> Vec<String> {
+        node_ids
+            .iter()
+            .map(|&node_id| label(document, node_id))
+            .collect()
+    }
+
+    #[test]
+    fn paint_styles() {
+        let style = |declarations: &str| {
+            let mut style = PaintStyle::default();
+            style.apply(&parse_declarations(declarations));
+            style
+        };
+
+        let positioned = style("position: relative; z-index: 2");
+        assert_eq!(positioned.positioning, Positioning::Positioned);
+        assert_eq!(positioned.z_index, Some(2));
+        assert_eq!(style("z-index: 3; z-index: auto").z_index, None);
+        assert_eq!(style("opacity: 50%").opacity, 0.5);
+        assert_eq!(style("opacity: 2").opacity, 1.0);
+        assert!(style("float: left").float);
+        assert!(!style("float: left; position: absolute").float);
+        assert_eq!(
+            style("position: absolute").positioning,
+            Positioning::Absolute
+        );
+        assert!(style("transform: scale(2)").transformed);
+        assert!(!style("transform: none").transformed);
+        assert!(style("transform: rotate(3deg)").effects);
+        assert!(style("will-change: top, transform").effects);
+        assert!(style("isolation: isolate").effects);
+        assert!(!style("transform: none; mix-blend-mode: normal").effects);
+        let flex = style("display: inline-flex");
+        assert_eq!(flex.display, DisplayKind::Inline);
+        assert!(flex.flex_container);
+
+        let document = parse("<span id=a>x</span><p hidden id=b></p><svg></svg>");
+        let doc = document.get();
+        let display = |id: &str| {
+            let node_id = doc.get_node_by_named_id(id).unwrap().id;
+            doc.paint_style(node_id).unwrap().display
+        };
+        assert_eq!(display("a"), DisplayKind::Inline);
+        assert_eq!(display("b"), DisplayKind::None);
+        let head = doc.elements_by_tag_name("head")[0];
+        assert_eq!(doc.paint_style(head).unwrap().display, DisplayKind::None);
+        let text = doc.get_node_by_named_id("a").unwrap().children[0];
+        assert!(doc.paint_style(text).is_none());
+    }
+
+    #[test]
+    fn paint_order() {
+        let document = parse(
+            r#"<div id=a style="position: relative; z-index: 1">
+                 <div id=b style="position: absolute; z-index: -1"></div>
+               </div>
+               <div id=c style="position: relative"><span id=d style="position: relative; z-index: 5"></span></div>
+               <div id=e style="float: left"><div id=f style="position: absolute"></div><p id=p></p></div>
+               <span id=g>text</span>
+               <div id=h style="opacity: 0.5"></div>
+               <div id=i style="position: relative; z-index: -2"></div>
+               <div style="display: flex"><div id=j style="z-index: 1"></div></div>"#,
+        );
+        let doc = document.get();
+        let tree = doc.stacking_context_tree().unwrap();
+
+        assert_eq!(
+            labels(&doc, &tree.paint_order()),
+            [
+                "html", "div#i", "body", "div", "div#e", "p#p", "span#g", "div#c", "div#f",
+                "div#h", "div#a", "div#b", "div#j", "span#d"
+            ]
+        );
+        // Positioned elements in a float or a positioned element belong to the stacking context
+        let float = &tree.floats[0];
+        assert_eq!(float.kind, StackingKind::Float);
+        assert_eq!(labels(&doc, &float.blocks), ["p#p"]);
+        assert!(float.zero.is_empty());
+        assert_eq!(tree.positive.len(), 3);
+    }
+
+    struct Layout(NodeId);
+
+    impl LayoutGeometry for Layout {
+        fn border_box(&self, _node_id: NodeId) -> Option<Rect> {
+            None
+        }
+
+        fn viewport(&self) -> Rect {
+            Rect::new(0.0, 0.0, 800.0, 600.0)
+        }
+
+        fn paint_style(&self, node_id: NodeId) -> Option<PaintStyle> {
+            (node_id == self.0).then(|| PaintStyle {
+                positioning: Positioning::Fixed,
+                ..PaintStyle::default()
+            })
+        }
+    }
+
+    #[test]
+    fn layout_paint_style_and_dump() {
+        let mut document = parse(
+            r#"<div id=a style="position: relative"><p id=b style="position: relative; z-index: -1"></p></div><em>x</em>"#,
+        );
+        let a = document.get().get_node_by_named_id("a").unwrap().id;
+        document.get_mut().set_layout_geometry(Rc::new(Layout(a)));
+
+        let doc = document.get();
+        assert_eq!(doc.paint_style(a).unwrap().positioning, Positioning::Fixed);
+        assert_eq!(
+            doc.stacking_context_tree().unwrap().dump(&doc),
+            "html (stacking context, z-index 0)\n\
+             \x20 blocks: body\n\
+             \x20 inlines: em\n\
+             \x20 div#a (stacking context, z-index 0)\n\
+             \x20   p#b (stacking context, z-index -1)\n"
+        );
+    }
+}
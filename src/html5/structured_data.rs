@@ -0,0 +1,285 @@
+//! Structured data extraction
+//!
+//! Extracts microdata (`itemscope` / `itemprop` attributes) and JSON-LD
+//! (`<script type="application/ld+json">`) items from a document. Microdata items are converted
+//! to JSON according to the microdata specification, so both sources can be consumed in the
+//! same way.
+//!
+//! See: https://html.spec.whatwg.org/multipage/microdata.html#json
+use crate::html5::node::{Node, NodeData, NodeId};
+use crate::html5::parser::document::Document;
+use serde_json::{json, Map, Value};
+use std::collections::HashSet;
+
+/// All structured data found in a document
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct StructuredData {
+    /// Top-level microdata items in document order
+    pub microdata: Vec<Value>,
+    /// Contents of all valid JSON-LD blocks in document order
+    pub json_ld: Vec<Value>,
+    /// Errors found while parsing JSON-LD blocks
+    pub errors: Vec<String>,
+}
+
+impl Document {
+    /// Extracts all microdata items and JSON-LD blocks from the document
+    pub fn structured_data(&self) -> StructuredData {
+        let mut data = StructuredData::default();
+
+        for node_id in self.elements_by_tag_name("script") {
+            let Some(node) = self.get_node_by_id(node_id) else {
+                continue;
+            };
+            if !is_json_ld(node) {
+                continue;
+            }
+
+            match serde_json::from_str::<Value>(&self.text_content(node_id)) {
+                Ok(value) => data.json_ld.push(value),
+                Err(err) => data.errors.push(format!("invalid json-ld block: {}", err)),
+            }
+        }
+
+        for node_id in self.top_level_items() {
+            let mut memory = HashSet::new();
+            data.microdata
+                .push(self.microdata_item(node_id, &mut memory));
+        }
+
+        data
+    }
+
+    /// Returns all elements that are top-level microdata items (itemscope without itemprop)
+    fn top_level_items(&self) -> Vec<NodeId> {
+        let mut items = Vec::new();
+
+        let mut stack = vec![NodeId::root()];
+        while let Some(node_id) = stack.pop() {
+            let Some(node) = self.get_node_by_id(node_id) else {
+                continue;
+            };
+            if node.get_attribute("itemscope").is_some() && node.get_attribute("itemprop").is_none()
+            {
+                items.push(node_id);
+            }
+            stack.extend(node.children.iter().rev());
+        }
+
+        items
+    }
+
+    /// Converts the item on the given node to a JSON object. The memory holds the items that are
+    /// currently being converted, so cyclic item references are not followed.
+    fn microdata_item(&self, item_id: NodeId, memory: &mut HashSet<NodeId>) -> Value {
+        let node = self.get_node_by_id(item_id).expect("item not found");
+        let mut result = Map::new();
+
+        if let Some(item_type) = node.get_attribute("itemtype") {
+            let types = item_type
+                .split_ascii_whitespace()
+                .map(|t| Value::String(t.to_string()))
+                .collect::<Vec<_>>();
+            if !types.is_empty() {
+                result.insert("type".into(), Value::Array(types));
+            }
+        }
+        if let Some(item_id) = node.get_attribute("itemid") {
+            result.insert("id".into(), Value::String(item_id.trim().to_string()));
+        }
+
+        memory.insert(item_id);
+
+        let mut properties = Map::new();
+        for property_id in self.item_properties(item_id) {
+            let property = self
+                .get_node_by_id(property_id)
+                .expect("property not found");
+
+            let value = if property.get_attribute("itemscope").is_some() {
+                if memory.contains(&property_id) {
+                    Value::String("ERROR".into())
+                } else {
+                    self.microdata_item(property_id, memory)
+                }
+            } else {
+                Value::String(self.property_value(property))
+            };
+
+            let names = property
+                .get_attribute("itemprop")
+                .cloned()
+                .unwrap_or_default();
+            for name in names.split_ascii_whitespace() {
+                properties
+                    .entry(name.to_string())
+                    .or_insert_with(|| json!([]))
+                    .as_array_mut()
+                    .expect("property list")
+                    .push(value.clone());
+            }
+        }
+
+        memory.remove(&item_id);
+
+        result.insert("properties".into(), Value::Object(properties));
+        Value::Object(result)
+    }
+
+    /// Returns the properties of the given item in tree order. The subtree of the item and all
+    /// elements referenced by its itemref attribute are crawled, without descending into nested
+    /// items.
+    fn item_properties(&self, item_id: NodeId) -> Vec<NodeId> {
+        let item = self.get_node_by_id(item_id).expect("item not found");
+
+        let mut pending = item.children.iter().rev().copied().collect::<Vec<_>>();
+        if let Some(item_ref) = item.get_attribute("itemref") {
+            for id in item_ref.split_ascii_whitespace().rev() {
+                if let Some(node) = self.get_node_by_named_id(id) {
+                    pending.push(node.id);
+                }
+            }
+        }
+
+        let mut visited = HashSet::new();
+        let mut properties = Vec::new();
+        while let Some(node_id) = pending.pop() {
+            if node_id == item_id || !visited.insert(node_id) {
+                continue;
+            }
+            let Some(node) = self.get_node_by_id(node_id) else {
+                continue;
+            };
+
+            if node.get_attribute("itemprop").is_some() {
+                properties.push(node_id);
+            }
+            if node.get_attribute("itemscope").is_none() {
+                pending.extend(node.children.iter().rev());
+            }
+        }
+
+        properties.sort_by_key(|node_id| self.tree_order(*node_id));
+        properties
+    }
+
+    /// Returns the value of a (non-item) property element
+    fn property_value(&self, node: &Node) -> String {
+        let attribute = match node.name.as_str() {
+            "meta" => Some("content"),
+            "audio" | "embed" | "iframe" | "img" | "source" | "track" | "video" => Some("src"),
+            "a" | "area" | "link" => Some("href"),
+            "object" => Some("data"),
+            "data" | "meter" => Some("value"),
+            "time" if node.get_attribute("datetime").is_some() => Some("datetime"),
+            _ => None,
+        };
+
+        match attribute {
+            Some(attribute) => node.get_attribute(attribute).cloned().unwrap_or_default(),
+            None => self.text_content(node.id),
+        }
+    }
+
+    /// Returns the path of child indices from the root to the node, which sorts in tree order
+    fn tree_order(&self, node_id: NodeId) -> Vec<usize> {
+        let mut path = Vec::new();
+
+        let mut current = node_id;
+        while let Some(parent_id) = self.get_node_by_id(current).and_then(|n| n.parent) {
+            let parent = self.get_node_by_id(parent_id).expect("parent not found");
+            let index = parent.children.iter().position(|&id| id == current);
+            path.push(index.unwrap_or_default());
+            current = parent_id;
+        }
+
+        path.reverse();
+        path
+    }
+}
+
+/// Returns true when the node is a script element containing JSON-LD
+fn is_json_ld(node: &Node) -> bool {
+    if !matches!(node.data, NodeData::Element(_)) {
+        return false;
+    }
+
+    node.get_attribute("type").is_some_and(|t| {
+        let mime_type = t.split(';').next().unwrap_or_default();
+        mime_type.trim().eq_ignore_ascii_case("application/ld+json")
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::parse;
+
+    #[test]
+    fn extract_microdata() {
+        let document = parse(
+            r#"<div itemscope itemtype="https://schema.org/Movie" itemid="urn:movie:1">
+              <h1 itemprop="name">Avatar</h1>
+              <span>Director: <span itemprop="director" itemscope itemtype="https://schema.org/Person">
+                <span itemprop="name">James Cameron</span></span></span>
+              <a itemprop="trailer url" href="/trailer.mp4">Trailer</a>
+              <meta itemprop="genre" content="Science fiction">
+              <img itemprop="image" src="avatar.jpg">
+            </div>"#,
+        );
+
+        let data = document.get().structured_data();
+        assert!(data.json_ld.is_empty());
+        assert_eq!(data.microdata.len(), 1);
+        assert_eq!(
+            data.microdata[0],
+            json!({
+                "type": ["https://schema.org/Movie"],
+                "id": "urn:movie:1",
+                "properties": {
+                    "name": ["Avatar"],
+                    "director": [{
+                        "type": ["https://schema.org/Person"],
+                        "properties": { "name": ["James Cameron"] }
+                    }],
+                    "trailer": ["/trailer.mp4"],
+                    "url": ["/trailer.mp4"],
+                    "genre": ["Science fiction"],
+                    "image": ["avatar.jpg"]
+                }
+            })
+        );
+    }
+
+    #[test]
+    fn microdata_itemref() {
+        let document = parse(
+            r#"<div itemscope itemref="extra"><p itemprop="a">1</p></div>
+               <p id="extra" itemprop="b">2</p>"#,
+        );
+
+        let data = document.get().structured_data();
+        assert_eq!(
+            data.microdata,
+            vec![json!({ "properties": { "a": ["1"], "b": ["2"] } })]
+        );
+    }
+
+    #[test]
+    fn extract_json_ld() {
+        let document = parse(
+            r#"<html><head>
+              <script type="application/ld+json">{"@context": "https://schema.org", "@type": "Organization"}</script>
+              <script type="Application/LD+JSON">[1, 2</script>
+              <script>var x = 1;</script>
+            </head></html>"#,
+        );
+
+        let data = document.get().structured_data();
+        assert_eq!(
+            data.json_ld,
+            vec![json!({"@context": "https://schema.org", "@type": "Organization"})]
+        );
+        assert_eq!(data.errors.len(), 1);
+    }
+}
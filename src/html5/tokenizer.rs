@@ -1,3 +1,4 @@
+pub mod charref;
 pub mod state;
 pub mod token;
 
@@ -5,9 +6,10 @@ mod character_reference;
 mod replacement_tables;
 
 use crate::bytes::Bytes::{self, *};
-use crate::bytes::{CharIterator, Position};
+use crate::bytes::{CharIterator, Position, Span};
 use crate::html5::error_logger::{ErrorLogger, ParserError};
 use crate::html5::node::HTML_NAMESPACE;
+use crate::html5::parser::limits::{truncate_text, Limit, ParserLimits};
 use crate::html5::tokenizer::state::State;
 use crate::html5::tokenizer::token::Token;
 use crate::types::{Error, Result};
@@ -44,14 +46,127 @@ pub struct Tokenizer<'stream> {
     pub temporary_buffer: String,
     /// Queue of emitted tokens. Needed because we can generate multiple tokens during iteration
     pub token_queue: Vec<Token>,
+    /// Spans of the tokens in the queue
+    pub(crate) token_spans: Vec<Span>,
     /// The last emitted start token (or empty if none)
     pub last_start_token: String,
+    /// Position of the last `<` that was read, where a tag, comment or doctype starts
+    last_less_than: Position,
+    /// Start of the token that is currently in the making
+    token_start: Position,
+    /// End of the last emitted token, where the next text token starts
+    last_token_end: Position,
+    /// Span of the token that was returned last
+    last_span: Span,
+    /// Character offset and byte offset of the last computed byte offset
+    byte_offset_cache: (usize, usize),
     /// Error logger to log errors to
     pub error_logger: Rc<RefCell<ErrorLogger>>,
+    /// Maximum number of attributes of a tag (later attributes are dropped)
+    max_attributes: Option<usize>,
+    /// Maximum size of an attribute value (longer values are truncated)
+    max_attribute_length: Option<usize>,
+    /// Limits that have been exceeded since the parser last looked
+    pub(crate) exceeded_limits: Vec<Limit>,
+}
+
+/// State of the tokenizer at a point in the input stream. When the tokenizer runs out of input
+/// while more can still arrive, it is restored to the checkpoint and tokenizes again when the
+/// input has been appended.
+pub(crate) struct Checkpoint {
+    state: State,
+    consumed: String,
+    current_attr_name: String,
+    current_attr_value: String,
+    current_attrs: HashMap<String, String>,
+    current_token: Option<Token>,
+    temporary_buffer: String,
+    token_queue: Vec<Token>,
+    token_spans: Vec<Span>,
+    last_start_token: String,
+    last_less_than: Position,
+    token_start: Position,
+    last_token_end: Position,
+    last_span: Span,
+    position: Position,
+    has_read_eof: bool,
+    error_count: usize,
 }
 
 impl<'stream> Tokenizer<'stream> {
+    /// Returns the current state of the tokenizer
+    pub(crate) fn checkpoint(&self) -> Checkpoint {
+        Checkpoint {
+            state: self.state,
+            consumed: self.consumed.clone(),
+            current_attr_name: self.current_attr_name.clone(),
+            current_attr_value: self.current_attr_value.clone(),
+            current_attrs: self.current_attrs.clone(),
+            current_token: self.current_token.clone(),
+            temporary_buffer: self.temporary_buffer.clone(),
+            token_queue: self.token_queue.clone(),
+            token_spans: self.token_spans.clone(),
+            last_start_token: self.last_start_token.clone(),
+            last_less_than: self.last_less_than,
+            token_start: self.token_start,
+            last_token_end: self.last_token_end,
+            last_span: self.last_span,
+            position: self.chars.position,
+            has_read_eof: self.chars.has_read_eof,
+            error_count: self.error_logger.borrow().error_count(),
+        }
+    }
+
+    /// Restores the tokenizer (and the position in the stream) to the checkpoint. Errors that were
+    /// logged after the checkpoint are removed.
+    pub(crate) fn restore(&mut self, checkpoint: Checkpoint) {
+        self.state = checkpoint.state;
+        self.consumed = checkpoint.consumed;
+        self.current_attr_name = checkpoint.current_attr_name;
+        self.current_attr_value = checkpoint.current_attr_value;
+        self.current_attrs = checkpoint.current_attrs;
+        self.current_token = checkpoint.current_token;
+        self.temporary_buffer = checkpoint.temporary_buffer;
+        self.token_queue = checkpoint.token_queue;
+        self.token_spans = checkpoint.token_spans;
+        self.last_start_token = checkpoint.last_start_token;
+        self.last_less_than = checkpoint.last_less_than;
+        self.token_start = checkpoint.token_start;
+        self.last_token_end = checkpoint.last_token_end;
+        self.last_span = checkpoint.last_span;
+        self.chars.position = checkpoint.position;
+        self.chars.has_read_eof = checkpoint.has_read_eof;
+        self.error_logger
+            .borrow_mut()
+            .truncate(checkpoint.error_count);
+    }
+
+    /// Resets the tokenizer to its initial state, for tokenizing the (rewound) stream again
+    pub(crate) fn reset(&mut self) {
+        self.state = State::Data;
+        self.consumed.clear();
+        self.current_attr_name.clear();
+        self.current_attr_value.clear();
+        self.current_attrs.clear();
+        self.current_token = None;
+        self.temporary_buffer.clear();
+        self.token_queue.clear();
+        self.token_spans.clear();
+        self.last_start_token.clear();
+        self.exceeded_limits.clear();
+        self.last_less_than = START;
+        self.token_start = START;
+        self.last_token_end = START;
+        self.last_span = EMPTY_SPAN;
+        self.byte_offset_cache = (0, 0);
+    }
+
+    /// Inserts tokens at the start of the queue. They get the span of the token that was
+    /// returned last, as they are made from it.
     pub(crate) fn insert_tokens_at_queue_start(&mut self, first_tokens: Vec<Token>) {
+        let spans = vec![self.last_span; first_tokens.len()];
+        self.token_spans.splice(0..0, spans);
+
         let mut new_queue = first_tokens.clone();
         new_queue.extend(self.token_queue.iter().cloned());
 
@@ -59,6 +174,21 @@ impl<'stream> Tokenizer<'stream> {
     }
 }
 
+/// Position of the start of the stream
+const START: Position = Position {
+    offset: 0,
+    line: 1,
+    col: 1,
+};
+
+/// Empty span at the start of the stream
+const EMPTY_SPAN: Span = Span {
+    start: START,
+    end: START,
+    start_byte: 0,
+    end_byte: 0,
+};
+
 /// This struct is a gateway between the parser and the tokenizer. It holds data that can be needed
 /// by the tokenizer in certain cases. See https://github.com/gosub-browser/gosub-engine/issues/230 for
 /// more information and how we should refactor this properly.
@@ -113,14 +243,29 @@ impl<'stream> Tokenizer<'stream> {
             consumed: String::new(),
             current_token: None,
             token_queue: vec![],
+            token_spans: vec![],
+            last_less_than: START,
+            token_start: START,
+            last_token_end: START,
+            last_span: EMPTY_SPAN,
+            byte_offset_cache: (0, 0),
             current_attr_name: String::new(),
             current_attr_value: String::new(),
             current_attrs: HashMap::new(),
             temporary_buffer: String::new(),
             error_logger,
+            max_attributes: None,
+            max_attribute_length: None,
+            exceeded_limits: Vec::new(),
         };
     }
 
+    /// Sets the limits on the attributes of tags
+    pub(crate) fn set_limits(&mut self, limits: &ParserLimits) {
+        self.max_attributes = limits.max_attributes;
+        self.max_attribute_length = limits.max_attribute_length;
+    }
+
     /// Returns the current position in the stream (with line/col number and position)
     pub(crate) fn get_position(&self) -> Position {
         self.chars.position
@@ -128,13 +273,49 @@ impl<'stream> Tokenizer<'stream> {
 
     /// Retrieves the next token from the input stream or Token::EOF when the end is reached
     pub fn next_token(&mut self, parser_data: ParserData) -> Result<Token> {
+        self.next_token_with_span(parser_data)
+            .map(|(token, _)| token)
+    }
+
+    /// Retrieves the next token like `next_token()`, together with the part of the input stream
+    /// it was made from. The span of a tag, comment or doctype runs from its `<` up to and
+    /// including its `>`, and the span of a text token covers the text between the tokens around
+    /// it (with the character references as written).
+    pub fn next_token_with_span(&mut self, parser_data: ParserData) -> Result<(Token, Span)> {
         self.consume_stream(parser_data)?;
 
         if self.token_queue.is_empty() {
-            return Ok(Token::Eof);
+            let position = self.chars.position;
+            let span = self.span(position, position);
+            return Ok((Token::Eof, span));
+        }
+
+        let span = self.token_spans.remove(0);
+        self.last_span = span;
+        Ok((self.token_queue.remove(0), span))
+    }
+
+    /// Returns the span between the positions
+    fn span(&mut self, start: Position, end: Position) -> Span {
+        Span {
+            start,
+            end,
+            start_byte: self.byte_offset(start.offset),
+            end_byte: self.byte_offset(end.offset),
         }
+    }
 
-        Ok(self.token_queue.remove(0))
+    /// Returns the byte offset of the character offset. Tokens are mostly emitted in order, so the
+    /// bytes are counted from the last computed offset.
+    fn byte_offset(&mut self, offset: usize) -> usize {
+        let (cached_offset, cached_bytes) = self.byte_offset_cache;
+        let bytes = if offset >= cached_offset {
+            cached_bytes + self.chars.utf8_len(cached_offset, offset)
+        } else {
+            cached_bytes - self.chars.utf8_len(offset, cached_offset)
+        };
+        self.byte_offset_cache = (offset, bytes);
+        bytes
     }
 
     /// Returns the error logger
@@ -263,7 +444,7 @@ impl<'stream> Tokenizer<'stream> {
                         Ch('!') => self.state = State::MarkupDeclarationOpen,
                         Ch('/') => self.state = State::EndTagOpen,
                         Ch(ch) if ch.is_ascii_alphabetic() => {
-                            self.current_token = Some(Token::StartTag {
+                            self.start_token(Token::StartTag {
                                 name: "".into(),
                                 is_self_closing: false,
                                 attributes: HashMap::new(),
@@ -272,7 +453,7 @@ impl<'stream> Tokenizer<'stream> {
                             self.state = State::TagName;
                         }
                         Ch('?') => {
-                            self.current_token = Some(Token::Comment("".into()));
+                            self.start_token(Token::Comment("".into()));
                             self.parse_error(ParserError::UnexpectedQuestionMarkInsteadOfTagName);
                             self.chars.unread();
                             self.state = State::BogusComment;
@@ -294,7 +475,7 @@ impl<'stream> Tokenizer<'stream> {
                     let c = self.read_char();
                     match c {
                         Ch(ch) if ch.is_ascii_alphabetic() => {
-                            self.current_token = Some(Token::EndTag {
+                            self.start_token(Token::EndTag {
                                 name: "".into(),
                                 is_self_closing: false,
                             });
@@ -313,7 +494,7 @@ impl<'stream> Tokenizer<'stream> {
                         }
                         _ => {
                             self.parse_error(ParserError::InvalidFirstCharacterOfTagName);
-                            self.current_token = Some(Token::Comment("".into()));
+                            self.start_token(Token::Comment("".into()));
                             self.chars.unread();
                             self.state = State::BogusComment;
                         }
@@ -360,7 +541,7 @@ impl<'stream> Tokenizer<'stream> {
                     let c = self.read_char();
                     match c {
                         Ch(ch) if ch.is_ascii_alphabetic() => {
-                            self.current_token = Some(Token::EndTag {
+                            self.start_token(Token::EndTag {
                                 name: "".into(),
                                 is_self_closing: false,
                             });
@@ -452,7 +633,7 @@ impl<'stream> Tokenizer<'stream> {
                     let c = self.read_char();
                     match c {
                         Ch(ch) if ch.is_ascii_alphabetic() => {
-                            self.current_token = Some(Token::EndTag {
+                            self.start_token(Token::EndTag {
                                 name: "".into(),
                                 is_self_closing: false,
                             });
@@ -549,7 +730,7 @@ impl<'stream> Tokenizer<'stream> {
                     let c = self.read_char();
                     match c {
                         Ch(ch) if ch.is_ascii_alphabetic() => {
-                            self.current_token = Some(Token::EndTag {
+                            self.start_token(Token::EndTag {
                                 name: "".into(),
                                 is_self_closing: false,
                             });
@@ -751,7 +932,7 @@ impl<'stream> Tokenizer<'stream> {
 
                     match c {
                         Ch(ch) if ch.is_ascii_alphabetic() => {
-                            self.current_token = Some(Token::EndTag {
+                            self.start_token(Token::EndTag {
                                 name: "".into(),
                                 is_self_closing: false,
                             });
@@ -1214,7 +1395,7 @@ impl<'stream> Tokenizer<'stream> {
                 }
                 State::MarkupDeclarationOpen => {
                     if self.chars.look_ahead_slice(2) == "--" {
-                        self.current_token = Some(Token::Comment("".into()));
+                        self.start_token(Token::Comment("".into()));
 
                         // Skip the two -- signs
                         self.chars.skip(2);
@@ -1238,7 +1419,7 @@ impl<'stream> Tokenizer<'stream> {
                         }
 
                         self.parse_error(ParserError::CdataInHtmlContent);
-                        self.current_token = Some(Token::Comment("[CDATA[".into()));
+                        self.start_token(Token::Comment("[CDATA[".into()));
 
                         self.state = State::BogusComment;
                         continue;
@@ -1247,7 +1428,7 @@ impl<'stream> Tokenizer<'stream> {
                     self.chars.read_char();
                     self.parse_error(ParserError::IncorrectlyOpenedComment);
                     self.chars.unread();
-                    self.current_token = Some(Token::Comment("".into()));
+                    self.start_token(Token::Comment("".into()));
 
                     self.state = State::BogusComment;
                 }
@@ -1472,7 +1653,7 @@ impl<'stream> Tokenizer<'stream> {
                             // ignore
                         }
                         Ch(ch @ 'A'..='Z') => {
-                            self.current_token = Some(Token::DocType {
+                            self.start_token(Token::DocType {
                                 name: None,
                                 force_quirks: false,
                                 pub_identifier: None,
@@ -1484,7 +1665,7 @@ impl<'stream> Tokenizer<'stream> {
                         }
                         Ch(CHAR_NUL) => {
                             self.parse_error(ParserError::UnexpectedNullCharacter);
-                            self.current_token = Some(Token::DocType {
+                            self.start_token(Token::DocType {
                                 name: None,
                                 force_quirks: false,
                                 pub_identifier: None,
@@ -1519,7 +1700,7 @@ impl<'stream> Tokenizer<'stream> {
                             self.state = State::Data;
                         }
                         _ => {
-                            self.current_token = Some(Token::DocType {
+                            self.start_token(Token::DocType {
                                 name: None,
                                 force_quirks: false,
                                 pub_identifier: None,
@@ -2001,16 +2182,20 @@ impl<'stream> Tokenizer<'stream> {
     /// This macro reads a character from the input stream and optionally generates (tokenization)
     /// errors if the character is not valid.
     fn read_char(&mut self) -> Bytes {
+        let position = self.chars.position;
         let mut c = self.chars.read_char();
+        if c == Ch('<') {
+            self.last_less_than = position;
+        }
         match c {
             Bytes::Surrogate(..) => {
                 self.parse_error(ParserError::SurrogateInInputStream);
                 c = Ch(CHAR_REPLACEMENT);
             }
-            Ch(c) if self.is_control_char(c as u32) => {
+            Ch(c) if charref::is_control_char(c as u32) => {
                 self.parse_error(ParserError::ControlCharacterInInputStream);
             }
-            Ch(c) if self.is_noncharacter(c as u32) => {
+            Ch(c) if charref::is_noncharacter(c as u32) => {
                 self.parse_error(ParserError::NoncharacterInInputStream);
             }
             _ => {}
@@ -2089,6 +2274,12 @@ impl<'stream> Tokenizer<'stream> {
         }
     }
 
+    /// Starts a new tag, comment or doctype token, at the last `<`
+    fn start_token(&mut self, token: Token) {
+        self.current_token = Some(token);
+        self.token_start = self.last_less_than;
+    }
+
     /// Emits the given stored token. It does not have to be stored first.
     fn emit_token(&mut self, token: Token) {
         // Save the start token name if we are pushing it. This helps us in detecting matching tags.
@@ -2096,16 +2287,33 @@ impl<'stream> Tokenizer<'stream> {
             self.last_start_token = String::from(name);
         }
 
+        let end = self.chars.position;
+        let start = match token {
+            Token::Text(_) => self.last_token_end,
+            Token::Eof => end,
+            _ => self.token_start,
+        };
+
         // If there is any consumed data, emit this first as a text token
         if self.has_consumed_data() {
             let value = self.get_consumed_str().to_string();
 
             self.token_queue.push(Token::Text(value.to_string()));
+            let text_end = if matches!(token, Token::Eof) {
+                end
+            } else {
+                start
+            };
+            let span = self.span(self.last_token_end, text_end);
+            self.token_spans.push(span);
 
             self.clear_consume_buffer();
         }
 
         self.token_queue.push(token);
+        let span = self.span(start, end);
+        self.token_spans.push(span);
+        self.last_token_end = end;
     }
 
     // Consumes the given character
@@ -2220,6 +2428,20 @@ impl<'stream> Tokenizer<'stream> {
         if !self.current_attr_name.is_empty()
             && !self.current_attrs.contains_key(&self.current_attr_name)
         {
+            if self
+                .max_attributes
+                .is_some_and(|max| self.current_attrs.len() >= max)
+            {
+                self.exceeded_limits.push(Limit::Attributes);
+                self.current_attr_name = String::new();
+                self.current_attr_value = String::new();
+                return;
+            }
+            if let Some(max) = self.max_attribute_length {
+                if truncate_text(&mut self.current_attr_value, max) {
+                    self.exceeded_limits.push(Limit::AttributeLength);
+                }
+            }
             self.current_attrs.insert(
                 self.current_attr_name.clone(),
                 self.current_attr_value.clone(),
@@ -2241,6 +2463,8 @@ impl<'stream> Tokenizer<'stream> {
 
         match self.current_token.as_mut().expect("current token") {
             Token::EndTag { .. } => {
+                // Attributes on end tags are dropped, and must not end up on the next start tag
+                self.current_attrs = HashMap::new();
                 self.parse_error(ParserError::EndTagWithAttributes);
             }
             Token::StartTag { attributes, .. } => {
@@ -2253,3 +2477,136 @@ impl<'stream> Tokenizer<'stream> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::ParseError;
+
+    fn tokenize(html: &str) -> (Vec<Token>, Vec<ParseError>) {
+        let mut chars = CharIterator::new();
+        chars.read_from_str(html, None);
+        let error_logger = Rc::new(RefCell::new(ErrorLogger::new()));
+        let mut tokenizer = Tokenizer::new(&mut chars, None, error_logger.clone());
+
+        let mut tokens = Vec::new();
+        loop {
+            let token = tokenizer.next_token(ParserData::default()).unwrap();
+            if token.is_eof() {
+                break;
+            }
+            tokens.push(token);
+        }
+
+        let errors = error_logger.borrow().get_errors().clone();
+        (tokens, errors)
+    }
+
+    #[test]
+    fn names_are_lowercased() {
+        let (tokens, _) = tokenize("<DiV ID=Main DaTa-X=Y></DIV>");
+        assert_eq!(
+            tokens,
+            vec![
+                Token::StartTag {
+                    name: "div".into(),
+                    is_self_closing: false,
+                    attributes: HashMap::from([
+                        ("id".into(), "Main".into()),
+                        ("data-x".into(), "Y".into()),
+                    ]),
+                },
+                Token::EndTag {
+                    name: "div".into(),
+                    is_self_closing: false,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn duplicate_attributes_are_dropped() {
+        let (tokens, errors) = tokenize("<a href=first HREF=second title=t>");
+        assert_eq!(
+            tokens,
+            vec![Token::StartTag {
+                name: "a".into(),
+                is_self_closing: false,
+                attributes: HashMap::from([
+                    ("href".into(), "first".into()),
+                    ("title".into(), "t".into()),
+                ]),
+            }]
+        );
+        assert!(errors.iter().any(|e| e.message == "duplicate-attribute"));
+    }
+
+    #[test]
+    fn end_tag_attributes_are_dropped() {
+        let (tokens, errors) = tokenize("<p></p class=x /><br/>");
+        assert_eq!(
+            tokens[1],
+            Token::EndTag {
+                name: "p".into(),
+                is_self_closing: false,
+            }
+        );
+        assert_eq!(
+            tokens[2],
+            Token::StartTag {
+                name: "br".into(),
+                is_self_closing: true,
+                attributes: HashMap::new(),
+            }
+        );
+        assert!(errors
+            .iter()
+            .any(|e| e.message == "end-tag-with-attributes"));
+        assert!(errors
+            .iter()
+            .any(|e| e.message == "end-tag-with-trailing-solidus"));
+    }
+
+    #[test]
+    fn eof_in_states() {
+        let cases: [(&str, Vec<Token>, &str); 8] = [
+            // Unfinished tags are dropped
+            ("<a b='c", vec![], "eof-in-tag"),
+            ("</a", vec![], "eof-in-tag"),
+            // Unfinished comments and doctypes are emitted
+            ("<!--x", vec![Token::Comment("x".into())], "eof-in-comment"),
+            (
+                "<!DOCTYPE html PUBLIC \"x",
+                vec![Token::DocType {
+                    name: Some("html".into()),
+                    force_quirks: true,
+                    pub_identifier: Some("x".into()),
+                    sys_identifier: None,
+                }],
+                "eof-in-doctype",
+            ),
+            // A lone '<' is text
+            ("</", vec![Token::Text("</".into())], "eof-before-tag-name"),
+            ("<", vec![Token::Text("<".into())], "eof-before-tag-name"),
+            (
+                "&#x",
+                vec![Token::Text("&#x".into())],
+                "absence-of-digits-in-numeric-character-reference",
+            ),
+            (
+                "<![CDATA[x",
+                vec![Token::Comment("[CDATA[x".into())],
+                "cdata-in-html-content",
+            ),
+        ];
+
+        for (html, expected, error) in cases {
+            let (tokens, errors) = tokenize(html);
+            assert_eq!(tokens, expected, "tokens for {html:?}");
+            assert!(
+                errors.iter().any(|e| e.message == error),
+                "{error} for {html:?}"
+            );
+        }
+    }
+}
@@ -1,10 +1,9 @@
-extern crate lazy_static;
-
 use crate::bytes::Bytes::{self, *};
 use crate::html5::error_logger::ParserError;
-use crate::html5::tokenizer::replacement_tables::{TOKEN_NAMED_CHARS, TOKEN_REPLACEMENTS};
-use crate::html5::tokenizer::{Tokenizer, CHAR_REPLACEMENT};
-use lazy_static::lazy_static;
+use crate::html5::tokenizer::charref::{
+    longest_named_reference, named_reference, numeric_reference, LONGEST_NAME_LENGTH,
+};
+use crate::html5::tokenizer::Tokenizer;
 
 /// Different states for the character references
 pub enum CcrState {
@@ -75,7 +74,7 @@ impl Tokenizer<'_> {
                             return;
                         }
 
-                        let entity_chars = *TOKEN_NAMED_CHARS.get(entity.as_str()).unwrap();
+                        let entity_chars = named_reference(&entity).unwrap_or_default();
 
                         // Flush codepoints consumed as character reference
                         for c in entity_chars.chars() {
@@ -236,47 +235,14 @@ impl Tokenizer<'_> {
                     }
                 }
                 CcrState::NumericalCharacterReferenceEnd => {
-                    let overflow = char_ref_code.is_none();
-                    let mut char_ref_code = char_ref_code.unwrap_or(0);
-
-                    if char_ref_code == 0 && !overflow {
-                        self.chars.read_char();
-                        self.parse_error(ParserError::NullCharacterReference);
-                        self.chars.unread();
-                        char_ref_code = CHAR_REPLACEMENT as u32;
-                    }
-
-                    if char_ref_code > 0x10FFFF || overflow {
-                        self.chars.read_char();
-                        self.parse_error(ParserError::CharacterReferenceOutsideUnicodeRange);
-                        self.chars.unread();
-                        char_ref_code = CHAR_REPLACEMENT as u32;
-                    }
-
-                    if self.is_surrogate(char_ref_code) {
-                        self.chars.read_char();
-                        self.parse_error(ParserError::SurrogateCharacterReference);
-                        self.chars.unread();
-                        char_ref_code = CHAR_REPLACEMENT as u32;
-                    }
-                    if self.is_noncharacter(char_ref_code) {
-                        self.chars.read_char();
-                        self.parse_error(ParserError::NoncharacterCharacterReference);
-                        self.chars.unread();
-                        // char_ref_code = CHAR_REPLACEMENT as u32;
-                    }
-                    if self.is_control_char(char_ref_code) || char_ref_code == 0x0D {
+                    let (c, error) = numeric_reference(char_ref_code);
+                    if let Some(error) = error {
                         self.chars.read_char();
-                        self.parse_error(ParserError::ControlCharacterReference);
+                        self.parse_error(error);
                         self.chars.unread();
-
-                        if TOKEN_REPLACEMENTS.contains_key(&char_ref_code) {
-                            char_ref_code = *TOKEN_REPLACEMENTS.get(&char_ref_code).unwrap() as u32;
-                        }
                     }
 
                     self.temporary_buffer.clear();
-                    let c = char::from_u32(char_ref_code).unwrap_or(CHAR_REPLACEMENT);
                     self.temporary_buffer.push(c);
                     self.consume_temp_buffer(as_attribute);
 
@@ -295,55 +261,14 @@ impl Tokenizer<'_> {
         self.temporary_buffer.clear();
     }
 
-    pub(crate) fn is_surrogate(&self, num: u32) -> bool {
-        (0xD800..=0xDFFF).contains(&num)
-    }
-
-    pub(crate) fn is_noncharacter(&self, num: u32) -> bool {
-        (0xFDD0..=0xFDEF).contains(&num)
-            || [
-                0xFFFE, 0xFFFF, 0x1FFFE, 0x1FFFF, 0x2FFFE, 0x2FFFF, 0x3FFFE, 0x3FFFF, 0x4FFFE,
-                0x4FFFF, 0x5FFFE, 0x5FFFF, 0x6FFFE, 0x6FFFF, 0x7FFFE, 0x7FFFF, 0x8FFFE, 0x8FFFF,
-                0x9FFFE, 0x9FFFF, 0xAFFFE, 0xAFFFF, 0xBFFFE, 0xBFFFF, 0xCFFFE, 0xCFFFF, 0xDFFFE,
-                0xDFFFF, 0xEFFFE, 0xEFFFF, 0xFFFFE, 0xFFFFF, 0x10FFFE, 0x10FFFF,
-            ]
-            .contains(&num)
-    }
-
-    pub(crate) fn is_control_char(&self, num: u32) -> bool {
-        // White spaces are ok
-        if [0x0009, 0x000A, 0x000C, 0x000D, 0x0020].contains(&num) {
-            return false;
-        }
-
-        (0x0001..=0x001F).contains(&num) || (0x007F..=0x009F).contains(&num)
-    }
-
     /// Finds the longest entity from the current position in the stream. Returns the entity
     /// replacement OR None when no entity has been found.
     fn find_entity(&mut self) -> Option<String> {
-        let s = self.chars.look_ahead_slice(*LONGEST_ENTITY_LENGTH);
-        let chars: Vec<char> = s.chars().collect();
-
-        for i in (0..=s.len()).rev() {
-            if let Some(slice) = chars.get(0..i) {
-                let entity: String = slice.iter().collect();
-                if TOKEN_NAMED_CHARS.contains_key(entity.as_str()) {
-                    return Some(entity);
-                }
-            }
-        }
-        None
+        let s = self.chars.look_ahead_slice(LONGEST_NAME_LENGTH);
+        longest_named_reference(&s).map(|(name, _)| name.to_string())
     }
 }
 
-lazy_static! {
-    // Returns the longest entity in the TOKEN_NAMED_CHARS map (this could be a const actually)
-    static ref LONGEST_ENTITY_LENGTH: usize = {
-        TOKEN_NAMED_CHARS.keys().map(|key| key.len()).max().unwrap_or(0)
-    };
-}
-
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -0,0 +1,209 @@
+//! Character references
+//!
+//! Named (`&amp;`) and numeric (`&#38;` or `&#x26;`) character references, as the tokenizer
+//! decodes them. The functions can be used on their own as well, for instance to decode text or
+//! attribute values that were taken from markup.
+//!
+//! Some named references (the legacy ones, like `&copy`) are also recognized without the
+//! semicolon. In attribute values, such a reference is kept as it is when it is followed by `=`
+//! or an alphanumeric character, so URLs like `?a=1&copy=2` survive.
+//!
+//! See: https://html.spec.whatwg.org/multipage/parsing.html#character-reference-state
+use crate::html5::error_logger::ParserError;
+use crate::html5::tokenizer::replacement_tables::{TOKEN_NAMED_CHARS, TOKEN_REPLACEMENTS};
+use crate::html5::tokenizer::CHAR_REPLACEMENT;
+
+/// Length of the longest name in the table of named references (`&CounterClockwiseContourIntegral;`)
+pub const LONGEST_NAME_LENGTH: usize = 32;
+
+/// Returns the characters of the named reference (without the `&`, and with the `;` if it has
+/// one), or None when it is unknown
+pub fn named_reference(name: &str) -> Option<&'static str> {
+    TOKEN_NAMED_CHARS.get(name).copied()
+}
+
+/// Finds the longest named reference the input starts with (after the `&`). Returns the name
+/// and its characters, or None when the input does not start with a named reference.
+pub fn longest_named_reference(input: &str) -> Option<(&str, &'static str)> {
+    let end = input
+        .char_indices()
+        .nth(LONGEST_NAME_LENGTH)
+        .map_or(input.len(), |(index, _)| index);
+    let candidates = input[..end]
+        .char_indices()
+        .map(|(index, c)| index + c.len_utf8())
+        .rev();
+    for length in candidates {
+        let name = &input[..length];
+        if let Some(characters) = named_reference(name) {
+            return Some((name, characters));
+        }
+    }
+    None
+}
+
+/// Returns the character of a numeric reference with the code (None when the code overflowed),
+/// and the parse error it gives, if any. Codes that are not allowed are replaced: 0, surrogates
+/// and codes outside of Unicode by U+FFFD, and C1 controls by their windows-1252 characters.
+pub fn numeric_reference(code: Option<u32>) -> (char, Option<ParserError>) {
+    let Some(code) = code else {
+        return (
+            CHAR_REPLACEMENT,
+            Some(ParserError::CharacterReferenceOutsideUnicodeRange),
+        );
+    };
+    if code == 0 {
+        return (CHAR_REPLACEMENT, Some(ParserError::NullCharacterReference));
+    }
+    if code > 0x10FFFF {
+        return (
+            CHAR_REPLACEMENT,
+            Some(ParserError::CharacterReferenceOutsideUnicodeRange),
+        );
+    }
+    if is_surrogate(code) {
+        return (
+            CHAR_REPLACEMENT,
+            Some(ParserError::SurrogateCharacterReference),
+        );
+    }
+
+    let c = char::from_u32(code).unwrap_or(CHAR_REPLACEMENT);
+    if is_noncharacter(code) {
+        return (c, Some(ParserError::NoncharacterCharacterReference));
+    }
+    if is_control_char(code) || code == 0x0D {
+        let c = TOKEN_REPLACEMENTS.get(&code).copied().unwrap_or(c);
+        return (c, Some(ParserError::ControlCharacterReference));
+    }
+    (c, None)
+}
+
+/// Returns true when the code is a surrogate (U+D800 to U+DFFF)
+pub fn is_surrogate(code: u32) -> bool {
+    (0xD800..=0xDFFF).contains(&code)
+}
+
+/// Returns true when the code is a noncharacter (like U+FFFE)
+pub fn is_noncharacter(code: u32) -> bool {
+    (0xFDD0..=0xFDEF).contains(&code) || ((code & 0xFFFE) == 0xFFFE && code <= 0x10FFFF)
+}
+
+/// Returns true when the code is a control character other than ASCII whitespace
+pub fn is_control_char(code: u32) -> bool {
+    // White spaces are ok
+    if [0x0009, 0x000A, 0x000C, 0x000D, 0x0020].contains(&code) {
+        return false;
+    }
+
+    (0x0001..=0x001F).contains(&code) || (0x007F..=0x009F).contains(&code)
+}
+
+/// Decodes the character references in the text, like the tokenizer does in text
+/// (`in_attribute` unset) or attribute values (`in_attribute` set). Invalid references are kept
+/// as they are.
+pub fn decode(input: &str, in_attribute: bool) -> String {
+    let mut output = String::with_capacity(input.len());
+    let mut rest = input;
+    while let Some(index) = rest.find('&') {
+        output.push_str(&rest[..index]);
+        rest = &rest[index + 1..];
+
+        if let Some(numeric) = rest.strip_prefix('#') {
+            let (radix, digits) = match numeric.strip_prefix(['x', 'X']) {
+                Some(digits) => (16, digits),
+                None => (10, numeric),
+            };
+            let length = digits
+                .find(|c: char| !c.is_digit(radix))
+                .unwrap_or(digits.len());
+            if length == 0 {
+                output.push('&');
+                continue;
+            }
+            let code = digits[..length].chars().try_fold(0u32, |code, c| {
+                code.checked_mul(radix)?
+                    .checked_add(c.to_digit(radix).unwrap_or(0))
+            });
+            output.push(numeric_reference(code).0);
+            let after = &digits[length..];
+            rest = after.strip_prefix(';').unwrap_or(after);
+            continue;
+        }
+
+        match longest_named_reference(rest) {
+            Some((name, characters)) => {
+                let next = rest[name.len()..].chars().next();
+                let legacy = !name.ends_with(';')
+                    && next.is_some_and(|c| c == '=' || c.is_ascii_alphanumeric());
+                if in_attribute && legacy {
+                    output.push('&');
+                    output.push_str(name);
+                } else {
+                    output.push_str(characters);
+                }
+                rest = &rest[name.len()..];
+            }
+            None => output.push('&'),
+        }
+    }
+    output.push_str(rest);
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn longest_name() {
+        let longest = TOKEN_NAMED_CHARS.keys().map(|key| key.len()).max();
+        assert_eq!(longest, Some(LONGEST_NAME_LENGTH));
+    }
+
+    #[test]
+    fn references() {
+        assert_eq!(named_reference("amp;"), Some("&"));
+        assert_eq!(named_reference("amp"), Some("&"));
+        assert_eq!(named_reference("ampx;"), None);
+        assert_eq!(
+            longest_named_reference("notin;x"),
+            Some(("notin;", "\u{2209}"))
+        );
+        assert_eq!(longest_named_reference("notit;"), Some(("not", "\u{00AC}")));
+        assert_eq!(longest_named_reference("nope"), None);
+
+        let error = |code| numeric_reference(code).1.map(|error| error.as_str());
+        assert_eq!(numeric_reference(Some(0x41)).0, 'A');
+        assert_eq!(error(Some(0x41)), None);
+        assert_eq!(numeric_reference(Some(0)).0, CHAR_REPLACEMENT);
+        assert_eq!(error(None), error(Some(0x110000)));
+        assert_eq!(numeric_reference(Some(0xD800)).0, CHAR_REPLACEMENT);
+        assert_eq!(numeric_reference(Some(0x80)).0, '\u{20AC}');
+        assert_eq!(numeric_reference(Some(0x81)).0, '\u{81}');
+        assert_eq!(numeric_reference(Some(0x1FFFF)).0, '\u{1FFFF}');
+        assert!(error(Some(0x1FFFF)).is_some());
+        assert!(!is_noncharacter(0xFFFD));
+        assert!(is_noncharacter(0x10FFFE));
+    }
+
+    #[test]
+    fn decode_text() {
+        assert_eq!(decode("a &amp; b &lt;&gt", false), "a & b <>");
+        assert_eq!(decode("&#65;&#x42;&#X43&#;&#x;", false), "ABC&#;&#x;");
+        assert_eq!(decode("&#99999999999;&#0;", false), "\u{FFFD}\u{FFFD}");
+        assert_eq!(
+            decode("&notit; &unknown; &", false),
+            "\u{00AC}it; &unknown; &"
+        );
+
+        // Legacy references without a semicolon are kept in attribute values when they are
+        // followed by = or an alphanumeric character
+        assert_eq!(decode("?a=1&copy=2&not;", false), "?a=1\u{00A9}=2\u{00AC}");
+        assert_eq!(decode("?a=1&copy=2&not;", true), "?a=1&copy=2\u{00AC}");
+        assert_eq!(
+            decode("&copya &copy &copy;x", true),
+            "&copya \u{00A9} \u{00A9}x"
+        );
+    }
+}
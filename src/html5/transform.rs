@@ -0,0 +1,560 @@
+//! CSS transforms
+//!
+//! The `transform` property maps the border box of an element (and its contents) to another
+//! place on the screen, without changing the layout. The transform functions are combined into a
+//! 2D matrix, which is applied around the `transform-origin` (the center of the border box by
+//! default). The transforms of the ancestors apply as well, so the matrix that maps an element to
+//! the viewport is the product of the matrices of its ancestors and its own.
+//!
+//! `bounding_client_rect()` returns the bounding box of the transformed border box, and
+//! `element_from_point()` maps the point back through the inverse matrices to find the element
+//! under it. Transformed elements create a stacking context (see `stacking`), and are the
+//! containing block of their positioned descendants (see `containing_block()`).
+//!
+//! Lengths are in CSS pixels (`px`, or the absolute units). 3D transforms are flattened to 2D.
+//!
+//! See: https://drafts.csswg.org/css-transforms-1/
+use crate::html5::element_style::cascaded_value;
+use crate::html5::geometry::Rect;
+use crate::html5::node::NodeId;
+use crate::html5::parser::document::Document;
+use crate::html5::stacking::DisplayKind;
+use crate::types::{Error, Result};
+
+/// A 2D affine transformation. A point (x, y) is mapped to (a * x + c * y + e, b * x + d * y + f),
+/// like the `matrix(a, b, c, d, e, f)` transform function.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Matrix {
+    pub a: f32,
+    pub b: f32,
+    pub c: f32,
+    pub d: f32,
+    pub e: f32,
+    pub f: f32,
+}
+
+impl Default for Matrix {
+    fn default() -> Self {
+        Self::IDENTITY
+    }
+}
+
+impl Matrix {
+    pub const IDENTITY: Matrix = Matrix::new(1.0, 0.0, 0.0, 1.0, 0.0, 0.0);
+
+    pub const fn new(a: f32, b: f32, c: f32, d: f32, e: f32, f: f32) -> Self {
+        Self { a, b, c, d, e, f }
+    }
+
+    pub fn translate(x: f32, y: f32) -> Self {
+        Self::new(1.0, 0.0, 0.0, 1.0, x, y)
+    }
+
+    pub fn scale(x: f32, y: f32) -> Self {
+        Self::new(x, 0.0, 0.0, y, 0.0, 0.0)
+    }
+
+    /// Rotation by the angle in radians, clockwise on the screen
+    pub fn rotate(angle: f32) -> Self {
+        let (sin, cos) = angle.sin_cos();
+        Self::new(cos, sin, -sin, cos, 0.0, 0.0)
+    }
+
+    /// Skew by the angles in radians along the x and y axis
+    pub fn skew(x: f32, y: f32) -> Self {
+        Self::new(1.0, y.tan(), x.tan(), 1.0, 0.0, 0.0)
+    }
+
+    /// Returns the matrix that applies the other matrix first, and then this one
+    pub fn multiply(&self, other: &Matrix) -> Matrix {
+        Matrix::new(
+            self.a * other.a + self.c * other.b,
+            self.b * other.a + self.d * other.b,
+            self.a * other.c + self.c * other.d,
+            self.b * other.c + self.d * other.d,
+            self.a * other.e + self.c * other.f + self.e,
+            self.b * other.e + self.d * other.f + self.f,
+        )
+    }
+
+    /// Returns the matrix that undoes this one, or None when it cannot be undone (like
+    /// `scale(0)`)
+    pub fn inverse(&self) -> Option<Matrix> {
+        let determinant = self.a * self.d - self.b * self.c;
+        if determinant.abs() < f32::EPSILON {
+            return None;
+        }
+        Some(Matrix::new(
+            self.d / determinant,
+            -self.b / determinant,
+            -self.c / determinant,
+            self.a / determinant,
+            (self.c * self.f - self.d * self.e) / determinant,
+            (self.b * self.e - self.a * self.f) / determinant,
+        ))
+    }
+
+    pub fn is_identity(&self) -> bool {
+        *self == Self::IDENTITY
+    }
+
+    pub fn map_point(&self, x: f32, y: f32) -> (f32, f32) {
+        (
+            self.a * x + self.c * y + self.e,
+            self.b * x + self.d * y + self.f,
+        )
+    }
+
+    /// Returns the bounding box of the mapped rectangle
+    pub fn map_rect(&self, rect: &Rect) -> Rect {
+        let corners = [
+            self.map_point(rect.x, rect.y),
+            self.map_point(rect.right(), rect.y),
+            self.map_point(rect.x, rect.bottom()),
+            self.map_point(rect.right(), rect.bottom()),
+        ];
+        let (mut left, mut top) = corners[0];
+        let (mut right, mut bottom) = corners[0];
+        for (x, y) in corners {
+            left = left.min(x);
+            top = top.min(y);
+            right = right.max(x);
+            bottom = bottom.max(y);
+        }
+        Rect::new(left, top, right - left, bottom - top)
+    }
+}
+
+/// A length in CSS pixels, or a percentage of a reference length
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LengthPercentage {
+    Length(f32),
+    Percentage(f32),
+}
+
+impl Default for LengthPercentage {
+    fn default() -> Self {
+        LengthPercentage::Length(0.0)
+    }
+}
+
+impl LengthPercentage {
+    /// Returns the length in pixels, with percentages relative to the reference length
+    pub fn resolve(&self, reference: f32) -> f32 {
+        match self {
+            LengthPercentage::Length(length) => *length,
+            LengthPercentage::Percentage(percentage) => reference * percentage / 100.0,
+        }
+    }
+
+    pub(crate) fn parse(value: &str) -> Result<Self> {
+        if let Some(percentage) = value.strip_suffix('%') {
+            return parse_number(percentage).map(LengthPercentage::Percentage);
+        }
+        parse_length(value).map(LengthPercentage::Length)
+    }
+}
+
+/// A transform function of the `transform` property
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TransformFunction {
+    Matrix(Matrix),
+    /// Translation, with percentages of the width and height of the border box
+    Translate(LengthPercentage, LengthPercentage),
+    Scale(f32, f32),
+    /// Rotation in radians
+    Rotate(f32),
+    /// Skew angles in radians
+    Skew(f32, f32),
+}
+
+impl TransformFunction {
+    /// Returns the matrix of the function for a border box of the given size
+    pub fn to_matrix(&self, width: f32, height: f32) -> Matrix {
+        match *self {
+            TransformFunction::Matrix(matrix) => matrix,
+            TransformFunction::Translate(x, y) => {
+                Matrix::translate(x.resolve(width), y.resolve(height))
+            }
+            TransformFunction::Scale(x, y) => Matrix::scale(x, y),
+            TransformFunction::Rotate(angle) => Matrix::rotate(angle),
+            TransformFunction::Skew(x, y) => Matrix::skew(x, y),
+        }
+    }
+}
+
+/// Parses a `transform` value into its functions. `none` has no functions.
+pub fn parse_transform(value: &str) -> Result<Vec<TransformFunction>> {
+    let value = value.trim();
+    if value.eq_ignore_ascii_case("none") {
+        return Ok(Vec::new());
+    }
+
+    let mut functions = Vec::new();
+    let mut rest = value;
+    while !rest.is_empty() {
+        let (Some(open), Some(close)) = (rest.find('('), rest.find(')')) else {
+            return Err(Error::Parse(format!("invalid transform '{value}'")));
+        };
+        if close < open {
+            return Err(Error::Parse(format!("invalid transform '{value}'")));
+        }
+        let name = rest[..open].trim().to_ascii_lowercase();
+        let arguments: Vec<&str> = rest[open + 1..close].split(',').map(str::trim).collect();
+        functions.push(parse_function(&name, &arguments)?);
+        rest = rest[close + 1..].trim_start();
+    }
+
+    if functions.is_empty() {
+        return Err(Error::Parse("empty transform".to_string()));
+    }
+    Ok(functions)
+}
+
+fn parse_function(name: &str, arguments: &[&str]) -> Result<TransformFunction> {
+    let zero = LengthPercentage::Length(0.0);
+    let function = match (name, arguments) {
+        ("matrix", [a, b, c, d, e, f]) => TransformFunction::Matrix(Matrix::new(
+            parse_number(a)?,
+            parse_number(b)?,
+            parse_number(c)?,
+            parse_number(d)?,
+            parse_number(e)?,
+            parse_number(f)?,
+        )),
+        ("translate", [x]) => TransformFunction::Translate(LengthPercentage::parse(x)?, zero),
+        ("translate", [x, y]) | ("translate3d", [x, y, _]) => {
+            TransformFunction::Translate(LengthPercentage::parse(x)?, LengthPercentage::parse(y)?)
+        }
+        ("translatex", [x]) => TransformFunction::Translate(LengthPercentage::parse(x)?, zero),
+        ("translatey", [y]) => TransformFunction::Translate(zero, LengthPercentage::parse(y)?),
+        ("scale", [scale]) => {
+            let scale = parse_number(scale)?;
+            TransformFunction::Scale(scale, scale)
+        }
+        ("scale", [x, y]) | ("scale3d", [x, y, _]) => {
+            TransformFunction::Scale(parse_number(x)?, parse_number(y)?)
+        }
+        ("scalex", [x]) => TransformFunction::Scale(parse_number(x)?, 1.0),
+        ("scaley", [y]) => TransformFunction::Scale(1.0, parse_number(y)?),
+        ("rotate" | "rotatez", [angle]) => TransformFunction::Rotate(parse_angle(angle)?),
+        ("skew", [x]) | ("skewx", [x]) => TransformFunction::Skew(parse_angle(x)?, 0.0),
+        ("skew", [x, y]) => TransformFunction::Skew(parse_angle(x)?, parse_angle(y)?),
+        ("skewy", [y]) => TransformFunction::Skew(0.0, parse_angle(y)?),
+        _ => {
+            return Err(Error::Parse(format!(
+                "unsupported transform function '{name}()'"
+            )))
+        }
+    };
+    Ok(function)
+}
+
+/// Parses a `transform-origin` value into the horizontal and vertical offset from the top left
+/// corner of the border box. The z offset is ignored.
+pub fn parse_transform_origin(value: &str) -> Result<(LengthPercentage, LengthPercentage)> {
+    let center = LengthPercentage::Percentage(50.0);
+    let keyword = |word: &str| match word.to_ascii_lowercase().as_str() {
+        "left" | "top" => Some(LengthPercentage::Percentage(0.0)),
+        "center" => Some(center),
+        "right" | "bottom" => Some(LengthPercentage::Percentage(100.0)),
+        _ => None,
+    };
+    let is_vertical = |word: &str| matches!(word.to_ascii_lowercase().as_str(), "top" | "bottom");
+    let is_horizontal = |word: &str| matches!(word.to_ascii_lowercase().as_str(), "left" | "right");
+    let component = |word: &str| match keyword(word) {
+        Some(offset) => Ok(offset),
+        None => LengthPercentage::parse(word),
+    };
+
+    let words: Vec<&str> = value.split_ascii_whitespace().collect();
+    match words.as_slice() {
+        [word] if is_vertical(word) => Ok((center, component(word)?)),
+        [word] => Ok((component(word)?, center)),
+        [first, second] | [first, second, _] => {
+            if is_vertical(first) || is_horizontal(second) {
+                if keyword(first).is_none() || keyword(second).is_none() {
+                    return Err(Error::Parse(format!("invalid transform-origin '{value}'")));
+                }
+                Ok((component(second)?, component(first)?))
+            } else {
+                Ok((component(first)?, component(second)?))
+            }
+        }
+        _ => Err(Error::Parse(format!("invalid transform-origin '{value}'"))),
+    }
+}
+
+pub(crate) fn parse_number(value: &str) -> Result<f32> {
+    value
+        .trim()
+        .parse::<f32>()
+        .ok()
+        .filter(|number| number.is_finite())
+        .ok_or_else(|| Error::Parse(format!("invalid number '{value}'")))
+}
+
+/// Parses a length in one of the absolute units, in CSS pixels. A unitless 0 is allowed.
+pub(crate) fn parse_length(value: &str) -> Result<f32> {
+    const UNITS: [(&str, f32); 7] = [
+        ("px", 1.0),
+        ("pt", 96.0 / 72.0),
+        ("pc", 16.0),
+        ("in", 96.0),
+        ("cm", 96.0 / 2.54),
+        ("mm", 96.0 / 25.4),
+        ("q", 96.0 / 101.6),
+    ];
+    let value = value.trim().to_ascii_lowercase();
+    for (unit, pixels) in UNITS {
+        if let Some(number) = value.strip_suffix(unit) {
+            return parse_number(number).map(|number| number * pixels);
+        }
+    }
+    match parse_number(&value) {
+        Ok(0.0) => Ok(0.0),
+        _ => Err(Error::Parse(format!("invalid length '{value}'"))),
+    }
+}
+
+/// Parses an angle in radians. A unitless 0 is allowed.
+pub(crate) fn parse_angle(value: &str) -> Result<f32> {
+    const UNITS: [(&str, f32); 4] = [
+        ("deg", std::f32::consts::PI / 180.0),
+        ("grad", std::f32::consts::PI / 200.0),
+        ("rad", 1.0),
+        ("turn", std::f32::consts::TAU),
+    ];
+    let value = value.trim().to_ascii_lowercase();
+    for (unit, radians) in UNITS {
+        if let Some(number) = value.strip_suffix(unit) {
+            return parse_number(number).map(|number| number * radians);
+        }
+    }
+    match parse_number(&value) {
+        Ok(0.0) => Ok(0.0),
+        _ => Err(Error::Parse(format!("invalid angle '{value}'"))),
+    }
+}
+
+impl Document {
+    /// Returns the transform of the element in viewport coordinates (around its transform
+    /// origin), or None when it has no transform or does not generate a box
+    pub fn transform_matrix(&self, node_id: NodeId) -> Option<Matrix> {
+        let geometry = self.geometry.0.as_ref()?;
+        if let Some(matrix) = geometry.transform(node_id) {
+            return Some(matrix);
+        }
+
+        let declarations = self.declared_style(node_id);
+        let functions = parse_transform(cascaded_value(&declarations, "transform")?).ok()?;
+        if functions.is_empty() {
+            return None;
+        }
+        let border_box = geometry.border_box(node_id)?;
+        let (origin_x, origin_y) = cascaded_value(&declarations, "transform-origin")
+            .and_then(|value| parse_transform_origin(value).ok())
+            .unwrap_or((
+                LengthPercentage::Percentage(50.0),
+                LengthPercentage::Percentage(50.0),
+            ));
+        let origin_x = border_box.x + origin_x.resolve(border_box.width);
+        let origin_y = border_box.y + origin_y.resolve(border_box.height);
+
+        let matrix =
+            functions
+                .iter()
+                .fold(Matrix::translate(origin_x, origin_y), |matrix, function| {
+                    matrix.multiply(&function.to_matrix(border_box.width, border_box.height))
+                });
+        Some(matrix.multiply(&Matrix::translate(-origin_x, -origin_y)))
+    }
+
+    /// Returns the matrix that maps the (untransformed) border box of the element to the
+    /// viewport: the transforms of its ancestors and its own, and the scroll offsets of the
+    /// scroll containers it is in (see `scroll`)
+    pub fn accumulated_transform(&self, node_id: NodeId) -> Matrix {
+        let scrolling = self.scrolling_ancestors(node_id);
+        let mut matrix = Matrix::IDENTITY;
+        let mut current = Some(node_id);
+        while let Some(node) = current.and_then(|node_id| self.get_node_by_id(node_id)) {
+            if scrolling.contains(&node.id) {
+                let (x, y) = self.scroll_offset(node.id);
+                matrix = Matrix::translate(-x, -y).multiply(&matrix);
+            }
+            if let Some(transform) = self.transform_matrix(node.id) {
+                matrix = transform.multiply(&matrix);
+            }
+            current = node.parent;
+        }
+        matrix
+    }
+
+    /// Returns the topmost element at the position in the viewport (like `elementFromPoint()`),
+    /// or None when there is no element or no layout. Elements are tested in reverse paint
+    /// order, through the inverse of their transforms. Parts of elements that the overflow of
+    /// their ancestors clips are not hit.
+    pub fn element_from_point(&self, x: f32, y: f32) -> Option<NodeId> {
+        let geometry = self.geometry.0.as_ref()?;
+        let tree = self.stacking_context_tree()?;
+        tree.paint_order().into_iter().rev().find(|&node_id| {
+            let Some(border_box) = geometry.border_box(node_id) else {
+                return false;
+            };
+            let Some(inverse) = self.accumulated_transform(node_id).inverse() else {
+                return false;
+            };
+            let (local_x, local_y) = inverse.map_point(x, y);
+            local_x >= border_box.x
+                && local_x < border_box.right()
+                && local_y >= border_box.y
+                && local_y < border_box.bottom()
+                && !self.is_clipped_at(node_id, x, y)
+        })
+    }
+
+    /// Returns true when the element is displayed and has a transform
+    pub(crate) fn is_transformed(&self, node_id: NodeId) -> bool {
+        self.paint_style(node_id)
+            .is_some_and(|style| style.transformed && style.display != DisplayKind::None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::html5::geometry::LayoutGeometry;
+    use crate::html5::node::NodeData;
+    use crate::testing::parse;
+    use std::collections::HashMap;
+    use std::rc::Rc;
+
+    fn assert_close(actual: (f32, f32), expected: (f32, f32)) {
+        assert!(
+            (actual.0 - expected.0).abs() < 1e-3 && (actual.1 - expected.1).abs() < 1e-3,
+            "{actual:?} != {expected:?}"
+        );
+    }
+
+    #[test]
+    fn matrices() {
+        let matrix = Matrix::translate(10.0, 0.0).multiply(&Matrix::scale(2.0, 3.0));
+        assert_eq!(matrix.map_point(1.0, 1.0), (12.0, 3.0));
+        let inverse = matrix.inverse().unwrap();
+        assert_close(inverse.map_point(12.0, 3.0), (1.0, 1.0));
+        assert!(inverse.multiply(&matrix).is_identity());
+        assert!(Matrix::scale(0.0, 1.0).inverse().is_none());
+
+        let rotated = Matrix::rotate(std::f32::consts::FRAC_PI_2);
+        assert_close(rotated.map_point(1.0, 0.0), (0.0, 1.0));
+        let rect = rotated.map_rect(&Rect::new(0.0, 0.0, 20.0, 10.0));
+        assert_close((rect.x, rect.y), (-10.0, 0.0));
+        assert_close((rect.width, rect.height), (10.0, 20.0));
+    }
+
+    #[test]
+    fn parse_values() {
+        assert_eq!(parse_transform("none").unwrap(), []);
+        assert_eq!(
+            parse_transform("translate(10px, 50%) SCALE(2) rotate(0.5turn) skewX(0)").unwrap(),
+            [
+                TransformFunction::Translate(
+                    LengthPercentage::Length(10.0),
+                    LengthPercentage::Percentage(50.0)
+                ),
+                TransformFunction::Scale(2.0, 2.0),
+                TransformFunction::Rotate(std::f32::consts::PI),
+                TransformFunction::Skew(0.0, 0.0),
+            ]
+        );
+        assert_eq!(
+            parse_transform("matrix(1, 0, 0, 1, 5, 6) translateY(1in)").unwrap(),
+            [
+                TransformFunction::Matrix(Matrix::translate(5.0, 6.0)),
+                TransformFunction::Translate(
+                    LengthPercentage::Length(0.0),
+                    LengthPercentage::Length(96.0)
+                ),
+            ]
+        );
+        assert!(parse_transform("translate(10)").is_err());
+        assert!(parse_transform("rotate(45)").is_err());
+        assert!(parse_transform("perspective(10px)").is_err());
+        assert!(parse_transform("scale(2").is_err());
+        assert!(parse_transform("").is_err());
+
+        let percentage = LengthPercentage::Percentage;
+        assert_eq!(
+            parse_transform_origin("top").unwrap(),
+            (percentage(50.0), percentage(0.0))
+        );
+        assert_eq!(
+            parse_transform_origin("bottom left").unwrap(),
+            (percentage(0.0), percentage(100.0))
+        );
+        assert_eq!(
+            parse_transform_origin("10px 20% 5px").unwrap(),
+            (LengthPercentage::Length(10.0), percentage(20.0))
+        );
+        assert!(parse_transform_origin("top 10px").is_err());
+    }
+
+    struct Layout(HashMap<NodeId, Rect>);
+
+    impl LayoutGeometry for Layout {
+        fn border_box(&self, node_id: NodeId) -> Option<Rect> {
+            self.0.get(&node_id).copied()
+        }
+
+        fn viewport(&self) -> Rect {
+            Rect::new(0.0, 0.0, 800.0, 600.0)
+        }
+    }
+
+    #[test]
+    fn transformed_geometry() {
+        let mut document = parse(
+            r#"<div id=outer style="transform: translateX(100px)">
+                 <div id=inner style="transform: rotate(90deg); transform-origin: left top"></div>
+               </div>
+               <div id=plain></div>"#,
+        );
+
+        let named = |id: &str| document.get().get_node_by_named_id(id).unwrap().id;
+        let (outer, inner, plain) = (named("outer"), named("inner"), named("plain"));
+        let mut boxes = HashMap::new();
+        for node in document.get().descendants(NodeId::root()) {
+            if let NodeData::Element(_) = node.data {
+                boxes.insert(node.id, Rect::new(0.0, 0.0, 800.0, 600.0));
+            }
+        }
+        boxes.insert(outer, Rect::new(0.0, 0.0, 200.0, 100.0));
+        boxes.insert(inner, Rect::new(0.0, 0.0, 50.0, 20.0));
+        boxes.insert(plain, Rect::new(0.0, 100.0, 200.0, 100.0));
+        document
+            .get_mut()
+            .set_layout_geometry(Rc::new(Layout(boxes)));
+
+        let doc = document.get();
+        assert!(doc.transform_matrix(plain).is_none());
+        let rect = doc.bounding_client_rect(inner).unwrap();
+        assert_close((rect.x, rect.y), (80.0, 0.0));
+        assert_close((rect.width, rect.height), (20.0, 50.0));
+        assert_eq!(
+            doc.bounding_client_rect(outer),
+            Some(Rect::new(100.0, 0.0, 200.0, 100.0))
+        );
+
+        // Hit testing maps the point back through the transforms
+        assert_eq!(doc.element_from_point(90.0, 40.0), Some(inner));
+        assert_eq!(doc.element_from_point(150.0, 80.0), Some(outer));
+        let body = doc.elements_by_tag_name("body")[0];
+        assert_eq!(doc.element_from_point(50.0, 80.0), Some(body));
+        assert_eq!(doc.element_from_point(10.0, 150.0), Some(plain));
+
+        // Transformed elements are the containing block of their positioned descendants
+        assert!(doc.is_transformed(outer));
+        assert!(!doc.is_transformed(plain));
+        assert_eq!(doc.offset_parent(inner), Some(outer));
+    }
+}
@@ -0,0 +1,169 @@
+//! Tree traversal
+//!
+//! Iterators over the nodes around a node: its children, descendants, ancestors and following
+//! siblings. They borrow the document and return references to the nodes, so walking the tree
+//! needs neither recursion nor clones of the nodes.
+use crate::html5::node::{Node, NodeId};
+use crate::html5::parser::document::Document;
+
+/// Iterator over the children of a node
+pub struct Children<'a> {
+    document: &'a Document,
+    children: std::slice::Iter<'a, NodeId>,
+}
+
+impl<'a> Iterator for Children<'a> {
+    type Item = &'a Node;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.children
+            .find_map(|&child| self.document.get_node_by_id(child))
+    }
+}
+
+/// Iterator over the descendants of a node in tree order (depth first, parents before their
+/// children)
+pub struct Descendants<'a> {
+    document: &'a Document,
+    /// Nodes that still need to be visited, in reverse order
+    stack: Vec<NodeId>,
+}
+
+impl<'a> Iterator for Descendants<'a> {
+    type Item = &'a Node;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while let Some(node_id) = self.stack.pop() {
+            let Some(node) = self.document.get_node_by_id(node_id) else {
+                continue;
+            };
+            self.stack.extend(node.children.iter().rev());
+            return Some(node);
+        }
+        None
+    }
+}
+
+/// Iterator over the ancestors of a node, starting with its parent and ending with the root
+pub struct Ancestors<'a> {
+    document: &'a Document,
+    next: Option<NodeId>,
+}
+
+impl<'a> Iterator for Ancestors<'a> {
+    type Item = &'a Node;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let node = self.document.get_node_by_id(self.next?)?;
+        self.next = node.parent;
+        Some(node)
+    }
+}
+
+impl Document {
+    /// Returns the children of the node, or nothing when the node does not exist
+    pub fn children(&self, node_id: NodeId) -> Children<'_> {
+        let children = self
+            .get_node_by_id(node_id)
+            .map_or(&[][..], |node| node.children.as_slice());
+        Children {
+            document: self,
+            children: children.iter(),
+        }
+    }
+
+    /// Returns the descendants of the node (not including the node itself) in tree order
+    pub fn descendants(&self, node_id: NodeId) -> Descendants<'_> {
+        let stack = self
+            .get_node_by_id(node_id)
+            .map(|node| node.children.iter().rev().copied().collect())
+            .unwrap_or_default();
+        Descendants {
+            document: self,
+            stack,
+        }
+    }
+
+    /// Returns the ancestors of the node, from its parent up to the root
+    pub fn ancestors(&self, node_id: NodeId) -> Ancestors<'_> {
+        Ancestors {
+            document: self,
+            next: self.get_node_by_id(node_id).and_then(|node| node.parent),
+        }
+    }
+
+    /// Returns the siblings after the node in tree order, or nothing when the node has no parent
+    pub fn following_siblings(&self, node_id: NodeId) -> Children<'_> {
+        let siblings = self
+            .get_node_by_id(node_id)
+            .and_then(|node| self.get_node_by_id(node.parent?))
+            .map_or(&[][..], |parent| parent.children.as_slice());
+        let position = siblings
+            .iter()
+            .position(|&id| id == node_id)
+            .map_or(siblings.len(), |index| index + 1);
+        Children {
+            document: self,
+            children: siblings[position..].iter(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::html5::node::{Node, NodeData, NodeId};
+    use crate::testing::parse;
+
+    fn names<'a>(nodes: impl Iterator<Item = &'a Node>) -> Vec<&'a str> {
+        nodes
+            .map(|node| match node.data {
+                NodeData::Document(_) => "#document",
+                NodeData::Text(_) => "#text",
+                NodeData::Comment(_) => "#comment",
+                _ => node.name.as_str(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn traversal() {
+        let document = parse("<div id=d><p>one<b>two</b></p><i></i><!-- c --><u></u></div>");
+        let doc = document.get();
+        let div = doc.get_node_by_named_id("d").unwrap().id;
+        let p = doc.children(div).next().unwrap().id;
+        let b = doc.children(p).nth(1).unwrap().id;
+
+        assert_eq!(names(doc.children(div)), ["p", "i", "#comment", "u"]);
+        assert_eq!(
+            names(doc.descendants(div)),
+            ["p", "#text", "b", "#text", "i", "#comment", "u"]
+        );
+        assert_eq!(
+            names(doc.ancestors(b)),
+            ["p", "div", "body", "html", "#document"]
+        );
+        assert_eq!(names(doc.following_siblings(p)), ["i", "#comment", "u"]);
+        assert_eq!(doc.following_siblings(b).count(), 0);
+    }
+
+    #[test]
+    fn missing_and_detached_nodes() {
+        let mut document = parse("<p>text</p>");
+        let missing = NodeId::from(42);
+        {
+            let doc = document.get();
+            assert_eq!(doc.children(missing).count(), 0);
+            assert_eq!(doc.descendants(missing).count(), 0);
+            assert_eq!(doc.ancestors(missing).count(), 0);
+            assert_eq!(doc.following_siblings(missing).count(), 0);
+            assert_eq!(doc.ancestors(NodeId::root()).count(), 0);
+        }
+
+        let p = document.query_selector("p").unwrap().unwrap();
+        document.detach_node_from_parent(p);
+        let doc = document.get();
+        assert_eq!(doc.ancestors(p).count(), 0);
+        assert_eq!(doc.following_siblings(p).count(), 0);
+        assert_eq!(names(doc.descendants(p)), ["#text"]);
+    }
+}
@@ -0,0 +1,552 @@
+//! Constraint validation
+//!
+//! Form controls are validated against the constraints in their attributes (`required`,
+//! `pattern`, `minlength`/`maxlength`, `min`/`max`/`step`) and their type (an email address, a
+//! URL, a number). The result is a `ValidityState` per control.
+//!
+//! `check_validity()` fires `invalid` on every invalid control. `report_validity()` does the same,
+//! and returns the problems that should be shown to the user, which are the controls whose
+//! `invalid` event was not canceled. Both work on a single control as well as on a whole form.
+//!
+//! See: https://html.spec.whatwg.org/multipage/form-control-infrastructure.html#constraints
+use crate::html5::events::Event;
+use crate::html5::node::{Node, NodeId, HTML_NAMESPACE};
+use crate::html5::parser::document::{Document, DocumentHandle};
+use lazy_static::lazy_static;
+use regex::Regex;
+use url::Url;
+
+lazy_static! {
+    static ref EMAIL: Regex = Regex::new(
+        r"^[a-zA-Z0-9.!#$%&'*+/=?^_`{|}~-]+@[a-zA-Z0-9](?:[a-zA-Z0-9-]{0,61}[a-zA-Z0-9])?(?:\.[a-zA-Z0-9](?:[a-zA-Z0-9-]{0,61}[a-zA-Z0-9])?)*$"
+    )
+    .unwrap();
+    static ref NUMBER: Regex = Regex::new(r"^-?(?:[0-9]+(?:\.[0-9]+)?|\.[0-9]+)(?:[eE][+-]?[0-9]+)?$").unwrap();
+    static ref DATE: Regex = Regex::new(r"^([0-9]{4,})-([0-9]{2})-([0-9]{2})$").unwrap();
+}
+
+/// Input types whose value is text that can be matched against a pattern
+const PATTERN_TYPES: &[&str] = &["text", "search", "url", "tel", "email", "password"];
+
+/// Input types that do not support the required attribute
+const NO_REQUIRED_TYPES: &[&str] = &[
+    "hidden", "range", "color", "submit", "reset", "button", "image",
+];
+
+/// The ways in which a control can be invalid
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ValidityState {
+    /// The control is required but has no value
+    pub value_missing: bool,
+    /// The value does not match the type of the control (like an email address)
+    pub type_mismatch: bool,
+    /// The value does not match the pattern attribute
+    pub pattern_mismatch: bool,
+    /// The value is longer than the maxlength attribute
+    pub too_long: bool,
+    /// The value is shorter than the minlength attribute
+    pub too_short: bool,
+    /// The value is less than the min attribute
+    pub range_underflow: bool,
+    /// The value is greater than the max attribute
+    pub range_overflow: bool,
+    /// The value does not fit the step attribute
+    pub step_mismatch: bool,
+    /// The value cannot be converted (like a number that does not parse)
+    pub bad_input: bool,
+    /// A custom validity message has been set
+    pub custom_error: bool,
+}
+
+impl ValidityState {
+    /// Returns true when the control satisfies all its constraints
+    pub fn valid(&self) -> bool {
+        *self == ValidityState::default()
+    }
+}
+
+/// Problems found by `report_validity()`
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ValidityReport {
+    /// True when all controls are valid
+    pub valid: bool,
+    /// Invalid controls with their validation message, for the controls whose invalid event was
+    /// not canceled
+    pub problems: Vec<(NodeId, String)>,
+}
+
+impl Document {
+    /// Returns true when the element is a form control that is validated: it is not disabled,
+    /// read-only, a button that does not submit, or inside a datalist
+    pub fn will_validate(&self, node_id: NodeId) -> bool {
+        let Some(node) = self.get_node_by_id(node_id) else {
+            return false;
+        };
+        if !node.is_namespace(HTML_NAMESPACE) {
+            return false;
+        }
+
+        let candidate = match node.name.as_str() {
+            "input" => {
+                !matches!(input_type(node).as_str(), "hidden" | "reset" | "button")
+                    && node.get_attribute("readonly").is_none()
+            }
+            "textarea" => node.get_attribute("readonly").is_none(),
+            "button" => node
+                .get_attribute("type")
+                .map_or(true, |t| t.eq_ignore_ascii_case("submit")),
+            "select" => true,
+            _ => false,
+        };
+
+        candidate && !self.is_control_disabled(node_id) && !self.has_ancestor(node_id, "datalist")
+    }
+
+    /// Returns the validity of the form control. Controls that are not validated are always
+    /// valid.
+    pub fn validity(&self, node_id: NodeId) -> ValidityState {
+        let mut validity = ValidityState::default();
+        if !self.will_validate(node_id) {
+            return validity;
+        }
+        let Some(node) = self.get_node_by_id(node_id) else {
+            return validity;
+        };
+
+        validity.custom_error = self.form_controls.custom_validity(node_id).is_some();
+        let value = self.control_value(node_id);
+        let required = node.get_attribute("required").is_some();
+
+        match node.name.as_str() {
+            "input" => self.input_validity(node, &value, required, &mut validity),
+            "textarea" => {
+                validity.value_missing = required && value.is_empty();
+                self.length_validity(node, &value, &mut validity);
+            }
+            "select" => {
+                validity.value_missing = required
+                    && self
+                        .selected_options(node_id)
+                        .iter()
+                        .all(|option_id| self.option_value(*option_id).is_empty());
+            }
+            _ => {}
+        }
+
+        validity
+    }
+
+    /// Returns the message describing why the control is invalid, or an empty string when it is
+    /// valid
+    pub fn validation_message(&self, node_id: NodeId) -> String {
+        let validity = self.validity(node_id);
+        if let Some(message) = self.form_controls.custom_validity(node_id) {
+            return message.to_string();
+        }
+
+        let attribute = |name: &str| {
+            self.get_node_by_id(node_id)
+                .and_then(|node| node.get_attribute(name).cloned())
+                .unwrap_or_default()
+        };
+        if validity.value_missing {
+            "Please fill out this field.".to_string()
+        } else if validity.type_mismatch {
+            format!("Please enter a valid {}.", attribute("type").to_lowercase())
+        } else if validity.pattern_mismatch {
+            "Please match the requested format.".to_string()
+        } else if validity.too_long {
+            format!("Please use at most {} characters.", attribute("maxlength"))
+        } else if validity.too_short {
+            format!("Please use at least {} characters.", attribute("minlength"))
+        } else if validity.range_underflow {
+            format!(
+                "Value must be greater than or equal to {}.",
+                attribute("min")
+            )
+        } else if validity.range_overflow {
+            format!("Value must be less than or equal to {}.", attribute("max"))
+        } else if validity.step_mismatch {
+            "Please enter a valid value.".to_string()
+        } else if validity.bad_input {
+            "Please enter a number.".to_string()
+        } else {
+            String::new()
+        }
+    }
+
+    /// Returns the controls that are validated together with the node: the controls of the form
+    /// when the node is a form, or the node itself otherwise
+    fn validated_controls(&self, node_id: NodeId) -> Vec<NodeId> {
+        let is_form = self
+            .get_node_by_id(node_id)
+            .is_some_and(|node| node.is_namespace(HTML_NAMESPACE) && node.name == "form");
+        if !is_form {
+            return vec![node_id];
+        }
+
+        let mut controls = Vec::new();
+        let mut stack = vec![NodeId::root()];
+        while let Some(current) = stack.pop() {
+            let Some(node) = self.get_node_by_id(current) else {
+                continue;
+            };
+            if matches!(
+                node.name.as_str(),
+                "input" | "textarea" | "select" | "button"
+            ) && self.form_owner(current) == Some(node_id)
+            {
+                controls.push(current);
+            }
+            stack.extend(node.children.iter().rev());
+        }
+        controls
+    }
+
+    fn input_validity(
+        &self,
+        node: &Node,
+        value: &str,
+        required: bool,
+        validity: &mut ValidityState,
+    ) {
+        let kind = input_type(node);
+        let kind = kind.as_str();
+
+        if required && !NO_REQUIRED_TYPES.contains(&kind) {
+            validity.value_missing = match kind {
+                "checkbox" => !self.is_checked(node.id),
+                "radio" => !self
+                    .radio_group(node.id)
+                    .iter()
+                    .any(|radio_id| self.is_checked(*radio_id)),
+                _ => value.is_empty(),
+            };
+        }
+        if value.is_empty() {
+            return;
+        }
+
+        let multiple = kind == "email" && node.get_attribute("multiple").is_some();
+        let values: Vec<&str> = if multiple {
+            value.split(',').map(str::trim).collect()
+        } else {
+            vec![value]
+        };
+
+        validity.type_mismatch = match kind {
+            "email" => !values.iter().all(|value| EMAIL.is_match(value)),
+            "url" => Url::parse(value).is_err(),
+            _ => false,
+        };
+
+        if PATTERN_TYPES.contains(&kind) {
+            let pattern = node
+                .get_attribute("pattern")
+                .and_then(|pattern| Regex::new(&format!("^(?:{})$", pattern)).ok());
+            if let Some(pattern) = pattern {
+                validity.pattern_mismatch = !values.iter().all(|value| pattern.is_match(value));
+            }
+            self.length_validity(node, value, validity);
+        }
+
+        match kind {
+            "number" | "range" => {
+                let Some(number) = parse_number(value) else {
+                    validity.bad_input = kind == "number";
+                    return;
+                };
+                let min = node.get_attribute("min").and_then(|min| parse_number(min));
+                let max = node.get_attribute("max").and_then(|max| parse_number(max));
+                // Range inputs clamp their value, so they cannot be out of range
+                if kind == "number" {
+                    validity.range_underflow = min.is_some_and(|min| number < min);
+                    validity.range_overflow = max.is_some_and(|max| number > max);
+                }
+
+                let step = match node.get_attribute("step") {
+                    Some(step) if step.eq_ignore_ascii_case("any") => None,
+                    Some(step) => {
+                        Some(parse_number(step).filter(|step| *step > 0.0).unwrap_or(1.0))
+                    }
+                    None => Some(1.0),
+                };
+                if let Some(step) = step {
+                    let steps = (number - min.unwrap_or(0.0)) / step;
+                    validity.step_mismatch = (steps - steps.round()).abs() > 1e-9;
+                }
+            }
+            "date" => {
+                let Some(date) = parse_date(value) else {
+                    validity.bad_input = true;
+                    return;
+                };
+                let min = node.get_attribute("min").and_then(|min| parse_date(min));
+                let max = node.get_attribute("max").and_then(|max| parse_date(max));
+                validity.range_underflow = min.is_some_and(|min| date < min);
+                validity.range_overflow = max.is_some_and(|max| date > max);
+            }
+            _ => {}
+        }
+    }
+
+    /// Checks the minlength and maxlength attributes. These only apply to values entered by the
+    /// user, not to the default value from the page.
+    fn length_validity(&self, node: &Node, value: &str, validity: &mut ValidityState) {
+        if !self.form_controls.is_dirty(node.id) || value.is_empty() {
+            return;
+        }
+
+        // Lengths are measured in UTF-16 code units, like in javascript
+        let length = value.encode_utf16().count();
+        let limit = |name: &str| {
+            node.get_attribute(name)
+                .and_then(|limit| limit.trim().parse::<usize>().ok())
+        };
+        validity.too_long = limit("maxlength").is_some_and(|max| length > max);
+        validity.too_short = limit("minlength").is_some_and(|min| length < min);
+    }
+
+    /// Returns true when the control is disabled, either by itself or by a disabled fieldset
+    pub(crate) fn is_control_disabled(&self, node_id: NodeId) -> bool {
+        let Some(node) = self.get_node_by_id(node_id) else {
+            return false;
+        };
+        if node.get_attribute("disabled").is_some() {
+            return true;
+        }
+
+        let mut current = node.parent;
+        while let Some(ancestor) = current.and_then(|id| self.get_node_by_id(id)) {
+            if ancestor.is_namespace(HTML_NAMESPACE)
+                && ancestor.name == "fieldset"
+                && ancestor.get_attribute("disabled").is_some()
+            {
+                return true;
+            }
+            current = ancestor.parent;
+        }
+        false
+    }
+
+    fn has_ancestor(&self, node_id: NodeId, name: &str) -> bool {
+        self.ancestors(node_id)
+            .any(|ancestor| ancestor.is_namespace(HTML_NAMESPACE) && ancestor.name == name)
+    }
+}
+
+impl DocumentHandle {
+    /// Returns true when the control (or every control of the form) is valid. Fires `invalid` on
+    /// every invalid control.
+    pub fn check_validity(&mut self, node_id: NodeId) -> bool {
+        self.fire_invalid_events(node_id).is_empty()
+    }
+
+    /// Like `check_validity()`, but also returns the problems to report to the user
+    pub fn report_validity(&mut self, node_id: NodeId) -> ValidityReport {
+        let invalid = self.fire_invalid_events(node_id);
+        let problems = invalid
+            .iter()
+            .filter(|(_, reported)| *reported)
+            .map(|(control_id, _)| (*control_id, self.get().validation_message(*control_id)))
+            .collect();
+
+        ValidityReport {
+            valid: invalid.is_empty(),
+            problems,
+        }
+    }
+
+    /// Fires `invalid` on the invalid controls, and returns them together with whether the event
+    /// was not canceled
+    fn fire_invalid_events(&mut self, node_id: NodeId) -> Vec<(NodeId, bool)> {
+        let invalid: Vec<NodeId> = {
+            let doc = self.get();
+            doc.validated_controls(node_id)
+                .into_iter()
+                .filter(|control_id| !doc.validity(*control_id).valid())
+                .collect()
+        };
+
+        invalid
+            .into_iter()
+            .map(|control_id| {
+                let mut event = Event::new("invalid", false, true);
+                (control_id, self.dispatch_event(control_id, &mut event))
+            })
+            .collect()
+    }
+}
+
+/// Returns the type of the input element. Unknown types are text inputs.
+fn input_type(node: &Node) -> String {
+    const TYPES: &[&str] = &[
+        "hidden",
+        "text",
+        "search",
+        "tel",
+        "url",
+        "email",
+        "password",
+        "date",
+        "month",
+        "week",
+        "time",
+        "datetime-local",
+        "number",
+        "range",
+        "color",
+        "checkbox",
+        "radio",
+        "file",
+        "submit",
+        "image",
+        "reset",
+        "button",
+    ];
+
+    let kind = node
+        .get_attribute("type")
+        .map(|kind| kind.to_ascii_lowercase())
+        .unwrap_or_default();
+    if TYPES.contains(&kind.as_str()) {
+        kind
+    } else {
+        "text".to_string()
+    }
+}
+
+/// Parses a valid floating-point number
+fn parse_number(value: &str) -> Option<f64> {
+    if !NUMBER.is_match(value) {
+        return None;
+    }
+    value
+        .parse::<f64>()
+        .ok()
+        .filter(|number| number.is_finite())
+}
+
+/// Parses a valid date string into a (year, month, day) that compares in date order
+fn parse_date(value: &str) -> Option<(u32, u32, u32)> {
+    let captures = DATE.captures(value)?;
+    let year: u32 = captures[1].parse().ok()?;
+    let month: u32 = captures[2].parse().ok()?;
+    let day: u32 = captures[3].parse().ok()?;
+
+    let leap = year % 4 == 0 && (year % 100 != 0 || year % 400 == 0);
+    let days = match month {
+        1 | 3 | 5 | 7 | 8 | 10 | 12 => 31,
+        4 | 6 | 9 | 11 => 30,
+        2 if leap => 29,
+        2 => 28,
+        _ => return None,
+    };
+    (year > 0 && (1..=days).contains(&day)).then_some((year, month, day))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::html5::events::EventCallback;
+    use crate::testing::parse;
+    use std::rc::Rc;
+
+    fn named(document: &DocumentHandle, id: &str) -> NodeId {
+        document.get().get_node_by_named_id(id).unwrap().id
+    }
+
+    #[test]
+    fn input_constraints() {
+        let mut document = parse(
+            "<input id=req required><input id=email type=email value=foo>\
+             <input id=pat pattern=[a-z]+ value=abc1><input id=num type=number min=1 max=10 step=2 value=4>\
+             <input id=len maxlength=3 value=toolong><input id=date type=date min=2020-01-01 value=2019-12-31>\
+             <input id=url type=url value=https://example.com><input id=dis required disabled>",
+        );
+        let validity =
+            |document: &DocumentHandle, id: &str| document.get().validity(named(document, id));
+
+        assert!(validity(&document, "req").value_missing);
+        assert!(validity(&document, "email").type_mismatch);
+        assert!(validity(&document, "pat").pattern_mismatch);
+        assert!(validity(&document, "num").step_mismatch);
+        assert!(validity(&document, "date").range_underflow);
+        assert!(validity(&document, "url").valid());
+        assert!(!document.get().will_validate(named(&document, "dis")));
+        assert!(validity(&document, "dis").valid());
+
+        // The maximum length only applies to values entered by the user
+        assert!(validity(&document, "len").valid());
+        let len = named(&document, "len");
+        document.set_control_value(len, "four");
+        assert!(validity(&document, "len").too_long);
+        assert_eq!(
+            document.get().validation_message(len),
+            "Please use at most 3 characters."
+        );
+
+        let num = named(&document, "num");
+        document.set_control_value(num, "11");
+        assert!(validity(&document, "num").range_overflow);
+        document.set_control_value(num, "1e");
+        assert!(validity(&document, "num").bad_input);
+        document.set_control_value(num, "5");
+        assert!(validity(&document, "num").valid());
+
+        let req = named(&document, "req");
+        document.set_control_value(req, "x");
+        assert!(validity(&document, "req").valid());
+        document.set_custom_validity(req, "taken");
+        assert!(validity(&document, "req").custom_error);
+        assert_eq!(document.get().validation_message(req), "taken");
+        document.set_custom_validity(req, "");
+        assert!(validity(&document, "req").valid());
+    }
+
+    #[test]
+    fn form_validity() {
+        let mut document = parse(
+            "<form id=f><input type=radio name=r required id=r1><input type=radio name=r id=r2>\
+             <input type=checkbox required id=c><select required id=s><option value=''>Pick</option>\
+             <option>one</option></select><textarea required id=t></textarea><button>go</button></form>\
+             <input form=f required id=outside>",
+        );
+        let form = named(&document, "f");
+
+        // Canceled invalid events are not reported
+        let textarea = named(&document, "t");
+        let cancel: EventCallback = Rc::new(|_, event| event.prevent_default());
+        document
+            .get_mut()
+            .add_event_listener(textarea, "invalid", cancel, Default::default());
+
+        let report = document.report_validity(form);
+        assert!(!report.valid);
+        let reported: Vec<NodeId> = report.problems.iter().map(|(id, _)| *id).collect();
+        assert_eq!(
+            reported,
+            vec![
+                named(&document, "r1"),
+                named(&document, "c"),
+                named(&document, "s"),
+                named(&document, "outside"),
+            ]
+        );
+        assert_eq!(report.problems[0].1, "Please fill out this field.");
+
+        // Checking the other radio button of the group satisfies the required radio button
+        document.set_checked(named(&document, "r2"), true);
+        document.set_checked(named(&document, "c"), true);
+        document
+            .set_select_value(named(&document, "s"), "one")
+            .unwrap();
+        document.set_control_value(named(&document, "t"), "text");
+        document.set_control_value(named(&document, "outside"), "x");
+        assert!(document.check_validity(form));
+        assert!(document.report_validity(form).problems.is_empty());
+
+        document.get_mut().reset_form_control(form);
+        assert!(!document.check_validity(form));
+    }
+}
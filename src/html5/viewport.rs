@@ -0,0 +1,330 @@
+//! Meta viewport handling
+//!
+//! Mobile-targeted pages describe their intended layout size with a `<meta name="viewport">`
+//! element. The content attribute is parsed into a [`Viewport`], which can then be resolved
+//! against the size of the device into the size of the initial containing block.
+//!
+//! See: https://drafts.csswg.org/css-viewport/#viewport-meta
+use crate::html5::parser::document::Document;
+
+/// Width that is used for documents that do not define a viewport width (desktop layout)
+pub const DEFAULT_VIEWPORT_WIDTH: f32 = 980.0;
+
+const MIN_LENGTH: f32 = 1.0;
+const MAX_LENGTH: f32 = 10000.0;
+const MIN_SCALE: f32 = 0.1;
+const MAX_SCALE: f32 = 10.0;
+
+/// A length as found in the width and height properties of the viewport
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ViewportLength {
+    /// The width of the device
+    DeviceWidth,
+    /// The height of the device
+    DeviceHeight,
+    /// Absolute length in CSS pixels
+    Px(f32),
+}
+
+impl ViewportLength {
+    /// Resolves the length against the given device size
+    fn resolve(&self, device: DeviceSize) -> f32 {
+        match self {
+            ViewportLength::DeviceWidth => device.width,
+            ViewportLength::DeviceHeight => device.height,
+            ViewportLength::Px(px) => *px,
+        }
+    }
+}
+
+/// Size of the screen of the device (in CSS pixels) the document is displayed on
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DeviceSize {
+    pub width: f32,
+    pub height: f32,
+}
+
+impl DeviceSize {
+    /// Create a new device size
+    pub fn new(width: f32, height: f32) -> Self {
+        Self { width, height }
+    }
+}
+
+/// Viewport that has been resolved against a device and can be used for laying out the document
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ResolvedViewport {
+    /// Width of the initial containing block in CSS pixels
+    pub width: f32,
+    /// Height of the initial containing block in CSS pixels
+    pub height: f32,
+    /// Initial zoom factor
+    pub scale: f32,
+    /// Smallest zoom factor the user is allowed to zoom out to
+    pub minimum_scale: f32,
+    /// Largest zoom factor the user is allowed to zoom in to
+    pub maximum_scale: f32,
+    /// When false, the user is not allowed to zoom
+    pub user_scalable: bool,
+}
+
+/// Typed representation of the content of a `<meta name="viewport">` element
+#[derive(Debug, Clone, PartialEq)]
+pub struct Viewport {
+    pub width: Option<ViewportLength>,
+    pub height: Option<ViewportLength>,
+    pub initial_scale: Option<f32>,
+    pub minimum_scale: Option<f32>,
+    pub maximum_scale: Option<f32>,
+    pub user_scalable: bool,
+}
+
+impl Default for Viewport {
+    fn default() -> Self {
+        Self {
+            width: None,
+            height: None,
+            initial_scale: None,
+            minimum_scale: None,
+            maximum_scale: None,
+            user_scalable: true,
+        }
+    }
+}
+
+impl Viewport {
+    /// Parses the content attribute of a meta viewport element. Unknown properties and invalid
+    /// values are ignored.
+    pub fn parse(content: &str) -> Self {
+        let mut viewport = Viewport::default();
+
+        for (key, value) in parse_properties(content) {
+            match key.as_str() {
+                "width" => viewport.width = parse_length(&value),
+                "height" => viewport.height = parse_length(&value),
+                "initial-scale" => viewport.initial_scale = parse_scale(&value),
+                "minimum-scale" => viewport.minimum_scale = parse_scale(&value),
+                "maximum-scale" => viewport.maximum_scale = parse_scale(&value),
+                "user-scalable" => viewport.user_scalable = parse_user_scalable(&value),
+                _ => {}
+            }
+        }
+
+        viewport
+    }
+
+    /// Resolves the viewport against the given device, which results in the size of the
+    /// initial containing block the document should be laid out in.
+    pub fn resolve(&self, device: DeviceSize) -> ResolvedViewport {
+        let minimum_scale = self.minimum_scale.unwrap_or(MIN_SCALE);
+        let maximum_scale = self.maximum_scale.unwrap_or(MAX_SCALE).max(minimum_scale);
+        let clamp_scale = |scale: f32| scale.clamp(minimum_scale, maximum_scale);
+
+        let width = match (self.width, self.initial_scale) {
+            (Some(width), _) => width.resolve(device),
+            (None, Some(scale)) => device.width / clamp_scale(scale),
+            (None, None) => match self.height {
+                Some(height) => height.resolve(device) * device.width / device.height,
+                None => DEFAULT_VIEWPORT_WIDTH,
+            },
+        };
+        let width = width.clamp(MIN_LENGTH, MAX_LENGTH);
+
+        let height = match self.height {
+            Some(height) => height.resolve(device),
+            None => width * device.height / device.width,
+        };
+        let height = height.clamp(MIN_LENGTH, MAX_LENGTH);
+
+        let scale = clamp_scale(self.initial_scale.unwrap_or(device.width / width));
+
+        ResolvedViewport {
+            width,
+            height,
+            scale,
+            minimum_scale,
+            maximum_scale,
+            user_scalable: self.user_scalable,
+        }
+    }
+}
+
+impl Document {
+    /// Returns the viewport as defined by the first `<meta name="viewport">` element in the
+    /// document, or None when the document does not define one.
+    pub fn viewport(&self) -> Option<Viewport> {
+        self.elements_by_tag_name("meta")
+            .into_iter()
+            .filter_map(|node_id| self.get_node_by_id(node_id))
+            .find_map(|node| {
+                let name = node.get_attribute("name")?;
+                if !name.trim().eq_ignore_ascii_case("viewport") {
+                    return None;
+                }
+                node.get_attribute("content").map(|c| Viewport::parse(c))
+            })
+    }
+}
+
+/// Splits the content into (lowercase) key/value pairs. Properties are separated by whitespace,
+/// commas or semicolons, and whitespace is allowed around the equal sign.
+fn parse_properties(content: &str) -> Vec<(String, String)> {
+    let is_separator = |c: char| c.is_ascii_whitespace() || c == ',' || c == ';';
+
+    let chars = content.chars().collect::<Vec<_>>();
+    let mut properties = Vec::new();
+    let mut i = 0;
+
+    let read_word = |i: &mut usize| {
+        let start = *i;
+        while *i < chars.len() && !is_separator(chars[*i]) && chars[*i] != '=' {
+            *i += 1;
+        }
+        chars[start..*i].iter().collect::<String>()
+    };
+    let skip_whitespace = |i: &mut usize| {
+        while *i < chars.len() && chars[*i].is_ascii_whitespace() {
+            *i += 1;
+        }
+    };
+
+    while i < chars.len() {
+        while i < chars.len() && (is_separator(chars[i]) || chars[i] == '=') {
+            i += 1;
+        }
+
+        let key = read_word(&mut i);
+        if key.is_empty() {
+            continue;
+        }
+
+        skip_whitespace(&mut i);
+        let mut value = String::new();
+        if i < chars.len() && chars[i] == '=' {
+            i += 1;
+            skip_whitespace(&mut i);
+            value = read_word(&mut i);
+        }
+
+        properties.push((key.to_ascii_lowercase(), value.to_ascii_lowercase()));
+    }
+
+    properties
+}
+
+/// Parses the leading number of a value (so "2.5px" results in 2.5)
+fn parse_number(value: &str) -> Option<f32> {
+    let end = value
+        .char_indices()
+        .find(|&(idx, c)| !(c.is_ascii_digit() || c == '.' || (idx == 0 && (c == '-' || c == '+'))))
+        .map_or(value.len(), |(idx, _)| idx);
+
+    value[..end].parse::<f32>().ok()
+}
+
+fn parse_length(value: &str) -> Option<ViewportLength> {
+    match value {
+        "device-width" => Some(ViewportLength::DeviceWidth),
+        "device-height" => Some(ViewportLength::DeviceHeight),
+        _ => {
+            let number = parse_number(value)?;
+            if number < 0.0 {
+                return None;
+            }
+            Some(ViewportLength::Px(number.clamp(MIN_LENGTH, MAX_LENGTH)))
+        }
+    }
+}
+
+fn parse_scale(value: &str) -> Option<f32> {
+    let number = match value {
+        "yes" => 1.0,
+        "device-width" | "device-height" => MAX_SCALE,
+        _ => parse_number(value)?,
+    };
+    if number < 0.0 {
+        return None;
+    }
+
+    Some(number.clamp(MIN_SCALE, MAX_SCALE))
+}
+
+fn parse_user_scalable(value: &str) -> bool {
+    let number = match value {
+        "yes" | "" => 1.0,
+        "no" => 0.0,
+        "device-width" | "device-height" => MAX_SCALE,
+        _ => parse_number(value).unwrap_or(0.0),
+    };
+
+    number.abs() >= 1.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::html5::parser::document::DocumentBuilder;
+    use crate::testing::parse;
+
+    #[test]
+    fn parse_viewport() {
+        let viewport = Viewport::parse("width=device-width, initial-scale=1.0, user-scalable=no");
+        assert_eq!(viewport.width, Some(ViewportLength::DeviceWidth));
+        assert_eq!(viewport.height, None);
+        assert_eq!(viewport.initial_scale, Some(1.0));
+        assert!(!viewport.user_scalable);
+    }
+
+    #[test]
+    fn parse_viewport_separators() {
+        let viewport = Viewport::parse(" WIDTH = 320 ; maximum-scale=20  foo=bar,height=480px");
+        assert_eq!(viewport.width, Some(ViewportLength::Px(320.0)));
+        assert_eq!(viewport.height, Some(ViewportLength::Px(480.0)));
+        assert_eq!(viewport.maximum_scale, Some(MAX_SCALE));
+        assert!(viewport.user_scalable);
+    }
+
+    #[test]
+    fn parse_invalid_values() {
+        let viewport = Viewport::parse("width=-100, initial-scale=abc, user-scalable=0.5");
+        assert_eq!(viewport.width, None);
+        assert_eq!(viewport.initial_scale, None);
+        assert!(!viewport.user_scalable);
+    }
+
+    #[test]
+    fn resolve_viewport() {
+        let device = DeviceSize::new(375.0, 812.0);
+
+        let resolved = Viewport::parse("width=device-width, initial-scale=1").resolve(device);
+        assert_eq!(resolved.width, 375.0);
+        assert_eq!(resolved.height, 812.0);
+        assert_eq!(resolved.scale, 1.0);
+
+        let resolved = Viewport::parse("initial-scale=2").resolve(device);
+        assert_eq!(resolved.width, 187.5);
+        assert_eq!(resolved.scale, 2.0);
+
+        let resolved = Viewport::default().resolve(device);
+        assert_eq!(resolved.width, DEFAULT_VIEWPORT_WIDTH);
+        assert!((resolved.scale - 375.0 / 980.0).abs() < 0.001);
+    }
+
+    #[test]
+    fn viewport_from_document() {
+        let document = parse(
+            "<html><head><meta name=description content=foo>\
+             <meta name=Viewport content=\"width=600\"><meta name=viewport content=\"width=700\">\
+             </head><body></body></html>",
+        );
+
+        let viewport = document.get().viewport().unwrap();
+        assert_eq!(viewport.width, Some(ViewportLength::Px(600.0)));
+    }
+
+    #[test]
+    fn document_without_viewport() {
+        let document = DocumentBuilder::new_document();
+        assert!(document.get().viewport().is_none());
+    }
+}
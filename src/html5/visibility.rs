@@ -0,0 +1,262 @@
+//! Disclosure, hidden and inert elements
+//!
+//! Some elements are not rendered because of the state of the document instead of their style:
+//! elements with the `hidden` attribute, and the contents of a closed `<details>` element (except
+//! for its summary). For rendering these are the equivalent of `display: none`, which is what
+//! `is_hidden()` reports. Elements with the `inert` attribute (and their descendants) are
+//! rendered, but cannot be interacted with.
+//!
+//! A `<details>` element is opened and closed through its `open` attribute. Changing it with
+//! `set_details_open()` or by clicking its summary fires a `toggle` event on the element.
+//!
+//! See: https://html.spec.whatwg.org/multipage/interactive-elements.html#the-details-element
+use crate::html5::events::Event;
+use crate::html5::node::{Node, NodeId, HTML_NAMESPACE};
+use crate::html5::parser::document::{Document, DocumentHandle};
+use crate::html5::parser::tree_builder::TreeBuilder;
+use crate::types::{Error, Result};
+
+impl Document {
+    /// Returns true when the node is a `<details>` element that is open
+    pub fn is_details_open(&self, node_id: NodeId) -> bool {
+        self.get_node_by_id(node_id)
+            .is_some_and(|node| is_html(node, "details") && node.get_attribute("open").is_some())
+    }
+
+    /// Returns the summary of a `<details>` element, which is its first `<summary>` child
+    pub fn details_summary(&self, node_id: NodeId) -> Option<NodeId> {
+        let node = self.get_node_by_id(node_id)?;
+        if !is_html(node, "details") {
+            return None;
+        }
+
+        node.children.iter().copied().find(|child_id| {
+            self.get_node_by_id(*child_id)
+                .is_some_and(|child| is_html(child, "summary"))
+        })
+    }
+
+    /// Returns true when the node is not rendered because it (or one of its ancestors) has the
+    /// `hidden` attribute or is inside a closed `<details>` element
+    pub fn is_hidden(&self, node_id: NodeId) -> bool {
+        let mut current = Some(node_id);
+        while let Some(node) = current.and_then(|node_id| self.get_node_by_id(node_id)) {
+            if node.is_namespace(HTML_NAMESPACE) && node.get_attribute("hidden").is_some() {
+                return true;
+            }
+
+            if let Some(parent_id) = node.parent {
+                if self
+                    .get_node_by_id(parent_id)
+                    .is_some_and(|parent| is_html(parent, "details"))
+                    && !self.is_details_open(parent_id)
+                    && self.details_summary(parent_id) != Some(node.id)
+                {
+                    return true;
+                }
+            }
+
+            current = node.parent;
+        }
+
+        false
+    }
+
+    /// Returns true when the node (or one of its ancestors) has the `inert` attribute, or when it
+    /// is blocked by a modal dialog
+    pub fn is_inert(&self, node_id: NodeId) -> bool {
+        if self.is_blocked_by_modal_dialog(node_id) {
+            return true;
+        }
+
+        let mut current = Some(node_id);
+        while let Some(node) = current.and_then(|node_id| self.get_node_by_id(node_id)) {
+            if node.is_namespace(HTML_NAMESPACE) && node.get_attribute("inert").is_some() {
+                return true;
+            }
+            current = node.parent;
+        }
+
+        false
+    }
+
+    /// Returns the `<details>` element that is toggled by activating the node: the node must be
+    /// (inside) the summary of the details element
+    fn summary_details(&self, node_id: NodeId) -> Option<NodeId> {
+        let mut current = Some(node_id);
+        while let Some(node) = current.and_then(|node_id| self.get_node_by_id(node_id)) {
+            if is_html(node, "summary") {
+                let parent_id = node.parent?;
+                return (self.details_summary(parent_id) == Some(node.id)).then_some(parent_id);
+            }
+            current = node.parent;
+        }
+
+        None
+    }
+}
+
+impl DocumentHandle {
+    /// Opens or closes a `<details>` element, and fires `toggle` when its state changed
+    pub fn set_details_open(&mut self, node_id: NodeId, open: bool) -> Result<()> {
+        let is_details = self
+            .get()
+            .get_node_by_id(node_id)
+            .is_some_and(|node| is_html(node, "details"));
+        if !is_details {
+            return Err(Error::DocumentTask(format!(
+                "Node ID {} is not a details element",
+                node_id
+            )));
+        }
+        if self.get().is_details_open(node_id) == open {
+            return Ok(());
+        }
+
+        self.set_boolean_attribute(node_id, "open", open)?;
+
+        let mut event = Event::new("toggle", false, false);
+        self.dispatch_event(node_id, &mut event);
+        Ok(())
+    }
+
+    /// Toggles a `<details>` element and returns whether it is open now
+    pub fn toggle_details(&mut self, node_id: NodeId) -> Result<bool> {
+        let open = !self.get().is_details_open(node_id);
+        self.set_details_open(node_id, open)?;
+        Ok(open)
+    }
+
+    /// Sets or removes the `hidden` attribute of an element
+    pub fn set_hidden(&mut self, node_id: NodeId, hidden: bool) -> Result<()> {
+        self.set_boolean_attribute(node_id, "hidden", hidden)
+    }
+
+    /// Sets or removes the `inert` attribute of an element
+    pub fn set_inert(&mut self, node_id: NodeId, inert: bool) -> Result<()> {
+        self.set_boolean_attribute(node_id, "inert", inert)
+    }
+
+    /// Clicks the node: fires `click`, and when it is not canceled runs the activation behavior
+    /// (toggling the `<details>` element of a summary). Inert nodes cannot be clicked. Returns
+    /// false when the node is inert or the click was canceled.
+    pub fn click(&mut self, node_id: NodeId) -> bool {
+        if self.get().is_inert(node_id) {
+            return false;
+        }
+
+        let mut event = Event::new("click", true, true);
+        if !self.dispatch_event(node_id, &mut event) {
+            return false;
+        }
+
+        let details = self.get().summary_details(node_id);
+        if let Some(details_id) = details {
+            let _ = self.toggle_details(details_id);
+        }
+        true
+    }
+
+    fn set_boolean_attribute(&mut self, node_id: NodeId, name: &str, value: bool) -> Result<()> {
+        if value {
+            self.insert_attribute(name, "", node_id)
+        } else {
+            self.remove_attribute(name, node_id)
+        }
+    }
+}
+
+fn is_html(node: &Node, name: &str) -> bool {
+    node.is_namespace(HTML_NAMESPACE) && node.name == name
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::html5::events::EventCallback;
+    use crate::testing::parse;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    fn element(document: &DocumentHandle, tag: &str) -> NodeId {
+        document.get().elements_by_tag_name(tag)[0]
+    }
+
+    #[test]
+    fn details() {
+        let mut document =
+            parse("<details><summary><b>More</b></summary><p>contents</p></details>");
+        let details = element(&document, "details");
+        let summary = element(&document, "summary");
+        let p = element(&document, "p");
+
+        let toggles = Rc::new(RefCell::new(0));
+        let counter = toggles.clone();
+        let callback: EventCallback = Rc::new(move |_, _| *counter.borrow_mut() += 1);
+        document
+            .get_mut()
+            .add_event_listener(details, "toggle", callback, Default::default());
+
+        assert_eq!(document.get().details_summary(details), Some(summary));
+        assert!(!document.get().is_details_open(details));
+        assert!(document.get().is_hidden(p));
+        assert!(!document.get().is_hidden(summary));
+
+        // Clicking (inside) the summary opens the details element
+        assert!(document.click(element(&document, "b")));
+        assert!(document.get().is_details_open(details));
+        assert!(!document.get().is_hidden(p));
+        assert_eq!(*toggles.borrow(), 1);
+
+        // Setting the current state does not fire toggle
+        document.set_details_open(details, true).unwrap();
+        assert_eq!(*toggles.borrow(), 1);
+        assert!(!document.toggle_details(details).unwrap());
+        assert!(document.get().is_hidden(p));
+        assert_eq!(*toggles.borrow(), 2);
+
+        // Clicking the contents does nothing
+        document.click(p);
+        assert!(!document.get().is_details_open(details));
+        assert!(document.set_details_open(p, true).is_err());
+    }
+
+    #[test]
+    fn hidden_and_inert() {
+        let mut document =
+            parse("<div><p>one</p></div><section inert><button>x</button></section>");
+        let div = element(&document, "div");
+        let p = element(&document, "p");
+        let button = element(&document, "button");
+
+        assert!(!document.get().is_hidden(p));
+        document.set_hidden(div, true).unwrap();
+        assert!(document.get().is_hidden(p));
+        document.set_hidden(div, false).unwrap();
+        assert!(!document.get().is_hidden(p));
+        assert!(document
+            .get()
+            .get_node_by_id(div)
+            .unwrap()
+            .get_attribute("hidden")
+            .is_none());
+
+        // Inert nodes cannot be clicked
+        let clicks = Rc::new(RefCell::new(0));
+        let counter = clicks.clone();
+        let callback: EventCallback = Rc::new(move |_, _| *counter.borrow_mut() += 1);
+        document
+            .get_mut()
+            .add_event_listener(button, "click", callback, Default::default());
+
+        assert!(document.get().is_inert(button));
+        assert!(!document.get().is_hidden(button));
+        assert!(!document.click(button));
+        assert_eq!(*clicks.borrow(), 0);
+
+        let section = element(&document, "section");
+        document.set_inert(section, false).unwrap();
+        assert!(document.click(button));
+        assert_eq!(*clicks.borrow(), 1);
+    }
+}
@@ -2,10 +2,13 @@ extern crate alloc;
 extern crate core;
 
 pub mod api;
+pub mod browsing_context;
 pub mod bytes;
 #[allow(dead_code)]
 pub mod css3;
+pub mod event_loop;
 #[allow(dead_code)]
 pub mod html5;
+pub mod net;
 pub mod testing;
 pub mod types;
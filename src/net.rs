@@ -0,0 +1,12 @@
+//! Networking
+//!
+//! Everything that is needed for talking to the network on behalf of a document, like storing
+//! cookies, checking requests against the origin and content security policy of the document, and
+//! creating requests with the right referrer and headers. Crawlers can opt in to honouring the
+//! robots.txt of the sites they visit.
+pub mod cookies;
+pub mod csp;
+pub mod fetch;
+pub mod origin;
+pub mod referrer;
+pub mod robots;
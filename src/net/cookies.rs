@@ -0,0 +1,521 @@
+//! Cookie storage
+//!
+//! The cookie jar stores cookies received through `Set-Cookie` headers or through
+//! `document.cookie`, and returns the cookies that should be sent along with a request.
+//!
+//! See: https://httpwg.org/specs/rfc6265.html
+use crate::html5::parser::document::Document;
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use url::Url;
+
+/// Defines through which API a cookie is read or written
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CookieSource {
+    /// HTTP headers (Set-Cookie / Cookie)
+    Http,
+    /// Scripts and embedders (document.cookie). HttpOnly cookies are not accessible.
+    NonHttp,
+}
+
+/// Value of the SameSite attribute
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SameSite {
+    None,
+    Lax,
+    Strict,
+}
+
+/// A single stored cookie
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cookie {
+    pub name: String,
+    pub value: String,
+    /// Domain of the cookie (lowercase, without leading dot)
+    pub domain: String,
+    /// When true, the cookie is only sent to the exact domain, not to its subdomains
+    pub host_only: bool,
+    pub path: String,
+    /// Expiry time, or None for a session cookie
+    pub expires: Option<SystemTime>,
+    pub secure: bool,
+    pub http_only: bool,
+    pub same_site: SameSite,
+    pub creation_time: SystemTime,
+}
+
+impl Cookie {
+    /// Returns true when the cookie has expired at the given time
+    pub fn is_expired(&self, now: SystemTime) -> bool {
+        self.expires.is_some_and(|expires| expires <= now)
+    }
+
+    /// Returns true when the cookie should be sent along with a request to the given url
+    fn matches(&self, url: &Url, source: CookieSource) -> bool {
+        let Some(host) = url.host_str() else {
+            return false;
+        };
+        let host = host.to_ascii_lowercase();
+
+        let domain_match = if self.host_only {
+            host == self.domain
+        } else {
+            domain_matches(&host, &self.domain)
+        };
+
+        domain_match
+            && path_matches(url.path(), &self.path)
+            && (!self.secure || url.scheme() == "https")
+            && (!self.http_only || source == CookieSource::Http)
+    }
+}
+
+/// Storage for all cookies
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CookieJar {
+    cookies: Vec<Cookie>,
+}
+
+impl CookieJar {
+    /// Creates a new empty cookie jar
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Parses a set-cookie string that is received for the given url and stores the cookie.
+    /// Returns false when the cookie was ignored.
+    pub fn set_cookie(&mut self, url: &Url, set_cookie: &str, source: CookieSource) -> bool {
+        self.set_cookie_at(url, set_cookie, source, SystemTime::now())
+    }
+
+    fn set_cookie_at(
+        &mut self,
+        url: &Url,
+        set_cookie: &str,
+        source: CookieSource,
+        now: SystemTime,
+    ) -> bool {
+        let Some(host) = url.host_str() else {
+            return false;
+        };
+        let host = host.to_ascii_lowercase();
+
+        let mut parts = set_cookie.split(';');
+        let name_value = parts.next().unwrap_or_default();
+        let (name, value) = match name_value.split_once('=') {
+            Some((name, value)) => (name.trim(), value.trim()),
+            None => ("", name_value.trim()),
+        };
+        if name.is_empty() && value.is_empty() {
+            return false;
+        }
+
+        let mut cookie = Cookie {
+            name: name.to_string(),
+            value: value.to_string(),
+            domain: host.clone(),
+            host_only: true,
+            path: default_path(url),
+            expires: None,
+            secure: false,
+            http_only: false,
+            same_site: SameSite::Lax,
+            creation_time: now,
+        };
+
+        let mut max_age = None;
+        let mut expires = None;
+        for attribute in parts {
+            let (key, value) = match attribute.split_once('=') {
+                Some((key, value)) => (key.trim(), value.trim()),
+                None => (attribute.trim(), ""),
+            };
+
+            match key.to_ascii_lowercase().as_str() {
+                "expires" => expires = parse_cookie_date(value).or(expires),
+                "max-age" => {
+                    if let Ok(seconds) = value.parse::<i64>() {
+                        max_age = Some(seconds);
+                    }
+                }
+                "domain" if !value.is_empty() => {
+                    let domain = value.trim_start_matches('.').to_ascii_lowercase();
+                    if !domain_matches(&host, &domain) {
+                        return false;
+                    }
+                    // Cookies for top level domains are not allowed
+                    if !domain.contains('.') && domain != host {
+                        return false;
+                    }
+                    cookie.host_only = false;
+                    cookie.domain = domain;
+                }
+                "path" if value.starts_with('/') => cookie.path = value.to_string(),
+                "secure" => cookie.secure = true,
+                "httponly" => cookie.http_only = true,
+                "samesite" => {
+                    cookie.same_site = match value.to_ascii_lowercase().as_str() {
+                        "none" => SameSite::None,
+                        "strict" => SameSite::Strict,
+                        _ => SameSite::Lax,
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        // Max-Age has precedence over Expires
+        cookie.expires = match max_age {
+            Some(seconds) if seconds <= 0 => Some(UNIX_EPOCH),
+            Some(seconds) => now.checked_add(Duration::from_secs(seconds as u64)),
+            None => expires,
+        };
+
+        if source == CookieSource::NonHttp && cookie.http_only {
+            return false;
+        }
+        if cookie.secure && url.scheme() != "https" {
+            return false;
+        }
+
+        if let Some(idx) = self.cookies.iter().position(|c| {
+            c.name == cookie.name && c.domain == cookie.domain && c.path == cookie.path
+        }) {
+            let existing = &self.cookies[idx];
+            if source == CookieSource::NonHttp && existing.http_only {
+                return false;
+            }
+            cookie.creation_time = existing.creation_time;
+            self.cookies.remove(idx);
+        }
+
+        if !cookie.is_expired(now) {
+            self.cookies.push(cookie);
+        }
+        true
+    }
+
+    /// Returns the cookies that should be sent to the given url. Cookies with longer paths are
+    /// listed first, and cookies with equal paths are listed in order of creation.
+    pub fn cookies_for(&self, url: &Url, source: CookieSource) -> Vec<&Cookie> {
+        let now = SystemTime::now();
+
+        let mut cookies = self
+            .cookies
+            .iter()
+            .filter(|c| !c.is_expired(now) && c.matches(url, source))
+            .collect::<Vec<_>>();
+        cookies.sort_by(|a, b| {
+            b.path
+                .len()
+                .cmp(&a.path.len())
+                .then(a.creation_time.cmp(&b.creation_time))
+        });
+        cookies
+    }
+
+    /// Returns the cookie string for the given url (as used in the Cookie header)
+    pub fn cookie_string(&self, url: &Url, source: CookieSource) -> String {
+        self.cookies_for(url, source)
+            .iter()
+            .map(|c| {
+                if c.name.is_empty() {
+                    c.value.clone()
+                } else {
+                    format!("{}={}", c.name, c.value)
+                }
+            })
+            .collect::<Vec<_>>()
+            .join("; ")
+    }
+
+    /// Removes all expired cookies from the jar
+    pub fn remove_expired(&mut self) {
+        let now = SystemTime::now();
+        self.cookies.retain(|c| !c.is_expired(now));
+    }
+
+    /// Returns all stored cookies
+    pub fn cookies(&self) -> &[Cookie] {
+        &self.cookies
+    }
+}
+
+impl Document {
+    /// Sets the cookie jar that is used for document.cookie
+    pub fn set_cookie_jar(&mut self, jar: Rc<RefCell<CookieJar>>) {
+        self.cookie_jar = Some(jar);
+    }
+
+    /// Returns the cookies of the document as a cookie string (document.cookie getter). HttpOnly
+    /// cookies are not returned.
+    pub fn cookie(&self) -> String {
+        match (self.cookie_url(), &self.cookie_jar) {
+            (Some(url), Some(jar)) => jar.borrow().cookie_string(url, CookieSource::NonHttp),
+            _ => String::new(),
+        }
+    }
+
+    /// Stores a cookie for the document (document.cookie setter). The value is parsed as a
+    /// set-cookie string.
+    pub fn set_cookie(&self, value: &str) {
+        if let (Some(url), Some(jar)) = (self.cookie_url(), &self.cookie_jar) {
+            jar.borrow_mut()
+                .set_cookie(url, value, CookieSource::NonHttp);
+        }
+    }
+
+    /// Returns the url used for cookies, or None when the document cannot have cookies (for
+    /// instance because it has no url, or is not loaded over http).
+    fn cookie_url(&self) -> Option<&Url> {
+        self.url
+            .as_ref()
+            .filter(|url| url.scheme() == "http" || url.scheme() == "https")
+    }
+}
+
+/// Returns true when the host is the domain, or a subdomain of the domain
+fn domain_matches(host: &str, domain: &str) -> bool {
+    if host == domain {
+        return true;
+    }
+
+    // IP addresses only match exactly
+    if host.parse::<std::net::IpAddr>().is_ok() || host.starts_with('[') {
+        return false;
+    }
+
+    host.ends_with(domain) && host[..host.len() - domain.len()].ends_with('.')
+}
+
+/// Returns true when the request path falls under the cookie path
+fn path_matches(request_path: &str, cookie_path: &str) -> bool {
+    if request_path == cookie_path {
+        return true;
+    }
+
+    request_path.starts_with(cookie_path)
+        && (cookie_path.ends_with('/') || request_path[cookie_path.len()..].starts_with('/'))
+}
+
+/// Returns the default cookie path for the url: the directory of the url path
+fn default_path(url: &Url) -> String {
+    let path = url.path();
+    match path.rfind('/') {
+        Some(0) | None => "/".to_string(),
+        Some(idx) => path[..idx].to_string(),
+    }
+}
+
+/// Parses a cookie date (as found in the Expires attribute)
+///
+/// See: https://httpwg.org/specs/rfc6265.html#cookie-date
+fn parse_cookie_date(value: &str) -> Option<SystemTime> {
+    const MONTHS: [&str; 12] = [
+        "jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec",
+    ];
+
+    let is_delimiter = |c: char| {
+        c == '\t'
+            || (' '..='/').contains(&c)
+            || (';'..='@').contains(&c)
+            || ('['..='`').contains(&c)
+            || ('{'..='~').contains(&c)
+    };
+
+    let mut time = None;
+    let mut day = None;
+    let mut month = None;
+    let mut year = None;
+
+    for token in value.split(is_delimiter).filter(|t| !t.is_empty()) {
+        if time.is_none() {
+            let parts = token.split(':').collect::<Vec<_>>();
+            if parts.len() == 3 {
+                let numbers = parts
+                    .iter()
+                    .map(|p| leading_number(p, 1, 2))
+                    .collect::<Option<Vec<_>>>();
+                if let Some(numbers) = numbers {
+                    time = Some((numbers[0], numbers[1], numbers[2]));
+                    continue;
+                }
+            }
+        }
+        if day.is_none() {
+            if let Some(d) = leading_number(token, 1, 2) {
+                day = Some(d);
+                continue;
+            }
+        }
+        if month.is_none() && token.len() >= 3 {
+            let prefix = token[..3].to_ascii_lowercase();
+            if let Some(m) = MONTHS.iter().position(|m| *m == prefix) {
+                month = Some(m as u64 + 1);
+                continue;
+            }
+        }
+        if year.is_none() {
+            if let Some(y) = leading_number(token, 2, 4) {
+                year = Some(y);
+                continue;
+            }
+        }
+    }
+
+    let (hour, minute, second) = time?;
+    let (day, month, mut year) = (day?, month?, year?);
+    if (70..=99).contains(&year) {
+        year += 1900;
+    } else if year <= 69 {
+        year += 2000;
+    }
+
+    if !(1..=31).contains(&day) || year < 1601 || hour > 23 || minute > 59 || second > 59 {
+        return None;
+    }
+
+    let days = days_from_civil(year, month, day);
+    if days < 0 {
+        return Some(UNIX_EPOCH);
+    }
+    let seconds = days as u64 * 86400 + hour * 3600 + minute * 60 + second;
+    UNIX_EPOCH.checked_add(Duration::from_secs(seconds))
+}
+
+/// Parses a number of min..=max digits at the start of the token. Trailing non-digits are allowed.
+fn leading_number(token: &str, min: usize, max: usize) -> Option<u64> {
+    let digits = token.chars().take_while(|c| c.is_ascii_digit()).count();
+    if digits < min || digits > max {
+        return None;
+    }
+    token[..digits].parse().ok()
+}
+
+/// Returns the number of days since 1970-01-01 for the given date
+fn days_from_civil(year: u64, month: u64, day: u64) -> i64 {
+    let (year, month, day) = (year as i64, month as i64, day as i64);
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 719468
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::html5::parser::document::DocumentBuilder;
+
+    fn url(url: &str) -> Url {
+        Url::parse(url).unwrap()
+    }
+
+    #[test]
+    fn set_and_get_cookies() {
+        let mut jar = CookieJar::new();
+        let site = url("https://www.example.com/shop/cart");
+
+        assert!(jar.set_cookie(&site, "session=abc; HttpOnly", CookieSource::Http));
+        assert!(jar.set_cookie(&site, "theme=dark; Path=/", CookieSource::Http));
+        assert!(jar.set_cookie(&site, "cart=3", CookieSource::Http));
+        assert!(jar.set_cookie(
+            &site,
+            "shared=1; Domain=.example.com; Path=/",
+            CookieSource::Http
+        ));
+
+        assert_eq!(
+            jar.cookie_string(&site, CookieSource::Http),
+            "session=abc; cart=3; theme=dark; shared=1"
+        );
+        assert_eq!(
+            jar.cookie_string(&site, CookieSource::NonHttp),
+            "cart=3; theme=dark; shared=1"
+        );
+
+        // Host only cookies are not sent to other subdomains, and path cookies not to other paths
+        let other = url("https://api.example.com/shop/cart");
+        assert_eq!(jar.cookie_string(&other, CookieSource::Http), "shared=1");
+        let root = url("https://www.example.com/");
+        assert_eq!(
+            jar.cookie_string(&root, CookieSource::Http),
+            "theme=dark; shared=1"
+        );
+    }
+
+    #[test]
+    fn reject_invalid_cookies() {
+        let mut jar = CookieJar::new();
+        let site = url("http://www.example.com/");
+
+        assert!(!jar.set_cookie(&site, "a=1; Domain=other.com", CookieSource::Http));
+        assert!(!jar.set_cookie(&site, "a=1; Domain=com", CookieSource::Http));
+        assert!(!jar.set_cookie(&site, "a=1; Secure", CookieSource::Http));
+        assert!(!jar.set_cookie(&site, "a=1; HttpOnly", CookieSource::NonHttp));
+        assert!(!jar.set_cookie(&site, "", CookieSource::Http));
+        assert!(jar.cookies().is_empty());
+    }
+
+    #[test]
+    fn expire_cookies() {
+        let mut jar = CookieJar::new();
+        let site = url("https://example.com/");
+
+        jar.set_cookie(&site, "a=1; Max-Age=3600", CookieSource::Http);
+        jar.set_cookie(
+            &site,
+            "b=1; Expires=Wed, 21 Oct 2099 07:28:00 GMT",
+            CookieSource::Http,
+        );
+        jar.set_cookie(
+            &site,
+            "c=1; Expires=Thu, 01 Jan 1970 00:00:00 GMT",
+            CookieSource::Http,
+        );
+        assert_eq!(jar.cookie_string(&site, CookieSource::Http), "a=1; b=1");
+
+        // Deleting a cookie is done by setting it with an expiry date in the past
+        jar.set_cookie(&site, "a=; Max-Age=0", CookieSource::Http);
+        assert_eq!(jar.cookie_string(&site, CookieSource::Http), "b=1");
+    }
+
+    #[test]
+    fn cookie_dates() {
+        let date = parse_cookie_date("Wed, 21 Oct 2015 07:28:00 GMT").unwrap();
+        assert_eq!(
+            date.duration_since(UNIX_EPOCH).unwrap().as_secs(),
+            1445412480
+        );
+        let date = parse_cookie_date("Sunday, 06-Nov-94 08:49:37 GMT").unwrap();
+        assert_eq!(
+            date.duration_since(UNIX_EPOCH).unwrap().as_secs(),
+            784111777
+        );
+        assert!(parse_cookie_date("not a date").is_none());
+        assert!(parse_cookie_date("Wed, 32 Oct 2015 07:28:00 GMT").is_none());
+    }
+
+    #[test]
+    fn document_cookie() {
+        let jar = Rc::new(RefCell::new(CookieJar::new()));
+        let site = url("https://example.com/index.html");
+        jar.borrow_mut()
+            .set_cookie(&site, "session=abc; HttpOnly", CookieSource::Http);
+
+        let mut document = DocumentBuilder::new_document();
+        document.get_mut().set_cookie_jar(jar.clone());
+        assert_eq!(document.get().cookie(), "");
+
+        document.get_mut().url = Some(site.clone());
+        document.get().set_cookie("visited=yes");
+        document.get().set_cookie("session=hijacked");
+        assert_eq!(document.get().cookie(), "visited=yes");
+        assert_eq!(
+            jar.borrow().cookie_string(&site, CookieSource::Http),
+            "session=abc; visited=yes"
+        );
+    }
+}
@@ -0,0 +1,659 @@
+//! Content Security Policy
+//!
+//! A content security policy restricts the resources a document can load and the scripts it can
+//! run, as a defense against cross-site scripting. Policies are delivered in the
+//! `Content-Security-Policy` header of the response (see
+//! `Document::add_content_security_policy()`), or in a `<meta http-equiv>` element in the head of
+//! the document, which the parser picks up.
+//!
+//! The `script-src`, `img-src`, `style-src` and `connect-src` directives are enforced, falling
+//! back to `default-src`. Requests are checked by `Document::check_request()` before they are
+//! fetched, and scripts are checked by the parser before they are handed to the script handler.
+//! Violations are reported to the diagnostics sink of the document. Report-only policies report
+//! violations, but do not block anything.
+//!
+//! Hash sources (like `'sha256-...'`) are not supported and never match.
+//!
+//! See: https://w3c.github.io/webappsec-csp/
+use crate::html5::node::NodeId;
+use crate::html5::parser::document::Document;
+use crate::net::origin::{Origin, RequestMode, ResponseTainting};
+use crate::types::{Error, Result};
+use core::fmt;
+use core::fmt::Debug;
+use std::cell::RefCell;
+use std::rc::Rc;
+use url::Url;
+
+/// Kind of resource, which decides the directive that restricts it
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ResourceKind {
+    Script,
+    Image,
+    Style,
+    /// Requests made by scripts (fetch, websockets, event sources)
+    Connect,
+}
+
+impl ResourceKind {
+    /// Returns the name of the directive that restricts the resource
+    pub fn directive(&self) -> &'static str {
+        match self {
+            ResourceKind::Script => "script-src",
+            ResourceKind::Image => "img-src",
+            ResourceKind::Style => "style-src",
+            ResourceKind::Connect => "connect-src",
+        }
+    }
+}
+
+/// Defines what happens when a policy is violated
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Disposition {
+    /// The resource is blocked and the violation is reported (`Content-Security-Policy`)
+    Enforce,
+    /// The violation is only reported (`Content-Security-Policy-Report-Only`)
+    Report,
+}
+
+/// A source expression in the source list of a directive
+#[derive(Debug, Clone, PartialEq)]
+enum Source {
+    /// `*`, which matches any network URL
+    Any,
+    /// `'self'`
+    SelfOrigin,
+    /// `'unsafe-inline'`
+    UnsafeInline,
+    /// `'nonce-...'`
+    Nonce(String),
+    /// Other keywords (like `'unsafe-eval'` and hashes), which never match a URL
+    Keyword(String),
+    /// Scheme source like `https:`
+    Scheme(String),
+    /// Host source like `https://*.example.com:8080/path/`
+    Host {
+        scheme: Option<String>,
+        host: String,
+        port: Option<String>,
+        path: Option<String>,
+    },
+}
+
+impl Source {
+    fn parse(expression: &str) -> Source {
+        if expression == "*" {
+            return Source::Any;
+        }
+
+        if expression.starts_with('\'') {
+            let keyword = expression.to_ascii_lowercase();
+            return match keyword.as_str() {
+                "'self'" => Source::SelfOrigin,
+                "'unsafe-inline'" => Source::UnsafeInline,
+                // Nonces are case-sensitive
+                _ if keyword.starts_with("'nonce-") && keyword.ends_with('\'') => {
+                    Source::Nonce(expression["'nonce-".len()..expression.len() - 1].to_string())
+                }
+                _ => Source::Keyword(keyword),
+            };
+        }
+
+        if let Some(scheme) = expression.strip_suffix(':') {
+            return Source::Scheme(scheme.to_ascii_lowercase());
+        }
+
+        let (scheme, rest) = match expression.split_once("://") {
+            Some((scheme, rest)) => (Some(scheme.to_ascii_lowercase()), rest),
+            None => (None, expression),
+        };
+        let (authority, path) = match rest.find('/') {
+            Some(index) => (&rest[..index], Some(rest[index..].to_string())),
+            None => (rest, None),
+        };
+        let (host, port) = match authority.split_once(':') {
+            Some((host, port)) => (host, Some(port.to_string())),
+            None => (authority, None),
+        };
+
+        Source::Host {
+            scheme,
+            host: host.to_ascii_lowercase(),
+            port,
+            path,
+        }
+    }
+
+    /// Returns true when the source matches the URL, for a document with the given origin
+    fn matches_url(&self, url: &Url, origin: &Origin) -> bool {
+        match self {
+            Source::Any => is_network_scheme(url.scheme()),
+            Source::SelfOrigin => origin.same_origin(&Origin::from_url(url)),
+            Source::Scheme(scheme) => scheme_matches(scheme, url.scheme()),
+            Source::Host {
+                scheme,
+                host,
+                port,
+                path,
+            } => {
+                // Without a scheme, the scheme of the document is used, so https pages are not
+                // downgraded to http
+                let scheme_matches = match (scheme, origin.scheme()) {
+                    (Some(scheme), _) => scheme_matches(scheme, url.scheme()),
+                    (None, Some(origin_scheme)) => scheme_matches(origin_scheme, url.scheme()),
+                    (None, None) => is_network_scheme(url.scheme()),
+                };
+                let Some(url_host) = url.host_str() else {
+                    return false;
+                };
+                let host_matches = match host.strip_prefix("*.") {
+                    Some(domain) => url_host
+                        .strip_suffix(domain)
+                        .is_some_and(|sub| sub.ends_with('.')),
+                    None => url_host == host,
+                };
+                // The url crate leaves out the port when it is the default port of the scheme
+                let port_matches = match port.as_deref() {
+                    Some("*") => true,
+                    Some(port) => port.parse::<u16>().ok() == url.port_or_known_default(),
+                    None => url.port().is_none(),
+                };
+                let path_matches = match path {
+                    Some(path) if path.ends_with('/') => url.path().starts_with(path.as_str()),
+                    Some(path) => url.path() == path,
+                    None => true,
+                };
+                scheme_matches && host_matches && port_matches && path_matches
+            }
+            Source::UnsafeInline | Source::Nonce(_) | Source::Keyword(_) => false,
+        }
+    }
+}
+
+fn is_network_scheme(scheme: &str) -> bool {
+    matches!(scheme, "http" | "https" | "ws" | "wss")
+}
+
+/// Returns true when the scheme of a source matches the scheme of a URL. Insecure schemes also
+/// match their secure variants.
+fn scheme_matches(source: &str, url: &str) -> bool {
+    source == url
+        || matches!(
+            (source, url),
+            ("http", "https") | ("ws", "wss" | "http" | "https") | ("wss", "https")
+        )
+}
+
+/// Source list of a directive
+#[derive(Debug, Clone, PartialEq)]
+struct SourceList(Vec<Source>);
+
+impl SourceList {
+    /// Parses the source expressions. A list with only `'none'` matches nothing.
+    fn parse<'a>(expressions: impl Iterator<Item = &'a str>) -> SourceList {
+        SourceList(
+            expressions
+                .filter(|expression| !expression.eq_ignore_ascii_case("'none'"))
+                .map(Source::parse)
+                .collect(),
+        )
+    }
+
+    fn allows_url(&self, url: &Url, origin: &Origin) -> bool {
+        self.0.iter().any(|source| source.matches_url(url, origin))
+    }
+
+    /// Returns true when an inline script or style with the given nonce is allowed. Nonces (and
+    /// hashes) disable `'unsafe-inline'`.
+    fn allows_inline(&self, nonce: Option<&str>) -> bool {
+        let mut unsafe_inline = false;
+        let mut nonce_or_hash = false;
+        for source in &self.0 {
+            match source {
+                Source::Nonce(value) if !value.is_empty() && nonce == Some(value.as_str()) => {
+                    return true
+                }
+                Source::Nonce(_) => nonce_or_hash = true,
+                Source::Keyword(keyword) if keyword.starts_with("'sha") => nonce_or_hash = true,
+                Source::UnsafeInline => unsafe_inline = true,
+                _ => {}
+            }
+        }
+        unsafe_inline && !nonce_or_hash
+    }
+}
+
+/// A single policy, as found in a header or meta element
+#[derive(Debug, Clone, PartialEq)]
+pub struct Policy {
+    pub disposition: Disposition,
+    /// The serialized policy, as it was delivered
+    pub text: String,
+    /// Directives by (lowercase) name, in the order they were found
+    directives: Vec<(String, SourceList)>,
+}
+
+impl Policy {
+    /// Parses a single serialized policy. Directives that are defined more than once only count
+    /// the first time.
+    pub fn parse(text: &str, disposition: Disposition) -> Policy {
+        let mut directives: Vec<(String, SourceList)> = Vec::new();
+        for directive in text.split(';') {
+            let mut tokens = directive.split_ascii_whitespace();
+            let Some(name) = tokens.next() else {
+                continue;
+            };
+            let name = name.to_ascii_lowercase();
+            if directives.iter().any(|(existing, _)| *existing == name) {
+                continue;
+            }
+            directives.push((name, SourceList::parse(tokens)));
+        }
+
+        Policy {
+            disposition,
+            text: text.trim().to_string(),
+            directives,
+        }
+    }
+
+    /// Returns true when the policy has a directive with the given name
+    pub fn has_directive(&self, name: &str) -> bool {
+        self.directive(name).is_some()
+    }
+
+    fn directive(&self, name: &str) -> Option<&SourceList> {
+        self.directives
+            .iter()
+            .find(|(directive, _)| directive == name)
+            .map(|(_, sources)| sources)
+    }
+
+    /// Returns the directive that applies to the kind of resource, with its name
+    fn effective_directive(&self, kind: ResourceKind) -> Option<(&'static str, &SourceList)> {
+        [kind.directive(), "default-src"]
+            .into_iter()
+            .find_map(|name| self.directive(name).map(|sources| (name, sources)))
+    }
+}
+
+/// All policies of a document. A resource must be allowed by every enforced policy.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ContentSecurityPolicy {
+    policies: Vec<Policy>,
+}
+
+impl ContentSecurityPolicy {
+    /// Adds the policies of a header value, which can contain multiple policies separated by
+    /// commas
+    pub fn add(&mut self, header: &str, disposition: Disposition) {
+        self.policies.extend(
+            header
+                .split(',')
+                .filter(|text| !text.trim().is_empty())
+                .map(|text| Policy::parse(text, disposition)),
+        );
+    }
+
+    pub fn policies(&self) -> &[Policy] {
+        &self.policies
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.policies.is_empty()
+    }
+
+    /// Checks the resource against every policy with the allows function. Returns whether the
+    /// resource is allowed, and the violations.
+    fn check(
+        &self,
+        kind: ResourceKind,
+        blocked_uri: &str,
+        node_id: Option<NodeId>,
+        allows: impl Fn(&SourceList) -> bool,
+    ) -> (bool, Vec<Violation>) {
+        let mut allowed = true;
+        let mut violations = Vec::new();
+        for policy in &self.policies {
+            let Some((name, sources)) = policy.effective_directive(kind) else {
+                continue;
+            };
+            if allows(sources) {
+                continue;
+            }
+
+            if policy.disposition == Disposition::Enforce {
+                allowed = false;
+            }
+            violations.push(Violation {
+                effective_directive: kind.directive(),
+                violated_directive: name,
+                blocked_uri: blocked_uri.to_string(),
+                node_id,
+                disposition: policy.disposition,
+                policy: policy.text.clone(),
+            });
+        }
+        (allowed, violations)
+    }
+}
+
+/// A resource that was not allowed by a policy
+#[derive(Debug, Clone, PartialEq)]
+pub struct Violation {
+    /// Directive for the kind of resource (like `script-src`)
+    pub effective_directive: &'static str,
+    /// Directive that did not allow the resource, which is `default-src` when the policy has no
+    /// directive for the kind of resource
+    pub violated_directive: &'static str,
+    /// URL of the resource, or `inline` for inline scripts and styles
+    pub blocked_uri: String,
+    /// Element that caused the violation (if any)
+    pub node_id: Option<NodeId>,
+    pub disposition: Disposition,
+    /// The violated policy
+    pub policy: String,
+}
+
+/// Receives the diagnostics of a document, implemented by the embedder (like a developer console)
+pub trait DiagnosticsSink {
+    /// Called when a resource violates a content security policy
+    fn report_csp_violation(&mut self, violation: &Violation);
+}
+
+/// Diagnostics sink of a document, if the embedder provided one
+#[derive(Default)]
+pub(crate) struct DiagnosticsSlot(Option<Rc<RefCell<dyn DiagnosticsSink>>>);
+
+impl Debug for DiagnosticsSlot {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "DiagnosticsSlot({})", self.0.is_some())
+    }
+}
+
+impl PartialEq for DiagnosticsSlot {
+    fn eq(&self, other: &Self) -> bool {
+        match (&self.0, &other.0) {
+            (Some(a), Some(b)) => std::ptr::eq(Rc::as_ptr(a).cast::<()>(), Rc::as_ptr(b).cast()),
+            (a, b) => a.is_none() && b.is_none(),
+        }
+    }
+}
+
+impl Document {
+    /// Adds the policies of a `Content-Security-Policy` (or `-Report-Only`) response header
+    pub fn add_content_security_policy(&mut self, header: &str, disposition: Disposition) {
+        self.csp.add(header, disposition);
+    }
+
+    pub fn content_security_policy(&self) -> &ContentSecurityPolicy {
+        &self.csp
+    }
+
+    /// Sets the sink that receives the diagnostics of the document
+    pub fn set_diagnostics_sink(&mut self, sink: Rc<RefCell<dyn DiagnosticsSink>>) {
+        self.diagnostics = DiagnosticsSlot(Some(sink));
+    }
+
+    /// Returns true when the content security policy allows loading the resource. Violations are
+    /// reported to the diagnostics sink.
+    pub fn allows_resource(&self, url: &Url, kind: ResourceKind) -> bool {
+        let (allowed, violations) = self.csp.check(kind, url.as_str(), None, |sources| {
+            sources.allows_url(url, &self.origin)
+        });
+        self.report_violations(&violations);
+        allowed
+    }
+
+    /// Returns true when the content security policy allows the inline script or style element
+    /// (or attribute) to run. Violations are reported to the diagnostics sink.
+    pub fn allows_inline(&self, kind: ResourceKind, node_id: NodeId) -> bool {
+        let nonce = self
+            .get_node_by_id(node_id)
+            .and_then(|node| node.get_attribute("nonce"))
+            .map(|nonce| nonce.as_str());
+        let (allowed, violations) = self.csp.check(kind, "inline", Some(node_id), |sources| {
+            sources.allows_inline(nonce)
+        });
+        self.report_violations(&violations);
+        allowed
+    }
+
+    /// Checks a request for a resource made by the document, against both the content security
+    /// policy and the origin of the document. See `Origin::check_fetch()`.
+    pub fn check_request(
+        &self,
+        url: &Url,
+        kind: ResourceKind,
+        mode: RequestMode,
+    ) -> Result<ResponseTainting> {
+        if !self.allows_resource(url, kind) {
+            return Err(Error::Security(format!(
+                "{} to {} is blocked by the content security policy",
+                kind.directive(),
+                url
+            )));
+        }
+        self.check_fetch(url, mode)
+    }
+
+    /// Adds the policy of a `<meta http-equiv="Content-Security-Policy">` element. Only meta
+    /// elements in the head of the document define a policy.
+    pub(crate) fn apply_meta_content_security_policy(&mut self, node_id: NodeId) {
+        let Some(node) = self.get_node_by_id(node_id) else {
+            return;
+        };
+        let in_head = node
+            .parent
+            .and_then(|parent| self.get_node_by_id(parent))
+            .is_some_and(|parent| parent.name == "head");
+        let is_csp = node
+            .get_attribute("http-equiv")
+            .is_some_and(|value| value.trim().eq_ignore_ascii_case("content-security-policy"));
+        if !in_head || !is_csp {
+            return;
+        }
+
+        if let Some(content) = node.get_attribute("content").cloned() {
+            self.csp.add(&content, Disposition::Enforce);
+        }
+    }
+
+    fn report_violations(&self, violations: &[Violation]) {
+        if let Some(sink) = &self.diagnostics.0 {
+            for violation in violations {
+                sink.borrow_mut().report_csp_violation(violation);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bytes::{CharIterator, Encoding};
+    use crate::html5::node::HTML_NAMESPACE;
+    use crate::html5::parser::document::{DocumentBuilder, DocumentHandle};
+    use crate::html5::parser::script::{Script, ScriptHandler, ScriptSource};
+    use crate::html5::parser::tree_builder::TreeBuilder;
+    use crate::html5::parser::{Html5Parser, Html5ParserOptions};
+
+    #[derive(Default)]
+    struct Recorder {
+        violations: Vec<Violation>,
+        executed: Vec<String>,
+    }
+
+    impl DiagnosticsSink for Recorder {
+        fn report_csp_violation(&mut self, violation: &Violation) {
+            self.violations.push(violation.clone());
+        }
+    }
+
+    impl ScriptHandler for Recorder {
+        fn execute(&mut self, _document: &DocumentHandle, script: &Script) {
+            self.executed.push(match &script.source {
+                ScriptSource::Inline(text) => text.clone(),
+                ScriptSource::External { src, .. } => src.clone(),
+            });
+        }
+    }
+
+    fn document(url: &str, policy: &str, disposition: Disposition) -> DocumentHandle {
+        let mut document = DocumentBuilder::new_document();
+        let url = Url::parse(url).unwrap();
+        document.get_mut().set_origin(Origin::from_url(&url));
+        document.get_mut().url = Some(url);
+        document
+            .get_mut()
+            .add_content_security_policy(policy, disposition);
+        document
+    }
+
+    fn url(url: &str) -> Url {
+        Url::parse(url).unwrap()
+    }
+
+    #[test]
+    fn source_matching() {
+        let document = document(
+            "https://example.com/",
+            "default-src 'self'; img-src * data:; script-src https://*.cdn.com:8443 \
+             http://example.org/lib/ https://example.net/app.js; connect-src 'none'",
+            Disposition::Enforce,
+        );
+        let doc = document.get();
+        let allows = |url_str: &str, kind| doc.allows_resource(&url(url_str), kind);
+
+        // style-src falls back to default-src
+        assert!(allows("https://example.com/style.css", ResourceKind::Style));
+        assert!(!allows("https://evil.com/style.css", ResourceKind::Style));
+
+        assert!(allows("https://evil.com/cat.png", ResourceKind::Image));
+        assert!(allows("data:image/png;base64,AAAA", ResourceKind::Image));
+        assert!(!allows("blob:https://example.com/1", ResourceKind::Image));
+
+        assert!(allows("https://a.cdn.com:8443/x.js", ResourceKind::Script));
+        assert!(!allows("https://cdn.com:8443/x.js", ResourceKind::Script));
+        assert!(!allows("https://a.cdn.com/x.js", ResourceKind::Script));
+        assert!(allows("https://example.org/lib/x.js", ResourceKind::Script));
+        assert!(!allows(
+            "https://example.org/other/x.js",
+            ResourceKind::Script
+        ));
+        assert!(allows("https://example.net/app.js", ResourceKind::Script));
+        assert!(!allows(
+            "https://example.net/app.js.map",
+            ResourceKind::Script
+        ));
+        assert!(!allows("https://example.com/self.js", ResourceKind::Script));
+
+        assert!(doc
+            .check_request(
+                &url("https://example.com/api"),
+                ResourceKind::Connect,
+                RequestMode::Cors
+            )
+            .is_err());
+    }
+
+    #[test]
+    fn sources_without_scheme() {
+        let policy = "script-src cdn.example.com";
+        let https = document("https://example.com/", policy, Disposition::Enforce);
+        let http = document("http://example.com/", policy, Disposition::Enforce);
+        let allows = |document: &DocumentHandle, scheme: &str| {
+            let script = url(&format!("{}://cdn.example.com/x.js", scheme));
+            document
+                .get()
+                .allows_resource(&script, ResourceKind::Script)
+        };
+
+        // The scheme of the page is used, which can be upgraded but not downgraded
+        assert!(allows(&https, "https"));
+        assert!(!allows(&https, "http"));
+        assert!(allows(&http, "http"));
+        assert!(allows(&http, "https"));
+        assert!(!allows(&http, "ftp"));
+    }
+
+    #[test]
+    fn inline_and_report_only() {
+        let sink = Rc::new(RefCell::new(Recorder::default()));
+        let mut document = document(
+            "https://example.com/",
+            "script-src 'unsafe-inline' 'nonce-r4nd0m', style-src 'unsafe-inline'",
+            Disposition::Enforce,
+        );
+        document
+            .get_mut()
+            .add_content_security_policy("style-src 'none'", Disposition::Report);
+        document.get_mut().set_diagnostics_sink(sink.clone());
+
+        let root = document.get().get_root().id;
+        let script = document
+            .create_element("script", root, None, HTML_NAMESPACE)
+            .unwrap();
+
+        // The nonce disables 'unsafe-inline'
+        assert!(!document.get().allows_inline(ResourceKind::Script, script));
+        document
+            .insert_attribute("nonce", "r4nd0m", script)
+            .unwrap();
+        assert!(document.get().allows_inline(ResourceKind::Script, script));
+
+        // Report-only policies do not block
+        assert!(document.get().allows_inline(ResourceKind::Style, script));
+
+        let sink = sink.borrow();
+        let violations = sink
+            .violations
+            .iter()
+            .map(|v| (v.effective_directive, v.blocked_uri.as_str(), v.disposition))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            violations,
+            [
+                ("script-src", "inline", Disposition::Enforce),
+                ("style-src", "inline", Disposition::Report),
+            ]
+        );
+    }
+
+    #[test]
+    fn meta_policy_blocks_scripts() {
+        let recorder = Rc::new(RefCell::new(Recorder::default()));
+        let mut document = DocumentBuilder::new_document();
+        let base = url("https://example.com/");
+        document.get_mut().set_origin(Origin::from_url(&base));
+        document.get_mut().url = Some(base);
+        document.get_mut().set_diagnostics_sink(recorder.clone());
+
+        let mut chars = CharIterator::new();
+        chars.read_from_str(
+            r#"<head><meta http-equiv="Content-Security-Policy" content="script-src 'self'">
+            <script src="/app.js"></script><script src="https://evil.com/x.js"></script>
+            <script>inline()</script></head><body><meta http-equiv="content-security-policy"
+            content="script-src 'none'"><script src="/late.js"></script>"#,
+            Some(Encoding::UTF8),
+        );
+        let options = Html5ParserOptions {
+            scripting_enabled: true,
+            script_handler: Some(recorder.clone()),
+            ..Default::default()
+        };
+        let _ = Html5Parser::parse_document(&mut chars, Document::clone(&document), Some(options));
+
+        // The meta element in the body is ignored
+        assert_eq!(document.get().content_security_policy().policies().len(), 1);
+
+        let recorder = recorder.borrow();
+        assert_eq!(recorder.executed, ["/app.js", "/late.js"]);
+        let blocked = recorder
+            .violations
+            .iter()
+            .map(|v| v.blocked_uri.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(blocked, ["https://evil.com/x.js", "inline"]);
+    }
+}
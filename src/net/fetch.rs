@@ -0,0 +1,430 @@
+//! Fetching
+//!
+//! The fetcher turns the resources a document needs (and the navigations of browsing contexts)
+//! into requests, and performs them. Requests for subresources are checked against the content
+//! security policy and origin of the document first, and every request gets the referrer its
+//! referrer policy allows, together with the default headers the embedder configured (like
+//! `User-Agent` and `Accept-Language`).
+//!
+//! In crawler mode, the fetcher also fetches the robots.txt of every origin it visits, and
+//! refuses or delays requests that robots.txt disallows or that come too soon after the previous
+//! request to the origin.
+//!
+//! See: https://fetch.spec.whatwg.org/
+use crate::browsing_context::navigation::NavigationRequest;
+use crate::html5::node::NodeId;
+use crate::html5::parser::document::Document;
+use crate::net::csp::ResourceKind;
+use crate::net::origin::{Origin, RequestMode, ResponseTainting};
+use crate::net::robots::{CrawlDecision, Crawler, RobotsTxt};
+use crate::types::{Error, Result};
+use std::io::Read;
+use std::time::Instant;
+use url::Url;
+
+/// User agent that is sent when the embedder does not configure one
+pub const DEFAULT_USER_AGENT: &str = concat!("gosub/", env!("CARGO_PKG_VERSION"));
+
+/// A request that is ready to be fetched
+#[derive(Debug, Clone, PartialEq)]
+pub struct Request {
+    pub url: Url,
+    pub mode: RequestMode,
+    /// Referrer as sent in the `Referer` header (if any)
+    pub referrer: Option<Url>,
+    /// How the response can be exposed to the document
+    pub tainting: ResponseTainting,
+    /// All headers of the request, including the default headers of the fetcher
+    pub headers: Vec<(String, String)>,
+}
+
+impl Request {
+    /// Returns the value of the header (case-insensitive)
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(header, _)| header.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+/// A fetched response
+#[derive(Debug, Clone, PartialEq)]
+pub struct Response {
+    /// Final URL of the response, after redirects
+    pub url: Url,
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+/// Creates and performs requests
+#[derive(Debug, Clone, PartialEq)]
+pub struct Fetcher {
+    /// Headers that are sent with every request
+    default_headers: Vec<(String, String)>,
+    /// Robots.txt state when crawler mode is enabled
+    crawler: Option<Crawler>,
+}
+
+impl Default for Fetcher {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Fetcher {
+    /// Creates a fetcher with the default user agent and `en` as accepted language
+    pub fn new() -> Self {
+        Self {
+            default_headers: vec![
+                ("User-Agent".to_string(), DEFAULT_USER_AGENT.to_string()),
+                ("Accept-Language".to_string(), "en".to_string()),
+            ],
+            crawler: None,
+        }
+    }
+
+    /// Returns the headers that are sent with every request
+    pub fn default_headers(&self) -> &[(String, String)] {
+        &self.default_headers
+    }
+
+    /// Sets a header that is sent with every request, replacing the existing value (if any)
+    pub fn set_default_header(&mut self, name: &str, value: &str) {
+        self.remove_default_header(name);
+        self.default_headers
+            .push((name.to_string(), value.to_string()));
+    }
+
+    /// Stops sending the header with every request
+    pub fn remove_default_header(&mut self, name: &str) {
+        self.default_headers
+            .retain(|(header, _)| !header.eq_ignore_ascii_case(name));
+    }
+
+    pub fn set_user_agent(&mut self, user_agent: &str) {
+        self.set_default_header("User-Agent", user_agent);
+    }
+
+    /// Sets the accepted languages, in order of preference (e.g. `nl-NL, nl;q=0.9, en;q=0.8`)
+    pub fn set_accept_language(&mut self, languages: &str) {
+        self.set_default_header("Accept-Language", languages);
+    }
+
+    /// Creates the request for a subresource of the document, optionally on behalf of an element
+    /// (whose `referrerpolicy` and `rel` attributes are taken into account). Fails when the
+    /// content security policy or the origin of the document does not allow the request.
+    pub fn subresource_request(
+        &self,
+        document: &Document,
+        url: &Url,
+        kind: ResourceKind,
+        mode: RequestMode,
+        node_id: Option<NodeId>,
+    ) -> Result<Request> {
+        let tainting = document.check_request(url, kind, mode)?;
+        let referrer = document.referrer_for(url, node_id);
+
+        let mut headers = self.default_headers.clone();
+        if tainting == ResponseTainting::Cors {
+            headers.push(("Origin".to_string(), document.origin().serialize()));
+        }
+
+        Ok(self.request(url.clone(), mode, referrer, tainting, headers))
+    }
+
+    /// Creates the request for a navigation. The referrer of the navigation is reduced according
+    /// to its referrer policy, for the final URL of the navigation.
+    pub fn navigation_request(&self, navigation: &NavigationRequest) -> Request {
+        let referrer = navigation.referrer.as_ref().and_then(|referrer| {
+            navigation
+                .referrer_policy
+                .referrer(referrer, &navigation.url)
+        });
+
+        self.request(
+            navigation.url.clone(),
+            RequestMode::Navigate,
+            referrer,
+            ResponseTainting::Basic,
+            self.default_headers.clone(),
+        )
+    }
+
+    fn request(
+        &self,
+        url: Url,
+        mode: RequestMode,
+        referrer: Option<Url>,
+        tainting: ResponseTainting,
+        mut headers: Vec<(String, String)>,
+    ) -> Request {
+        if let Some(referrer) = &referrer {
+            headers.push(("Referer".to_string(), referrer.to_string()));
+        }
+        Request {
+            url,
+            mode,
+            referrer,
+            tainting,
+            headers,
+        }
+    }
+
+    /// Performs the request (blocking), following redirects. Responses with an error status are
+    /// returned as well; only network errors fail.
+    pub fn fetch(&self, request: &Request) -> Result<Response> {
+        let mut call = ureq::get(request.url.as_str());
+        for (name, value) in &request.headers {
+            call = call.set(name, value);
+        }
+
+        let response = match call.call() {
+            Ok(response) => response,
+            Err(ureq::Error::Status(_, response)) => response,
+            Err(error) => return Err(Box::new(error).into()),
+        };
+
+        let url = Url::parse(response.get_url()).unwrap_or_else(|_| request.url.clone());
+        let status = response.status();
+        let headers = response
+            .headers_names()
+            .into_iter()
+            .filter_map(|name| {
+                let value = response.header(&name)?.to_string();
+                Some((name, value))
+            })
+            .collect();
+
+        let mut body = Vec::new();
+        response.into_reader().read_to_end(&mut body)?;
+
+        Ok(Response {
+            url,
+            status,
+            headers,
+            body,
+        })
+    }
+
+    /// Enables crawler mode, where robots.txt rules for the user agent token (e.g. `gosub`) are
+    /// honoured by `crawl()`
+    pub fn enable_crawler_mode(&mut self, user_agent: &str) {
+        self.crawler = Some(Crawler::new(user_agent));
+    }
+
+    pub fn disable_crawler_mode(&mut self) {
+        self.crawler = None;
+    }
+
+    /// Returns the crawler state, when crawler mode is enabled
+    pub fn crawler(&self) -> Option<&Crawler> {
+        self.crawler.as_ref()
+    }
+
+    pub fn crawler_mut(&mut self) -> Option<&mut Crawler> {
+        self.crawler.as_mut()
+    }
+
+    /// Returns what the crawler should do with a request for the URL right now, fetching the
+    /// robots.txt of its origin first when it is not known yet. Everything is allowed when
+    /// crawler mode is disabled.
+    pub fn crawl_decision(&mut self, url: &Url) -> CrawlDecision {
+        let Some(crawler) = &self.crawler else {
+            return CrawlDecision::Allow;
+        };
+        if let Some(decision) = crawler.decide(url, Instant::now()) {
+            return decision;
+        }
+
+        let robots = match self.fetch_robots_txt(url) {
+            Some(robots) => robots,
+            // The site is unreachable: do not crawl it now, but try again next time
+            None => return CrawlDecision::Disallow,
+        };
+
+        let Some(crawler) = &mut self.crawler else {
+            return CrawlDecision::Allow;
+        };
+        crawler.set_robots_txt(Origin::from_url(url), robots);
+        crawler
+            .decide(url, Instant::now())
+            .unwrap_or(CrawlDecision::Allow)
+    }
+
+    /// Performs the request like `fetch()`, but in crawler mode the request is refused when
+    /// robots.txt disallows it, and delayed until the crawl delay of its origin has passed
+    pub fn crawl(&mut self, request: &Request) -> Result<Response> {
+        match self.crawl_decision(&request.url) {
+            CrawlDecision::Allow => {}
+            CrawlDecision::Wait(delay) => std::thread::sleep(delay),
+            CrawlDecision::Disallow => return Err(Error::Robots(request.url.to_string())),
+        }
+
+        if let Some(crawler) = &mut self.crawler {
+            crawler.record_request(&request.url, Instant::now());
+        }
+        self.fetch(request)
+    }
+
+    /// Fetches the robots.txt for the origin of the URL. Missing files allow everything, and
+    /// server errors disallow everything. Returns None when the site cannot be reached.
+    fn fetch_robots_txt(&self, url: &Url) -> Option<RobotsTxt> {
+        let robots_url = url.join("/robots.txt").ok()?;
+        let request = self.request(
+            robots_url,
+            RequestMode::NoCors,
+            None,
+            ResponseTainting::Basic,
+            self.default_headers.clone(),
+        );
+        let response = self.fetch(&request).ok()?;
+
+        Some(match response.status {
+            200..=299 => RobotsTxt::parse(&String::from_utf8_lossy(&response.body)),
+            400..=499 => RobotsTxt::allow_all(),
+            _ => RobotsTxt::disallow_all(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::browsing_context::navigation::NavigationCause;
+    use crate::browsing_context::FrameTree;
+    use crate::bytes::{CharIterator, Encoding};
+    use crate::event_loop::EventLoopHandle;
+    use crate::html5::parser::document::{DocumentBuilder, DocumentHandle};
+    use crate::html5::parser::Html5Parser;
+    use crate::net::origin::Origin;
+    use crate::net::referrer::ReferrerPolicy;
+
+    fn load(url: &str, html: &str) -> DocumentHandle {
+        let mut chars = CharIterator::new();
+        chars.read_from_str(html, Some(Encoding::UTF8));
+
+        let mut document = DocumentBuilder::new_document();
+        let url = Url::parse(url).unwrap();
+        document.get_mut().set_origin(Origin::from_url(&url));
+        document.get_mut().url = Some(url);
+        let _ = Html5Parser::parse_document(&mut chars, Document::clone(&document), None);
+        document
+    }
+
+    #[test]
+    fn subresource_requests() {
+        let document = load(
+            "https://example.com/page",
+            r#"<head><meta name="referrer" content="origin">
+            <meta http-equiv="Content-Security-Policy" content="img-src https:">
+            </head><body><img id="full" referrerpolicy="unsafe-url">"#,
+        );
+        let doc = document.get();
+        assert_eq!(doc.referrer_policy(), ReferrerPolicy::Origin);
+
+        let mut fetcher = Fetcher::new();
+        fetcher.set_user_agent("crawler/1.0");
+        let image = Url::parse("https://cdn.com/cat.png").unwrap();
+
+        let request = fetcher
+            .subresource_request(&doc, &image, ResourceKind::Image, RequestMode::Cors, None)
+            .unwrap();
+        assert_eq!(request.tainting, ResponseTainting::Cors);
+        assert_eq!(request.header("origin"), Some("https://example.com"));
+        assert_eq!(request.header("referer"), Some("https://example.com/"));
+        assert_eq!(request.header("user-agent"), Some("crawler/1.0"));
+        assert_eq!(request.header("accept-language"), Some("en"));
+
+        // The element overrides the policy of the document
+        let node_id = doc.get_node_by_named_id("full").unwrap().id;
+        let request = fetcher
+            .subresource_request(
+                &doc,
+                &image,
+                ResourceKind::Image,
+                RequestMode::NoCors,
+                Some(node_id),
+            )
+            .unwrap();
+        assert_eq!(request.tainting, ResponseTainting::Opaque);
+        assert_eq!(request.header("origin"), None);
+        assert_eq!(request.header("referer"), Some("https://example.com/page"));
+
+        let insecure = Url::parse("http://cdn.com/cat.png").unwrap();
+        assert!(fetcher
+            .subresource_request(
+                &doc,
+                &insecure,
+                ResourceKind::Image,
+                RequestMode::NoCors,
+                None
+            )
+            .is_err());
+    }
+
+    #[test]
+    fn navigation_requests() {
+        let mut tree = FrameTree::new(EventLoopHandle::new());
+        let top = tree.create_top_level("");
+        tree.get_mut(top)
+            .unwrap()
+            .load_html(
+                Url::parse("https://example.com/page?q=1").unwrap(),
+                r#"<a id="same" href="/about">about</a>
+                   <a id="cross" href="https://other.com/">other</a>
+                   <a id="none" href="https://other.com/" rel="noreferrer">other</a>"#,
+            )
+            .unwrap();
+        let document = tree.get(top).unwrap().active_document();
+        let node = |id: &str| document.get().get_node_by_named_id(id).unwrap().id;
+
+        let mut fetcher = Fetcher::new();
+        fetcher.set_accept_language("nl-NL, nl;q=0.9");
+        fetcher.remove_default_header("User-Agent");
+
+        let mut referer = |id: &str| {
+            let (navigation, _) = tree.follow_hyperlink(top, node(id)).unwrap();
+            assert_eq!(navigation.cause, NavigationCause::LinkClick);
+            let request = fetcher.navigation_request(&navigation);
+            assert_eq!(request.mode, RequestMode::Navigate);
+            assert_eq!(request.header("accept-language"), Some("nl-NL, nl;q=0.9"));
+            assert_eq!(request.header("user-agent"), None);
+            request.header("referer").map(|referer| referer.to_string())
+        };
+        assert_eq!(
+            referer("same").as_deref(),
+            Some("https://example.com/page?q=1")
+        );
+        assert_eq!(referer("cross").as_deref(), Some("https://example.com/"));
+        assert_eq!(referer("none"), None);
+    }
+
+    #[test]
+    fn crawler_mode() {
+        let mut fetcher = Fetcher::new();
+        let page = Url::parse("https://example.com/private/page").unwrap();
+        assert_eq!(fetcher.crawl_decision(&page), CrawlDecision::Allow);
+
+        fetcher.enable_crawler_mode("gosub");
+        let crawler = fetcher.crawler_mut().unwrap();
+        crawler.set_robots_txt(
+            Origin::from_url(&page),
+            RobotsTxt::parse("User-agent: gosub\nDisallow: /private"),
+        );
+        assert_eq!(fetcher.crawl_decision(&page), CrawlDecision::Disallow);
+
+        let request = fetcher.request(
+            page.clone(),
+            RequestMode::Navigate,
+            None,
+            ResponseTainting::Basic,
+            Vec::new(),
+        );
+        assert!(matches!(fetcher.crawl(&request), Err(Error::Robots(_))));
+
+        fetcher.disable_crawler_mode();
+        assert_eq!(fetcher.crawl_decision(&page), CrawlDecision::Allow);
+    }
+}
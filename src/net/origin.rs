@@ -0,0 +1,187 @@
+//! Origins
+//!
+//! The origin of a document is the security boundary of the web: documents can only access each
+//! other (and each other's data) when they have the same origin. An origin is either a tuple of
+//! scheme, host and port, taken from the URL of the document, or an opaque origin, which is only
+//! the same as itself (e.g. for `about:blank` or `data:` documents).
+//!
+//! Fetches are checked against the origin of the document that makes them: depending on the mode
+//! of the request, a cross-origin response is blocked, made opaque, or needs CORS.
+//!
+//! See: https://html.spec.whatwg.org/multipage/browsers.html#origin
+use crate::html5::parser::document::Document;
+use crate::types::{Error, Result};
+use url::Url;
+
+/// Origin of a document or request
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Origin(url::Origin);
+
+impl Origin {
+    /// Returns the origin of the URL. URLs without a host (like `about:` and `data:` URLs) have a
+    /// new opaque origin.
+    pub fn from_url(url: &Url) -> Self {
+        Self(url.origin())
+    }
+
+    /// Creates a new opaque origin, which is only the same origin as itself (and its clones)
+    pub fn new_opaque() -> Self {
+        Self(url::Origin::new_opaque())
+    }
+
+    pub fn is_opaque(&self) -> bool {
+        !self.0.is_tuple()
+    }
+
+    /// Returns the scheme of the origin, or None for opaque origins
+    pub fn scheme(&self) -> Option<&str> {
+        match &self.0 {
+            url::Origin::Tuple(scheme, _, _) => Some(scheme),
+            url::Origin::Opaque(_) => None,
+        }
+    }
+
+    /// Returns true when both origins are the same
+    pub fn same_origin(&self, other: &Origin) -> bool {
+        self == other
+    }
+
+    /// Returns the origin as used in the `Origin` header (e.g. `https://example.com:8080`), or
+    /// `null` for opaque origins
+    pub fn serialize(&self) -> String {
+        self.0.ascii_serialization()
+    }
+
+    /// Checks a request for the URL with the given mode made from this origin, and returns how
+    /// the response can be exposed. Requests that cannot be made result in a security error.
+    ///
+    /// See: https://fetch.spec.whatwg.org/#main-fetch
+    pub fn check_fetch(&self, url: &Url, mode: RequestMode) -> Result<ResponseTainting> {
+        if self.same_origin(&Origin::from_url(url))
+            || url.scheme() == "data"
+            || mode == RequestMode::Navigate
+        {
+            return Ok(ResponseTainting::Basic);
+        }
+
+        match mode {
+            RequestMode::SameOrigin => Err(Error::Security(format!(
+                "request from {} to {} is not same-origin",
+                self.serialize(),
+                url
+            ))),
+            RequestMode::NoCors => Ok(ResponseTainting::Opaque),
+            _ if !matches!(url.scheme(), "http" | "https") => Err(Error::Security(format!(
+                "cross-origin request to {} is only supported for http(s)",
+                url
+            ))),
+            _ => Ok(ResponseTainting::Cors),
+        }
+    }
+}
+
+/// Mode of a request, which decides how cross-origin responses are handled
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RequestMode {
+    /// Navigating a browsing context, which can go to any origin
+    Navigate,
+    /// Cross-origin requests are not allowed
+    SameOrigin,
+    /// Cross-origin responses are opaque (e.g. images and scripts without `crossorigin`)
+    NoCors,
+    /// Cross-origin responses must be allowed by the server through CORS
+    Cors,
+}
+
+/// How a response can be exposed to the document that requested it
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ResponseTainting {
+    /// Same-origin response that is fully exposed
+    Basic,
+    /// Cross-origin response that is exposed as far as the CORS headers allow
+    Cors,
+    /// Cross-origin response whose contents are not exposed
+    Opaque,
+}
+
+impl Document {
+    /// Returns the origin of the document
+    pub fn origin(&self) -> &Origin {
+        &self.origin
+    }
+
+    /// Sets the origin of the document (e.g. from its URL when it is loaded, or the origin of its
+    /// creator for an `about:blank` document)
+    pub fn set_origin(&mut self, origin: Origin) {
+        self.origin = origin;
+    }
+
+    /// Checks a request for the URL made by the document. See `Origin::check_fetch()`.
+    pub fn check_fetch(&self, url: &Url, mode: RequestMode) -> Result<ResponseTainting> {
+        self.origin.check_fetch(url, mode)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn url(url: &str) -> Url {
+        Url::parse(url).unwrap()
+    }
+
+    #[test]
+    fn same_origin() {
+        let origin = Origin::from_url(&url("https://example.com/index.html"));
+        assert_eq!(origin.serialize(), "https://example.com");
+        assert!(origin.same_origin(&Origin::from_url(&url("https://example.com:443/other"))));
+        assert!(!origin.same_origin(&Origin::from_url(&url("http://example.com/"))));
+        assert!(!origin.same_origin(&Origin::from_url(&url("https://example.com:8080/"))));
+        assert!(!origin.same_origin(&Origin::from_url(&url("https://www.example.com/"))));
+
+        // Opaque origins are only the same as themselves
+        let opaque = Origin::from_url(&url("about:blank"));
+        assert!(opaque.is_opaque());
+        assert_eq!(opaque.serialize(), "null");
+        assert!(opaque.same_origin(&opaque.clone()));
+        assert!(!opaque.same_origin(&Origin::from_url(&url("about:blank"))));
+    }
+
+    #[test]
+    fn check_fetch() {
+        let origin = Origin::from_url(&url("https://example.com/"));
+        let same = url("https://example.com/data.json");
+        let cross = url("https://api.example.org/data.json");
+
+        for mode in [
+            RequestMode::Navigate,
+            RequestMode::SameOrigin,
+            RequestMode::NoCors,
+            RequestMode::Cors,
+        ] {
+            assert_eq!(
+                origin.check_fetch(&same, mode).unwrap(),
+                ResponseTainting::Basic
+            );
+        }
+
+        assert!(origin.check_fetch(&cross, RequestMode::SameOrigin).is_err());
+        assert_eq!(
+            origin.check_fetch(&cross, RequestMode::NoCors).unwrap(),
+            ResponseTainting::Opaque
+        );
+        assert_eq!(
+            origin.check_fetch(&cross, RequestMode::Cors).unwrap(),
+            ResponseTainting::Cors
+        );
+        assert!(origin
+            .check_fetch(&url("file:///etc/passwd"), RequestMode::Cors)
+            .is_err());
+        assert_eq!(
+            origin
+                .check_fetch(&url("data:text/plain,hi"), RequestMode::SameOrigin)
+                .unwrap(),
+            ResponseTainting::Basic
+        );
+    }
+}
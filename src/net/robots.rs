@@ -0,0 +1,327 @@
+//! Robots exclusion
+//!
+//! Crawlers are expected to honour the `/robots.txt` of a site: it lists which paths may be
+//! crawled by which user agents, and optionally how long a crawler should wait between requests
+//! (`Crawl-delay`). The crawler keeps the parsed robots.txt of every origin it visits, and decides
+//! for each request whether it can be made now, later, or not at all.
+//!
+//! See: https://www.rfc-editor.org/rfc/rfc9309
+use crate::net::origin::Origin;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+use url::Url;
+
+/// Parsed robots.txt of a site
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RobotsTxt {
+    groups: Vec<Group>,
+}
+
+/// Rules for one or more user agents
+#[derive(Debug, Clone, Default, PartialEq)]
+struct Group {
+    /// User agent tokens (lowercase), `*` matches all crawlers
+    agents: Vec<String>,
+    rules: Vec<Rule>,
+    crawl_delay: Option<Duration>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Rule {
+    allow: bool,
+    /// Path pattern, which can contain `*` wildcards and end in `$`
+    pattern: String,
+}
+
+impl RobotsTxt {
+    /// Parses the contents of a robots.txt file. Unknown and malformed lines are ignored.
+    pub fn parse(text: &str) -> Self {
+        let mut groups: Vec<Group> = Vec::new();
+        // True while the user-agent lines of a group are read
+        let mut in_agents = false;
+
+        for line in text.lines() {
+            let line = line.split('#').next().unwrap_or_default().trim();
+            let Some((key, value)) = line.split_once(':') else {
+                continue;
+            };
+            let value = value.trim();
+
+            match key.trim().to_ascii_lowercase().as_str() {
+                "user-agent" => {
+                    if !in_agents {
+                        groups.push(Group::default());
+                        in_agents = true;
+                    }
+                    if let Some(group) = groups.last_mut() {
+                        group.agents.push(value.to_ascii_lowercase());
+                    }
+                }
+                key @ ("allow" | "disallow") => {
+                    in_agents = false;
+                    // An empty disallow allows everything, which is the same as no rule
+                    if value.is_empty() {
+                        continue;
+                    }
+                    if let Some(group) = groups.last_mut() {
+                        group.rules.push(Rule {
+                            allow: key == "allow",
+                            pattern: value.to_string(),
+                        });
+                    }
+                }
+                "crawl-delay" => {
+                    in_agents = false;
+                    let delay = value
+                        .parse::<f64>()
+                        .ok()
+                        .filter(|d| d.is_finite() && *d >= 0.0);
+                    if let (Some(group), Some(delay)) = (groups.last_mut(), delay) {
+                        group.crawl_delay = Some(Duration::from_secs_f64(delay));
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        Self { groups }
+    }
+
+    /// Returns a robots.txt that allows everything (used when a site has none)
+    pub fn allow_all() -> Self {
+        Self::default()
+    }
+
+    /// Returns a robots.txt that disallows everything (used when the robots.txt of a site cannot
+    /// be fetched because of a server or network error)
+    pub fn disallow_all() -> Self {
+        Self::parse("User-agent: *\nDisallow: /")
+    }
+
+    /// Returns true when the user agent (e.g. `gosub`) may crawl the URL
+    pub fn is_allowed(&self, user_agent: &str, url: &Url) -> bool {
+        if url.path() == "/robots.txt" {
+            return true;
+        }
+        let Some(group) = self.group(user_agent) else {
+            return true;
+        };
+
+        let mut path = url.path().to_string();
+        if let Some(query) = url.query() {
+            path.push('?');
+            path.push_str(query);
+        }
+
+        // The most specific (longest) matching rule wins, and allow wins from disallow
+        group
+            .rules
+            .iter()
+            .filter(|rule| matches_pattern(&rule.pattern, &path))
+            .max_by_key(|rule| (rule.pattern.len(), rule.allow))
+            .map_or(true, |rule| rule.allow)
+    }
+
+    /// Returns the delay the user agent should keep between requests (if any)
+    pub fn crawl_delay(&self, user_agent: &str) -> Option<Duration> {
+        self.group(user_agent)?.crawl_delay
+    }
+
+    /// Returns the group for the user agent, or the group for all crawlers
+    fn group(&self, user_agent: &str) -> Option<&Group> {
+        let user_agent = user_agent.to_ascii_lowercase();
+        self.groups
+            .iter()
+            .find(|group| group.agents.contains(&user_agent))
+            .or_else(|| {
+                self.groups
+                    .iter()
+                    .find(|group| group.agents.iter().any(|agent| agent == "*"))
+            })
+    }
+}
+
+/// Returns true when the path starts with the pattern, where `*` matches any characters and a
+/// trailing `$` matches the end of the path
+fn matches_pattern(pattern: &str, path: &str) -> bool {
+    let (pattern, anchored) = match pattern.strip_suffix('$') {
+        Some(pattern) => (pattern, true),
+        None => (pattern, false),
+    };
+
+    let mut parts = pattern.split('*');
+    let Some(first) = parts.next() else {
+        return true;
+    };
+    let Some(mut rest) = path.strip_prefix(first) else {
+        return false;
+    };
+
+    let parts: Vec<&str> = parts.collect();
+    for (i, part) in parts.iter().enumerate() {
+        let last = i == parts.len() - 1;
+        if last && anchored {
+            return rest.ends_with(part);
+        }
+        match rest.find(part) {
+            Some(pos) => rest = &rest[pos + part.len()..],
+            None => return false,
+        }
+    }
+
+    !anchored || rest.is_empty()
+}
+
+/// What a crawler should do with a request
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CrawlDecision {
+    /// The request can be made now
+    Allow,
+    /// The request can be made after waiting for the duration (the crawl delay of the origin)
+    Wait(Duration),
+    /// The robots.txt of the origin does not allow the request
+    Disallow,
+}
+
+/// Keeps the robots.txt and the time of the last request of every origin that is crawled
+#[derive(Debug, Clone, PartialEq)]
+pub struct Crawler {
+    /// User agent token that is matched against the robots.txt rules
+    user_agent: String,
+    /// Delay between requests to an origin whose robots.txt does not set a crawl delay
+    default_delay: Duration,
+    robots: HashMap<Origin, RobotsTxt>,
+    last_request: HashMap<Origin, Instant>,
+}
+
+impl Crawler {
+    /// Creates a crawler that identifies itself with the user agent token in robots.txt rules
+    pub fn new(user_agent: &str) -> Self {
+        Self {
+            user_agent: user_agent.to_string(),
+            default_delay: Duration::ZERO,
+            robots: HashMap::new(),
+            last_request: HashMap::new(),
+        }
+    }
+
+    pub fn user_agent(&self) -> &str {
+        &self.user_agent
+    }
+
+    /// Sets the delay between requests to origins that do not set a crawl delay
+    pub fn set_default_delay(&mut self, delay: Duration) {
+        self.default_delay = delay;
+    }
+
+    /// Returns the robots.txt of the origin, if it is known
+    pub fn robots_txt(&self, origin: &Origin) -> Option<&RobotsTxt> {
+        self.robots.get(origin)
+    }
+
+    /// Sets the robots.txt of the origin (the fetcher does this the first time an origin is
+    /// crawled)
+    pub fn set_robots_txt(&mut self, origin: Origin, robots: RobotsTxt) {
+        self.robots.insert(origin, robots);
+    }
+
+    /// Returns what to do with a request for the URL at the given time, or None when the
+    /// robots.txt of its origin is not known yet
+    pub fn decide(&self, url: &Url, now: Instant) -> Option<CrawlDecision> {
+        let origin = Origin::from_url(url);
+        let robots = self.robots.get(&origin)?;
+        if !robots.is_allowed(&self.user_agent, url) {
+            return Some(CrawlDecision::Disallow);
+        }
+
+        let delay = robots
+            .crawl_delay(&self.user_agent)
+            .unwrap_or(self.default_delay);
+        let wait = self.last_request.get(&origin).and_then(|last| {
+            let ready = *last + delay;
+            (ready > now).then(|| ready - now)
+        });
+
+        Some(wait.map_or(CrawlDecision::Allow, CrawlDecision::Wait))
+    }
+
+    /// Records that a request for the URL was made at the given time
+    pub fn record_request(&mut self, url: &Url, now: Instant) {
+        self.last_request.insert(Origin::from_url(url), now);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn url(url: &str) -> Url {
+        Url::parse(url).unwrap()
+    }
+
+    #[test]
+    fn rules() {
+        let robots = RobotsTxt::parse(
+            "# comment\n\
+             User-agent: *\n\
+             Disallow: /private\n\
+             Allow: /private/public\n\
+             Disallow: /*.pdf$\n\
+             \n\
+             User-agent: gosub\n\
+             User-agent: other\n\
+             Disallow: /\n\
+             Allow: /open/\n\
+             Crawl-delay: 2.5\n",
+        );
+
+        assert!(robots.is_allowed("crawler", &url("https://example.com/")));
+        assert!(!robots.is_allowed("crawler", &url("https://example.com/private/x")));
+        assert!(robots.is_allowed("crawler", &url("https://example.com/private/public/x")));
+        assert!(!robots.is_allowed("crawler", &url("https://example.com/a/b.pdf")));
+        assert!(robots.is_allowed("crawler", &url("https://example.com/a/b.pdf?x")));
+        assert_eq!(robots.crawl_delay("crawler"), None);
+
+        assert!(!robots.is_allowed("Gosub", &url("https://example.com/page")));
+        assert!(robots.is_allowed("gosub", &url("https://example.com/open/page")));
+        assert!(robots.is_allowed("gosub", &url("https://example.com/robots.txt")));
+        assert_eq!(
+            robots.crawl_delay("other"),
+            Some(Duration::from_millis(2500))
+        );
+
+        assert!(RobotsTxt::allow_all().is_allowed("gosub", &url("https://example.com/")));
+        assert!(!RobotsTxt::disallow_all().is_allowed("gosub", &url("https://example.com/")));
+    }
+
+    #[test]
+    fn decisions() {
+        let mut crawler = Crawler::new("gosub");
+        let page = url("https://example.com/page");
+        let now = Instant::now();
+        assert_eq!(crawler.decide(&page, now), None);
+
+        crawler.set_robots_txt(
+            Origin::from_url(&page),
+            RobotsTxt::parse("User-agent: *\nDisallow: /admin\nCrawl-delay: 10"),
+        );
+        assert_eq!(crawler.decide(&page, now), Some(CrawlDecision::Allow));
+        assert_eq!(
+            crawler.decide(&url("https://example.com/admin"), now),
+            Some(CrawlDecision::Disallow)
+        );
+
+        crawler.record_request(&page, now);
+        assert_eq!(
+            crawler.decide(&page, now + Duration::from_secs(4)),
+            Some(CrawlDecision::Wait(Duration::from_secs(6)))
+        );
+        assert_eq!(
+            crawler.decide(&page, now + Duration::from_secs(10)),
+            Some(CrawlDecision::Allow)
+        );
+
+        // Other origins have their own robots.txt
+        assert_eq!(crawler.decide(&url("https://other.com/"), now), None);
+    }
+}
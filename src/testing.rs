@@ -1,6 +1,17 @@
 //! Testing harness and utilities for testing the engine
+#[cfg(feature = "html5ever")]
+pub mod differential;
+pub mod html5lib;
+pub mod parse_errors;
+pub mod stress;
+#[cfg(test)]
+mod support;
 pub mod tokenizer;
 pub mod tree_construction;
+pub mod wpt;
 
 pub const FIXTURE_ROOT: &str = "./tests/data/html5lib-tests";
 pub const TREE_CONSTRUCTION_PATH: &str = "tree-construction";
+
+#[cfg(test)]
+pub(crate) use support::{parse, Boxes};
@@ -0,0 +1,180 @@
+//! Differential testing against html5ever
+//!
+//! Parses the same input with the gosub parser and with html5ever (as reference parser), and
+//! compares the resulting trees in html5lib dump form. Any difference points to a conformance gap
+//! in one of the parsers. To make the gap easier to track down, the position in the source at
+//! which the trees start to diverge is found by comparing the parse results of prefixes of the
+//! input.
+use crate::bytes::{CharIterator, Encoding, Position};
+use crate::html5::html5ever_sink::DocumentSink;
+use crate::html5::parser::document::{Document, DocumentBuilder};
+use crate::html5::parser::{Html5Parser, Html5ParserOptions};
+use crate::testing::tree_construction::generator::TreeOutputGenerator;
+use html5ever::tendril::TendrilSink;
+use html5ever::tree_builder::TreeBuilderOpts;
+use html5ever::ParseOpts;
+use std::fmt;
+
+/// The first difference between the gosub and the reference tree
+#[derive(Debug, Clone, PartialEq)]
+pub struct Divergence {
+    /// Index of the first differing line in the tree dumps
+    pub line: usize,
+    /// Line of the gosub tree (None when the gosub tree has fewer lines)
+    pub gosub: Option<String>,
+    /// Line of the reference tree (None when the reference tree has fewer lines)
+    pub reference: Option<String>,
+    /// Position in the source after which the trees start to diverge
+    pub position: Position,
+}
+
+impl fmt::Display for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "trees diverge at line {}:{} of the source (dump line {})",
+            self.position.line,
+            self.position.col,
+            self.line + 1
+        )?;
+        writeln!(
+            f,
+            "  gosub:     {}",
+            self.gosub.as_deref().unwrap_or("<missing>")
+        )?;
+        write!(
+            f,
+            "  html5ever: {}",
+            self.reference.as_deref().unwrap_or("<missing>")
+        )
+    }
+}
+
+/// Parses the html with gosub and returns the tree in html5lib dump form
+pub fn dump_gosub(html: &str, scripting_enabled: bool) -> Vec<String> {
+    let mut chars = CharIterator::new();
+    chars.read_from_str(html, Some(Encoding::UTF8));
+
+    let document = DocumentBuilder::new_document();
+    let options = Html5ParserOptions {
+        scripting_enabled,
+        ..Default::default()
+    };
+    let _ = Html5Parser::parse_document(&mut chars, Document::clone(&document), Some(options));
+
+    TreeOutputGenerator::new(document).generate()
+}
+
+/// Parses the html with html5ever and returns the tree in html5lib dump form
+pub fn dump_reference(html: &str, scripting_enabled: bool) -> Vec<String> {
+    let options = ParseOpts {
+        tree_builder: TreeBuilderOpts {
+            scripting_enabled,
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    let document = html5ever::parse_document(DocumentSink::new(), options).one(html);
+
+    TreeOutputGenerator::new(document).generate()
+}
+
+/// Returns the index of the first line that differs between both dumps
+fn first_difference(gosub: &[String], reference: &[String]) -> Option<usize> {
+    (0..gosub.len().max(reference.len())).find(|&i| gosub.get(i) != reference.get(i))
+}
+
+fn diverges(html: &str, scripting_enabled: bool) -> bool {
+    dump_gosub(html, scripting_enabled) != dump_reference(html, scripting_enabled)
+}
+
+/// Parses the html with both parsers and returns the first divergence, or None when both trees
+/// are equal
+pub fn compare(html: &str, scripting_enabled: bool) -> Option<Divergence> {
+    let gosub = dump_gosub(html, scripting_enabled);
+    let reference = dump_reference(html, scripting_enabled);
+    let line = first_difference(&gosub, &reference)?;
+
+    // Bisect the prefixes of the input. The empty prefix never diverges and the full input does,
+    // so this finds a character after which the parsers no longer agree.
+    let boundaries: Vec<usize> = html
+        .char_indices()
+        .map(|(i, _)| i)
+        .chain(std::iter::once(html.len()))
+        .collect();
+    let (mut agree, mut differ) = (0, boundaries.len() - 1);
+    while differ - agree > 1 {
+        let middle = (agree + differ) / 2;
+        if diverges(&html[..boundaries[middle]], scripting_enabled) {
+            differ = middle;
+        } else {
+            agree = middle;
+        }
+    }
+
+    Some(Divergence {
+        line,
+        gosub: gosub.get(line).cloned(),
+        reference: reference.get(line).cloned(),
+        position: position_of(html, boundaries[differ]),
+    })
+}
+
+/// Returns the position of the character ending at the given byte offset
+fn position_of(html: &str, end: usize) -> Position {
+    let mut position = Position::new(0, 1, 0);
+    let mut after_newline = false;
+
+    for (offset, c) in html[..end].chars().enumerate() {
+        if after_newline {
+            position.line += 1;
+            position.col = 0;
+        }
+        position.offset = offset;
+        position.col += 1;
+        after_newline = c == '\n';
+    }
+
+    position
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn equal_trees() {
+        assert_eq!(compare("<!DOCTYPE html><p>a<b>b<i>c</b>d</p>", true), None);
+        assert_eq!(
+            compare("<table><td>x</table><template>y</template>", false),
+            None
+        );
+    }
+
+    #[test]
+    fn divergence_position() {
+        // html5ever 0.26 predates the spec change that breaks out of foreign content on </br>
+        let divergence = compare("<p>\n<svg></br><foo>", true).unwrap();
+        assert_eq!(divergence.position, Position::new(13, 2, 10));
+        assert_eq!(divergence.gosub.as_deref(), Some("|       <br>"));
+        assert_eq!(divergence.reference.as_deref(), Some("|         <br>"));
+    }
+
+    #[test]
+    fn first_difference_of_dumps() {
+        let gosub = dump_gosub("<p>a</p>", true);
+        let reference = dump_gosub("<p>b</p>", true);
+        assert_eq!(first_difference(&gosub, &reference), Some(4));
+        assert_eq!(gosub[4], "|       \"a\"");
+        assert_eq!(first_difference(&gosub, &gosub[..3]), Some(3));
+    }
+
+    #[test]
+    fn source_position() {
+        assert_eq!(position_of("abc", 1), Position::new(0, 1, 1));
+        assert_eq!(position_of("ab\ncd\nef", 4), Position::new(3, 2, 1));
+        assert_eq!(position_of("ab\ncd\nef", 5), Position::new(4, 2, 2));
+        assert_eq!(position_of("ab\ncd", 3), Position::new(2, 1, 3));
+        assert_eq!(position_of("é\néx", 5), Position::new(2, 2, 1));
+    }
+}
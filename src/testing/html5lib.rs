@@ -0,0 +1,312 @@
+//! html5lib-tests conformance
+//!
+//! The `tokenizer` and `tree_construction` integration tests only run the fixture files (and the
+//! cases) that are known to pass, and stop at the first failure. This runs every test of every
+//! tokenizer `.test` and tree-construction `.dat` file in the html5lib-tests suite instead, and
+//! reports the number of passed tests per fixture file, so spec conformance can be tracked as a
+//! number over time.
+//!
+//! A tokenizer test passes when it produces the expected tokens and errors in all its initial
+//! states. A tree-construction test passes when it produces the expected tree and errors in all
+//! its scripting modes. Tests that panic fail.
+use crate::testing::tokenizer::{fixture_from_path, FixtureFile as TokenizerFixture};
+use crate::testing::tree_construction::fixture::read_fixture_from_path;
+use crate::testing::tree_construction::Harness;
+use crate::testing::{FIXTURE_ROOT, TREE_CONSTRUCTION_PATH};
+use crate::types::Result;
+use serde::Serialize;
+use std::fmt;
+use std::fs;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+
+/// Directory of the tokenizer fixtures, relative to the fixture root
+pub const TOKENIZER_PATH: &str = "tokenizer";
+
+/// The part of html5lib-tests a fixture file belongs to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Suite {
+    Tokenizer,
+    TreeConstruction,
+}
+
+impl fmt::Display for Suite {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Suite::Tokenizer => write!(f, "tokenizer"),
+            Suite::TreeConstruction => write!(f, "tree-construction"),
+        }
+    }
+}
+
+/// A test that failed
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Failure {
+    /// Description of a tokenizer test, or the line and input of a tree-construction test
+    pub name: String,
+    pub message: String,
+}
+
+/// Results of all tests in a single fixture file
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct FixtureResult {
+    pub suite: Suite,
+    /// Name of the fixture file (e.g. `tests1.dat`)
+    pub name: String,
+    pub passed: usize,
+    pub failures: Vec<Failure>,
+}
+
+impl FixtureResult {
+    fn new(suite: Suite, path: &Path) -> Self {
+        Self {
+            suite,
+            name: path
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_default(),
+            passed: 0,
+            failures: Vec::new(),
+        }
+    }
+
+    /// Number of tests in the fixture file
+    pub fn total(&self) -> usize {
+        self.passed + self.failures.len()
+    }
+
+    fn record(&mut self, name: String, result: std::result::Result<(), String>) {
+        match result {
+            Ok(()) => self.passed += 1,
+            Err(message) => self.failures.push(Failure { name, message }),
+        }
+    }
+}
+
+/// Runs the function, and turns a panic into a failure
+fn run_guarded<F>(f: F) -> std::result::Result<(), String>
+where
+    F: FnOnce() -> std::result::Result<(), String>,
+{
+    catch_unwind(AssertUnwindSafe(f)).unwrap_or_else(|panic| {
+        let message = panic
+            .downcast_ref::<String>()
+            .cloned()
+            .or_else(|| panic.downcast_ref::<&str>().map(|s| s.to_string()))
+            .unwrap_or_default();
+        Err(format!("panicked: {}", message))
+    })
+}
+
+/// Runs all tests of a tokenizer `.test` file
+pub fn run_tokenizer_fixture(path: &Path) -> Result<FixtureResult> {
+    let tests = match fixture_from_path(&path)? {
+        TokenizerFixture::Tests { tests } => tests,
+        TokenizerFixture::XmlTests { tests } => tests,
+    };
+
+    let mut result = FixtureResult::new(Suite::Tokenizer, path);
+    for test in tests {
+        result.record(test.description.clone(), run_guarded(|| test.check()));
+    }
+    Ok(result)
+}
+
+/// Runs all tests of a tree-construction `.dat` file
+pub fn run_tree_construction_fixture(path: &Path) -> Result<FixtureResult> {
+    let fixture = read_fixture_from_path(&path.to_path_buf())?;
+
+    let mut harness = Harness::new();
+    let mut result = FixtureResult::new(Suite::TreeConstruction, path);
+    for test in fixture.tests {
+        let name = format!("{}: {}", test.line, test.spec_data());
+        let outcome = run_guarded(|| {
+            for &scripting_enabled in test.script_modes() {
+                let passed = harness
+                    .run_test(test.clone(), scripting_enabled)
+                    .map_err(|e| e.to_string())?
+                    .is_success();
+                if !passed {
+                    return Err(format!(
+                        "tree or errors do not match (scripting {})",
+                        if scripting_enabled { "on" } else { "off" }
+                    ));
+                }
+            }
+            Ok(())
+        });
+        result.record(name, outcome);
+    }
+    Ok(result)
+}
+
+/// Returns the files with the extension in the directory, ordered by name
+fn fixture_paths(dir: &Path, extension: &str) -> Result<Vec<PathBuf>> {
+    let mut paths = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_file() && path.extension().is_some_and(|ext| ext == extension) {
+            paths.push(path);
+        }
+    }
+    paths.sort();
+    Ok(paths)
+}
+
+/// Results of a run of the html5lib-tests suite
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct Report {
+    pub fixtures: Vec<FixtureResult>,
+}
+
+impl Report {
+    /// Runs all tokenizer and tree-construction fixtures of the html5lib-tests in the repository
+    pub fn run() -> Result<Self> {
+        Self::run_from(Path::new(FIXTURE_ROOT))
+    }
+
+    /// Runs all tokenizer and tree-construction fixtures below the root of an html5lib-tests
+    /// checkout
+    pub fn run_from(root: &Path) -> Result<Self> {
+        let mut fixtures = Vec::new();
+        for path in fixture_paths(&root.join(TOKENIZER_PATH), "test")? {
+            fixtures.push(run_tokenizer_fixture(&path)?);
+        }
+        for path in fixture_paths(&root.join(TREE_CONSTRUCTION_PATH), "dat")? {
+            fixtures.push(run_tree_construction_fixture(&path)?);
+        }
+
+        Ok(Self { fixtures })
+    }
+
+    /// Number of tests in the suite (or in all suites when none is given)
+    pub fn total(&self, suite: Option<Suite>) -> usize {
+        self.fixtures_of(suite).map(FixtureResult::total).sum()
+    }
+
+    /// Number of passed tests in the suite (or in all suites when none is given)
+    pub fn passed(&self, suite: Option<Suite>) -> usize {
+        self.fixtures_of(suite).map(|fixture| fixture.passed).sum()
+    }
+
+    /// Percentage of passed tests in the suite (or in all suites when none is given)
+    pub fn pass_rate(&self, suite: Option<Suite>) -> f64 {
+        match self.total(suite) {
+            0 => 0.0,
+            total => self.passed(suite) as f64 * 100.0 / total as f64,
+        }
+    }
+
+    /// Results in JSON form, so the pass rate can be tracked over time
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).unwrap_or_default()
+    }
+
+    fn fixtures_of(&self, suite: Option<Suite>) -> impl Iterator<Item = &FixtureResult> {
+        self.fixtures
+            .iter()
+            .filter(move |fixture| suite.map_or(true, |suite| fixture.suite == suite))
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for fixture in &self.fixtures {
+            writeln!(
+                f,
+                "{} {}/{} ({}/{})",
+                if fixture.failures.is_empty() {
+                    "PASS"
+                } else {
+                    "FAIL"
+                },
+                fixture.suite,
+                fixture.name,
+                fixture.passed,
+                fixture.total()
+            )?;
+        }
+        for suite in [Suite::Tokenizer, Suite::TreeConstruction] {
+            writeln!(
+                f,
+                "{}: {} of {} tests passed ({:.1}%)",
+                suite,
+                self.passed(Some(suite)),
+                self.total(Some(suite)),
+                self.pass_rate(Some(suite))
+            )?;
+        }
+        write!(
+            f,
+            "{} of {} tests passed ({:.1}%)",
+            self.passed(None),
+            self.total(None),
+            self.pass_rate(None)
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn passing_fixtures() {
+        let root = Path::new(FIXTURE_ROOT);
+
+        let result = run_tokenizer_fixture(&root.join(TOKENIZER_PATH).join("test1.test")).unwrap();
+        assert_eq!(result.suite, Suite::Tokenizer);
+        assert_eq!(result.name, "test1.test");
+        assert!(result.passed > 0);
+        assert_eq!(result.failures, vec![]);
+
+        let path = root.join(TREE_CONSTRUCTION_PATH).join("tests1.dat");
+        let result = run_tree_construction_fixture(&path).unwrap();
+        assert_eq!(result.suite, Suite::TreeConstruction);
+        assert!(result.passed > 0);
+        assert_eq!(result.failures, vec![]);
+    }
+
+    #[test]
+    fn failures_are_reported() {
+        let root = std::env::temp_dir().join(format!("gosub-html5lib-{}", std::process::id()));
+        fs::create_dir_all(root.join(TOKENIZER_PATH)).unwrap();
+        fs::create_dir_all(root.join(TREE_CONSTRUCTION_PATH)).unwrap();
+        fs::write(
+            root.join(TOKENIZER_PATH).join("small.test"),
+            r#"{"tests": [
+                {"description": "good", "input": "<a>", "output": [["StartTag", "a", {}]]},
+                {"description": "bad", "input": "<b>", "output": [["StartTag", "a", {}]]}
+            ]}"#,
+        )
+        .unwrap();
+        fs::write(
+            root.join(TREE_CONSTRUCTION_PATH).join("small.dat"),
+            "#data\n<!DOCTYPE html><p>x\n#errors\n#document\n| <!DOCTYPE html>\n| <html>\n|   <head>\n|   <body>\n|     <p>\n|       \"y\"\n",
+        )
+        .unwrap();
+
+        let report = Report::run_from(&root).unwrap();
+        fs::remove_dir_all(&root).unwrap();
+
+        assert_eq!(report.fixtures.len(), 2);
+        let tokenizer = &report.fixtures[0];
+        assert_eq!((tokenizer.passed, tokenizer.total()), (1, 2));
+        assert_eq!(tokenizer.failures[0].name, "bad");
+        assert!(tokenizer.failures[0].message.contains("wanted token"));
+        let tree = &report.fixtures[1];
+        assert_eq!((tree.passed, tree.total()), (0, 1));
+        assert_eq!(tree.failures[0].name, "1: <!DOCTYPE html><p>x");
+
+        assert_eq!(report.passed(None), 1);
+        assert_eq!(report.total(Some(Suite::Tokenizer)), 2);
+        assert_eq!(report.pass_rate(Some(Suite::Tokenizer)), 50.0);
+        let output = report.to_string();
+        assert!(
+            output.contains("FAIL tokenizer/small.test (1/2)"),
+            "{output}"
+        );
+        assert!(output.ends_with("1 of 3 tests passed (33.3%)"), "{output}");
+    }
+}
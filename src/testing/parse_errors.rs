@@ -0,0 +1,167 @@
+//! Parse error snapshots
+//!
+//! The html5lib tests check the number of parse errors, but not which errors are reported or
+//! where. The parse error corpus records the errors of every input in a golden file next to it,
+//! so changes in error reporting show up as a diff. A corpus is a directory of `.html` files;
+//! the golden file of `foo.html` is `foo.errors` and holds one `line:col code` line per error.
+//!
+//! Golden files that do not exist yet are written on the first run. Set `GOSUB_UPDATE_GOLDEN` in
+//! the environment to rewrite all golden files after an intended change.
+use crate::bytes::{CharIterator, Encoding};
+use crate::html5::error_logger::sort_errors;
+use crate::html5::parser::document::{Document, DocumentBuilder};
+use crate::html5::parser::Html5Parser;
+use crate::types::{Error, ParseError, Result};
+use std::fmt::Write;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Environment variable that rewrites the golden files instead of comparing against them
+pub const UPDATE_ENV: &str = "GOSUB_UPDATE_GOLDEN";
+
+/// Extension of the inputs in a corpus
+pub const INPUT_EXTENSION: &str = "html";
+
+/// Extension of the golden files in a corpus
+pub const GOLDEN_EXTENSION: &str = "errors";
+
+/// Golden file that does not match the errors of its input
+#[derive(Debug, Clone, PartialEq)]
+pub struct Mismatch {
+    /// Path of the golden file
+    pub path: PathBuf,
+    /// Lines of the golden file that were not reported (`-`) and reported errors that are not
+    /// in the golden file (`+`)
+    pub diff: String,
+}
+
+/// Returns true when golden files must be rewritten
+pub fn update_requested() -> bool {
+    std::env::var_os(UPDATE_ENV).is_some()
+}
+
+/// Parses the html and returns its parse errors, ordered by position
+pub fn parse_errors(html: &str) -> Result<Vec<ParseError>> {
+    let mut chars = CharIterator::new();
+    chars.read_from_str(html, Some(Encoding::UTF8));
+
+    let document = DocumentBuilder::new_document();
+    let mut errors = Html5Parser::parse_document(&mut chars, Document::clone(&document), None)?;
+    sort_errors(&mut errors);
+    Ok(errors)
+}
+
+/// Returns the golden file contents of the errors: one `line:col code` line per error
+pub fn format_errors(errors: &[ParseError]) -> String {
+    let mut output = String::new();
+    for error in errors {
+        let _ = writeln!(output, "{}:{} {}", error.line, error.col, error.message);
+    }
+    output
+}
+
+/// Compares the errors of the html with the golden file. The golden file is written when it does
+/// not exist or when `update` is set.
+pub fn check_golden(html: &str, golden: &Path, update: bool) -> Result<()> {
+    let actual = format_errors(&parse_errors(html)?);
+    if update || !golden.exists() {
+        fs::write(golden, actual)?;
+        return Ok(());
+    }
+
+    let expected = fs::read_to_string(golden)?;
+    match diff(&expected, &actual) {
+        Some(diff) => Err(Error::Test(format!(
+            "parse errors do not match {}:\n{diff}",
+            golden.display()
+        ))),
+        None => Ok(()),
+    }
+}
+
+/// Checks all inputs of the corpus directory against their golden files, in order of their
+/// names. Returns the golden files that do not match.
+pub fn check_corpus(dir: &Path, update: bool) -> Result<Vec<Mismatch>> {
+    let mut inputs = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.extension().is_some_and(|ext| ext == INPUT_EXTENSION) {
+            inputs.push(path);
+        }
+    }
+    inputs.sort();
+
+    let mut mismatches = Vec::new();
+    for input in inputs {
+        let html = fs::read_to_string(&input)?;
+        let golden = input.with_extension(GOLDEN_EXTENSION);
+        match check_golden(&html, &golden, update) {
+            Ok(()) => {}
+            Err(Error::Test(diff)) => mismatches.push(Mismatch { path: golden, diff }),
+            Err(err) => return Err(err),
+        }
+    }
+
+    Ok(mismatches)
+}
+
+/// Returns the lines that were removed from and added to the expected output, or None when
+/// both are the same
+fn diff(expected: &str, actual: &str) -> Option<String> {
+    if expected == actual {
+        return None;
+    }
+
+    let expected: Vec<&str> = expected.lines().collect();
+    let actual: Vec<&str> = actual.lines().collect();
+    let mut output = String::new();
+    for line in expected.iter().filter(|line| !actual.contains(line)) {
+        let _ = writeln!(output, "-{line}");
+    }
+    for line in actual.iter().filter(|line| !expected.contains(line)) {
+        let _ = writeln!(output, "+{line}");
+    }
+    if output.is_empty() {
+        // Same errors, but in a different order or with different duplicates
+        output.push_str("errors are reported in a different order\n");
+    }
+
+    Some(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn errors_are_ordered() {
+        let errors = parse_errors("<p>one</b>\n<!DOCTYPE html>").unwrap();
+        assert!(!errors.is_empty());
+        assert!(errors
+            .windows(2)
+            .all(|pair| (pair[0].offset, pair[0].line, pair[0].col)
+                <= (pair[1].offset, pair[1].line, pair[1].col)));
+
+        let output = format_errors(&errors);
+        assert_eq!(output.lines().count(), errors.len());
+        assert!(output.starts_with("1:"));
+    }
+
+    #[test]
+    fn golden_file() {
+        let golden =
+            std::env::temp_dir().join(format!("gosub-golden-{}.errors", std::process::id()));
+        let _ = fs::remove_file(&golden);
+
+        // Written on the first run, matches on the next
+        check_golden("<p>one</b>", &golden, false).unwrap();
+        check_golden("<p>one</b>", &golden, false).unwrap();
+
+        let err = check_golden("<!DOCTYPE html><p>one</p>", &golden, false).unwrap_err();
+        assert!(err.to_string().contains("\n-1:"), "{err}");
+
+        check_golden("<!DOCTYPE html><p>one</p>", &golden, true).unwrap();
+        assert_eq!(fs::read_to_string(&golden).unwrap(), "");
+        fs::remove_file(&golden).unwrap();
+    }
+}
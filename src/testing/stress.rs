@@ -0,0 +1,75 @@
+//! Large document generation
+//!
+//! Generates (very) large documents to find algorithmic hazards in the tokenizer and parser. The
+//! generated markup mimics real-world pages: long flat lists and tables, deeply nested sections,
+//! formatting elements, comments and entities. Since the output is fully deterministic, timings
+//! of different runs can be compared.
+use std::fmt::Write;
+
+/// Markup that is repeated, in order, until the document reaches the requested size
+const BLOCKS: [&str; 6] = [
+    "<section class=\"s{n}\"><h2 id=\"h{n}\">Heading {n}</h2><p>Some <b>bold</b> and <i>italic</i> text &amp; an <a href=\"/page/{n}\">anchor</a>.</p></section>\n",
+    "<ul><li>one {n}<li>two<li>three <em>emphasis</em></ul>\n",
+    "<table><tr><td>{n}<td>cell<td><span>nested</span></tr><tr><td>a<td>b<td>c</table>\n",
+    "<!-- comment {n} --><div><div><div><p>deep {n}</p></div></div></div>\n",
+    "<p>unclosed paragraph {n} with <b>misnested <i>formatting</b> elements</i>\n",
+    "<form><input name=\"field{n}\" value=\"&lt;{n}&gt;\"><select><option>{n}</select></form>\n",
+];
+
+/// Returns a document of (at least) the given number of bytes
+pub fn generate_document(size: usize) -> String {
+    let mut html = String::with_capacity(size + 256);
+    html.push_str("<!DOCTYPE html><html><head><title>Large document</title></head><body>\n");
+
+    let mut n = 0;
+    while html.len() < size {
+        let block = BLOCKS[n % BLOCKS.len()];
+        let _ = write!(html, "{}", block.replace("{n}", &n.to_string()));
+        n += 1;
+    }
+
+    html.push_str("</body></html>\n");
+    html
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bytes::{CharIterator, Encoding};
+    use crate::html5::parser::document::{Document, DocumentBuilder};
+    use crate::html5::parser::Html5Parser;
+    use std::time::Instant;
+
+    fn parse_duration(size: usize) -> std::time::Duration {
+        let html = generate_document(size);
+        let mut chars = CharIterator::new();
+        chars.read_from_str(&html, Some(Encoding::UTF8));
+
+        let document = DocumentBuilder::new_document();
+        let start = Instant::now();
+        let _ = Html5Parser::parse_document(&mut chars, Document::clone(&document), None);
+        start.elapsed()
+    }
+
+    #[test]
+    fn generated_size() {
+        let html = generate_document(10_000);
+        assert!(html.len() >= 10_000 && html.len() < 11_000);
+        assert!(html.starts_with("<!DOCTYPE html>"));
+    }
+
+    #[test]
+    fn parsing_scales_linearly() {
+        // Warm up, then compare the time for a document with one that is four times larger. A
+        // quadratic algorithm would take sixteen times as long.
+        parse_duration(50_000);
+        let small = parse_duration(100_000);
+        let large = parse_duration(400_000);
+        assert!(
+            large < small * 10,
+            "parsing 4x more input took {:?} instead of {:?}",
+            large,
+            small
+        );
+    }
+}
@@ -0,0 +1,54 @@
+//! Helpers shared by the tests of the engine
+use crate::bytes::{CharIterator, Encoding};
+use crate::html5::geometry::{LayoutGeometry, Rect};
+use crate::html5::node::NodeId;
+use crate::html5::parser::document::{Document, DocumentBuilder, DocumentHandle};
+use crate::html5::parser::Html5Parser;
+use std::cell::RefCell;
+use std::collections::HashMap;
+
+/// Parses the HTML into a new document
+pub(crate) fn parse(html: &str) -> DocumentHandle {
+    let mut chars = CharIterator::new();
+    chars.read_from_str(html, Some(Encoding::UTF8));
+
+    let document = DocumentBuilder::new_document();
+    let _ = Html5Parser::parse_document(&mut chars, Document::clone(&document), None);
+    document
+}
+
+/// Layout where the border boxes can be set and moved around
+pub(crate) struct Boxes {
+    boxes: RefCell<HashMap<NodeId, Rect>>,
+    viewport: Rect,
+}
+
+impl Boxes {
+    /// Creates a layout with the given viewport and no boxes
+    pub(crate) fn new(viewport: Rect) -> Self {
+        Self {
+            boxes: RefCell::new(HashMap::new()),
+            viewport,
+        }
+    }
+
+    /// Sets the border box of the node
+    pub(crate) fn set(&self, node_id: NodeId, rect: Rect) {
+        self.boxes.borrow_mut().insert(node_id, rect);
+    }
+
+    /// Removes the box of the node, like when it no longer generates one
+    pub(crate) fn remove(&self, node_id: NodeId) {
+        self.boxes.borrow_mut().remove(&node_id);
+    }
+}
+
+impl LayoutGeometry for Boxes {
+    fn border_box(&self, node_id: NodeId) -> Option<Rect> {
+        self.boxes.borrow().get(&node_id).copied()
+    }
+
+    fn viewport(&self) -> Rect {
+        self.viewport
+    }
+}
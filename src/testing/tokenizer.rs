@@ -206,29 +206,57 @@ impl TestSpec {
     }
 
     pub fn assert_valid(&self) {
+        if let Err(message) = self.check() {
+            panic!("{}", message);
+        }
+    }
+
+    /// Runs the test in all its initial states, and returns a message describing the first
+    /// difference with the expected tokens and errors (if any)
+    pub fn check(&self) -> std::result::Result<(), String> {
         for mut builder in self.builders() {
             let mut tokenizer = builder.build();
 
             // If there is no output, still do an (initial) next token so the parser can generate
             // errors.
             if self.output.is_empty() {
-                tokenizer.next_token(ParserData::default()).unwrap();
+                tokenizer
+                    .next_token(ParserData::default())
+                    .map_err(|e| e.to_string())?;
             }
 
             // There can be multiple tokens to match. Make sure we match all of them
             for expected in self.output.iter() {
-                let actual = tokenizer.next_token(ParserData::default()).unwrap();
-                assert_eq!(self.escape(&actual), self.escape(expected));
+                let actual = tokenizer
+                    .next_token(ParserData::default())
+                    .map_err(|e| e.to_string())?;
+                if self.escape(&actual) != self.escape(expected) {
+                    return Err(format!(
+                        "[{}]: wanted token {:?}, got {:?}",
+                        self.description,
+                        self.escape(expected),
+                        self.escape(&actual)
+                    ));
+                }
             }
 
-            let borrowed_error_logger = tokenizer.error_logger.borrow();
-            assert_eq!(borrowed_error_logger.get_errors().len(), self.errors.len());
+            let error_count = tokenizer.error_logger.borrow().get_errors().len();
+            if error_count != self.errors.len() {
+                return Err(format!(
+                    "[{}]: wanted {} errors, got {}",
+                    self.description,
+                    self.errors.len(),
+                    error_count
+                ));
+            }
 
             // Check error messages
             for error in &self.errors {
-                self.assert_error(&tokenizer, error);
+                self.check_error(&tokenizer, error)?;
             }
         }
+
+        Ok(())
     }
 
     /// Run through the parsing without making assertions, for use in benchmarking and in order to
@@ -243,14 +271,18 @@ impl TestSpec {
         }
     }
 
-    fn assert_error(&self, tokenizer: &Tokenizer, expected: &TokenError) {
+    fn check_error(
+        &self,
+        tokenizer: &Tokenizer,
+        expected: &TokenError,
+    ) -> std::result::Result<(), String> {
         // Iterate all generated errors to see if we have an exact match
         for actual in tokenizer.get_error_logger().get_errors() {
             if actual.message == expected.code
                 && actual.line == expected.line
                 && actual.col == expected.col
             {
-                return;
+                return Ok(());
             }
         }
 
@@ -260,17 +292,17 @@ impl TestSpec {
             if actual.message == expected.code
                 && (actual.line != expected.line || actual.col != expected.col)
             {
-                panic!(
+                return Err(format!(
                     "[{}]: wanted {:?}, got {:?}",
                     self.description, expected, actual
-                );
+                ));
             }
         }
 
-        panic!(
+        Err(format!(
             "expected error '{}' at {}:{}",
             expected.code, expected.line, expected.col
-        );
+        ))
     }
 
     fn escape(&self, token: &Token) -> Token {
@@ -1,5 +1,5 @@
 pub mod fixture;
-mod generator;
+pub(crate) mod generator;
 pub(crate) mod parser;
 pub mod result;
 
@@ -118,7 +118,7 @@ impl Harness {
 
             // Add context node
             let context_node_id =
-                main_document.create_element(element.as_str(), NodeId::root(), None, namespace);
+                main_document.create_element(element.as_str(), NodeId::root(), None, namespace)?;
             context_node = Some(
                 main_document
                     .get()
@@ -128,13 +128,16 @@ impl Harness {
             );
 
             is_fragment = true;
-            document = DocumentBuilder::new_document_fragment(context_node.clone().expect(""));
+            document = DocumentBuilder::new_document_fragment(&main_document);
         } else {
             is_fragment = false;
             document = DocumentBuilder::new_document();
         };
 
-        let options = Html5ParserOptions { scripting_enabled };
+        let options = Html5ParserOptions {
+            scripting_enabled,
+            ..Default::default()
+        };
 
         let mut chars = CharIterator::new();
         chars.read_from_str(self.test.spec_data(), None);
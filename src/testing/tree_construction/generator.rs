@@ -35,10 +35,10 @@ impl TreeOutputGenerator {
             if node.type_of() == NodeType::Element {
                 if let NodeData::Element(element) = &node.data {
                     let mut sorted_attrs = vec![];
-                    for attr in element.attributes.iter() {
-                        sorted_attrs.push(attr);
+                    for (name, value) in element.attributes.iter() {
+                        sorted_attrs.push((self.output_attribute_name(node, name), value));
                     }
-                    sorted_attrs.sort_by(|a, b| a.0.cmp(b.0));
+                    sorted_attrs.sort_by(|a, b| a.0.cmp(&b.0));
 
                     for attr in sorted_attrs.iter() {
                         output.push(format!(
@@ -68,6 +68,18 @@ impl TreeOutputGenerator {
         output
     }
 
+    /// Generate the output for an attribute name. Namespaced attributes are written as
+    /// "prefix localname" (e.g. `xlink href`).
+    fn output_attribute_name(&self, node: &Node, name: &str) -> String {
+        if node.attribute_namespace(name).is_none() {
+            return name.to_string();
+        }
+        match name.split_once(':') {
+            Some((prefix, local_name)) => format!("{prefix} {local_name}"),
+            None => format!("{name} {name}"),
+        }
+    }
+
     /// Generate the output for a single node
     fn output_node(&self, node: &Node) -> String {
         match node.data.clone() {
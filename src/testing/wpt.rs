@@ -0,0 +1,395 @@
+//! web-platform-tests bridge
+//!
+//! WPT tests are written in JavaScript on top of testharness.js. As long as the engine cannot run
+//! scripts, tests are ported to Rust on top of a small native bridge that mirrors the
+//! testharness.js API (`test`, `assert_equals`, ...). Results are reported per WPT test file with
+//! the testharness.js statuses, so the pass rate can be tracked and compared with other engines.
+//!
+//! The `html/syntax/parsing` tests of WPT are generated from the html5lib tree-construction tests,
+//! so that part of the suite is run directly from the html5lib fixtures.
+pub mod dom;
+
+use crate::bytes::{CharIterator, Encoding};
+use crate::html5::parser::document::{Document, DocumentBuilder, DocumentHandle};
+use crate::html5::parser::Html5Parser;
+use crate::testing::tree_construction::fixture::read_fixtures;
+use crate::testing::tree_construction::Harness;
+use serde::Serialize;
+use std::fmt;
+use std::path::Path;
+
+/// Result of an assertion. The error is the message reported for the failing subtest.
+pub type AssertResult = std::result::Result<(), String>;
+
+/// Status of a single subtest (as used by testharness.js)
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum Status {
+    Pass,
+    Fail,
+    NotRun,
+}
+
+/// Result of a single subtest
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SubtestResult {
+    pub name: String,
+    pub status: Status,
+    /// Message of the failed assertion
+    pub message: Option<String>,
+}
+
+/// Results of all subtests in a single WPT test file
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct FileResult {
+    /// Path of the test in the WPT repository (e.g. `dom/nodes/Node-textContent.html`)
+    pub path: String,
+    pub subtests: Vec<SubtestResult>,
+}
+
+impl FileResult {
+    /// Number of passed subtests
+    pub fn passed(&self) -> usize {
+        self.subtests
+            .iter()
+            .filter(|subtest| subtest.status == Status::Pass)
+            .count()
+    }
+}
+
+/// Bridge for the testharness.js `test()` function. Holds the document of the test file and
+/// collects the results of the subtests.
+pub struct TestHarness {
+    document: DocumentHandle,
+    results: Vec<SubtestResult>,
+}
+
+impl TestHarness {
+    /// Parses the html of the test file into a new document
+    pub fn new(html: &str) -> Self {
+        let mut chars = CharIterator::new();
+        chars.read_from_str(html, Some(Encoding::UTF8));
+
+        let document = DocumentBuilder::new_document();
+        let _ = Html5Parser::parse_document(&mut chars, Document::clone(&document), None);
+
+        Self {
+            document,
+            results: Vec::new(),
+        }
+    }
+
+    /// Document of the test file. Changes made by a subtest are visible to the next subtests,
+    /// just like in the browser.
+    pub fn document(&self) -> DocumentHandle {
+        Document::clone(&self.document)
+    }
+
+    /// Runs a single subtest
+    pub fn test<F>(&mut self, name: &str, f: F)
+    where
+        F: FnOnce(&mut DocumentHandle) -> AssertResult,
+    {
+        let mut document = self.document();
+        let (status, message) = match f(&mut document) {
+            Ok(()) => (Status::Pass, None),
+            Err(message) => (Status::Fail, Some(message)),
+        };
+
+        self.results.push(SubtestResult {
+            name: name.to_string(),
+            status,
+            message,
+        });
+    }
+
+    /// Records a subtest that cannot be run yet (for instance, because it needs APIs that are not
+    /// implemented)
+    pub fn not_run(&mut self, name: &str, reason: &str) {
+        self.results.push(SubtestResult {
+            name: name.to_string(),
+            status: Status::NotRun,
+            message: Some(reason.to_string()),
+        });
+    }
+
+    /// Returns the results of all subtests
+    pub fn finish(self) -> Vec<SubtestResult> {
+        self.results
+    }
+}
+
+/// Asserts that both values are equal
+pub fn assert_equals<T: PartialEq + fmt::Debug>(
+    actual: T,
+    expected: T,
+    description: &str,
+) -> AssertResult {
+    if actual == expected {
+        return Ok(());
+    }
+    Err(format!(
+        "assert_equals: {} expected {:?} but got {:?}",
+        description, expected, actual
+    ))
+}
+
+/// Asserts that both values are not equal
+pub fn assert_not_equals<T: PartialEq + fmt::Debug>(
+    actual: T,
+    expected: T,
+    description: &str,
+) -> AssertResult {
+    if actual != expected {
+        return Ok(());
+    }
+    Err(format!(
+        "assert_not_equals: {} got disallowed value {:?}",
+        description, actual
+    ))
+}
+
+/// Asserts that the value is true
+pub fn assert_true(actual: bool, description: &str) -> AssertResult {
+    if actual {
+        return Ok(());
+    }
+    Err(format!(
+        "assert_true: {} expected true got false",
+        description
+    ))
+}
+
+/// Asserts that the value is false
+pub fn assert_false(actual: bool, description: &str) -> AssertResult {
+    if !actual {
+        return Ok(());
+    }
+    Err(format!(
+        "assert_false: {} expected false got true",
+        description
+    ))
+}
+
+/// Asserts that both arrays have the same length and the same values
+pub fn assert_array_equals<T: PartialEq + fmt::Debug>(
+    actual: &[T],
+    expected: &[T],
+    description: &str,
+) -> AssertResult {
+    if actual.len() != expected.len() {
+        return Err(format!(
+            "assert_array_equals: {} lengths differ, expected array {:?} length {}, got {:?} length {}",
+            description,
+            expected,
+            expected.len(),
+            actual,
+            actual.len()
+        ));
+    }
+    match actual.iter().zip(expected).position(|(a, e)| a != e) {
+        None => Ok(()),
+        Some(index) => Err(format!(
+            "assert_array_equals: {} expected property {} to be {:?} but got {:?} (expected array {:?} got {:?})",
+            description, index, expected[index], actual[index], expected, actual
+        )),
+    }
+}
+
+/// A WPT test file that is ported to the native bridge
+pub struct WptTest {
+    /// Path of the test in the WPT repository
+    pub path: &'static str,
+    /// Markup of the test file (without the testharness.js scripts)
+    pub html: &'static str,
+    /// Runs the subtests of the file
+    pub run: fn(&mut TestHarness),
+}
+
+impl WptTest {
+    /// Runs the test file and returns the results of its subtests
+    pub fn run(&self) -> FileResult {
+        let mut harness = TestHarness::new(self.html);
+        (self.run)(&mut harness);
+
+        FileResult {
+            path: self.path.to_string(),
+            subtests: harness.finish(),
+        }
+    }
+}
+
+/// Runs the `html/syntax/parsing` tests from the html5lib tree-construction fixtures. Every
+/// fixture file maps to a generated `html5lib_<name>.html` WPT file, and every test in it to a
+/// subtest. Tests are run with scripting enabled, unless the test requires it to be disabled.
+pub fn run_html_syntax() -> Vec<FileResult> {
+    let Ok(fixtures) = read_fixtures(None) else {
+        return vec![];
+    };
+
+    let mut files = vec![];
+    for fixture in fixtures {
+        let name = Path::new(&fixture.path)
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().replace('-', "_"))
+            .unwrap_or_default();
+
+        let mut harness = Harness::new();
+        let subtests = fixture
+            .tests
+            .into_iter()
+            .map(|test| {
+                let name = format!("html5lib_{}.html {}", name, test.line);
+                let scripting_enabled = *test.script_modes().last().unwrap_or(&false);
+                match harness.run_test(test, scripting_enabled) {
+                    Ok(result) if result.is_success() => SubtestResult {
+                        name,
+                        status: Status::Pass,
+                        message: None,
+                    },
+                    Ok(_) => SubtestResult {
+                        name,
+                        status: Status::Fail,
+                        message: Some("tree does not match".to_string()),
+                    },
+                    Err(e) => SubtestResult {
+                        name,
+                        status: Status::Fail,
+                        message: Some(e.to_string()),
+                    },
+                }
+            })
+            .collect();
+
+        files.push(FileResult {
+            path: format!("html/syntax/parsing/html5lib_{}.html", name),
+            subtests,
+        });
+    }
+
+    files
+}
+
+/// Results of a run of the (supported part of the) suite
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct Report {
+    pub files: Vec<FileResult>,
+}
+
+impl Report {
+    /// Runs all ported tests and the html/syntax tests
+    pub fn run() -> Self {
+        let mut files: Vec<_> = dom::tests().iter().map(WptTest::run).collect();
+        files.extend(run_html_syntax());
+
+        Self { files }
+    }
+
+    /// Number of subtests
+    pub fn total(&self) -> usize {
+        self.files.iter().map(|file| file.subtests.len()).sum()
+    }
+
+    /// Number of passed subtests
+    pub fn passed(&self) -> usize {
+        self.files.iter().map(FileResult::passed).sum()
+    }
+
+    /// Percentage of passed subtests
+    pub fn pass_rate(&self) -> f64 {
+        match self.total() {
+            0 => 0.0,
+            total => self.passed() as f64 * 100.0 / total as f64,
+        }
+    }
+
+    /// Results in JSON form, so the pass rate can be tracked over time
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).unwrap_or_default()
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for file in &self.files {
+            writeln!(
+                f,
+                "{} {} ({}/{})",
+                if file.passed() == file.subtests.len() {
+                    "PASS"
+                } else {
+                    "FAIL"
+                },
+                file.path,
+                file.passed(),
+                file.subtests.len()
+            )?;
+        }
+        write!(
+            f,
+            "{} of {} subtests passed ({:.1}%)",
+            self.passed(),
+            self.total(),
+            self.pass_rate()
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn assertions() {
+        assert_eq!(assert_equals(1, 1, "one"), Ok(()));
+        assert_eq!(
+            assert_equals("a", "b", "letter"),
+            Err("assert_equals: letter expected \"b\" but got \"a\"".to_string())
+        );
+        assert!(assert_not_equals(1, 1, "one").is_err());
+        assert!(assert_true(false, "").is_err());
+        assert!(assert_false(false, "").is_ok());
+        assert!(assert_array_equals(&[1, 2], &[1, 2], "").is_ok());
+        assert!(assert_array_equals(&[1, 3], &[1, 2], "")
+            .unwrap_err()
+            .contains("expected property 1 to be 2 but got 3"));
+        assert!(assert_array_equals(&[1], &[1, 2], "")
+            .unwrap_err()
+            .contains("lengths differ"));
+    }
+
+    #[test]
+    fn harness_collects_results() {
+        let mut harness = TestHarness::new("<p id=a>x</p>");
+        harness.test("passes", |document| {
+            assert_true(document.get().get_node_by_named_id("a").is_some(), "a")
+        });
+        harness.test("fails", |_| assert_equals(1, 2, "numbers"));
+        harness.not_run("later", "needs scripting");
+
+        let results = harness.finish();
+        let statuses: Vec<_> = results.iter().map(|r| r.status).collect();
+        assert_eq!(statuses, vec![Status::Pass, Status::Fail, Status::NotRun]);
+        assert_eq!(
+            results[1].message.as_deref(),
+            Some("assert_equals: numbers expected 2 but got 1")
+        );
+    }
+
+    #[test]
+    fn ported_dom_tests_pass() {
+        for test in dom::tests() {
+            let result = test.run();
+            for subtest in &result.subtests {
+                assert_ne!(
+                    subtest.status,
+                    Status::Fail,
+                    "{}: {} {:?}",
+                    result.path,
+                    subtest.name,
+                    subtest.message
+                );
+            }
+        }
+    }
+}
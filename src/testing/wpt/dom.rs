@@ -0,0 +1,230 @@
+//! Ported `dom/` tests
+//!
+//! Subsets of the WPT `dom/nodes` tests, limited to the subtests that can be expressed with the
+//! DOM APIs of the engine. Subtests that need APIs that do not exist yet are reported as not run.
+use crate::html5::element_ref::ElementRef;
+use crate::html5::node::{Node, NodeId, HTML_NAMESPACE};
+use crate::html5::parser::document::{Document, DocumentHandle};
+use crate::html5::selector::Selector;
+use crate::testing::wpt::{assert_array_equals, assert_equals, assert_true, TestHarness, WptTest};
+use std::collections::HashMap;
+
+/// All ported tests
+pub fn tests() -> Vec<WptTest> {
+    vec![
+        WptTest {
+            path: "dom/nodes/Document-getElementById.html",
+            html: r#"<!DOCTYPE html><title>getElementById</title><body><div id="log"></div>
+<div id=""></div><div id="test1"><span id="test2"></span></div><p id="test1">second</p>"#,
+            run: document_get_element_by_id,
+        },
+        WptTest {
+            path: "dom/nodes/Node-appendChild.html",
+            html: "<!DOCTYPE html><body><div id=a><span id=s></span></div><div id=b></div>",
+            run: node_append_child,
+        },
+        WptTest {
+            path: "dom/nodes/Node-parentNode.html",
+            html: "<!DOCTYPE html><body><div id=a><p id=b></p></div>",
+            run: node_parent_node,
+        },
+        WptTest {
+            path: "dom/nodes/Node-textContent.html",
+            html: "<!DOCTYPE html><body><div id=a>a<b>b<!--c--></b><i>d</i></div><p id=e></p>",
+            run: node_text_content,
+        },
+        WptTest {
+            path: "dom/nodes/ParentNode-querySelector-All.html",
+            html: r#"<!DOCTYPE html><body><div id=root><p class=a id=p1></p><div><p class=a id=p2>
+<em id=em1></em></p></div><p class="a b" id=p3></p></div>"#,
+            run: parent_node_query_selector_all,
+        },
+    ]
+}
+
+fn element_id(document: &Document, node_id: NodeId) -> String {
+    ElementRef::wrap(document, node_id)
+        .and_then(|element| element.attr("id"))
+        .unwrap_or_default()
+        .to_string()
+}
+
+fn document_get_element_by_id(t: &mut TestHarness) {
+    t.test(
+        "Calling document.getElementById with an empty string argument.",
+        |document| {
+            assert_true(
+                document.get().get_node_by_named_id("").is_none(),
+                "empty id",
+            )
+        },
+    );
+
+    t.test(
+        "Document.getElementById returns the first element in tree order",
+        |document| {
+            let doc = document.get();
+            let node = doc.get_node_by_named_id("test1");
+            assert_equals(node.map(|node| node.name.as_str()), Some("div"), "element")
+        },
+    );
+
+    t.test(
+        "Document.getElementById with an element added to the document",
+        |document| {
+            let body = document.get().elements_by_tag_name("body")[0];
+            let attributes = HashMap::from([("id".to_string(), "added".to_string())]);
+            let added = document
+                .add_node(
+                    Node::new_element("div", attributes, HTML_NAMESPACE),
+                    body,
+                    None,
+                )
+                .map_err(|e| e.to_string())?;
+            assert_equals(
+                document
+                    .get()
+                    .get_node_by_named_id("added")
+                    .map(|node| node.id),
+                Some(added),
+                "added element",
+            )
+        },
+    );
+
+    t.test(
+        "Document.getElementById with a non-existing id",
+        |document| {
+            assert_true(
+                document.get().get_node_by_named_id("missing").is_none(),
+                "missing id",
+            )
+        },
+    );
+
+    t.not_run(
+        "Document.getElementById after removing the id attribute",
+        "attribute mutation does not update the id map",
+    );
+}
+
+fn node_append_child(t: &mut TestHarness) {
+    t.test("Appending a node moves it to the new parent", |document| {
+        let (a, b, s) = {
+            let doc = document.get();
+            let id = |name: &str| doc.get_node_by_named_id(name).map(|node| node.id).unwrap();
+            (id("a"), id("b"), id("s"))
+        };
+
+        document.relocate(s, b);
+
+        let doc = document.get();
+        assert_equals(doc.get_node_by_id(s).unwrap().parent, Some(b), "parent")?;
+        assert_array_equals(&doc.get_node_by_id(a).unwrap().children, &[], "old parent")?;
+        assert_array_equals(&doc.get_node_by_id(b).unwrap().children, &[s], "new parent")
+    });
+
+    t.test("Appending an ancestor is refused", |document| {
+        let (b, s) = {
+            let doc = document.get();
+            let id = |name: &str| doc.get_node_by_named_id(name).map(|node| node.id).unwrap();
+            (id("b"), id("s"))
+        };
+
+        assert_true(
+            document.attach_node_to_parent(b, s, None).is_err(),
+            "refused",
+        )?;
+        assert_true(
+            document.attach_node_to_parent(b, b, None).is_err(),
+            "refused to attach to itself",
+        )
+    });
+}
+
+fn node_parent_node(t: &mut TestHarness) {
+    t.test("Document", |document| {
+        assert_equals(document.get().get_root().parent, None, "parent")
+    });
+
+    t.test("Element", |document| {
+        let doc = document.get();
+        let html = doc.root_element().unwrap();
+        assert_equals(html.node().parent, Some(NodeId::root()), "html")?;
+        let b = doc.get_node_by_named_id("b").unwrap();
+        assert_equals(element_id(&doc, b.parent.unwrap()), "a".to_string(), "p")
+    });
+
+    t.test("Removed element", |document| {
+        let b = document.get().get_node_by_named_id("b").unwrap().id;
+        document.detach_node_from_parent(b);
+        assert_equals(
+            document.get().get_node_by_id(b).unwrap().parent,
+            None,
+            "parent",
+        )
+    });
+}
+
+fn node_text_content(t: &mut TestHarness) {
+    t.test("For an Element", |document| {
+        let doc = document.get();
+        let a = doc.get_node_by_named_id("a").unwrap().id;
+        assert_equals(doc.text_content(a), "abd".to_string(), "textContent")
+    });
+
+    t.test("For an empty Element", |document| {
+        let doc = document.get();
+        let e = doc.get_node_by_named_id("e").unwrap().id;
+        assert_equals(doc.text_content(e), String::new(), "textContent")
+    });
+
+    t.not_run(
+        "For a Document",
+        "textContent of a document is null, which is not modelled",
+    );
+}
+
+fn parent_node_query_selector_all(t: &mut TestHarness) {
+    let select = |document: &DocumentHandle, selector: &str| {
+        let selector = Selector::parse(selector).expect("valid selector");
+        let doc = document.get();
+        let root = ElementRef::wrap(&doc, doc.get_node_by_named_id("root").unwrap().id).unwrap();
+        root.select(&selector)
+            .filter_map(|element| element.attr("id").map(str::to_string))
+            .collect::<Vec<_>>()
+    };
+
+    t.test("Element.querySelectorAll: class selector", |document| {
+        assert_array_equals(
+            &select(document, ".a"),
+            &["p1", "p2", "p3"].map(String::from),
+            "",
+        )
+    });
+
+    t.test("Element.querySelectorAll: compound selector", |document| {
+        assert_array_equals(&select(document, "p.a.b"), &["p3".to_string()], "")
+    });
+
+    t.test("Element.querySelectorAll: child combinator", |document| {
+        assert_array_equals(&select(document, "div > p > em"), &["em1".to_string()], "")
+    });
+
+    t.test(
+        "Element.querySelectorAll: descendant combinator",
+        |document| assert_array_equals(&select(document, "div em"), &["em1".to_string()], ""),
+    );
+
+    t.test("Element.querySelectorAll: :not()", |document| {
+        assert_array_equals(
+            &select(document, "p:not(.b)"),
+            &["p1", "p2"].map(String::from),
+            "",
+        )
+    });
+
+    t.test("Element.querySelector: no match", |document| {
+        assert_array_equals(&select(document, "section"), &[], "")
+    });
+}
@@ -37,6 +37,52 @@ pub enum Error {
 
     #[error("document task error: {0}")]
     DocumentTask(String),
+
+    #[error("document task {index} failed and was rolled back: {message}")]
+    DocumentTaskRolledBack { index: usize, message: String },
+
+    #[error("hierarchy request error: {0}")]
+    Hierarchy(#[from] HierarchyError),
+
+    #[error("snapshot error: {0}")]
+    Snapshot(String),
+
+    #[error("security error: {0}")]
+    Security(String),
+
+    #[error("disallowed by robots.txt: {0}")]
+    Robots(String),
+}
+
+/// Reason why a node cannot be inserted into a parent (a `HierarchyRequestError` in the DOM)
+#[derive(Error, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HierarchyError {
+    #[error("node not found")]
+    NotFound,
+
+    #[error("a node cannot be inserted into itself or one of its descendants")]
+    Cycle,
+
+    #[error("the document node cannot be inserted")]
+    DocumentNode,
+
+    #[error("text, comment and doctype nodes cannot have children")]
+    ParentCannotHaveChildren,
+
+    #[error("text cannot be a child of the document")]
+    TextInDocument,
+
+    #[error("a doctype can only be a child of the document")]
+    DoctypeOutsideDocument,
+
+    #[error("the document already has a doctype")]
+    SecondDoctype,
+
+    #[error("the document already has an element")]
+    SecondElement,
+
+    #[error("the doctype must come before the document element")]
+    DoctypeAfterElement,
 }
 
 /// Result that can be returned which holds either T or an Error
@@ -1,5 +1,6 @@
 extern crate regex;
 extern crate serde_derive;
 
+mod parse_errors;
 mod tokenizer;
 mod tree_construction;
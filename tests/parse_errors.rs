@@ -0,0 +1,17 @@
+use gosub_engine::testing::parse_errors::{check_corpus, update_requested};
+use std::path::Path;
+
+const CORPUS_PATH: &str = "./tests/data/parse-errors";
+
+#[test]
+fn parse_error_corpus() {
+    let mismatches = check_corpus(Path::new(CORPUS_PATH), update_requested()).unwrap();
+    for mismatch in &mismatches {
+        println!("{}", mismatch.diff);
+    }
+    assert!(
+        mismatches.is_empty(),
+        "{} golden files differ",
+        mismatches.len()
+    );
+}